@@ -0,0 +1,92 @@
+//! Get and put latency of the response cache with and without compression,
+//! across body sizes around
+//! [`DEFAULT_CACHE_COMPRESSION_THRESHOLD`](v402_client::config::DEFAULT_CACHE_COMPRESSION_THRESHOLD).
+//!
+//! Run with `cargo bench --features compression --bench cache_compression`.
+
+use bytes::Bytes;
+use chrono::Utc;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::collections::HashMap;
+use v402_client::{cache::CacheManager, config::CacheConfig, NetworkId, PaymentResponse};
+
+/// Body sizes benchmarked, in bytes.
+const SIZES: [usize; 5] = [1024, 4 * 1024, 16 * 1024, 64 * 1024, 256 * 1024];
+
+/// Returns a JSON array of records about `size` bytes long.
+fn json_body(size: usize) -> Bytes {
+    let mut body = String::from("[");
+    let mut id = 0;
+    while body.len() < size {
+        body.push_str(&format!(
+            r#"{{"id":{id},"title":"Article {id}","author":"author-{}","tags":["news","paid"],"price":"0.0{}"}},"#,
+            id % 37,
+            id % 10
+        ));
+        id += 1;
+    }
+    body.pop();
+    body.push(']');
+    Bytes::from(body)
+}
+
+fn response(body: Bytes) -> PaymentResponse {
+    PaymentResponse {
+        url: "https://api.example.com/articles".to_string(),
+        status: 200,
+        headers: HashMap::new(),
+        body,
+        payment_made: true,
+        payment_amount: Some("1000".to_string()),
+        payment_asset: Some("0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913".to_string()),
+        network: Some(NetworkId::from("base")),
+        transaction_hash: None,
+        payer: None,
+        request_id: String::new(),
+        batch_id: None,
+        timestamp: Utc::now(),
+        payment_timing: None,
+        not_modified: false,
+        requirements: None,
+        dry_run: false,
+        content_hash: None,
+        payment_hints: None,
+    }
+}
+
+fn new_cache(compression: bool) -> CacheManager {
+    let config = CacheConfig {
+        compression,
+        // Compress every size so the benchmark shows where it starts to pay off
+        compression_threshold_bytes: 0,
+        ..CacheConfig::default()
+    };
+    CacheManager::new(&config).expect("valid cache config")
+}
+
+fn bench_cache(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().expect("runtime");
+
+    for (name, compression) in [("plain", false), ("zstd", true)] {
+        let mut group = c.benchmark_group(format!("cache_{name}"));
+        for size in SIZES {
+            let response = response(json_body(size));
+            group.throughput(Throughput::Bytes(size as u64));
+
+            let cache = new_cache(compression);
+            group.bench_with_input(BenchmarkId::new("insert", size), &response, |b, response| {
+                b.to_async(&runtime).iter(|| async { cache.insert("bench", response).await.unwrap() })
+            });
+
+            let cache = new_cache(compression);
+            runtime.block_on(cache.insert("bench", &response)).unwrap();
+            group.bench_function(BenchmarkId::new("get", size), |b| {
+                b.to_async(&runtime).iter(|| async { cache.get("bench").await.unwrap().unwrap() })
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_cache);
+criterion_main!(benches);
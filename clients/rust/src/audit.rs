@@ -0,0 +1,264 @@
+//! Audit trail of the client's configuration.
+//!
+//! The client records the configuration it was created with and every
+//! later change to its behavior, such as added middleware or a chain
+//! released from quarantine by hand, so what it was configured to do can
+//! be shown for any point in time. Changes made through
+//! [`Client::with_reason`](crate::Client::with_reason) carry the caller's
+//! reason.
+//!
+//! The log is append-only and keeps the latest
+//! [`Config::audit_log_capacity`](crate::Config::audit_log_capacity)
+//! entries; dropped entries are counted in [`AuditLog::overflowed`] and
+//! show as a gap in the sequence numbers. Every entry is also published as
+//! [`ClientEvent::Audit`], so external systems can keep a complete copy.
+//!
+//! ```rust,no_run
+//! # use v402_client::Client;
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! # let client = Client::builder().build().await?;
+//! client
+//!     .with_reason("CHG-1042: require checksums on reports")
+//!     .add_verifier(Box::new(v402_client::verify::Sha256Verifier::new().required()));
+//!
+//! let log = client.audit_log();
+//! std::fs::write("audit.json", log.to_json()?)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    config::Config,
+    error::Result,
+    events::{ClientEvent, EventBus},
+};
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::VecDeque, time::Duration};
+
+/// Entries kept by the audit log unless set with
+/// [`Config::audit_log_capacity`](crate::Config::audit_log_capacity).
+pub const DEFAULT_AUDIT_LOG_CAPACITY: usize = 1000;
+
+/// Value replacing secrets in the recorded configuration.
+const REDACTED: &str = "[REDACTED]";
+
+/// Recorded change to the client.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Position in the log, starting at 0 and never reused
+    pub sequence: u64,
+
+    /// Time of the change
+    pub timestamp: DateTime<Utc>,
+
+    /// What changed
+    pub action: AuditAction,
+
+    /// Reason given with [`Client::with_reason`](crate::Client::with_reason)
+    pub reason: Option<String>,
+}
+
+/// Change recorded in the audit log.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum AuditAction {
+    /// The client was created.
+    ClientCreated {
+        /// Instance ID of the client
+        instance_id: String,
+
+        /// Effective configuration, with secrets and RPC URL paths redacted
+        config: Value,
+    },
+
+    /// A middleware was added to the stack.
+    MiddlewareAdded {
+        /// Name of the middleware
+        middleware: String,
+    },
+
+    /// A response verifier was added.
+    VerifierAdded {
+        /// Name of the verifier
+        verifier: String,
+    },
+
+    /// A spend alert handler was added.
+    SpendAlertHandlerAdded,
+
+    /// The payment approver was set.
+    PaymentApproverSet,
+
+    /// The challenge handler was set.
+    ChallengeHandlerSet,
+
+    /// A spend reporter was started.
+    SpendReporterStarted {
+        /// Interval between reports
+        interval: Duration,
+    },
+
+    /// A chain was checked by hand, which releases it from quarantine if it passes.
+    ChainRechecked {
+        /// Name of the chain
+        chain: String,
+
+        /// Whether the chain was quarantined before the check
+        was_quarantined: bool,
+
+        /// Whether the chain is quarantined after the check
+        quarantined: bool,
+    },
+
+    /// The cache or the metrics collector failed and was replaced by one that
+    /// does nothing, see [`degradation`](crate::degradation).
+    ComponentDegraded {
+        /// Name of the component
+        component: String,
+
+        /// Error the component failed with
+        error: String,
+    },
+
+    /// A degraded component was initialized again.
+    ComponentRecovered {
+        /// Name of the component
+        component: String,
+    },
+
+    /// The client was closed.
+    ClientClosed,
+}
+
+/// Contents of the audit log, returned by [`Client::audit_log`](crate::Client::audit_log).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditLog {
+    /// Entries still kept, oldest first
+    pub entries: Vec<AuditEntry>,
+
+    /// Entries dropped to stay within the log's capacity
+    pub overflowed: u64,
+}
+
+impl AuditLog {
+    /// Exports the log as pretty-printed JSON.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+/// Append-only, bounded store of audit entries.
+#[derive(Debug)]
+pub(crate) struct AuditTrail {
+    capacity: usize,
+    state: Mutex<TrailState>,
+    events: EventBus,
+}
+
+#[derive(Debug, Default)]
+struct TrailState {
+    entries: VecDeque<AuditEntry>,
+    next_sequence: u64,
+    overflowed: u64,
+}
+
+impl AuditTrail {
+    /// Creates a trail keeping up to `capacity` entries and publishing them on `events`.
+    pub(crate) fn new(capacity: usize, events: EventBus) -> Self {
+        Self {
+            capacity,
+            state: Mutex::new(TrailState::default()),
+            events,
+        }
+    }
+
+    /// Appends an entry for `action`, dropping the oldest if the trail is full.
+    pub(crate) fn record(&self, action: AuditAction, reason: Option<String>) {
+        let entry = {
+            let mut state = self.state.lock();
+            let entry = AuditEntry {
+                sequence: state.next_sequence,
+                timestamp: Utc::now(),
+                action,
+                reason,
+            };
+            state.next_sequence += 1;
+            state.entries.push_back(entry.clone());
+            while state.entries.len() > self.capacity {
+                state.entries.pop_front();
+                state.overflowed += 1;
+            }
+            entry
+        };
+        self.events.publish(ClientEvent::Audit(entry));
+    }
+
+    /// Returns the entries kept and the number dropped.
+    pub(crate) fn snapshot(&self) -> AuditLog {
+        let state = self.state.lock();
+        AuditLog {
+            entries: state.entries.iter().cloned().collect(),
+            overflowed: state.overflowed,
+        }
+    }
+}
+
+/// Returns `config` as JSON for the audit log.
+///
+/// Secrets are not serialized in the first place; values of fields named
+/// like keys, secrets or passwords are replaced as well, RPC URLs are cut
+/// down to their origin as their paths often carry API keys, and
+/// credentials and query strings are removed from other URLs.
+pub(crate) fn redacted_config(config: &Config) -> Value {
+    let mut value = serde_json::to_value(config).unwrap_or(Value::Null);
+    redact(&mut value);
+    value
+}
+
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(fields) => {
+            for (name, field) in fields.iter_mut() {
+                match field {
+                    Value::String(text) if is_secret(name) => *text = REDACTED.to_string(),
+                    Value::String(text) if name == "rpc_url" => *text = endpoint_name(text),
+                    Value::String(text) if name.ends_with("url") => *text = without_credentials(text),
+                    field => redact(field),
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+/// Whether a field with `name` holds a secret.
+fn is_secret(name: &str) -> bool {
+    name == "key" || ["_key", "secret", "password", "passphrase"].iter().any(|suffix| name.ends_with(suffix))
+}
+
+/// Removes the user, password and query string from `url`.
+fn without_credentials(url: &str) -> String {
+    match url::Url::parse(url) {
+        Ok(mut parsed) => {
+            let _ = parsed.set_username("");
+            let _ = parsed.set_password(None);
+            parsed.set_query(None);
+            parsed.to_string()
+        }
+        Err(_) => url.split('?').next().unwrap_or_default().to_string(),
+    }
+}
+
+/// Returns the scheme, host and port of `url`, leaving out paths that may contain API keys.
+fn endpoint_name(url: &str) -> String {
+    match url::Url::parse(url) {
+        Ok(parsed) => parsed.origin().ascii_serialization(),
+        Err(_) => url.split(['/', '?']).take(3).collect::<Vec<_>>().join("/"),
+    }
+}
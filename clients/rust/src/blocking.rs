@@ -0,0 +1,93 @@
+//! Blocking client for synchronous code.
+//!
+//! [`Client`] wraps an async [`crate::Client`] and the Tokio runtime that
+//! drives it, so scripts and synchronous services can make paid requests
+//! without an async runtime of their own. Background tasks such as pending
+//! payment monitors keep running on the runtime's worker thread between
+//! calls.
+//!
+//! Like `reqwest::blocking`, it must not be created or dropped from within
+//! an async runtime, which panics.
+//!
+//! ```rust,no_run
+//! use v402_client::{blocking::Client, Config};
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let client = Client::new(Config::default())?;
+//! let response = client.get("https://api.example.com/premium-data")?;
+//! println!("{} (paid: {})", response.status, response.payment_made);
+//!
+//! // Anything else is available through the async client
+//! let check = client.block_on(client.inner().head_check("https://api.example.com/report"))?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    config::Config,
+    error::{Error, Result},
+    types::{PaymentHistory, PaymentResponse, PaymentStatistics, RequestOptions},
+};
+use std::future::Future;
+use tokio::runtime::{Builder, Runtime};
+
+/// Blocking wrapper of [`crate::Client`].
+#[derive(Debug)]
+pub struct Client {
+    inner: crate::Client,
+    runtime: Runtime,
+}
+
+impl Client {
+    /// Creates a client from `config`, as [`crate::Client::new`] does.
+    pub fn new(config: Config) -> Result<Self> {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("v402-blocking")
+            .enable_all()
+            .build()
+            .map_err(|e| Error::Internal(format!("Failed to start runtime: {}", e).into()))?;
+        let inner = runtime.block_on(crate::Client::new(config))?;
+        Ok(Self { inner, runtime })
+    }
+
+    /// Returns the async client, to [`block_on`](Self::block_on) calls not wrapped here.
+    pub fn inner(&self) -> &crate::Client {
+        &self.inner
+    }
+
+    /// Runs `future` to completion on the client's runtime.
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+
+    /// Performs a GET request, paying if required, see [`crate::Client::get`].
+    pub fn get(&self, url: &str) -> Result<PaymentResponse> {
+        self.block_on(self.inner.get(url))
+    }
+
+    /// Performs a GET request with per-request options, see [`crate::Client::get_with_options`].
+    pub fn get_with_options(&self, url: &str, options: RequestOptions) -> Result<PaymentResponse> {
+        self.block_on(self.inner.get_with_options(url, options))
+    }
+
+    /// Performs a POST request, paying if required, see [`crate::Client::post`].
+    pub fn post<B: AsRef<[u8]> + Send>(&self, url: &str, body: Option<B>) -> Result<PaymentResponse> {
+        self.block_on(self.inner.post(url, body))
+    }
+
+    /// Returns up to `limit` of the most recent payments, newest first.
+    pub fn get_payment_history(&self, limit: usize) -> Result<Vec<PaymentHistory>> {
+        self.block_on(self.inner.get_payment_history(limit))
+    }
+
+    /// Returns aggregate statistics over the recorded payments.
+    pub fn get_payment_statistics(&self) -> Result<PaymentStatistics> {
+        self.block_on(self.inner.get_payment_statistics())
+    }
+
+    /// Closes the client, see [`crate::Client::close`].
+    pub fn close(&self) -> Result<()> {
+        self.block_on(self.inner.close())
+    }
+}
@@ -0,0 +1,193 @@
+//! Request bodies streamed from files and readers.
+//!
+//! Request bodies given as bytes are held in memory, which does not scale
+//! to uploads of several gigabytes. A [`Body`] read from a file or a reader
+//! is streamed instead, with a `Content-Length` when its length is known and
+//! chunked otherwise.
+//!
+//! Paying for a request means sending it twice: once to receive the
+//! `402 Payment Required` and again with the payment. Files and
+//! [`Body::from_fn`] factories are read again for the second attempt, but a
+//! [`Body::reader`] can only be read once. Requests with such a body fail
+//! before anything is sent while the client pays automatically; use a file
+//! or a factory for paid uploads.
+//!
+//! ```rust,no_run
+//! use v402_client::{Body, Client};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! # let client = Client::builder().build().await?;
+//! let response = client
+//!     .request_builder(reqwest::Method::PUT, "https://api.example.com/datasets/2024")
+//!     .header("Content-Type", "application/octet-stream")
+//!     .upload(Body::file("/data/dataset-2024.parquet"))
+//!     .send()
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::error::{Error, Result};
+use bytes::Bytes;
+use parking_lot::Mutex;
+use std::{fmt, io, path::PathBuf, sync::Arc};
+use tokio::io::AsyncRead;
+use tokio_util::io::ReaderStream;
+
+/// Reader a streamed body is read from.
+pub type BodyReader = Box<dyn AsyncRead + Send + Sync + Unpin>;
+
+/// Creates a new reader each time a [`Body::Factory`] is sent.
+pub type BodyFactory = Arc<dyn Fn() -> io::Result<BodyReader> + Send + Sync>;
+
+/// Body of a request, see the [module documentation](self).
+pub enum Body {
+    /// Bytes held in memory, passed to middleware like any other body
+    Bytes(Bytes),
+
+    /// Contents of a file, opened each time the request is sent
+    File(PathBuf),
+
+    /// Data read from a reader, which can only be sent once
+    Reader {
+        /// Source of the data
+        reader: BodyReader,
+        /// Length sent as `Content-Length`, chunked transfer if `None`
+        content_length: Option<u64>,
+    },
+
+    /// Data read from readers created each time the request is sent
+    Factory {
+        /// Creates the reader for each attempt
+        factory: BodyFactory,
+        /// Length sent as `Content-Length`, chunked transfer if `None`
+        content_length: Option<u64>,
+    },
+}
+
+impl Body {
+    /// Creates a body streamed from the file at `path`, with its size as length.
+    pub fn file<P: Into<PathBuf>>(path: P) -> Self {
+        Self::File(path.into())
+    }
+
+    /// Creates a body streamed from `reader`, which can only be sent once.
+    pub fn reader<R>(reader: R, content_length: Option<u64>) -> Self
+    where
+        R: AsyncRead + Send + Sync + Unpin + 'static,
+    {
+        Self::Reader {
+            reader: Box::new(reader),
+            content_length,
+        }
+    }
+
+    /// Creates a body streamed from a reader returned by `factory`, which is
+    /// called again whenever the request has to be resent.
+    pub fn from_fn<F, R>(content_length: Option<u64>, factory: F) -> Self
+    where
+        F: Fn() -> io::Result<R> + Send + Sync + 'static,
+        R: AsyncRead + Send + Sync + Unpin + 'static,
+    {
+        Self::Factory {
+            factory: Arc::new(move || factory().map(|reader| Box::new(reader) as BodyReader)),
+            content_length,
+        }
+    }
+
+    /// Splits the body into the bytes held in memory or the source to stream it from.
+    pub(crate) fn into_parts(self) -> (Option<Vec<u8>>, Option<BodySource>) {
+        match self {
+            Self::Bytes(bytes) => (Some(bytes.to_vec()), None),
+            Self::File(path) => (None, Some(BodySource::File(path))),
+            Self::Reader { reader, content_length } => {
+                (None, Some(BodySource::Once(Arc::new(Mutex::new(Some(reader))), content_length)))
+            }
+            Self::Factory { factory, content_length } => (None, Some(BodySource::Factory(factory, content_length))),
+        }
+    }
+}
+
+impl fmt::Debug for Body {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bytes(bytes) => f.debug_tuple("Bytes").field(&bytes.len()).finish(),
+            Self::File(path) => f.debug_tuple("File").field(path).finish(),
+            Self::Reader { content_length, .. } => {
+                f.debug_struct("Reader").field("content_length", content_length).finish_non_exhaustive()
+            }
+            Self::Factory { content_length, .. } => {
+                f.debug_struct("Factory").field("content_length", content_length).finish_non_exhaustive()
+            }
+        }
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self::Bytes(bytes.into())
+    }
+}
+
+impl From<Bytes> for Body {
+    fn from(bytes: Bytes) -> Self {
+        Self::Bytes(bytes)
+    }
+}
+
+impl From<String> for Body {
+    fn from(text: String) -> Self {
+        Self::Bytes(text.into())
+    }
+}
+
+impl From<&'static str> for Body {
+    fn from(text: &'static str) -> Self {
+        Self::Bytes(Bytes::from_static(text.as_bytes()))
+    }
+}
+
+/// Source of a streamed request body, shared by clones of the request.
+#[derive(Clone)]
+pub(crate) enum BodySource {
+    File(PathBuf),
+    Once(Arc<Mutex<Option<BodyReader>>>, Option<u64>),
+    Factory(BodyFactory, Option<u64>),
+}
+
+impl BodySource {
+    /// Whether the body can be sent more than once.
+    pub(crate) fn is_replayable(&self) -> bool {
+        !matches!(self, Self::Once(..))
+    }
+
+    /// Opens the body for sending, returning it with its length if known.
+    pub(crate) async fn open(&self) -> Result<(reqwest::Body, Option<u64>)> {
+        let (reader, content_length): (BodyReader, _) = match self {
+            Self::File(path) => {
+                let file = tokio::fs::File::open(path).await?;
+                let length = file.metadata().await?.len();
+                (Box::new(file), Some(length))
+            }
+            Self::Once(reader, content_length) => {
+                let reader = reader.lock().take().ok_or_else(|| {
+                    Error::Config("Request body read from a reader was already sent and cannot be resent".to_string().into())
+                })?;
+                (reader, *content_length)
+            }
+            Self::Factory(factory, content_length) => (factory()?, *content_length),
+        };
+        Ok((reqwest::Body::wrap_stream(ReaderStream::new(reader)), content_length))
+    }
+}
+
+impl fmt::Debug for BodySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File(path) => f.debug_tuple("File").field(path).finish(),
+            Self::Once(_, content_length) => f.debug_tuple("Once").field(content_length).finish(),
+            Self::Factory(_, content_length) => f.debug_tuple("Factory").field(content_length).finish(),
+        }
+    }
+}
@@ -0,0 +1,997 @@
+//! Response caching.
+//!
+//! Responses are cached by URL and accounted by their approximate size in
+//! bytes rather than by entry count, so a handful of large paid downloads
+//! cannot crowd out memory while small responses stay cheap to keep.
+//!
+//! With preemptive refresh enabled, an entry read during the last 10% of its
+//! TTL is refreshed once in the background while callers keep receiving the
+//! stale copy, so concurrent callers never all miss and pay at expiry.
+//!
+//! Managers created with [`CacheManager::with_namespace`] share storage with
+//! the manager they were created from and prefix their keys with
+//! `"{namespace}:"`, so tenants sharing a cache never see each other's entries.
+//!
+//! With [`CacheConfig::encryption_key`] or
+//! [`encryption_passphrase`](CacheConfig::encryption_passphrase) set, entries
+//! are encrypted with AES-256-GCM and stored under hashed keys. Entries
+//! failing decryption are evicted, and [`CacheManager::rewrap`] rotates the
+//! key, re-encrypting existing entries in the background.
+//!
+//! With [`CacheConfig::content_dedup`] set, bodies are indexed by their
+//! SHA-256 hash and entries with identical bodies share a single copy. Each
+//! entry holds a reference to its body, released when the entry is evicted,
+//! expires or is replaced; the body is forgotten with its last reference.
+//! Sizes still count the body once per entry, so the size limit stays an
+//! upper bound. The index is namespaced like keys.
+//!
+//! With [`CacheConfig::compression`] set and the `compression` feature
+//! enabled, bodies of at least
+//! [`compression_threshold_bytes`](CacheConfig::compression_threshold_bytes)
+//! are stored compressed with zstd unless a sample of them shows they barely
+//! compress. Each entry records whether its body is compressed, so entries
+//! stored before compression was enabled stay readable. Deduplicated bodies
+//! are shared and not compressed.
+//!
+//! Without the `cache` feature, every manager behaves as if
+//! [`CacheConfig::enabled`] were off: lookups miss and nothing is stored.
+
+// Without the `cache` feature the storage cannot exist, leaving code that uses it unreachable
+#![cfg_attr(not(feature = "cache"), allow(dead_code, unused_variables))]
+
+mod compression;
+mod encryption;
+mod store;
+
+pub use encryption::CacheKey;
+
+use crate::{
+    config::CacheConfig,
+    degradation::{self, Component},
+    error::{Error, Result},
+    tasks::TaskManager,
+    types::PaymentResponse,
+};
+use bytes::Bytes;
+use compression::Compressor;
+use encryption::{Keyring, Sealed};
+use store::Store;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tracing::{debug, warn};
+
+/// Fixed per-entry overhead added to the size estimate.
+const ENTRY_OVERHEAD_BYTES: u64 = 256;
+
+/// Fraction of the TTL at the end of which entries are considered stale.
+const STALE_WINDOW_FRACTION: f64 = 0.1;
+
+/// A cached response and the time it was stored.
+#[derive(Debug)]
+struct CachedEntry {
+    content: Content,
+    inserted_at: Instant,
+    /// Estimated memory used by the entry in bytes
+    size: u64,
+    /// Key of the shared body in the content index, if deduplicated
+    content_key: Option<String>,
+    /// Whether the body is compressed
+    compressed: bool,
+}
+
+/// A cached response, in the clear or encrypted.
+#[derive(Debug)]
+enum Content {
+    Plain(Box<PaymentResponse>),
+    Sealed(Sealed),
+}
+
+/// Cache statistics.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CacheStats {
+    /// Number of cached entries
+    pub entries: u64,
+
+    /// Estimated total size of cached entries in bytes
+    pub size_bytes: u64,
+
+    /// Configured size limit in bytes
+    pub max_size_bytes: u64,
+
+    /// Lookups served from the cache
+    pub hits: u64,
+
+    /// Lookups that missed the cache
+    pub misses: u64,
+
+    /// Responses not cached because they exceeded the per-entry limit
+    pub rejected_oversize: u64,
+
+    /// Background refreshes started for stale entries
+    pub background_refreshes: u64,
+
+    /// Distinct bodies held by the content index, with
+    /// [`CacheConfig::content_dedup`]
+    #[serde(default)]
+    pub unique_contents: u64,
+
+    /// Original size per stored byte of the bodies compressed so far, 1.0
+    /// if none were, with [`CacheConfig::compression`]
+    #[serde(default)]
+    pub compression_ratio: f64,
+}
+
+/// Bodies of deduplicated entries by namespaced content hash.
+#[derive(Debug, Default)]
+struct ContentIndex {
+    contents: Mutex<HashMap<String, SharedContent>>,
+}
+
+/// A body stored once for every entry referencing it.
+#[derive(Debug)]
+struct SharedContent {
+    /// First response stored with the body, served for other URLs with the same hash
+    response: PaymentResponse,
+    /// Time the body was last stored
+    stored_at: Instant,
+    /// Cached entries referencing the body
+    refs: usize,
+}
+
+impl ContentIndex {
+    /// Takes a reference to the body stored under `content_key`, storing
+    /// `response`'s body if there is none, and returns the shared body.
+    fn acquire(&self, content_key: &str, response: &PaymentResponse) -> Bytes {
+        let mut contents = self.contents.lock();
+        let shared = contents
+            .entry(content_key.to_string())
+            .or_insert_with(|| SharedContent {
+                response: response.clone(),
+                stored_at: Instant::now(),
+                refs: 0,
+            });
+        shared.stored_at = Instant::now();
+        shared.refs += 1;
+        shared.response.body.clone()
+    }
+
+    /// Releases a reference taken by [`acquire`](Self::acquire), forgetting
+    /// the body with its last reference.
+    fn release(&self, content_key: &str) {
+        let mut contents = self.contents.lock();
+        if let Some(shared) = contents.get_mut(content_key) {
+            shared.refs = shared.refs.saturating_sub(1);
+            if shared.refs == 0 {
+                contents.remove(content_key);
+            }
+        }
+    }
+
+    /// Returns the response stored with the body under `content_key` if it
+    /// was stored within `ttl`.
+    fn get(&self, content_key: &str, ttl: Duration) -> Option<PaymentResponse> {
+        let contents = self.contents.lock();
+        let shared = contents.get(content_key)?;
+        (shared.stored_at.elapsed() < ttl).then(|| shared.response.clone())
+    }
+
+    /// Number of distinct bodies.
+    fn len(&self) -> usize {
+        self.contents.lock().len()
+    }
+}
+
+/// How a new entry is stored.
+#[derive(Debug, Clone, Copy)]
+enum Storage<'a> {
+    /// As is
+    Plain,
+    /// With its body shared through the index, under keys with the given namespace prefix
+    Deduplicated(&'a ContentIndex, Option<&'a str>),
+    /// Encrypted
+    Sealed(&'a Keyring),
+}
+
+impl<'a> Storage<'a> {
+    fn new(keyring: Option<&'a Keyring>, contents: Option<&'a ContentIndex>, key_prefix: Option<&'a str>) -> Self {
+        match (keyring, contents) {
+            (Some(keyring), _) => Self::Sealed(keyring),
+            (None, Some(contents)) => Self::Deduplicated(contents, key_prefix),
+            (None, None) => Self::Plain,
+        }
+    }
+}
+
+/// Size-bounded LRU cache of responses.
+#[derive(Debug)]
+pub struct CacheManager {
+    cache: Option<Store>,
+    /// Prefix of every key, `"{namespace}:"`
+    key_prefix: Option<String>,
+    ttl: Duration,
+    max_size_bytes: u64,
+    max_entry_size_bytes: u64,
+    preemptive_refresh: AtomicBool,
+    refreshing: Arc<Mutex<HashSet<String>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    rejected_oversize: Arc<AtomicU64>,
+    background_refreshes: AtomicU64,
+    /// Runs background refreshes when the cache belongs to a client
+    tasks: Option<Arc<TaskManager>>,
+    /// Encrypts entries when encryption is enabled
+    keyring: Option<Arc<Keyring>>,
+    /// Shares identical bodies when content deduplication is enabled
+    contents: Option<Arc<ContentIndex>>,
+    /// Compresses large bodies when compression is enabled
+    compressor: Option<Arc<Compressor>>,
+}
+
+/// A cache that stores nothing is the no-op version.
+impl Component for CacheManager {
+    const NAME: &'static str = degradation::CACHE;
+
+    type Config = CacheConfig;
+
+    fn create(config: &CacheConfig) -> Result<Self> {
+        Self::new(config)
+    }
+
+    fn noop(config: &CacheConfig) -> Self {
+        let config = CacheConfig {
+            enabled: false,
+            ..config.clone()
+        };
+        Self::build(&config, None)
+    }
+}
+
+impl CacheManager {
+    /// Creates a cache from the cache configuration.
+    pub fn new(config: &CacheConfig) -> Result<Self> {
+        Ok(Self::build(config, Keyring::from_config(config)?.map(Arc::new)))
+    }
+
+    /// Creates a cache from the cache configuration, encrypting entries with `keyring`.
+    fn build(config: &CacheConfig, keyring: Option<Arc<Keyring>>) -> Self {
+        // Encrypted entries must not leave plaintext bodies in the index
+        let contents = (config.enabled && config.content_dedup && keyring.is_none()).then(Arc::<ContentIndex>::default);
+        let cache = store::build(config, contents.clone());
+
+        Self {
+            cache,
+            key_prefix: config.namespace.as_deref().map(namespace_prefix),
+            ttl: config.ttl,
+            max_size_bytes: config.max_size_bytes,
+            max_entry_size_bytes: config.max_entry_size_bytes,
+            preemptive_refresh: AtomicBool::new(config.preemptive_refresh),
+            refreshing: Arc::new(Mutex::new(HashSet::new())),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            rejected_oversize: Arc::new(AtomicU64::new(0)),
+            background_refreshes: AtomicU64::new(0),
+            tasks: None,
+            keyring,
+            contents,
+            compressor: Compressor::from_config(config).map(Arc::new),
+        }
+    }
+
+    /// Runs background refreshes as tasks of `tasks`.
+    pub(crate) fn with_tasks(mut self, tasks: Arc<TaskManager>) -> Self {
+        self.tasks = Some(tasks);
+        self
+    }
+
+    /// Returns a manager sharing this cache's storage whose keys are
+    /// prefixed with `"{namespace}:"`.
+    ///
+    /// Entries stored through the returned manager are invisible to this one
+    /// and to managers with other namespaces. Hit and miss counts are kept
+    /// per manager, while entry counts and sizes cover the shared storage.
+    pub fn with_namespace(&self, namespace: &str) -> CacheManager {
+        Self {
+            cache: self.cache.clone(),
+            key_prefix: Some(namespace_prefix(namespace)),
+            ttl: self.ttl,
+            max_size_bytes: self.max_size_bytes,
+            max_entry_size_bytes: self.max_entry_size_bytes,
+            preemptive_refresh: AtomicBool::new(self.preemptive_refresh.load(Ordering::Relaxed)),
+            refreshing: self.refreshing.clone(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            rejected_oversize: self.rejected_oversize.clone(),
+            background_refreshes: AtomicU64::new(0),
+            tasks: self.tasks.clone(),
+            keyring: self.keyring.clone(),
+            contents: self.contents.clone(),
+            compressor: self.compressor.clone(),
+        }
+    }
+
+    /// Namespace of this manager's keys, if any.
+    pub fn namespace(&self) -> Option<&str> {
+        self.key_prefix.as_deref().map(|prefix| &prefix[..prefix.len() - 1])
+    }
+
+    /// Removes every entry stored under `namespace` from the shared storage,
+    /// returning how many were removed.
+    pub async fn flush_namespace(&self, namespace: &str) -> Result<u64> {
+        let Some(cache) = &self.cache else {
+            return Ok(0);
+        };
+
+        let prefix = namespace_prefix(namespace);
+        let mut removed = 0;
+        for (key, _) in cache.iter() {
+            if key.starts_with(&prefix) {
+                cache.invalidate(key.as_str()).await;
+                removed += 1;
+            }
+        }
+        debug!(namespace, removed, "Flushed cache namespace");
+        Ok(removed)
+    }
+
+    /// Enables or disables preemptive background refresh of stale entries.
+    pub fn set_preemptive_refresh(&self, enabled: bool) {
+        self.preemptive_refresh.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Number of background refreshes started so far.
+    pub fn background_refresh_count(&self) -> u64 {
+        self.background_refreshes.load(Ordering::Relaxed)
+    }
+
+    /// Returns the cached response for `key`, if any.
+    pub async fn get(&self, key: &str) -> Result<Option<PaymentResponse>> {
+        let Some(cache) = &self.cache else {
+            return Ok(None);
+        };
+
+        let key = self.key(key);
+        let response = match cache.get(key.as_ref()).await {
+            Some(entry) => self.read(cache, &key, &entry).await,
+            None => None,
+        };
+        let counter = if response.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        Ok(response)
+    }
+
+    /// Returns the cached response for `key`, refreshing it in the background if stale.
+    ///
+    /// When preemptive refresh is enabled and the entry is within the stale
+    /// window, the first caller spawns `refresh` and every caller, including
+    /// those arriving while the refresh runs, receives the stale entry. The
+    /// refreshed response replaces the entry once `refresh` completes.
+    pub async fn get_with_refresh<F, Fut>(&self, key: &str, refresh: F) -> Result<Option<PaymentResponse>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<PaymentResponse>> + Send + 'static,
+    {
+        let Some(cache) = &self.cache else {
+            return Ok(None);
+        };
+
+        let storage_key = self.key(key);
+        let entry = cache.get(storage_key.as_ref()).await;
+        let response = match &entry {
+            Some(entry) => self.read(cache, &storage_key, entry).await,
+            None => None,
+        };
+        let (Some(entry), Some(response)) = (entry, response) else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return Ok(None);
+        };
+        self.hits.fetch_add(1, Ordering::Relaxed);
+
+        if self.preemptive_refresh.load(Ordering::Relaxed) && self.is_stale(&entry) {
+            let claimed = self.refreshing.lock().insert(storage_key.to_string());
+            if claimed {
+                self.background_refreshes.fetch_add(1, Ordering::Relaxed);
+                debug!(key = %storage_key, "Refreshing stale cache entry in the background");
+                self.spawn_refresh(cache.clone(), storage_key.into_owned(), key.to_string(), refresh());
+            }
+        }
+
+        Ok(Some(response))
+    }
+
+    /// Returns a cached response whose body has the hex-encoded SHA-256
+    /// `content_hash`, whatever URL it was cached under.
+    ///
+    /// Always `None` unless [`CacheConfig::content_dedup`] is enabled.
+    pub async fn get_by_content_hash(&self, content_hash: &str) -> Result<Option<PaymentResponse>> {
+        let Some(contents) = &self.contents else {
+            return Ok(None);
+        };
+
+        let content_hash = content_hash.trim().trim_start_matches("0x").to_ascii_lowercase();
+        let response = contents.get(&self.namespaced(&content_hash), self.ttl);
+        let counter = if response.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        Ok(response)
+    }
+
+    /// Caches a response under `key`.
+    ///
+    /// Responses larger than the per-entry limit are never cached.
+    pub async fn insert(&self, key: &str, response: &PaymentResponse) -> Result<()> {
+        if let Some(cache) = &self.cache {
+            let storage_key = self.key(key);
+            store(
+                cache,
+                self.storage(),
+                self.compressor.as_deref(),
+                &storage_key,
+                key,
+                response,
+                self.max_entry_size_bytes,
+                &self.rejected_oversize,
+            )
+            .await;
+        }
+        Ok(())
+    }
+
+    /// Removes the entry for `key`, returning `true` if there was one.
+    pub async fn invalidate(&self, key: &str) -> bool {
+        match &self.cache {
+            Some(cache) => cache.remove(self.key(key).as_ref()).await.is_some(),
+            None => false,
+        }
+    }
+
+    /// Removes every entry whose key starts with `prefix` within this
+    /// manager's namespace, returning how many were removed.
+    pub async fn invalidate_prefix(&self, prefix: &str) -> u64 {
+        let Some(cache) = &self.cache else {
+            return 0;
+        };
+
+        // Encrypted entries only reveal their key once decrypted
+        let namespaced = self.namespaced(prefix);
+        let mut removed = 0;
+        for (storage_key, entry) in cache.iter() {
+            let matches = match (&entry.content, &self.keyring) {
+                (Content::Sealed(sealed), Some(keyring)) if self.owns(&storage_key) => keyring
+                    .open(&storage_key, sealed)
+                    .is_ok_and(|(key, _)| key.starts_with(prefix)),
+                (Content::Plain(_), _) => storage_key.starts_with(namespaced.as_ref()),
+                _ => false,
+            };
+            if matches && cache.remove(storage_key.as_str()).await.is_some() {
+                removed += 1;
+            }
+        }
+        debug!(prefix = %namespaced, removed, "Invalidated cache entries by prefix");
+        removed
+    }
+
+    /// Encrypts new entries with `key` and re-encrypts existing ones in the background.
+    ///
+    /// Entries stay readable while they are re-encrypted; the previous keys
+    /// are forgotten once every entry has been rewrapped. The storage is
+    /// shared by all namespaces, so all of them are rewrapped. Fails if
+    /// encryption is not enabled.
+    pub async fn rewrap(&self, key: CacheKey) -> Result<()> {
+        let Some(keyring) = &self.keyring else {
+            return Err(Error::Cache("Cache encryption is not enabled".to_string().into()));
+        };
+        let key_id = key.id().to_string();
+        let retired = keyring.rotate(key);
+        let Some(cache) = self.cache.clone() else {
+            keyring.forget(&retired);
+            return Ok(());
+        };
+
+        let keyring = keyring.clone();
+        let task = async move {
+            let mut rewrapped = 0u64;
+            for (storage_key, entry) in cache.iter() {
+                let Content::Sealed(sealed) = &entry.content else {
+                    continue;
+                };
+                if sealed.key_id() == key_id {
+                    continue;
+                }
+                let resealed = keyring
+                    .open(&storage_key, sealed)
+                    .and_then(|(key, response)| keyring.seal(&storage_key, &key, &response));
+                match resealed {
+                    Ok(resealed) => {
+                        let entry = CachedEntry {
+                            size: sealed_size(&storage_key, &resealed),
+                            content: Content::Sealed(resealed),
+                            inserted_at: entry.inserted_at,
+                            content_key: None,
+                            compressed: entry.compressed,
+                        };
+                        cache.insert(storage_key.to_string(), Arc::new(entry)).await;
+                        rewrapped += 1;
+                    }
+                    Err(e) => {
+                        warn!(error = %e, "Evicting cache entry that could not be rewrapped");
+                        cache.invalidate(storage_key.as_str()).await;
+                    }
+                }
+            }
+            keyring.forget(&retired);
+            debug!(key_id = %key_id, rewrapped, "Rewrapped cache entries");
+        };
+        match &self.tasks {
+            Some(tasks) => tasks.spawn_once("cache_rewrap", task),
+            None => {
+                tokio::spawn(task);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns current cache statistics.
+    pub async fn stats(&self) -> CacheStats {
+        let (entries, size_bytes) = match &self.cache {
+            Some(cache) => {
+                cache.run_pending_tasks().await;
+                (cache.entry_count(), cache.weighted_size())
+            }
+            None => (0, 0),
+        };
+
+        CacheStats {
+            entries,
+            size_bytes,
+            max_size_bytes: self.max_size_bytes,
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            rejected_oversize: self.rejected_oversize.load(Ordering::Relaxed),
+            background_refreshes: self.background_refresh_count(),
+            unique_contents: self.contents.as_ref().map_or(0, |contents| contents.len() as u64),
+            compression_ratio: self.compressor.as_ref().map_or(1.0, |compressor| compressor.ratio()),
+        }
+    }
+
+    /// Checks that the cache is usable.
+    pub async fn health_check(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Drops all cached entries, or only this manager's namespace if it has one.
+    pub async fn close(&self) -> Result<()> {
+        if let Some(namespace) = self.namespace() {
+            self.flush_namespace(namespace).await?;
+        } else if let Some(cache) = &self.cache {
+            cache.invalidate_all();
+            cache.run_pending_tasks().await;
+        }
+        Ok(())
+    }
+}
+
+impl CacheManager {
+    /// Returns the storage key of `key` within this manager's namespace,
+    /// hashed if entries are encrypted.
+    fn key<'a>(&self, key: &'a str) -> Cow<'a, str> {
+        match &self.keyring {
+            Some(keyring) => self.namespaced(&keyring.hash_key(key)).into_owned().into(),
+            None => self.namespaced(key),
+        }
+    }
+
+    /// Returns how new entries of this manager are stored.
+    fn storage(&self) -> Storage<'_> {
+        Storage::new(self.keyring.as_deref(), self.contents.as_deref(), self.key_prefix.as_deref())
+    }
+
+    /// Returns `key` prefixed with this manager's namespace.
+    fn namespaced<'a>(&self, key: &'a str) -> Cow<'a, str> {
+        match &self.key_prefix {
+            Some(prefix) => Cow::Owned(format!("{}{}", prefix, key)),
+            None => Cow::Borrowed(key),
+        }
+    }
+
+    /// Returns `true` if a hashed storage key belongs to this manager's namespace.
+    fn owns(&self, storage_key: &str) -> bool {
+        match &self.key_prefix {
+            Some(prefix) => storage_key.starts_with(prefix.as_str()),
+            None => !storage_key.contains(':'),
+        }
+    }
+
+    /// Returns the response of an entry, evicting it if it has outlived the
+    /// TTL or fails decryption or decompression.
+    async fn read(
+        &self,
+        cache: &Store,
+        storage_key: &str,
+        entry: &CachedEntry,
+    ) -> Option<PaymentResponse> {
+        // Rewrapped entries keep their original insertion time
+        if entry.inserted_at.elapsed() >= self.ttl {
+            cache.invalidate(storage_key).await;
+            return None;
+        }
+
+        let mut response = match (&entry.content, &self.keyring) {
+            (Content::Plain(response), _) => response.as_ref().clone(),
+            (Content::Sealed(sealed), Some(keyring)) => match keyring.open(storage_key, sealed) {
+                Ok((_, response)) => response,
+                Err(e) => {
+                    warn!(error = %e, "Evicting cache entry that failed decryption");
+                    cache.invalidate(storage_key).await;
+                    return None;
+                }
+            },
+            (Content::Sealed(_), None) => return None,
+        };
+
+        if entry.compressed {
+            match compression::decompress(&response.body) {
+                Ok(body) => response.body = body,
+                Err(e) => {
+                    warn!(error = %e, "Evicting cache entry that failed decompression");
+                    cache.invalidate(storage_key).await;
+                    return None;
+                }
+            }
+        }
+        Some(response)
+    }
+
+    /// Returns `true` if the entry has entered the stale window before expiry.
+    fn is_stale(&self, entry: &CachedEntry) -> bool {
+        entry.inserted_at.elapsed() >= self.ttl.mul_f64(1.0 - STALE_WINDOW_FRACTION)
+    }
+
+    /// Runs a refresh in the background and stores its successful result.
+    fn spawn_refresh<Fut>(&self, cache: Store, storage_key: String, key: String, refresh: Fut)
+    where
+        Fut: Future<Output = Result<PaymentResponse>> + Send + 'static,
+    {
+        let refreshing = self.refreshing.clone();
+        let rejected_oversize = self.rejected_oversize.clone();
+        let max_entry_size_bytes = self.max_entry_size_bytes;
+        let keyring = self.keyring.clone();
+        let contents = self.contents.clone();
+        let key_prefix = self.key_prefix.clone();
+        let compressor = self.compressor.clone();
+
+        let task = async move {
+            match refresh.await {
+                // The placeholder of a dry run must not replace real content
+                Ok(response) if response.dry_run => {
+                    debug!(key = %storage_key, "Background refresh was a dry run, keeping the entry");
+                }
+                Ok(response) if response.is_success() => {
+                    let storage = Storage::new(keyring.as_deref(), contents.as_deref(), key_prefix.as_deref());
+                    store(
+                        &cache,
+                        storage,
+                        compressor.as_deref(),
+                        &storage_key,
+                        &key,
+                        &response,
+                        max_entry_size_bytes,
+                        &rejected_oversize,
+                    )
+                    .await;
+                }
+                Ok(response) => {
+                    debug!(key = %storage_key, status = response.status, "Background refresh returned an error status");
+                }
+                Err(e) => {
+                    warn!(key = %storage_key, error = %e, "Background cache refresh failed");
+                }
+            }
+            refreshing.lock().remove(&storage_key);
+        };
+        match &self.tasks {
+            Some(tasks) => tasks.spawn_once("cache_refresh", task),
+            None => {
+                tokio::spawn(task);
+            }
+        }
+    }
+}
+
+/// Returns the key prefix of a namespace.
+fn namespace_prefix(namespace: &str) -> String {
+    format!("{}:", namespace)
+}
+
+/// Returns the hex-encoded SHA-256 hash of a body.
+pub(crate) fn content_hash(body: &[u8]) -> String {
+    hex::encode(Sha256::digest(body))
+}
+
+/// Stores a response cached under `key` at `storage_key` as `storage` says,
+/// unless it exceeds the per-entry size limit, compressing its body if a
+/// compressor is given and the body is large and compressible enough.
+#[allow(clippy::too_many_arguments)]
+async fn store(
+    cache: &Store,
+    storage: Storage<'_>,
+    compressor: Option<&Compressor>,
+    storage_key: &str,
+    key: &str,
+    response: &PaymentResponse,
+    max_entry_size_bytes: u64,
+    rejected_oversize: &AtomicU64,
+) {
+    let size = entry_size(storage_key, response);
+    if size > max_entry_size_bytes {
+        rejected_oversize.fetch_add(1, Ordering::Relaxed);
+        debug!(key = storage_key, size, limit = max_entry_size_bytes, "Response too large to cache");
+        return;
+    }
+
+    // Deduplicated bodies are shared between entries, so they stay as they are
+    let compressed_body = match (storage, compressor) {
+        (Storage::Deduplicated(..), _) | (_, None) => None,
+        (_, Some(compressor)) => compressor.compress(&response.body),
+    };
+    let compressed = compressed_body.is_some();
+    let response = match compressed_body {
+        Some(body) => Cow::Owned(PaymentResponse {
+            body,
+            ..response.clone()
+        }),
+        None => Cow::Borrowed(response),
+    };
+
+    let (content, size, content_key) = match storage {
+        Storage::Sealed(keyring) => match keyring.seal(storage_key, key, &response) {
+            Ok(sealed) => {
+                let size = sealed_size(storage_key, &sealed);
+                (Content::Sealed(sealed), size, None)
+            }
+            Err(e) => {
+                warn!(error = %e, "Failed to encrypt response, not caching it");
+                return;
+            }
+        },
+        Storage::Deduplicated(contents, key_prefix) => {
+            let mut response = response.into_owned();
+            let hash = response.content_hash.get_or_insert_with(|| content_hash(&response.body));
+            let content_key = format!("{}{}", key_prefix.unwrap_or_default(), hash);
+            response.body = contents.acquire(&content_key, &response);
+            (Content::Plain(Box::new(response)), size, Some(content_key))
+        }
+        Storage::Plain => {
+            let size = entry_size(storage_key, &response);
+            (Content::Plain(Box::new(response.into_owned())), size, None)
+        }
+    };
+    let entry = CachedEntry {
+        content,
+        inserted_at: Instant::now(),
+        size,
+        content_key,
+        compressed,
+    };
+    cache.insert(storage_key.to_string(), Arc::new(entry)).await;
+}
+
+/// Estimates the memory used by an encrypted entry: ciphertext and key.
+fn sealed_size(storage_key: &str, sealed: &Sealed) -> u64 {
+    (sealed.len() + storage_key.len()) as u64 + ENTRY_OVERHEAD_BYTES
+}
+
+/// Estimates the memory used by a cached response: body, headers, key and URL.
+fn entry_size(key: &str, response: &PaymentResponse) -> u64 {
+    let headers: usize = response
+        .headers
+        .iter()
+        .map(|(name, value)| name.len() + value.len())
+        .sum();
+
+    (response.body.len() + headers + key.len() + response.url.len()) as u64 + ENTRY_OVERHEAD_BYTES
+}
+
+#[cfg(all(test, feature = "cache"))]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    /// Returns a successful response for `url` with body `body`.
+    fn response(url: &str, body: &'static str) -> PaymentResponse {
+        PaymentResponse {
+            url: url.to_string(),
+            status: 200,
+            headers: HashMap::new(),
+            body: Bytes::from_static(body.as_bytes()),
+            payment_made: true,
+            payment_amount: Some("1000".to_string()),
+            payment_asset: None,
+            network: None,
+            transaction_hash: None,
+            payer: None,
+            request_id: String::new(),
+            batch_id: None,
+            timestamp: Utc::now(),
+            payment_timing: None,
+            not_modified: false,
+            requirements: None,
+            dry_run: false,
+            content_hash: None,
+            payment_hints: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn stale_entries_are_refreshed_once_for_concurrent_callers() {
+        const URL: &str = "https://api.example.com/data";
+        let cache = Arc::new(
+            CacheManager::new(&CacheConfig {
+                ttl: Duration::from_secs(1),
+                ..CacheConfig::default()
+            })
+            .unwrap(),
+        );
+        cache.set_preemptive_refresh(true);
+        cache.insert(URL, &response(URL, "stale")).await.unwrap();
+        // Enter the last 10% of the TTL
+        tokio::time::sleep(Duration::from_millis(920)).await;
+
+        let payments = Arc::new(AtomicU64::new(0));
+        let callers = (0..16).map(|_| {
+            let (cache, payments) = (cache.clone(), payments.clone());
+            async move {
+                cache
+                    .get_with_refresh(URL, || async move {
+                        payments.fetch_add(1, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(20)).await;
+                        Ok(response(URL, "fresh"))
+                    })
+                    .await
+                    .unwrap()
+                    .unwrap()
+            }
+        });
+        for cached in futures::future::join_all(callers).await {
+            assert_eq!(cached.body, "stale");
+        }
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert_eq!(payments.load(Ordering::SeqCst), 1);
+        assert_eq!(cache.background_refresh_count(), 1);
+        assert_eq!(cache.get(URL).await.unwrap().unwrap().body, "fresh");
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_inserts_stay_within_the_size_limit() {
+        const MAX_SIZE_BYTES: u64 = 64 * 1024;
+        const MAX_ENTRY_SIZE_BYTES: u64 = 8 * 1024;
+        let cache = Arc::new(
+            CacheManager::new(&CacheConfig {
+                max_size_bytes: MAX_SIZE_BYTES,
+                max_entry_size_bytes: MAX_ENTRY_SIZE_BYTES,
+                ..CacheConfig::default()
+            })
+            .unwrap(),
+        );
+
+        // Bodies from 200 bytes to past the per-entry limit
+        let body_size = |task: usize, i: usize| 200 + (task * 997 + i * 7919) % 12_000;
+        let tasks = (0..32).map(|task| {
+            let cache = cache.clone();
+            tokio::spawn(async move {
+                for i in 0..50 {
+                    let key = format!("task-{task}-{i}");
+                    let cached = PaymentResponse {
+                        body: vec![b'x'; body_size(task, i)].into(),
+                        ..response("https://api.example.com/data", "")
+                    };
+                    cache.insert(&key, &cached).await.unwrap();
+                    cache.get(&key).await.unwrap();
+                    cache.get(&format!("task-{}-{i}", (task + 1) % 32)).await.unwrap();
+                }
+            })
+        });
+        let joined = tokio::time::timeout(Duration::from_secs(30), futures::future::join_all(tasks))
+            .await
+            .expect("concurrent inserts and reads deadlocked");
+        for result in joined {
+            result.unwrap();
+        }
+
+        let stats = cache.stats().await;
+        assert!(stats.size_bytes <= MAX_SIZE_BYTES, "{} > {}", stats.size_bytes, MAX_SIZE_BYTES);
+        assert!(stats.rejected_oversize > 0);
+        for (key, entry) in cache.cache.as_ref().unwrap().iter() {
+            assert!(entry.size <= MAX_ENTRY_SIZE_BYTES, "{key} stored {} bytes", entry.size);
+        }
+        for task in 0..32 {
+            for i in (0..50).filter(|&i| body_size(task, i) as u64 > MAX_ENTRY_SIZE_BYTES) {
+                assert!(cache.get(&format!("task-{task}-{i}")).await.unwrap().is_none());
+            }
+        }
+    }
+
+    /// Returns a JSON body of about 64 KiB that compresses well.
+    #[cfg(feature = "compression")]
+    fn json_body() -> Bytes {
+        let items: Vec<_> = (0..2000).map(|i| format!(r#"{{"id":{i},"status":"active"}}"#)).collect();
+        format!("[{}]", items.join(",")).into()
+    }
+
+    /// Returns whether the entry stored under `key` is compressed.
+    #[cfg(feature = "compression")]
+    async fn is_compressed(cache: &CacheManager, key: &str) -> bool {
+        cache.cache.as_ref().unwrap().get(key).await.unwrap().compressed
+    }
+
+    #[cfg(feature = "compression")]
+    #[tokio::test]
+    async fn large_compressible_bodies_are_stored_compressed() {
+        let cache = CacheManager::new(&CacheConfig {
+            compression: true,
+            ..CacheConfig::default()
+        })
+        .unwrap();
+
+        let large = PaymentResponse {
+            body: json_body(),
+            ..response("https://api.example.com/large", "")
+        };
+        cache.insert("large", &large).await.unwrap();
+        cache.insert("small", &response("https://api.example.com/small", "{}")).await.unwrap();
+        // Random bytes fail the sample check however large they are
+        let random: Vec<u8> = (0..4096).flat_map(|_| *uuid::Uuid::new_v4().as_bytes()).collect();
+        let noise = PaymentResponse {
+            body: random.clone().into(),
+            ..response("https://api.example.com/noise", "")
+        };
+        cache.insert("noise", &noise).await.unwrap();
+
+        assert!(is_compressed(&cache, "large").await);
+        assert!(!is_compressed(&cache, "small").await);
+        assert!(!is_compressed(&cache, "noise").await);
+        assert_eq!(cache.get("large").await.unwrap().unwrap().body, json_body());
+        assert_eq!(cache.get("small").await.unwrap().unwrap().body, "{}");
+        assert_eq!(cache.get("noise").await.unwrap().unwrap().body, random);
+
+        let stats = cache.stats().await;
+        assert!(stats.compression_ratio > 5.0, "{}", stats.compression_ratio);
+        assert!(stats.size_bytes < (json_body().len() + random.len()) as u64);
+    }
+
+    #[cfg(feature = "compression")]
+    #[tokio::test]
+    async fn entries_stored_before_compression_stay_readable() {
+        let mut cache = CacheManager::new(&CacheConfig {
+            compression: true,
+            ..CacheConfig::default()
+        })
+        .unwrap();
+        let body = PaymentResponse {
+            body: json_body(),
+            ..response("https://api.example.com/data", "")
+        };
+
+        let compressor = cache.compressor.take();
+        cache.insert("before", &body).await.unwrap();
+        assert_eq!(cache.stats().await.compression_ratio, 1.0);
+        cache.compressor = compressor;
+        cache.insert("after", &body).await.unwrap();
+
+        assert!(!is_compressed(&cache, "before").await);
+        assert!(is_compressed(&cache, "after").await);
+        for key in ["before", "after"] {
+            assert_eq!(cache.get(key).await.unwrap().unwrap().body, json_body(), "{key}");
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! zstd compression of cached bodies.
+
+use crate::config::CacheConfig;
+use bytes::Bytes;
+use std::{
+    io,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// Leading bytes of a body compressed first to estimate whether the rest compresses.
+const SAMPLE_BYTES: usize = 4096;
+
+/// Smallest saving, as a fraction of the size, for which a body is stored compressed.
+const MIN_SAVING: f64 = 0.1;
+
+/// Compresses bodies of new entries above the configured size.
+#[derive(Debug)]
+pub(super) struct Compressor {
+    level: i32,
+    threshold_bytes: u64,
+    /// Size of the bodies stored compressed, before compression
+    original_bytes: AtomicU64,
+    /// Size of the bodies stored compressed, after compression
+    compressed_bytes: AtomicU64,
+}
+
+impl Compressor {
+    /// Creates the compressor configured by `config`, if compression is
+    /// enabled and available.
+    pub(super) fn from_config(config: &CacheConfig) -> Option<Self> {
+        (config.compression && cfg!(feature = "compression")).then(|| Self {
+            level: config.compression_level,
+            threshold_bytes: config.compression_threshold_bytes,
+            original_bytes: AtomicU64::new(0),
+            compressed_bytes: AtomicU64::new(0),
+        })
+    }
+
+    /// Returns `body` compressed, or `None` if it is below the threshold or
+    /// does not compress well enough to be worth decompressing on every read.
+    ///
+    /// Bodies whose leading [`SAMPLE_BYTES`] barely compress, such as images
+    /// or archives, are skipped without compressing the rest.
+    pub(super) fn compress(&self, body: &[u8]) -> Option<Bytes> {
+        if (body.len() as u64) < self.threshold_bytes {
+            return None;
+        }
+        if body.len() > SAMPLE_BYTES {
+            let sample = compress(&body[..SAMPLE_BYTES], self.level).ok()?;
+            if !worth_it(SAMPLE_BYTES, sample.len()) {
+                return None;
+            }
+        }
+
+        let compressed = compress(body, self.level).ok()?;
+        if !worth_it(body.len(), compressed.len()) {
+            return None;
+        }
+        self.original_bytes.fetch_add(body.len() as u64, Ordering::Relaxed);
+        self.compressed_bytes.fetch_add(compressed.len() as u64, Ordering::Relaxed);
+        Some(compressed.into())
+    }
+
+    /// Original size per compressed byte of the bodies compressed so far,
+    /// 1.0 if none were.
+    pub(super) fn ratio(&self) -> f64 {
+        match self.compressed_bytes.load(Ordering::Relaxed) {
+            0 => 1.0,
+            compressed => self.original_bytes.load(Ordering::Relaxed) as f64 / compressed as f64,
+        }
+    }
+}
+
+/// Returns `true` if compressing `original` bytes to `compressed` saves at least [`MIN_SAVING`].
+fn worth_it(original: usize, compressed: usize) -> bool {
+    (compressed as f64) <= original as f64 * (1.0 - MIN_SAVING)
+}
+
+#[cfg(feature = "compression")]
+fn compress(data: &[u8], level: i32) -> io::Result<Vec<u8>> {
+    zstd::bulk::compress(data, level)
+}
+
+#[cfg(not(feature = "compression"))]
+fn compress(_data: &[u8], _level: i32) -> io::Result<Vec<u8>> {
+    Err(unavailable())
+}
+
+/// Restores a body compressed by a [`Compressor`].
+#[cfg(feature = "compression")]
+pub(super) fn decompress(body: &[u8]) -> io::Result<Bytes> {
+    zstd::stream::decode_all(body).map(Bytes::from)
+}
+
+/// Restores a body compressed by a [`Compressor`].
+#[cfg(not(feature = "compression"))]
+pub(super) fn decompress(_body: &[u8]) -> io::Result<Bytes> {
+    Err(unavailable())
+}
+
+#[cfg(not(feature = "compression"))]
+fn unavailable() -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, "cache compression requires the `compression` feature")
+}
@@ -0,0 +1,252 @@
+//! Encryption at rest of cached responses.
+//!
+//! Each response is serialized together with its cache key and sealed with
+//! AES-256-GCM under a fresh random nonce, with the storage key as
+//! associated data so entries cannot be swapped. Storage keys are
+//! HMAC-SHA256 digests of the cache keys under a key derived from the
+//! encryption key, so neither the index nor the ciphertext reveals which
+//! URLs were paid for, even to someone hashing candidate URLs. The digest
+//! key is derived from the key the cache was created with and kept across
+//! [rewraps](super::CacheManager::rewrap), so storage keys do not change.
+
+use crate::{
+    config::CacheConfig,
+    error::{Error, Result},
+    types::PaymentResponse,
+};
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Nonce,
+};
+use hmac::{Hmac, Mac};
+use parking_lot::RwLock;
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, fmt, sync::Arc};
+
+/// Length of an AES-256 key in bytes.
+const CACHE_KEY_LEN: usize = 32;
+
+/// Length of an AES-GCM nonce in bytes.
+const NONCE_LEN: usize = 12;
+
+/// PBKDF2-HMAC-SHA256 iterations when deriving a key from a passphrase.
+const PBKDF2_ROUNDS: u32 = 600_000;
+
+/// Salt of keys derived from a passphrase, fixed so a passphrase always
+/// derives the same key.
+const PASSPHRASE_SALT: &[u8] = b"v402-client/cache-encryption/v1";
+
+/// Message authenticated by a key to derive the key of its storage key digests.
+const INDEX_KEY_TAG: &[u8] = b"v402-client/cache-index/v1";
+
+/// Key encrypting cached responses, identified by a hash of the key.
+#[derive(Clone)]
+pub struct CacheKey {
+    id: String,
+    cipher: Aes256Gcm,
+    /// Key of the storage key digests, `HMAC-SHA256(key, INDEX_KEY_TAG)`
+    index_key: [u8; 32],
+}
+
+impl CacheKey {
+    /// Creates a key from 32 raw bytes.
+    pub fn new(key: &[u8]) -> Result<Self> {
+        let cipher = Aes256Gcm::new_from_slice(key).map_err(|_| {
+            Error::Config(format!("Cache encryption key must be {} bytes, got {}", CACHE_KEY_LEN, key.len()).into())
+        })?;
+        let id = hex::encode(&Sha256::digest(key)[..8]);
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts keys of any length");
+        mac.update(INDEX_KEY_TAG);
+        let index_key = mac.finalize().into_bytes().into();
+        Ok(Self { id, cipher, index_key })
+    }
+
+    /// Creates a key from 64 hex characters, optionally prefixed with `0x`.
+    pub fn from_hex(key: &str) -> Result<Self> {
+        let stripped = key.trim().strip_prefix("0x").unwrap_or(key.trim());
+        let bytes = hex::decode(stripped)
+            .map_err(|e| Error::Config(format!("Cache encryption key is not valid hex: {}", e).into()))?;
+        Self::new(&bytes)
+    }
+
+    /// Derives a key from a passphrase with PBKDF2-HMAC-SHA256.
+    ///
+    /// Derivation is deliberately slow; derive once and reuse the key.
+    pub fn from_passphrase(passphrase: &str) -> Self {
+        let mut key = [0u8; CACHE_KEY_LEN];
+        pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), PASSPHRASE_SALT, PBKDF2_ROUNDS, &mut key);
+        let derived = Self::new(&key);
+        key.fill(0);
+        derived.expect("derived key has the AES-256 key length")
+    }
+
+    /// Identifier of the key stored with every entry it encrypts.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+impl fmt::Debug for CacheKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CacheKey").field("id", &self.id).finish_non_exhaustive()
+    }
+}
+
+/// An encrypted cache entry.
+#[derive(Debug)]
+pub(super) struct Sealed {
+    key_id: String,
+    nonce: [u8; NONCE_LEN],
+    ciphertext: Vec<u8>,
+}
+
+impl Sealed {
+    /// Size of the ciphertext in bytes.
+    pub(super) fn len(&self) -> usize {
+        self.ciphertext.len()
+    }
+
+    /// ID of the key the entry was encrypted with.
+    pub(super) fn key_id(&self) -> &str {
+        &self.key_id
+    }
+}
+
+/// Plaintext of a sealed entry.
+#[derive(Serialize, Deserialize)]
+struct SealedPayload {
+    key: String,
+    response: PaymentResponse,
+}
+
+/// The current encryption key and the retired keys entries may still use.
+#[derive(Debug)]
+pub(super) struct Keyring {
+    current: RwLock<Arc<CacheKey>>,
+    keys: RwLock<HashMap<String, Arc<CacheKey>>>,
+    /// Key of the storage key digests, from the key the keyring was created with
+    index_key: [u8; 32],
+}
+
+impl Keyring {
+    /// Builds the keyring configured by `config`, if encryption is enabled.
+    pub(super) fn from_config(config: &CacheConfig) -> Result<Option<Self>> {
+        let key = match (&config.encryption_key, &config.encryption_passphrase) {
+            (Some(key), _) => CacheKey::from_hex(key.expose_secret())?,
+            (None, Some(passphrase)) => CacheKey::from_passphrase(passphrase.expose_secret()),
+            (None, None) => return Ok(None),
+        };
+        Ok(Some(Self::new(key)))
+    }
+
+    /// Creates a keyring encrypting with `key`.
+    pub(super) fn new(key: CacheKey) -> Self {
+        let key = Arc::new(key);
+        Self {
+            index_key: key.index_key,
+            keys: RwLock::new(HashMap::from([(key.id.clone(), key.clone())])),
+            current: RwLock::new(key),
+        }
+    }
+
+    /// Encrypts `response`, cached under `key` and stored under `storage_key`, with the current key.
+    pub(super) fn seal(&self, storage_key: &str, key: &str, response: &PaymentResponse) -> Result<Sealed> {
+        let plaintext = serde_json::to_vec(&SealedPayload {
+            key: key.to_string(),
+            response: response.clone(),
+        })?;
+
+        let current = self.current.read().clone();
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = current
+            .cipher
+            .encrypt(&nonce, Payload { msg: &plaintext, aad: storage_key.as_bytes() })
+            .map_err(|_| Error::Cache("Failed to encrypt cache entry".to_string().into()))?;
+
+        let mut sealed_nonce = [0u8; NONCE_LEN];
+        sealed_nonce.copy_from_slice(&nonce);
+        Ok(Sealed {
+            key_id: current.id.clone(),
+            nonce: sealed_nonce,
+            ciphertext,
+        })
+    }
+
+    /// Decrypts an entry stored under `storage_key`, returning its cache key and response.
+    ///
+    /// Fails if the entry's key is unknown or the ciphertext was tampered with.
+    pub(super) fn open(&self, storage_key: &str, sealed: &Sealed) -> Result<(String, PaymentResponse)> {
+        let key = self
+            .keys
+            .read()
+            .get(&sealed.key_id)
+            .cloned()
+            .ok_or_else(|| Error::Cache(format!("Unknown cache encryption key {}", sealed.key_id).into()))?;
+        let plaintext = key
+            .cipher
+            .decrypt(
+                Nonce::from_slice(&sealed.nonce),
+                Payload { msg: &sealed.ciphertext, aad: storage_key.as_bytes() },
+            )
+            .map_err(|_| Error::Cache("Cache entry failed its integrity check".to_string().into()))?;
+
+        let payload: SealedPayload = serde_json::from_slice(&plaintext)?;
+        Ok((payload.key, payload.response))
+    }
+
+    /// ID of the key new entries are encrypted with.
+    pub(super) fn current_id(&self) -> String {
+        self.current.read().id.clone()
+    }
+
+    /// Encrypts new entries with `key`, keeping the previous keys for
+    /// decryption, and returns the IDs of the keys it replaced.
+    pub(super) fn rotate(&self, key: CacheKey) -> Vec<String> {
+        let key = Arc::new(key);
+        let mut keys = self.keys.write();
+        let retired = keys.keys().filter(|id| **id != key.id).cloned().collect();
+        keys.insert(key.id.clone(), key.clone());
+        *self.current.write() = key;
+        retired
+    }
+
+    /// Forgets retired keys no entry is encrypted with anymore.
+    pub(super) fn forget(&self, ids: &[String]) {
+        let current = self.current_id();
+        self.keys.write().retain(|id, _| *id == current || !ids.contains(id));
+    }
+
+    /// Returns the storage key hiding a cache key: its hex-encoded keyed digest.
+    pub(super) fn hash_key(&self, key: &str) -> String {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.index_key).expect("HMAC accepts keys of any length");
+        mac.update(key.as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "https://api.example.com/data";
+
+    #[test]
+    fn storage_keys_depend_on_the_encryption_key() {
+        let keyring = Keyring::new(CacheKey::new(&[1; CACHE_KEY_LEN]).unwrap());
+        let other = Keyring::new(CacheKey::new(&[2; CACHE_KEY_LEN]).unwrap());
+
+        assert_eq!(keyring.hash_key(URL), keyring.hash_key(URL));
+        assert_ne!(keyring.hash_key(URL), other.hash_key(URL));
+        assert_ne!(keyring.hash_key(URL), hex::encode(Sha256::digest(URL.as_bytes())));
+    }
+
+    #[test]
+    fn storage_keys_survive_rotation() {
+        let keyring = Keyring::new(CacheKey::new(&[1; CACHE_KEY_LEN]).unwrap());
+        let before = keyring.hash_key(URL);
+        keyring.rotate(CacheKey::new(&[2; CACHE_KEY_LEN]).unwrap());
+        assert_eq!(keyring.hash_key(URL), before);
+    }
+}
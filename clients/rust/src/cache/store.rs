@@ -0,0 +1,94 @@
+//! Storage of cached entries.
+//!
+//! With the `cache` feature, entries are kept in a size-bounded moka LRU
+//! cache. Without it, moka is not compiled in and [`Store`] cannot be
+//! created, so every [`CacheManager`](super::CacheManager) behaves as if
+//! caching were disabled while keeping its API.
+
+use super::{CachedEntry, ContentIndex};
+use crate::config::CacheConfig;
+use std::sync::Arc;
+
+/// Storage of cached entries by storage key.
+#[cfg(feature = "cache")]
+pub(super) type Store = moka::future::Cache<String, Arc<CachedEntry>>;
+
+/// Creates the storage for `config`, or `None` if caching is disabled.
+///
+/// Entries leaving the storage release their body from `contents`.
+#[cfg(feature = "cache")]
+pub(super) fn build(config: &CacheConfig, contents: Option<Arc<ContentIndex>>) -> Option<Store> {
+    use moka::policy::EvictionPolicy;
+
+    config.enabled.then(|| {
+        let builder = Store::builder()
+            .max_capacity(config.max_size_bytes)
+            .weigher(|_key: &String, entry: &Arc<CachedEntry>| entry.size.try_into().unwrap_or(u32::MAX))
+            .eviction_policy(EvictionPolicy::lru())
+            .time_to_live(config.ttl);
+        match contents {
+            // Evicted, expired, replaced and removed entries all release their body
+            Some(contents) => builder
+                .eviction_listener(move |_key, entry: Arc<CachedEntry>, _cause| {
+                    if let Some(content_key) = &entry.content_key {
+                        contents.release(content_key);
+                    }
+                })
+                .build(),
+            None => builder.build(),
+        }
+    })
+}
+
+/// Storage of cached entries, which cannot exist without the `cache` feature.
+#[cfg(not(feature = "cache"))]
+#[derive(Debug, Clone)]
+pub(super) enum Store {}
+
+/// Returns `None`: caching requires the `cache` feature.
+#[cfg(not(feature = "cache"))]
+pub(super) fn build(config: &CacheConfig, _contents: Option<Arc<ContentIndex>>) -> Option<Store> {
+    if config.enabled {
+        tracing::warn!("Response caching requires the `cache` feature, responses will not be cached");
+    }
+    None
+}
+
+#[cfg(not(feature = "cache"))]
+impl Store {
+    pub(super) async fn get(&self, _key: &str) -> Option<Arc<CachedEntry>> {
+        match *self {}
+    }
+
+    pub(super) async fn insert(&self, _key: String, _entry: Arc<CachedEntry>) {
+        match *self {}
+    }
+
+    pub(super) async fn invalidate(&self, _key: &str) {
+        match *self {}
+    }
+
+    pub(super) async fn remove(&self, _key: &str) -> Option<Arc<CachedEntry>> {
+        match *self {}
+    }
+
+    pub(super) fn invalidate_all(&self) {
+        match *self {}
+    }
+
+    pub(super) async fn run_pending_tasks(&self) {
+        match *self {}
+    }
+
+    pub(super) fn iter(&self) -> std::iter::Empty<(Arc<String>, Arc<CachedEntry>)> {
+        match *self {}
+    }
+
+    pub(super) fn entry_count(&self) -> u64 {
+        match *self {}
+    }
+
+    pub(super) fn weighted_size(&self) -> u64 {
+        match *self {}
+    }
+}
@@ -0,0 +1,308 @@
+//! Gas price monitoring with threshold alerts.
+
+use crate::{error::Result, metrics::MetricsCollector};
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt, sync::Arc, time::Duration};
+use tokio::{task::JoinHandle, time::MissedTickBehavior};
+use tracing::{debug, info};
+
+/// Interval between gas price checks of [`ChainManager::monitor_gas_prices`](super::ChainManager::monitor_gas_prices).
+pub const GAS_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Source of current gas prices.
+///
+/// Implemented by [`ChainManager`](super::ChainManager) with `eth_gasPrice`;
+/// other implementations can drive a monitor started with
+/// [`GasMonitorHandle::with_oracle`].
+#[async_trait]
+pub trait GasOracle: Send + Sync + fmt::Debug {
+    /// Returns the gas price of `chain` in gwei.
+    async fn gas_price_gwei(&self, chain: &str) -> Result<f64>;
+}
+
+/// Whether a gas price moved above or below its threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GasDirection {
+    /// The price rose above the threshold
+    Spiked,
+    /// The price fell back to or below the threshold
+    Dropped,
+}
+
+/// A chain's gas price crossed its alert threshold.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GasAlert {
+    /// Name of the chain
+    pub chain: String,
+    /// Gas price that crossed the threshold, in gwei
+    pub current_gwei: f64,
+    /// Threshold set for the chain, in gwei
+    pub threshold_gwei: f64,
+    /// Direction of the crossing
+    pub direction: GasDirection,
+}
+
+/// Alert threshold of a chain and which side of it the price was last seen on.
+#[derive(Debug, Clone, Copy)]
+struct Threshold {
+    gwei: f64,
+    /// `None` until the first price after the threshold was set
+    above: Option<bool>,
+}
+
+/// Background gas price monitor, stopped when dropped.
+///
+/// No alerts are raised for a chain until a threshold is set for it with
+/// [`set_alert_threshold`](Self::set_alert_threshold). A price already above
+/// a new threshold raises [`GasDirection::Spiked`] on the next check; after
+/// that, alerts are raised each time the price crosses the threshold.
+///
+/// ```rust
+/// use std::time::Duration;
+/// use v402_client::chains::{GasDirection, GasMonitorHandle, GasOracle};
+///
+/// #[derive(Debug)]
+/// struct Fixed(f64);
+///
+/// #[async_trait::async_trait]
+/// impl GasOracle for Fixed {
+///     async fn gas_price_gwei(&self, _chain: &str) -> v402_client::Result<f64> {
+///         Ok(self.0)
+///     }
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let (sender, mut alerts) = tokio::sync::mpsc::unbounded_channel();
+/// let monitor = GasMonitorHandle::with_oracle(
+///     std::sync::Arc::new(Fixed(80.0)),
+///     vec!["base".to_string()],
+///     Duration::from_millis(10),
+///     move |alert| drop(sender.send(alert)),
+/// );
+/// monitor.set_alert_threshold("base", 50.0);
+///
+/// let alert = alerts.recv().await.unwrap();
+/// assert_eq!(alert.direction, GasDirection::Spiked);
+/// assert_eq!(alert.current_gwei, 80.0);
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct GasMonitorHandle {
+    thresholds: Arc<Mutex<HashMap<String, Threshold>>>,
+    task: JoinHandle<()>,
+}
+
+impl GasMonitorHandle {
+    /// Starts polling `oracle` for the gas prices of `chains` every
+    /// `interval`, calling `callback` when a price crosses its threshold.
+    pub fn with_oracle<F>(oracle: Arc<dyn GasOracle>, chains: Vec<String>, interval: Duration, callback: F) -> Self
+    where
+        F: Fn(GasAlert) + Send + 'static,
+    {
+        Self::start(oracle, chains, interval, None, callback)
+    }
+
+    /// Starts the monitor, recording prices as gauges of `metrics` if given.
+    pub(crate) fn start<F>(
+        oracle: Arc<dyn GasOracle>,
+        chains: Vec<String>,
+        interval: Duration,
+        metrics: Option<Arc<MetricsCollector>>,
+        callback: F,
+    ) -> Self
+    where
+        F: Fn(GasAlert) + Send + 'static,
+    {
+        let thresholds: Arc<Mutex<HashMap<String, Threshold>>> = Arc::default();
+        let shared = thresholds.clone();
+        let task = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                let checks = chains.iter().map(|chain| oracle.gas_price_gwei(chain));
+                let prices = futures::future::join_all(checks).await;
+                for (chain, price) in chains.iter().zip(prices) {
+                    let gwei = match price {
+                        Ok(gwei) => gwei,
+                        Err(e) => {
+                            debug!(chain = %chain, error = %e, "Failed to fetch gas price");
+                            continue;
+                        }
+                    };
+                    if let Some(metrics) = &metrics {
+                        metrics.set_gas_price(chain, gwei);
+                    }
+                    if let Some(alert) = observe(&shared, chain, gwei) {
+                        info!(
+                            chain = %alert.chain,
+                            current_gwei = alert.current_gwei,
+                            threshold_gwei = alert.threshold_gwei,
+                            direction = ?alert.direction,
+                            "Gas price crossed alert threshold"
+                        );
+                        callback(alert);
+                    }
+                }
+            }
+        });
+        Self { thresholds, task }
+    }
+
+    /// Sets the gas price of `chain` above which [`GasDirection::Spiked`] is raised.
+    pub fn set_alert_threshold(&self, chain: &str, gwei: f64) {
+        self.thresholds
+            .lock()
+            .insert(chain.to_string(), Threshold { gwei, above: None });
+    }
+
+    /// Stops the monitor; dropping the handle does the same.
+    pub fn stop(self) {}
+}
+
+impl Drop for GasMonitorHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Records the price of `chain`, returning the alert it raises, if any.
+fn observe(thresholds: &Mutex<HashMap<String, Threshold>>, chain: &str, gwei: f64) -> Option<GasAlert> {
+    let mut thresholds = thresholds.lock();
+    let threshold = thresholds.get_mut(chain)?;
+    let above = gwei > threshold.gwei;
+    let direction = match (threshold.above.replace(above), above) {
+        (None | Some(false), true) => GasDirection::Spiked,
+        (Some(true), false) => GasDirection::Dropped,
+        _ => return None,
+    };
+    Some(GasAlert {
+        chain: chain.to_string(),
+        current_gwei: gwei,
+        threshold_gwei: threshold.gwei,
+        direction,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use std::{
+        collections::VecDeque,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    const INTERVAL: Duration = Duration::from_secs(30);
+
+    /// Oracle returning scripted prices per chain, then errors once a script runs out.
+    #[derive(Debug)]
+    struct MockOracle {
+        prices: Mutex<HashMap<String, VecDeque<f64>>>,
+        calls: AtomicUsize,
+    }
+
+    impl MockOracle {
+        fn new(scripts: &[(&str, Vec<f64>)]) -> Arc<Self> {
+            let prices = scripts
+                .iter()
+                .map(|(chain, prices)| (chain.to_string(), prices.iter().copied().collect()))
+                .collect();
+            Arc::new(Self {
+                prices: Mutex::new(prices),
+                calls: AtomicUsize::new(0),
+            })
+        }
+
+        /// Yields until the monitor has made `calls` price checks in total.
+        async fn wait_for_calls(&self, calls: usize) {
+            while self.calls.load(Ordering::SeqCst) < calls {
+                tokio::task::yield_now().await;
+            }
+        }
+    }
+
+    #[async_trait]
+    impl GasOracle for MockOracle {
+        async fn gas_price_gwei(&self, chain: &str) -> Result<f64> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            self.prices
+                .lock()
+                .get_mut(chain)
+                .and_then(VecDeque::pop_front)
+                .ok_or_else(|| Error::Chain(format!("no gas price for {}", chain).into()))
+        }
+    }
+
+    fn alert(threshold_gwei: f64, current_gwei: f64, direction: GasDirection) -> GasAlert {
+        GasAlert {
+            chain: "base".to_string(),
+            current_gwei,
+            threshold_gwei,
+            direction,
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn alerts_are_raised_when_the_threshold_is_crossed() {
+        // Polygon has no script, so every check of it fails
+        let oracle = MockOracle::new(&[("base", vec![80.0, 90.0, 30.0, 25.0])]);
+        let alerts: Arc<Mutex<Vec<GasAlert>>> = Arc::default();
+        let received = alerts.clone();
+        let monitor = GasMonitorHandle::with_oracle(
+            oracle.clone(),
+            vec!["base".to_string(), "polygon".to_string()],
+            INTERVAL,
+            move |alert| received.lock().push(alert),
+        );
+        monitor.set_alert_threshold("base", 50.0);
+        monitor.set_alert_threshold("polygon", 10.0);
+
+        // The first reading is already above the threshold
+        oracle.wait_for_calls(2).await;
+        assert_eq!(*alerts.lock(), [alert(50.0, 80.0, GasDirection::Spiked)]);
+
+        // Staying above raises nothing
+        tokio::time::advance(INTERVAL).await;
+        oracle.wait_for_calls(4).await;
+        assert_eq!(alerts.lock().len(), 1);
+
+        tokio::time::advance(INTERVAL).await;
+        oracle.wait_for_calls(6).await;
+        assert_eq!(alerts.lock()[1..], [alert(50.0, 30.0, GasDirection::Dropped)]);
+
+        // A new threshold forgets which side of the old one the price was on
+        monitor.set_alert_threshold("base", 20.0);
+        assert_eq!(monitor.thresholds.lock()["base"].above, None);
+        tokio::time::advance(INTERVAL).await;
+        oracle.wait_for_calls(8).await;
+        assert_eq!(alerts.lock()[2..], [alert(20.0, 25.0, GasDirection::Spiked)]);
+
+        // Failed checks are skipped without raising alerts or changing state
+        tokio::time::advance(INTERVAL).await;
+        oracle.wait_for_calls(10).await;
+        assert_eq!(alerts.lock().len(), 3);
+        assert_eq!(monitor.thresholds.lock()["base"].above, Some(true));
+        assert_eq!(monitor.thresholds.lock()["polygon"].above, None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn dropping_the_handle_stops_the_monitor() {
+        let oracle = MockOracle::new(&[("base", vec![80.0; 8])]);
+        let monitor = GasMonitorHandle::with_oracle(oracle.clone(), vec!["base".to_string()], INTERVAL, |_| {});
+        oracle.wait_for_calls(1).await;
+
+        drop(monitor);
+        for _ in 0..4 {
+            tokio::time::advance(INTERVAL).await;
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(oracle.calls.load(Ordering::SeqCst), 1);
+        // The aborted task released its reference to the oracle
+        assert_eq!(Arc::strong_count(&oracle), 1);
+    }
+}
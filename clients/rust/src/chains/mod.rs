@@ -0,0 +1,1036 @@
+//! Multi-chain access: JSON-RPC calls, health checks and payment signing.
+//!
+//! Each chain with monitoring enabled in its [`ChainHealthConfig`] is checked
+//! in the background. A chain that fails `failure_threshold` consecutive
+//! checks is quarantined: it is skipped when selecting among the payment
+//! options offered by a server and reported unhealthy until it recovers.
+//!
+//! Token symbols and decimals are kept in a [`TokenRegistry`].
+//!
+//! Gas prices of EVM chains can be watched with
+//! [`ChainManager::monitor_gas_prices`], which raises a [`GasAlert`] when a
+//! price crosses its threshold.
+
+mod gas;
+mod network;
+mod tokens;
+
+pub use gas::{GasAlert, GasDirection, GasMonitorHandle, GasOracle, GAS_POLL_INTERVAL};
+pub use network::NetworkId;
+pub use tokens::{format_units, truncate_address, TokenInfo, TokenRegistry};
+
+use crate::{
+    config::{ChainConfig, ChainHealthConfig, ChainType, Config, DEFAULT_WALLET},
+    crypto::{self, Signer},
+    error::{dns_error, Error, Result},
+    events::{ClientEvent, EventBus},
+    http,
+    metrics::MetricsCollector,
+    payment::{Authorization, PaymentRequirements},
+    tasks::{Restart, TaskManager},
+};
+use async_trait::async_trait;
+use bytes::Bytes;
+use parking_lot::Mutex;
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, info, warn};
+
+/// Timeout applied to individual RPC calls.
+const RPC_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Fewest RPC calls between checks for the error rate to be considered.
+const MIN_ERROR_RATE_SAMPLES: u64 = 4;
+
+/// Default EIP-712 domain of USDC, used when the requirements carry none.
+const DEFAULT_TOKEN_NAME: &str = "USD Coin";
+const DEFAULT_TOKEN_VERSION: &str = "2";
+
+/// Signature of the EIP-3009 function a facilitator calls to settle a payment.
+const TRANSFER_WITH_AUTHORIZATION_SIGNATURE: &str =
+    "transferWithAuthorization(address,address,uint256,uint256,uint256,bytes32,uint8,bytes32,bytes32)";
+
+/// Manages connections to the configured blockchain networks.
+#[derive(Debug)]
+pub struct ChainManager {
+    config: Arc<Config>,
+    /// Signers by wallet label, including the private key's [`DEFAULT_WALLET`]
+    signers: HashMap<String, Signer>,
+    rpc_client: reqwest::Client,
+    next_rpc_id: AtomicU64,
+    /// Health monitor state per chain name
+    health: Mutex<HashMap<String, HealthState>>,
+    events: EventBus,
+    /// Known tokens, including those resolved on-chain
+    tokens: TokenRegistry,
+    /// Receives the gas prices seen by gas monitors
+    metrics: Option<Arc<MetricsCollector>>,
+}
+
+/// Health of a chain as seen by the health monitor.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainHealth {
+    /// Name of the chain
+    pub chain: String,
+
+    /// Whether the chain is excluded from payment selection
+    pub quarantined: bool,
+
+    /// Failed checks since the last successful one
+    pub consecutive_failures: u32,
+
+    /// Latest block number (slot on Solana) observed
+    pub block_number: Option<u64>,
+
+    /// RPC latency of the last check
+    pub latency: Option<Duration>,
+
+    /// Fraction of RPC calls that failed between the last two checks
+    pub error_rate: f64,
+
+    /// Reason the last check failed
+    pub last_error: Option<String>,
+}
+
+/// Mutable health monitor state of one chain.
+#[derive(Debug, Default)]
+struct HealthState {
+    consecutive_failures: u32,
+    quarantined: bool,
+    block_number: Option<u64>,
+    block_advanced_at: Option<Instant>,
+    latency: Option<Duration>,
+    error_rate: f64,
+    last_error: Option<String>,
+    /// RPC calls and failures since the last check
+    rpc_calls: u64,
+    rpc_errors: u64,
+}
+
+impl HealthState {
+    fn report(&self, chain: &str) -> ChainHealth {
+        ChainHealth {
+            chain: chain.to_string(),
+            quarantined: self.quarantined,
+            consecutive_failures: self.consecutive_failures,
+            block_number: self.block_number,
+            latency: self.latency,
+            error_rate: self.error_rate,
+            last_error: self.last_error.clone(),
+        }
+    }
+}
+
+impl ChainManager {
+    /// Creates a chain manager for the configured networks.
+    pub async fn new(config: &Arc<Config>) -> Result<Self> {
+        let mut signers = HashMap::new();
+        if let Some(key) = &config.private_key {
+            signers.insert(DEFAULT_WALLET.to_string(), Signer::from_hex(key.expose_secret())?);
+        }
+        for wallet in &config.wallets {
+            signers.insert(wallet.label.clone(), Signer::from_hex(wallet.private_key.expose_secret())?);
+        }
+
+        let rpc_client = http::with_dns(reqwest::Client::builder(), config)
+            .timeout(RPC_TIMEOUT)
+            .user_agent(crate::USER_AGENT)
+            .build()
+            .map_err(|e| Error::Network(format!("Failed to build RPC client: {}", e).into()))?;
+
+        let tokens = TokenRegistry::new();
+        for chain in &config.chains {
+            for token in &chain.tokens {
+                tokens.register(&chain.name, token.clone());
+            }
+        }
+
+        Ok(Self {
+            config: config.clone(),
+            signers,
+            rpc_client,
+            next_rpc_id: AtomicU64::new(1),
+            health: Mutex::new(HashMap::new()),
+            events: EventBus::new(),
+            tokens,
+            metrics: None,
+        })
+    }
+
+    /// Publishes quarantine and recovery events on `events`.
+    pub(crate) fn with_events(mut self, events: EventBus) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Records the gas prices seen by gas monitors in `metrics`.
+    pub(crate) fn with_metrics(mut self, metrics: Arc<MetricsCollector>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Starts a background health monitor for every chain with monitoring enabled.
+    ///
+    /// Monitors run as tasks of `tasks`, restarted if they panic, and stop
+    /// when `tasks` shuts down or the manager is dropped.
+    pub(crate) fn start_health_monitor(self: &Arc<Self>, tasks: &TaskManager) {
+        for chain in &self.config.chains {
+            let health = chain.health_config();
+            if !health.enabled {
+                continue;
+            }
+
+            let manager = Arc::downgrade(self);
+            let chain = chain.clone();
+            let name = format!("chain_health_monitor:{}", chain.name);
+            tasks.spawn(&name, Restart::WithBackoff, move |shutdown| {
+                let manager = manager.clone();
+                let chain = chain.clone();
+                async move {
+                    let mut ticker = tokio::time::interval(health.check_interval);
+                    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                    // The first tick completes immediately; wait a full interval instead
+                    ticker.tick().await;
+                    loop {
+                        tokio::select! {
+                            _ = shutdown.cancelled() => break,
+                            _ = ticker.tick() => {}
+                        }
+                        let Some(manager) = manager.upgrade() else { break };
+                        manager.check_chain(&chain).await;
+                    }
+                }
+            });
+        }
+    }
+
+    /// Returns `true` if `network` is quarantined by the health monitor.
+    pub fn is_quarantined(&self, network: &str) -> bool {
+        let name = self.config.chain(network).map_or(network, |chain| chain.name.as_str());
+        self.health
+            .lock()
+            .get(name)
+            .is_some_and(|state| state.quarantined)
+    }
+
+    /// Returns the monitored health of every configured chain.
+    pub fn chain_health(&self) -> Vec<ChainHealth> {
+        let health = self.health.lock();
+        self.config
+            .chains
+            .iter()
+            .map(|chain| match health.get(&chain.name) {
+                Some(state) => state.report(&chain.name),
+                None => HealthState::default().report(&chain.name),
+            })
+            .collect()
+    }
+
+    /// Checks a chain immediately instead of waiting for the next scheduled check.
+    ///
+    /// A quarantined chain that passes is released from quarantine at once.
+    pub async fn force_recheck(&self, network: &str) -> Result<ChainHealth> {
+        let chain = self.chain(network)?;
+        Ok(self.check_chain(chain).await)
+    }
+
+    /// Checks that the RPC endpoint of every configured chain answers,
+    /// failing with the first error.
+    pub(crate) async fn check_connections(&self) -> Result<()> {
+        let checks = self.config.chains.iter().map(|chain| async move {
+            let result = self.send_rpc(chain, block_number_method(chain), json!([])).await?;
+            parse_block_number(chain, &result)
+        });
+        futures::future::try_join_all(checks).await?;
+        Ok(())
+    }
+
+    /// Runs one health check, updating the chain's quarantine state.
+    async fn check_chain(&self, chain: &ChainConfig) -> ChainHealth {
+        let config = chain.health_config();
+
+        let started = Instant::now();
+        let block = self
+            .rpc(chain, block_number_method(chain), json!([]))
+            .await
+            .and_then(|result| parse_block_number(chain, &result));
+        let latency = started.elapsed();
+
+        let mut health = self.health.lock();
+        let state = health.entry(chain.name.clone()).or_default();
+
+        state.error_rate = if state.rpc_calls >= MIN_ERROR_RATE_SAMPLES {
+            state.rpc_errors as f64 / state.rpc_calls as f64
+        } else {
+            0.0
+        };
+        state.rpc_calls = 0;
+        state.rpc_errors = 0;
+        state.latency = Some(latency);
+
+        let failure = match block {
+            Err(e) => Some(e.to_string()),
+            Ok(block) => {
+                let now = Instant::now();
+                if state.block_number.map_or(true, |last| block > last) {
+                    state.block_number = Some(block);
+                    state.block_advanced_at = Some(now);
+                }
+                let stalled_for = state.block_advanced_at.map_or(Duration::ZERO, |at| now - at);
+
+                failed_threshold(&config, latency, stalled_for, state.error_rate)
+            }
+        };
+
+        let event = match failure {
+            Some(reason) => {
+                state.consecutive_failures += 1;
+                state.last_error = Some(reason.clone());
+                warn!(
+                    chain = %chain.name,
+                    failures = state.consecutive_failures,
+                    reason = %reason,
+                    "Chain health check failed"
+                );
+                (!state.quarantined && state.consecutive_failures >= config.failure_threshold).then(|| {
+                    state.quarantined = true;
+                    ClientEvent::ChainQuarantined {
+                        chain: chain.name.clone(),
+                        consecutive_failures: state.consecutive_failures,
+                        reason,
+                    }
+                })
+            }
+            None => {
+                state.consecutive_failures = 0;
+                state.last_error = None;
+                std::mem::take(&mut state.quarantined).then(|| ClientEvent::ChainRecovered {
+                    chain: chain.name.clone(),
+                })
+            }
+        };
+
+        let report = state.report(&chain.name);
+        drop(health);
+
+        if let Some(event) = event {
+            match &event {
+                ClientEvent::ChainQuarantined { .. } => warn!(chain = %chain.name, "Chain quarantined"),
+                _ => info!(chain = %chain.name, "Chain recovered from quarantine"),
+            }
+            self.events.publish(event);
+        }
+
+        report
+    }
+
+    /// Returns the configuration of a network, or [`Error::UnknownNetwork`]
+    /// listing the configured ones.
+    pub fn chain(&self, network: &str) -> Result<&ChainConfig> {
+        self.config.chain(network).ok_or_else(|| Error::UnknownNetwork {
+            network: network.to_string(),
+            configured: self.config.chains.iter().map(|chain| chain.name.clone()).collect(),
+            context: None,
+        })
+    }
+
+    /// Returns the configuration of a network, if one is configured.
+    pub fn get(&self, network: &NetworkId) -> Option<&ChainConfig> {
+        self.config.chain(network.as_str())
+    }
+
+    /// Returns the payer address of the default wallet on a network.
+    pub fn address(&self, network: &str) -> Result<String> {
+        let wallet = self.config.default_wallet.as_deref().unwrap_or(DEFAULT_WALLET);
+        self.wallet_address(wallet, network)
+    }
+
+    /// Returns the payer address of the wallet labelled `wallet` on a network.
+    pub fn wallet_address(&self, wallet: &str, network: &str) -> Result<String> {
+        if let Some(chain) = self.config.chain(network) {
+            if !chain.chain_type.is_evm() {
+                return Err(Error::Chain(format!("Payments on {} are not supported", network).into()));
+            }
+        }
+
+        match self.signers.get(wallet) {
+            Some(signer) => Ok(signer.address().to_string()),
+            None if self.signers.is_empty() => {
+                Err(Error::Config("A private key is required to make payments".to_string().into()))
+            }
+            None => Err(Error::Config(format!("Wallet {} is not configured", wallet).into())),
+        }
+    }
+
+    /// Labels of the configured wallets, sorted.
+    pub fn wallets(&self) -> Vec<String> {
+        let mut labels: Vec<_> = self.signers.keys().cloned().collect();
+        labels.sort();
+        labels
+    }
+
+    /// Returns the label of the wallet paying for `url`, see [`Config::wallet_for`].
+    ///
+    /// Fails with [`Error::NoWalletForDomain`] if wallets are configured but
+    /// none is routed to the URL and there is no default.
+    pub fn route_wallet(&self, url: &str) -> Result<String> {
+        if self.signers.is_empty() {
+            return Err(Error::Config("A private key is required to make payments".to_string().into()));
+        }
+        self.config
+            .wallet_for(url)
+            .map(str::to_string)
+            .ok_or_else(|| Error::NoWalletForDomain {
+                domain: url::Url::parse(url)
+                    .ok()
+                    .and_then(|url| url.host_str().map(str::to_string))
+                    .unwrap_or_else(|| url.to_string()),
+                context: None,
+            })
+    }
+
+    /// Derives the payment nonce of the wallet whose address is `payer`, see
+    /// [`payment::nonce`](crate::payment::nonce).
+    pub(crate) fn derive_nonce(
+        &self,
+        payer: &str,
+        resource_hash: &[u8; 32],
+        requirements_hash: &[u8; 32],
+        counter: u64,
+    ) -> Result<[u8; 32]> {
+        Ok(self.signer_for(payer)?.derive_nonce(resource_hash, requirements_hash, counter))
+    }
+
+    /// Returns the signer whose address is `address`.
+    fn signer_for(&self, address: &str) -> Result<&Signer> {
+        if self.signers.is_empty() {
+            return Err(Error::Config("A private key is required to make payments".to_string().into()));
+        }
+        self.signers
+            .values()
+            .find(|signer| signer.address().eq_ignore_ascii_case(address))
+            .ok_or_else(|| Error::Config(format!("No configured wallet has address {}", address).into()))
+    }
+
+    /// Signs an EIP-3009 `TransferWithAuthorization` for the given requirements.
+    ///
+    /// The authorization is signed by the wallet whose address is its `from`.
+    /// The EIP-712 domain is built from the `name` and `version` in the
+    /// requirements' `extra` data, the chain ID and the asset contract.
+    pub async fn sign_authorization(
+        &self,
+        requirements: &PaymentRequirements,
+        authorization: &Authorization,
+    ) -> Result<String> {
+        let signer = self.signer_for(&authorization.from)?;
+
+        let chain = self.chain(requirements.network.as_str())?;
+        let chain_id = chain
+            .chain_id
+            .ok_or_else(|| Error::Chain(format!("Network {} has no chain ID", chain.name).into()))?;
+
+        let asset = requirements
+            .primary_asset()
+            .ok_or_else(|| Error::Payment("Payment requirements list no asset".to_string().into()))?;
+        let name = asset.name.as_deref().unwrap_or(DEFAULT_TOKEN_NAME);
+        let version = asset.version.as_deref().unwrap_or(DEFAULT_TOKEN_VERSION);
+
+        let domain = crypto::domain_separator(name, version, chain_id, &crypto::parse_address(&asset.address)?);
+
+        let type_hash = crypto::keccak256(
+            b"TransferWithAuthorization(address from,address to,uint256 value,uint256 validAfter,uint256 validBefore,bytes32 nonce)",
+        );
+        let mut encoded = Vec::with_capacity(32 * 7);
+        encoded.extend_from_slice(&type_hash);
+        encoded.extend_from_slice(&crypto::address_word(&crypto::parse_address(&authorization.from)?));
+        encoded.extend_from_slice(&crypto::address_word(&crypto::parse_address(&authorization.to)?));
+        encoded.extend_from_slice(&crypto::decimal_word(&authorization.value)?);
+        encoded.extend_from_slice(&crypto::decimal_word(&authorization.valid_after)?);
+        encoded.extend_from_slice(&crypto::decimal_word(&authorization.valid_before)?);
+        encoded.extend_from_slice(&crypto::parse_bytes32(&authorization.nonce)?);
+
+        let digest = crypto::typed_data_hash(&domain, &crypto::keccak256(&encoded));
+        signer.sign_hash(&digest)
+    }
+
+    /// Signs `message` with EIP-191 `personal_sign` using the wallet whose address is `signer`.
+    pub(crate) fn sign_message(&self, signer: &str, message: &[u8]) -> Result<String> {
+        self.signer_for(signer)?
+            .sign_hash(&crypto::personal_message_hash(message))
+    }
+
+    /// Performs a read-only contract call (`eth_call`) with ABI-encoded calldata.
+    ///
+    /// Returns the raw ABI-encoded return data. Fails with
+    /// [`Error::ContractReverted`] if the call reverts.
+    pub async fn call_view(&self, network: &str, contract: &str, calldata: &[u8]) -> Result<Bytes> {
+        let chain = self.chain(network)?;
+        if !chain.chain_type.is_evm() {
+            return Err(Error::Chain(format!("View calls are not supported on {}", network).into()));
+        }
+        self.eth_call(chain, None, contract, calldata).await
+    }
+
+    /// Runs `eth_call` against `contract`, optionally on behalf of `from`,
+    /// decoding reverts into [`Error::ContractReverted`].
+    async fn eth_call(&self, chain: &ChainConfig, from: Option<&str>, contract: &str, calldata: &[u8]) -> Result<Bytes> {
+        let mut call = json!({
+            "to": contract,
+            "data": format!("0x{}", hex::encode(calldata)),
+        });
+        if let Some(from) = from {
+            call["from"] = json!(from);
+        }
+        let params = json!([call, "latest"]);
+
+        // A revert is the contract's answer, not an RPC failure
+        let response = self.send_rpc_envelope(chain, "eth_call", params).await;
+        let error = response.as_ref().ok().and_then(|response| response.get("error"));
+        let revert = error.and_then(decode_revert);
+        self.count_rpc(chain, response.is_err() || (error.is_some() && revert.is_none()));
+        let response = response?;
+        if let Some((reason, data)) = revert {
+            return Err(Error::ContractReverted {
+                contract: contract.to_string(),
+                reason,
+                data: if data.is_empty() { String::new() } else { format!("0x{}", hex::encode(&data)) },
+                context: None,
+            });
+        }
+        if let Some(error) = response.get("error") {
+            return Err(Error::Chain(format!("{} RPC error: {}", chain.name, error).into()));
+        }
+
+        let result = response
+            .get("result")
+            .ok_or_else(|| Error::Chain(format!("{} RPC response has no result", chain.name).into()))?;
+        let data = result
+            .as_str()
+            .ok_or_else(|| Error::Chain(format!("Unexpected eth_call result: {}", result).into()))?;
+
+        Ok(Bytes::from(crypto::decode_hex(data)?))
+    }
+
+    /// Sends a JSON-RPC request to the RPC endpoint of an EVM network.
+    #[cfg(feature = "proof")]
+    pub(crate) async fn evm_rpc(&self, network: &str, method: &str, params: Value) -> Result<Value> {
+        let chain = self.chain(network)?;
+        if !chain.chain_type.is_evm() {
+            return Err(Error::Chain(format!("{} is not supported on {}", method, network).into()));
+        }
+        self.rpc(chain, method, params).await
+    }
+
+    /// Simulates the `transferWithAuthorization` call that settles a signed
+    /// authorization, using `eth_call` against the `token` contract.
+    ///
+    /// Returns the revert reason if the transfer would fail, `None` if it
+    /// would succeed.
+    pub async fn simulate_transfer(
+        &self,
+        network: &str,
+        token: &str,
+        authorization: &Authorization,
+        signature: &str,
+    ) -> Result<Option<String>> {
+        let chain = self.chain(network)?;
+        if !chain.chain_type.is_evm() {
+            return Err(Error::Chain(format!("Dry-run payments are not supported on {}", network).into()));
+        }
+
+        let signature = crypto::decode_hex(signature)?;
+        let (rs, v) = match signature.as_slice() {
+            [rs @ .., v] if rs.len() == 64 => (rs, *v),
+            _ => return Err(Error::Chain(format!("Invalid signature length {}", signature.len()).into())),
+        };
+        let v = if v < 27 { v + 27 } else { v };
+
+        let mut calldata = crypto::function_selector(TRANSFER_WITH_AUTHORIZATION_SIGNATURE).to_vec();
+        calldata.extend(crypto::address_word(&crypto::parse_address(&authorization.from)?));
+        calldata.extend(crypto::address_word(&crypto::parse_address(&authorization.to)?));
+        calldata.extend(crypto::decimal_word(&authorization.value)?);
+        calldata.extend(crypto::decimal_word(&authorization.valid_after)?);
+        calldata.extend(crypto::decimal_word(&authorization.valid_before)?);
+        calldata.extend(crypto::parse_bytes32(&authorization.nonce)?);
+        calldata.extend(crypto::uint_word(v.into()));
+        calldata.extend(rs);
+
+        let params = json!([
+            {
+                "from": authorization.from,
+                "to": token,
+                "data": format!("0x{}", hex::encode(calldata)),
+            },
+            "latest"
+        ]);
+
+        // A revert comes back as an RPC error and is the outcome, not a failure
+        let response = self.send_rpc_envelope(chain, "eth_call", params).await?;
+        Ok(response.get("error").map(|error| {
+            error
+                .get("message")
+                .and_then(Value::as_str)
+                .map_or_else(|| error.to_string(), str::to_string)
+        }))
+    }
+
+    /// Signs and sends an EIP-1559 transaction from the wallet with address
+    /// `from` calling `to` with `calldata`, returning its hash.
+    ///
+    /// The nonce, gas limit and fees are read from the chain; the limit
+    /// gets a 20% margin over the estimate. On [dry-run](ChainConfig::dry_run)
+    /// chains the call is only simulated with `eth_call` and `None` is
+    /// returned; a revert fails with [`Error::ContractReverted`].
+    pub(crate) async fn send_transaction(
+        &self,
+        network: &str,
+        from: &str,
+        to: &str,
+        calldata: &[u8],
+    ) -> Result<Option<String>> {
+        let chain = self.chain(network)?;
+        if !chain.chain_type.is_evm() {
+            return Err(Error::Chain(format!("Transactions are not supported on {}", network).into()));
+        }
+        let chain_id = chain
+            .chain_id
+            .ok_or_else(|| Error::Config(format!("No chain ID configured for {}", network).into()))?;
+        let signer = self.signer_for(from)?;
+
+        if chain.dry_run {
+            self.eth_call(chain, Some(from), to, calldata).await?;
+            info!(network, to, "Simulated transaction on dry-run chain");
+            return Ok(None);
+        }
+
+        let call = json!({
+            "from": from,
+            "to": to,
+            "data": format!("0x{}", hex::encode(calldata)),
+        });
+        let (nonce, gas, priority_fee, block) = futures::try_join!(
+            self.rpc(chain, "eth_getTransactionCount", json!([from, "pending"])),
+            self.rpc(chain, "eth_estimateGas", json!([call])),
+            self.rpc(chain, "eth_maxPriorityFeePerGas", json!([])),
+            self.rpc(chain, "eth_getBlockByNumber", json!(["latest", false])),
+        )?;
+        let priority_fee = quantity(&priority_fee)?;
+        let base_fee = block.get("baseFeePerGas").map(quantity).transpose()?.unwrap_or(0);
+
+        let transaction = Eip1559Transaction {
+            chain_id,
+            nonce: quantity(&nonce)?,
+            max_priority_fee_per_gas: priority_fee,
+            max_fee_per_gas: base_fee.saturating_mul(2).saturating_add(priority_fee),
+            gas_limit: quantity(&gas)?.saturating_mul(6) / 5,
+            to: crypto::parse_address(to)?,
+            data: calldata.to_vec(),
+        };
+        let signature = crypto::decode_hex(&signer.sign_hash(&transaction.signing_hash())?)?;
+        let raw = format!("0x{}", hex::encode(transaction.encode_signed(&signature)));
+
+        let tx_hash = self.rpc(chain, "eth_sendRawTransaction", json!([raw])).await?;
+        let tx_hash = tx_hash
+            .as_str()
+            .ok_or_else(|| Error::Chain(format!("Unexpected eth_sendRawTransaction result: {}", tx_hash).into()))?;
+        info!(network, tx_hash, "Sent transaction");
+        Ok(Some(tx_hash.to_string()))
+    }
+
+    /// Known tokens of all chains.
+    pub fn tokens(&self) -> &TokenRegistry {
+        &self.tokens
+    }
+
+    /// Returns the symbol and decimals of a token.
+    ///
+    /// Tokens missing from the registry are read from the token contract
+    /// with `symbol()` and `decimals()` once and cached, unless
+    /// [`Config::resolve_tokens_on_chain`] is disabled.
+    pub async fn resolve_token(&self, network: &str, address: &str) -> Result<TokenInfo> {
+        if let Some(token) = self.tokens.get(network, address) {
+            return Ok(token);
+        }
+
+        if !self.config.resolve_tokens_on_chain {
+            return Err(Error::Chain(format!("Unknown token {} on {}", address, network).into()));
+        }
+
+        let decimals = self
+            .call_view(network, address, &crypto::function_selector(tokens::DECIMALS_SIGNATURE))
+            .await?;
+        let decimals = u8::try_from(crypto::word_to_uint(&decimals)?)
+            .map_err(|_| Error::Chain(format!("Invalid decimals for token {}", address).into()))?;
+        let symbol = self
+            .call_view(network, address, &crypto::function_selector(tokens::SYMBOL_SIGNATURE))
+            .await?;
+        let symbol = tokens::decode_symbol(&symbol)?;
+
+        debug!(network, address, %symbol, decimals, "Resolved token on-chain");
+        let token = TokenInfo::new(address, symbol, decimals);
+        self.tokens.register(network, token.clone());
+        Ok(token)
+    }
+
+    /// Returns the balance of `owner` in units of `token`.
+    pub async fn token_balance(&self, network: &str, token: &str, owner: &str) -> Result<u128> {
+        let mut calldata = crypto::function_selector(tokens::BALANCE_OF_SIGNATURE).to_vec();
+        calldata.extend_from_slice(&crypto::address_word(&crypto::parse_address(owner)?));
+        let result = self.call_view(network, token, &calldata).await?;
+        crypto::word_to_uint(&result)
+    }
+
+    /// Returns `true` once a transaction has been included in a block.
+    ///
+    /// Uses `eth_getTransactionReceipt` on EVM chains and
+    /// `getSignatureStatuses` on Solana.
+    pub async fn transaction_confirmed(&self, network: &str, tx_hash: &str) -> Result<bool> {
+        Ok(self.transaction_status(network, tx_hash).await?.is_some())
+    }
+
+    /// Returns whether a transaction succeeded once it has been included in
+    /// a block, or `None` while it is pending.
+    ///
+    /// Reverted EVM transactions and failed Solana transactions are included
+    /// but did not succeed.
+    pub async fn transaction_status(&self, network: &str, tx_hash: &str) -> Result<Option<bool>> {
+        let chain = self.chain(network)?;
+        let status = match chain.chain_type {
+            ChainType::Solana => {
+                let result = self.rpc(chain, "getSignatureStatuses", json!([[tx_hash]])).await?;
+                result
+                    .pointer("/value/0")
+                    .filter(|status| !status.is_null())
+                    .map(|status| status["err"].is_null())
+            }
+            _ => {
+                let receipt = self.rpc(chain, "eth_getTransactionReceipt", json!([tx_hash])).await?;
+                (!receipt.is_null()).then(|| receipt.get("status").and_then(Value::as_str) != Some("0x0"))
+            }
+        };
+        Ok(status)
+    }
+
+    /// Checks connectivity to every configured network.
+    ///
+    /// Quarantined networks are reported unhealthy even if they respond.
+    pub async fn health_check(&self) -> Result<HashMap<String, bool>> {
+        let checks = self.config.chains.iter().map(|chain| async move {
+            let method = match chain.chain_type {
+                ChainType::Solana => "getHealth",
+                _ => "eth_chainId",
+            };
+            let healthy = match self.rpc(chain, method, json!([])).await {
+                Ok(_) => true,
+                Err(e) => {
+                    warn!(chain = %chain.name, error = %e, "Chain health check failed");
+                    self.health.lock().entry(chain.name.clone()).or_default().last_error = Some(e.to_string());
+                    false
+                }
+            };
+            (chain.name.clone(), healthy && !self.is_quarantined(&chain.name))
+        });
+
+        Ok(futures::future::join_all(checks).await.into_iter().collect())
+    }
+
+    /// Opens the RPC connection of `network` and fetches its gas price and
+    /// the pending nonces of the configured wallets.
+    pub(crate) async fn warm_up(&self, network: &str) -> Result<()> {
+        let chain = self.chain(network)?;
+        if !chain.chain_type.is_evm() {
+            self.rpc(chain, "getLatestBlockhash", json!([])).await?;
+            return Ok(());
+        }
+
+        let nonces = self
+            .wallets()
+            .into_iter()
+            .filter_map(|wallet| self.wallet_address(&wallet, network).ok())
+            .map(|address| self.rpc(chain, "eth_getTransactionCount", json!([address, "pending"])));
+        futures::try_join!(
+            self.rpc(chain, "eth_gasPrice", json!([])),
+            futures::future::try_join_all(nonces),
+        )?;
+        Ok(())
+    }
+
+    /// Returns the gas price of the EVM network `network` in gwei.
+    pub async fn gas_price_gwei(&self, network: &str) -> Result<f64> {
+        let chain = self.chain(network)?;
+        if !chain.chain_type.is_evm() {
+            return Err(Error::Chain(format!("{} has no gas price", network).into()));
+        }
+        let result = self.rpc(chain, "eth_gasPrice", json!([])).await?;
+        let wei = result
+            .as_str()
+            .and_then(|price| u128::from_str_radix(price.trim_start_matches("0x"), 16).ok())
+            .ok_or_else(|| Error::Chain(format!("Invalid eth_gasPrice result: {}", result).into()))?;
+        Ok(wei as f64 / 1e9)
+    }
+
+    /// Starts polling the gas price of every EVM chain each
+    /// [`GAS_POLL_INTERVAL`], calling `callback` when one crosses the
+    /// threshold set on the returned handle.
+    ///
+    /// Prices are exported as the `v402_chain_gas_price_gwei{chain}` gauge
+    /// of the client's metrics. The monitor stops when the handle is dropped.
+    pub fn monitor_gas_prices(self: &Arc<Self>, callback: impl Fn(GasAlert) + Send + 'static) -> GasMonitorHandle {
+        let chains = self
+            .config
+            .chains
+            .iter()
+            .filter(|chain| chain.chain_type.is_evm())
+            .map(|chain| chain.name.clone())
+            .collect();
+        GasMonitorHandle::start(self.clone(), chains, GAS_POLL_INTERVAL, self.metrics.clone(), callback)
+    }
+
+    /// Releases chain connections.
+    ///
+    /// Health monitors are stopped by the client's task manager.
+    pub async fn close(&self) -> Result<()> {
+        debug!("Closing chain manager");
+        Ok(())
+    }
+
+    /// Sends a JSON-RPC request to a chain's RPC endpoint, counting failures
+    /// towards the chain's error rate.
+    async fn rpc(&self, chain: &ChainConfig, method: &str, params: Value) -> Result<Value> {
+        let result = self.send_rpc(chain, method, params).await;
+        self.count_rpc(chain, result.is_err());
+        result
+    }
+
+    /// Counts an RPC call towards the chain's error rate.
+    fn count_rpc(&self, chain: &ChainConfig, failed: bool) {
+        let mut health = self.health.lock();
+        let state = health.entry(chain.name.clone()).or_default();
+        state.rpc_calls += 1;
+        if failed {
+            state.rpc_errors += 1;
+        }
+    }
+
+    /// Sends a JSON-RPC request.
+    async fn send_rpc(&self, chain: &ChainConfig, method: &str, params: Value) -> Result<Value> {
+        let response = self.send_rpc_envelope(chain, method, params).await?;
+        if let Some(error) = response.get("error") {
+            return Err(Error::Chain(format!("{} RPC error: {}", chain.name, error).into()));
+        }
+
+        response
+            .get("result")
+            .cloned()
+            .ok_or_else(|| Error::Chain(format!("{} RPC response has no result", chain.name).into()))
+    }
+
+    /// Sends a JSON-RPC request and returns the whole response object,
+    /// including any `error` member.
+    async fn send_rpc_envelope(&self, chain: &ChainConfig, method: &str, params: Value) -> Result<Value> {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": self.next_rpc_id.fetch_add(1, Ordering::Relaxed),
+            "method": method,
+            "params": params,
+        });
+
+        let response: Value = self
+            .rpc_client
+            .post(&chain.rpc_url)
+            .json(&request)
+            .send()
+            .await
+            .map_err(|e| dns_error(&e).unwrap_or_else(|| Error::Network(format!("RPC request to {} failed: {}", chain.name, e).into())))?
+            .json()
+            .await
+            .map_err(|e| Error::Chain(format!("Invalid RPC response from {}: {}", chain.name, e).into()))?;
+
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl GasOracle for ChainManager {
+    async fn gas_price_gwei(&self, chain: &str) -> Result<f64> {
+        ChainManager::gas_price_gwei(self, chain).await
+    }
+}
+
+/// Selector of the `Error(string)` revert of `require` and `revert` with a message.
+const ERROR_STRING_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+
+/// Selector of the `Panic(uint256)` revert of failed assertions and arithmetic errors.
+const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+/// Returns the reason and data of a revert reported as a JSON-RPC error,
+/// or `None` if the error is not a revert.
+///
+/// Nodes put the revert data in `data`, as a hex string or nested in an
+/// object; without data, a reason may still follow `execution reverted` in
+/// the message.
+fn decode_revert(error: &Value) -> Option<(String, Vec<u8>)> {
+    let message = error.get("message").and_then(Value::as_str).unwrap_or_default();
+    let data = match error.get("data") {
+        Some(Value::String(data)) => Some(data.as_str()),
+        Some(data) => data.get("data").and_then(Value::as_str),
+        None => None,
+    }
+    .and_then(|data| crypto::decode_hex(data).ok());
+
+    match data {
+        Some(data) if !data.is_empty() => Some((revert_reason(&data), data)),
+        _ if message.contains("revert") => {
+            let reason = message
+                .split_once("reverted")
+                .map(|(_, reason)| reason.trim_start_matches(':').trim())
+                .filter(|reason| !reason.is_empty())
+                .unwrap_or("no reason given");
+            Some((reason.to_string(), Vec::new()))
+        }
+        _ => None,
+    }
+}
+
+/// Decodes revert data: the message of `Error(string)`, the code of
+/// `Panic(uint256)` or the selector of a custom error.
+fn revert_reason(data: &[u8]) -> String {
+    let (selector, payload) = data.split_at(data.len().min(4));
+    if selector == ERROR_STRING_SELECTOR {
+        let message = payload.get(32..64).and_then(|len| crypto::word_to_uint(len).ok()).and_then(|len| {
+            let len = usize::try_from(len).ok()?;
+            payload.get(64..64usize.checked_add(len)?)
+        });
+        if let Some(message) = message {
+            return String::from_utf8_lossy(message).into_owned();
+        }
+    }
+    if selector == PANIC_SELECTOR {
+        if let Some(code) = payload.get(..32).and_then(|code| crypto::word_to_uint(code).ok()) {
+            let cause = match code {
+                0x01 => "assertion failed",
+                0x11 => "arithmetic overflow or underflow",
+                0x12 => "division by zero",
+                0x21 => "invalid enum value",
+                0x31 => "pop from empty array",
+                0x32 => "array index out of bounds",
+                0x41 => "out of memory",
+                _ => "panic",
+            };
+            return format!("{} (panic code 0x{:02x})", cause, code);
+        }
+    }
+    format!("custom error 0x{}", hex::encode(selector))
+}
+
+/// RPC method returning the latest block of `chain`, parsed by [`parse_block_number`].
+fn block_number_method(chain: &ChainConfig) -> &'static str {
+    match chain.chain_type {
+        ChainType::Solana => "getSlot",
+        _ => "eth_blockNumber",
+    }
+}
+
+/// Parses the result of `eth_blockNumber` (hex) or `getSlot` (integer).
+fn parse_block_number(chain: &ChainConfig, result: &Value) -> Result<u64> {
+    let block = match result {
+        Value::String(hex) => u64::from_str_radix(hex.trim_start_matches("0x"), 16).ok(),
+        value => value.as_u64(),
+    };
+    block.ok_or_else(|| Error::Chain(format!("Invalid block number from {}: {}", chain.name, result).into()))
+}
+
+/// Returns why a successful RPC check still fails the chain's thresholds, if it does.
+fn failed_threshold(
+    config: &ChainHealthConfig,
+    latency: Duration,
+    stalled_for: Duration,
+    error_rate: f64,
+) -> Option<String> {
+    if latency > config.max_latency {
+        Some(format!(
+            "RPC latency {}ms exceeds {}ms",
+            latency.as_millis(),
+            config.max_latency.as_millis()
+        ))
+    } else if stalled_for > config.stall_timeout {
+        Some(format!("No new block for {}s", stalled_for.as_secs()))
+    } else if error_rate > config.max_error_rate {
+        Some(format!("{:.0}% of RPC calls failed", error_rate * 100.0))
+    } else {
+        None
+    }
+}
+
+/// Parses a JSON-RPC hex quantity.
+fn quantity(value: &Value) -> Result<u64> {
+    value
+        .as_str()
+        .and_then(|quantity| u64::from_str_radix(quantity.trim_start_matches("0x"), 16).ok())
+        .ok_or_else(|| Error::Chain(format!("Invalid quantity in RPC result: {}", value).into()))
+}
+
+/// An EIP-1559 transaction sending no value.
+struct Eip1559Transaction {
+    chain_id: u64,
+    nonce: u64,
+    max_priority_fee_per_gas: u64,
+    max_fee_per_gas: u64,
+    gas_limit: u64,
+    to: [u8; 20],
+    data: Vec<u8>,
+}
+
+impl Eip1559Transaction {
+    /// Type byte of EIP-1559 transactions.
+    const TYPE: u8 = 0x02;
+
+    /// RLP-encoded fields covered by the signature.
+    fn fields(&self) -> Vec<Vec<u8>> {
+        vec![
+            crypto::rlp_uint(self.chain_id),
+            crypto::rlp_uint(self.nonce),
+            crypto::rlp_uint(self.max_priority_fee_per_gas),
+            crypto::rlp_uint(self.max_fee_per_gas),
+            crypto::rlp_uint(self.gas_limit),
+            crypto::rlp_bytes(&self.to),
+            crypto::rlp_uint(0),
+            crypto::rlp_bytes(&self.data),
+            crypto::rlp_list(&[]),
+        ]
+    }
+
+    /// Hash the sender signs.
+    fn signing_hash(&self) -> [u8; 32] {
+        let mut payload = vec![Self::TYPE];
+        payload.extend(crypto::rlp_list(&self.fields()));
+        crypto::keccak256(&payload)
+    }
+
+    /// Encodes the transaction with its 65-byte `r || s || v` signature for `eth_sendRawTransaction`.
+    fn encode_signed(&self, signature: &[u8]) -> Vec<u8> {
+        // Signatures are integers in the envelope, without leading zeros
+        let integer = |bytes: &[u8]| {
+            let start = bytes.iter().take_while(|byte| **byte == 0).count();
+            crypto::rlp_bytes(&bytes[start..])
+        };
+        let mut fields = self.fields();
+        fields.push(crypto::rlp_uint(u64::from(signature[64].saturating_sub(27))));
+        fields.push(integer(&signature[..32]));
+        fields.push(integer(&signature[32..64]));
+
+        let mut encoded = vec![Self::TYPE];
+        encoded.extend(crypto::rlp_list(&fields));
+        encoded
+    }
+}
@@ -0,0 +1,145 @@
+//! Typed network identifiers.
+
+use std::{convert::Infallible, fmt, str::FromStr};
+
+/// Network a payment is made on, named as in v402 payment requirements.
+///
+/// Parsing never fails: names are matched case-insensitively against the
+/// canonical names and common aliases such as `eth`, `mainnet` or
+/// `base-mainnet`, and anything else is kept, lowercased, as
+/// [`Other`](NetworkId::Other). Serialized as the canonical name.
+///
+/// ```rust
+/// use v402_client::NetworkId;
+///
+/// assert_eq!("ETH".parse::<NetworkId>().unwrap(), NetworkId::Ethereum);
+/// assert_eq!(NetworkId::from("base_mainnet"), NetworkId::Base);
+/// assert_eq!(NetworkId::BaseSepolia.as_str(), "base-sepolia");
+/// assert_eq!(NetworkId::from("Sei"), NetworkId::Other("sei".to_string()));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum NetworkId {
+    /// Ethereum mainnet, `ethereum`
+    Ethereum,
+    /// Ethereum Sepolia testnet, `ethereum-sepolia`
+    EthereumSepolia,
+    /// Base mainnet, `base`
+    Base,
+    /// Base Sepolia testnet, `base-sepolia`
+    BaseSepolia,
+    /// Polygon PoS mainnet, `polygon`
+    Polygon,
+    /// Arbitrum One, `arbitrum`
+    Arbitrum,
+    /// OP Mainnet, `optimism`
+    Optimism,
+    /// Avalanche C-Chain, `avalanche`
+    Avalanche,
+    /// Avalanche Fuji testnet, `avalanche-fuji`
+    AvalancheFuji,
+    /// BNB Smart Chain, `bsc`
+    Bsc,
+    /// BNB Smart Chain testnet, `bsc-testnet`
+    BscTestnet,
+    /// Solana mainnet, `solana`
+    Solana,
+    /// Solana devnet, `solana-devnet`
+    SolanaDevnet,
+    /// Any other network, by its lowercased name
+    Other(String),
+}
+
+/// Known networks by canonical name and aliases, all lowercase with `-` separators.
+const NETWORKS: &[(NetworkId, &str, &[&str])] = &[
+    (NetworkId::Ethereum, "ethereum", &["eth", "mainnet", "ethereum-mainnet", "eth-mainnet"]),
+    (NetworkId::EthereumSepolia, "ethereum-sepolia", &["sepolia", "eth-sepolia"]),
+    (NetworkId::Base, "base", &["base-mainnet"]),
+    (NetworkId::BaseSepolia, "base-sepolia", &["base-testnet"]),
+    (NetworkId::Polygon, "polygon", &["matic", "polygon-mainnet", "polygon-pos"]),
+    (NetworkId::Arbitrum, "arbitrum", &["arbitrum-one", "arbitrum-mainnet", "arb"]),
+    (NetworkId::Optimism, "optimism", &["op", "op-mainnet", "optimism-mainnet"]),
+    (NetworkId::Avalanche, "avalanche", &["avax", "avalanche-c", "avalanche-mainnet"]),
+    (NetworkId::AvalancheFuji, "avalanche-fuji", &["fuji", "avax-fuji"]),
+    (NetworkId::Bsc, "bsc", &["bnb", "binance", "bsc-mainnet"]),
+    (NetworkId::BscTestnet, "bsc-testnet", &["bnb-testnet"]),
+    (NetworkId::Solana, "solana", &["sol", "solana-mainnet", "mainnet-beta"]),
+    (NetworkId::SolanaDevnet, "solana-devnet", &["sol-devnet"]),
+];
+
+impl NetworkId {
+    /// Canonical name, as used in payment requirements.
+    pub fn as_str(&self) -> &str {
+        match self {
+            NetworkId::Other(name) => name,
+            known => NETWORKS
+                .iter()
+                .find(|(id, ..)| id == known)
+                .map(|(_, name, _)| *name)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Returns `true` for networks not known by name.
+    pub fn is_other(&self) -> bool {
+        matches!(self, NetworkId::Other(_))
+    }
+}
+
+impl FromStr for NetworkId {
+    type Err = Infallible;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let normalized = name.trim().to_ascii_lowercase().replace('_', "-");
+        let known = NETWORKS
+            .iter()
+            .find(|(_, canonical, aliases)| *canonical == normalized || aliases.contains(&normalized.as_str()));
+        Ok(match known {
+            Some((id, ..)) => id.clone(),
+            None => NetworkId::Other(normalized),
+        })
+    }
+}
+
+impl From<&str> for NetworkId {
+    fn from(name: &str) -> Self {
+        match name.parse() {
+            Ok(id) => id,
+            Err(never) => match never {},
+        }
+    }
+}
+
+impl From<String> for NetworkId {
+    fn from(name: String) -> Self {
+        NetworkId::from(name.as_str())
+    }
+}
+
+impl From<NetworkId> for String {
+    fn from(id: NetworkId) -> Self {
+        match id {
+            NetworkId::Other(name) => name,
+            known => known.as_str().to_string(),
+        }
+    }
+}
+
+impl fmt::Display for NetworkId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl PartialEq<str> for NetworkId {
+    fn eq(&self, other: &str) -> bool {
+        let other = NetworkId::from(other);
+        *self == other
+    }
+}
+
+impl PartialEq<&str> for NetworkId {
+    fn eq(&self, other: &&str) -> bool {
+        *self == **other
+    }
+}
@@ -0,0 +1,220 @@
+//! Token metadata used to display and budget payment amounts.
+//!
+//! Payment requirements name assets by contract address only. The
+//! [`TokenRegistry`] maps `(network, address)` to a symbol and decimals so
+//! amounts can be shown as `1.5 USDC` instead of `1500000`. It is preloaded
+//! with the major stablecoins and WETH on every built-in network, extended
+//! with the tokens registered on each [`ChainConfig`](crate::ChainConfig),
+//! and filled in from the token contract for anything else when
+//! [`Config::resolve_tokens_on_chain`](crate::Config::resolve_tokens_on_chain)
+//! is enabled.
+
+use crate::{
+    crypto,
+    error::{Error, Result},
+};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Signature of the ERC-20 `decimals` view function.
+pub(crate) const DECIMALS_SIGNATURE: &str = "decimals()";
+
+/// Signature of the ERC-20 `symbol` view function.
+pub(crate) const SYMBOL_SIGNATURE: &str = "symbol()";
+
+/// Signature of the ERC-20 `balanceOf` view function.
+pub(crate) const BALANCE_OF_SIGNATURE: &str = "balanceOf(address)";
+
+/// Tokens known without configuration: `(network, address, symbol, decimals)`.
+const KNOWN_TOKENS: &[(&str, &str, &str, u8)] = &[
+    // Ethereum
+    ("ethereum", "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48", "USDC", 6),
+    ("ethereum", "0xdAC17F958D2ee523a2206206994597C13D831ec7", "USDT", 6),
+    ("ethereum", "0x6B175474E89094C44Da98b954EedeAC495271d0F", "DAI", 18),
+    ("ethereum", "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2", "WETH", 18),
+    ("ethereum-sepolia", "0x1c7D4B196Cb0C7B01d743Fbc6116a902379C7238", "USDC", 6),
+    // Base
+    ("base", "0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913", "USDC", 6),
+    ("base", "0x50c5725949A6F0c72E6C4a641F24049A917DB0Cb", "DAI", 18),
+    ("base", "0x4200000000000000000000000000000000000006", "WETH", 18),
+    ("base-sepolia", "0x036CbD53842c5426634e7929541eC2318f3dCF7e", "USDC", 6),
+    // Polygon
+    ("polygon", "0x3c499c542cEF5E3811e1192ce70d8cC03d5c3359", "USDC", 6),
+    ("polygon", "0xc2132D05D31c914a87C6611C10748AEb04B58e8F", "USDT", 6),
+    ("polygon", "0x8f3Cf7ad23Cd3CaDbD9735AFf958023239c6A063", "DAI", 18),
+    ("polygon", "0x7ceB23fD6bC0adD59E62ac25578270cFf1b9f619", "WETH", 18),
+    // Arbitrum
+    ("arbitrum", "0xaf88d065e77c8cC2239327C5EDb3A432268e5831", "USDC", 6),
+    ("arbitrum", "0xFd086bC7CD5C481DCC9C85ebE478A1C0b69FCbb9", "USDT", 6),
+    ("arbitrum", "0xDA10009cBd5D07dd0CeCc66161FC93D7c9000da1", "DAI", 18),
+    ("arbitrum", "0x82aF49447D8a07e3bd95BD0d56f35241523fBab1", "WETH", 18),
+    // Optimism
+    ("optimism", "0x0b2C639c533813f4Aa9D7837CAf62653d097Ff85", "USDC", 6),
+    ("optimism", "0x94b008aA00579c1307B0EF2c499aD98a8ce58e58", "USDT", 6),
+    ("optimism", "0xDA10009cBd5D07dd0CeCc66161FC93D7c9000da1", "DAI", 18),
+    ("optimism", "0x4200000000000000000000000000000000000006", "WETH", 18),
+    // Avalanche
+    ("avalanche", "0xB97EF9Ef8734C71904D8002F8b6Bc66Dd9c48a6E", "USDC", 6),
+    ("avalanche", "0x9702230A8Ea53601f5cD2dc00fDBc13d4dF4A8c7", "USDT", 6),
+    ("avalanche", "0xd586E7F844cEa2F87f50152665BCbc2C279D8d70", "DAI.e", 18),
+    ("avalanche", "0x49D5c2BdFfac6CE2BFdB6640F4F80f226bc10bAB", "WETH.e", 18),
+    ("avalanche-fuji", "0x5425890298aed601595a70AB815c96711a31Bc65", "USDC", 6),
+    // BNB Smart Chain (bridged stablecoins use 18 decimals)
+    ("bsc", "0x8AC76a51cc950d9822D68b83fE1Ad97B32Cd580d", "USDC", 18),
+    ("bsc", "0x55d398326f99059fF775485246999027B3197955", "USDT", 18),
+    ("bsc", "0x1AF3F329e8BE154074D8769D1FFa4eE058B1DBc3", "DAI", 18),
+    // Solana
+    ("solana", "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v", "USDC", 6),
+    ("solana", "Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB", "USDT", 6),
+    ("solana-devnet", "4zMMC9srt5Ri5X14GAgXhaHii3GnPAEERYPJgZJDncDU", "USDC", 6),
+];
+
+/// Symbol and decimals of a token.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenInfo {
+    /// Token contract address (mint address on Solana)
+    pub address: String,
+
+    /// Ticker symbol, e.g. `USDC`
+    pub symbol: String,
+
+    /// Number of decimals of the smallest unit
+    pub decimals: u8,
+}
+
+impl TokenInfo {
+    /// Creates token metadata.
+    pub fn new<A: Into<String>, S: Into<String>>(address: A, symbol: S, decimals: u8) -> Self {
+        Self {
+            address: address.into(),
+            symbol: symbol.into(),
+            decimals,
+        }
+    }
+
+    /// Formats an amount in the token's smallest unit, e.g. `1.5 USDC`.
+    pub fn format_amount(&self, amount: &str) -> String {
+        format!("{} {}", format_units(amount, self.decimals), self.symbol)
+    }
+}
+
+/// Known tokens per network.
+#[derive(Debug)]
+pub struct TokenRegistry {
+    /// Tokens keyed by `(network, normalized address)`
+    tokens: RwLock<HashMap<(String, String), TokenInfo>>,
+}
+
+impl Default for TokenRegistry {
+    fn default() -> Self {
+        let registry = Self::empty();
+        for (network, address, symbol, decimals) in KNOWN_TOKENS {
+            registry.register(network, TokenInfo::new(*address, *symbol, *decimals));
+        }
+        registry
+    }
+}
+
+impl TokenRegistry {
+    /// Creates a registry preloaded with the built-in tokens.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a registry without any tokens.
+    pub fn empty() -> Self {
+        Self {
+            tokens: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Adds or replaces a token on `network`.
+    pub fn register(&self, network: &str, token: TokenInfo) {
+        let key = (network.to_string(), normalize_address(&token.address));
+        self.tokens.write().insert(key, token);
+    }
+
+    /// Looks up a token by address.
+    pub fn get(&self, network: &str, address: &str) -> Option<TokenInfo> {
+        let key = (network.to_string(), normalize_address(address));
+        self.tokens.read().get(&key).cloned()
+    }
+
+    /// Formats an amount for display.
+    ///
+    /// Known tokens are shown with their symbol and decimals. Unknown tokens
+    /// are shown in raw units with a shortened address rather than guessing
+    /// their decimals, e.g. `1500000 units of 0x1234…cdef`.
+    pub fn format_amount(&self, network: &str, address: &str, amount: &str) -> String {
+        match self.get(network, address) {
+            Some(token) => token.format_amount(amount),
+            None => format!("{} units of {}", amount, truncate_address(address)),
+        }
+    }
+}
+
+/// Formats an integer amount of smallest units with `decimals` decimal places,
+/// dropping trailing zeros, e.g. `1500000` with 6 decimals is `1.5`.
+pub fn format_units(amount: &str, decimals: u8) -> String {
+    let decimals = decimals as usize;
+    let raw = amount.trim_start_matches('0');
+
+    let (int_part, frac_part) = if raw.len() > decimals {
+        raw.split_at(raw.len() - decimals)
+    } else {
+        ("", raw)
+    };
+
+    let frac = format!("{:0>width$}", frac_part, width = decimals);
+    let frac = frac.trim_end_matches('0');
+    let int_part = if int_part.is_empty() { "0" } else { int_part };
+
+    if frac.is_empty() {
+        int_part.to_string()
+    } else {
+        format!("{}.{}", int_part, frac)
+    }
+}
+
+/// Shortens an address to its first six and last four characters.
+pub fn truncate_address(address: &str) -> String {
+    if address.len() <= 12 || !address.is_ascii() {
+        return address.to_string();
+    }
+    format!("{}…{}", &address[..6], &address[address.len() - 4..])
+}
+
+/// EVM addresses are case-insensitive; Solana's base58 addresses are not.
+fn normalize_address(address: &str) -> String {
+    if address.starts_with("0x") || address.starts_with("0X") {
+        address.to_ascii_lowercase()
+    } else {
+        address.to_string()
+    }
+}
+
+/// Decodes the return value of `symbol()`.
+///
+/// Accepts the standard ABI-encoded `string` as well as the `bytes32` used
+/// by some early tokens.
+pub(crate) fn decode_symbol(data: &[u8]) -> Result<String> {
+    let invalid = || Error::Chain("Invalid symbol() return data".to_string().into());
+
+    let bytes = if data.len() == 32 {
+        let end = data.iter().position(|b| *b == 0).unwrap_or(32);
+        &data[..end]
+    } else {
+        let offset = usize::try_from(crypto::word_to_uint(data.get(..32).ok_or_else(invalid)?)?)
+            .map_err(|_| invalid())?;
+        let length_word = data.get(offset..offset + 32).ok_or_else(invalid)?;
+        let length = usize::try_from(crypto::word_to_uint(length_word)?).map_err(|_| invalid())?;
+        data.get(offset + 32..offset + 32 + length).ok_or_else(invalid)?
+    };
+
+    let symbol = std::str::from_utf8(bytes).map_err(|_| invalid())?.trim();
+    if symbol.is_empty() {
+        return Err(invalid());
+    }
+    Ok(symbol.to_string())
+}
@@ -0,0 +1,100 @@
+//! Authentication challenges on paid requests.
+//!
+//! Some providers want a session as well as a payment: the paid request is
+//! answered with `401 Unauthorized` or `403 Forbidden` and a challenge, and
+//! content is only served to a follow-up request proving who paid. A
+//! [`ChallengeHandler`] answers such challenges with extra headers, and the
+//! client retries the request once with both the payment and those headers.
+//!
+//! Without a handler, these responses are returned as they are.
+//!
+//! ```rust,no_run
+//! use v402_client::{crypto::SiweHandler, Client};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let client = Client::builder()
+//!     .challenge_handler(Box::new(SiweHandler::new().statement("Sign in to read paid articles")))
+//!     .build()
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{chains::ChainManager, error::Result};
+use async_trait::async_trait;
+use std::{collections::HashMap, fmt};
+
+/// A `401` or `403` response to a paid request, and the wallet that paid.
+#[derive(Debug)]
+pub struct Challenge<'a> {
+    /// URL of the request
+    pub url: &'a str,
+
+    /// Status of the response, `401` or `403`
+    pub status: u16,
+
+    /// Headers of the response, such as `WWW-Authenticate`
+    pub headers: &'a HashMap<String, String>,
+
+    /// Body of the response
+    pub body: &'a [u8],
+
+    /// Address of the wallet that paid
+    pub address: &'a str,
+
+    /// Chain ID of the network paid on, if known
+    pub chain_id: Option<u64>,
+
+    chain_manager: &'a ChainManager,
+}
+
+impl<'a> Challenge<'a> {
+    /// Creates a challenge for a response to a request paid by `address`.
+    pub(crate) fn new(
+        url: &'a str,
+        status: u16,
+        headers: &'a HashMap<String, String>,
+        body: &'a [u8],
+        address: &'a str,
+        chain_id: Option<u64>,
+        chain_manager: &'a ChainManager,
+    ) -> Self {
+        Self {
+            url,
+            status,
+            headers,
+            body,
+            address,
+            chain_id,
+            chain_manager,
+        }
+    }
+
+    /// Returns the value of a response header, matching its name case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&'a str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Signs `message` with EIP-191 `personal_sign` using the key of the
+    /// wallet that paid, returning the 65-byte signature as hex.
+    pub fn sign_message(&self, message: &[u8]) -> Result<String> {
+        self.chain_manager.sign_message(self.address, message)
+    }
+}
+
+/// Answers authentication challenges received for paid requests.
+///
+/// Set with [`ClientBuilder::challenge_handler`](crate::ClientBuilder::challenge_handler)
+/// or [`Client::set_challenge_handler`](crate::Client::set_challenge_handler).
+#[async_trait]
+pub trait ChallengeHandler: Send + Sync + fmt::Debug {
+    /// Returns the headers to retry the request with, or `None` to return
+    /// the response as it is.
+    ///
+    /// An error fails the request.
+    async fn respond(&self, challenge: &Challenge<'_>) -> Result<Option<HashMap<String, String>>>;
+}
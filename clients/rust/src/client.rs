@@ -1,26 +1,58 @@
 //! High-performance async v402 client implementation.
 
 use crate::{
-    config::Config,
-    error::{Error, Result},
+    audit::{self, AuditAction, AuditLog, AuditTrail},
+    body::Body,
+    config::{Config, PaymentRequiredBehavior, PriceJumpAction, RetryConfig},
+    error::{Error, ErrorContext, Result},
     middleware::{Middleware, MiddlewareStack},
-    types::{PaymentResponse, PaymentHistory, PaymentStatistics, HealthStatus},
-    http::HttpClient,
-    payment::PaymentManager,
-    chains::ChainManager,
-    cache::CacheManager,
-    metrics::MetricsCollector,
+    types::{
+        BatchResponse, BatchSummary, HealthStatus, HostStatistics, PaymentCheckResult, PaymentHistory,
+        PaymentResponse, PaymentStatistics, PaymentTiming, PrefetchReport, Priority, RequestOptions, Validator,
+        WarmUpReport, WarmUpResult,
+    },
+    limiter::PriorityLimiter,
+    http::{self, ConnectionPoolStats, HttpClient, StreamSlot},
+    payment::{
+        export::{self, DateRange, ExportFormat},
+        time_phase, PaymentApprover, PaymentHints, PaymentManager, PaymentRequirements, PendingPayment, PollOptions,
+        Receipt, Settlement, PAYMENT_REQUIRED_HEADER,
+        SpendAlertHandler,
+        PAYMENT_METADATA_HEADER, PAYMENT_RECEIPT_HEADER,
+    },
+    chains::{ChainHealth, ChainManager, GasAlert, GasMonitorHandle, NetworkId},
+    challenge::{Challenge, ChallengeHandler},
+    download::{self, DownloadOptions, DownloadReport, PartialDownload},
+    events::{ClientEvent, EventBus},
+    cache::{CacheKey, CacheManager, CacheStats},
+    degradation::{self, Component, Degradation, DegradedComponent},
+    metrics::{MetricsCollector, MetricsCounters, MetricsSnapshot},
+    verify::{ResponseVerifier, VerifierSet},
+    report::{SpendReporter, SpendSink, SpendTotals},
+    tasks::{Restart, TaskManager},
 };
-use async_trait::async_trait;
-use futures::future::try_join_all;
+use arc_swap::ArcSwap;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use futures::{stream::Stream, FutureExt};
 use parking_lot::RwLock;
+use serde::Serialize;
 use std::{
-    collections::HashMap,
-    sync::{atomic::{AtomicBool, AtomicU64, Ordering}, Arc},
+    collections::{BTreeMap, HashMap},
+    fmt,
+    panic::AssertUnwindSafe,
+    path::Path,
+    pin::Pin,
+    sync::{atomic::{AtomicBool, AtomicU64, Ordering}, Arc, Weak},
+    task::{Context, Poll},
     time::{Duration, Instant},
 };
-use tokio::{sync::Semaphore, time::timeout};
-use tracing::{debug, error, info, instrument, warn};
+use tokio::{
+    io::AsyncWrite,
+    sync::{broadcast, mpsc, OwnedSemaphorePermit, Semaphore},
+    task::JoinSet,
+    time::MissedTickBehavior,
+};
+use tracing::{debug, error, info, info_span, instrument, warn, Instrument};
 use uuid::Uuid;
 
 /// High-performance async client for the v402 protocol.
@@ -53,7 +85,7 @@ use uuid::Uuid;
 /// - **Memory-efficient** batch processing with semaphore-based limiting
 /// - **Circuit breaker** pattern for automatic failure recovery
 /// - **Comprehensive observability** with metrics and distributed tracing
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct Client {
     /// Client configuration (immutable after creation)
     config: Arc<Config>,
@@ -67,15 +99,36 @@ pub struct Client {
     /// Multi-chain manager
     chain_manager: Arc<ChainManager>,
     
-    /// Response cache manager
-    cache_manager: Arc<CacheManager>,
+    /// Response cache manager, replaced when the cache degrades or recovers
+    cache_manager: Arc<ArcSwap<CacheManager>>,
     
     /// Metrics collector
     metrics: Arc<MetricsCollector>,
     
+    /// Cache and metrics failures the client continues without
+    degradation: Arc<Degradation>,
+    
     /// Middleware stack for request/response processing
     middleware_stack: Arc<MiddlewareStack>,
     
+    /// Integrity checks run on responses before they are cached
+    verifiers: Arc<VerifierSet>,
+    
+    /// Answers authentication challenges to paid requests
+    challenge_handler: Arc<RwLock<Option<Arc<dyn ChallengeHandler>>>>,
+    
+    /// Priority-aware limit on requests in flight
+    limiter: Arc<PriorityLimiter>,
+    
+    /// Event bus for subscribers
+    events: EventBus,
+    
+    /// Background tasks, stopped on close
+    tasks: Arc<TaskManager>,
+    
+    /// Record of the configuration and changes made to it
+    audit: Arc<AuditTrail>,
+    
     /// Client state
     state: Arc<ClientState>,
 }
@@ -96,8 +149,33 @@ struct ClientState {
     instance_id: Uuid,
 }
 
+/// How far a request got, reported in the [`ErrorContext`] of its errors.
+#[derive(Debug, Default)]
+struct Attempts {
+    /// Times the request has been sent
+    sent: u32,
+    /// Whether it has been sent with a signed payment
+    payment_attempted: bool,
+}
+
+/// Response to a request sent with a payment, with the payment that was accepted.
+#[derive(Debug)]
+struct PaidResponse {
+    response: PaymentResponse,
+    /// `X-PAYMENT` header of the last attempt
+    payment_header: String,
+    /// Time the last attempt was sent
+    paid_at: chrono::DateTime<chrono::Utc>,
+    /// Label of the wallet that paid
+    wallet: String,
+    /// Facilitator the payment was made through
+    facilitator: String,
+}
+
 /// Client statistics for monitoring and debugging.
-#[derive(Debug, Clone, Default)]
+///
+/// Request durations are tracked by the [`MetricsCollector`] histograms.
+#[derive(Debug, Clone)]
 struct ClientStats {
     /// Total requests made
     total_requests: u64,
@@ -114,11 +192,8 @@ struct ClientStats {
     /// Total amount paid (in wei)
     total_amount_paid: u128,
     
-    /// Average request duration
-    average_duration: Duration,
-    
-    /// Client start time
-    start_time: Instant,
+    /// Total amount paid per network
+    paid_by_network: BTreeMap<String, u128>,
 }
 
 impl Client {
@@ -140,7 +215,7 @@ impl Client {
     /// 
     /// # Example
     /// 
-    /// ```rust
+    /// ```rust,no_run
     /// use v402_client::{Client, Config};
     /// 
     /// # #[tokio::main]
@@ -154,37 +229,79 @@ impl Client {
     /// # }
     /// ```
     #[instrument(skip_all, fields(chains = config.chains.len()))]
-    pub async fn new(config: Config) -> Result<Self> {
+    pub async fn new(mut config: Config) -> Result<Self> {
         info!("Initializing v402 client");
         
+        // Fetch the private key from the secret store
+        config.load_secrets().await?;
+        
         let config = Arc::new(config);
         let instance_id = Uuid::new_v4();
         
         // Initialize HTTP client
         let http_client = Arc::new(HttpClient::new(&config).await?);
         
-        // Initialize chain manager
-        let chain_manager = Arc::new(ChainManager::new(&config).await?);
+        // Initialize event bus
+        let events = EventBus::new();
+        
+        // Initialize audit trail
+        let audit = Arc::new(AuditTrail::new(config.audit_log_capacity, events.clone()));
+        
+        // Initialize background task manager
+        let tasks = Arc::new(TaskManager::new());
+        
+        // Initialize degradation tracking for the optional components
+        let degradation = Arc::new(Degradation::new(config.degradation_policy));
+        
+        // Initialize metrics collector, recording nothing if it fails and the policy allows
+        let metrics: MetricsCollector = degradation.create(&config.metrics)?;
+        let metrics = Arc::new(
+            metrics
+                .with_degradation(degradation.clone())
+                .with_connection_pool(http_client.connection_pool()),
+        );
+        
+        // Initialize chain manager and its health monitors
+        let chain_manager = Arc::new(
+            Self::connect_chains(&config)
+                .await?
+                .with_events(events.clone())
+                .with_metrics(metrics.clone()),
+        );
+        chain_manager.start_health_monitor(&tasks);
         
         // Initialize payment manager
-        let payment_manager = Arc::new(PaymentManager::new(&config, &chain_manager).await?);
+        let payment_manager = Arc::new(PaymentManager::new(&config, &chain_manager).await?.with_events(events.clone()));
+        payment_manager.start_pending_monitor(&tasks);
+        payment_manager.start_settlement_monitor(&tasks);
         
-        // Initialize cache manager
-        let cache_manager = Arc::new(CacheManager::new(&config.cache)?);
+        // Approve configured token allowances before the first payment needs them
+        payment_manager.preapprove_configured().await;
         
-        // Initialize metrics collector
-        let metrics = Arc::new(MetricsCollector::new(&config.metrics)?);
+        // Initialize cache manager, caching nothing if it fails and the policy allows
+        let cache_manager: CacheManager = degradation.create(&config.cache)?;
+        let cache_manager = Arc::new(ArcSwap::from_pointee(cache_manager.with_tasks(tasks.clone())));
         
         // Initialize middleware stack
         let middleware_stack = Arc::new(MiddlewareStack::new());
         
+        // Initialize response verifiers
+        let verifiers = Arc::new(VerifierSet::default());
+        
+        // Initialize concurrency limiter
+        let limiter = Arc::new(PriorityLimiter::new(config.max_concurrent_requests, config.priority_aging));
+        
         // Initialize client state
         let state = Arc::new(ClientState {
             closed: AtomicBool::new(false),
             active_requests: AtomicU64::new(0),
             stats: RwLock::new(ClientStats {
-                start_time: Instant::now(),
-                ..Default::default()
+                total_requests: 0,
+                successful_requests: 0,
+                failed_requests: 0,
+                payments_made: 0,
+                total_amount_paid: 0,
+                paid_by_network: BTreeMap::new(),
             }),
             instance_id,
         });
@@ -196,9 +313,32 @@ impl Client {
             chain_manager,
             cache_manager,
             metrics,
+            degradation,
             middleware_stack,
+            verifiers,
+            challenge_handler: Arc::new(RwLock::new(None)),
+            limiter,
+            audit,
+            events,
+            tasks,
             state,
         };
+        client.audit.record(
+            AuditAction::ClientCreated {
+                instance_id: instance_id.to_string(),
+                config: audit::redacted_config(&client.config),
+            },
+            None,
+        );
+        for degraded in client.degradation.components() {
+            client.audit.record(
+                AuditAction::ComponentDegraded {
+                    component: degraded.name,
+                    error: degraded.error,
+                },
+                None,
+            );
+        }
         
         info!(
             instance_id = %instance_id,
@@ -208,11 +348,60 @@ impl Client {
         Ok(client)
     }
 
+    /// Creates the chain manager, first waiting for every chain's RPC
+    /// endpoint to answer with [`Config::connection_retry_on_start`].
+    async fn connect_chains(config: &Arc<Config>) -> Result<ChainManager> {
+        if !config.connection_retry_on_start {
+            return ChainManager::new(config).await;
+        }
+        
+        let deadline = Instant::now() + config.connection_retry_max_wait;
+        let mut first_error: Option<Error> = None;
+        let mut attempt = 0u32;
+        loop {
+            attempt += 1;
+            let connected = async {
+                let chain_manager = ChainManager::new(config).await?;
+                chain_manager.check_connections().await?;
+                Ok::<_, Error>(chain_manager)
+            };
+            // An endpoint that never answers must not hold the attempt past the deadline
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let connected = tokio::time::timeout(remaining, connected).await.unwrap_or_else(|_| {
+                Err(Error::Timeout("chain RPC endpoints".into(), config.connection_retry_max_wait))
+            });
+            let e = match connected {
+                Ok(chain_manager) => {
+                    if attempt > 1 {
+                        info!(attempt, "Connected to chain RPC endpoints");
+                    }
+                    return Ok(chain_manager);
+                }
+                Err(e) => e,
+            };
+            
+            // Give up with the first error once no retry fits before the deadline
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining < CONNECTION_RETRY_INTERVAL {
+                warn!(attempt, error = %e, "Chain RPC endpoints still unavailable, giving up");
+                return Err(first_error.unwrap_or(e));
+            }
+            warn!(
+                attempt,
+                error = %e,
+                remaining_secs = remaining.as_secs(),
+                "Chain RPC endpoints unavailable, retrying"
+            );
+            first_error.get_or_insert(e);
+            tokio::time::sleep(CONNECTION_RETRY_INTERVAL).await;
+        }
+    }
+
     /// Creates a new client builder for advanced configuration.
     /// 
     /// # Example
     /// 
-    /// ```rust
+    /// ```rust,no_run
     /// use v402_client::Client;
     /// 
     /// # #[tokio::main]
@@ -254,9 +443,11 @@ impl Client {
     /// - `Error::Payment` for payment-related failures
     /// - `Error::ClientClosed` if client has been closed
     /// 
+    /// Errors record the URL, request ID and attempt, see [`Error::context`].
+    /// 
     /// # Example
     /// 
-    /// ```rust
+    /// ```rust,no_run
     /// # use v402_client::Client;
     /// # #[tokio::main]
     /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -264,7 +455,7 @@ impl Client {
     /// let response = client.get("https://example.com/premium").await?;
     /// 
     /// if response.payment_made {
-    ///     println!("Paid {} wei", response.payment_amount.unwrap());
+    ///     println!("Paid {} wei", response.payment_amount.as_deref().unwrap_or_default());
     /// }
     /// 
     /// let content = response.text().await?;
@@ -274,13 +465,230 @@ impl Client {
     /// ```
     #[instrument(skip(self), fields(
         instance_id = %self.state.instance_id,
-        url = %url
+        url = %url.as_ref()
     ))]
     pub async fn get<U>(&self, url: U) -> Result<PaymentResponse>
     where
         U: AsRef<str> + Send,
     {
-        self.request(reqwest::Method::GET, url, None::<&[u8]>).await
+        self.request(reqwest::Method::GET, url, None, RequestOptions::default()).await
+    }
+
+    /// Performs an HTTP GET request with per-request options.
+    /// 
+    /// # Example
+    /// 
+    /// ```rust,no_run
+    /// # use v402_client::{Client, types::RequestOptions};
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let client = Client::builder().build().await?;
+    /// let options = RequestOptions::new().payment_metadata("order_id", "ord_1234");
+    /// let response = client.get_with_options("https://example.com/premium", options).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[instrument(skip(self, options), fields(
+        instance_id = %self.state.instance_id,
+        url = %url.as_ref()
+    ))]
+    pub async fn get_with_options<U>(&self, url: U, options: RequestOptions) -> Result<PaymentResponse>
+    where
+        U: AsRef<str> + Send,
+    {
+        self.request(reqwest::Method::GET, url, None, options).await
+    }
+
+    /// Performs a conditional GET, fetching the content only if it changed
+    /// since `validator` was obtained.
+    /// 
+    /// Sends `If-None-Match` or `If-Modified-Since` and bypasses the response
+    /// cache. An unchanged resource comes back as a 304 with
+    /// [`not_modified`](PaymentResponse::not_modified) set and an empty body,
+    /// and is never paid for, even with auto-pay on. A 402 is treated as
+    /// changed content and paid for as usual.
+    /// 
+    /// # Example
+    /// 
+    /// ```rust,no_run
+    /// # use v402_client::{Client, Validator};
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let client = Client::builder().build().await?;
+    /// let etag = Validator::ETag("\"v42\"".to_string());
+    /// let response = client.get_if_modified("https://example.com/premium", etag).await?;
+    /// if !response.not_modified {
+    ///     // Store the new content along with response.validators()
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[instrument(skip(self, validator), fields(
+        instance_id = %self.state.instance_id,
+        url = %url.as_ref()
+    ))]
+    pub async fn get_if_modified<U>(&self, url: U, validator: Validator) -> Result<PaymentResponse>
+    where
+        U: AsRef<str> + Send,
+    {
+        let options = RequestOptions::new().if_modified(validator).bypass_cache();
+        self.request(reqwest::Method::GET, url, None, options).await
+    }
+
+    /// Downloads `url` to the file at `path`, streaming the body to disk.
+    /// 
+    /// The body is written to `<path>.part`, renamed to `path` once complete.
+    /// A download interrupted in an earlier call is continued from where it
+    /// stopped, and one interrupted during this call is resumed up to
+    /// [`max_retries`](DownloadOptions::max_retries) times. A paid resource is
+    /// paid for once, with the receipt proving payment for the remaining
+    /// ranges; see [`download`](crate::download) for details.
+    /// 
+    /// # Example
+    /// 
+    /// ```rust,no_run
+    /// # use v402_client::{download::DownloadOptions, Client};
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let client = Client::builder().build().await?;
+    /// let report = client
+    ///     .download("https://data.example/model.bin", "model.bin", DownloadOptions::new().max_retries(10))
+    ///     .await?;
+    /// println!("Wrote {} bytes to {}", report.bytes, report.path.display());
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[instrument(skip(self, path, options), fields(instance_id = %self.state.instance_id, url = %url))]
+    pub async fn download(&self, url: &str, path: impl AsRef<Path>, options: DownloadOptions) -> Result<DownloadReport> {
+        self.ensure_not_closed()?;
+        self.http_client.check_host(url)?;
+        let path = path.as_ref();
+        
+        let mut part = PartialDownload::open(url, path, options.resume).await?;
+        let resumed_from = part.len();
+        let mut payment_made = false;
+        let mut retries = 0;
+        
+        loop {
+            match self.download_range(url, &mut part, &options, &mut payment_made).await {
+                Ok(true) => break,
+                Ok(false) => {}
+                Err(e) if retries < options.max_retries && matches!(e, Error::Network(_) | Error::Timeout(..)) => {
+                    retries += 1;
+                    warn!(url, offset = part.len(), retry = retries, error = %e, "Download interrupted, resuming");
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        
+        let bytes = part.finish(path).await?;
+        info!(url, path = %path.display(), bytes, resumed_from, retries, "Download complete");
+        Ok(DownloadReport {
+            path: path.to_path_buf(),
+            bytes,
+            resumed_from,
+            payment_made,
+            retries,
+        })
+    }
+
+    /// Requests the rest of a download and writes what comes back to `part`,
+    /// returning `true` once the content is complete.
+    async fn download_range(
+        &self,
+        url: &str,
+        part: &mut PartialDownload,
+        options: &DownloadOptions,
+        payment_made: &mut bool,
+    ) -> Result<bool> {
+        let mut request = http::Request::new(reqwest::Method::GET, url)?;
+        request.headers.insert(self.config.request_id_header.clone(), request.request_id.clone());
+        request.headers.extend(part.request_headers()?);
+        
+        let slot = StreamSlot::new(options.timeout);
+        let response = self.middleware_stack.execute_streaming(request, &self.http_client, &slot).await?;
+        let offset = part.len();
+        let (start, total) = response.header("content-range").map(download::content_range).unwrap_or_default();
+        
+        match response.status {
+            402 if *payment_made || part.receipt().is_some() => {
+                Err(Error::RangePaymentRequired { url: url.to_string(), context: None })
+            }
+            402 => self.pay_download(url, part, payment_made).await,
+            200 => {
+                part.begin(&response.headers).await?;
+                let length = response.header("content-length").and_then(|length| length.parse().ok());
+                write_download_body(part, &slot, &response, length).await
+            }
+            206 if start == Some(offset) => write_download_body(part, &slot, &response, total).await,
+            416 if total == Some(offset) => Ok(true),
+            206 | 416 if offset > 0 => {
+                // The server cannot continue from what is on disk, so start over
+                debug!(url, offset, status = response.status, "Restarting download");
+                part.restart().await?;
+                Ok(false)
+            }
+            status => Err(Error::Download {
+                url: url.to_string(),
+                reason: format!("unexpected status {status}"),
+                context: None,
+            }),
+        }
+    }
+
+    /// Pays for a download by requesting its next byte through the payment
+    /// flow, keeping the receipt to request the remaining ranges with.
+    async fn pay_download(&self, url: &str, part: &mut PartialDownload, payment_made: &mut bool) -> Result<bool> {
+        let offset = part.len();
+        let mut options = RequestOptions::new().bypass_cache();
+        options.headers = part.request_headers()?;
+        options.headers.insert("Range".to_string(), format!("bytes={offset}-{offset}"));
+        
+        let response = self.get_with_options(url, options).await?;
+        *payment_made |= response.payment_made;
+        match response.status {
+            // The server ignored the range and sent everything
+            200 => {
+                part.begin(&response.headers).await?;
+                part.write(&response.body).await?;
+                Ok(true)
+            }
+            206 => {
+                let (start, total) = response.header("content-range").map(download::content_range).unwrap_or_default();
+                if start != Some(offset) {
+                    return Err(Error::Download {
+                        url: url.to_string(),
+                        reason: format!("paid range starts at {start:?} instead of {offset}"),
+                        context: None,
+                    });
+                }
+                if offset == 0 {
+                    part.begin(&response.headers).await?;
+                }
+                part.write(&response.body).await?;
+                if let Some(receipt) = self.get_receipt(&response.request_id) {
+                    part.set_receipt(receipt).await?;
+                }
+                Ok(total == Some(part.len()))
+            }
+            402 => Err(match response.requirements {
+                Some(requirements) => Error::PaymentRequired {
+                    url: url.to_string(),
+                    requirements: Box::new(requirements),
+                    context: None,
+                },
+                None => Error::Download {
+                    url: url.to_string(),
+                    reason: "payment was not accepted".to_string(),
+                    context: None,
+                },
+            }),
+            status => Err(Error::Download {
+                url: url.to_string(),
+                reason: format!("unexpected status {status} to the paid request"),
+                context: None,
+            }),
+        }
     }
 
     /// Performs an HTTP POST request with automatic payment handling.
@@ -292,7 +700,7 @@ impl Client {
     /// 
     /// # Example
     /// 
-    /// ```rust
+    /// ```rust,no_run
     /// # use v402_client::Client;
     /// # #[tokio::main]
     /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -312,25 +720,219 @@ impl Client {
         U: AsRef<str> + Send,
         B: AsRef<[u8]> + Send,
     {
-        self.request(reqwest::Method::POST, url, body).await
+        self.request(reqwest::Method::POST, url, body.map(|body| body.as_ref().to_vec().into()), RequestOptions::default()).await
     }
 
-    /// Core request method that handles all HTTP methods.
-    async fn request<U, B>(
+    /// Performs an HTTP POST request with per-request options.
+    #[instrument(skip(self, body, options), fields(
+        instance_id = %self.state.instance_id,
+        url = %url.as_ref()
+    ))]
+    pub async fn post_with_options<U, B>(
+        &self,
+        url: U,
+        body: Option<B>,
+        options: RequestOptions,
+    ) -> Result<PaymentResponse>
+    where
+        U: AsRef<str> + Send,
+        B: AsRef<[u8]> + Send,
+    {
+        self.request(reqwest::Method::POST, url, body.map(|body| body.as_ref().to_vec().into()), options).await
+    }
+
+    /// Performs an HTTP POST request streaming its body from `reader`.
+    /// 
+    /// The body is sent with a `Content-Length` of `content_length` if
+    /// given, and with chunked transfer encoding otherwise. A reader can
+    /// only be read once, so the body cannot be resent with a payment: while
+    /// the client pays automatically this fails before sending anything.
+    /// Stream paid uploads from a file or factory with
+    /// [`ClientRequestBuilder::upload`] instead.
+    /// 
+    /// # Example
+    /// 
+    /// ```rust,no_run
+    /// # use v402_client::Client;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let client = Client::builder().auto_pay(false).build().await?;
+    /// let file = tokio::fs::File::open("/data/export.csv").await?;
+    /// let length = file.metadata().await?.len();
+    /// 
+    /// let response = client
+    ///     .post_stream("https://api.example.com/imports", file, Some(length))
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[instrument(skip(self, reader), fields(
+        instance_id = %self.state.instance_id,
+        url = %url.as_ref()
+    ))]
+    pub async fn post_stream<U, R>(&self, url: U, reader: R, content_length: Option<u64>) -> Result<PaymentResponse>
+    where
+        U: AsRef<str> + Send,
+        R: tokio::io::AsyncRead + Send + Sync + Unpin + 'static,
+    {
+        let body = Body::reader(reader, content_length);
+        self.request(reqwest::Method::POST, url, Some(body), RequestOptions::default()).await
+    }
+
+    /// Performs an HTTP PATCH request with automatic payment handling.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `url` - The URL to request
+    /// * `body` - The partial update to send (optional)
+    /// 
+    /// # Example
+    /// 
+    /// ```rust,no_run
+    /// # use v402_client::Client;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let client = Client::builder().build().await?;
+    /// let response = client
+    ///     .patch("https://api.example.com/documents/42", Some(br#"{"title":"Draft 2"}"#))
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[instrument(skip(self, body), fields(
+        instance_id = %self.state.instance_id,
+        url = %url.as_ref()
+    ))]
+    pub async fn patch<U, B>(&self, url: U, body: Option<B>) -> Result<PaymentResponse>
+    where
+        U: AsRef<str> + Send,
+        B: AsRef<[u8]> + Send,
+    {
+        self.request(reqwest::Method::PATCH, url, body.map(|body| body.as_ref().to_vec().into()), RequestOptions::default()).await
+    }
+
+    /// Performs an HTTP PATCH request with per-request options.
+    #[instrument(skip(self, body, options), fields(
+        instance_id = %self.state.instance_id,
+        url = %url.as_ref()
+    ))]
+    pub async fn patch_with_options<U, B>(
         &self,
-        method: reqwest::Method,
         url: U,
         body: Option<B>,
+        options: RequestOptions,
     ) -> Result<PaymentResponse>
     where
         U: AsRef<str> + Send,
         B: AsRef<[u8]> + Send,
     {
+        self.request(reqwest::Method::PATCH, url, body.map(|body| body.as_ref().to_vec().into()), options).await
+    }
+
+    /// Creates a builder for a fully custom request.
+    ///
+    /// This is the escape hatch for HTTP methods, headers and body formats not
+    /// covered by the convenience methods. Payment handling, middleware and
+    /// caching apply exactly as they do for [`get`](Self::get).
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use v402_client::Client;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let client = Client::builder().build().await?;
+    /// let response = client
+    ///     .request_builder(reqwest::Method::PATCH, "https://api.example.com/items/42")
+    ///     .header("If-Match", "\"v7\"")
+    ///     .json(&serde_json::json!({ "title": "Updated" }))
+    ///     .send()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn request_builder(&self, method: reqwest::Method, url: &str) -> ClientRequestBuilder<'_> {
+        ClientRequestBuilder::new(self, method, url)
+    }
+
+    /// Core request method that handles all HTTP methods.
+    ///
+    /// Errors carry an [`ErrorContext`] describing the request.
+    async fn request<U>(
+        &self,
+        method: reqwest::Method,
+        url: U,
+        body: Option<Body>,
+        options: RequestOptions,
+    ) -> Result<PaymentResponse>
+    where
+        U: AsRef<str> + Send,
+    {
+        // Every request carries a correlation ID, generated unless the caller supplied one
+        let mut options = options;
+        let request_id = options
+            .request_id
+            .get_or_insert_with(http::new_request_id)
+            .clone();
+        
+        let mut attempts = Attempts::default();
+        self.request_with_id(method, url.as_ref(), body, options, &mut attempts)
+            .await
+            .map_err(|e| {
+                e.with_context(ErrorContext {
+                    url: Some(url.as_ref().to_string()),
+                    request_id: Some(request_id),
+                    attempt: attempts.sent,
+                    payment_attempted: attempts.payment_attempted,
+                })
+            })
+    }
+
+    /// Validates the URL and runs the request inside its correlation span.
+    async fn request_with_id(
+        &self,
+        method: reqwest::Method,
+        url: &str,
+        body: Option<Body>,
+        options: RequestOptions,
+        attempts: &mut Attempts,
+    ) -> Result<PaymentResponse> {
         self.ensure_not_closed()?;
         
-        let url = url.as_ref();
+        // Reject malformed URLs up front and normalize them unless asked not to
+        let url = if options.preserve_url {
+            http::parse_url(url)?;
+            url.trim().to_string()
+        } else {
+            http::normalize_url(url)?
+        };
+        let url = url.as_str();
+        
+        // Refused hosts never reach the cache or the network
+        self.http_client.check_host(url)?;
         let start_time = Instant::now();
         
+        let span = info_span!(
+            "v402_request",
+            request_id = options.request_id.as_deref().unwrap_or_default(),
+            batch_id = options.batch_id.as_deref().unwrap_or_default()
+        );
+        
+        self.request_in_span(method, url, body, options, start_time, attempts)
+            .instrument(span)
+            .await
+    }
+
+    /// Runs a request inside its correlation span.
+    async fn request_in_span(
+        &self,
+        method: reqwest::Method,
+        url: &str,
+        body: Option<Body>,
+        options: RequestOptions,
+        start_time: Instant,
+        attempts: &mut Attempts,
+    ) -> Result<PaymentResponse> {
         // Increment active request counter
         self.state.active_requests.fetch_add(1, Ordering::Relaxed);
         
@@ -338,24 +940,102 @@ impl Client {
         let _guard = RequestGuard::new(&self.state);
         
         // Check cache for GET requests
-        if method == reqwest::Method::GET {
-            if let Some(cached) = self.cache_manager.get(url).await? {
+        let cache_key = http::cache_key(url, options.preserve_url)?;
+        if method == reqwest::Method::GET && !options.bypass_cache {
+            // Used only if the entry is stale and preemptive refresh is enabled
+            let refresh = || {
+                let client = self.clone();
+                let url = url.to_string();
+                let options = RequestOptions {
+                    request_id: Some(http::new_request_id()),
+                    ..options.clone()
+                };
+                async move {
+                    let response = client
+                        .execute_request(reqwest::Method::GET, &url, None, &options, &mut Attempts::default())
+                        .await?;
+                    // The refreshed response replaces the entry, so it passes the same checks
+                    if response.is_success() && !response.not_modified {
+                        if let Err(e) = client.verifiers.verify(&response) {
+                            warn!(url = %url, error = %e, "Refreshed response failed integrity check");
+                            client.metrics.increment_integrity_failures();
+                            return Err(e);
+                        }
+                    }
+                    Ok(response)
+                }
+            };
+            let cached = match self.cache().get_with_refresh(&cache_key, refresh).await {
+                Ok(cached) => cached,
+                Err(e) => {
+                    self.cache_failed(e)?;
+                    None
+                }
+            };
+            if let Some(mut cached) = cached {
                 debug!(url = %url, "Cache hit");
                 self.metrics.increment_cache_hits();
+                cached.request_id = options.request_id.clone().unwrap_or_default();
+                cached.batch_id = options.batch_id.clone();
                 return Ok(cached);
             }
         }
         
         // Execute request through middleware stack
-        let result = self.execute_request(method, url, body).await;
+        let mut result = self.execute_request(method.clone(), url, body, &options, attempts).await;
+        
+        // Verify content integrity before the response can be cached
+        if let Ok(response) = &result {
+            if response.is_success() && !response.not_modified {
+                if let Err(e) = self.verifiers.verify(response) {
+                    warn!(url = %url, error = %e, "Response failed integrity check");
+                    self.metrics.increment_integrity_failures();
+                    result = Err(e);
+                }
+            }
+        }
+        
+        // Read advertised prices, caching full requirements to pay for those resources up front
+        if let Ok(response) = &mut result {
+            if response.status != 402 && !self.config.payment_hint_headers.is_empty() {
+                response.payment_hints =
+                    PaymentHints::parse(&response.url, &response.headers, &self.config.payment_hint_headers);
+                if let Some(hints) = &response.payment_hints {
+                    self.cache_hinted_requirements(&response.url, hints);
+                }
+            }
+        }
+        
+        // Hash bodies so identical content cached under other URLs is stored once
+        if self.config.cache.content_dedup {
+            if let Ok(response) = &mut result {
+                if response.is_success() && !response.not_modified && response.content_hash.is_none() {
+                    response.content_hash = Some(crate::cache::content_hash(&response.body));
+                }
+            }
+        }
+        
+        // Cache successful GET responses; dry runs return a placeholder, not the content
+        if method == reqwest::Method::GET && !options.bypass_cache {
+            if let Ok(response) = &result {
+                if response.is_success() && !response.dry_run {
+                    if let Err(e) = self.cache().insert(&cache_key, response).await {
+                        if let Err(e) = self.cache_failed(e) {
+                            warn!(url = %url, error = %e, "Failed to cache response");
+                        }
+                    }
+                }
+            }
+        }
         
         // Update statistics
         let duration = start_time.elapsed();
-        self.update_stats(&result, duration).await;
+        self.update_stats(&result).await;
         
         // Record metrics
         self.metrics.record_request(
-            &method.to_string(),
+            method.as_ref(),
+            url,
             &result,
             duration,
         );
@@ -364,84 +1044,606 @@ impl Client {
     }
 
     /// Executes the actual HTTP request through the middleware stack.
-    async fn execute_request<B>(
+    async fn execute_request(
         &self,
         method: reqwest::Method,
         url: &str,
-        body: Option<B>,
-    ) -> Result<PaymentResponse>
-    where
-        B: AsRef<[u8]> + Send,
-    {
+        body: Option<Body>,
+        options: &RequestOptions,
+        attempts: &mut Attempts,
+    ) -> Result<PaymentResponse> {
         // Create request
-        let mut request = crate::http::Request::new(method, url)?;
+        let mut request = if options.preserve_url {
+            crate::http::Request::new_exact(method, url)?
+        } else {
+            crate::http::Request::new(method, url)?
+        };
+        
+        // Wait for a concurrency slot, held until the request and any payment complete
+        let _permit = self.limiter.acquire(options.priority).await;
         
         if let Some(body) = body {
-            request = request.body(body.as_ref().to_vec());
+            (request.body, request.stream) = body.into_parts();
+        }
+        
+        for (name, value) in &options.headers {
+            request.headers.insert(name.clone(), value.clone());
+        }
+        if let Some(validator) = &options.if_modified {
+            let (name, value) = validator.header();
+            request.headers.insert(name.to_string(), value.to_string());
+        }
+        
+        // Propagate correlation IDs so servers and facilitators can log them
+        if let Some(request_id) = &options.request_id {
+            request.request_id = request_id.clone();
+        }
+        request.headers
+            .entry(self.config.request_id_header.clone())
+            .or_insert_with(|| request.request_id.clone());
+        if let Some(batch_id) = &options.batch_id {
+            request.batch_id = Some(batch_id.clone());
+            request.headers.insert(BATCH_ID_HEADER.to_string(), batch_id.clone());
+        }
+        
+        // A body read from a reader is gone once sent, so it could not be resent with a payment
+        if self.pays() && request.stream.as_ref().is_some_and(|stream| !stream.is_replayable()) {
+            return Err(Error::Config(
+                "A request body read from a reader can only be sent once, but would have to be resent \
+                 after paying; use Body::file or Body::from_fn, or disable auto_pay"
+                    .to_string().into(),
+            ));
+        }
+        
+        // Skip the unpaid round-trip when this URL's requirements are already known,
+        // except for conditional requests which must not pay for unchanged content
+        if self.pays() && self.config.preemptive_payment && options.if_modified.is_none() {
+            if let Some(requirements) = self.payment_manager.cached_requirements(url) {
+                return self.pay_preemptively(request, requirements, options, attempts).await;
+            }
         }
         
         // Execute through middleware stack
-        let response = self.middleware_stack.execute(request, &*self.http_client).await?;
+        attempts.sent += 1;
+        let response = self.middleware_stack.execute(request.clone(), &self.http_client).await?;
         
         // Handle 402 Payment Required
-        if response.status == 402 && self.config.auto_pay {
-            return self.handle_payment_required(request, response).await;
+        if response.status == 402 {
+            self.metrics.increment_payment_required();
+            match self.config.on_payment_required {
+                PaymentRequiredBehavior::Pay if self.config.auto_pay => {
+                    // A 402 to a conditional request is treated as changed content, paid for in full
+                    if let Some(validator) = &options.if_modified {
+                        request.headers.remove(validator.header().0);
+                    }
+                    return self.handle_payment_required(request, response, options, attempts).await;
+                }
+                PaymentRequiredBehavior::Pay => {}
+                PaymentRequiredBehavior::ReturnParsed => {
+                    let mut response = response;
+                    match self.payment_manager.requirements_from_response(&response).await {
+                        Ok(requirements) => response.requirements = Some(requirements),
+                        Err(e) => debug!(url = %request.url, error = %e, "Could not parse payment requirements"),
+                    }
+                    return Ok(response);
+                }
+                PaymentRequiredBehavior::Error => {
+                    let requirements = self.payment_manager.requirements_from_response(&response).await?;
+                    return Err(Error::PaymentRequired {
+                        url: request.url,
+                        requirements: Box::new(requirements),
+                        context: None,
+                    });
+                }
+            }
         }
         
         Ok(response)
     }
 
+    /// Caches the requirements carried by payment hints on a response from
+    /// `url`, for resources on the same origin and a configured network.
+    fn cache_hinted_requirements(&self, url: &str, hints: &PaymentHints) {
+        let Ok(origin) = url::Url::parse(url).map(|url| url.origin()) else {
+            return;
+        };
+        for hint in &hints.hints {
+            let Some(requirements) = &hint.requirements else {
+                continue;
+            };
+            let same_origin = url::Url::parse(&hint.url).is_ok_and(|hinted| hinted.origin() == origin);
+            if !same_origin || self.config.chain(requirements.network.as_str()).is_none() {
+                debug!(url, hinted = %hint.url, network = %requirements.network, "Not caching hinted payment requirements");
+                continue;
+            }
+            if let Ok(hinted) = http::normalize_url(&hint.url) {
+                debug!(url, hinted = %hinted, "Caching payment requirements from hint");
+                self.payment_manager.cache_requirements(&hinted, requirements);
+            }
+        }
+    }
+
+    /// Whether 402 responses are paid automatically.
+    fn pays(&self) -> bool {
+        self.config.auto_pay && self.config.on_payment_required == PaymentRequiredBehavior::Pay
+    }
+
     /// Handles 402 Payment Required responses.
     async fn handle_payment_required(
         &self,
-        mut request: crate::http::Request,
+        request: crate::http::Request,
         response: PaymentResponse,
+        options: &RequestOptions,
+        attempts: &mut Attempts,
     ) -> Result<PaymentResponse> {
         info!(url = %request.url, "Payment required, processing payment");
         
+        let mut timing = PaymentTiming::default();
+        
+        // Serve identical content cached under another URL instead of paying for it again
+        if let Some(cached) = self.cached_content(&request, &response, None, options).await? {
+            return Ok(cached);
+        }
+        
         // Parse payment requirements
-        let payment_requirements = self.payment_manager
-            .parse_payment_requirements(&response.body)
+        let payment_requirements = time_phase(
+            info_span!("parse_requirements"),
+            &mut timing.parse_requirements,
+            self.payment_manager.requirements_from_response(&response),
+        )
+        .await?;
+        
+        // Follow the server's clock for the validity window, if configured
+        self.payment_manager.clock().observe(&response);
+        
+        if let Some(cached) = self.cached_content(&request, &response, Some(&payment_requirements), options).await? {
+            return Ok(cached);
+        }
+        
+        // Remember them so the next request to this URL can pay up front
+        if self.config.preemptive_payment {
+            self.payment_manager.cache_requirements(&request.url, &payment_requirements);
+        }
+        
+        let paid = self
+            .send_with_payment(request.clone(), &payment_requirements, options, &mut timing, attempts)
             .await?;
+        Ok(self.finalize_payment(&request.url, &payment_requirements, paid, options, timing).await)
+    }
+
+    /// Returns content cached under another URL with the hash a 402 response
+    /// announces, if skipping payment for cached content is enabled.
+    /// 
+    /// Without `requirements` the hash is read from the response's digest
+    /// headers, so no requirements need parsing; with them, only from
+    /// `contentHash` in their `extra` data.
+    async fn cached_content(
+        &self,
+        request: &crate::http::Request,
+        response: &PaymentResponse,
+        requirements: Option<&PaymentRequirements>,
+        options: &RequestOptions,
+    ) -> Result<Option<PaymentResponse>> {
+        if !self.config.cache.skip_payment_for_cached_content
+            || request.method != reqwest::Method::GET
+            || options.bypass_cache
+        {
+            return Ok(None);
+        }
+        let announced = match requirements {
+            None => crate::verify::expected_sha256(response).ok().flatten().map(hex::encode),
+            Some(requirements) => requirements.extra.get("contentHash").and_then(|hash| hash.as_str()).map(str::to_string),
+        };
+        let Some(content_hash) = announced else {
+            return Ok(None);
+        };
         
-        // Create payment header
-        let payment_header = self.payment_manager
-            .create_payment_header(&payment_requirements)
+        let cached = match self.cache().get_by_content_hash(&content_hash).await {
+            Ok(cached) => cached,
+            Err(e) => {
+                self.cache_failed(e)?;
+                None
+            }
+        };
+        let Some(mut cached) = cached else {
+            return Ok(None);
+        };
+        info!(url = %request.url, content_hash = %content_hash, "Content already cached, skipping payment");
+        self.metrics.increment_cache_hits();
+        cached.url = request.url.clone();
+        cached.request_id = request.request_id.clone();
+        cached.batch_id = request.batch_id.clone();
+        cached.payment_made = false;
+        cached.payment_amount = None;
+        cached.payment_asset = None;
+        cached.network = None;
+        cached.transaction_hash = None;
+        cached.payer = None;
+        cached.payment_timing = None;
+        Ok(Some(cached))
+    }
+
+    /// Pays up front using requirements cached from an earlier 402 for this URL.
+    ///
+    /// Falls back to the normal two-step flow if the server still answers 402,
+    /// which means the requirements have changed.
+    async fn pay_preemptively(
+        &self,
+        request: crate::http::Request,
+        payment_requirements: PaymentRequirements,
+        options: &RequestOptions,
+        attempts: &mut Attempts,
+    ) -> Result<PaymentResponse> {
+        debug!(url = %request.url, "Attaching payment from cached requirements");
+        
+        let mut timing = PaymentTiming::default();
+        let paid = self
+            .send_with_payment(request.clone(), &payment_requirements, options, &mut timing, attempts)
             .await?;
         
+        if paid.response.status == 402 && !paid.response.dry_run {
+            info!(url = %request.url, "Cached payment requirements rejected, falling back");
+            self.payment_manager.invalidate_requirements(&request.url);
+            self.metrics.increment_preemptive_payment_fallbacks();
+            return self.handle_payment_required(request, paid.response, options, attempts).await;
+        }
+        
+        self.metrics.increment_preemptive_payments();
+        Ok(self.finalize_payment(&request.url, &payment_requirements, paid, options, timing).await)
+    }
+
+    /// Signs a payment for `payment_requirements` and sends the request with it.
+    ///
+    /// Backs off on 429/503, reusing the payment unless it would expire while waiting.
+    /// Time spent signing and sending is added to `timing`, and each send to `attempts`.
+    /// Only the nonce of a payment the server accepted is used up, see
+    /// [`PaymentManager::replay_protection`]; those of payments not sent or
+    /// refused are handed out again.
+    async fn send_with_payment(
+        &self,
+        request: crate::http::Request,
+        payment_requirements: &PaymentRequirements,
+        options: &RequestOptions,
+        timing: &mut PaymentTiming,
+        attempts: &mut Attempts,
+    ) -> Result<PaidResponse> {
+        let mut signed = Vec::new();
+        let paid = self
+            .send_signed_payments(request, payment_requirements, options, timing, attempts, &mut signed)
+            .await;
+
+        let network = payment_requirements.network.as_str();
+        let accepted = paid
+            .as_ref()
+            .ok()
+            .filter(|paid| !paid.response.dry_run && paid.response.status != 402)
+            .map(|paid| paid.payment_header.as_str());
+        for payment_header in &signed {
+            if accepted == Some(payment_header.as_str()) {
+                self.payment_manager.commit_nonce(network, payment_header).await;
+            } else {
+                self.payment_manager.release_nonce(network, payment_header).await;
+            }
+        }
+        paid
+    }
+
+    /// Sends the request with payments for `payment_requirements`, for
+    /// [`send_with_payment`](Self::send_with_payment), adding each payment
+    /// signed to `signed`.
+    async fn send_signed_payments(
+        &self,
+        mut request: crate::http::Request,
+        payment_requirements: &PaymentRequirements,
+        options: &RequestOptions,
+        timing: &mut PaymentTiming,
+        attempts: &mut Attempts,
+        signed: &mut Vec<String>,
+    ) -> Result<PaidResponse> {
+        // Reject an invalid facilitator override before signing anything
+        let facilitator = self.payment_manager
+            .facilitator_for(payment_requirements.network.as_str(), options.facilitator_url.as_deref())?;
+        
+        // Hold back payments far above what this resource usually costs
+        if let Some(jump) = self.payment_manager.detect_price_jump(&request.url, payment_requirements) {
+            self.metrics.increment_price_jumps();
+            self.payment_manager.resolve_price_jump(jump).await?;
+        }
+        
+        // Pick the wallet routed to this URL
+        let wallet = self.chain_manager.route_wallet(&request.url)?;
+        
+        // Create payment header
+        let payment_header = self.sign_payment(payment_requirements, &wallet, facilitator, timing, signed).await?;
+        
         // Add payment header and retry
-        request.headers.insert("X-PAYMENT".to_string(), payment_header);
+        request.headers.insert("X-PAYMENT".to_string(), payment_header.clone());
+        
+        // Attach caller-supplied metadata alongside the payment
+        if !options.payment_metadata.is_empty() {
+            let metadata_header = self.payment_manager.encode_metadata(&options.payment_metadata)?;
+            request.headers.insert(PAYMENT_METADATA_HEADER.to_string(), metadata_header);
+        }
+        
+        // On dry-run chains, simulate the settlement instead of sending the payment
+        if self.config.chain(payment_requirements.network.as_str()).is_some_and(|chain| chain.dry_run) {
+            let settlement = time_phase(
+                info_span!("simulate_payment"),
+                &mut timing.paid_request,
+                self.payment_manager.simulate_payment(payment_requirements, &payment_header),
+            )
+            .await?;
+            info!(
+                url = %request.url,
+                amount = %payment_requirements.max_amount_required,
+                network = %payment_requirements.network,
+                wallet = %wallet,
+                success = settlement.success,
+                revert_reason = settlement.error_reason.as_deref().unwrap_or_default(),
+                "Simulated dry-run payment"
+            );
+            
+            return Ok(PaidResponse {
+                response: dry_run_response(&request, &settlement)?,
+                payment_header,
+                paid_at: chrono::Utc::now(),
+                wallet,
+                facilitator: facilitator.to_string(),
+            });
+        }
         
         info!(
             url = %request.url,
             amount = %payment_requirements.max_amount_required,
+            display_amount = %self.payment_manager.format_amount(payment_requirements, &payment_requirements.max_amount_required),
             network = %payment_requirements.network,
-            "Retrying request with payment"
+            facilitator = %facilitator,
+            wallet = %wallet,
+            "Sending request with payment"
         );
         
         // Execute paid request
-        let mut paid_response = self.middleware_stack
-            .execute(request, &*self.http_client)
+        let mut payment_header = payment_header;
+        let mut paid_at = chrono::Utc::now();
+        attempts.sent += 1;
+        attempts.payment_attempted = true;
+        let mut paid_response = time_phase(
+            info_span!("paid_request"),
+            &mut timing.paid_request,
+            self.middleware_stack.execute(request.clone(), &self.http_client),
+        )
+        .await?;
+
+        // Re-sign once on the server's clock if the payment was rejected as outside its validity window
+        if let Some(reason) = self.payment_manager.time_validity_error(&paid_response).await {
+            let clock = self.payment_manager.clock();
+            if let Some(Ok(offset_secs)) = paid_response.header("Date").map(|date| clock.calibrate(date)) {
+                warn!(
+                    url = %request.url,
+                    reason = %reason,
+                    offset_secs,
+                    "Payment rejected outside its validity window, retrying with recalibrated clock"
+                );
+                self.metrics.increment_clock_skew_retries();
+
+                payment_header = self.sign_payment(payment_requirements, &wallet, facilitator, timing, signed).await?;
+                request.headers.insert("X-PAYMENT".to_string(), payment_header.clone());
+                paid_at = chrono::Utc::now();
+                attempts.sent += 1;
+                paid_response = time_phase(
+                    info_span!("paid_request", clock_offset_secs = offset_secs),
+                    &mut timing.paid_request,
+                    self.middleware_stack.execute(request.clone(), &self.http_client),
+                )
+                .await?;
+            }
+        }
+
+        let mut retries = 0;
+        while retries < self.config.retry.max_rate_limit_retries {
+            let Some(delay) = retry_after_delay(&paid_response, &self.config.retry) else {
+                break;
+            };
+            retries += 1;
+
+            let resume_at = self.payment_manager.clock().now() + delay.as_secs() as i64 + PAYMENT_EXPIRY_MARGIN_SECS;
+            let still_valid = request
+                .headers
+                .get("X-PAYMENT")
+                .and_then(|header| PaymentManager::payment_valid_before(header))
+                .is_some_and(|valid_before| valid_before > resume_at);
+
+            warn!(
+                url = %request.url,
+                status = paid_response.status,
+                delay_ms = delay.as_millis() as u64,
+                attempt = retries,
+                resign = !still_valid,
+                "Paid request rate limited, backing off"
+            );
+            tokio::time::sleep(delay).await;
+
+            if still_valid {
+                self.metrics.increment_payments_reused();
+            } else {
+                payment_header = self.sign_payment(payment_requirements, &wallet, facilitator, timing, signed).await?;
+                request.headers.insert("X-PAYMENT".to_string(), payment_header.clone());
+                self.metrics.increment_payments_resigned();
+            }
+
+            paid_at = chrono::Utc::now();
+            attempts.sent += 1;
+            paid_response = time_phase(
+                info_span!("paid_request", attempt = retries),
+                &mut timing.paid_request,
+                self.middleware_stack.execute(request.clone(), &self.http_client),
+            )
+            .await?;
+        }
+
+        // Answer an authentication challenge once, sending the same payment again
+        let handler = self.challenge_handler.read().clone();
+        if let Some(handler) = handler.filter(|_| matches!(paid_response.status, 401 | 403)) {
+            let network = payment_requirements.network.as_str();
+            let payer = self.chain_manager.wallet_address(&wallet, network)?;
+            let chain_id = self.config.chain(network).and_then(|chain| chain.chain_id);
+            let auth_headers = {
+                let challenge = Challenge::new(
+                    &request.url,
+                    paid_response.status,
+                    &paid_response.headers,
+                    &paid_response.body,
+                    &payer,
+                    chain_id,
+                    &self.chain_manager,
+                );
+                handler.respond(&challenge).await?
+            };
+
+            if let Some(auth_headers) = auth_headers {
+                info!(
+                    url = %request.url,
+                    status = paid_response.status,
+                    "Paid request challenged, retrying with authentication"
+                );
+                request.headers.extend(auth_headers);
+                paid_at = chrono::Utc::now();
+                attempts.sent += 1;
+                paid_response = time_phase(
+                    info_span!("paid_request", challenge = true),
+                    &mut timing.paid_request,
+                    self.middleware_stack.execute(request.clone(), &self.http_client),
+                )
+                .await?;
+            }
+        }
+
+        Ok(PaidResponse {
+            response: paid_response,
+            payment_header,
+            paid_at,
+            wallet,
+            facilitator: facilitator.to_string(),
+        })
+    }
+
+    /// Signs a payment for `requirements` from `wallet`, adding it to `signed`.
+    ///
+    /// With [`Config::verify_payments`] set, `facilitator` verifies the
+    /// payment before it is returned, except on dry-run chains where it is
+    /// only simulated. Verification counts towards signing in `timing`.
+    async fn sign_payment(
+        &self,
+        requirements: &PaymentRequirements,
+        wallet: &str,
+        facilitator: &str,
+        timing: &mut PaymentTiming,
+        signed: &mut Vec<String>,
+    ) -> Result<String> {
+        let payment_header = self.payment_manager
+            .create_payment_header_timed(requirements, wallet, timing)
+            .await?;
+        signed.push(payment_header.clone());
+
+        let dry_run = self.config.chain(requirements.network.as_str()).is_some_and(|chain| chain.dry_run);
+        if self.config.verify_payments && !dry_run {
+            time_phase(
+                info_span!("verify_payment", facilitator = %facilitator),
+                &mut timing.sign_payment,
+                self.payment_manager.verify_payment(facilitator, &payment_header, requirements),
+            )
             .await?;
+        }
+        Ok(payment_header)
+    }
+
+    /// Marks a response as paid, applies settlement details and records the payment.
+    ///
+    /// A paid request answered with another 402 had its payment refused and
+    /// its nonce released, so the response is not marked as paid and the
+    /// payment is recorded as failed.
+    async fn finalize_payment(
+        &self,
+        url: &str,
+        payment_requirements: &PaymentRequirements,
+        paid: PaidResponse,
+        options: &RequestOptions,
+        mut timing: PaymentTiming,
+    ) -> PaymentResponse {
+        let mut paid_response = paid.response;
+        let refused = paid_response.status == 402 && !paid_response.dry_run;
         
-        // Mark as paid and update payment info
-        paid_response.payment_made = true;
-        paid_response.payment_amount = Some(payment_requirements.max_amount_required);
-        paid_response.network = Some(payment_requirements.network);
+        // Mark as paid and update payment info; dry-run payments were only simulated
+        paid_response.payment_made = !paid_response.dry_run && !refused;
+        paid_response.payment_amount = Some(payment_requirements.max_amount_required.clone());
+        paid_response.payment_asset = payment_requirements.primary_asset().map(|asset| asset.address.clone());
+        paid_response.network = Some(payment_requirements.network.clone());
         
-        // Process settlement if available
-        if let Some(settlement_header) = paid_response.headers.get("X-PAYMENT-RESPONSE") {
+        // Process settlement if available, issuing a receipt for successful ones
+        let mut receipt = None;
+        let settlement_header = paid_response.header("X-PAYMENT-RESPONSE").filter(|_| !refused).map(str::to_string);
+        if let Some(settlement_header) = settlement_header {
             // Decode and process settlement
-            if let Ok(settlement) = self.payment_manager
-                .process_settlement(settlement_header)
-                .await
-            {
+            let settlement = time_phase(
+                info_span!("process_settlement"),
+                &mut timing.process_settlement,
+                async {
+                    let settlement = self.payment_manager.process_settlement(&settlement_header).await?;
+                    if settlement.success && !paid_response.dry_run {
+                        let issued = self.payment_manager.issue_receipt(
+                            url,
+                            payment_requirements,
+                            &paid.payment_header,
+                            &settlement,
+                            &paid_response.request_id,
+                            paid.paid_at,
+                        );
+                        receipt = issued
+                            .await
+                            .map_err(|e| warn!(url, error = %e, "Failed to issue payment receipt"))
+                            .ok();
+                    }
+                    Ok::<_, Error>(settlement)
+                },
+            )
+            .await;
+            if let Ok(settlement) = settlement {
                 paid_response.transaction_hash = settlement.transaction_hash;
                 paid_response.payer = settlement.payer;
             }
         }
         
-        Ok(paid_response)
+        // Prefer the metadata echoed back by the server, falling back to what was sent
+        let metadata = paid_response
+            .header(PAYMENT_METADATA_HEADER)
+            .and_then(|header| PaymentManager::decode_metadata(header).ok())
+            .unwrap_or_else(|| options.payment_metadata.clone());
+        
+        debug!(
+            url,
+            total_ms = timing.total().as_millis() as u64,
+            timing = ?timing,
+            "Payment timing"
+        );
+        paid_response.payment_timing = Some(timing);
+        self.metrics.record_payment_timing(&timing);
+        if !refused {
+            self.metrics.increment_payments(paid_response.dry_run);
+        }
+        
+        // Without a settlement, the 402 status has the payment recorded as failed
+        self.payment_manager.record_payment(
+            url,
+            payment_requirements,
+            &paid.payment_header,
+            &paid_response,
+            metadata,
+            &paid.facilitator,
+            receipt,
+            &paid.wallet,
+        );
+        
+        paid_response
     }
 
     /// Performs multiple GET requests concurrently.
@@ -485,60 +1687,194 @@ impl Client {
     /// # Ok(())
     /// # }
     /// ```
-    #[instrument(skip(self, urls), fields(
-        instance_id = %self.state.instance_id,
-        url_count = urls.len(),
-        max_concurrent = max_concurrent
-    ))]
     pub async fn batch_get(
         &self,
         urls: &[impl AsRef<str> + Send + Sync],
         max_concurrent: usize,
     ) -> Result<Vec<Result<PaymentResponse, Error>>> {
+        Ok(self.batch(urls).max_concurrent(max_concurrent).send().await?.results)
+    }
+
+    /// Fetches URLs into the cache ahead of use, most urgent first.
+    /// 
+    /// Each URL comes with a priority from 0 to 255, higher being more
+    /// urgent; URLs of equal priority keep their order. URLs already cached
+    /// are not fetched again. The rest are fetched as a batch, paying as
+    /// needed, and cached as they complete. Failed URLs are counted in the
+    /// report rather than failing the prefetch.
+    /// 
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use v402_client::Client;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let client = Client::builder().build().await?;
+    /// let report = client
+    ///     .prefetch(&[
+    ///         ("https://example.com/chapter-2", 200),
+    ///         ("https://example.com/chapter-3", 100),
+    ///     ])
+    ///     .await?;
+    /// 
+    /// println!("Fetched {}, {} already cached", report.fetched, report.from_cache);
+    /// for (asset, amount) in &report.total_paid {
+    ///     println!("Paid {amount} of {asset}");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn prefetch(&self, urls: &[(&str, u8)]) -> Result<PrefetchReport> {
         self.ensure_not_closed()?;
+        let started = Instant::now();
+        let mut report = PrefetchReport::default();
+        
+        let mut ordered = urls.to_vec();
+        ordered.sort_by(|(_, a), (_, b)| b.cmp(a));
+        
+        // Skip URLs already cached; malformed ones count as failed
+        let mut pending = Vec::with_capacity(ordered.len());
+        for (url, _) in ordered {
+            match self.get_cached(url).await {
+                Ok(Some(_)) => report.from_cache += 1,
+                Ok(None) => pending.push(url),
+                Err(e) => {
+                    debug!(url, error = %e, "Not prefetching URL");
+                    report.failed += 1;
+                }
+            }
+        }
         
-        if urls.is_empty() {
-            return Ok(Vec::new());
+        let mut total_paid: BTreeMap<String, u128> = BTreeMap::new();
+        for result in self.batch_get(&pending, DEFAULT_BATCH_CONCURRENCY).await? {
+            match result {
+                Ok(response) if response.is_success() => {
+                    report.fetched += 1;
+                    if response.payment_made {
+                        add_payment(&mut total_paid, &response);
+                    }
+                }
+                Ok(response) => {
+                    debug!(url = %response.url, status = response.status, "Prefetch returned an error status");
+                    report.failed += 1;
+                }
+                Err(e) => {
+                    debug!(error = %e, "Prefetch request failed");
+                    report.failed += 1;
+                }
+            }
         }
+        report.total_paid = total_paid.into_iter().map(|(asset, amount)| (asset, amount.to_string())).collect();
+        report.duration = started.elapsed();
         
         info!(
-            url_count = urls.len(),
-            max_concurrent = max_concurrent,
-            "Starting batch GET requests"
+            fetched = report.fetched,
+            from_cache = report.from_cache,
+            failed = report.failed,
+            total_paid = ?report.total_paid,
+            "Prefetch completed"
         );
+        Ok(report)
+    }
+
+    /// Creates a builder for a batch of GET requests.
+    ///
+    /// Unlike [`batch_get`](Self::batch_get), the builder supports an overall
+    /// deadline and returns a [`BatchSummary`] alongside the results.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use v402_client::Client;
+    /// # use std::time::Duration;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let client = Client::builder().build().await?;
+    /// # let urls = vec!["https://example.com/1", "https://example.com/2"];
+    /// let batch = client
+    ///     .batch(&urls)
+    ///     .max_concurrent(20)
+    ///     .total_deadline(Duration::from_secs(15 * 60))
+    ///     .send()
+    ///     .await?;
+    ///
+    /// println!(
+    ///     "{} succeeded, {} skipped, paid {}",
+    ///     batch.summary.succeeded, batch.summary.skipped, batch.summary.total_paid
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn batch<U: AsRef<str>>(&self, urls: &[U]) -> BatchBuilder<'_> {
+        BatchBuilder::new(self, urls.iter().map(|url| url.as_ref().to_string()).collect())
+    }
+
+    /// Checks whether a URL requires payment without downloading it.
+    ///
+    /// Sends a `HEAD` request, falling back to a one-byte ranged `GET` when
+    /// the server does not support `HEAD`. For a 402 response the
+    /// requirements are read from the `X-PAYMENT-REQUIRED` header, so no
+    /// body is transferred. A 402 without the header or a body still
+    /// requires payment, with unknown requirements. No payment is made.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use v402_client::Client;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let client = Client::builder().build().await?;
+    /// let check = client.head_check("https://api.example.com/dataset.parquet").await?;
+    ///
+    /// if let Some(requirements) = &check.requirements {
+    ///     println!("Costs {} for {:?} bytes", requirements.max_amount_required, check.content_length);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[instrument(skip(self), fields(instance_id = %self.state.instance_id))]
+    pub async fn head_check(&self, url: &str) -> Result<PaymentCheckResult> {
+        self.ensure_not_closed()?;
         
-        // Create semaphore for concurrency limiting
-        let semaphore = Arc::new(Semaphore::new(max_concurrent));
+        let mut response = self.probe(reqwest::Method::HEAD, url, None).await?;
         
-        // Create tasks for each URL
-        let tasks = urls.iter().map(|url| {
-            let url = url.as_ref().to_string();
-            let client = self.clone();
-            let semaphore = semaphore.clone();
-            
-            tokio::spawn(async move {
-                // Acquire semaphore permit
-                let _permit = semaphore.acquire().await.map_err(|_| {
-                    Error::Internal("Failed to acquire semaphore permit".to_string())
-                })?;
-                
-                // Make request with timeout
-                let request_timeout = client.config.timeout;
-                timeout(request_timeout, client.get(&url)).await
-                    .map_err(|_| Error::Timeout(url.clone(), request_timeout))?
-            })
-        });
+        // HEAD unsupported, fetch a single byte instead
+        if matches!(response.status, 405 | 501) {
+            debug!(url, status = response.status, "HEAD not supported, probing with ranged GET");
+            response = self.probe(reqwest::Method::GET, url, Some("bytes=0-0")).await?;
+        }
         
-        // Execute all tasks concurrently
-        let results = try_join_all(tasks).await
-            .map_err(|e| Error::Internal(format!("Batch request task failed: {}", e)))?;
+        let requires_payment = response.status == 402;
+        let advertised = response.header(PAYMENT_REQUIRED_HEADER).is_some() || !response.body.is_empty();
+        let requirements = if requires_payment && advertised {
+            Some(self.payment_manager.requirements_from_response(&response).await?)
+        } else {
+            None
+        };
         
-        info!(
-            url_count = urls.len(),
-            "Batch GET requests completed"
-        );
+        // A ranged response carries the full size after the slash of Content-Range
+        let content_length = match response.header("content-range") {
+            Some(range) => range.rsplit('/').next().and_then(|total| total.parse().ok()),
+            None => response.header("content-length").and_then(|length| length.parse().ok()),
+        };
+        
+        Ok(PaymentCheckResult {
+            requires_payment,
+            requirements,
+            content_type: response.header("content-type").map(str::to_string),
+            content_length,
+        })
+    }
+
+    /// Sends an unpaid probe request through the middleware stack.
+    async fn probe(&self, method: reqwest::Method, url: &str, range: Option<&str>) -> Result<PaymentResponse> {
+        let mut request = crate::http::Request::new(method, url)?;
+        request.headers.insert(self.config.request_id_header.clone(), request.request_id.clone());
+        if let Some(range) = range {
+            request.headers.insert("Range".to_string(), range.to_string());
+        }
         
-        Ok(results)
+        self.middleware_stack.execute(request, &self.http_client).await
     }
 
     /// Retrieves payment history.
@@ -568,6 +1904,75 @@ impl Client {
         self.payment_manager.get_history(limit).await
     }
 
+    /// Returns the receipt of the payment made by the request with `request_id`.
+    ///
+    /// Receipts are issued for settled payments and kept with the payment history.
+    pub fn get_receipt(&self, request_id: &str) -> Option<Receipt> {
+        self.payment_manager.get_receipt(request_id)
+    }
+
+    /// Attaches a receipt as proof of an earlier payment, for servers that
+    /// accept one in the `X-PAYMENT-RECEIPT` header instead of a new payment.
+    ///
+    /// ```rust,no_run
+    /// # async fn example(client: v402_client::Client) -> v402_client::Result<()> {
+    /// if let Some(receipt) = client.get_receipt("request-id") {
+    ///     let builder = client.request_builder(v402_client::Method::GET, "https://example.com/article");
+    ///     let response = client.attach_receipt(builder, &receipt).send().await?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn attach_receipt<'a>(&self, builder: ClientRequestBuilder<'a>, receipt: &Receipt) -> ClientRequestBuilder<'a> {
+        match receipt.to_header() {
+            Ok(header) => builder.header(PAYMENT_RECEIPT_HEADER, header),
+            Err(e) => {
+                let mut builder = builder;
+                builder.error.get_or_insert(e);
+                builder
+            }
+        }
+    }
+
+    /// Returns settled payments whose transaction has no receipt yet, oldest first.
+    ///
+    /// Subscribe with [`subscribe`](Self::subscribe) to be told when one is stuck.
+    pub fn get_pending_payments(&self) -> Vec<PendingPayment> {
+        self.payment_manager.get_pending_payments()
+    }
+
+    /// Waits for the facilitator to settle the payment `payment_id`, for
+    /// facilitators that settle asynchronously and leave the paid response
+    /// without an `X-PAYMENT-RESPONSE` header.
+    ///
+    /// The payment's record is updated in place with the settlement and
+    /// returned, and [`PaymentEvent::PaymentSettled`](crate::events::PaymentEvent::PaymentSettled)
+    /// is published. See [`payment::facilitator`](crate::payment::facilitator)
+    /// for the endpoint queried, and [`Config::poll_settlement`] to poll in
+    /// the background instead.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use v402_client::{payment::PollOptions, Client};
+    /// use std::time::Duration;
+    ///
+    /// # async fn example(client: Client) -> v402_client::Result<()> {
+    /// for payment in client.get_payment_history(10).await? {
+    ///     if payment.transaction_hash.is_none() {
+    ///         let options = PollOptions::new().timeout(Duration::from_secs(60));
+    ///         let settled = client.poll_settlement(&payment.payment_id, options).await?;
+    ///         println!("{} settled in {:?}", settled.payment_id, settled.transaction_hash);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn poll_settlement(&self, payment_id: &str, options: PollOptions) -> Result<PaymentHistory> {
+        self.ensure_not_closed()?;
+        self.payment_manager.poll_settlement(payment_id, &options).await
+    }
+
     /// Retrieves payment statistics.
     /// 
     /// # Example
@@ -589,7 +1994,7 @@ impl Client {
         self.payment_manager.get_statistics().await
     }
 
-    /// Performs a comprehensive health check.
+    /// Gets payment statistics per paid host, keyed by host name.
     /// 
     /// # Example
     /// 
@@ -598,79 +2003,442 @@ impl Client {
     /// # #[tokio::main]
     /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
     /// # let client = Client::builder().build().await?;
-    /// let health = client.health_check().await?;
-    /// 
-    /// if health.healthy {
-    ///     println!("Client is healthy");
-    /// } else {
-    ///     println!("Client has issues: {:?}", health.issues);
+    /// for (host, stats) in client.get_payment_statistics_by_host().await? {
+    ///     println!("{}: {} payments, {} total", host, stats.total_payments, stats.total_amount);
     /// }
     /// # Ok(())
     /// # }
     /// ```
-    pub async fn health_check(&self) -> Result<HealthStatus> {
-        let mut status = HealthStatus {
-            healthy: true,
-            timestamp: chrono::Utc::now(),
-            components: HashMap::new(),
-            issues: Vec::new(),
-            metrics: HashMap::new(),
-        };
-        
-        // Check HTTP client
-        let http_healthy = self.http_client.health_check().await.is_ok();
-        status.components.insert("http_client".to_string(), http_healthy);
-        if !http_healthy {
-            status.healthy = false;
-            status.issues.push("HTTP client unhealthy".to_string());
-        }
-        
-        // Check chain manager
-        let chain_health = self.chain_manager.health_check().await?;
-        for (chain, healthy) in &chain_health {
-            status.components.insert(format!("chain_{}", chain), *healthy);
-            if !healthy {
-                status.healthy = false;
-                status.issues.push(format!("Chain {} unhealthy", chain));
-            }
-        }
-        
-        // Check cache
-        let cache_healthy = self.cache_manager.health_check().await.is_ok();
-        status.components.insert("cache".to_string(), cache_healthy);
-        
-        // Add metrics
-        let stats = self.state.stats.read().clone();
-        status.metrics.insert("total_requests".to_string(), stats.total_requests.into());
-        status.metrics.insert("successful_requests".to_string(), stats.successful_requests.into());
-        status.metrics.insert("failed_requests".to_string(), stats.failed_requests.into());
-        status.metrics.insert("active_requests".to_string(), 
-            self.state.active_requests.load(Ordering::Relaxed).into());
-        
-        Ok(status)
+    pub async fn get_payment_statistics_by_host(&self) -> Result<BTreeMap<String, HostStatistics>> {
+        self.ensure_not_closed()?;
+        Ok(self.payment_manager.get_statistics_by_host())
     }
 
-    /// Adds a middleware to the middleware stack.
+    /// Returns the labels of the configured wallets.
+    pub fn wallets(&self) -> Vec<String> {
+        self.chain_manager.wallets()
+    }
+
+    /// Returns the label of the wallet that pays for `url`.
+    /// 
+    /// Fails with [`Error::NoWalletForDomain`] if no route or default wallet applies.
+    pub fn wallet_for(&self, url: &str) -> Result<String> {
+        self.chain_manager.route_wallet(url)
+    }
+
+    /// Returns the balance of `token` held by the wallet labelled `wallet` on
+    /// `network`, in the token's smallest unit.
+    pub async fn wallet_balance(&self, wallet: &str, network: &str, token: &str) -> Result<u128> {
+        self.ensure_not_closed()?;
+        let owner = self.chain_manager.wallet_address(wallet, network)?;
+        self.chain_manager.token_balance(network, token, &owner).await
+    }
+
+    /// Exports the full payment history to `writer`.
     /// 
-    /// Middlewares are executed in the order they are added.
+    /// Records are streamed page by page, so the export never holds the
+    /// whole history in memory. Returns the number of records written.
     /// 
     /// # Example
     /// 
     /// ```rust
-    /// use v402_client::{Client, middleware::Middleware};
-    /// 
+    /// # use v402_client::{Client, payment::export::ExportFormat};
     /// # #[tokio::main]
     /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    /// let mut client = Client::builder().build().await?;
+    /// # let client = Client::builder().build().await?;
+    /// let mut file = tokio::fs::File::create("payments.csv").await?;
+    /// let count = client.export_payment_history(ExportFormat::Csv, &mut file).await?;
     /// 
-    /// // Add custom middleware
-    /// client.add_middleware(Box::new(MyCustomMiddleware::new()));
+    /// println!("Exported {} payments", count);
     /// # Ok(())
     /// # }
     /// ```
-    pub fn add_middleware(&self, middleware: Box<dyn Middleware>) {
-        self.middleware_stack.add(middleware);
-    }
+    pub async fn export_payment_history<W>(&self, format: ExportFormat, writer: &mut W) -> Result<usize>
+    where
+        W: AsyncWrite + Unpin + Send + ?Sized,
+    {
+        self.export_payment_history_range(format, DateRange::all(), writer).await
+    }
+
+    /// Exports payments made within `range` to `writer`.
+    /// 
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use v402_client::{Client, payment::export::{DateRange, ExportFormat}};
+    /// # use chrono::{TimeZone, Utc};
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let client = Client::builder().build().await?;
+    /// let october = DateRange::between(
+    ///     Utc.with_ymd_and_hms(2024, 10, 1, 0, 0, 0).unwrap(),
+    ///     Utc.with_ymd_and_hms(2024, 10, 31, 23, 59, 59).unwrap(),
+    /// );
+    /// 
+    /// let mut out = Vec::new();
+    /// client.export_payment_history_range(ExportFormat::JsonLines, october, &mut out).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn export_payment_history_range<W>(
+        &self,
+        format: ExportFormat,
+        range: DateRange,
+        writer: &mut W,
+    ) -> Result<usize>
+    where
+        W: AsyncWrite + Unpin + Send + ?Sized,
+    {
+        self.ensure_not_closed()?;
+        export::export_history(&self.payment_manager, format, range, writer).await
+    }
+
+    /// Opens connections and fetches chain state ahead of the first request.
+    /// 
+    /// Concurrently connects to each of `hosts` (a host name, or a URL for
+    /// another scheme or port), fetches the gas price and wallet nonces of
+    /// every configured chain, and connects to their facilitators. Each
+    /// target has its own timeout so one slow host does not hold up the
+    /// rest; failures are reported, not returned as errors.
+    /// 
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use v402_client::Client;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let client = Client::builder().build().await?;
+    /// let report = client.warm_up(&["api.example.com"]).await;
+    /// 
+    /// for failure in report.failures() {
+    ///     println!("{} not warmed up: {:?}", failure.target, failure.error);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn warm_up(&self, hosts: &[&str]) -> WarmUpReport {
+        let start = Instant::now();
+        
+        let hosts = hosts.iter().map(|host| {
+            let url = if host.contains("://") { host.to_string() } else { format!("https://{}/", host) };
+            warm_up_target(host.to_string(), async move { self.http_client.preconnect(&url).await })
+        });
+        let chains = self
+            .config
+            .chains
+            .iter()
+            .map(|chain| warm_up_target(chain.name.clone(), self.chain_manager.warm_up(&chain.name)));
+        
+        let mut facilitators: Vec<&str> =
+            self.config.chains.iter().map(|chain| self.config.facilitator_for(&chain.name)).collect();
+        facilitators.sort_unstable();
+        facilitators.dedup();
+        let facilitators = facilitators.into_iter().map(|url| {
+            let health_url = format!("{}/health", url.trim_end_matches('/'));
+            warm_up_target(url.to_string(), async move { self.http_client.preconnect(&health_url).await })
+        });
+        
+        let (hosts, chains, facilitators) = futures::join!(
+            futures::future::join_all(hosts),
+            futures::future::join_all(chains),
+            futures::future::join_all(facilitators),
+        );
+        let report = WarmUpReport { hosts, chains, facilitators, elapsed: start.elapsed() };
+        
+        for failure in report.failures() {
+            warn!(target = %failure.target, error = ?failure.error, "Warm-up failed");
+        }
+        info!(elapsed = ?report.elapsed, complete = report.is_complete(), "Warm-up finished");
+        report
+    }
+    
+    /// Performs a comprehensive health check.
+    /// 
+    /// The resulting [`ServiceState`](crate::types::ServiceState) is `Healthy`
+    /// when every component is up, `Degraded` when some are down but the
+    /// healthy fraction is at least the degraded threshold, and `Unhealthy`
+    /// otherwise.
+    /// 
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use v402_client::{Client, types::ServiceState};
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let client = Client::builder().build().await?;
+    /// let health = client.health_check().await?;
+    /// 
+    /// match health.state {
+    ///     ServiceState::Healthy => println!("Client is healthy"),
+    ///     ServiceState::Degraded => println!(
+    ///         "Client degraded: {}/{} components up",
+    ///         health.healthy_component_count(),
+    ///         health.total_component_count()
+    ///     ),
+    ///     ServiceState::Unhealthy => println!("Client has issues: {:?}", health.issues),
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn health_check(&self) -> Result<HealthStatus> {
+        let mut status = HealthStatus::new();
+        
+        // Check HTTP client
+        let http_healthy = self.http_client.health_check().await.is_ok();
+        status.components.insert("http_client".to_string(), http_healthy);
+        if !http_healthy {
+            status.issues.push("HTTP client unhealthy".to_string());
+        }
+        
+        // Check chain manager
+        let chain_health = self.chain_manager.health_check().await?;
+        let chain_reports = self.chain_manager.chain_health();
+        for (chain, healthy) in &chain_health {
+            status.components.insert(format!("chain_{}", chain), *healthy);
+            if self.chain_manager.is_quarantined(chain) {
+                status.issues.push(format!("Chain {} quarantined", chain));
+            } else if !healthy {
+                // Name the cause so DNS failures can be told apart from unreachable hosts
+                match chain_reports.iter().find(|report| &report.chain == chain).and_then(|r| r.last_error.as_ref()) {
+                    Some(reason) => status.issues.push(format!("Chain {} unhealthy: {}", chain, reason)),
+                    None => status.issues.push(format!("Chain {} unhealthy", chain)),
+                }
+            }
+        }
+        
+        // Check cache and metrics, which are unhealthy while degraded
+        let cache_degraded = self.degradation.get(degradation::CACHE);
+        let cache_healthy = cache_degraded.is_none() && self.cache_manager.load_full().health_check().await.is_ok();
+        status.components.insert("cache".to_string(), cache_healthy);
+        if let Some(degraded) = cache_degraded {
+            status.issues.push(format!("Cache degraded: {}", degraded.error));
+        } else if !cache_healthy {
+            status.issues.push("Cache unhealthy".to_string());
+        }
+        
+        let metrics_degraded = self.degradation.get(degradation::METRICS);
+        status.components.insert("metrics".to_string(), metrics_degraded.is_none());
+        if let Some(degraded) = metrics_degraded {
+            status.issues.push(format!("Metrics degraded: {}", degraded.error));
+        }
+        
+        status.update_state();
+        status.background_tasks = self.tasks.tasks();
+        
+        // Add metrics
+        let stats = self.state.stats.read().clone();
+        status.metrics.insert("total_requests".to_string(), stats.total_requests.into());
+        status.metrics.insert("successful_requests".to_string(), stats.successful_requests.into());
+        status.metrics.insert("failed_requests".to_string(), stats.failed_requests.into());
+        status.metrics.insert("active_requests".to_string(), 
+            self.state.active_requests.load(Ordering::Relaxed).into());
+        
+        let pool = self.http_client.connection_pool_stats();
+        status.metrics.insert("http_pool_active".to_string(), pool.active.into());
+        status.metrics.insert("http_pool_idle".to_string(), pool.idle.into());
+        status.metrics.insert("http_pool_max".to_string(), pool.max.into());
+        status.metrics.insert("http_pool_pending".to_string(), pool.pending.into());
+        status.metrics.insert("degraded_operations".to_string(), self.degradation.operations().into());
+        
+        Ok(status)
+    }
+
+    /// Subscribes to client events such as chain quarantine and recovery.
+    /// 
+    /// # Example
+    /// 
+    /// ```rust
+    /// use v402_client::{Client, ClientEvent};
+    /// 
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let client = Client::builder().build().await?;
+    /// let mut events = client.subscribe();
+    /// 
+    /// tokio::spawn(async move {
+    ///     while let Ok(event) = events.recv().await {
+    ///         if let ClientEvent::ChainQuarantined { chain, reason, .. } = event {
+    ///             eprintln!("{} quarantined: {}", chain, reason);
+    ///         }
+    ///     }
+    /// });
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn subscribe(&self) -> broadcast::Receiver<ClientEvent> {
+        self.events.subscribe()
+    }
+
+    /// Returns the health monitor's view of every configured chain.
+    pub fn chain_health(&self) -> Vec<ChainHealth> {
+        self.chain_manager.chain_health()
+    }
+
+    /// Runs a health check on `chain` now, releasing it from quarantine if it passes.
+    pub async fn force_chain_recheck(&self, chain: &str) -> Result<ChainHealth> {
+        self.audit_scope(None).force_chain_recheck(chain).await
+    }
+
+    /// Returns the components running in degraded mode after a failure, see
+    /// [`degradation`](crate::degradation).
+    pub fn degraded_components(&self) -> Vec<DegradedComponent> {
+        self.degradation.components()
+    }
+
+    /// Initializes a degraded component again from the client's configuration.
+    /// 
+    /// `name` is [`degradation::CACHE`] or [`degradation::METRICS`]; the
+    /// payment and chain components never run degraded and cannot be
+    /// recovered this way. Recovering a component that is not degraded does
+    /// nothing. A recovered cache starts empty, and a recovered metrics
+    /// collector resumes recording into the same counters, so handles from
+    /// [`MetricsCollector::counter`] stay valid. If initialization fails
+    /// again, the component stays degraded and the error is returned.
+    pub async fn try_recover_component(&self, name: &str) -> Result<()> {
+        self.audit_scope(None).try_recover_component(name).await
+    }
+
+    /// Adds a middleware to the middleware stack.
+    /// 
+    /// Middlewares are executed in the order they are added. The stack is
+    /// copy-on-write: requests already in flight keep the stack they started
+    /// with, and the new middleware applies to every request started afterwards.
+    /// 
+    /// # Example
+    /// 
+    /// ```rust
+    /// use v402_client::{Client, middleware::DeduplicationMiddleware};
+    /// 
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let client = Client::builder().build().await?;
+    /// 
+    /// // Share one response between identical concurrent requests
+    /// client.add_middleware(Box::new(DeduplicationMiddleware::new()));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn add_middleware(&self, middleware: Box<dyn Middleware>) {
+        self.audit_scope(None).add_middleware(middleware);
+    }
+
+    /// Adds a response verifier, run after those already added.
+    /// 
+    /// Verifiers check every successful response before it is cached; see
+    /// [`verify`](crate::verify). Requests started afterwards are checked by
+    /// the new verifier.
+    pub fn add_verifier(&self, verifier: Box<dyn ResponseVerifier>) {
+        self.audit_scope(None).add_verifier(verifier);
+    }
+
+    /// Adds a handler called when spend to a host crosses its alert threshold.
+    /// 
+    /// Thresholds are set with [`ConfigBuilder::spend_alert`](crate::ConfigBuilder::spend_alert);
+    /// alerts are also published as [`PaymentEvent::SpendAlert`](crate::PaymentEvent::SpendAlert).
+    pub fn add_spend_alert_handler(&self, handler: Box<dyn SpendAlertHandler>) {
+        self.audit_scope(None).add_spend_alert_handler(handler);
+    }
+
+    /// Sets the approver consulted for payments far above their usual price.
+    /// 
+    /// Only used with [`PriceJumpAction::Approve`]; without an approver such
+    /// payments are refused.
+    pub fn set_payment_approver(&self, approver: Box<dyn PaymentApprover>) {
+        self.audit_scope(None).set_payment_approver(approver);
+    }
+
+    /// Links settlement transactions on `chain` to `url` followed by the
+    /// transaction hash, see [`PaymentManager::register_explorer`].
+    pub fn register_explorer(&self, chain: &str, url: &str) {
+        self.payment_manager.register_explorer(chain, url);
+    }
+
+    /// Returns the block explorer page of the settlement transaction of
+    /// `payment`, see [`PaymentManager::explorer_url`].
+    pub fn explorer_url(&self, payment: &PaymentHistory) -> Option<url::Url> {
+        self.payment_manager.explorer_url(payment)
+    }
+
+    /// Sets the handler answering `401` and `403` responses to paid requests,
+    /// replacing any previous one.
+    /// 
+    /// See [`challenge`](crate::challenge). Without a handler, such responses
+    /// are returned as they are.
+    pub fn set_challenge_handler(&self, handler: Box<dyn ChallengeHandler>) {
+        self.audit_scope(None).set_challenge_handler(handler);
+    }
+
+    /// Starts monitoring the gas prices of the EVM chains, calling `callback`
+    /// when one crosses its threshold.
+    /// 
+    /// See [`ChainManager::monitor_gas_prices`]; thresholds are set on the
+    /// returned handle, and the monitor stops when it is dropped.
+    pub fn monitor_gas_prices(&self, callback: impl Fn(GasAlert) + Send + 'static) -> GasMonitorHandle {
+        self.chain_manager.monitor_gas_prices(callback)
+    }
+
+    /// Reports the client's activity to `sink` every `interval`.
+    /// 
+    /// Each [`SpendSnapshot`](crate::report::SpendSnapshot) covers the
+    /// activity since the previous one, the first since this call; a last
+    /// one is reported when the client is closed. Intervals under a second
+    /// are raised to one second. Does nothing on a closed client.
+    pub async fn start_spend_reporter(&self, interval: Duration, sink: Box<dyn SpendSink>) {
+        self.audit_scope(None).start_spend_reporter(interval, sink).await;
+    }
+
+    /// Spawns the task reporting to `sink` every `interval`.
+    async fn spawn_spend_reporter(&self, interval: Duration, sink: Box<dyn SpendSink>) -> Duration {
+        let interval = interval.max(MIN_SPEND_REPORT_INTERVAL);
+        let sink: Arc<dyn SpendSink> = Arc::from(sink);
+        let reporter = Arc::new(SpendReporter::new(spend_totals(&self.state, &self.cache_manager.load_full()).await));
+        let state = Arc::downgrade(&self.state);
+        let cache_manager = Arc::downgrade(&self.cache_manager);
+        
+        self.tasks.spawn("spend_reporter", Restart::WithBackoff, move |shutdown| {
+            let (state, cache_manager) = (state.clone(), cache_manager.clone());
+            let (reporter, sink) = (reporter.clone(), sink.clone());
+            async move {
+                let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+                ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                loop {
+                    // Report once more on shutdown so the last period is not lost
+                    let closing = tokio::select! {
+                        _ = shutdown.cancelled() => true,
+                        _ = ticker.tick() => false,
+                    };
+                    let (Some(state), Some(cache_manager)) = (state.upgrade(), cache_manager.upgrade()) else {
+                        break;
+                    };
+                    let totals = spend_totals(&state, &cache_manager.load_full()).await;
+                    sink.report(reporter.snapshot(totals)).await;
+                    if closing {
+                        break;
+                    }
+                }
+            }
+        });
+        interval
+    }
+
+    /// Returns a handle making changes to the client that are recorded in
+    /// the audit log with `reason`.
+    /// 
+    /// Changes made directly on the client are recorded without a reason.
+    /// See [`audit`](crate::audit) for an example.
+    pub fn with_reason<S: Into<String>>(&self, reason: S) -> AuditScope<'_> {
+        self.audit_scope(Some(reason.into()))
+    }
+
+    fn audit_scope(&self, reason: Option<String>) -> AuditScope<'_> {
+        AuditScope { client: self, reason }
+    }
+
+    /// Returns the audit log of the configuration the client was created
+    /// with and the changes made to it since, see [`audit`](crate::audit).
+    pub fn audit_log(&self) -> AuditLog {
+        self.audit.snapshot()
+    }
 
     /// Gracefully closes the client and releases all resources.
     /// 
@@ -719,6 +2487,9 @@ impl Client {
             );
         }
         
+        // Stop background tasks
+        self.tasks.shutdown(TASK_SHUTDOWN_TIMEOUT).await;
+        
         // Close all components
         if let Err(e) = self.chain_manager.close().await {
             error!("Error closing chain manager: {}", e);
@@ -728,7 +2499,7 @@ impl Client {
             error!("Error closing payment manager: {}", e);
         }
         
-        if let Err(e) = self.cache_manager.close().await {
+        if let Err(e) = self.cache_manager.load_full().close().await {
             error!("Error closing cache manager: {}", e);
         }
         
@@ -736,11 +2507,149 @@ impl Client {
             error!("Error closing metrics collector: {}", e);
         }
         
+        self.audit.record(AuditAction::ClientClosed, None);
         info!("v402 client closed successfully");
         
         Ok(())
     }
 
+    /// Returns a handle for read-only contract calls on the EVM network `network`.
+    #[cfg(feature = "ethereum")]
+    pub fn ethereum(&self, network: &str) -> Result<crate::ethereum::EthereumChain> {
+        crate::ethereum::EthereumChain::new(Arc::clone(&self.chain_manager), network)
+    }
+
+    /// Returns `true` if the rule's owner, by default this client's wallet
+    /// on the rule's network, holds enough of the rule's token.
+    ///
+    /// Check it before requesting content gated on holding an NFT or
+    /// subscription token rather than on per-request payments.
+    ///
+    /// ```rust,no_run
+    /// use v402_client::{ethereum::{OnchainAccessRule, TokenContract}, Client};
+    ///
+    /// # async fn example(client: Client) -> v402_client::Result<()> {
+    /// let membership = TokenContract::Erc721("0x1111111111111111111111111111111111111111".into());
+    /// if client.check_onchain_access(&OnchainAccessRule::new("base", membership, 1)).await? {
+    ///     let article = client.get("https://example.com/members/article").await?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "ethereum")]
+    pub async fn check_onchain_access(&self, rule: &crate::ethereum::OnchainAccessRule) -> Result<bool> {
+        let owner = match &rule.owner {
+            Some(owner) => owner.clone(),
+            None => self.chain_manager.address(&rule.network)?,
+        };
+        self.ethereum(&rule.network)?
+            .has_token_balance(&owner, &rule.contract, rule.min_balance)
+            .await
+    }
+
+    /// Returns the client's metrics collector, to export metrics or register
+    /// application metrics with [`MetricsCollector::counter`] and
+    /// [`MetricsCollector::gauge`].
+    pub fn metrics(&self) -> &MetricsCollector {
+        &self.metrics
+    }
+
+    /// Returns the client's metric counters, including payment reuse and
+    /// preemptive payment outcomes.
+    pub fn metrics_counters(&self) -> MetricsCounters {
+        let [high, normal, low] = self.limiter.queue_depths();
+        MetricsCounters {
+            queued_high: high as u64,
+            queued_normal: normal as u64,
+            queued_low: low as u64,
+            ..self.metrics.counters()
+        }
+    }
+
+    /// Returns a snapshot of all client metrics, including p50/p90/p99/p999
+    /// request latency per method and host and payment latency histograms.
+    ///
+    /// Snapshots never block request recording and can be polled freely.
+    pub fn metrics_snapshot(&self) -> MetricsSnapshot {
+        let mut snapshot = self.metrics.snapshot();
+        let [high, normal, low] = self.limiter.queue_depths();
+        snapshot.counters.queued_high = high as u64;
+        snapshot.counters.queued_normal = normal as u64;
+        snapshot.counters.queued_low = low as u64;
+        snapshot
+    }
+
+    /// Returns connection usage of the HTTP transport, to diagnose requests
+    /// stalling on exhausted connections.
+    pub fn connection_pool_stats(&self) -> ConnectionPoolStats {
+        self.http_client.connection_pool_stats()
+    }
+
+    /// Returns response cache statistics, including the cached size in bytes.
+    pub async fn cache_stats(&self) -> CacheStats {
+        self.cache_manager.load_full().stats().await
+    }
+
+    /// Returns the cached response for `url` without fetching or paying on a miss.
+    /// 
+    /// The URL is normalized as for [`get`](Self::get), so only entries cached
+    /// without [`RequestOptions::preserve_url`] are found.
+    /// 
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use v402_client::Client;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let client = Client::builder().build().await?;
+    /// match client.get_cached("https://example.com/premium-content").await? {
+    ///     Some(response) => println!("Cached: {}", response.text().await?),
+    ///     None => println!("Not cached, try again later"),
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get_cached(&self, url: &str) -> Result<Option<PaymentResponse>> {
+        self.ensure_not_closed()?;
+        let cached = match self.cache().get(&http::cache_key(url, false)?).await {
+            Ok(cached) => cached,
+            Err(e) => {
+                self.cache_failed(e)?;
+                None
+            }
+        };
+        if cached.is_some() {
+            debug!(url = %url, "Cache hit");
+            self.metrics.increment_cache_hits();
+        }
+        Ok(cached)
+    }
+
+    /// Removes the cached response for `url`, returning `true` if one was cached.
+    pub async fn invalidate_cache(&self, url: &str) -> Result<bool> {
+        self.ensure_not_closed()?;
+        Ok(self.cache().invalidate(&http::cache_key(url, false)?).await)
+    }
+
+    /// Removes every cached response whose URL starts with `prefix`,
+    /// returning how many were removed.
+    /// 
+    /// Only the scheme and host of `prefix` are normalized, so
+    /// `https://Example.com/api/` removes `https://example.com/api/items`
+    /// but not `https://example.com/apiv2`.
+    pub async fn invalidate_cache_prefix(&self, prefix: &str) -> Result<usize> {
+        self.ensure_not_closed()?;
+        let removed = self.cache().invalidate_prefix(&http::cache_key(prefix, true)?).await;
+        Ok(removed as usize)
+    }
+
+    /// Rotates the cache encryption key, re-encrypting cached responses in
+    /// the background; see [`CacheManager::rewrap`].
+    pub async fn rewrap_cache(&self, key: CacheKey) -> Result<()> {
+        self.ensure_not_closed()?;
+        self.cache_manager.load_full().rewrap(key).await
+    }
+
     /// Checks if the client is closed.
     pub fn is_closed(&self) -> bool {
         self.state.closed.load(Ordering::Relaxed)
@@ -751,6 +2660,52 @@ impl Client {
         &self.config
     }
 
+    /// Returns a handle to the client that does not keep it alive.
+    pub fn downgrade(&self) -> WeakClient {
+        WeakClient {
+            config: Arc::downgrade(&self.config),
+            http_client: Arc::downgrade(&self.http_client),
+            payment_manager: Arc::downgrade(&self.payment_manager),
+            chain_manager: Arc::downgrade(&self.chain_manager),
+            cache_manager: Arc::downgrade(&self.cache_manager),
+            metrics: Arc::downgrade(&self.metrics),
+            degradation: Arc::downgrade(&self.degradation),
+            middleware_stack: Arc::downgrade(&self.middleware_stack),
+            verifiers: Arc::downgrade(&self.verifiers),
+            challenge_handler: Arc::downgrade(&self.challenge_handler),
+            limiter: Arc::downgrade(&self.limiter),
+            events: self.events.clone(),
+            tasks: Arc::downgrade(&self.tasks),
+            audit: Arc::downgrade(&self.audit),
+            state: Arc::downgrade(&self.state),
+        }
+    }
+
+    /// Returns the cache manager, counting the operation if the cache is degraded.
+    fn cache(&self) -> Arc<CacheManager> {
+        self.degradation.record_operation(degradation::CACHE);
+        self.cache_manager.load_full()
+    }
+
+    /// Handles a cache failure according to [`Config::degradation_policy`],
+    /// replacing the cache with one that caches nothing unless the policy is
+    /// to fail.
+    fn cache_failed(&self, error: Error) -> Result<()> {
+        let message = error.to_string();
+        if self.degradation.absorb(degradation::CACHE, error)? {
+            self.cache_manager.store(Arc::new(CacheManager::noop(&self.config.cache)));
+            self.audit.record(
+                AuditAction::ComponentDegraded {
+                    component: degradation::CACHE.to_string(),
+                    error: message,
+                },
+                None,
+            );
+        }
+        self.degradation.record_operation(degradation::CACHE);
+        Ok(())
+    }
+
     /// Ensures the client is not closed.
     fn ensure_not_closed(&self) -> Result<()> {
         if self.is_closed() {
@@ -761,7 +2716,7 @@ impl Client {
     }
 
     /// Updates client statistics.
-    async fn update_stats(&self, result: &Result<PaymentResponse>, duration: Duration) {
+    async fn update_stats(&self, result: &Result<PaymentResponse>) {
         let mut stats = self.state.stats.write();
         
         stats.total_requests += 1;
@@ -776,6 +2731,8 @@ impl Client {
                     if let Some(amount_str) = &response.payment_amount {
                         if let Ok(amount) = amount_str.parse::<u128>() {
                             stats.total_amount_paid += amount;
+                            let network = response.network.as_ref().map(NetworkId::to_string).unwrap_or_default();
+                            *stats.paid_by_network.entry(network).or_default() += amount;
                         }
                     }
                 }
@@ -784,39 +2741,918 @@ impl Client {
                 stats.failed_requests += 1;
             }
         }
-        
-        // Update average duration (simple moving average)
-        if stats.total_requests == 1 {
-            stats.average_duration = duration;
-        } else {
-            let total_duration = stats.average_duration * (stats.total_requests - 1) as u32 + duration;
-            stats.average_duration = total_duration / stats.total_requests as u32;
-        }
     }
 }
 
-/// RAII guard for tracking active requests.
-struct RequestGuard<'a> {
-    state: &'a ClientState,
+/// Interval between attempts to reach the chains with [`Config::connection_retry_on_start`].
+const CONNECTION_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Shortest interval accepted by [`Client::start_spend_reporter`].
+const MIN_SPEND_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Adds the amount paid for `response` to its asset's total in `totals`,
+/// keyed by `"{network}:{asset}"`.
+fn add_payment(totals: &mut BTreeMap<String, u128>, response: &PaymentResponse) {
+    let (Some(amount), Some(asset)) = (response.payment_amount.as_deref(), response.payment_asset.as_deref()) else {
+        return;
+    };
+    let Ok(amount) = amount.parse::<u128>() else {
+        warn!(url = %response.url, amount, "Leaving payment with an invalid amount out of the totals");
+        return;
+    };
+    let network = response.network.as_ref().map(NetworkId::as_str).unwrap_or_default();
+    let total = totals.entry(format!("{}:{}", network, asset)).or_default();
+    *total = total.checked_add(amount).unwrap_or_else(|| {
+        warn!(asset, "Total paid exceeds the largest representable amount, capping it");
+        u128::MAX
+    });
 }
 
-impl<'a> RequestGuard<'a> {
-    fn new(state: &'a ClientState) -> Self {
-        Self { state }
+/// Returns the cumulative counters spend reports are computed from.
+async fn spend_totals(state: &ClientState, cache_manager: &CacheManager) -> SpendTotals {
+    let cache = cache_manager.stats().await;
+    let stats = state.stats.read();
+    SpendTotals {
+        requests: stats.total_requests,
+        failed_requests: stats.failed_requests,
+        payments: stats.payments_made,
+        paid_by_network: stats.paid_by_network.clone(),
+        cache_hits: cache.hits,
+        cache_misses: cache.misses,
     }
 }
 
-impl Drop for RequestGuard<'_> {
-    fn drop(&mut self) {
-        self.state.active_requests.fetch_sub(1, Ordering::Relaxed);
+/// Default number of concurrent requests in a batch.
+const DEFAULT_BATCH_CONCURRENCY: usize = 10;
+
+/// Default time in-flight batch requests may run past the total deadline.
+const DEFAULT_BATCH_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// Header carrying the batch ID of requests issued by `batch_get`.
+const BATCH_ID_HEADER: &str = "X-Batch-ID";
+
+/// Time background tasks are given to stop when the client is closed.
+const TASK_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Writes the body of a download response to `part`, returning `true` once
+/// the content reaches `expected` bytes or, without a known size, ends.
+///
+/// A body ending short of `expected` is reported as a network error, so the
+/// download is resumed.
+async fn write_download_body(
+    part: &mut PartialDownload,
+    slot: &StreamSlot,
+    response: &PaymentResponse,
+    expected: Option<u64>,
+) -> Result<bool> {
+    match slot.take() {
+        Some(mut body) => {
+            while let Some(chunk) = body.chunk().await? {
+                part.write(&chunk).await?;
+            }
+        }
+        // A middleware answered without sending the request
+        None => part.write(&response.body).await?,
+    }
+    match expected {
+        Some(expected) if part.len() < expected => Err(Error::Network(format!(
+            "connection closed after {} of {expected} bytes of {}",
+            part.len(),
+            response.url
+        ).into())),
+        _ => Ok(true),
     }
 }
 
-/// Builder for creating a v402 client with custom configuration.
-#[derive(Debug)]
-pub struct ClientBuilder {
+/// Builds the response standing in for a paid request on a dry-run chain.
+///
+/// The simulated settlement is carried in `X-PAYMENT-RESPONSE` like a real
+/// one, with status 200 if it succeeded and 402 if the transfer would revert.
+fn dry_run_response(request: &crate::http::Request, settlement: &Settlement) -> Result<PaymentResponse> {
+    let encoded = BASE64.encode(serde_json::to_vec(settlement)?);
+
+    Ok(PaymentResponse {
+        url: request.url.clone(),
+        status: if settlement.success { 200 } else { 402 },
+        headers: HashMap::from([("X-PAYMENT-RESPONSE".to_string(), encoded)]),
+        body: bytes::Bytes::new(),
+        payment_made: false,
+        payment_amount: None,
+        payment_asset: None,
+        network: None,
+        transaction_hash: None,
+        payer: None,
+        request_id: request.request_id.clone(),
+        batch_id: request.batch_id.clone(),
+        timestamp: chrono::Utc::now(),
+        payment_timing: None,
+        not_modified: false,
+        requirements: None,
+        dry_run: true,
+        content_hash: None,
+        payment_hints: None,
+    })
+}
+
+/// Seconds of validity a payment must have left after a backoff to be reused.
+const PAYMENT_EXPIRY_MARGIN_SECS: i64 = 5;
+
+/// Returns how long to wait before retrying a rate-limited or unavailable response.
+///
+/// Applies to 429 responses and to 503 responses carrying `Retry-After`.
+/// `Retry-After` may be delay-seconds or an HTTP-date; the result is capped
+/// at `max_retry_after`.
+fn retry_after_delay(response: &PaymentResponse, retry: &RetryConfig) -> Option<Duration> {
+    let retry_after = response.header("Retry-After").map(str::trim);
+
+    let delay = match (response.status, retry_after) {
+        (429 | 503, Some(value)) => value
+            .parse::<u64>()
+            .map(Duration::from_secs)
+            .ok()
+            .or_else(|| {
+                let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+                let wait = date.with_timezone(&chrono::Utc) - chrono::Utc::now();
+                Some(wait.to_std().unwrap_or(Duration::ZERO))
+            })
+            .unwrap_or(retry.default_retry_after),
+        (429, None) => retry.default_retry_after,
+        _ => return None,
+    };
+
+    Some(delay.min(retry.max_retry_after))
+}
+
+/// RAII guard for tracking active requests.
+struct RequestGuard<'a> {
+    state: &'a ClientState,
+}
+
+impl<'a> RequestGuard<'a> {
+    fn new(state: &'a ClientState) -> Self {
+        Self { state }
+    }
+}
+
+impl Drop for RequestGuard<'_> {
+    fn drop(&mut self) {
+        self.state.active_requests.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Builder for a single custom request, created by [`Client::request_builder`].
+///
+/// Errors from building the request (invalid headers, serialization failures)
+/// are deferred and returned by [`send`](Self::send).
+#[derive(Debug)]
+#[must_use = "a request builder does nothing until `send` is called"]
+pub struct ClientRequestBuilder<'a> {
+    client: &'a Client,
+    method: reqwest::Method,
+    url: String,
+    query: Vec<(String, String)>,
+    body: Option<Body>,
+    options: RequestOptions,
+    error: Option<Error>,
+}
+
+impl<'a> ClientRequestBuilder<'a> {
+    fn new(client: &'a Client, method: reqwest::Method, url: &str) -> Self {
+        Self {
+            client,
+            method,
+            url: url.to_string(),
+            query: Vec::new(),
+            body: None,
+            options: RequestOptions::default(),
+            error: None,
+        }
+    }
+
+    /// Adds a header.
+    ///
+    /// A header added more than once, under any case, is sent once with its
+    /// values comma-separated, or separated by `; ` for `Cookie`.
+    pub fn header<K: Into<String>, V: Into<String>>(mut self, name: K, value: V) -> Self {
+        self.append_header(name.into(), value.into());
+        self
+    }
+
+    /// Adds every header from a `HeaderMap`, combining repeated names like
+    /// [`header`](Self::header).
+    pub fn headers(mut self, headers: reqwest::header::HeaderMap) -> Self {
+        for (name, value) in headers.iter() {
+            match value.to_str() {
+                Ok(value) => self.append_header(name.to_string(), value.to_string()),
+                Err(_) => {
+                    self.error.get_or_insert_with(|| {
+                        Error::Network(format!("Header {} is not valid UTF-8", name).into())
+                    });
+                }
+            }
+        }
+        self
+    }
+
+    /// Adds `value` to the header `name`, after any value it already has.
+    fn append_header(&mut self, name: String, value: String) {
+        let existing = self
+            .options
+            .headers
+            .iter_mut()
+            .find(|(existing, _)| existing.eq_ignore_ascii_case(&name));
+        match existing {
+            Some((_, values)) => {
+                values.push_str(if name.eq_ignore_ascii_case("cookie") { "; " } else { ", " });
+                values.push_str(&value);
+            }
+            None => {
+                self.options.headers.insert(name, value);
+            }
+        }
+    }
+
+    /// Sets the raw request body.
+    pub fn body<B: AsRef<[u8]>>(mut self, body: B) -> Self {
+        self.body = Some(body.as_ref().to_vec().into());
+        self
+    }
+
+    /// Sets the request body from a [`Body`], streaming files and readers
+    /// instead of holding them in memory.
+    ///
+    /// See [`body`](crate::body) for which bodies can be paid for.
+    pub fn upload(mut self, body: Body) -> Self {
+        self.body = Some(body);
+        self
+    }
+
+    /// Serializes `value` as the JSON request body and sets `Content-Type`.
+    pub fn json<T: Serialize + ?Sized>(mut self, value: &T) -> Self {
+        match serde_json::to_vec(value) {
+            Ok(body) => {
+                self.body = Some(body.into());
+                let has_content_type = self
+                    .options
+                    .headers
+                    .keys()
+                    .any(|name| name.eq_ignore_ascii_case("content-type"));
+                if !has_content_type {
+                    self.options.headers.insert("Content-Type".to_string(), "application/json".to_string());
+                }
+            }
+            Err(e) => {
+                self.error.get_or_insert(e.into());
+            }
+        }
+        self
+    }
+
+    /// Appends query parameters serialized from a map or struct.
+    pub fn query<T: Serialize + ?Sized>(mut self, query: &T) -> Self {
+        match serde_json::to_value(query) {
+            Ok(serde_json::Value::Object(params)) => {
+                for (key, value) in params {
+                    let value = match value {
+                        serde_json::Value::Null => continue,
+                        serde_json::Value::String(s) => s,
+                        other => other.to_string(),
+                    };
+                    self.query.push((key, value));
+                }
+            }
+            Ok(_) => {
+                self.error.get_or_insert_with(|| {
+                    Error::Serialization("Query parameters must serialize to a map".to_string().into())
+                });
+            }
+            Err(e) => {
+                self.error.get_or_insert(e.into());
+            }
+        }
+        self
+    }
+
+    /// Attaches metadata to any payment made for this request.
+    pub fn payment_metadata(mut self, metadata: HashMap<String, String>) -> Self {
+        self.options.payment_metadata.extend(metadata);
+        self
+    }
+
+    /// Skips the response cache for this request.
+    pub fn bypass_cache(mut self) -> Self {
+        self.options.bypass_cache = true;
+        self
+    }
+
+    /// Overrides the facilitator for any payment made for this request.
+    ///
+    /// Useful for testing against a staging facilitator. The URL must use
+    /// HTTPS unless insecure facilitators are allowed in the configuration.
+    pub fn facilitator<S: Into<String>>(mut self, url: S) -> Self {
+        self.options.facilitator_url = Some(url.into());
+        self
+    }
+
+    /// Uses the given correlation ID instead of generating one.
+    ///
+    /// The ID is sent in the configured request ID header and recorded on the
+    /// response and any resulting payment, for end-to-end correlation.
+    pub fn request_id<S: Into<String>>(mut self, id: S) -> Self {
+        self.options.request_id = Some(id.into());
+        self
+    }
+
+    /// Sets the priority used when waiting for a concurrency slot.
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.options.priority = priority;
+        self
+    }
+
+    /// Sends the URL's path exactly as given instead of normalizing it, for
+    /// servers that treat `/path/` and `/path` differently.
+    pub fn preserve_url(mut self) -> Self {
+        self.options.preserve_url = true;
+        self
+    }
+
+    /// Sends the request.
+    #[instrument(skip(self), fields(
+        instance_id = %self.client.state.instance_id,
+        method = %self.method,
+        url = %self.url
+    ))]
+    pub async fn send(self) -> Result<PaymentResponse> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+
+        let url = if self.query.is_empty() {
+            self.url
+        } else {
+            let mut url = http::parse_url(&self.url)?;
+            url.query_pairs_mut().extend_pairs(&self.query);
+            url.to_string()
+        };
+
+        self.client.request(self.method, url, self.body, self.options).await
+    }
+}
+
+/// Handle recording changes to a client with a reason, created by [`Client::with_reason`].
+#[derive(Debug)]
+#[must_use = "a reason is only recorded with a change made through it"]
+pub struct AuditScope<'a> {
+    client: &'a Client,
+    reason: Option<String>,
+}
+
+impl AuditScope<'_> {
+    fn record(self, action: AuditAction) {
+        self.client.audit.record(action, self.reason);
+    }
+
+    /// Adds a middleware, see [`Client::add_middleware`].
+    pub fn add_middleware(self, middleware: Box<dyn Middleware>) {
+        let name = middleware.name().to_string();
+        self.client.middleware_stack.add(middleware);
+        self.record(AuditAction::MiddlewareAdded { middleware: name });
+    }
+
+    /// Adds a response verifier, see [`Client::add_verifier`].
+    pub fn add_verifier(self, verifier: Box<dyn ResponseVerifier>) {
+        let name = verifier.name().to_string();
+        self.client.verifiers.add(verifier);
+        self.record(AuditAction::VerifierAdded { verifier: name });
+    }
+
+    /// Adds a spend alert handler, see [`Client::add_spend_alert_handler`].
+    pub fn add_spend_alert_handler(self, handler: Box<dyn SpendAlertHandler>) {
+        self.client.payment_manager.add_spend_alert_handler(handler);
+        self.record(AuditAction::SpendAlertHandlerAdded);
+    }
+
+    /// Sets the payment approver, see [`Client::set_payment_approver`].
+    pub fn set_payment_approver(self, approver: Box<dyn PaymentApprover>) {
+        self.client.payment_manager.set_payment_approver(approver);
+        self.record(AuditAction::PaymentApproverSet);
+    }
+
+    /// Sets the challenge handler, see [`Client::set_challenge_handler`].
+    pub fn set_challenge_handler(self, handler: Box<dyn ChallengeHandler>) {
+        *self.client.challenge_handler.write() = Some(Arc::from(handler));
+        self.record(AuditAction::ChallengeHandlerSet);
+    }
+
+    /// Starts a spend reporter, see [`Client::start_spend_reporter`].
+    pub async fn start_spend_reporter(self, interval: Duration, sink: Box<dyn SpendSink>) {
+        let interval = self.client.spawn_spend_reporter(interval, sink).await;
+        self.record(AuditAction::SpendReporterStarted { interval });
+    }
+
+    /// Checks a chain now, releasing it from quarantine if it passes, see
+    /// [`Client::force_chain_recheck`].
+    pub async fn force_chain_recheck(self, chain: &str) -> Result<ChainHealth> {
+        let was_quarantined = self.client.chain_manager.is_quarantined(chain);
+        let health = self.client.chain_manager.force_recheck(chain).await?;
+        self.record(AuditAction::ChainRechecked {
+            chain: health.chain.clone(),
+            was_quarantined,
+            quarantined: health.quarantined,
+        });
+        Ok(health)
+    }
+
+    /// Initializes a degraded component again, see [`Client::try_recover_component`].
+    pub async fn try_recover_component(self, name: &str) -> Result<()> {
+        let client = self.client;
+        let component = match name {
+            degradation::CACHE => degradation::CACHE,
+            degradation::METRICS => degradation::METRICS,
+            _ => {
+                return Err(Error::Config(format!(
+                    "Component {} cannot be recovered; only {} and {} run degraded",
+                    name,
+                    degradation::CACHE,
+                    degradation::METRICS
+                ).into()))
+            }
+        };
+        if client.degradation.get(component).is_none() {
+            return Ok(());
+        }
+        
+        if component == degradation::CACHE {
+            let cache_manager = CacheManager::create(&client.config.cache)?.with_tasks(client.tasks.clone());
+            client.cache_manager.store(Arc::new(cache_manager));
+        } else {
+            let enabled = MetricsCollector::create(&client.config.metrics)?.is_enabled();
+            client.metrics.set_enabled(enabled);
+        }
+        if client.degradation.recover(component) {
+            info!(component, "Degraded component recovered");
+            self.record(AuditAction::ComponentRecovered {
+                component: component.to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Builder for a batch of GET requests, created by [`Client::batch`].
+#[derive(Debug)]
+#[must_use = "a batch builder does nothing until `send` is called"]
+pub struct BatchBuilder<'a> {
+    client: &'a Client,
+    urls: Vec<String>,
+    max_concurrent: usize,
+    total_deadline: Option<Duration>,
+    grace_period: Duration,
+    priority: Priority,
+}
+
+impl<'a> BatchBuilder<'a> {
+    fn new(client: &'a Client, urls: Vec<String>) -> Self {
+        Self {
+            client,
+            urls,
+            max_concurrent: DEFAULT_BATCH_CONCURRENCY,
+            total_deadline: None,
+            grace_period: DEFAULT_BATCH_GRACE_PERIOD,
+            priority: Priority::Low,
+        }
+    }
+
+    /// Sets the maximum number of requests in flight at once.
+    pub fn max_concurrent(mut self, max_concurrent: usize) -> Self {
+        self.max_concurrent = max_concurrent.max(1);
+        self
+    }
+
+    /// Sets an overall time budget for the batch.
+    ///
+    /// Once it elapses, requests not yet started complete with
+    /// [`Error::BatchDeadlineExceeded`] without being sent, and requests in
+    /// flight are given the [grace period](Self::grace_period) to finish.
+    pub fn total_deadline(mut self, deadline: Duration) -> Self {
+        self.total_deadline = Some(deadline);
+        self
+    }
+
+    /// Sets the priority of the batch's requests, [`Priority::Low`] by default.
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Sets how long in-flight requests may run past the total deadline.
+    pub fn grace_period(mut self, grace_period: Duration) -> Self {
+        self.grace_period = grace_period;
+        self
+    }
+
+    /// Runs the batch.
+    ///
+    /// `results[i]` is always the outcome for the `i`-th URL, whichever
+    /// requests finish first.
+    #[instrument(skip(self), fields(
+        instance_id = %self.client.state.instance_id,
+        url_count = self.urls.len(),
+        max_concurrent = self.max_concurrent
+    ))]
+    pub async fn send(self) -> Result<BatchResponse> {
+        self.client.ensure_not_closed()?;
+        
+        let started = Instant::now();
+        let url_count = self.urls.len();
+        let mut stream = self.stream();
+        
+        // Store each outcome at its URL's index as it completes so the order
+        // never depends on completion order
+        let mut outcomes: Vec<Option<BatchOutcome>> = (0..url_count).map(|_| None).collect();
+        while let Some(outcome) = stream.next_outcome().await {
+            let index = outcome.index;
+            outcomes[index] = Some(outcome);
+        }
+        
+        let mut summary = BatchSummary::default();
+        let mut total_paid: u128 = 0;
+        let mut results = Vec::with_capacity(url_count);
+        for (index, outcome) in outcomes.into_iter().enumerate() {
+            let BatchOutcome { attempted, result, .. } = outcome.unwrap_or_else(|| BatchOutcome {
+                index,
+                attempted: true,
+                result: Err(Error::Internal("Batch request did not complete".to_string().into())),
+            });
+            match (&result, attempted) {
+                (_, false) => summary.skipped += 1,
+                (Ok(response), true) => {
+                    summary.succeeded += 1;
+                    if response.payment_made {
+                        let amount = response.payment_amount.as_deref().and_then(|a| a.parse::<u128>().ok());
+                        total_paid += amount.unwrap_or(0);
+                    }
+                }
+                (Err(_), true) => summary.failed += 1,
+            }
+            summary.attempted += u64::from(attempted);
+            results.push(result);
+        }
+        summary.total_paid = total_paid.to_string();
+        summary.elapsed = started.elapsed();
+        
+        info!(
+            batch_id = %stream.batch_id,
+            url_count,
+            succeeded = summary.succeeded,
+            failed = summary.failed,
+            skipped = summary.skipped,
+            "Batch GET requests completed"
+        );
+        
+        Ok(BatchResponse { results, summary })
+    }
+
+    /// Runs the batch, yielding each URL's index and outcome as soon as its
+    /// request completes.
+    ///
+    /// New requests are only started while the consumer keeps up, so a slow
+    /// consumer throttles the batch instead of buffering every response.
+    /// Dropping the stream aborts the requests still running.
+    ///
+    /// ```rust,no_run
+    /// use futures::StreamExt;
+    /// use v402_client::Client;
+    ///
+    /// # async fn example(client: Client, urls: Vec<String>) {
+    /// let mut results = client.batch(&urls).max_concurrent(32).execute_streaming();
+    /// while let Some((index, result)) = results.next().await {
+    ///     match result {
+    ///         Ok(response) => println!("{}: {} bytes", index, response.body.len()),
+    ///         Err(e) => eprintln!("{}: {}", index, e),
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    pub fn execute_streaming(self) -> BatchStream {
+        if let Err(e) = self.client.ensure_not_closed() {
+            return BatchStream::failed(self.urls.len(), e);
+        }
+        self.stream()
+    }
+
+    /// Starts the task feeding the batch's outcomes into a stream.
+    fn stream(self) -> BatchStream {
+        let batch_id = http::new_request_id();
+        let url_count = self.urls.len();
+        
+        info!(
+            batch_id = %batch_id,
+            url_count,
+            deadline_secs = self.total_deadline.map(|d| d.as_secs()),
+            "Starting batch GET requests"
+        );
+        
+        // Room for one outcome per running request, so finished requests never wait on each other
+        let (sender, receiver) = mpsc::channel(self.max_concurrent);
+        let semaphore = Arc::new(Semaphore::new(self.max_concurrent));
+        let deadline = self.total_deadline.map(|d| tokio::time::Instant::now() + d);
+        let grace_period = self.grace_period;
+        let client = self.client.clone();
+        let options = RequestOptions::new().batch_id(&batch_id).priority(self.priority);
+        
+        let driver = tokio::spawn(async move {
+            let mut urls = self.urls.into_iter().enumerate();
+            let mut next = urls.next();
+            let mut running = JoinSet::new();
+            
+            loop {
+                tokio::select! {
+                    biased;
+                    
+                    // Hand completed requests to the consumer first; waiting on a slow
+                    // consumer here holds back new requests
+                    Some(joined) = running.join_next(), if !running.is_empty() => {
+                        // Panics are caught in the task, so it only fails to join when the runtime shuts down
+                        let Ok(outcome) = joined else { continue };
+                        if sender.send(outcome).await.is_err() {
+                            return;
+                        }
+                    }
+                    
+                    // Wait for a slot, giving up once the deadline has passed
+                    permit = acquire_batch_slot(&semaphore, deadline), if next.is_some() => {
+                        let Some((index, url)) = next.take() else { continue };
+                        next = urls.next();
+                        let Some(permit) = permit else {
+                            let outcome = BatchOutcome { index, attempted: false, result: Err(Error::BatchDeadlineExceeded) };
+                            if sender.send(outcome).await.is_err() {
+                                return;
+                            }
+                            continue;
+                        };
+                        
+                        let client = client.clone();
+                        let options = options.clone();
+                        running.spawn(async move {
+                            let _permit = permit;
+                            
+                            // Make request with timeout, cut short by the deadline plus grace period
+                            let request_timeout = client.config.timeout;
+                            let mut limit = tokio::time::Instant::now() + request_timeout;
+                            if let Some(deadline) = deadline {
+                                limit = limit.min(deadline + grace_period);
+                            }
+                            
+                            // A panicking request fails only its own URL
+                            let request = AssertUnwindSafe(client.get_with_options(&url, options)).catch_unwind();
+                            let result = match tokio::time::timeout_at(limit, request).await {
+                                Ok(Ok(result)) => result,
+                                Ok(Err(_)) => Err(Error::Internal("Batch request task panicked".to_string().into())),
+                                Err(_) if deadline.is_some_and(|d| tokio::time::Instant::now() >= d) => {
+                                    Err(Error::BatchDeadlineExceeded)
+                                }
+                                Err(_) => Err(Error::Timeout(url.into(), request_timeout)),
+                            };
+                            BatchOutcome { index, attempted: true, result }
+                        });
+                    }
+                    
+                    else => break,
+                }
+            }
+        });
+        
+        BatchStream {
+            batch_id,
+            receiver,
+            driver: Some(driver),
+        }
+    }
+}
+
+/// Waits for a batch concurrency slot, returning `None` once `deadline` has passed.
+async fn acquire_batch_slot(
+    semaphore: &Arc<Semaphore>,
+    deadline: Option<tokio::time::Instant>,
+) -> Option<OwnedSemaphorePermit> {
+    let permit = match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline, semaphore.clone().acquire_owned()).await.ok()?,
+        None => semaphore.clone().acquire_owned().await,
+    };
+    // The semaphore is never closed
+    permit.ok()
+}
+
+/// Outcome of one request of a batch.
+#[derive(Debug)]
+struct BatchOutcome {
+    /// Index of the request's URL in the batch
+    index: usize,
+    /// Whether the request was sent, as opposed to skipped at the deadline
+    attempted: bool,
+    result: Result<PaymentResponse>,
+}
+
+/// Outcomes of a batch in completion order, created by
+/// [`BatchBuilder::execute_streaming`].
+///
+/// Each item is the index of a URL in the batch and the outcome of its
+/// request. Dropping the stream aborts the requests still running and
+/// releases their concurrency slots.
+#[derive(Debug)]
+#[must_use = "streams do nothing unless polled"]
+pub struct BatchStream {
+    batch_id: String,
+    receiver: mpsc::Receiver<BatchOutcome>,
+    driver: Option<tokio::task::JoinHandle<()>>,
+}
+
+impl BatchStream {
+    /// A stream failing every one of `url_count` requests with `error`.
+    fn failed(url_count: usize, error: Error) -> Self {
+        let (sender, receiver) = mpsc::channel(url_count.max(1));
+        let message = error.to_string();
+        let mut error = Some(error);
+        for index in 0..url_count {
+            let result = Err(error.take().unwrap_or_else(|| Error::Internal(message.clone().into())));
+            let _ = sender.try_send(BatchOutcome { index, attempted: false, result });
+        }
+        Self {
+            batch_id: String::new(),
+            receiver,
+            driver: None,
+        }
+    }
+
+    /// ID of the batch, sent with each of its requests.
+    pub fn batch_id(&self) -> &str {
+        &self.batch_id
+    }
+
+    /// Receives the next outcome, or `None` once every request has completed.
+    async fn next_outcome(&mut self) -> Option<BatchOutcome> {
+        self.receiver.recv().await
+    }
+}
+
+impl Stream for BatchStream {
+    type Item = (usize, Result<PaymentResponse>);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver
+            .poll_recv(cx)
+            .map(|outcome| outcome.map(|outcome| (outcome.index, outcome.result)))
+    }
+}
+
+impl Drop for BatchStream {
+    fn drop(&mut self) {
+        // Dropping the driver's task set aborts the requests it is running
+        if let Some(driver) = self.driver.take() {
+            driver.abort();
+        }
+    }
+}
+
+/// Longest time [`Client::warm_up`] spends on any one target.
+const WARM_UP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Runs one warm-up step under [`WARM_UP_TIMEOUT`], timing it.
+async fn warm_up_target(target: String, step: impl std::future::Future<Output = Result<()>>) -> WarmUpResult {
+    let start = Instant::now();
+    let error = match tokio::time::timeout(WARM_UP_TIMEOUT, step).await {
+        Ok(Ok(())) => None,
+        Ok(Err(e)) => Some(e.to_string()),
+        Err(_) => Some(format!("Timed out after {:?}", WARM_UP_TIMEOUT)),
+    };
+    WarmUpResult { target, elapsed: start.elapsed(), error }
+}
+
+/// Handle to a [`Client`] that does not keep it alive, with read-only access
+/// to its configuration, statistics and cache.
+///
+/// Middleware created by [`ClientBuilder::middleware_fn`] receive one, so
+/// they can consult the client they belong to without a reference cycle
+/// keeping it alive. Every method fails with [`Error::ClientClosed`] once
+/// all `Client` handles have been dropped.
+#[derive(Debug, Clone)]
+pub struct WeakClient {
+    config: Weak<Config>,
+    http_client: Weak<HttpClient>,
+    payment_manager: Weak<PaymentManager>,
+    chain_manager: Weak<ChainManager>,
+    cache_manager: Weak<ArcSwap<CacheManager>>,
+    metrics: Weak<MetricsCollector>,
+    degradation: Weak<Degradation>,
+    middleware_stack: Weak<MiddlewareStack>,
+    verifiers: Weak<VerifierSet>,
+    challenge_handler: Weak<RwLock<Option<Arc<dyn ChallengeHandler>>>>,
+    limiter: Weak<PriorityLimiter>,
+    events: EventBus,
+    tasks: Weak<TaskManager>,
+    audit: Weak<AuditTrail>,
+    state: Weak<ClientState>,
+}
+
+impl WeakClient {
+    /// Returns `true` while the client exists and has not been closed.
+    pub fn is_alive(&self) -> bool {
+        self.state
+            .upgrade()
+            .is_some_and(|state| !state.closed.load(Ordering::Relaxed))
+    }
+
+    /// Returns the client's configuration.
+    pub fn config(&self) -> Result<Arc<Config>> {
+        self.config.upgrade().ok_or(Error::ClientClosed)
+    }
+
+    /// Returns payment statistics, as [`Client::get_payment_statistics`] does.
+    pub async fn get_payment_statistics(&self) -> Result<PaymentStatistics> {
+        self.upgrade()?.get_payment_statistics().await
+    }
+
+    /// Returns payment statistics per host, as
+    /// [`Client::get_payment_statistics_by_host`] does.
+    pub async fn get_payment_statistics_by_host(&self) -> Result<BTreeMap<String, HostStatistics>> {
+        self.upgrade()?.get_payment_statistics_by_host().await
+    }
+
+    /// Returns the most recent payments, as [`Client::get_payment_history`] does.
+    pub async fn get_payment_history(&self, limit: usize) -> Result<Vec<PaymentHistory>> {
+        self.upgrade()?.get_payment_history(limit).await
+    }
+
+    /// Returns a snapshot of the client's metrics.
+    pub fn metrics_snapshot(&self) -> Result<MetricsSnapshot> {
+        Ok(self.upgrade()?.metrics_snapshot())
+    }
+
+    /// Returns response cache statistics.
+    pub async fn cache_stats(&self) -> Result<CacheStats> {
+        Ok(self.upgrade()?.cache_stats().await)
+    }
+
+    /// Returns the cached response for `url`, as [`Client::get_cached`] does.
+    pub async fn get_cached(&self, url: &str) -> Result<Option<PaymentResponse>> {
+        self.upgrade()?.get_cached(url).await
+    }
+
+    /// Recreates the client while it exists, for the read-only methods above.
+    fn upgrade(&self) -> Result<Client> {
+        let upgrade = || {
+            Some(Client {
+                config: self.config.upgrade()?,
+                http_client: self.http_client.upgrade()?,
+                payment_manager: self.payment_manager.upgrade()?,
+                chain_manager: self.chain_manager.upgrade()?,
+                cache_manager: self.cache_manager.upgrade()?,
+                metrics: self.metrics.upgrade()?,
+                degradation: self.degradation.upgrade()?,
+                middleware_stack: self.middleware_stack.upgrade()?,
+                verifiers: self.verifiers.upgrade()?,
+                challenge_handler: self.challenge_handler.upgrade()?,
+                limiter: self.limiter.upgrade()?,
+                events: self.events.clone(),
+                tasks: self.tasks.upgrade()?,
+                audit: self.audit.upgrade()?,
+                state: self.state.upgrade()?,
+            })
+        };
+        upgrade().ok_or(Error::ClientClosed)
+    }
+}
+
+/// Creates a middleware once the client exists, see [`ClientBuilder::middleware_fn`].
+type MiddlewareFactory = Box<dyn FnOnce(WeakClient) -> Box<dyn Middleware> + Send>;
+
+/// Middleware added to a [`ClientBuilder`], ready or still to be created.
+enum BuilderMiddleware {
+    Ready(Box<dyn Middleware>),
+    Factory(MiddlewareFactory),
+}
+
+impl fmt::Debug for BuilderMiddleware {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuilderMiddleware::Ready(middleware) => f.debug_tuple("Ready").field(middleware).finish(),
+            BuilderMiddleware::Factory(_) => f.write_str("Factory"),
+        }
+    }
+}
+
+/// Builder for creating a v402 client with custom configuration.
+#[derive(Debug)]
+pub struct ClientBuilder {
     config_builder: crate::config::ConfigBuilder,
-    middlewares: Vec<Box<dyn Middleware>>,
+    middlewares: Vec<BuilderMiddleware>,
+    verifiers: Vec<Box<dyn ResponseVerifier>>,
+    spend_alert_handlers: Vec<Box<dyn SpendAlertHandler>>,
+    payment_approver: Option<Box<dyn PaymentApprover>>,
+    challenge_handler: Option<Box<dyn ChallengeHandler>>,
+    warm_up_on_build: bool,
+    spend_reporter: Option<(Duration, Box<dyn SpendSink>)>,
 }
 
 impl ClientBuilder {
@@ -825,6 +3661,12 @@ impl ClientBuilder {
         Self {
             config_builder: crate::config::ConfigBuilder::new(),
             middlewares: Vec::new(),
+            verifiers: Vec::new(),
+            spend_alert_handlers: Vec::new(),
+            payment_approver: None,
+            challenge_handler: None,
+            warm_up_on_build: false,
+            spend_reporter: None,
         }
     }
 
@@ -840,6 +3682,12 @@ impl ClientBuilder {
         self
     }
 
+    /// Sets how 402 responses are handled.
+    pub fn on_payment_required(mut self, behavior: PaymentRequiredBehavior) -> Self {
+        self.config_builder = self.config_builder.on_payment_required(behavior);
+        self
+    }
+
     /// Sets the maximum amount to pay per request.
     pub fn max_amount_per_request<S: Into<String>>(mut self, amount: S) -> Self {
         self.config_builder = self.config_builder.max_amount_per_request(amount);
@@ -852,22 +3700,202 @@ impl ClientBuilder {
         self
     }
 
+    /// Adds a labelled wallet to pay from.
+    pub fn wallet<L: Into<String>, K: Into<String>>(mut self, label: L, private_key: K) -> Self {
+        self.config_builder = self.config_builder.wallet(label, private_key);
+        self
+    }
+
+    /// Pays for URLs matching `pattern` from the wallet labelled `wallet`.
+    pub fn route_wallet<P: Into<String>, W: Into<String>>(mut self, pattern: P, wallet: W) -> Self {
+        self.config_builder = self.config_builder.route_wallet(pattern, wallet);
+        self
+    }
+
+    /// Sets the wallet paying for URLs no route matches.
+    pub fn default_wallet<S: Into<String>>(mut self, wallet: S) -> Self {
+        self.config_builder = self.config_builder.default_wallet(wallet);
+        self
+    }
+
+    /// Alerts when spend to any one host within `window` reaches `threshold`.
+    pub fn spend_alert<S: Into<String>>(mut self, window: Duration, threshold: S) -> Self {
+        self.config_builder = self.config_builder.spend_alert(window, threshold);
+        self
+    }
+
+    /// Alerts when spend to a host matching `pattern` reaches `threshold`.
+    pub fn host_spend_alert<P: Into<String>, S: Into<String>>(mut self, pattern: P, threshold: S) -> Self {
+        self.config_builder = self.config_builder.host_spend_alert(pattern, threshold);
+        self
+    }
+
+    /// Adds a handler called when spend to a host crosses its alert threshold.
+    pub fn on_spend_alert(mut self, handler: Box<dyn SpendAlertHandler>) -> Self {
+        self.spend_alert_handlers.push(handler);
+        self
+    }
+
+    /// Flags payments above `multiplier` times the usual price of their
+    /// resource, handling them as `action` says.
+    pub fn price_jump(mut self, multiplier: f64, action: PriceJumpAction) -> Self {
+        self.config_builder = self.config_builder.price_jump(multiplier, action);
+        self
+    }
+
+    /// Sets the approver consulted for payments far above their usual price.
+    pub fn payment_approver(mut self, approver: Box<dyn PaymentApprover>) -> Self {
+        self.payment_approver = Some(approver);
+        self
+    }
+
+    /// Simulates payments on the named chains instead of sending them.
+    pub fn dry_run_chains(mut self, chains: &[&str]) -> Self {
+        self.config_builder = self.config_builder.dry_run_chains(chains);
+        self
+    }
+
+    /// Warms up the chains and facilitators during [`build`](Self::build),
+    /// as [`Client::warm_up`] does; failures are logged, not returned.
+    pub fn warm_up_on_build(mut self, enabled: bool) -> Self {
+        self.warm_up_on_build = enabled;
+        self
+    }
+
+    /// Reports the client's activity to `sink` every `interval`, as
+    /// [`Client::start_spend_reporter`] does.
+    pub fn spend_reporter(mut self, interval: Duration, sink: Box<dyn SpendSink>) -> Self {
+        self.spend_reporter = Some((interval, sink));
+        self
+    }
+
     /// Adds a middleware to the client.
+    ///
+    /// Middleware run in the order they are added, see [`middleware_fn`](Self::middleware_fn).
     pub fn middleware(mut self, middleware: Box<dyn Middleware>) -> Self {
-        self.middlewares.push(middleware);
+        self.middlewares.push(BuilderMiddleware::Ready(middleware));
+        self
+    }
+
+    /// Adds a middleware created by `factory` once the client exists, for
+    /// middleware that need to consult the client through a [`WeakClient`].
+    ///
+    /// Middleware run in the order they were added to the builder, whether
+    /// with [`middleware`](Self::middleware) or with this method, the first
+    /// one outermost. Factories are called in that order after the client is
+    /// constructed and before [`build`](Self::build) returns, so every
+    /// middleware is in place for the first request. Middleware added later
+    /// with [`Client::add_middleware`] run after these.
+    ///
+    /// ```rust
+    /// use async_trait::async_trait;
+    /// use std::sync::{Arc, Mutex};
+    /// use v402_client::{middleware::{Middleware, Next}, Client, Error, PaymentResponse, Request, Result, WeakClient};
+    ///
+    /// /// Records its name, then passes the request on.
+    /// #[derive(Debug)]
+    /// struct Record(&'static str, Arc<Mutex<Vec<&'static str>>>);
+    ///
+    /// #[async_trait]
+    /// impl Middleware for Record {
+    ///     async fn handle(&self, request: Request, next: Next<'_>) -> Result<PaymentResponse> {
+    ///         self.1.lock().unwrap().push(self.0);
+    ///         next.run(request).await
+    ///     }
+    /// }
+    ///
+    /// /// Refuses requests once the client has made 100 payments.
+    /// #[derive(Debug)]
+    /// struct PaymentLimit(WeakClient);
+    ///
+    /// #[async_trait]
+    /// impl Middleware for PaymentLimit {
+    ///     async fn handle(&self, request: Request, next: Next<'_>) -> Result<PaymentResponse> {
+    ///         let stats = self.0.get_payment_statistics().await?;
+    ///         if stats.total_payments >= 100 {
+    ///             return Err(Error::Internal("payment limit reached".into()));
+    ///         }
+    ///         // Stop here so the example makes no network request
+    ///         Err(Error::Internal(format!("{} payments so far", stats.total_payments).into()))
+    ///     }
+    /// }
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<()> {
+    /// let order = Arc::new(Mutex::new(Vec::new()));
+    /// let recorded = order.clone();
+    /// let client = Client::builder()
+    ///     .middleware(Box::new(Record("first", order.clone())))
+    ///     .middleware_fn(move |_| Box::new(Record("second", recorded)))
+    ///     .middleware_fn(|client| Box::new(PaymentLimit(client)))
+    ///     .build()
+    ///     .await?;
+    /// client.add_middleware(Box::new(Record("added later", order.clone())));
+    ///
+    /// assert!(client.get("https://example.com/").await.is_err());
+    /// assert_eq!(*order.lock().unwrap(), ["first", "second"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn middleware_fn<F>(mut self, factory: F) -> Self
+    where
+        F: FnOnce(WeakClient) -> Box<dyn Middleware> + Send + 'static,
+    {
+        self.middlewares.push(BuilderMiddleware::Factory(Box::new(factory)));
+        self
+    }
+
+    /// Sets the handler answering authentication challenges to paid requests.
+    pub fn challenge_handler(mut self, handler: Box<dyn ChallengeHandler>) -> Self {
+        self.challenge_handler = Some(handler);
+        self
+    }
+
+    /// Adds a response verifier to the client.
+    pub fn verifier(mut self, verifier: Box<dyn ResponseVerifier>) -> Self {
+        self.verifiers.push(verifier);
         self
     }
 
     /// Builds the client.
     pub async fn build(self) -> Result<Client> {
         let config = self.config_builder.build()?;
-        let mut client = Client::new(config).await?;
+        let client = Client::new(config).await?;
         
-        // Add middlewares
+        // Add middlewares in order, creating those that need the client
         for middleware in self.middlewares {
+            let middleware = match middleware {
+                BuilderMiddleware::Ready(middleware) => middleware,
+                BuilderMiddleware::Factory(factory) => factory(client.downgrade()),
+            };
             client.add_middleware(middleware);
         }
         
+        // Add response verifiers
+        for verifier in self.verifiers {
+            client.add_verifier(verifier);
+        }
+        
+        for handler in self.spend_alert_handlers {
+            client.add_spend_alert_handler(handler);
+        }
+        
+        if let Some(approver) = self.payment_approver {
+            client.set_payment_approver(approver);
+        }
+        
+        if let Some(handler) = self.challenge_handler {
+            client.set_challenge_handler(handler);
+        }
+        
+        if let Some((interval, sink)) = self.spend_reporter {
+            client.start_spend_reporter(interval, sink).await;
+        }
+        
+        if self.warm_up_on_build {
+            client.warm_up(&[]).await;
+        }
+        
         Ok(client)
     }
 }
@@ -878,6 +3906,388 @@ impl Default for ClientBuilder {
     }
 }
 
-// Implement Send + Sync for Client (all components are thread-safe)
-unsafe impl Send for Client {}
-unsafe impl Sync for Client {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::ChainConfig, types::PaymentStatus};
+    use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, COOKIE};
+    use wiremock::{matchers::method, Mock, MockServer, ResponseTemplate};
+
+    const REQUIREMENTS: &str = r#"{"scheme":"exact","network":"base","maxAmountRequired":"1000","resource":"https://api.example.com/data","payTo":"0x1111111111111111111111111111111111111111","asset":"0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913"}"#;
+
+    async fn head_402(response: ResponseTemplate) -> PaymentCheckResult {
+        let server = MockServer::start().await;
+        Mock::given(method("HEAD")).respond_with(response).expect(1).mount(&server).await;
+        let client = Client::builder().build().await.unwrap();
+        client.head_check(&server.uri()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn head_check_reads_requirements_from_header() {
+        let check = head_402(ResponseTemplate::new(402).insert_header(PAYMENT_REQUIRED_HEADER, REQUIREMENTS)).await;
+        assert!(check.requires_payment);
+        assert_eq!(check.requirements.unwrap().max_amount_required, "1000");
+    }
+
+    #[tokio::test]
+    async fn head_check_accepts_402_without_requirements() {
+        let check = head_402(ResponseTemplate::new(402)).await;
+        assert!(check.requires_payment);
+        assert!(check.requirements.is_none());
+    }
+
+    #[tokio::test]
+    async fn repeated_headers_are_combined() {
+        let server = MockServer::start().await;
+        Mock::given(wiremock::matchers::any())
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+        let client = Client::builder().build().await.unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.append(ACCEPT, HeaderValue::from_static("application/json"));
+        headers.append(ACCEPT, HeaderValue::from_static("text/plain"));
+        headers.append(COOKIE, HeaderValue::from_static("a=1"));
+        client
+            .request_builder(reqwest::Method::GET, &server.uri())
+            .headers(headers)
+            .header("cookie", "b=2")
+            .bypass_cache()
+            .send()
+            .await
+            .unwrap();
+
+        let request = &server.received_requests().await.unwrap()[0];
+        let values = |name: &str| request.headers.get_all(name).iter().map(|value| value.to_str().unwrap()).collect::<Vec<_>>();
+        assert_eq!(values("accept"), ["application/json, text/plain"]);
+        assert_eq!(values("cookie"), ["a=1; b=2"]);
+    }
+
+    #[test]
+    fn prefetch_totals_are_kept_per_asset() {
+        let paid = |network: &str, asset: &str, amount: u128| {
+            serde_json::from_value::<PaymentResponse>(serde_json::json!({
+                "url": "https://api.example.com/data",
+                "status": 200,
+                "headers": {},
+                "body": [],
+                "payment_made": true,
+                "payment_amount": amount.to_string(),
+                "payment_asset": asset,
+                "network": network,
+                "timestamp": chrono::Utc::now(),
+            }))
+            .unwrap()
+        };
+        let mut totals = BTreeMap::new();
+        add_payment(&mut totals, &paid("base", "0xusdc", 1000));
+        add_payment(&mut totals, &paid("base", "0xusdc", 500));
+        add_payment(&mut totals, &paid("base", "0xeurc", 7));
+        add_payment(&mut totals, &paid("polygon", "0xusdc", 1));
+        assert_eq!(
+            totals,
+            BTreeMap::from([
+                ("base:0xeurc".to_string(), 7),
+                ("base:0xusdc".to_string(), 1500),
+                ("polygon:0xusdc".to_string(), 1),
+            ])
+        );
+
+        add_payment(&mut totals, &paid("base", "0xeurc", u128::MAX));
+        assert_eq!(totals["base:0xeurc"], u128::MAX);
+    }
+
+    fn retrying_chain_config(rpc: &MockServer, max_wait: Duration) -> Arc<Config> {
+        Arc::new(Config {
+            chains: vec![crate::ChainConfig::base_mainnet().with_rpc_url(rpc.uri())],
+            connection_retry_on_start: true,
+            connection_retry_max_wait: max_wait,
+            ..Config::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn connect_chains_retries_until_the_endpoint_answers() {
+        let rpc = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .mount(&rpc)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "result": "0x10",
+            })))
+            .mount(&rpc)
+            .await;
+
+        Client::connect_chains(&retrying_chain_config(&rpc, Duration::from_secs(10))).await.unwrap();
+        assert_eq!(rpc.received_requests().await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn connect_chains_gives_up_at_the_deadline() {
+        let rpc = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(30)))
+            .mount(&rpc)
+            .await;
+
+        let started = Instant::now();
+        let error = Client::connect_chains(&retrying_chain_config(&rpc, Duration::from_secs(1))).await.unwrap_err();
+        assert!(matches!(error, Error::Timeout(..)), "{error}");
+        assert!(started.elapsed() < Duration::from_secs(3), "{:?}", started.elapsed());
+    }
+
+    #[tokio::test]
+    async fn batch_results_follow_the_order_of_the_urls() {
+        let server = MockServer::start().await;
+        // Earlier URLs answer later, so completions arrive in reverse order
+        for index in 0..5u64 {
+            Mock::given(wiremock::matchers::path(format!("/{}", index)))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .set_body_string(index.to_string())
+                        .set_delay(Duration::from_millis(50 * (5 - index))),
+                )
+                .mount(&server)
+                .await;
+        }
+        let client = Client::builder().build().await.unwrap();
+
+        let mut urls: Vec<String> = (0..5).map(|index| format!("{}/{}", server.uri(), index)).collect();
+        urls.insert(2, "not a url".to_string());
+        let results = client.batch_get(&urls, urls.len()).await.unwrap();
+
+        assert_eq!(results.len(), urls.len());
+        assert!(results[2].is_err());
+        let bodies: Vec<_> = results
+            .iter()
+            .filter_map(|result| result.as_ref().ok())
+            .map(|response| String::from_utf8_lossy(&response.body).into_owned())
+            .collect();
+        assert_eq!(bodies, ["0", "1", "2", "3", "4"]);
+    }
+
+    /// Returns a JSON-RPC response carrying `outcome`, a `result` or an `error`.
+    fn rpc_response(outcome: serde_json::Value) -> ResponseTemplate {
+        let mut body = serde_json::json!({ "jsonrpc": "2.0", "id": 1 });
+        body.as_object_mut().unwrap().extend(outcome.as_object().unwrap().clone());
+        ResponseTemplate::new(200).set_body_json(body)
+    }
+
+    /// Starts a server asking for a payment on `/data`, with an RPC on
+    /// `/rpc` whose `transferWithAuthorization` simulation returns `simulation`.
+    async fn dry_run_server(simulation: serde_json::Value) -> MockServer {
+        use wiremock::matchers::{body_partial_json, body_string_contains, path};
+
+        let server = MockServer::start().await;
+        Mock::given(path("/rpc"))
+            .and(body_string_contains("0xe3ee160e"))
+            .respond_with(rpc_response(simulation))
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(path("/rpc"))
+            .and(body_partial_json(serde_json::json!({ "method": "eth_chainId" })))
+            .respond_with(rpc_response(serde_json::json!({ "result": "0x2105" })))
+            .with_priority(2)
+            .mount(&server)
+            .await;
+        // Balance and allowance reads
+        Mock::given(path("/rpc"))
+            .and(body_partial_json(serde_json::json!({ "method": "eth_call" })))
+            .respond_with(rpc_response(serde_json::json!({
+                "result": format!("0x{}{}", "0".repeat(32), "f".repeat(32)),
+            })))
+            .with_priority(3)
+            .mount(&server)
+            .await;
+        Mock::given(path("/data"))
+            .respond_with(ResponseTemplate::new(402).set_body_json(serde_json::json!({
+                "x402Version": 1,
+                "accepts": [serde_json::from_str::<serde_json::Value>(REQUIREMENTS).unwrap()],
+            })))
+            .mount(&server)
+            .await;
+        server
+    }
+
+    async fn dry_run_client(server: &MockServer) -> Client {
+        Client::new(Config {
+            chains: vec![ChainConfig::base_mainnet()
+                .with_rpc_url(format!("{}/rpc", server.uri()))
+                .with_dry_run(true)],
+            private_key: Some("0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318".to_string().into()),
+            ..Config::default()
+        })
+        .await
+        .unwrap()
+    }
+
+    /// Returns the requests `server` received for `path` with a payment.
+    async fn paid_requests(server: &MockServer, path: &str) -> usize {
+        let requests = server.received_requests().await.unwrap();
+        requests.iter().filter(|request| request.url.path() == path && request.headers.contains_key("x-payment")).count()
+    }
+
+    #[tokio::test]
+    async fn dry_run_payments_are_simulated_not_sent() {
+        let server = dry_run_server(serde_json::json!({ "result": "0x" })).await;
+        let client = dry_run_client(&server).await;
+
+        let response = client.get(&format!("{}/data", server.uri())).await.unwrap();
+        assert!(response.dry_run);
+        assert_eq!(response.status, 200);
+        assert!(!response.payment_made);
+        assert_eq!(paid_requests(&server, "/data").await, 0);
+        let requests = server.received_requests().await.unwrap();
+        assert!(requests.iter().all(|request| !String::from_utf8_lossy(&request.body).contains("eth_sendRawTransaction")));
+
+        let history = client.get_payment_history(10).await.unwrap();
+        assert_eq!(history.len(), 1);
+        assert!(history[0].dry_run);
+        assert_eq!(history[0].status, PaymentStatus::Confirmed);
+
+        let metrics = client.metrics_snapshot();
+        assert_eq!((metrics.counters.payments_dry_run, metrics.counters.payments_live), (1, 0));
+    }
+
+    #[tokio::test]
+    async fn reverted_dry_run_payments_are_recorded_as_failed() {
+        let revert = serde_json::json!({ "error": { "code": 3, "message": "execution reverted: FiatTokenV2: invalid signature" } });
+        let server = dry_run_server(revert).await;
+        let client = dry_run_client(&server).await;
+
+        let response = client.get(&format!("{}/data", server.uri())).await.unwrap();
+        assert!(response.dry_run);
+        assert_eq!(response.status, 402);
+        assert_eq!(paid_requests(&server, "/data").await, 0);
+
+        let history = client.get_payment_history(10).await.unwrap();
+        assert!(history[0].dry_run);
+        assert_eq!(history[0].status, PaymentStatus::Failed);
+    }
+
+    #[tokio::test]
+    async fn errors_carry_the_request_that_failed() {
+        let server = dry_run_server(serde_json::json!({ "result": "0x" })).await;
+        let client = Client::new(Config {
+            on_payment_required: PaymentRequiredBehavior::Error,
+            ..Config::default()
+        })
+        .await
+        .unwrap();
+        let url = format!("{}/data", server.uri());
+
+        let error = client
+            .request_builder(reqwest::Method::GET, &url)
+            .request_id("batch-7-item-12")
+            .send()
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::PaymentRequired { .. }), "{error}");
+        assert_eq!(error.url(), Some(url.as_str()));
+        assert_eq!(error.request_id(), Some("batch-7-item-12"));
+        assert_eq!(error.attempt(), 1);
+        assert!(!error.payment_attempted());
+        assert!(error.to_string().contains(&format!("url {url}, request batch-7-item-12, attempt 1")), "{error}");
+
+        // Requests failing before they are sent still say which they were
+        let error = client.get("not a url").await.unwrap_err();
+        assert_eq!(error.url(), Some("not a url"));
+        assert!(error.request_id().is_some());
+        assert_eq!(error.attempt(), 0);
+    }
+
+    #[tokio::test]
+    async fn error_context_survives_payment_retries() {
+        use wiremock::matchers::{header_exists, path};
+
+        let server = dry_run_server(serde_json::json!({ "result": "0x" })).await;
+        Mock::given(path("/data"))
+            .and(header_exists("x-payment"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "0"))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(path("/data"))
+            .and(header_exists("x-payment"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(5)))
+            .with_priority(2)
+            .mount(&server)
+            .await;
+        let client = Client::new(Config {
+            chains: vec![ChainConfig::base_mainnet().with_rpc_url(format!("{}/rpc", server.uri()))],
+            private_key: Some("0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318".to_string().into()),
+            timeout: Duration::from_millis(500),
+            ..Config::default()
+        })
+        .await
+        .unwrap();
+        let url = format!("{}/data", server.uri());
+
+        let error = client.get(&url).await.unwrap_err();
+        assert_eq!(paid_requests(&server, "/data").await, 2);
+        assert_eq!(error.url(), Some(url.as_str()));
+        assert!(error.request_id().is_some());
+        // The unpaid request, the rate-limited payment and its retry
+        assert_eq!(error.attempt(), 3, "{error}");
+        assert!(error.payment_attempted());
+        assert!(error.to_string().ends_with(", attempt 3, payment attempted)"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn refused_payments_release_their_nonce_and_are_recorded_as_failed() {
+        use wiremock::matchers::{body_string_contains, path};
+
+        // Every request to /data, paid or not, is answered with a 402
+        let server = dry_run_server(serde_json::json!({ "result": "0x" })).await;
+        let get_nonce = hex::encode(crate::crypto::function_selector("getNonce(address)"));
+        Mock::given(path("/rpc"))
+            .and(body_string_contains(get_nonce.as_str()))
+            .respond_with(rpc_response(serde_json::json!({
+                "result": format!("0x{}", hex::encode(crate::crypto::uint_word(7))),
+            })))
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        let client = Client::new(Config {
+            chains: vec![ChainConfig::base_mainnet()
+                .with_rpc_url(format!("{}/rpc", server.uri()))
+                .with_payment_contract("0x2222222222222222222222222222222222222222")],
+            private_key: Some("0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318".to_string().into()),
+            on_chain_nonce: true,
+            preemptive_payment: false,
+            ..Config::default()
+        })
+        .await
+        .unwrap();
+        let url = format!("{}/data", server.uri());
+
+        for _ in 0..2 {
+            let response = client.get(&url).await.unwrap();
+            assert_eq!(response.status, 402);
+            assert!(!response.payment_made);
+        }
+        assert_eq!(paid_requests(&server, "/data").await, 2);
+
+        // The second payment got the nonce the refused first one released
+        let nonce = format!("0x{}", hex::encode(crate::crypto::uint_word(7)));
+        let history = client.get_payment_history(10).await.unwrap();
+        assert_eq!(history.len(), 2);
+        for record in &history {
+            assert_eq!(record.status, PaymentStatus::Failed);
+            assert_eq!(record.authorization_nonce.as_deref(), Some(nonce.as_str()));
+        }
+
+        let statistics = client.get_payment_statistics().await.unwrap();
+        assert_eq!((statistics.successful_payments, statistics.failed_payments), (0, 2));
+        assert_eq!(client.metrics_snapshot().counters.payments_live, 0);
+    }
+}
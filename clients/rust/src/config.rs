@@ -0,0 +1,2448 @@
+//! Client configuration.
+//!
+//! Configuration is built with [`ConfigBuilder`], which validates the result
+//! before handing out an immutable [`Config`].
+//!
+//! ```rust,no_run
+//! use v402_client::{Config, ChainConfig};
+//! use std::time::Duration;
+//!
+//! let config = Config::builder()
+//!     .private_key("0x...")
+//!     .auto_pay(true)
+//!     .timeout(Duration::from_secs(30))
+//!     .add_chain(ChainConfig::base_mainnet())
+//!     .build()?;
+//! # Ok::<(), v402_client::Error>(())
+//! ```
+
+use crate::{
+    chains::{NetworkId, TokenInfo},
+    crypto,
+    error::{dns_error, Error, Result},
+    http::{self, DnsResolver},
+    payment::hints::DEFAULT_PAYMENT_HINT_HEADERS,
+    secrets::{SecretProvider, SecretStore},
+    DEFAULT_FACILITATOR_URL, MAX_PAYMENT_AMOUNT,
+};
+use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize};
+use reqwest::dns::Resolve;
+use std::{
+    collections::{HashMap, HashSet},
+    net::SocketAddr,
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+
+/// Default header carrying the request correlation ID.
+pub const DEFAULT_REQUEST_ID_HEADER: &str = "X-Request-ID";
+
+/// Longest time [`Config::validate_deep`] waits for a facilitator health check.
+const FACILITATOR_HEALTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Label of the wallet holding [`Config::private_key`].
+pub const DEFAULT_WALLET: &str = "default";
+
+/// Token price API used when no price oracle is configured.
+pub const DEFAULT_PRICE_ORACLE_URL: &str = "https://api.coingecko.com/api/v3/simple/token_price";
+
+/// Supported blockchain networks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChainType {
+    /// Ethereum mainnet and testnets
+    Ethereum,
+    /// Base (Coinbase L2)
+    Base,
+    /// Polygon PoS
+    Polygon,
+    /// Arbitrum One
+    Arbitrum,
+    /// Optimism
+    Optimism,
+    /// Avalanche C-Chain
+    Avalanche,
+    /// BNB Smart Chain
+    Bsc,
+    /// Solana
+    Solana,
+}
+
+impl ChainType {
+    /// Returns `true` for chains using the EVM and Ethereum ABI.
+    pub fn is_evm(&self) -> bool {
+        !matches!(self, ChainType::Solana)
+    }
+}
+
+/// Configuration for a single blockchain network.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChainConfig {
+    /// Network name as used in payment requirements (e.g. `base`, `base-sepolia`)
+    pub name: String,
+
+    /// Chain family
+    pub chain_type: ChainType,
+
+    /// EVM chain ID
+    pub chain_id: Option<u64>,
+
+    /// RPC endpoint
+    pub rpc_url: String,
+
+    /// Native currency symbol used for gas
+    pub native_currency: String,
+
+    /// Block explorer base URL
+    pub explorer_url: Option<String>,
+
+    /// Payment contract exposing `getNonce(address)`, used for on-chain nonces
+    #[serde(default)]
+    pub payment_contract: Option<String>,
+
+    /// Contracts exposing `batchTransfer(address[],uint256[])` by the token
+    /// they pay in, used by `PaymentManager::batch_pay`
+    #[serde(default)]
+    pub batch_contracts: HashMap<String, String>,
+
+    /// Facilitator for payments on this chain, overriding [`Config::facilitator_url`]
+    #[serde(default)]
+    pub facilitator_url: Option<String>,
+
+    /// Health monitor thresholds, defaulting to [`ChainHealthConfig::for_chain_type`]
+    #[serde(default)]
+    pub health: Option<ChainHealthConfig>,
+
+    /// Tokens on this chain in addition to the built-in [`TokenRegistry`](crate::chains::TokenRegistry)
+    #[serde(default)]
+    pub tokens: Vec<TokenInfo>,
+
+    /// Preference when several offered chains can pay; higher values are preferred.
+    /// Chains listed in [`Config::chain_priority`] rank above any value set here.
+    #[serde(default)]
+    pub priority: Option<u8>,
+
+    /// Simulate payments with `eth_call` instead of sending them, see [`ConfigBuilder::dry_run_chains`]
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+impl ChainConfig {
+    /// Creates a chain configuration.
+    pub fn new<N, R>(name: N, chain_type: ChainType, rpc_url: R) -> Self
+    where
+        N: Into<String>,
+        R: Into<String>,
+    {
+        Self {
+            name: name.into(),
+            chain_type,
+            chain_id: None,
+            rpc_url: rpc_url.into(),
+            native_currency: if chain_type == ChainType::Solana { "SOL" } else { "ETH" }.to_string(),
+            explorer_url: None,
+            payment_contract: None,
+            batch_contracts: HashMap::new(),
+            facilitator_url: None,
+            health: None,
+            tokens: Vec::new(),
+            priority: None,
+            dry_run: false,
+        }
+    }
+
+    /// Sets the EVM chain ID.
+    pub fn with_chain_id(mut self, chain_id: u64) -> Self {
+        self.chain_id = Some(chain_id);
+        self
+    }
+
+    /// Overrides the RPC endpoint.
+    pub fn with_rpc_url<S: Into<String>>(mut self, rpc_url: S) -> Self {
+        self.rpc_url = rpc_url.into();
+        self
+    }
+
+    /// Sets the native currency symbol.
+    pub fn with_native_currency<S: Into<String>>(mut self, symbol: S) -> Self {
+        self.native_currency = symbol.into();
+        self
+    }
+
+    /// Sets the block explorer base URL.
+    pub fn with_explorer_url<S: Into<String>>(mut self, url: S) -> Self {
+        self.explorer_url = Some(url.into());
+        self
+    }
+
+    /// Sets the payment contract queried for on-chain nonces.
+    pub fn with_payment_contract<S: Into<String>>(mut self, address: S) -> Self {
+        self.payment_contract = Some(address.into());
+        self
+    }
+
+    /// Sets the contract batch payments in `token` are sent to.
+    ///
+    /// The contract pays out of the payer's balance, so the payer must have
+    /// approved it to spend the token.
+    pub fn with_batch_contract<T: Into<String>, C: Into<String>>(mut self, token: T, contract: C) -> Self {
+        self.batch_contracts.insert(token.into(), contract.into());
+        self
+    }
+
+    /// Returns the batch transfer contract configured for `token`, if any.
+    pub fn batch_contract(&self, token: &str) -> Option<&str> {
+        self.batch_contracts
+            .iter()
+            .find(|(configured, _)| configured.eq_ignore_ascii_case(token))
+            .map(|(_, contract)| contract.as_str())
+    }
+
+    /// Sets the facilitator used for payments on this chain.
+    pub fn with_facilitator_url<S: Into<String>>(mut self, url: S) -> Self {
+        self.facilitator_url = Some(url.into());
+        self
+    }
+
+    /// Sets the preference of this chain when several offered chains can pay.
+    pub fn with_priority(mut self, priority: u8) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    /// Simulates payments on this chain instead of sending them, see
+    /// [`ConfigBuilder::dry_run_chains`].
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Overrides the health monitor thresholds.
+    pub fn with_health(mut self, health: ChainHealthConfig) -> Self {
+        self.health = Some(health);
+        self
+    }
+
+    /// Registers a token so its amounts are shown with `symbol` and `decimals`.
+    ///
+    /// Overrides a built-in token with the same address.
+    pub fn register_token<A, S>(mut self, address: A, symbol: S, decimals: u8) -> Self
+    where
+        A: Into<String>,
+        S: Into<String>,
+    {
+        self.tokens.push(TokenInfo::new(address, symbol, decimals));
+        self
+    }
+
+    /// Health monitor thresholds in effect for this chain.
+    pub fn health_config(&self) -> ChainHealthConfig {
+        self.health
+            .clone()
+            .unwrap_or_else(|| ChainHealthConfig::for_chain_type(self.chain_type))
+    }
+
+    /// Ethereum mainnet.
+    pub fn ethereum_mainnet() -> Self {
+        Self::new("ethereum", ChainType::Ethereum, "https://eth.llamarpc.com")
+            .with_chain_id(1)
+            .with_explorer_url("https://etherscan.io")
+    }
+
+    /// Ethereum Sepolia testnet.
+    pub fn ethereum_sepolia() -> Self {
+        Self::new("ethereum-sepolia", ChainType::Ethereum, "https://rpc.sepolia.org")
+            .with_chain_id(11_155_111)
+            .with_explorer_url("https://sepolia.etherscan.io")
+    }
+
+    /// Base mainnet.
+    pub fn base_mainnet() -> Self {
+        Self::new("base", ChainType::Base, "https://mainnet.base.org")
+            .with_chain_id(8453)
+            .with_explorer_url("https://basescan.org")
+    }
+
+    /// Base Sepolia testnet.
+    pub fn base_sepolia() -> Self {
+        Self::new("base-sepolia", ChainType::Base, "https://sepolia.base.org")
+            .with_chain_id(84532)
+            .with_explorer_url("https://sepolia.basescan.org")
+    }
+
+    /// Polygon PoS mainnet.
+    pub fn polygon_mainnet() -> Self {
+        Self::new("polygon", ChainType::Polygon, "https://polygon-rpc.com")
+            .with_chain_id(137)
+            .with_native_currency("POL")
+            .with_explorer_url("https://polygonscan.com")
+    }
+
+    /// Arbitrum One mainnet.
+    pub fn arbitrum_mainnet() -> Self {
+        Self::new("arbitrum", ChainType::Arbitrum, "https://arb1.arbitrum.io/rpc")
+            .with_chain_id(42161)
+            .with_explorer_url("https://arbiscan.io")
+    }
+
+    /// Optimism mainnet.
+    pub fn optimism_mainnet() -> Self {
+        Self::new("optimism", ChainType::Optimism, "https://mainnet.optimism.io")
+            .with_chain_id(10)
+            .with_explorer_url("https://optimistic.etherscan.io")
+    }
+
+    /// Avalanche C-Chain mainnet.
+    pub fn avalanche_mainnet() -> Self {
+        Self::new("avalanche", ChainType::Avalanche, "https://api.avax.network/ext/bc/C/rpc")
+            .with_chain_id(43114)
+            .with_native_currency("AVAX")
+            .with_explorer_url("https://snowtrace.io")
+    }
+
+    /// Avalanche Fuji testnet.
+    pub fn avalanche_fuji() -> Self {
+        Self::new("avalanche-fuji", ChainType::Avalanche, "https://api.avax-test.network/ext/bc/C/rpc")
+            .with_chain_id(43113)
+            .with_native_currency("AVAX")
+            .with_explorer_url("https://testnet.snowtrace.io")
+    }
+
+    /// BNB Smart Chain mainnet.
+    pub fn bsc_mainnet() -> Self {
+        Self::bsc_mainnet_with_rpc("https://bsc-dataseed.binance.org/")
+    }
+
+    /// BNB Smart Chain mainnet using a custom RPC endpoint.
+    pub fn bsc_mainnet_with_rpc<S: Into<String>>(rpc_url: S) -> Self {
+        Self::new("bsc", ChainType::Bsc, rpc_url)
+            .with_chain_id(56)
+            .with_native_currency("BNB")
+            .with_explorer_url("https://bscscan.com")
+    }
+
+    /// BNB Smart Chain testnet.
+    pub fn bsc_testnet() -> Self {
+        Self::new("bsc-testnet", ChainType::Bsc, "https://data-seed-prebsc-1-s1.binance.org:8545/")
+            .with_chain_id(97)
+            .with_native_currency("BNB")
+            .with_explorer_url("https://testnet.bscscan.com")
+    }
+
+    /// Solana mainnet-beta.
+    pub fn solana_mainnet() -> Self {
+        Self::new("solana", ChainType::Solana, "https://api.mainnet-beta.solana.com")
+            .with_explorer_url("https://explorer.solana.com")
+    }
+
+    /// Solana devnet.
+    pub fn solana_devnet() -> Self {
+        Self::new("solana-devnet", ChainType::Solana, "https://api.devnet.solana.com")
+            .with_explorer_url("https://explorer.solana.com")
+    }
+}
+
+/// Thresholds of the background health monitor for a chain.
+///
+/// A check fails when the RPC call errors, is slower than `max_latency`, the
+/// chain has produced no new block for `stall_timeout`, or more than
+/// `max_error_rate` of the RPC calls since the previous check failed. After
+/// `failure_threshold` consecutive failed checks the chain is quarantined and
+/// excluded from payment selection until a check succeeds again.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChainHealthConfig {
+    /// Whether the chain is monitored in the background
+    pub enabled: bool,
+
+    /// Interval between checks
+    pub check_interval: Duration,
+
+    /// Consecutive failed checks before the chain is quarantined
+    pub failure_threshold: u32,
+
+    /// Slowest acceptable RPC response
+    pub max_latency: Duration,
+
+    /// Longest acceptable time without a new block
+    pub stall_timeout: Duration,
+
+    /// Highest acceptable fraction of failed RPC calls between checks
+    pub max_error_rate: f64,
+}
+
+impl ChainHealthConfig {
+    /// Thresholds suited to EVM chains, whose blocks arrive every 2-12 seconds.
+    pub fn evm() -> Self {
+        Self {
+            enabled: true,
+            check_interval: Duration::from_secs(30),
+            failure_threshold: 3,
+            max_latency: Duration::from_secs(5),
+            stall_timeout: Duration::from_secs(120),
+            max_error_rate: 0.5,
+        }
+    }
+
+    /// Thresholds suited to Solana, whose slots advance every ~400 milliseconds.
+    pub fn solana() -> Self {
+        Self {
+            enabled: true,
+            check_interval: Duration::from_secs(10),
+            failure_threshold: 3,
+            max_latency: Duration::from_secs(2),
+            stall_timeout: Duration::from_secs(30),
+            max_error_rate: 0.5,
+        }
+    }
+
+    /// Default thresholds for a chain family.
+    pub fn for_chain_type(chain_type: ChainType) -> Self {
+        match chain_type {
+            ChainType::Solana => Self::solana(),
+            _ => Self::evm(),
+        }
+    }
+
+    /// Disables background monitoring; the chain is never quarantined automatically.
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            ..Self::evm()
+        }
+    }
+}
+
+impl Default for ChainHealthConfig {
+    fn default() -> Self {
+        Self::evm()
+    }
+}
+
+/// What the client does with a `402 Payment Required` response.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PaymentRequiredBehavior {
+    /// Pay if [`Config::auto_pay`] is enabled, otherwise return the 402 response as is
+    #[default]
+    Pay,
+    /// Never pay; return the 402 response with its parsed
+    /// [`requirements`](crate::PaymentResponse::requirements)
+    ReturnParsed,
+    /// Never pay; fail with [`Error::PaymentRequired`] carrying the parsed requirements
+    Error,
+}
+
+/// A labelled wallet, for paying from several wallets with one client.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalletConfig {
+    /// Name the wallet is referred to by in routes, history and statistics
+    pub label: String,
+
+    /// Hex-encoded private key of the wallet, zeroized on drop
+    #[serde(skip_serializing)]
+    pub private_key: SecretString,
+
+    /// Maximum amount this wallet pays per request, in the asset's smallest
+    /// unit, on top of the client-wide limits
+    #[serde(default)]
+    pub max_amount_per_request: Option<String>,
+}
+
+impl WalletConfig {
+    /// Creates a wallet without its own spending limit.
+    pub fn new<L: Into<String>, K: Into<String>>(label: L, private_key: K) -> Self {
+        Self {
+            label: label.into(),
+            private_key: SecretString::new(private_key.into()),
+            max_amount_per_request: None,
+        }
+    }
+
+    /// Limits the amount this wallet pays per request.
+    pub fn max_amount_per_request<S: Into<String>>(mut self, amount: S) -> Self {
+        self.max_amount_per_request = Some(amount.into());
+        self
+    }
+}
+
+/// Sends payments for matching URLs from a given wallet.
+///
+/// A pattern is either a host (`api.example.com`), a wildcard matching any
+/// subdomain (`*.example.com`), or an absolute URL prefix
+/// (`https://example.com/premium/`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WalletRoute {
+    /// Host, wildcard or URL prefix to match
+    pub pattern: String,
+
+    /// Label of the wallet paying for matching URLs
+    pub wallet: String,
+}
+
+impl WalletRoute {
+    /// Returns `true` if `url` matches the route's pattern.
+    pub fn matches(&self, url: &url::Url) -> bool {
+        pattern_matches(&self.pattern, url)
+    }
+}
+
+/// Returns `true` if `url` matches a host, wildcard or URL prefix pattern,
+/// as described on [`WalletRoute`].
+pub(crate) fn pattern_matches(pattern: &str, url: &url::Url) -> bool {
+    let pattern = pattern.trim();
+    if pattern.contains("://") {
+        return url.as_str().starts_with(pattern);
+    }
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host
+            .strip_suffix(domain)
+            .is_some_and(|subdomain| subdomain.ends_with('.') && domain.len() < host.len()),
+        None => host.eq_ignore_ascii_case(pattern),
+    }
+}
+
+/// Alerting on runaway spend to a single host.
+///
+/// Spend is summed per host over a sliding [`window`](Self::window); when
+/// the sum reaches the host's threshold a
+/// [`PaymentEvent::SpendAlert`](crate::PaymentEvent::SpendAlert) is published
+/// and every [`SpendAlertHandler`](crate::payment::SpendAlertHandler) is
+/// called. A host alerts again only after its spend has dropped below the
+/// threshold.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpendAlertConfig {
+    /// Length of the window spend is summed over
+    pub window: Duration,
+
+    /// Spend per host within the window that triggers an alert, in the asset's smallest unit
+    pub threshold: Option<String>,
+
+    /// Thresholds for hosts matching a pattern, overriding [`threshold`](Self::threshold); first match wins
+    pub host_thresholds: Vec<HostSpendThreshold>,
+}
+
+impl Default for SpendAlertConfig {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(3600),
+            threshold: None,
+            host_thresholds: Vec::new(),
+        }
+    }
+}
+
+impl SpendAlertConfig {
+    /// Returns the threshold applying to `url`, if any.
+    pub fn threshold_for(&self, url: &url::Url) -> Option<&str> {
+        self.host_thresholds
+            .iter()
+            .find(|threshold| pattern_matches(&threshold.pattern, url))
+            .map(|threshold| threshold.threshold.as_str())
+            .or(self.threshold.as_deref())
+    }
+}
+
+/// Spend alert threshold for the hosts matching a pattern.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostSpendThreshold {
+    /// Host, wildcard or URL prefix to match, as for [`WalletRoute`]
+    pub pattern: String,
+
+    /// Spend within the window that triggers an alert, in the asset's smallest unit
+    pub threshold: String,
+}
+
+/// What happens when the cache or the metrics collector fails, see
+/// [`degradation`](crate::degradation).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DegradationPolicy {
+    /// Log the failure and continue without the component
+    #[default]
+    Degrade,
+    /// Return the failure as an error, failing client creation or the request
+    Fail,
+}
+
+/// What happens when a 402 asks for far more than a resource usually costs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PriceJumpAction {
+    /// Pay, logging a warning and publishing a [`PaymentEvent::PriceJump`](crate::PaymentEvent::PriceJump)
+    #[default]
+    Warn,
+    /// Pay only if the [`PaymentApprover`](crate::payment::PaymentApprover) approves
+    Approve,
+    /// Refuse to pay
+    Reject,
+}
+
+/// Detection of sudden price increases.
+///
+/// The prices quoted by 402 responses, paid or not, are kept per host and
+/// path prefix. When a 402 asks for
+/// more than [`multiplier`](Self::multiplier) times the median of the
+/// recent prices for its prefix, [`action`](Self::action) decides whether
+/// the payment goes ahead. Taking the median means a single spike never
+/// raises the baseline; a new price becomes the baseline once it makes up
+/// more than half the history.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PriceJumpConfig {
+    /// Ratio of a new price to the baseline counted as a jump; unset disables detection
+    pub multiplier: Option<f64>,
+
+    /// What happens on a jump
+    pub action: PriceJumpAction,
+
+    /// Number of recent prices kept per prefix
+    pub history_size: usize,
+
+    /// Number of leading path segments prices are grouped by; 0 groups by host only
+    pub path_depth: usize,
+}
+
+impl Default for PriceJumpConfig {
+    fn default() -> Self {
+        Self {
+            multiplier: None,
+            action: PriceJumpAction::Warn,
+            history_size: 16,
+            path_depth: 1,
+        }
+    }
+}
+
+/// Response cache configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// Whether responses are cached
+    pub enabled: bool,
+
+    /// Maximum total size of cached responses in bytes
+    pub max_size_bytes: u64,
+
+    /// Responses larger than this many bytes are never cached
+    pub max_entry_size_bytes: u64,
+
+    /// Time-to-live of cached entries
+    pub ttl: Duration,
+
+    /// Refresh entries in the background during the last 10% of their TTL
+    pub preemptive_refresh: bool,
+
+    /// Prefix keeping this client's entries apart in a shared cache, see
+    /// [`CacheManager::with_namespace`](crate::cache::CacheManager::with_namespace)
+    pub namespace: Option<String>,
+
+    /// Hex-encoded 32-byte key encrypting cached responses with AES-256-GCM
+    #[serde(skip_serializing)]
+    pub encryption_key: Option<SecretString>,
+
+    /// Passphrase the cache encryption key is derived from if no
+    /// [`encryption_key`](Self::encryption_key) is set
+    #[serde(skip_serializing)]
+    pub encryption_passphrase: Option<SecretString>,
+
+    /// Hash response bodies and store identical bodies cached under
+    /// different URLs once; not available with encryption
+    pub content_dedup: bool,
+
+    /// Serve content already cached under another URL instead of paying when
+    /// a 402 response announces its hash; requires
+    /// [`content_dedup`](Self::content_dedup)
+    pub skip_payment_for_cached_content: bool,
+
+    /// Store large bodies compressed with zstd; requires the `compression` feature
+    pub compression: bool,
+
+    /// zstd level of compressed bodies, 1 to 22
+    pub compression_level: i32,
+
+    /// Bodies smaller than this many bytes are stored uncompressed
+    pub compression_threshold_bytes: u64,
+}
+
+/// Default of [`CacheConfig::compression_threshold_bytes`].
+///
+/// At zstd level 3 compressing a 16 KiB JSON body takes tens of
+/// microseconds and decompressing it on each hit around ten, while below it
+/// the bytes saved are small next to the per-entry overhead; see the
+/// `cache_compression` benchmark.
+pub const DEFAULT_CACHE_COMPRESSION_THRESHOLD: u64 = 16 * 1024;
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_size_bytes: 64 * 1024 * 1024,
+            max_entry_size_bytes: 8 * 1024 * 1024,
+            ttl: Duration::from_secs(300),
+            preemptive_refresh: false,
+            namespace: None,
+            encryption_key: None,
+            encryption_passphrase: None,
+            content_dedup: false,
+            skip_payment_for_cached_content: false,
+            compression: false,
+            compression_level: 3,
+            compression_threshold_bytes: DEFAULT_CACHE_COMPRESSION_THRESHOLD,
+        }
+    }
+}
+
+/// Metrics collection configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    /// Whether metrics are collected
+    pub enabled: bool,
+
+    /// Prefix of exported metric names
+    pub prefix: String,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            prefix: "v402".to_string(),
+        }
+    }
+}
+
+/// Retry behaviour for rate-limited and temporarily unavailable responses.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryConfig {
+    /// Maximum number of retries after a 429 or 503 response
+    pub max_rate_limit_retries: u32,
+
+    /// Upper bound on the delay honoured from a `Retry-After` header
+    pub max_retry_after: Duration,
+
+    /// Delay used for a 429 response without a `Retry-After` header
+    pub default_retry_after: Duration,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_rate_limit_retries: 3,
+            max_retry_after: Duration::from_secs(30),
+            default_retry_after: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Time used for the validity window of payment authorizations.
+///
+/// Facilitators reject authorizations whose `validAfter` is still in the
+/// future or whose `validBefore` has passed by their clock, so a machine
+/// whose clock is off signs payments that fail. The window is padded by
+/// [`tolerance`](Self::tolerance) on both sides, and the clock can be
+/// corrected by a fixed offset or calibrated against the `Date` header of
+/// the server's 402 responses.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClockConfig {
+    /// Seconds added to the local clock, e.g. `-300` for a clock five minutes fast
+    pub offset_secs: i64,
+
+    /// Padding moving `validAfter` back and `validBefore` forward
+    pub tolerance: Duration,
+
+    /// Replace the offset with the difference to the server's `Date` header on every 402
+    pub calibrate_from_server: bool,
+}
+
+impl Default for ClockConfig {
+    fn default() -> Self {
+        Self {
+            offset_secs: 0,
+            tolerance: Duration::from_secs(60),
+            calibrate_from_server: false,
+        }
+    }
+}
+
+/// ERC-20 allowance granted when the client is created, see [`Config::preapprove_tokens`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PreapproveConfig {
+    /// Name of the chain the token is on
+    pub chain: String,
+
+    /// Address of the token contract
+    pub token: String,
+
+    /// Address allowed to spend the default wallet's tokens
+    pub spender: String,
+
+    /// Allowance to grant, in the token's smallest unit
+    pub amount: u128,
+}
+
+/// Immutable client configuration.
+///
+/// Secrets are never included in `Debug` output or in the default
+/// serialization; use [`serialize_with_secrets`](Self::serialize_with_secrets)
+/// to persist a complete configuration to trusted storage.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Hex-encoded private key used to sign payments, zeroized on drop
+    #[serde(skip_serializing)]
+    pub private_key: Option<SecretString>,
+
+    /// Path of the private key in the [`secret_store`](Self::secret_store),
+    /// fetched into [`private_key`](Self::private_key) by [`Client::new`](crate::Client::new)
+    pub private_key_secret_path: Option<String>,
+
+    /// Store the private key is fetched from
+    #[serde(skip)]
+    pub secret_store: Option<SecretStore>,
+
+    /// Additional labelled wallets; the private key above is the wallet labelled [`DEFAULT_WALLET`]
+    pub wallets: Vec<WalletConfig>,
+
+    /// Routes choosing the paying wallet by URL, first match wins
+    pub wallet_routes: Vec<WalletRoute>,
+
+    /// Wallet paying for URLs no route matches, defaulting to [`DEFAULT_WALLET`]
+    pub default_wallet: Option<String>,
+
+    /// Configured blockchain networks
+    pub chains: Vec<ChainConfig>,
+
+    /// Token allowances of the default wallet approved when the client is
+    /// created, unless the current allowance already covers them or the chain
+    /// is dry-run; failed approvals are logged without failing the client
+    pub preapprove_tokens: Vec<PreapproveConfig>,
+
+    /// Chain names in order of preference when a server offers several networks
+    pub chain_priority: Vec<String>,
+
+    /// Automatically pay for 402 responses
+    pub auto_pay: bool,
+
+    /// How 402 responses are handled; anything but [`PaymentRequiredBehavior::Pay`] never pays
+    pub on_payment_required: PaymentRequiredBehavior,
+
+    /// Maximum amount to pay per request, in the asset's smallest unit
+    pub max_amount_per_request: Option<String>,
+
+    /// Maximum amount to pay per request in US dollars, taking priority over
+    /// [`max_amount_per_request`](Self::max_amount_per_request)
+    pub max_amount_usd: Option<f64>,
+
+    /// CoinGecko-compatible token price API used to convert
+    /// [`max_amount_usd`](Self::max_amount_usd), defaulting to [`DEFAULT_PRICE_ORACLE_URL`]
+    pub price_oracle_url: Option<String>,
+
+    /// Seconds a token price is cached
+    pub price_ttl_secs: u64,
+
+    /// Read payment nonces from the chain's payment contract instead of generating them randomly
+    pub on_chain_nonce: bool,
+
+    /// File persisting the counters of deterministically derived payment nonces, see
+    /// [`payment::nonce`](crate::payment::nonce); unset, nonces are random
+    pub nonce_counter_path: Option<PathBuf>,
+
+    /// Reuse payment requirements from an earlier 402 to pay on the first attempt
+    pub preemptive_payment: bool,
+
+    /// Response headers read for [payment hints](crate::payment::hints); empty disables them
+    pub payment_hint_headers: Vec<String>,
+
+    /// Interval at which settlement transactions are checked for a receipt
+    pub pending_poll_interval: Duration,
+
+    /// Age after which a payment without a receipt is reported as stuck
+    pub stuck_payment_threshold: Duration,
+
+    /// Ask the facilitator every [`pending_poll_interval`](Self::pending_poll_interval)
+    /// for the settlement of payments whose response carried none
+    pub poll_settlement: bool,
+
+    /// Age after which [`poll_settlement`](Self::poll_settlement) stops asking about a payment
+    pub settlement_poll_timeout: Duration,
+
+    /// Wait for every chain's RPC endpoint to answer when the client is
+    /// created, retrying every second, instead of starting without checking
+    pub connection_retry_on_start: bool,
+
+    /// Longest time [`connection_retry_on_start`](Self::connection_retry_on_start) waits
+    pub connection_retry_max_wait: Duration,
+
+    /// Read `symbol()` and `decimals()` from the contract of tokens missing from the registry
+    pub resolve_tokens_on_chain: bool,
+
+    /// Facilitator used for chains without their own, defaulting to [`DEFAULT_FACILITATOR_URL`]
+    pub facilitator_url: Option<String>,
+
+    /// Accept `http://` facilitator URLs (for local testing only)
+    pub allow_insecure_facilitator: bool,
+
+    /// Have the payment's facilitator verify each signed payment before it
+    /// is sent, so payments it would refuse to settle fail early
+    pub verify_payments: bool,
+
+    /// Request timeout
+    pub timeout: Duration,
+
+    /// Hosts resolved to a fixed address instead of through DNS; the port
+    /// of the address is ignored in favour of the URL's
+    pub dns_overrides: HashMap<String, SocketAddr>,
+
+    /// Resolver used instead of the system's for hosts without an override
+    #[serde(skip)]
+    pub dns_resolver: Option<DnsResolver>,
+
+    /// Header used to send the per-request correlation ID
+    pub request_id_header: String,
+
+    /// Entries kept by the audit log, see [`Client::audit_log`](crate::Client::audit_log);
+    /// the oldest are dropped beyond it
+    pub audit_log_capacity: usize,
+
+    /// Whether cache and metrics failures fail the client or leave it running without them
+    pub degradation_policy: DegradationPolicy,
+
+    /// Maximum number of concurrent connections
+    pub max_connections: usize,
+
+    /// Maximum number of requests in flight at once; further requests queue by priority
+    pub max_concurrent_requests: usize,
+
+    /// Time after which a queued request is served as if it were high priority
+    pub priority_aging: Option<Duration>,
+
+    /// Response cache settings
+    pub cache: CacheConfig,
+
+    /// Metrics settings
+    pub metrics: MetricsConfig,
+
+    /// Rate-limit retry settings
+    pub retry: RetryConfig,
+
+    /// Clock used for payment validity windows
+    pub clock: ClockConfig,
+
+    /// Per-host spend alerts
+    pub spend_alerts: SpendAlertConfig,
+
+    /// Detection of sudden price increases
+    pub price_jumps: PriceJumpConfig,
+
+    /// Hosts requests and redirects may go to; empty allows every host not denied,
+    /// see [`ConfigBuilder::allowed_hosts`]
+    pub allowed_hosts: Vec<String>,
+
+    /// Hosts requests and redirects never go to, taking precedence over
+    /// [`allowed_hosts`](Self::allowed_hosts)
+    pub denied_hosts: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            private_key: None,
+            private_key_secret_path: None,
+            secret_store: None,
+            wallets: Vec::new(),
+            wallet_routes: Vec::new(),
+            default_wallet: None,
+            chains: Vec::new(),
+            preapprove_tokens: Vec::new(),
+            chain_priority: Vec::new(),
+            auto_pay: true,
+            on_payment_required: PaymentRequiredBehavior::Pay,
+            max_amount_per_request: None,
+            max_amount_usd: None,
+            price_oracle_url: None,
+            price_ttl_secs: 60,
+            on_chain_nonce: false,
+            nonce_counter_path: None,
+            preemptive_payment: true,
+            payment_hint_headers: DEFAULT_PAYMENT_HINT_HEADERS.iter().map(|name| name.to_string()).collect(),
+            pending_poll_interval: Duration::from_secs(15),
+            stuck_payment_threshold: Duration::from_secs(300),
+            poll_settlement: false,
+            settlement_poll_timeout: Duration::from_secs(3600),
+            connection_retry_on_start: false,
+            connection_retry_max_wait: Duration::from_secs(60),
+            resolve_tokens_on_chain: true,
+            facilitator_url: None,
+            allow_insecure_facilitator: false,
+            verify_payments: false,
+            timeout: Duration::from_secs(30),
+            dns_overrides: HashMap::new(),
+            dns_resolver: None,
+            request_id_header: DEFAULT_REQUEST_ID_HEADER.to_string(),
+            audit_log_capacity: crate::audit::DEFAULT_AUDIT_LOG_CAPACITY,
+            degradation_policy: DegradationPolicy::default(),
+            max_connections: 100,
+            max_concurrent_requests: 100,
+            priority_aging: Some(Duration::from_secs(10)),
+            cache: CacheConfig::default(),
+            metrics: MetricsConfig::default(),
+            retry: RetryConfig::default(),
+            clock: ClockConfig::default(),
+            spend_alerts: SpendAlertConfig::default(),
+            price_jumps: PriceJumpConfig::default(),
+            allowed_hosts: Vec::new(),
+            denied_hosts: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Creates a new configuration builder.
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::new()
+    }
+
+    /// Returns the configuration of the chain with the given name.
+    ///
+    /// Names are compared as [`NetworkId`]s, so aliases such as `eth` or
+    /// `base-mainnet` find the chain configured as `ethereum` or `base`.
+    pub fn chain(&self, name: &str) -> Option<&ChainConfig> {
+        let network = NetworkId::from(name);
+        self.chains
+            .iter()
+            .find(|chain| chain.name == name || network == chain.name.as_str())
+    }
+
+    /// Returns the label of the wallet paying for `url`.
+    ///
+    /// The first matching [`WalletRoute`] decides, then
+    /// [`default_wallet`](Self::default_wallet), then the wallet of
+    /// [`private_key`](Self::private_key) if one is set.
+    pub fn wallet_for(&self, url: &str) -> Option<&str> {
+        let routed = url::Url::parse(url)
+            .ok()
+            .and_then(|url| self.wallet_routes.iter().find(|route| route.matches(&url)));
+        match (routed, &self.default_wallet) {
+            (Some(route), _) => Some(&route.wallet),
+            (None, Some(wallet)) => Some(wallet),
+            (None, None) => self.has_private_key().then_some(DEFAULT_WALLET),
+        }
+    }
+
+    /// Returns the configuration of the wallet with the given label.
+    pub fn wallet(&self, label: &str) -> Option<&WalletConfig> {
+        self.wallets.iter().find(|wallet| wallet.label == label)
+    }
+
+    /// Returns `true` if a wallet with the given label is configured,
+    /// including the [`DEFAULT_WALLET`] of the private key.
+    fn has_wallet(&self, label: &str) -> bool {
+        (label == DEFAULT_WALLET && self.has_private_key()) || self.wallet(label).is_some()
+    }
+
+    /// Returns `true` if the private key is set or will be fetched from the secret store.
+    fn has_private_key(&self) -> bool {
+        self.private_key.is_some() || self.private_key_secret_path.is_some()
+    }
+
+    /// Fetches the private key from the secret store, if its path is set.
+    pub(crate) async fn load_secrets(&mut self) -> Result<()> {
+        let (Some(path), Some(store)) = (&self.private_key_secret_path, &self.secret_store) else {
+            return Ok(());
+        };
+
+        let mut secret = store.get_secret(path).await?;
+        // Move the key out so the only copy is the zeroized-on-drop config field
+        let key = SecretString::new(std::mem::take(&mut *secret));
+        if let Some(issue) = private_key_issue("private_key_secret_path", &key) {
+            return Err(Error::InvalidConfig(vec![issue]));
+        }
+        self.private_key = Some(key);
+        Ok(())
+    }
+
+    /// Sort key ranking `network` among the chains offered by a server; lower keys are preferred.
+    ///
+    /// Chains are ordered by their position in [`chain_priority`](Self::chain_priority),
+    /// then by [`ChainConfig::priority`], highest first. Ties keep the server's order.
+    pub fn chain_rank(&self, network: &str) -> (usize, std::cmp::Reverse<Option<u8>>) {
+        let id = NetworkId::from(network);
+        let position = self
+            .chain_priority
+            .iter()
+            .position(|name| name == network || id == name.as_str())
+            .unwrap_or(usize::MAX);
+        let priority = self.chain(network).and_then(|chain| chain.priority);
+        (position, std::cmp::Reverse(priority))
+    }
+
+    /// Returns the facilitator URL for payments on `network`.
+    ///
+    /// Resolution order: the chain's own facilitator, the global
+    /// [`facilitator_url`](Self::facilitator_url), then [`DEFAULT_FACILITATOR_URL`].
+    pub fn facilitator_for(&self, network: &str) -> &str {
+        self.chain(network)
+            .and_then(|chain| chain.facilitator_url.as_deref())
+            .or(self.facilitator_url.as_deref())
+            .unwrap_or(DEFAULT_FACILITATOR_URL)
+    }
+
+    /// Checks that a facilitator URL is valid and uses HTTPS, unless
+    /// [`allow_insecure_facilitator`](Self::allow_insecure_facilitator) is set.
+    pub fn validate_facilitator_url(&self, url: &str) -> Result<()> {
+        match self.facilitator_url_issue("facilitator_url", url) {
+            Some(issue) => Err(Error::Config(format!("{}; {}", issue.message, issue.hint).into())),
+            None => Ok(()),
+        }
+    }
+
+    /// Serializes the configuration including secrets.
+    ///
+    /// The regular `Serialize` implementation omits private keys so that
+    /// configurations can be logged or exported safely. Only use this for
+    /// trusted persistence, such as an encrypted secrets store.
+    ///
+    /// ```rust
+    /// use v402_client::Config;
+    ///
+    /// let key = "0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+    /// let config = Config::builder().private_key(key).build()?;
+    ///
+    /// assert!(!format!("{:?}", config).contains(&key[2..]));
+    /// assert!(!serde_json::to_string(&config)?.contains(&key[2..]));
+    /// assert_eq!(config.serialize_with_secrets()?["private_key"], key);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn serialize_with_secrets(&self) -> Result<serde_json::Value> {
+        let mut value = serde_json::to_value(self)?;
+        if let (serde_json::Value::Object(map), Some(key)) = (&mut value, &self.private_key) {
+            map.insert(
+                "private_key".to_string(),
+                serde_json::Value::String(key.expose_secret().clone()),
+            );
+        }
+        let cache_secrets = [
+            ("encryption_key", &self.cache.encryption_key),
+            ("encryption_passphrase", &self.cache.encryption_passphrase),
+        ];
+        if let Some(serde_json::Value::Object(cache)) = value.get_mut("cache") {
+            for (field, secret) in cache_secrets {
+                if let Some(secret) = secret {
+                    cache.insert(field.to_string(), serde_json::Value::String(secret.expose_secret().clone()));
+                }
+            }
+        }
+        if let Some(serde_json::Value::Array(wallets)) = value.get_mut("wallets") {
+            for (wallet, config) in wallets.iter_mut().zip(&self.wallets) {
+                if let serde_json::Value::Object(map) = wallet {
+                    map.insert(
+                        "private_key".to_string(),
+                        serde_json::Value::String(config.private_key.expose_secret().clone()),
+                    );
+                }
+            }
+        }
+        Ok(value)
+    }
+
+    /// Checks the whole configuration, reporting every problem found.
+    ///
+    /// [`ConfigBuilder::build`] runs this automatically; call it directly to
+    /// lint a configuration file without constructing a client.
+    ///
+    /// ```rust
+    /// use v402_client::{Config, Error};
+    ///
+    /// let config: Config = serde_json::from_str(r#"{"timeout": {"secs": 0, "nanos": 0}}"#)?;
+    /// if let Err(Error::InvalidConfig(issues)) = config.validate() {
+    ///     for issue in issues {
+    ///         eprintln!("{}", issue);
+    ///     }
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn validate(&self) -> Result<()> {
+        let issues = self.issues();
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::InvalidConfig(issues))
+        }
+    }
+
+    /// Validates the configuration and checks that every facilitator is reachable.
+    ///
+    /// Runs [`validate`](Self::validate) first, then requests
+    /// `{facilitator_url}/health` from the global facilitator and each chain's
+    /// own, reporting unreachable ones as [`ConfigIssue`]s. Use this at
+    /// startup to catch a mistyped private facilitator before the first payment.
+    ///
+    /// ```rust,no_run
+    /// use v402_client::Config;
+    ///
+    /// # async fn run() -> v402_client::Result<()> {
+    /// let config = Config::builder()
+    ///     .facilitator_url("https://facilitator.internal.example.com")
+    ///     .build()?;
+    /// config.validate_deep().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn validate_deep(&self) -> Result<()> {
+        self.validate()?;
+
+        let http = http::with_dns(reqwest::Client::builder(), self)
+            .timeout(self.timeout.min(FACILITATOR_HEALTH_TIMEOUT))
+            .user_agent(crate::USER_AGENT)
+            .build()
+            .map_err(|e| Error::Network(format!("Failed to build HTTP client: {}", e).into()))?;
+
+        // Each distinct facilitator is checked once, reported under the first field using it
+        let mut facilitators = vec![(
+            "facilitator_url".to_string(),
+            self.facilitator_url.as_deref().unwrap_or(DEFAULT_FACILITATOR_URL),
+        )];
+        for (index, chain) in self.chains.iter().enumerate() {
+            if let Some(url) = chain.facilitator_url.as_deref() {
+                if facilitators.iter().all(|(_, seen)| *seen != url) {
+                    facilitators.push((format!("chains[{}].facilitator_url", index), url));
+                }
+            }
+        }
+
+        let checks = facilitators.into_iter().map(|(field, url)| {
+            let http = &http;
+            async move {
+                let health_url = format!("{}/health", url.trim_end_matches('/'));
+                let problem = match http.get(&health_url).send().await {
+                    Ok(response) if response.status().is_success() => return None,
+                    Ok(response) => format!("returned {}", response.status()),
+                    Err(e) => dns_error(&e).map_or_else(|| e.to_string(), |dns| dns.to_string()),
+                };
+                Some(
+                    ConfigIssue::new(field, format!("Facilitator {} is unreachable: {}", health_url, problem))
+                        .value(url)
+                        .hint("Check the URL and that the facilitator is running"),
+                )
+            }
+        });
+        let issues: Vec<_> = futures::future::join_all(checks).await.into_iter().flatten().collect();
+
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::InvalidConfig(issues))
+        }
+    }
+
+    /// Collects every validation problem, in field order.
+    fn issues(&self) -> Vec<ConfigIssue> {
+        let mut issues = Vec::new();
+
+        if let Some(key) = &self.private_key {
+            issues.extend(private_key_issue("private_key", key));
+        }
+
+        match (&self.private_key_secret_path, &self.secret_store) {
+            (Some(_), None) => issues.push(
+                ConfigIssue::new("private_key_secret_path", "Private key secret path is set without a secret store")
+                    .hint("Set a store with ConfigBuilder::secret_store"),
+            ),
+            (Some(_), Some(_)) if self.private_key.is_some() => issues.push(
+                ConfigIssue::new("private_key_secret_path", "Private key is set both directly and as a secret")
+                    .hint("Remove private_key to load the key from the secret store"),
+            ),
+            _ => {}
+        }
+
+        let mut labels = HashSet::new();
+        if self.has_private_key() {
+            labels.insert(DEFAULT_WALLET);
+        }
+        for (index, wallet) in self.wallets.iter().enumerate() {
+            let field = format!("wallets[{}]", index);
+
+            if !labels.insert(wallet.label.as_str()) {
+                issues.push(
+                    ConfigIssue::new(format!("{}.label", field), "Wallet label is used more than once")
+                        .value(&wallet.label)
+                        .hint(format!(
+                            "Give each wallet a unique label; {:?} is taken by private_key if set",
+                            DEFAULT_WALLET
+                        )),
+                );
+            }
+            issues.extend(private_key_issue(&format!("{}.private_key", field), &wallet.private_key));
+            if let Some(amount) = &wallet.max_amount_per_request {
+                if amount.parse::<u128>().is_err() {
+                    issues.push(
+                        ConfigIssue::new(format!("{}.max_amount_per_request", field), "Amount must be an integer")
+                            .value(amount)
+                            .hint("Give the amount in the asset's smallest unit, e.g. 1000000 for 1 USDC"),
+                    );
+                }
+            }
+        }
+
+        for (index, preapprove) in self.preapprove_tokens.iter().enumerate() {
+            let field = format!("preapprove_tokens[{}]", index);
+
+            match self.chain(&preapprove.chain) {
+                Some(chain) if !chain.chain_type.is_evm() => issues.push(
+                    ConfigIssue::new(format!("{}.chain", field), "Token approvals are only supported on EVM chains")
+                        .value(&preapprove.chain),
+                ),
+                Some(_) => {}
+                None => issues.push(
+                    ConfigIssue::new(format!("{}.chain", field), "Chain is not configured")
+                        .value(&preapprove.chain)
+                        .hint("Add the chain with ConfigBuilder::chains"),
+                ),
+            }
+            for (name, address) in [("token", &preapprove.token), ("spender", &preapprove.spender)] {
+                if crypto::parse_address(address).is_err() {
+                    issues.push(
+                        ConfigIssue::new(format!("{}.{}", field, name), "Not a 20-byte hex address")
+                            .value(address),
+                    );
+                }
+            }
+            if !self.has_private_key() {
+                issues.push(
+                    ConfigIssue::new(field, "Token approvals need a private key")
+                        .hint("Set private_key to sign the approval transactions"),
+                );
+            }
+        }
+
+        for (index, route) in self.wallet_routes.iter().enumerate() {
+            if !self.has_wallet(&route.wallet) {
+                issues.push(
+                    ConfigIssue::new(format!("wallet_routes[{}].wallet", index), "Wallet is not configured")
+                        .value(&route.wallet)
+                        .hint("Add the wallet with add_wallet or route to an existing label"),
+                );
+            }
+        }
+
+        if let Some(wallet) = &self.default_wallet {
+            if !self.has_wallet(wallet) {
+                issues.push(
+                    ConfigIssue::new("default_wallet", "Wallet is not configured")
+                        .value(wallet)
+                        .hint("Add the wallet with add_wallet or use an existing label"),
+                );
+            }
+        }
+
+        let mut seen = HashSet::new();
+        for (index, chain) in self.chains.iter().enumerate() {
+            let field = format!("chains[{}]", index);
+
+            if !seen.insert(chain.name.as_str()) {
+                issues.push(
+                    ConfigIssue::new(format!("{}.name", field), "Chain is configured more than once")
+                        .value(&chain.name)
+                        .hint("Remove the duplicate or give each chain a unique name"),
+                );
+            }
+
+            if self.on_chain_nonce && chain.chain_type.is_evm() && chain.payment_contract.is_none() {
+                issues.push(
+                    ConfigIssue::new(
+                        format!("{}.payment_contract", field),
+                        "on_chain_nonce requires a payment contract",
+                    )
+                    .value(&chain.name)
+                    .hint("Set the chain's payment_contract or disable on_chain_nonce"),
+                );
+            }
+
+            if let Some(url) = &chain.facilitator_url {
+                issues.extend(self.facilitator_url_issue(&format!("{}.facilitator_url", field), url));
+            }
+
+            if let Some(health) = &chain.health {
+                if health.enabled && health.check_interval.is_zero() {
+                    issues.push(
+                        ConfigIssue::new(
+                            format!("{}.health.check_interval", field),
+                            "Health check interval is zero",
+                        )
+                        .value(format!("{:?}", health.check_interval))
+                        .hint("Use a positive interval or disable monitoring with ChainHealthConfig::disabled()"),
+                    );
+                }
+                if health.enabled && health.failure_threshold == 0 {
+                    issues.push(
+                        ConfigIssue::new(
+                            format!("{}.health.failure_threshold", field),
+                            "Failure threshold is zero",
+                        )
+                        .value("0")
+                        .hint("Quarantine needs at least one failed check"),
+                    );
+                }
+                if !(0.0..=1.0).contains(&health.max_error_rate) {
+                    issues.push(
+                        ConfigIssue::new(format!("{}.health.max_error_rate", field), "Error rate is out of range")
+                            .value(health.max_error_rate.to_string())
+                            .hint("Use a fraction between 0 and 1"),
+                    );
+                }
+            }
+        }
+
+        for (index, name) in self.chain_priority.iter().enumerate() {
+            if self.chain(name).is_none() {
+                issues.push(
+                    ConfigIssue::new(format!("chain_priority[{}]", index), "Chain is not configured")
+                        .value(name)
+                        .hint("Add the chain with add_chain or remove it from chain_priority"),
+                );
+            }
+        }
+
+        if let Some(amount) = &self.max_amount_per_request {
+            match amount.parse::<u128>() {
+                Err(_) => issues.push(
+                    ConfigIssue::new("max_amount_per_request", "Amount must be an integer")
+                        .value(amount)
+                        .hint("Give the amount in the asset's smallest unit, e.g. 1000000 for 1 USDC"),
+                ),
+                Ok(value) if value > MAX_PAYMENT_AMOUNT.parse::<u128>().unwrap_or(u128::MAX) => issues.push(
+                    ConfigIssue::new("max_amount_per_request", "Amount exceeds the client's hard limit")
+                        .value(amount)
+                        .hint(format!("Must not exceed MAX_PAYMENT_AMOUNT ({})", MAX_PAYMENT_AMOUNT)),
+                ),
+                Ok(_) => {}
+            }
+        }
+
+        if let Some(usd) = self.max_amount_usd {
+            if !usd.is_finite() || usd <= 0.0 {
+                issues.push(
+                    ConfigIssue::new("max_amount_usd", "USD cap must be positive")
+                        .value(usd.to_string())
+                        .hint("Use a positive dollar amount such as 0.50"),
+                );
+            }
+        }
+
+        if let Some(url) = &self.price_oracle_url {
+            if let Err(e) = url::Url::parse(url) {
+                issues.push(
+                    ConfigIssue::new("price_oracle_url", format!("Invalid URL: {}", e))
+                        .value(url)
+                        .hint("Use an absolute URL of a CoinGecko-compatible token price API"),
+                );
+            }
+        }
+
+        if let Some(url) = &self.facilitator_url {
+            issues.extend(self.facilitator_url_issue("facilitator_url", url));
+        }
+
+        if self.timeout.is_zero() {
+            issues.push(
+                ConfigIssue::new("timeout", "Timeout must be greater than zero")
+                    .value(format!("{:?}", self.timeout))
+                    .hint("Use a timeout such as 30 seconds"),
+            );
+        }
+
+        if reqwest::header::HeaderName::from_bytes(self.request_id_header.as_bytes()).is_err() {
+            issues.push(
+                ConfigIssue::new("request_id_header", "Invalid header name")
+                    .value(&self.request_id_header)
+                    .hint("Use ASCII letters, digits and dashes, e.g. X-Request-ID"),
+            );
+        }
+
+        if self.max_concurrent_requests == 0 {
+            issues.push(
+                ConfigIssue::new("max_concurrent_requests", "Concurrency limit must be greater than zero")
+                    .value("0")
+                    .hint("Use at least 1; requests beyond the limit queue by priority"),
+            );
+        }
+
+        if self.pending_poll_interval.is_zero() {
+            issues.push(
+                ConfigIssue::new("pending_poll_interval", "Poll interval must be non-zero")
+                    .value(format!("{:?}", self.pending_poll_interval))
+                    .hint("Use an interval such as 15 seconds"),
+            );
+        }
+
+        if self.connection_retry_on_start && self.connection_retry_max_wait.is_zero() {
+            issues.push(
+                ConfigIssue::new("connection_retry_max_wait", "Retry wait must be non-zero when retrying on start")
+                    .value(format!("{:?}", self.connection_retry_max_wait))
+                    .hint("Use a wait such as 60 seconds, or disable connection_retry_on_start"),
+            );
+        }
+
+        if self.cache.enabled && self.cache.ttl.is_zero() {
+            issues.push(
+                ConfigIssue::new(
+                    "cache.ttl",
+                    "Cache TTL is zero, so nothing would ever be served from the cache",
+                )
+                .value(format!("{:?}", self.cache.ttl))
+                .hint("Use a positive TTL or set cache.enabled to false"),
+            );
+        }
+
+        let thresholds = self
+            .spend_alerts
+            .threshold
+            .iter()
+            .map(|threshold| ("spend_alerts.threshold".to_string(), threshold))
+            .chain(self.spend_alerts.host_thresholds.iter().enumerate().map(|(index, host)| {
+                (format!("spend_alerts.host_thresholds[{}].threshold", index), &host.threshold)
+            }));
+        for (field, threshold) in thresholds {
+            if threshold.parse::<u128>().is_err() {
+                issues.push(
+                    ConfigIssue::new(field, "Amount must be an integer")
+                        .value(threshold)
+                        .hint("Give the amount in the asset's smallest unit, e.g. 1000000 for 1 USDC"),
+                );
+            }
+        }
+
+        if self.spend_alerts.window.is_zero() {
+            issues.push(
+                ConfigIssue::new("spend_alerts.window", "Spend alert window must be non-zero")
+                    .value(format!("{:?}", self.spend_alerts.window))
+                    .hint("Use a window such as one hour"),
+            );
+        }
+
+        if let Some(multiplier) = self.price_jumps.multiplier {
+            if !(multiplier.is_finite() && multiplier > 1.0) {
+                issues.push(
+                    ConfigIssue::new("price_jumps.multiplier", "Price jump multiplier must be greater than 1")
+                        .value(multiplier.to_string())
+                        .hint("Use a ratio such as 5.0 to flag prices five times the usual"),
+                );
+            }
+        }
+
+        if self.price_jumps.history_size == 0 {
+            issues.push(
+                ConfigIssue::new("price_jumps.history_size", "Price history must keep at least one price")
+                    .value("0")
+                    .hint("Keep enough prices for the median to ride out spikes, e.g. 16"),
+            );
+        }
+
+        for (field, patterns) in [("allowed_hosts", &self.allowed_hosts), ("denied_hosts", &self.denied_hosts)] {
+            for pattern in patterns {
+                if let Err(e) = crate::hosts::validate_pattern(pattern) {
+                    issues.push(
+                        ConfigIssue::new(field, e)
+                            .value(pattern.as_str())
+                            .hint("Use a host, `.domain`, a glob such as `*.example.com` or a CIDR range"),
+                    );
+                }
+            }
+        }
+
+        if let Some(key) = &self.cache.encryption_key {
+            if let Err(e) = crate::cache::CacheKey::from_hex(key.expose_secret()) {
+                issues.push(
+                    ConfigIssue::new("cache.encryption_key", e.to_string())
+                        .hint("Use 64 hex characters, e.g. the output of `openssl rand -hex 32`"),
+                );
+            }
+            if self.cache.encryption_passphrase.is_some() {
+                issues.push(
+                    ConfigIssue::new("cache.encryption_passphrase", "Both an encryption key and a passphrase are set")
+                        .hint("Set only one; the key takes precedence"),
+                );
+            }
+        }
+
+        let encrypted = self.cache.encryption_key.is_some() || self.cache.encryption_passphrase.is_some();
+        if self.cache.content_dedup && encrypted {
+            issues.push(
+                ConfigIssue::new("cache.content_dedup", "Content deduplication is not available with cache encryption")
+                    .hint("Disable one of cache.content_dedup and cache encryption"),
+            );
+        }
+        if self.cache.skip_payment_for_cached_content && !self.cache.content_dedup {
+            issues.push(
+                ConfigIssue::new(
+                    "cache.skip_payment_for_cached_content",
+                    "Skipping payment for cached content requires content deduplication",
+                )
+                .hint("Set cache.content_dedup to true"),
+            );
+        }
+
+        if self.cache.compression {
+            if !cfg!(feature = "compression") {
+                issues.push(
+                    ConfigIssue::new("cache.compression", "Cache compression requires the `compression` feature")
+                        .hint("Enable the `compression` feature of v402-client"),
+                );
+            }
+            if !(1..=22).contains(&self.cache.compression_level) {
+                issues.push(
+                    ConfigIssue::new("cache.compression_level", "Compression level is out of range")
+                        .value(self.cache.compression_level.to_string())
+                        .hint("Use a zstd level from 1 to 22; 3 is the default"),
+                );
+            }
+        }
+
+        if self.cache.max_entry_size_bytes > self.cache.max_size_bytes {
+            issues.push(
+                ConfigIssue::new(
+                    "cache.max_entry_size_bytes",
+                    "Entry size limit exceeds the total cache size",
+                )
+                .value(self.cache.max_entry_size_bytes.to_string())
+                .hint(format!(
+                    "Must not exceed cache.max_size_bytes ({})",
+                    self.cache.max_size_bytes
+                )),
+            );
+        }
+
+        issues
+    }
+
+    /// Returns the problem with a facilitator URL, if any.
+    fn facilitator_url_issue(&self, field: &str, url: &str) -> Option<ConfigIssue> {
+        let scheme = match url::Url::parse(url) {
+            Ok(parsed) => parsed.scheme().to_string(),
+            Err(e) => {
+                return Some(
+                    ConfigIssue::new(field, format!("Invalid facilitator URL {:?}: {}", url, e))
+                        .value(url)
+                        .hint("Use an absolute URL such as https://facilitator.example.com"),
+                )
+            }
+        };
+
+        match scheme.as_str() {
+            "https" => None,
+            "http" if self.allow_insecure_facilitator => None,
+            _ => Some(
+                ConfigIssue::new(
+                    field,
+                    format!("Facilitator URL {} must use https (got {})", url, scheme),
+                )
+                .value(url)
+                .hint("set allow_insecure_facilitator to permit it"),
+            ),
+        }
+    }
+}
+
+/// Reports a private key that is not 32 bytes of hex, without revealing it.
+fn private_key_issue(field: &str, key: &SecretString) -> Option<ConfigIssue> {
+    let key = key.expose_secret();
+    let hex_key = key.strip_prefix("0x").unwrap_or(key);
+    if hex_key.len() == 64 && hex_key.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some(
+        ConfigIssue::new(field, "Private key must be 32 bytes of hex")
+            .value(format!("<redacted, {} characters>", key.len()))
+            .hint("Expected 64 hex characters, optionally prefixed with 0x"),
+    )
+}
+
+/// A single problem found while validating a [`Config`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfigIssue {
+    /// Path of the offending field, e.g. `chains[1].facilitator_url`
+    pub field: String,
+
+    /// The value provided, with secrets redacted
+    pub value: Option<String>,
+
+    /// What is wrong
+    pub message: String,
+
+    /// How to fix it
+    pub hint: String,
+}
+
+impl ConfigIssue {
+    fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            value: None,
+            message: message.into(),
+            hint: String::new(),
+        }
+    }
+
+    fn value(mut self, value: impl Into<String>) -> Self {
+        self.value = Some(value.into());
+        self
+    }
+
+    fn hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = hint.into();
+        self
+    }
+}
+
+impl std::fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)?;
+        if let Some(value) = &self.value {
+            write!(f, " (got {})", value)?;
+        }
+        if !self.hint.is_empty() {
+            write!(f, "; {}", self.hint)?;
+        }
+        Ok(())
+    }
+}
+
+/// Builder for [`Config`].
+#[derive(Debug, Clone, Default)]
+pub struct ConfigBuilder {
+    config: Config,
+    /// Chains switched to dry-run mode once all chains are known
+    dry_run_chains: Vec<String>,
+}
+
+impl ConfigBuilder {
+    /// Creates a builder with default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the private key used to sign payments.
+    pub fn private_key<S: Into<String>>(mut self, key: S) -> Self {
+        self.config.private_key = Some(SecretString::new(key.into()));
+        self
+    }
+
+    /// Sets the store the private key is fetched from, see [`secrets`](crate::secrets).
+    pub fn secret_store(mut self, provider: impl SecretProvider + 'static) -> Self {
+        self.config.secret_store = Some(SecretStore::new(Arc::new(provider)));
+        self
+    }
+
+    /// Fetches the private key from `path` in the [secret store](Self::secret_store)
+    /// when the client is created, instead of setting it with [`private_key`](Self::private_key).
+    pub fn private_key_secret_path<S: Into<String>>(mut self, path: S) -> Self {
+        self.config.private_key_secret_path = Some(path.into());
+        self
+    }
+
+    /// Enables or disables automatic payment.
+    pub fn auto_pay(mut self, enabled: bool) -> Self {
+        self.config.auto_pay = enabled;
+        self
+    }
+
+    /// Sets how 402 responses are handled.
+    ///
+    /// ```rust
+    /// use v402_client::{Config, PaymentRequiredBehavior};
+    ///
+    /// // A crawler that records prices but never pays
+    /// let config = Config::builder()
+    ///     .on_payment_required(PaymentRequiredBehavior::Error)
+    ///     .build()?;
+    /// # Ok::<(), v402_client::Error>(())
+    /// ```
+    pub fn on_payment_required(mut self, behavior: PaymentRequiredBehavior) -> Self {
+        self.config.on_payment_required = behavior;
+        self
+    }
+
+    /// Sets the maximum amount to pay per request.
+    pub fn max_amount_per_request<S: Into<String>>(mut self, amount: S) -> Self {
+        self.config.max_amount_per_request = Some(amount.into());
+        self
+    }
+
+    /// Sets the maximum amount to pay per request in US dollars.
+    ///
+    /// Converted to token units with the price oracle before each payment.
+    /// If the oracle is unavailable, [`max_amount_per_request`](Self::max_amount_per_request)
+    /// is used instead when set; otherwise the payment fails.
+    pub fn max_amount_usd(mut self, amount: f64) -> Self {
+        self.config.max_amount_usd = Some(amount);
+        self
+    }
+
+    /// Sets the CoinGecko-compatible token price API.
+    pub fn price_oracle_url<S: Into<String>>(mut self, url: S) -> Self {
+        self.config.price_oracle_url = Some(url.into());
+        self
+    }
+
+    /// Sets how long token prices are cached.
+    pub fn price_ttl(mut self, ttl: Duration) -> Self {
+        self.config.price_ttl_secs = ttl.as_secs();
+        self
+    }
+
+    /// Sets how often settlement transactions are checked for a receipt.
+    pub fn pending_poll_interval(mut self, interval: Duration) -> Self {
+        self.config.pending_poll_interval = interval;
+        self
+    }
+
+    /// Sets the age after which an unconfirmed payment is reported as stuck.
+    pub fn stuck_payment_threshold(mut self, threshold: Duration) -> Self {
+        self.config.stuck_payment_threshold = threshold;
+        self
+    }
+
+    /// Enables polling the facilitator in the background for the settlement
+    /// of payments made without one in their response, see
+    /// [`Client::poll_settlement`](crate::Client::poll_settlement).
+    pub fn poll_settlement(mut self, enabled: bool) -> Self {
+        self.config.poll_settlement = enabled;
+        self
+    }
+
+    /// Sets the age after which background polling gives up on a payment's settlement.
+    pub fn settlement_poll_timeout(mut self, timeout: Duration) -> Self {
+        self.config.settlement_poll_timeout = timeout;
+        self
+    }
+
+    /// Makes [`Client::new`](crate::Client::new) wait for every chain's RPC
+    /// endpoint to answer, retrying every second for up to `max_wait`, e.g.
+    /// for an RPC sidecar that starts after the application.
+    ///
+    /// Attempts are cut off at `max_wait`. If the endpoints still fail, the
+    /// error of the first attempt is returned, or [`Error::Timeout`] if no
+    /// attempt finished in time.
+    pub fn connection_retry_on_start(mut self, max_wait: Duration) -> Self {
+        self.config.connection_retry_on_start = true;
+        self.config.connection_retry_max_wait = max_wait;
+        self
+    }
+
+    /// Enables reading the symbol and decimals of unknown tokens from their contract.
+    pub fn resolve_tokens_on_chain(mut self, enabled: bool) -> Self {
+        self.config.resolve_tokens_on_chain = enabled;
+        self
+    }
+
+    /// Enables reading payment nonces from the payment contract.
+    ///
+    /// Each chain used for payments must have a
+    /// [`payment_contract`](ChainConfig::payment_contract) configured.
+    pub fn on_chain_nonce(mut self, enabled: bool) -> Self {
+        self.config.on_chain_nonce = enabled;
+        self
+    }
+
+    /// Derives payment nonces from the wallet key, the requirements and a
+    /// counter persisted to `path`, so payments interrupted by a crash can be
+    /// reconstructed; see [`payment::nonce`](crate::payment::nonce).
+    ///
+    /// Leave unset for facilitators that supply their own nonces.
+    pub fn deterministic_nonces<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.config.nonce_counter_path = Some(path.into());
+        self
+    }
+
+    /// Enables or disables paying up front with cached payment requirements.
+    ///
+    /// Disable for servers with dynamic pricing, where cached requirements
+    /// would often be rejected.
+    pub fn preemptive_payment(mut self, enabled: bool) -> Self {
+        self.config.preemptive_payment = enabled;
+        self
+    }
+
+    /// Sets the response headers read for [payment hints](crate::payment::hints),
+    /// replacing the defaults; pass none to ignore hints.
+    pub fn payment_hint_headers(mut self, names: &[&str]) -> Self {
+        self.config.payment_hint_headers = names.iter().map(|name| name.to_string()).collect();
+        self
+    }
+
+    /// Sets the facilitator used for chains without their own, such as a
+    /// private deployment. The URL is validated by [`build`](Self::build).
+    pub fn facilitator_url<S: Into<String>>(mut self, url: S) -> Self {
+        self.config.facilitator_url = Some(url.into());
+        self
+    }
+
+    /// Allows `http://` facilitator URLs. Intended for local testing only.
+    pub fn allow_insecure_facilitator(mut self, allow: bool) -> Self {
+        self.config.allow_insecure_facilitator = allow;
+        self
+    }
+
+    /// Has the payment's facilitator verify each signed payment with
+    /// `POST {facilitator}/verify` before it is sent.
+    pub fn verify_payments(mut self, enabled: bool) -> Self {
+        self.config.verify_payments = enabled;
+        self
+    }
+
+    /// Resolves `host` to `addr` for every request made by the client,
+    /// e.g. to pin a canary instance or reach hosts missing from DNS.
+    ///
+    /// As DNS has no notion of ports, the port of `addr` is ignored; put a
+    /// non-default port in the request URL instead.
+    ///
+    /// ```rust
+    /// use v402_client::Config;
+    ///
+    /// let config = Config::builder()
+    ///     .resolve("api.staging.example.com", "10.0.4.17:443".parse()?)
+    ///     .build()?;
+    /// assert_eq!(config.dns_overrides.len(), 1);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn resolve<H: Into<String>>(mut self, host: H, addr: SocketAddr) -> Self {
+        self.config.dns_overrides.insert(host.into(), addr);
+        self
+    }
+
+    /// Resolves hosts without a [static override](Self::resolve) through
+    /// `resolver` instead of the system resolver.
+    pub fn dns_resolver(mut self, resolver: Arc<dyn Resolve>) -> Self {
+        self.config.dns_resolver = Some(DnsResolver::new(resolver));
+        self
+    }
+
+    /// Sets the request timeout.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.config.timeout = timeout;
+        self
+    }
+
+    /// Sets the header used to send the per-request correlation ID.
+    pub fn request_id_header<S: Into<String>>(mut self, name: S) -> Self {
+        self.config.request_id_header = name.into();
+        self
+    }
+
+    /// Sets the number of entries kept by the audit log.
+    pub fn audit_log_capacity(mut self, capacity: usize) -> Self {
+        self.config.audit_log_capacity = capacity;
+        self
+    }
+
+    /// Sets whether cache and metrics failures fail the client or leave it running without them.
+    pub fn degradation_policy(mut self, policy: DegradationPolicy) -> Self {
+        self.config.degradation_policy = policy;
+        self
+    }
+
+    /// Sets the maximum number of concurrent connections.
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.config.max_connections = max;
+        self
+    }
+
+    /// Sets the maximum number of requests in flight at once.
+    pub fn max_concurrent_requests(mut self, max: usize) -> Self {
+        self.config.max_concurrent_requests = max;
+        self
+    }
+
+    /// Sets how long a queued request waits before being served ahead of
+    /// higher priorities; `None` disables aging.
+    pub fn priority_aging(mut self, aging: Option<Duration>) -> Self {
+        self.config.priority_aging = aging;
+        self
+    }
+
+    /// Adds a labelled wallet to pay from.
+    pub fn wallet<L: Into<String>, K: Into<String>>(self, label: L, private_key: K) -> Self {
+        self.add_wallet(WalletConfig::new(label, private_key))
+    }
+
+    /// Adds a labelled wallet, with its own spending limit if set.
+    pub fn add_wallet(mut self, wallet: WalletConfig) -> Self {
+        self.config.wallets.push(wallet);
+        self
+    }
+
+    /// Pays for URLs matching `pattern` from the wallet labelled `wallet`.
+    ///
+    /// Routes are tried in the order they are added; see [`WalletRoute`]
+    /// for the pattern syntax.
+    ///
+    /// ```rust
+    /// use v402_client::Config;
+    ///
+    /// let config = Config::builder()
+    ///     .wallet("research", "0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318")
+    ///     .wallet("marketing", "0x646f1ce2fdad0e6deeeb5c7e8e5543bdde65e86029e2fd9fc169899c440a7913")
+    ///     .route_wallet("*.papers.example", "research")
+    ///     .route_wallet("https://data.example/ads/", "marketing")
+    ///     .default_wallet("research")
+    ///     .build()?;
+    ///
+    /// assert_eq!(config.wallet_for("https://api.papers.example/doc/1"), Some("research"));
+    /// assert_eq!(config.wallet_for("https://data.example/ads/daily"), Some("marketing"));
+    /// assert_eq!(config.wallet_for("https://other.example/"), Some("research"));
+    /// # Ok::<(), v402_client::Error>(())
+    /// ```
+    pub fn route_wallet<P: Into<String>, W: Into<String>>(mut self, pattern: P, wallet: W) -> Self {
+        self.config.wallet_routes.push(WalletRoute {
+            pattern: pattern.into(),
+            wallet: wallet.into(),
+        });
+        self
+    }
+
+    /// Sets the wallet paying for URLs no route matches.
+    pub fn default_wallet<S: Into<String>>(mut self, wallet: S) -> Self {
+        self.config.default_wallet = Some(wallet.into());
+        self
+    }
+
+    /// Adds a blockchain network.
+    pub fn add_chain(mut self, chain: ChainConfig) -> Self {
+        self.config.chains.push(chain);
+        self
+    }
+
+    /// Sets the order in which chains are preferred when a server offers several.
+    ///
+    /// ```rust
+    /// use v402_client::{ChainConfig, Config};
+    ///
+    /// let config = Config::builder()
+    ///     .add_chain(ChainConfig::base_mainnet())
+    ///     .add_chain(ChainConfig::polygon_mainnet())
+    ///     .chain_priority(["base", "polygon"])
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(config.chain_priority, ["base", "polygon"]);
+    /// ```
+    pub fn chain_priority<I, S>(mut self, chains: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.chain_priority = chains.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Approves `spender` to spend `amount` of `token` from the default
+    /// wallet when the client is created, if its allowance is lower.
+    pub fn preapprove_token<C, T, S>(mut self, chain: C, token: T, spender: S, amount: u128) -> Self
+    where
+        C: Into<String>,
+        T: Into<String>,
+        S: Into<String>,
+    {
+        self.config.preapprove_tokens.push(PreapproveConfig {
+            chain: chain.into(),
+            token: token.into(),
+            spender: spender.into(),
+            amount,
+        });
+        self
+    }
+
+    /// Replaces the configured blockchain networks.
+    pub fn chains(mut self, chains: Vec<ChainConfig>) -> Self {
+        self.config.chains = chains;
+        self
+    }
+
+    /// Sets the response cache configuration.
+    pub fn cache(mut self, cache: CacheConfig) -> Self {
+        self.config.cache = cache;
+        self
+    }
+
+    /// Prefixes every cache key of the client with `"{namespace}:"`.
+    pub fn cache_namespace(mut self, namespace: &str) -> Self {
+        self.config.cache.namespace = Some(namespace.to_string());
+        self
+    }
+
+    /// Encrypts cached responses with a hex-encoded 32-byte AES-256-GCM key.
+    ///
+    /// ```rust
+    /// use v402_client::Config;
+    ///
+    /// let config = Config::builder()
+    ///     .cache_encryption_key("0x000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f")
+    ///     .build()?;
+    /// assert!(!format!("{:?}", config).contains("0f1011"));
+    /// # Ok::<(), v402_client::Error>(())
+    /// ```
+    pub fn cache_encryption_key<S: Into<String>>(mut self, key: S) -> Self {
+        self.config.cache.encryption_key = Some(SecretString::new(key.into()));
+        self
+    }
+
+    /// Encrypts cached responses with a key derived from `passphrase` using
+    /// PBKDF2-HMAC-SHA256.
+    pub fn cache_encryption_passphrase<S: Into<String>>(mut self, passphrase: S) -> Self {
+        self.config.cache.encryption_passphrase = Some(SecretString::new(passphrase.into()));
+        self
+    }
+
+    /// Stores cached bodies of at least
+    /// [`DEFAULT_CACHE_COMPRESSION_THRESHOLD`] bytes compressed with zstd.
+    pub fn cache_compression(mut self, enabled: bool) -> Self {
+        self.config.cache.compression = enabled;
+        self
+    }
+
+    /// Stores identical response bodies cached under different URLs once.
+    pub fn cache_content_dedup(mut self, enabled: bool) -> Self {
+        self.config.cache.content_dedup = enabled;
+        self
+    }
+
+    /// Serves content already cached under another URL instead of paying
+    /// when a 402 response announces its SHA-256 hash, in a
+    /// [`CONTENT_SHA256_HEADER`](crate::verify::CONTENT_SHA256_HEADER) or
+    /// `Content-Digest` header or as `contentHash` in the requirements'
+    /// `extra` data. Enables [content deduplication](Self::cache_content_dedup).
+    pub fn skip_payment_for_cached_content(mut self, enabled: bool) -> Self {
+        if enabled {
+            self.config.cache.content_dedup = true;
+        }
+        self.config.cache.skip_payment_for_cached_content = enabled;
+        self
+    }
+
+    /// Sets the metrics configuration.
+    pub fn metrics(mut self, metrics: MetricsConfig) -> Self {
+        self.config.metrics = metrics;
+        self
+    }
+
+    /// Puts the named chains in dry-run mode.
+    ///
+    /// Payments on a dry-run chain are signed as usual, but instead of being
+    /// sent to the server the transfer they authorize is simulated with
+    /// `eth_call` against the token contract. The request gets a response
+    /// without a body, `200` if the transfer would succeed and `402` with the
+    /// revert reason in the settlement if not, and the payment is recorded
+    /// with [`PaymentHistory::dry_run`](crate::PaymentHistory::dry_run) set.
+    /// No tokens are spent.
+    ///
+    /// ```rust
+    /// use v402_client::{ChainConfig, Config};
+    ///
+    /// let config = Config::builder()
+    ///     .add_chain(ChainConfig::base_sepolia())
+    ///     .dry_run_chains(&["base-sepolia"])
+    ///     .build()?;
+    /// assert!(config.chain("base-sepolia").unwrap().dry_run);
+    /// # Ok::<(), v402_client::Error>(())
+    /// ```
+    pub fn dry_run_chains(mut self, chains: &[&str]) -> Self {
+        self.dry_run_chains.extend(chains.iter().map(|chain| chain.to_string()));
+        self
+    }
+
+    /// Sets the rate-limit retry configuration.
+    pub fn retry(mut self, retry: RetryConfig) -> Self {
+        self.config.retry = retry;
+        self
+    }
+
+    /// Sets the clock configuration for payment validity windows.
+    ///
+    /// ```rust
+    /// use v402_client::{ClockConfig, Config};
+    ///
+    /// // This machine's clock runs two minutes slow; follow the server's clock from then on
+    /// let config = Config::builder()
+    ///     .clock(ClockConfig {
+    ///         offset_secs: 120,
+    ///         calibrate_from_server: true,
+    ///         ..ClockConfig::default()
+    ///     })
+    ///     .build()?;
+    /// # Ok::<(), v402_client::Error>(())
+    /// ```
+    pub fn clock(mut self, clock: ClockConfig) -> Self {
+        self.config.clock = clock;
+        self
+    }
+
+    /// Alerts when spend to any one host within `window` reaches `threshold`,
+    /// in the asset's smallest unit.
+    ///
+    /// ```rust
+    /// use std::time::Duration;
+    /// use v402_client::Config;
+    ///
+    /// // Alert on 10 USDC to any host per hour, but 1 USDC to the crawler's target
+    /// let config = Config::builder()
+    ///     .spend_alert(Duration::from_secs(3600), "10000000")
+    ///     .host_spend_alert("*.example.com", "1000000")
+    ///     .build()?;
+    /// # Ok::<(), v402_client::Error>(())
+    /// ```
+    pub fn spend_alert<S: Into<String>>(mut self, window: Duration, threshold: S) -> Self {
+        self.config.spend_alerts.window = window;
+        self.config.spend_alerts.threshold = Some(threshold.into());
+        self
+    }
+
+    /// Alerts when spend to a host matching `pattern` reaches `threshold`,
+    /// instead of the global threshold.
+    ///
+    /// Patterns are matched as for [`route_wallet`](Self::route_wallet).
+    pub fn host_spend_alert<P: Into<String>, S: Into<String>>(mut self, pattern: P, threshold: S) -> Self {
+        self.config.spend_alerts.host_thresholds.push(HostSpendThreshold {
+            pattern: pattern.into(),
+            threshold: threshold.into(),
+        });
+        self
+    }
+
+    /// Flags payments above `multiplier` times the usual price of their
+    /// host and path prefix, handling them as `action` says.
+    ///
+    /// ```rust
+    /// use v402_client::{config::PriceJumpAction, Config};
+    ///
+    /// // Refuse to pay ten times what a resource usually costs
+    /// let config = Config::builder()
+    ///     .price_jump(10.0, PriceJumpAction::Reject)
+    ///     .build()?;
+    /// # Ok::<(), v402_client::Error>(())
+    /// ```
+    pub fn price_jump(mut self, multiplier: f64, action: PriceJumpAction) -> Self {
+        self.config.price_jumps.multiplier = Some(multiplier);
+        self.config.price_jumps.action = action;
+        self
+    }
+
+    /// Restricts requests, and the redirects they follow, to hosts matching
+    /// one of `patterns`; other requests fail with
+    /// [`Error::HostNotAllowed`](crate::Error::HostNotAllowed) before
+    /// reaching the cache or the network.
+    ///
+    /// A pattern is a host (`api.example.com`), a domain with its
+    /// subdomains (`.example.com`), a glob in which `*` matches any
+    /// characters (`*.example.com`), or an IP address or CIDR range
+    /// (`10.0.0.0/8`). Internationalized names match in their punycode form,
+    /// whichever way the URL or pattern spells them.
+    ///
+    /// ```rust
+    /// use v402_client::Config;
+    ///
+    /// let config = Config::builder()
+    ///     .allowed_hosts(&["*.example.com", "10.0.0.0/8"])
+    ///     .denied_hosts(&["admin.example.com"])
+    ///     .build()?;
+    /// assert_eq!(config.allowed_hosts.len(), 2);
+    /// # Ok::<(), v402_client::Error>(())
+    /// ```
+    pub fn allowed_hosts(mut self, patterns: &[&str]) -> Self {
+        self.config.allowed_hosts.extend(patterns.iter().map(|pattern| pattern.to_string()));
+        self
+    }
+
+    /// Refuses requests, and the redirects they follow, to hosts matching
+    /// one of `patterns`, written as for [`allowed_hosts`](Self::allowed_hosts).
+    pub fn denied_hosts(mut self, patterns: &[&str]) -> Self {
+        self.config.denied_hosts.extend(patterns.iter().map(|pattern| pattern.to_string()));
+        self
+    }
+
+    /// Applies `f` to the builder only when `condition` is true.
+    ///
+    /// ```rust
+    /// use v402_client::{ChainConfig, Config};
+    ///
+    /// let testnet = std::env::var("USE_TESTNET").is_ok();
+    /// let config = Config::builder()
+    ///     .when(testnet, |b| b.add_chain(ChainConfig::base_sepolia()))
+    ///     .when(!testnet, |b| b.add_chain(ChainConfig::base_mainnet()))
+    ///     .build();
+    /// ```
+    pub fn when<F>(self, condition: bool, f: F) -> Self
+    where
+        F: FnOnce(Self) -> Self,
+    {
+        if condition {
+            f(self)
+        } else {
+            self
+        }
+    }
+
+    /// Applies `f` to the builder only when the environment variable `var` is set.
+    pub fn when_env_set<F>(self, var: &str, f: F) -> Self
+    where
+        F: FnOnce(Self) -> Self,
+    {
+        self.when(std::env::var_os(var).is_some(), f)
+    }
+
+    /// Validates and builds the configuration.
+    ///
+    /// Defaults to Base mainnet when no chain has been added. Every problem
+    /// found is reported at once in [`Error::InvalidConfig`], see
+    /// [`Config::validate`].
+    pub fn build(mut self) -> Result<Config> {
+        if self.config.chains.is_empty() {
+            self.config.chains.push(ChainConfig::base_mainnet());
+        }
+
+        let mut issues = Vec::new();
+        for (index, name) in self.dry_run_chains.iter().enumerate() {
+            let chain = self.config.chains.iter_mut().find(|chain| &chain.name == name);
+            match chain {
+                Some(chain) => chain.dry_run = true,
+                None => issues.push(
+                    ConfigIssue::new(format!("dry_run_chains[{}]", index), "Dry-run chain is not configured")
+                        .value(name)
+                        .hint("Add the chain with add_chain before marking it as dry-run"),
+                ),
+            }
+        }
+
+        issues.extend(self.config.issues());
+        if !issues.is_empty() {
+            return Err(Error::InvalidConfig(issues));
+        }
+        Ok(self.config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+
+    /// Returns the fields of the issues found in `config`.
+    fn issue_fields(config: &Config) -> Vec<String> {
+        match config.validate() {
+            Ok(()) => Vec::new(),
+            Err(Error::InvalidConfig(issues)) => issues.into_iter().map(|issue| issue.field).collect(),
+            Err(e) => panic!("unexpected error: {e}"),
+        }
+    }
+
+    #[test]
+    fn each_rule_fires_on_its_own() {
+        assert_eq!(issue_fields(&Config::default()), Vec::<String>::new());
+
+        let cases = [
+            (
+                Config {
+                    private_key: Some("0x1234".to_string().into()),
+                    ..Config::default()
+                },
+                "private_key",
+            ),
+            (
+                Config {
+                    chains: vec![ChainConfig::base_mainnet(), ChainConfig::base_mainnet()],
+                    ..Config::default()
+                },
+                "chains[1].name",
+            ),
+            (
+                Config {
+                    cache: CacheConfig {
+                        ttl: Duration::ZERO,
+                        ..CacheConfig::default()
+                    },
+                    ..Config::default()
+                },
+                "cache.ttl",
+            ),
+            (
+                Config {
+                    max_amount_per_request: Some(format!("{}0", MAX_PAYMENT_AMOUNT)),
+                    ..Config::default()
+                },
+                "max_amount_per_request",
+            ),
+            (
+                Config {
+                    facilitator_url: Some("/facilitator".to_string()),
+                    ..Config::default()
+                },
+                "facilitator_url",
+            ),
+        ];
+        for (config, field) in cases {
+            assert_eq!(issue_fields(&config), vec![field.to_string()]);
+        }
+    }
+
+    #[test]
+    fn build_reports_every_issue_at_once() {
+        let error = Config::builder()
+            .private_key("0x1234")
+            .max_amount_per_request("lots")
+            .facilitator_url("http://facilitator.example.com")
+            .build()
+            .unwrap_err();
+        let Error::InvalidConfig(issues) = error else {
+            panic!("unexpected error: {error}");
+        };
+        let fields: Vec<_> = issues.iter().map(|issue| issue.field.as_str()).collect();
+        assert_eq!(fields, ["private_key", "max_amount_per_request", "facilitator_url"]);
+
+        // The rejected key is described, never shown
+        let key = issues[0].value.as_deref().unwrap();
+        assert!(!key.contains("1234"), "{key}");
+    }
+
+    #[test]
+    fn valid_configs_build() {
+        let config = Config::builder().private_key(KEY).build().unwrap();
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn secrets_stay_out_of_debug_and_serialized_configs() {
+        let wallet_key = KEY.replace("4c08", "5d19");
+        let cache_key = "ab".repeat(32);
+        let config = Config::builder()
+            .private_key(KEY)
+            .add_wallet(WalletConfig::new("treasury", wallet_key.as_str()))
+            .cache_encryption_key(cache_key.as_str())
+            .build()
+            .unwrap();
+        let secrets = [&KEY[2..], &wallet_key[2..], cache_key.as_str()];
+
+        for output in [
+            format!("{:?}", config),
+            format!("{:#?}", config),
+            serde_json::to_string(&config).unwrap(),
+        ] {
+            for secret in secrets {
+                assert!(!output.contains(secret), "secret leaked in {output}");
+            }
+        }
+
+        let trusted = config.serialize_with_secrets().unwrap().to_string();
+        for secret in secrets {
+            assert!(trusted.contains(secret));
+        }
+    }
+}
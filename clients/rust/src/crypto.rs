@@ -0,0 +1,383 @@
+//! Cryptographic primitives for EVM payment signing.
+//!
+//! Covers the small subset of Keccak hashing, ABI word encoding and EIP-712
+//! typed-data hashing needed to sign EIP-3009 authorizations, and
+//! [`SiweHandler`], which answers authentication challenges with a
+//! Sign-In with Ethereum message.
+
+use crate::{
+    challenge::{Challenge, ChallengeHandler},
+    error::{Error, Result},
+};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use chrono::{SecondsFormat, Utc};
+use hmac::{Hmac, Mac};
+use k256::ecdsa::{RecoveryId, Signature, SigningKey, VerifyingKey};
+use secrecy::zeroize::Zeroizing;
+use sha2::Sha256;
+use sha3::{Digest, Keccak256};
+use std::{collections::HashMap, fmt};
+use uuid::Uuid;
+
+/// Domain separation tag prefixed to the message of derived payment nonces.
+const PAYMENT_NONCE_TAG: &[u8] = b"v402-payment-nonce-v1";
+
+/// Computes the Keccak-256 hash of `data`.
+pub(crate) fn keccak256(data: &[u8]) -> [u8; 32] {
+    Keccak256::digest(data).into()
+}
+
+/// Returns the 4-byte ABI selector of a function signature such as `getNonce(address)`.
+pub(crate) fn function_selector(signature: &str) -> [u8; 4] {
+    let hash = keccak256(signature.as_bytes());
+    [hash[0], hash[1], hash[2], hash[3]]
+}
+
+/// Parses a `0x`-prefixed 20-byte address.
+pub(crate) fn parse_address(address: &str) -> Result<[u8; 20]> {
+    let bytes = decode_hex(address)?;
+    bytes
+        .try_into()
+        .map_err(|_| Error::Chain(format!("Invalid address: {}", address).into()))
+}
+
+/// Parses a `0x`-prefixed 32-byte value.
+pub(crate) fn parse_bytes32(value: &str) -> Result<[u8; 32]> {
+    let bytes = decode_hex(value)?;
+    bytes
+        .try_into()
+        .map_err(|_| Error::Chain(format!("Invalid bytes32 value: {}", value).into()))
+}
+
+/// Decodes a hex string with an optional `0x` prefix.
+pub(crate) fn decode_hex(value: &str) -> Result<Vec<u8>> {
+    let stripped = value.strip_prefix("0x").unwrap_or(value);
+    hex::decode(stripped).map_err(|e| Error::Chain(format!("Invalid hex {:?}: {}", value, e).into()))
+}
+
+/// ABI-encodes an address as a 32-byte word.
+pub(crate) fn address_word(address: &[u8; 20]) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    word[12..].copy_from_slice(address);
+    word
+}
+
+/// ABI-encodes an unsigned integer as a 32-byte word.
+pub(crate) fn uint_word(value: u128) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    word[16..].copy_from_slice(&value.to_be_bytes());
+    word
+}
+
+/// ABI-encodes a decimal integer string as a 32-byte word.
+pub(crate) fn decimal_word(value: &str) -> Result<[u8; 32]> {
+    value
+        .parse::<u128>()
+        .map(uint_word)
+        .map_err(|_| Error::Payment(format!("Invalid integer amount: {}", value).into()))
+}
+
+/// Derives a payment nonce as
+/// `HMAC-SHA256(key, tag || resource_hash || requirements_hash || counter)`,
+/// with the counter as 8 big-endian bytes.
+pub(crate) fn derive_payment_nonce(
+    key: &[u8],
+    resource_hash: &[u8; 32],
+    requirements_hash: &[u8; 32],
+    counter: u64,
+) -> [u8; 32] {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(PAYMENT_NONCE_TAG);
+    mac.update(resource_hash);
+    mac.update(requirements_hash);
+    mac.update(&counter.to_be_bytes());
+    mac.finalize().into_bytes().into()
+}
+
+/// Decimal representation of the largest `uint256` value.
+const UINT256_MAX: &str = "115792089237316195423570985008687907853269984665640564039457584007913129639935";
+
+/// Returns `true` if `value` is a decimal integer that fits in a `uint256`.
+pub(crate) fn is_uint256(value: &str) -> bool {
+    if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return false;
+    }
+    let digits = value.trim_start_matches('0');
+    digits.len() < UINT256_MAX.len() || (digits.len() == UINT256_MAX.len() && digits <= UINT256_MAX)
+}
+
+/// Decodes a 32-byte ABI word as an unsigned integer.
+///
+/// Fails if the value does not fit in 128 bits.
+pub(crate) fn word_to_uint(word: &[u8]) -> Result<u128> {
+    if word.len() != 32 || word[..16].iter().any(|b| *b != 0) {
+        return Err(Error::Chain("ABI word is not a 128-bit unsigned integer".to_string().into()));
+    }
+    let mut low = [0u8; 16];
+    low.copy_from_slice(&word[16..]);
+    Ok(u128::from_be_bytes(low))
+}
+
+/// Computes the EIP-712 domain separator.
+pub(crate) fn domain_separator(name: &str, version: &str, chain_id: u64, verifying_contract: &[u8; 20]) -> [u8; 32] {
+    let type_hash = keccak256(
+        b"EIP712Domain(string name,string version,uint256 chainId,address verifyingContract)",
+    );
+
+    let mut encoded = Vec::with_capacity(32 * 5);
+    encoded.extend_from_slice(&type_hash);
+    encoded.extend_from_slice(&keccak256(name.as_bytes()));
+    encoded.extend_from_slice(&keccak256(version.as_bytes()));
+    encoded.extend_from_slice(&uint_word(chain_id as u128));
+    encoded.extend_from_slice(&address_word(verifying_contract));
+    keccak256(&encoded)
+}
+
+/// Computes the EIP-712 digest of a struct hash under a domain.
+pub(crate) fn typed_data_hash(domain_separator: &[u8; 32], struct_hash: &[u8; 32]) -> [u8; 32] {
+    let mut encoded = Vec::with_capacity(2 + 64);
+    encoded.extend_from_slice(&[0x19, 0x01]);
+    encoded.extend_from_slice(domain_separator);
+    encoded.extend_from_slice(struct_hash);
+    keccak256(&encoded)
+}
+
+/// Computes the EIP-191 `personal_sign` hash of `message`.
+pub(crate) fn personal_message_hash(message: &[u8]) -> [u8; 32] {
+    let mut data = format!("\x19Ethereum Signed Message:\n{}", message.len()).into_bytes();
+    data.extend_from_slice(message);
+    keccak256(&data)
+}
+
+/// Returns `address` with the mixed-case EIP-55 checksum.
+pub(crate) fn checksum_address(address: &str) -> String {
+    let lower = address.trim_start_matches("0x").to_ascii_lowercase();
+    let hash = keccak256(lower.as_bytes());
+    let checksummed: String = lower
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            let nibble = (hash[i / 2] >> (if i % 2 == 0 { 4 } else { 0 })) & 0x0f;
+            if nibble >= 8 { c.to_ascii_uppercase() } else { c }
+        })
+        .collect();
+    format!("0x{}", checksummed)
+}
+
+/// Recovers the address that produced a 65-byte `r || s || v` signature over `digest`.
+pub(crate) fn recover_address(digest: &[u8; 32], signature: &str) -> Result<String> {
+    let invalid = |reason: &str| Error::Payment(format!("Invalid signature: {}", reason).into());
+
+    let bytes = decode_hex(signature)?;
+    if bytes.len() != 65 {
+        return Err(invalid("expected 65 bytes"));
+    }
+    let signature = Signature::from_slice(&bytes[..64]).map_err(|e| invalid(&e.to_string()))?;
+    let v = bytes[64];
+    let recovery_id = RecoveryId::from_byte(if v >= 27 { v - 27 } else { v }).ok_or_else(|| invalid("bad recovery id"))?;
+
+    let key = VerifyingKey::recover_from_prehash(digest, &signature, recovery_id).map_err(|e| invalid(&e.to_string()))?;
+    Ok(eth_address(&key))
+}
+
+/// Returns the address of a hex-encoded SEC1 public key, or the input itself if it is already an address.
+pub(crate) fn public_key_address(key: &str) -> Result<String> {
+    let bytes = decode_hex(key)?;
+    if bytes.len() == 20 {
+        return Ok(format!("0x{}", hex::encode(bytes)));
+    }
+    let key = VerifyingKey::from_sec1_bytes(&bytes).map_err(|e| Error::Config(format!("Invalid public key: {}", e).into()))?;
+    Ok(eth_address(&key))
+}
+
+/// RLP-encodes a byte string.
+pub(crate) fn rlp_bytes(bytes: &[u8]) -> Vec<u8> {
+    if let [byte @ 0x00..=0x7f] = bytes {
+        return vec![*byte];
+    }
+    let mut out = rlp_header(bytes.len(), 0x80);
+    out.extend(bytes);
+    out
+}
+
+/// RLP-encodes a list of encoded items.
+pub(crate) fn rlp_list(items: &[Vec<u8>]) -> Vec<u8> {
+    let payload = items.concat();
+    let mut out = rlp_header(payload.len(), 0xc0);
+    out.extend(payload);
+    out
+}
+
+/// RLP-encodes an unsigned integer.
+pub(crate) fn rlp_uint(value: u64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let start = bytes.iter().take_while(|byte| **byte == 0).count();
+    rlp_bytes(&bytes[start..])
+}
+
+/// Encodes the length prefix of a string (`offset` 0x80) or list (0xc0).
+fn rlp_header(len: usize, offset: u8) -> Vec<u8> {
+    if len < 56 {
+        return vec![offset + len as u8];
+    }
+    let bytes = len.to_be_bytes();
+    let start = bytes.iter().take_while(|byte| **byte == 0).count();
+    let mut out = vec![offset + 55 + (bytes.len() - start) as u8];
+    out.extend(&bytes[start..]);
+    out
+}
+
+/// secp256k1 signer holding the client's private key.
+///
+/// The key is zeroized on drop and never printed by `Debug`.
+pub(crate) struct Signer {
+    key: SigningKey,
+    address: String,
+}
+
+impl Signer {
+    /// Creates a signer from a hex-encoded private key.
+    pub(crate) fn from_hex(private_key: &str) -> Result<Self> {
+        let bytes = Zeroizing::new(
+            parse_bytes32(private_key)
+                .map_err(|_| Error::Config("Private key must be 32 bytes of hex".to_string().into()))?,
+        );
+        let key = SigningKey::from_bytes(&(*bytes).into())
+            .map_err(|e| Error::Config(format!("Invalid private key: {}", e).into()))?;
+        let address = eth_address(key.verifying_key());
+
+        Ok(Self { key, address })
+    }
+
+    /// Returns the `0x`-prefixed Ethereum address of the signer.
+    pub(crate) fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Derives a payment nonce keyed with the signer's private key, see [`derive_payment_nonce`].
+    pub(crate) fn derive_nonce(&self, resource_hash: &[u8; 32], requirements_hash: &[u8; 32], counter: u64) -> [u8; 32] {
+        let key = Zeroizing::new(self.key.to_bytes());
+        derive_payment_nonce(&key, resource_hash, requirements_hash, counter)
+    }
+
+    /// Signs a 32-byte digest, returning the 65-byte `r || s || v` signature as hex.
+    pub(crate) fn sign_hash(&self, digest: &[u8; 32]) -> Result<String> {
+        let (signature, recovery_id) = self
+            .key
+            .sign_prehash_recoverable(digest)
+            .map_err(|e| Error::Payment(format!("Signing failed: {}", e).into()))?;
+
+        let mut bytes = signature.to_bytes().to_vec();
+        bytes.push(27 + recovery_id.to_byte());
+        Ok(format!("0x{}", hex::encode(bytes)))
+    }
+}
+
+impl fmt::Debug for Signer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Signer")
+            .field("address", &self.address)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Derives the Ethereum address of a public key.
+fn eth_address(key: &VerifyingKey) -> String {
+    let point = key.to_encoded_point(false);
+    let hash = keccak256(&point.as_bytes()[1..]);
+    format!("0x{}", hex::encode(&hash[12..]))
+}
+
+/// Answers authentication challenges with a signed EIP-4361 Sign-In with Ethereum message.
+///
+/// The message is signed by the wallet that paid, for the domain and URL of
+/// the request and the chain paid on. Its nonce is the `nonce` parameter of
+/// the `WWW-Authenticate` header, else the `nonce` field of a JSON body,
+/// else a random one.
+///
+/// The message and signature are sent as
+/// `Authorization: SIWE <base64 of {"message": ..., "signature": ...}>`.
+#[derive(Debug, Clone, Default)]
+pub struct SiweHandler {
+    statement: Option<String>,
+}
+
+impl SiweHandler {
+    /// Creates a handler signing messages without a statement.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the human-readable statement included in the message.
+    pub fn statement(mut self, statement: impl Into<String>) -> Self {
+        self.statement = Some(statement.into());
+        self
+    }
+
+    /// Builds the EIP-4361 message answering `challenge` with `nonce`.
+    fn message(&self, challenge: &Challenge<'_>, nonce: &str) -> Result<String> {
+        let invalid = |reason: String| Error::InvalidUrl {
+            input: challenge.url.to_string(),
+            reason,
+            context: None,
+        };
+        let url = url::Url::parse(challenge.url).map_err(|e| invalid(e.to_string()))?;
+        let host = url.host_str().ok_or_else(|| invalid("URL has no host".to_string()))?;
+        let domain = match url.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        };
+        let statement = self
+            .statement
+            .as_deref()
+            .map(|statement| format!("{}\n", statement))
+            .unwrap_or_default();
+
+        Ok(format!(
+            "{} wants you to sign in with your Ethereum account:\n{}\n\n{}\nURI: {}\nVersion: 1\nChain ID: {}\nNonce: {}\nIssued At: {}",
+            domain,
+            checksum_address(challenge.address),
+            statement,
+            challenge.url,
+            challenge.chain_id.unwrap_or(1),
+            nonce,
+            Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        ))
+    }
+}
+
+#[async_trait]
+impl ChallengeHandler for SiweHandler {
+    async fn respond(&self, challenge: &Challenge<'_>) -> Result<Option<HashMap<String, String>>> {
+        let nonce = challenge
+            .header("WWW-Authenticate")
+            .and_then(|header| auth_param(header, "nonce"))
+            .or_else(|| {
+                serde_json::from_slice::<serde_json::Value>(challenge.body)
+                    .ok()?
+                    .get("nonce")?
+                    .as_str()
+                    .map(str::to_string)
+            })
+            .unwrap_or_else(|| Uuid::new_v4().simple().to_string());
+
+        let message = self.message(challenge, &nonce)?;
+        let signature = challenge.sign_message(message.as_bytes())?;
+        let credentials = serde_json::json!({ "message": message, "signature": signature });
+
+        Ok(Some(HashMap::from([(
+            "Authorization".to_string(),
+            format!("SIWE {}", BASE64.encode(credentials.to_string())),
+        )])))
+    }
+}
+
+/// Returns the value of the auth parameter `name` in a `WWW-Authenticate` header.
+fn auth_param(header: &str, name: &str) -> Option<String> {
+    header
+        .split([',', ' '])
+        .filter_map(|param| param.split_once('='))
+        .find(|(key, _)| key.trim().eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.trim().trim_matches('"').to_string())
+}
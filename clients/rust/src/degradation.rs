@@ -0,0 +1,229 @@
+//! Degraded mode for the cache and metrics.
+//!
+//! The cache and the metrics collector are optional: requests and payments
+//! work without them. With [`DegradationPolicy::Degrade`], the default, a
+//! failure of either one while the client is created or in use is logged and
+//! the component is replaced by one that does nothing, a cache that never
+//! hits or a collector that records nothing, instead of failing the client or
+//! the request. Payment and chain failures are always returned as errors.
+//!
+//! A degraded component shows as unhealthy in
+//! [`Client::health_check`](crate::Client::health_check), and every operation
+//! it skips is counted in the `degraded_operations` metric of the health
+//! status and in [`DegradedComponent::operations`]. Once the cause is fixed,
+//! [`Client::try_recover_component`](crate::Client::try_recover_component)
+//! initializes the component again.
+//!
+//! ```rust
+//! # use v402_client::Client;
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! # let client = Client::builder().build().await?;
+//! for component in client.degraded_components() {
+//!     println!("{} degraded since {}: {}", component.name, component.since, component.error);
+//!     client.try_recover_component(&component.name).await?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    config::DegradationPolicy,
+    error::{Error, Result},
+};
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+use tracing::warn;
+
+/// Name of the response cache component.
+pub const CACHE: &str = "cache";
+
+/// Name of the metrics collector component.
+pub const METRICS: &str = "metrics";
+
+/// Optional component of a client, with a version of itself that does nothing.
+///
+/// The client creates the cache and the metrics collector through this
+/// trait and never builds them directly, so it does not depend on the
+/// `cache` and `metrics` features: without them, the components created are
+/// their no-op versions.
+pub(crate) trait Component: Sized {
+    /// Name of the component, [`CACHE`] or [`METRICS`]
+    const NAME: &'static str;
+
+    /// Configuration the component is created from
+    type Config;
+
+    /// Creates the component from its configuration.
+    fn create(config: &Self::Config) -> Result<Self>;
+
+    /// Creates a version of the component that does nothing, used in place
+    /// of one that failed.
+    fn noop(config: &Self::Config) -> Self;
+}
+
+/// Component running in degraded mode after a failure.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DegradedComponent {
+    /// Name of the component, [`CACHE`] or [`METRICS`]
+    pub name: String,
+
+    /// Time of the failure
+    pub since: DateTime<Utc>,
+
+    /// Error the component failed with
+    pub error: String,
+
+    /// Operations skipped since the failure
+    pub operations: u64,
+}
+
+/// Degraded components of a client and the operations they skipped.
+#[derive(Debug)]
+pub(crate) struct Degradation {
+    policy: DegradationPolicy,
+    /// Whether any component is degraded, checked before taking the lock
+    active: AtomicBool,
+    components: Mutex<BTreeMap<&'static str, DegradedComponent>>,
+    operations: AtomicU64,
+}
+
+impl Degradation {
+    /// Creates a tracker handling failures according to `policy`.
+    pub(crate) fn new(policy: DegradationPolicy) -> Self {
+        Self {
+            policy,
+            active: AtomicBool::new(false),
+            components: Mutex::new(BTreeMap::new()),
+            operations: AtomicU64::new(0),
+        }
+    }
+
+    /// Handles a failure of `component`.
+    ///
+    /// Returns `error` under [`DegradationPolicy::Fail`]. Otherwise logs it,
+    /// marks the component degraded and returns whether it was working
+    /// before, in which case the caller replaces it with its no-op version.
+    pub(crate) fn absorb(&self, component: &'static str, error: Error) -> Result<bool> {
+        if self.policy == DegradationPolicy::Fail {
+            return Err(error);
+        }
+        warn!(component, error = %error, "Component failed, continuing in degraded mode");
+        let mut components = self.components.lock();
+        if components.contains_key(component) {
+            return Ok(false);
+        }
+        components.insert(
+            component,
+            DegradedComponent {
+                name: component.to_string(),
+                since: Utc::now(),
+                error: error.to_string(),
+                operations: 0,
+            },
+        );
+        self.active.store(true, Ordering::Relaxed);
+        Ok(true)
+    }
+
+    /// Creates a component, or its no-op version if that fails and the
+    /// policy is not [`DegradationPolicy::Fail`].
+    pub(crate) fn create<C: Component>(&self, config: &C::Config) -> Result<C> {
+        match C::create(config) {
+            Ok(component) => Ok(component),
+            Err(error) => {
+                self.absorb(C::NAME, error)?;
+                Ok(C::noop(config))
+            }
+        }
+    }
+
+    /// Counts an operation skipped by `component` if it is degraded.
+    pub(crate) fn record_operation(&self, component: &'static str) {
+        if !self.active.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Some(degraded) = self.components.lock().get_mut(component) {
+            degraded.operations += 1;
+            self.operations.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the failure of `component`, if it is degraded.
+    pub(crate) fn get(&self, component: &str) -> Option<DegradedComponent> {
+        if !self.active.load(Ordering::Relaxed) {
+            return None;
+        }
+        self.components.lock().get(component).cloned()
+    }
+
+    /// Marks `component` as working again, returning whether it was degraded.
+    pub(crate) fn recover(&self, component: &str) -> bool {
+        let mut components = self.components.lock();
+        let recovered = components.remove(component).is_some();
+        self.active.store(!components.is_empty(), Ordering::Relaxed);
+        recovered
+    }
+
+    /// Returns the degraded components, by name.
+    pub(crate) fn components(&self) -> Vec<DegradedComponent> {
+        self.components.lock().values().cloned().collect()
+    }
+
+    /// Returns the operations skipped by degraded components since the client was created.
+    pub(crate) fn operations(&self) -> u64 {
+        self.operations.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Component whose creation fails unless its configuration says otherwise.
+    #[derive(Debug, PartialEq)]
+    enum Probe {
+        Working,
+        Noop,
+    }
+
+    impl Component for Probe {
+        const NAME: &'static str = CACHE;
+
+        type Config = bool;
+
+        fn create(works: &bool) -> Result<Self> {
+            if *works {
+                Ok(Self::Working)
+            } else {
+                Err(Error::Cache("backend unavailable".into()))
+            }
+        }
+
+        fn noop(_works: &bool) -> Self {
+            Self::Noop
+        }
+    }
+
+    #[test]
+    fn failed_components_are_replaced_by_their_noop_version() {
+        let degradation = Degradation::new(DegradationPolicy::Degrade);
+        assert_eq!(degradation.create::<Probe>(&true).unwrap(), Probe::Working);
+        assert!(degradation.get(CACHE).is_none());
+
+        assert_eq!(degradation.create::<Probe>(&false).unwrap(), Probe::Noop);
+        assert!(degradation.get(CACHE).unwrap().error.contains("backend unavailable"));
+    }
+
+    #[test]
+    fn failed_components_fail_the_client_under_fail_policy() {
+        let degradation = Degradation::new(DegradationPolicy::Fail);
+        assert!(matches!(degradation.create::<Probe>(&false), Err(Error::Cache(_))));
+        assert!(degradation.components().is_empty());
+    }
+}
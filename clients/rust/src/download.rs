@@ -0,0 +1,276 @@
+//! Resumable downloads to disk.
+//!
+//! [`Client::download`](crate::Client::download) streams a resource to a
+//! `.part` file next to its destination and renames it once complete, so
+//! the destination never holds a partial file. An interrupted download is
+//! resumed with a `Range` request validated by `If-Range`, and starts over
+//! if the resource changed in between.
+//!
+//! A paid resource is paid for once per download: later ranges are
+//! requested with the receipt of that payment in the `X-PAYMENT-RECEIPT`
+//! header. Servers that want a new payment for each range fail the download
+//! with [`Error::RangePaymentRequired`](crate::Error::RangePaymentRequired).
+//!
+//! ```rust,no_run
+//! use v402_client::{download::DownloadOptions, Client};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let client = Client::builder().build().await?;
+//! let report = client
+//!     .download("https://data.example/dataset.parquet", "dataset.parquet", DownloadOptions::new())
+//!     .await?;
+//! println!("{} bytes, resumed from {}", report.bytes, report.resumed_from);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    error::Result,
+    payment::{Receipt, PAYMENT_RECEIPT_HEADER},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    io::SeekFrom,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::{
+    fs::{self, File, OpenOptions},
+    io::{AsyncSeekExt, AsyncWriteExt},
+};
+use tracing::debug;
+
+/// Time allowed for each request of a download, including reading its body,
+/// unless set with [`DownloadOptions::timeout`].
+pub const DEFAULT_DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(3600);
+
+/// Options of [`Client::download`](crate::Client::download).
+#[derive(Debug, Clone)]
+pub struct DownloadOptions {
+    /// Continue from an existing `.part` file instead of starting over
+    pub resume: bool,
+
+    /// Times an interrupted transfer is resumed before giving up
+    pub max_retries: u32,
+
+    /// Time allowed for each request, including reading its body
+    pub timeout: Duration,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        Self {
+            resume: true,
+            max_retries: 3,
+            timeout: DEFAULT_DOWNLOAD_TIMEOUT,
+        }
+    }
+}
+
+impl DownloadOptions {
+    /// Creates options that resume downloads and retry interrupted transfers three times.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether an existing `.part` file is continued.
+    pub fn resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    /// Sets how many times an interrupted transfer is resumed.
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Sets the time allowed for each request, including reading its body.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+/// Outcome of a completed download.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DownloadReport {
+    /// File the resource was written to
+    pub path: PathBuf,
+
+    /// Size of the file
+    pub bytes: u64,
+
+    /// Bytes already on disk from an earlier attempt, 0 for a fresh download
+    pub resumed_from: u64,
+
+    /// Whether a payment was made by this call
+    pub payment_made: bool,
+
+    /// Interrupted transfers that were resumed
+    pub retries: u32,
+}
+
+/// State of a download kept next to its `.part` file, to resume it later.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PartState {
+    url: String,
+    /// Strong ETag or `Last-Modified` date of the content in the `.part`
+    /// file; resuming is only possible with one
+    validator: Option<String>,
+    /// Receipt of the payment covering the download
+    receipt: Option<Receipt>,
+}
+
+/// A `.part` file being written and its saved state.
+#[derive(Debug)]
+pub(crate) struct PartialDownload {
+    part_path: PathBuf,
+    state_path: PathBuf,
+    file: File,
+    len: u64,
+    state: PartState,
+}
+
+impl PartialDownload {
+    /// Opens the `.part` file of `path`, continuing it if `resume` is set and
+    /// its saved state is for `url` and can be validated.
+    pub(crate) async fn open(url: &str, path: &Path, resume: bool) -> Result<Self> {
+        let part_path = with_suffix(path, ".part");
+        let state_path = with_suffix(path, ".part.json");
+
+        let saved = match fs::read(&state_path).await {
+            Ok(bytes) => serde_json::from_slice::<PartState>(&bytes).ok().filter(|state| state.url == url),
+            Err(_) => None,
+        };
+        let file = OpenOptions::new().create(true).truncate(false).write(true).open(&part_path).await?;
+        let state = saved.unwrap_or_else(|| PartState {
+            url: url.to_string(),
+            ..PartState::default()
+        });
+
+        let mut download = Self {
+            part_path,
+            state_path,
+            file,
+            len: 0,
+            state,
+        };
+        if resume && download.state.validator.is_some() {
+            download.len = download.file.seek(SeekFrom::End(0)).await?;
+        } else {
+            download.restart().await?;
+        }
+        debug!(url, path = %download.part_path.display(), offset = download.len, "Opened partial download");
+        Ok(download)
+    }
+
+    /// Bytes written so far.
+    pub(crate) fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Receipt of the payment covering the download, if one was made.
+    pub(crate) fn receipt(&self) -> Option<&Receipt> {
+        self.state.receipt.as_ref()
+    }
+
+    /// Keeps `receipt` to prove payment for the rest of the download.
+    pub(crate) async fn set_receipt(&mut self, receipt: Receipt) -> Result<()> {
+        self.state.receipt = Some(receipt);
+        self.save().await
+    }
+
+    /// Returns the headers requesting the rest of the content: a validated
+    /// `Range` once some of it is on disk, and the payment receipt.
+    pub(crate) fn request_headers(&self) -> Result<HashMap<String, String>> {
+        let mut headers = HashMap::new();
+        if let (true, Some(validator)) = (self.len > 0, &self.state.validator) {
+            headers.insert("Range".to_string(), format!("bytes={}-", self.len));
+            headers.insert("If-Range".to_string(), validator.clone());
+        }
+        if let Some(receipt) = &self.state.receipt {
+            headers.insert(PAYMENT_RECEIPT_HEADER.to_string(), receipt.to_header()?);
+        }
+        Ok(headers)
+    }
+
+    /// Records the validator of a response whose body is written from the start.
+    ///
+    /// Without range support, shown by `Accept-Ranges: bytes` or a
+    /// `Content-Range`, and a strong ETag or a `Last-Modified` date, the
+    /// download cannot be resumed and starts over if interrupted.
+    pub(crate) async fn begin(&mut self, headers: &HashMap<String, String>) -> Result<()> {
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.as_str())
+        };
+        let accepts_ranges = header("content-range").is_some()
+            || header("accept-ranges").is_some_and(|ranges| ranges.eq_ignore_ascii_case("bytes"));
+        let validator = header("etag")
+            .filter(|etag| !etag.starts_with("W/"))
+            .or_else(|| header("last-modified"));
+
+        self.restart().await?;
+        self.state.validator = validator.filter(|_| accepts_ranges).map(str::to_string);
+        self.save().await
+    }
+
+    /// Appends a chunk of the body.
+    pub(crate) async fn write(&mut self, chunk: &[u8]) -> Result<()> {
+        self.file.write_all(chunk).await?;
+        self.len += chunk.len() as u64;
+        Ok(())
+    }
+
+    /// Discards the content written so far.
+    pub(crate) async fn restart(&mut self) -> Result<()> {
+        self.file.set_len(0).await?;
+        self.file.seek(SeekFrom::Start(0)).await?;
+        self.len = 0;
+        self.state.validator = None;
+        self.save().await
+    }
+
+    /// Syncs the `.part` file to disk and renames it to `path`, returning its size.
+    pub(crate) async fn finish(mut self, path: &Path) -> Result<u64> {
+        self.file.flush().await?;
+        self.file.sync_all().await?;
+        drop(self.file);
+        fs::rename(&self.part_path, path).await?;
+        match fs::remove_file(&self.state_path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        Ok(self.len)
+    }
+
+    /// Writes the state next to the `.part` file.
+    async fn save(&self) -> Result<()> {
+        let state = serde_json::to_vec(&self.state)?;
+        fs::write(&self.state_path, state).await?;
+        Ok(())
+    }
+}
+
+/// Parses the first byte position and total size of a `Content-Range: bytes start-end/total` header.
+pub(crate) fn content_range(header: &str) -> (Option<u64>, Option<u64>) {
+    let Some(range) = header.trim().strip_prefix("bytes ") else {
+        return (None, None);
+    };
+    let (positions, total) = range.split_once('/').unwrap_or((range, "*"));
+    let start = positions.split_once('-').and_then(|(start, _)| start.trim().parse().ok());
+    (start, total.trim().parse().ok())
+}
+
+/// Returns `path` with `suffix` appended to its file name.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
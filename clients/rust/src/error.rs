@@ -0,0 +1,627 @@
+//! Error types for the v402 client.
+//!
+//! [`Error`] is `Send + Sync + 'static`, so `?` converts it into
+//! `anyhow::Error` and it can be wrapped with `#[from]` in your own
+//! `thiserror` enums:
+//!
+//! ```rust
+//! #[derive(Debug, thiserror::Error)]
+//! enum AppError {
+//!     #[error("payment failed: {0}")]
+//!     Payment(#[from] v402_client::Error),
+//! }
+//!
+//! fn fetch() -> v402_client::Result<()> {
+//!     Err(v402_client::Error::ClientClosed)
+//! }
+//!
+//! fn with_thiserror() -> Result<(), AppError> {
+//!     fetch()?;
+//!     Ok(())
+//! }
+//!
+//! fn with_anyhow() -> anyhow::Result<()> {
+//!     fetch()?;
+//!     Ok(())
+//! }
+//!
+//! assert!(matches!(with_thiserror(), Err(AppError::Payment(_))));
+//! assert!(with_anyhow().unwrap_err().downcast_ref::<v402_client::Error>().is_some());
+//! ```
+//!
+//! Converting into `anyhow::Error` needs no feature: it comes from anyhow's
+//! blanket `From` impl for standard errors, which a crate cannot add to or
+//! override. The `anyhow` feature adds the opposite direction, turning an
+//! `anyhow::Error` into [`Error::Internal`] so `?` works in functions
+//! returning [`Result`], for example in middleware calling anyhow-based code.
+//!
+//! Errors returned by requests record the URL, request ID and attempt they
+//! came from alongside the variant, so matching on variants is unaffected:
+//!
+//! ```rust
+//! use v402_client::error::{Error, ErrorContext};
+//!
+//! let err = Error::Network("connection reset".into()).with_context(ErrorContext {
+//!     url: Some("https://api.example.com/data".to_string()),
+//!     request_id: Some("req-42".to_string()),
+//!     attempt: 2,
+//!     payment_attempted: true,
+//! });
+//!
+//! assert!(matches!(err, Error::Network(_)));
+//! assert_eq!(err.request_id(), Some("req-42"));
+//! assert_eq!(
+//!     err.to_string(),
+//!     "Network error: connection reset (url https://api.example.com/data, request req-42, attempt 2, payment attempted)"
+//! );
+//! ```
+
+use crate::{config::ConfigIssue, payment::PaymentRequirements};
+use std::{fmt, ops::Deref, time::Duration};
+use thiserror::Error;
+
+/// Convenient result alias used throughout the crate.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Errors returned by the v402 client.
+///
+/// Every variant except [`Error::InvalidConfig`], [`Error::Io`],
+/// [`Error::BatchDeadlineExceeded`] and [`Error::ClientClosed`] can record the
+/// request it came from, attached with [`Error::with_context`]: in the
+/// [`ErrorMessage`] of message variants and in the `context` field of the
+/// others. It is included in the `Display` output.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// Network-level failure (connection refused, DNS, TLS, ...)
+    #[error("Network error: {0}{}", suffix(.0.context.as_deref()))]
+    Network(ErrorMessage),
+
+    /// Host name could not be resolved, by DNS or the configured resolver
+    #[error("DNS resolution failed for {host}: {reason}{}", suffix(context.as_deref()))]
+    Dns {
+        /// Host that failed to resolve
+        host: String,
+        /// Resolver error
+        reason: String,
+        /// Request the error came from
+        context: Option<Box<ErrorContext>>,
+    },
+
+    /// Request URL could not be parsed or is not an HTTP(S) URL
+    #[error("Invalid URL {input:?}: {reason}{}", suffix(context.as_deref()))]
+    InvalidUrl {
+        /// URL as given by the caller
+        input: String,
+        /// Why it was rejected
+        reason: String,
+        /// Request the error came from
+        context: Option<Box<ErrorContext>>,
+    },
+
+    /// Request or redirect target is refused by the configured allowed or denied hosts
+    #[error("Requests to {host} are not allowed: {reason}{}", suffix(context.as_deref()))]
+    HostNotAllowed {
+        /// Host of the refused URL
+        host: String,
+        /// Pattern or rule that refused it
+        reason: String,
+        /// Request the error came from
+        context: Option<Box<ErrorContext>>,
+    },
+
+    /// Wallets are configured, but none is routed to the domain and there is no default
+    #[error("No wallet configured for {domain}{}", suffix(context.as_deref()))]
+    NoWalletForDomain {
+        /// Host of the URL being paid for
+        domain: String,
+        /// Request the error came from
+        context: Option<Box<ErrorContext>>,
+    },
+
+    /// Payment could not be created, signed or settled
+    #[error("Payment error: {0}{}", suffix(.0.context.as_deref()))]
+    Payment(ErrorMessage),
+
+    /// Credentials could not be obtained or were rejected
+    #[error("Authentication error: {0}{}", suffix(.0.context.as_deref()))]
+    Auth(ErrorMessage),
+
+    /// No token price was available to enforce a USD payment cap
+    #[error("Price oracle unavailable: {0}{}", suffix(.0.context.as_deref()))]
+    PriceOracleUnavailable(ErrorMessage),
+
+    /// Payment was required and the client is configured not to pay
+    #[error(
+        "Payment required for {url}: {} on {}{}",
+        requirements.max_amount_required,
+        requirements.network,
+        suffix(context.as_deref())
+    )]
+    PaymentRequired {
+        /// URL that answered with 402
+        url: String,
+        /// Requirements parsed from the response
+        requirements: Box<PaymentRequirements>,
+        /// Request the error came from
+        context: Option<Box<ErrorContext>>,
+    },
+
+    /// A 402 response did not carry parseable payment requirements
+    #[error(
+        "Invalid payment requirements ({}): {parse_error}; body starts with {body_preview:?}{}",
+        content_type.as_deref().unwrap_or("no content type"),
+        suffix(context.as_deref())
+    )]
+    InvalidPaymentRequirements {
+        /// `Content-Type` of the response, if any
+        content_type: Option<String>,
+        /// Up to the first 512 bytes of the body, lossily decoded as UTF-8
+        body_preview: String,
+        /// Why the body was rejected
+        parse_error: String,
+        /// Request the error came from
+        context: Option<Box<ErrorContext>>,
+    },
+
+    /// Response failed a [`ResponseVerifier`](crate::verify::ResponseVerifier) check
+    #[error("Integrity check failed for {url}: {reason}{}", suffix(context.as_deref()))]
+    IntegrityCheckFailed {
+        /// URL of the response
+        url: String,
+        /// Which check failed and why
+        reason: String,
+        /// Request the error came from
+        context: Option<Box<ErrorContext>>,
+    },
+
+    /// A download was paid for, but the server wants a new payment for each range
+    #[error("Server demands payment per range for {url}{}", suffix(context.as_deref()))]
+    RangePaymentRequired {
+        /// URL being downloaded
+        url: String,
+        /// Request the error came from
+        context: Option<Box<ErrorContext>>,
+    },
+
+    /// A download could not be completed
+    #[error("Download of {url} failed: {reason}{}", suffix(context.as_deref()))]
+    Download {
+        /// URL being downloaded
+        url: String,
+        /// Why the download failed
+        reason: String,
+        /// Request the error came from
+        context: Option<Box<ErrorContext>>,
+    },
+
+    /// Blockchain interaction failed
+    #[error("Chain error: {0}{}", suffix(.0.context.as_deref()))]
+    Chain(ErrorMessage),
+
+    /// No chain is configured for the requested network
+    #[error(
+        "Network {network} is not configured (configured: {}){}",
+        configured.join(", "),
+        suffix(context.as_deref())
+    )]
+    UnknownNetwork {
+        /// Network that was requested
+        network: String,
+        /// Names of the configured chains
+        configured: Vec<String>,
+        /// Request the error came from
+        context: Option<Box<ErrorContext>>,
+    },
+
+    /// Contract call reverted
+    #[error("Call to {contract} reverted: {reason}{}", suffix(context.as_deref()))]
+    ContractReverted {
+        /// Address of the called contract
+        contract: String,
+        /// Decoded `Error(string)` message, panic code or custom error selector
+        reason: String,
+        /// Raw revert data as `0x`-prefixed hex, empty if the node returned none
+        data: String,
+        /// Request the error came from
+        context: Option<Box<ErrorContext>>,
+    },
+
+    /// Invalid client configuration
+    #[error("Configuration error: {0}{}", suffix(.0.context.as_deref()))]
+    Config(ErrorMessage),
+
+    /// Configuration failed validation, with every problem found
+    #[error(
+        "Invalid configuration: {}",
+        .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; ")
+    )]
+    InvalidConfig(Vec<ConfigIssue>),
+
+    /// Cache backend failure
+    #[error("Cache error: {0}{}", suffix(.0.context.as_deref()))]
+    Cache(ErrorMessage),
+
+    /// Encoding or decoding failure
+    #[error("Serialization error: {0}{}", suffix(.0.context.as_deref()))]
+    Serialization(ErrorMessage),
+
+    /// Stored record written by a newer, unsupported schema
+    #[error(
+        "Unsupported schema version {found}, newest supported is {supported}{}",
+        suffix(context.as_deref())
+    )]
+    UnsupportedSchemaVersion {
+        /// Version found in the record
+        found: u32,
+        /// Newest version this release can read
+        supported: u32,
+        /// Request the error came from
+        context: Option<Box<ErrorContext>>,
+    },
+
+    /// I/O failure, e.g. while writing an export
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// Request exceeded its timeout
+    #[error("Request to {0} timed out after {1:?}{}", suffix(.0.context.as_deref()))]
+    Timeout(ErrorMessage, Duration),
+
+    /// Batch request not completed before the batch's total deadline
+    #[error("Batch deadline exceeded")]
+    BatchDeadlineExceeded,
+
+    /// Operation attempted on a closed client
+    #[error("Client has been closed")]
+    ClientClosed,
+
+    /// Unexpected internal failure
+    #[error("Internal error: {0}{}", suffix(.0.context.as_deref()))]
+    Internal(ErrorMessage),
+}
+
+/// Message of an [`Error`] variant, with the request it came from.
+///
+/// Dereferences to the message, which is what it displays; the context is
+/// displayed by the error.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct ErrorMessage {
+    message: String,
+    context: Option<Box<ErrorContext>>,
+}
+
+impl ErrorMessage {
+    /// Returns the message.
+    pub fn as_str(&self) -> &str {
+        &self.message
+    }
+
+    /// Returns the request the message came from, if known.
+    pub fn context(&self) -> Option<&ErrorContext> {
+        self.context.as_deref()
+    }
+}
+
+impl Deref for ErrorMessage {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for ErrorMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl fmt::Debug for ErrorMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.context {
+            Some(context) => f
+                .debug_struct("ErrorMessage")
+                .field("message", &self.message)
+                .field("context", context)
+                .finish(),
+            None => fmt::Debug::fmt(&self.message, f),
+        }
+    }
+}
+
+impl From<String> for ErrorMessage {
+    fn from(message: String) -> Self {
+        Self { message, context: None }
+    }
+}
+
+impl From<&str> for ErrorMessage {
+    fn from(message: &str) -> Self {
+        message.to_string().into()
+    }
+}
+
+impl From<ErrorMessage> for String {
+    fn from(message: ErrorMessage) -> Self {
+        message.message
+    }
+}
+
+impl PartialEq<str> for ErrorMessage {
+    fn eq(&self, other: &str) -> bool {
+        self.message == other
+    }
+}
+
+impl PartialEq<&str> for ErrorMessage {
+    fn eq(&self, other: &&str) -> bool {
+        self.message == *other
+    }
+}
+
+/// Request an [`Error`] came from, attached by [`Error::with_context`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ErrorContext {
+    /// URL of the request, as given by the caller
+    pub url: Option<String>,
+    /// Correlation ID of the request
+    pub request_id: Option<String>,
+    /// Number of times the request had been sent when it failed, 0 if it
+    /// failed before being sent
+    pub attempt: u32,
+    /// Whether the request had been sent with a signed payment, which the
+    /// server may have settled even though the request failed
+    pub payment_attempted: bool,
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(url) = &self.url {
+            write!(f, "url {url}, ")?;
+        }
+        if let Some(request_id) = &self.request_id {
+            write!(f, "request {request_id}, ")?;
+        }
+        write!(f, "attempt {}", self.attempt)?;
+        if self.payment_attempted {
+            write!(f, ", payment attempted")?;
+        }
+        Ok(())
+    }
+}
+
+/// Formats `context` as the suffix of an error message.
+fn suffix(context: Option<&ErrorContext>) -> String {
+    context.map(|context| format!(" ({context})")).unwrap_or_default()
+}
+
+impl Error {
+    /// Attaches `context`, replacing any context already attached.
+    ///
+    /// Does nothing on the variants that cannot record one.
+    pub fn with_context(mut self, context: ErrorContext) -> Self {
+        if let Some(slot) = self.context_slot() {
+            *slot = Some(Box::new(context));
+        }
+        self
+    }
+
+    /// Returns where the variant stores its context.
+    fn context_slot(&mut self) -> Option<&mut Option<Box<ErrorContext>>> {
+        match self {
+            Error::Network(message)
+            | Error::Payment(message)
+            | Error::Auth(message)
+            | Error::PriceOracleUnavailable(message)
+            | Error::Chain(message)
+            | Error::Config(message)
+            | Error::Cache(message)
+            | Error::Serialization(message)
+            | Error::Timeout(message, _)
+            | Error::Internal(message) => Some(&mut message.context),
+            Error::Dns { context, .. }
+            | Error::InvalidUrl { context, .. }
+            | Error::HostNotAllowed { context, .. }
+            | Error::NoWalletForDomain { context, .. }
+            | Error::PaymentRequired { context, .. }
+            | Error::InvalidPaymentRequirements { context, .. }
+            | Error::IntegrityCheckFailed { context, .. }
+            | Error::RangePaymentRequired { context, .. }
+            | Error::Download { context, .. }
+            | Error::UnknownNetwork { context, .. }
+            | Error::ContractReverted { context, .. }
+            | Error::UnsupportedSchemaVersion { context, .. } => Some(context),
+            Error::InvalidConfig(_) | Error::Io(_) | Error::BatchDeadlineExceeded | Error::ClientClosed => None,
+        }
+    }
+
+    /// Returns the request the error came from, if known.
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            Error::Network(message)
+            | Error::Payment(message)
+            | Error::Auth(message)
+            | Error::PriceOracleUnavailable(message)
+            | Error::Chain(message)
+            | Error::Config(message)
+            | Error::Cache(message)
+            | Error::Serialization(message)
+            | Error::Timeout(message, _)
+            | Error::Internal(message) => message.context(),
+            Error::Dns { context, .. }
+            | Error::InvalidUrl { context, .. }
+            | Error::HostNotAllowed { context, .. }
+            | Error::NoWalletForDomain { context, .. }
+            | Error::PaymentRequired { context, .. }
+            | Error::InvalidPaymentRequirements { context, .. }
+            | Error::IntegrityCheckFailed { context, .. }
+            | Error::RangePaymentRequired { context, .. }
+            | Error::Download { context, .. }
+            | Error::UnknownNetwork { context, .. }
+            | Error::ContractReverted { context, .. }
+            | Error::UnsupportedSchemaVersion { context, .. } => context.as_deref(),
+            Error::InvalidConfig(_) | Error::Io(_) | Error::BatchDeadlineExceeded | Error::ClientClosed => None,
+        }
+    }
+
+    /// URL of the request the error came from.
+    pub fn url(&self) -> Option<&str> {
+        self.context().and_then(|context| context.url.as_deref())
+    }
+
+    /// Correlation ID of the request the error came from.
+    pub fn request_id(&self) -> Option<&str> {
+        self.context().and_then(|context| context.request_id.as_deref())
+    }
+
+    /// Number of times the request had been sent when it failed, 0 if
+    /// unknown or it failed before being sent.
+    pub fn attempt(&self) -> u32 {
+        self.context().map_or(0, |context| context.attempt)
+    }
+
+    /// Whether the request had been sent with a signed payment.
+    pub fn payment_attempted(&self) -> bool {
+        self.context().is_some_and(|context| context.payment_attempted)
+    }
+
+    /// Converts an error of a `reqwest` request sent with `timeout`.
+    ///
+    /// Maps timeouts to [`Error::Timeout`] carrying `timeout`, undecodable
+    /// bodies to [`Error::Serialization`] and everything else to
+    /// [`Error::Network`].
+    ///
+    /// ```rust,no_run
+    /// # use std::time::Duration;
+    /// # use v402_client::Error;
+    /// # async fn fetch() -> Result<(), Error> {
+    /// let timeout = Duration::from_secs(5);
+    /// let http = reqwest::Client::builder().timeout(timeout).build()?;
+    /// let status = http
+    ///     .get("https://api.example.com/status")
+    ///     .send()
+    ///     .await
+    ///     .map_err(|e| Error::from_reqwest(e, timeout))?
+    ///     .status();
+    /// println!("{status}");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_reqwest(err: reqwest::Error, timeout: Duration) -> Self {
+        if let Some(dns) = dns_error(&err) {
+            return dns;
+        }
+        if let Some(refused) = crate::hosts::redirect_refused(&err) {
+            return refused;
+        }
+
+        let url = err.url().map(ToString::to_string).unwrap_or_default();
+        if err.is_timeout() {
+            Error::Timeout(url.into(), timeout)
+        } else if err.is_decode() {
+            Error::Serialization(err.to_string().into())
+        } else {
+            Error::Network(err.to_string().into())
+        }
+    }
+}
+
+// Keeps the conversions shown in the module docs working
+const _: fn() = || {
+    fn assert_error<E: std::error::Error + Send + Sync + 'static>() {}
+    assert_error::<Error>();
+};
+
+impl From<reqwest::Error> for Error {
+    /// Converts like [`Error::from_reqwest`].
+    ///
+    /// `reqwest` does not report the timeout it applied, so timeouts carry a
+    /// zero duration; use [`Error::from_reqwest`] to record it.
+    fn from(err: reqwest::Error) -> Self {
+        Error::from_reqwest(err, Duration::ZERO)
+    }
+}
+
+/// Returns [`Error::Dns`] if a request failed because its host could not be resolved.
+///
+/// Connection errors only describe their cause in text, so the source chain
+/// is searched for the `dns error` raised by the connector.
+pub(crate) fn dns_error(err: &reqwest::Error) -> Option<Error> {
+    const MARKER: &str = "dns error";
+
+    let mut source = std::error::Error::source(err);
+    while let Some(cause) = source {
+        let message = cause.to_string();
+        if let Some(index) = message.find(MARKER) {
+            let reason = message[index + MARKER.len()..].trim_start_matches(':').trim();
+            return Some(Error::Dns {
+                host: err.url().and_then(|url| url.host_str()).unwrap_or_default().to_string(),
+                reason: if reason.is_empty() { message.clone() } else { reason.to_string() },
+                context: None,
+            });
+        }
+        source = cause.source();
+    }
+    None
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Serialization(err.to_string().into())
+    }
+}
+
+#[cfg(feature = "anyhow")]
+impl From<anyhow::Error> for Error {
+    /// Recovers an [`Error`] carried by `err`, or wraps `err` in
+    /// [`Error::Internal`] with its whole chain of causes.
+    ///
+    /// ```rust
+    /// use v402_client::Error;
+    ///
+    /// fn load() -> anyhow::Result<u32> {
+    ///     let port: u32 = "80x".parse()?;
+    ///     Ok(port)
+    /// }
+    ///
+    /// fn configure() -> v402_client::Result<u32> {
+    ///     Ok(load()?)
+    /// }
+    ///
+    /// assert!(matches!(configure(), Err(Error::Internal(_))));
+    ///
+    /// // Errors of this crate come back as they went in
+    /// let err: Error = anyhow::Error::from(Error::ClientClosed).into();
+    /// assert!(matches!(err, Error::ClientClosed));
+    /// ```
+    fn from(err: anyhow::Error) -> Self {
+        match err.downcast::<Error>() {
+            Ok(err) => err,
+            Err(err) => Error::Internal(format!("{:#}", err).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn timeouts_carry_the_applied_timeout() {
+        let server = MockServer::start().await;
+        Mock::given(wiremock::matchers::any())
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(5)))
+            .mount(&server)
+            .await;
+
+        let timeout = Duration::from_millis(50);
+        let http = reqwest::Client::builder().timeout(timeout).build().unwrap();
+        let error = http.get(server.uri()).send().await.unwrap_err();
+        assert!(matches!(Error::from_reqwest(error, timeout), Error::Timeout(_, applied) if applied == timeout));
+
+        let client = crate::Client::builder().timeout(timeout).build().await.unwrap();
+        let error = client.get(server.uri()).await.unwrap_err();
+        assert!(matches!(error, Error::Timeout(_, applied) if applied == timeout), "{error:?}");
+    }
+}
@@ -0,0 +1,604 @@
+//! Read-only contract calls on EVM chains.
+//!
+//! [`EthereumChain`] calls `view` functions given their human-readable
+//! signature, ABI-encoding the arguments and decoding the results as
+//! [`Token`]s, and checks token balances for content gated on holding an
+//! ERC-20, ERC-721 or ERC-1155 token rather than on per-request payments.
+//! Calls that revert fail with [`Error::ContractReverted`] carrying the
+//! decoded revert reason.
+//!
+//! Integers are limited to 128 bits; larger values fail to decode.
+//!
+//! ```rust
+//! use v402_client::ethereum::{AbiFunction, Token};
+//!
+//! let function = AbiFunction::parse("function balanceOf(address owner) view returns (uint256)")?;
+//! let calldata = function.encode_call(&[Token::Address("0x1111111111111111111111111111111111111111".into())])?;
+//! assert_eq!(hex::encode(&calldata[..4]), "70a08231");
+//!
+//! let mut output = [0u8; 32];
+//! output[31] = 42;
+//! assert_eq!(function.decode_output(&output)?, Token::Uint(42));
+//! # Ok::<(), v402_client::Error>(())
+//! ```
+
+use crate::{
+    chains::ChainManager,
+    crypto,
+    error::{Error, Result},
+};
+use std::sync::Arc;
+
+/// Size of an ABI word in bytes.
+const WORD: usize = 32;
+
+/// A value passed to or returned by a contract function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    /// `address`, as `0x`-prefixed hex; decoded addresses are lowercase
+    Address(String),
+    /// `uint8` to `uint256`
+    Uint(u128),
+    /// `int8` to `int256`
+    Int(i128),
+    /// `bool`
+    Bool(bool),
+    /// `bytes`
+    Bytes(Vec<u8>),
+    /// `bytes1` to `bytes32`
+    FixedBytes(Vec<u8>),
+    /// `string`
+    String(String),
+    /// `T[]` or `T[k]`
+    Array(Vec<Token>),
+    /// `(T1,T2,...)`, also the results of a function with several outputs
+    Tuple(Vec<Token>),
+}
+
+impl Token {
+    /// Returns the value of a `Uint` token.
+    pub fn as_uint(&self) -> Option<u128> {
+        match self {
+            Self::Uint(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+/// Type of a function parameter or result.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParamType {
+    /// `address`
+    Address,
+    /// `uint<bits>`
+    Uint(usize),
+    /// `int<bits>`
+    Int(usize),
+    /// `bool`
+    Bool,
+    /// `bytes`
+    Bytes,
+    /// `bytes<len>`
+    FixedBytes(usize),
+    /// `string`
+    String,
+    /// `T[]`
+    Array(Box<ParamType>),
+    /// `T[len]`
+    FixedArray(Box<ParamType>, usize),
+    /// `(T1,T2,...)`
+    Tuple(Vec<ParamType>),
+}
+
+impl ParamType {
+    /// Parses a Solidity type such as `uint256`, `address[]` or `(bool,bytes32)`.
+    pub fn parse(name: &str) -> Result<Self> {
+        let name = name.trim();
+        let invalid = || Error::Chain(format!("Unsupported ABI type {:?}", name).into());
+
+        if let Some(element) = name.strip_suffix(']') {
+            let open = element.rfind('[').ok_or_else(invalid)?;
+            let inner = Box::new(Self::parse(&element[..open])?);
+            return match &element[open + 1..] {
+                "" => Ok(Self::Array(inner)),
+                len => Ok(Self::FixedArray(inner, len.parse().map_err(|_| invalid())?)),
+            };
+        }
+        if let Some(fields) = name.strip_prefix('(').and_then(|rest| rest.strip_suffix(')')) {
+            return split_params(fields)?.iter().map(|field| Self::parse(field)).collect::<Result<_>>().map(Self::Tuple);
+        }
+
+        let bits = |digits: &str| match digits {
+            "" => Some(256),
+            digits => digits.parse().ok().filter(|bits| *bits > 0 && *bits <= 256 && bits % 8 == 0),
+        };
+        match name {
+            "address" => Ok(Self::Address),
+            "bool" => Ok(Self::Bool),
+            "bytes" => Ok(Self::Bytes),
+            "string" => Ok(Self::String),
+            _ => {
+                if let Some(digits) = name.strip_prefix("uint") {
+                    bits(digits).map(Self::Uint).ok_or_else(invalid)
+                } else if let Some(digits) = name.strip_prefix("int") {
+                    bits(digits).map(Self::Int).ok_or_else(invalid)
+                } else if let Some(digits) = name.strip_prefix("bytes") {
+                    digits
+                        .parse()
+                        .ok()
+                        .filter(|len| (1..=WORD).contains(len))
+                        .map(Self::FixedBytes)
+                        .ok_or_else(invalid)
+                } else {
+                    Err(invalid())
+                }
+            }
+        }
+    }
+
+    /// Returns the canonical name used in function signatures, e.g. `uint256`.
+    pub fn canonical(&self) -> String {
+        match self {
+            Self::Address => "address".to_string(),
+            Self::Uint(bits) => format!("uint{}", bits),
+            Self::Int(bits) => format!("int{}", bits),
+            Self::Bool => "bool".to_string(),
+            Self::Bytes => "bytes".to_string(),
+            Self::FixedBytes(len) => format!("bytes{}", len),
+            Self::String => "string".to_string(),
+            Self::Array(inner) => format!("{}[]", inner.canonical()),
+            Self::FixedArray(inner, len) => format!("{}[{}]", inner.canonical(), len),
+            Self::Tuple(fields) => format!("({})", fields.iter().map(Self::canonical).collect::<Vec<_>>().join(",")),
+        }
+    }
+
+    /// Returns `true` if values of the type are encoded out of line.
+    fn is_dynamic(&self) -> bool {
+        match self {
+            Self::Bytes | Self::String | Self::Array(_) => true,
+            Self::FixedArray(inner, _) => inner.is_dynamic(),
+            Self::Tuple(fields) => fields.iter().any(Self::is_dynamic),
+            _ => false,
+        }
+    }
+
+    /// Size of the type in the head of an encoding.
+    fn head_size(&self) -> usize {
+        match self {
+            _ if self.is_dynamic() => WORD,
+            Self::FixedArray(inner, len) => inner.head_size() * len,
+            Self::Tuple(fields) => fields.iter().map(Self::head_size).sum(),
+            _ => WORD,
+        }
+    }
+}
+
+/// A contract function parsed from its human-readable signature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AbiFunction {
+    /// Function name
+    pub name: String,
+
+    /// Parameter types
+    pub inputs: Vec<ParamType>,
+
+    /// Result types
+    pub outputs: Vec<ParamType>,
+}
+
+impl AbiFunction {
+    /// Parses a signature such as `balanceOf(address) returns (uint256)`.
+    ///
+    /// A leading `function`, parameter names, data locations and modifiers
+    /// such as `view` or `external` are accepted and ignored.
+    pub fn parse(signature: &str) -> Result<Self> {
+        let invalid = |reason: &str| Error::Chain(format!("Invalid function signature {:?}: {}", signature, reason).into());
+
+        let rest = signature.trim();
+        let rest = rest.strip_prefix("function ").unwrap_or(rest).trim_start();
+        let open = rest.find('(').ok_or_else(|| invalid("missing parameter list"))?;
+        let name = rest[..open].trim();
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(invalid("missing or invalid name"));
+        }
+        let close = matching_paren(rest, open).ok_or_else(|| invalid("unbalanced parentheses"))?;
+        let inputs = parse_params(&rest[open + 1..close])?;
+
+        let rest = rest[close + 1..].trim();
+        let outputs = match rest.find("returns") {
+            Some(index) => {
+                let returns = rest[index + "returns".len()..].trim();
+                let close = returns
+                    .starts_with('(')
+                    .then(|| matching_paren(returns, 0))
+                    .flatten()
+                    .ok_or_else(|| invalid("invalid return types"))?;
+                parse_params(&returns[1..close])?
+            }
+            None => Vec::new(),
+        };
+
+        Ok(Self {
+            name: name.to_string(),
+            inputs,
+            outputs,
+        })
+    }
+
+    /// Returns the canonical signature, e.g. `balanceOf(address)`.
+    pub fn signature(&self) -> String {
+        let inputs: Vec<_> = self.inputs.iter().map(ParamType::canonical).collect();
+        format!("{}({})", self.name, inputs.join(","))
+    }
+
+    /// Returns the 4-byte selector of the function.
+    pub fn selector(&self) -> [u8; 4] {
+        crypto::function_selector(&self.signature())
+    }
+
+    /// Encodes a call of the function with `args`.
+    pub fn encode_call(&self, args: &[Token]) -> Result<Vec<u8>> {
+        let mut calldata = self.selector().to_vec();
+        calldata.extend(encode(&self.inputs, args)?);
+        Ok(calldata)
+    }
+
+    /// Decodes the data returned by a call: the result itself for a single
+    /// output, otherwise a [`Token::Tuple`] of the results.
+    pub fn decode_output(&self, data: &[u8]) -> Result<Token> {
+        let mut tokens = decode(&self.outputs, data)?;
+        Ok(if tokens.len() == 1 { tokens.remove(0) } else { Token::Tuple(tokens) })
+    }
+}
+
+/// ABI-encodes `tokens` as values of `types`.
+pub fn encode(types: &[ParamType], tokens: &[Token]) -> Result<Vec<u8>> {
+    if types.len() != tokens.len() {
+        return Err(Error::Chain(format!("Expected {} ABI values, got {}", types.len(), tokens.len()).into()));
+    }
+
+    let head_size: usize = types.iter().map(ParamType::head_size).sum();
+    let mut head = Vec::with_capacity(head_size);
+    let mut tail = Vec::new();
+    for (kind, token) in types.iter().zip(tokens) {
+        let encoded = encode_token(kind, token)?;
+        if kind.is_dynamic() {
+            head.extend(crypto::uint_word((head_size + tail.len()) as u128));
+            tail.extend(encoded);
+        } else {
+            head.extend(encoded);
+        }
+    }
+    head.extend(tail);
+    Ok(head)
+}
+
+/// ABI-decodes values of `types` from `data`.
+pub fn decode(types: &[ParamType], data: &[u8]) -> Result<Vec<Token>> {
+    decode_at(types, data, 0)
+}
+
+/// Encodes one value, without the offset of dynamic types.
+fn encode_token(kind: &ParamType, token: &Token) -> Result<Vec<u8>> {
+    let out_of_range = || Error::Chain(format!("ABI value {:?} does not fit {}", token, kind.canonical()).into());
+
+    Ok(match (kind, token) {
+        (ParamType::Address, Token::Address(address)) => crypto::address_word(&crypto::parse_address(address)?).to_vec(),
+        (ParamType::Uint(bits), Token::Uint(value)) => {
+            if *bits < 128 && *value >> bits != 0 {
+                return Err(out_of_range());
+            }
+            crypto::uint_word(*value).to_vec()
+        }
+        (ParamType::Int(bits), Token::Int(value)) => {
+            if *bits < 128 {
+                let limit = 1i128 << (bits - 1);
+                if *value < -limit || *value >= limit {
+                    return Err(out_of_range());
+                }
+            }
+            let mut word = if *value < 0 { [0xff; WORD] } else { [0; WORD] };
+            word[16..].copy_from_slice(&value.to_be_bytes());
+            word.to_vec()
+        }
+        (ParamType::Bool, Token::Bool(value)) => crypto::uint_word(u128::from(*value)).to_vec(),
+        (ParamType::FixedBytes(len), Token::FixedBytes(bytes)) => {
+            if bytes.len() != *len {
+                return Err(out_of_range());
+            }
+            let mut word = [0u8; WORD];
+            word[..bytes.len()].copy_from_slice(bytes);
+            word.to_vec()
+        }
+        (ParamType::Bytes, Token::Bytes(bytes)) => encode_bytes(bytes),
+        (ParamType::String, Token::String(text)) => encode_bytes(text.as_bytes()),
+        (ParamType::Array(inner), Token::Array(items)) => {
+            let mut encoded = crypto::uint_word(items.len() as u128).to_vec();
+            encoded.extend(encode(&vec![(**inner).clone(); items.len()], items)?);
+            encoded
+        }
+        (ParamType::FixedArray(inner, len), Token::Array(items)) => {
+            if items.len() != *len {
+                return Err(out_of_range());
+            }
+            encode(&vec![(**inner).clone(); *len], items)?
+        }
+        (ParamType::Tuple(fields), Token::Tuple(items)) => encode(fields, items)?,
+        _ => return Err(Error::Chain(format!("ABI value {:?} is not a {}", token, kind.canonical()).into())),
+    })
+}
+
+/// Encodes `bytes` or `string` contents: the length, then the data padded to whole words.
+fn encode_bytes(bytes: &[u8]) -> Vec<u8> {
+    let mut encoded = crypto::uint_word(bytes.len() as u128).to_vec();
+    encoded.extend(bytes);
+    encoded.resize(WORD + (bytes.len() + WORD - 1) / WORD * WORD, 0);
+    encoded
+}
+
+/// Decodes values of `types` whose encoding starts at `base`.
+fn decode_at(types: &[ParamType], data: &[u8], base: usize) -> Result<Vec<Token>> {
+    let mut head = base;
+    let mut tokens = Vec::with_capacity(types.len());
+    for kind in types {
+        let token = if kind.is_dynamic() {
+            let offset = read_length(data, head)?;
+            decode_token(kind, data, base.checked_add(offset).ok_or_else(truncated)?)?
+        } else {
+            decode_token(kind, data, head)?
+        };
+        tokens.push(token);
+        head += kind.head_size();
+    }
+    Ok(tokens)
+}
+
+/// Decodes one value whose encoding starts at `at`.
+fn decode_token(kind: &ParamType, data: &[u8], at: usize) -> Result<Token> {
+    let word = read_word(data, at)?;
+    Ok(match kind {
+        ParamType::Address => Token::Address(format!("0x{}", hex::encode(&word[12..]))),
+        ParamType::Uint(_) => Token::Uint(crypto::word_to_uint(word)?),
+        ParamType::Int(_) => {
+            let sign = if word[16] & 0x80 == 0 { 0 } else { 0xff };
+            if word[..16].iter().any(|byte| *byte != sign) {
+                return Err(Error::Chain("ABI word is not a 128-bit signed integer".to_string().into()));
+            }
+            let mut low = [0u8; 16];
+            low.copy_from_slice(&word[16..]);
+            Token::Int(i128::from_be_bytes(low))
+        }
+        ParamType::Bool => match crypto::word_to_uint(word)? {
+            0 => Token::Bool(false),
+            1 => Token::Bool(true),
+            _ => return Err(Error::Chain("ABI word is not a bool".to_string().into())),
+        },
+        ParamType::FixedBytes(len) => Token::FixedBytes(word[..*len].to_vec()),
+        ParamType::Bytes => Token::Bytes(read_bytes(data, at)?.to_vec()),
+        ParamType::String => Token::String(
+            String::from_utf8(read_bytes(data, at)?.to_vec())
+                .map_err(|_| Error::Chain("ABI string is not valid UTF-8".to_string().into()))?,
+        ),
+        ParamType::Array(inner) => {
+            let len = read_length(data, at)?;
+            // Every element takes at least one word, which bounds the allocation
+            if len > data.len() / WORD {
+                return Err(truncated());
+            }
+            Token::Array(decode_at(&vec![(**inner).clone(); len], data, at + WORD)?)
+        }
+        ParamType::FixedArray(inner, len) => Token::Array(decode_at(&vec![(**inner).clone(); *len], data, at)?),
+        ParamType::Tuple(fields) => Token::Tuple(decode_at(fields, data, at)?),
+    })
+}
+
+/// Reads the word at `at`.
+fn read_word(data: &[u8], at: usize) -> Result<&[u8]> {
+    at.checked_add(WORD)
+        .and_then(|end| data.get(at..end))
+        .ok_or_else(truncated)
+}
+
+/// Reads a length or offset word.
+fn read_length(data: &[u8], at: usize) -> Result<usize> {
+    usize::try_from(crypto::word_to_uint(read_word(data, at)?)?).map_err(|_| truncated())
+}
+
+/// Reads the contents of `bytes` or `string` whose length word is at `at`.
+fn read_bytes(data: &[u8], at: usize) -> Result<&[u8]> {
+    let len = read_length(data, at)?;
+    let start = at + WORD;
+    start
+        .checked_add(len)
+        .and_then(|end| data.get(start..end))
+        .ok_or_else(truncated)
+}
+
+fn truncated() -> Error {
+    Error::Chain("ABI data is truncated or has an invalid offset".to_string().into())
+}
+
+/// Parses a comma-separated parameter list, dropping names and data locations.
+fn parse_params(list: &str) -> Result<Vec<ParamType>> {
+    split_params(list)?
+        .iter()
+        .map(|param| {
+            // The type is everything up to the first space outside parentheses
+            let mut depth = 0usize;
+            let end = param
+                .char_indices()
+                .find(|(_, c)| {
+                    match c {
+                        '(' => depth += 1,
+                        ')' => depth = depth.saturating_sub(1),
+                        _ => {}
+                    }
+                    depth == 0 && c.is_whitespace()
+                })
+                .map_or(param.len(), |(index, _)| index);
+            ParamType::parse(&param[..end])
+        })
+        .collect()
+}
+
+/// Splits a parameter list at commas outside parentheses.
+fn split_params(list: &str) -> Result<Vec<&str>> {
+    if list.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut params = Vec::new();
+    let (mut depth, mut start) = (0usize, 0);
+    for (index, c) in list.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth = depth
+                    .checked_sub(1)
+                    .ok_or_else(|| Error::Chain(format!("Unbalanced parentheses in {:?}", list).into()))?
+            }
+            ',' if depth == 0 => {
+                params.push(list[start..index].trim());
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    params.push(list[start..].trim());
+    Ok(params)
+}
+
+/// Returns the index of the parenthesis closing the one at `open`.
+fn matching_paren(text: &str, open: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    for (index, c) in text[open..].char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(open + index);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// A token contract whose balance gates access.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenContract {
+    /// Fungible token; the balance is in its smallest unit
+    Erc20(String),
+    /// NFT collection; the balance is the number of tokens held
+    Erc721(String),
+    /// Multi-token contract; the balance is of the token with `id`
+    Erc1155 {
+        /// Contract address
+        address: String,
+        /// Token ID
+        id: u128,
+    },
+}
+
+impl TokenContract {
+    /// Address of the contract.
+    pub fn address(&self) -> &str {
+        match self {
+            Self::Erc20(address) | Self::Erc721(address) | Self::Erc1155 { address, .. } => address,
+        }
+    }
+}
+
+/// Entitlement granted by holding a token, checked with
+/// [`Client::check_onchain_access`](crate::Client::check_onchain_access).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OnchainAccessRule {
+    /// Network the token lives on
+    pub network: String,
+
+    /// Token contract to hold
+    pub contract: TokenContract,
+
+    /// Minimum balance granting access
+    pub min_balance: u128,
+
+    /// Address that must hold the token; the client's wallet on `network` if unset
+    pub owner: Option<String>,
+}
+
+impl OnchainAccessRule {
+    /// Creates a rule granting access to holders of at least `min_balance`
+    /// of `contract` on `network`.
+    pub fn new<N: Into<String>>(network: N, contract: TokenContract, min_balance: u128) -> Self {
+        Self {
+            network: network.into(),
+            contract,
+            min_balance,
+            owner: None,
+        }
+    }
+
+    /// Checks the balance of `owner` instead of the client's wallet.
+    pub fn owner<S: Into<String>>(mut self, owner: S) -> Self {
+        self.owner = Some(owner.into());
+        self
+    }
+}
+
+/// Read-only access to the contracts of one EVM network.
+#[derive(Debug, Clone)]
+pub struct EthereumChain {
+    chain_manager: Arc<ChainManager>,
+    network: String,
+}
+
+impl EthereumChain {
+    /// Creates a handle for `network`, which must be a configured EVM chain.
+    pub fn new<N: Into<String>>(chain_manager: Arc<ChainManager>, network: N) -> Result<Self> {
+        let network = network.into();
+        if !chain_manager.chain(&network)?.chain_type.is_evm() {
+            return Err(Error::Chain(format!("{} is not an EVM chain", network).into()));
+        }
+        Ok(Self { chain_manager, network })
+    }
+
+    /// Name of the network.
+    pub fn network(&self) -> &str {
+        &self.network
+    }
+
+    /// Calls the view function `abi_fn` of `contract` with `args`, see
+    /// [`AbiFunction::parse`] and [`AbiFunction::decode_output`].
+    ///
+    /// Fails with [`Error::ContractReverted`] if the call reverts.
+    pub async fn call_view(&self, contract: &str, abi_fn: &str, args: &[Token]) -> Result<Token> {
+        let function = AbiFunction::parse(abi_fn)?;
+        let calldata = function.encode_call(args)?;
+        let output = self.chain_manager.call_view(&self.network, contract, &calldata).await?;
+        function.decode_output(&output)
+    }
+
+    /// Returns the balance of `owner` in `contract`.
+    pub async fn token_balance(&self, owner: &str, contract: &TokenContract) -> Result<u128> {
+        let owner = Token::Address(owner.to_string());
+        let balance = match contract {
+            TokenContract::Erc20(address) | TokenContract::Erc721(address) => {
+                self.call_view(address, "balanceOf(address) returns (uint256)", &[owner]).await?
+            }
+            TokenContract::Erc1155 { address, id } => {
+                self.call_view(address, "balanceOf(address,uint256) returns (uint256)", &[owner, Token::Uint(*id)])
+                    .await?
+            }
+        };
+        balance
+            .as_uint()
+            .ok_or_else(|| Error::Chain(format!("Unexpected balance {:?}", balance).into()))
+    }
+
+    /// Returns `true` if `owner` holds at least `min` of `contract`.
+    pub async fn has_token_balance(&self, owner: &str, contract: &TokenContract, min: u128) -> Result<bool> {
+        Ok(self.token_balance(owner, contract).await? >= min)
+    }
+}
@@ -0,0 +1,162 @@
+//! Client event bus.
+//!
+//! Notable state changes are published as [`ClientEvent`]s to every
+//! subscriber obtained from [`Client::subscribe`](crate::Client::subscribe).
+//! Events are delivered on a bounded broadcast channel: a subscriber that
+//! falls too far behind misses the oldest events and receives
+//! [`RecvError::Lagged`](tokio::sync::broadcast::error::RecvError::Lagged).
+
+use crate::{audit::AuditEntry, config::PriceJumpAction};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+/// Number of events buffered for slow subscribers.
+const EVENT_CAPACITY: usize = 256;
+
+/// Event published by the client.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum ClientEvent {
+    /// A chain failed its health checks and is excluded from payment selection.
+    ChainQuarantined {
+        /// Name of the chain
+        chain: String,
+
+        /// Consecutive failed checks
+        consecutive_failures: u32,
+
+        /// Reason the last check failed
+        reason: String,
+    },
+
+    /// A quarantined chain passed a health check and is selectable again.
+    ChainRecovered {
+        /// Name of the chain
+        chain: String,
+    },
+
+    /// A payment lifecycle event.
+    Payment(PaymentEvent),
+
+    /// A change to the client was recorded in its audit log, see [`audit`](crate::audit).
+    Audit(AuditEntry),
+}
+
+/// Event concerning a payment made by the client.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "payment_event", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum PaymentEvent {
+    /// A settlement transaction has had no receipt for longer than
+    /// [`Config::stuck_payment_threshold`](crate::Config::stuck_payment_threshold).
+    ///
+    /// Reported once per transaction.
+    PendingPaymentStuck {
+        /// Settlement transaction hash
+        tx_hash: String,
+
+        /// Time since the payment was settled
+        age: Duration,
+    },
+
+    /// Spend to a host within the configured window reached its alert threshold.
+    SpendAlert(SpendAlert),
+
+    /// A 402 asked for far more than its resource usually costs.
+    PriceJump(PriceJump),
+
+    /// The facilitator settled, or failed to settle, a payment whose
+    /// response carried no settlement.
+    PaymentSettled {
+        /// ID of the payment in the history
+        payment_id: String,
+
+        /// Settlement transaction hash, if settled
+        transaction_hash: Option<String>,
+
+        /// Whether settlement succeeded
+        success: bool,
+    },
+}
+
+/// Spend to a single host that crossed its
+/// [alert threshold](crate::config::SpendAlertConfig).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpendAlert {
+    /// Host the payments were made to
+    pub host: String,
+
+    /// URL of the payment that crossed the threshold
+    pub url: String,
+
+    /// Spend within the window, in the asset's smallest unit
+    pub spent: String,
+
+    /// Threshold that was crossed, in the asset's smallest unit
+    pub threshold: String,
+
+    /// Number of payments within the window
+    pub payments: u64,
+
+    /// Length of the window
+    pub window: Duration,
+}
+
+/// A price well above the usual price of a host and path prefix, see
+/// [`PriceJumpConfig`](crate::config::PriceJumpConfig).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PriceJump {
+    /// Host asking for payment
+    pub host: String,
+
+    /// Path prefix prices are grouped by
+    pub path_prefix: String,
+
+    /// URL of the request asking for the new price
+    pub url: String,
+
+    /// Amount asked for, in the asset's smallest unit
+    pub amount: String,
+
+    /// Median of the recent prices, in the asset's smallest unit
+    pub baseline: String,
+
+    /// Ratio of the amount to the baseline
+    pub ratio: f64,
+
+    /// Action configured for jumps
+    pub action: PriceJumpAction,
+}
+
+/// Broadcasts [`ClientEvent`]s to subscribers.
+#[derive(Debug, Clone)]
+pub(crate) struct EventBus {
+    sender: broadcast::Sender<ClientEvent>,
+}
+
+impl EventBus {
+    /// Creates a bus with no subscribers.
+    pub(crate) fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_CAPACITY);
+        Self { sender }
+    }
+
+    /// Publishes an event to current subscribers.
+    pub(crate) fn publish(&self, event: ClientEvent) {
+        // Sending only fails when nobody is subscribed
+        let _ = self.sender.send(event);
+    }
+
+    /// Returns a receiver for events published from now on.
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<ClientEvent> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
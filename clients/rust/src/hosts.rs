@@ -0,0 +1,269 @@
+//! Host allowlist and denylist enforced on every request.
+//!
+//! Patterns are matched against the host in its ASCII form: the URL parser
+//! converts internationalized names to punycode and lowercases them, and
+//! patterns are normalized the same way, so a `xn--` spelling of a host
+//! matches the same patterns as its Unicode spelling.
+
+use crate::{
+    config::Config,
+    error::{Error, Result},
+};
+use std::{fmt, net::IpAddr};
+use url::{Host, Url};
+
+/// A host pattern of [`Config::allowed_hosts`] or [`Config::denied_hosts`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum HostPattern {
+    /// The host itself, e.g. `api.example.com`
+    Exact(String),
+    /// The domain and all its subdomains, written `.example.com`
+    Suffix(String),
+    /// `*` matches any run of characters, e.g. `*.example.com` or `api-*.example.com`
+    Glob(String),
+    /// Addresses in a network, e.g. `10.0.0.0/8` or a single address
+    Network(IpAddr, u8),
+}
+
+impl HostPattern {
+    /// Parses a pattern, normalizing internationalized names to punycode.
+    fn parse(pattern: &str) -> std::result::Result<Self, String> {
+        let pattern = pattern.trim();
+        if pattern.is_empty() {
+            return Err("Host pattern is empty".to_string());
+        }
+
+        if let Some((address, prefix)) = pattern.split_once('/') {
+            let address: IpAddr = address
+                .parse()
+                .map_err(|_| format!("Invalid network address in {:?}", pattern))?;
+            let max = if address.is_ipv4() { 32 } else { 128 };
+            let prefix = prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix| *prefix <= max)
+                .ok_or_else(|| format!("Invalid prefix length in {:?}, expected 0 to {}", pattern, max))?;
+            return Ok(Self::network(address, prefix));
+        }
+        if let Ok(address) = pattern.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+            let prefix = if address.is_ipv4() { 32 } else { 128 };
+            return Ok(Self::network(address, prefix));
+        }
+
+        if let Some(domain) = pattern.strip_prefix('.') {
+            return Ok(Self::Suffix(normalize_domain(domain)?));
+        }
+        if pattern.contains('*') {
+            // Wildcards are not valid in host names, so normalize the labels around them
+            let labels = pattern
+                .trim_end_matches('.')
+                .split('.')
+                .map(|label| if label.contains('*') { Ok(label.to_ascii_lowercase()) } else { normalize_domain(label) })
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            return Ok(Self::Glob(labels.join(".")));
+        }
+        Ok(Self::Exact(normalize_domain(pattern)?))
+    }
+
+    /// Creates a network pattern, expressing IPv4-mapped IPv6 networks as IPv4.
+    fn network(address: IpAddr, prefix: u8) -> Self {
+        match canonical_ip(address) {
+            IpAddr::V4(v4) if address.is_ipv6() && prefix >= 96 => Self::Network(IpAddr::V4(v4), prefix - 96),
+            _ => Self::Network(address, prefix),
+        }
+    }
+
+    /// Returns `true` if the pattern matches `host`.
+    fn matches(&self, host: &RequestHost) -> bool {
+        match (self, host) {
+            (Self::Exact(pattern), RequestHost::Domain(domain)) => pattern == domain,
+            (Self::Suffix(pattern), RequestHost::Domain(domain)) => {
+                domain == pattern || domain.ends_with(&format!(".{}", pattern))
+            }
+            (Self::Glob(pattern), RequestHost::Domain(domain)) => glob_matches(pattern, domain),
+            (Self::Network(network, prefix), RequestHost::Ip(address)) => in_network(*address, *network, *prefix),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for HostPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Exact(host) | Self::Glob(host) => f.write_str(host),
+            Self::Suffix(domain) => write!(f, ".{}", domain),
+            Self::Network(address, prefix) => write!(f, "{}/{}", address, prefix),
+        }
+    }
+}
+
+/// Host of a request URL, normalized for matching.
+#[derive(Debug)]
+enum RequestHost {
+    Domain(String),
+    Ip(IpAddr),
+}
+
+impl RequestHost {
+    fn from_url(url: &Url) -> Option<Self> {
+        Some(match url.host()? {
+            Host::Domain(domain) => Self::Domain(domain.trim_end_matches('.').to_ascii_lowercase()),
+            Host::Ipv4(address) => Self::Ip(IpAddr::V4(address)),
+            Host::Ipv6(address) => Self::Ip(canonical_ip(IpAddr::V6(address))),
+        })
+    }
+}
+
+/// Allowed and denied hosts of a client.
+#[derive(Debug, Default)]
+pub(crate) struct HostPolicy {
+    allowed: Vec<HostPattern>,
+    denied: Vec<HostPattern>,
+}
+
+impl HostPolicy {
+    /// Builds the policy configured by `config`.
+    pub(crate) fn new(config: &Config) -> Result<Self> {
+        let parse = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|pattern| HostPattern::parse(pattern).map_err(|reason| Error::Config(reason.into())))
+                .collect::<Result<Vec<_>>>()
+        };
+        Ok(Self {
+            allowed: parse(&config.allowed_hosts)?,
+            denied: parse(&config.denied_hosts)?,
+        })
+    }
+
+    /// Returns the reason requests to `url` are refused, if they are.
+    ///
+    /// Denied patterns take precedence; with any allowed patterns, hosts
+    /// matching none of them are refused.
+    pub(crate) fn refusal(&self, url: &Url) -> Option<String> {
+        if self.allowed.is_empty() && self.denied.is_empty() {
+            return None;
+        }
+        let Some(host) = RequestHost::from_url(url) else {
+            return Some("URL has no host".to_string());
+        };
+
+        if let Some(pattern) = self.denied.iter().find(|pattern| pattern.matches(&host)) {
+            return Some(format!("host matches denied pattern {}", pattern));
+        }
+        if !self.allowed.is_empty() && !self.allowed.iter().any(|pattern| pattern.matches(&host)) {
+            return Some("host matches no allowed pattern".to_string());
+        }
+        None
+    }
+
+    /// Fails with [`Error::HostNotAllowed`] if requests to `url` are refused.
+    pub(crate) fn check(&self, url: &str) -> Result<()> {
+        let parsed = crate::http::parse_url(url)?;
+        match self.refusal(&parsed) {
+            Some(reason) => Err(Error::HostNotAllowed {
+                host: parsed.host_str().unwrap_or_default().to_string(),
+                reason,
+                context: None,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Error stopping a redirect to a refused host, recovered from the
+/// transport error as [`Error::HostNotAllowed`].
+#[derive(Debug)]
+pub(crate) struct RedirectRefused {
+    pub(crate) host: String,
+    pub(crate) reason: String,
+}
+
+impl fmt::Display for RedirectRefused {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "redirect to {} refused: {}", self.host, self.reason)
+    }
+}
+
+impl std::error::Error for RedirectRefused {}
+
+/// Returns [`Error::HostNotAllowed`] if a request failed because it was
+/// redirected to a refused host.
+pub(crate) fn redirect_refused(err: &reqwest::Error) -> Option<Error> {
+    let mut source = std::error::Error::source(err);
+    while let Some(cause) = source {
+        if let Some(refused) = cause.downcast_ref::<RedirectRefused>() {
+            return Some(Error::HostNotAllowed {
+                host: refused.host.clone(),
+                reason: refused.reason.clone(),
+                context: None,
+            });
+        }
+        source = cause.source();
+    }
+    None
+}
+
+/// Checks that `pattern` is a valid host pattern.
+pub(crate) fn validate_pattern(pattern: &str) -> std::result::Result<(), String> {
+    HostPattern::parse(pattern).map(|_| ())
+}
+
+/// Converts a domain to lowercase punycode, as the URL parser does for hosts.
+fn normalize_domain(domain: &str) -> std::result::Result<String, String> {
+    let domain = domain.trim_end_matches('.');
+    if domain.is_ascii() {
+        return Ok(domain.to_ascii_lowercase());
+    }
+    match Host::parse(domain) {
+        Ok(Host::Domain(ascii)) => Ok(ascii),
+        _ => Err(format!("Invalid host name {:?}", domain)),
+    }
+}
+
+/// Maps IPv4-mapped IPv6 addresses to IPv4, so `::ffff:10.0.0.1` matches `10.0.0.0/8`.
+fn canonical_ip(address: IpAddr) -> IpAddr {
+    match address {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(address, IpAddr::V4),
+        IpAddr::V4(_) => address,
+    }
+}
+
+/// Returns `true` if `address` is in the network `network/prefix`.
+fn in_network(address: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    match (address, network) {
+        (IpAddr::V4(address), IpAddr::V4(network)) => {
+            let mask = u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+            u32::from(address) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(address), IpAddr::V6(network)) => {
+            let mask = u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0);
+            u128::from(address) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+/// Matches `text` against a pattern in which `*` stands for any run of characters.
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let (pattern, text) = (pattern.as_bytes(), text.as_bytes());
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and the text position it is currently matched up to
+    let mut backtrack = None;
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == b'*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == text[t] {
+            p += 1;
+            t += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|byte| *byte == b'*')
+}
@@ -0,0 +1,677 @@
+//! HTTP transport built on `reqwest`.
+
+use crate::{
+    body::BodySource,
+    config::Config,
+    error::{dns_error, Error, Result},
+    hosts::{redirect_refused, HostPolicy, RedirectRefused},
+    types::PaymentResponse,
+};
+use bytes::Bytes;
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Resolve, Resolving};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tracing::debug;
+use url::Url;
+use uuid::Uuid;
+
+/// Headers whose values are never included in `Debug` output.
+const REDACTED_HEADERS: [&str; 4] = ["authorization", "proxy-authorization", "cookie", "x-api-key"];
+
+/// Outgoing HTTP request passed through the middleware stack.
+#[derive(Clone)]
+pub struct Request {
+    /// HTTP method
+    pub method: reqwest::Method,
+
+    /// Absolute request URL
+    pub url: String,
+
+    /// Request headers
+    pub headers: HashMap<String, String>,
+
+    /// Request body
+    pub body: Option<Vec<u8>>,
+
+    /// Body streamed from a file or reader instead of [`body`](Self::body)
+    pub(crate) stream: Option<BodySource>,
+
+    /// Correlation ID of the request (UUID v7 unless supplied by the caller)
+    pub request_id: String,
+
+    /// ID of the batch this request belongs to
+    pub batch_id: Option<String>,
+
+    /// Time the request was created
+    pub created_at: Instant,
+
+    /// Callbacks run once the request and all its retries are done
+    pub(crate) completion: Arc<Completion>,
+}
+
+/// Callbacks run when the last clone of a request is dropped, which is when
+/// the client is done with the request, its payment and its retries.
+#[derive(Default)]
+pub(crate) struct Completion {
+    callbacks: parking_lot::Mutex<Vec<Box<dyn FnOnce() + Send>>>,
+}
+
+impl Completion {
+    /// Runs `callback` once the request is done.
+    pub(crate) fn on_complete(&self, callback: impl FnOnce() + Send + 'static) {
+        self.callbacks.lock().push(Box::new(callback));
+    }
+}
+
+impl Drop for Completion {
+    fn drop(&mut self) {
+        for callback in self.callbacks.get_mut().drain(..) {
+            callback();
+        }
+    }
+}
+
+impl Request {
+    /// Creates a request for the normalized form of `url`, see [`normalize_url`].
+    pub fn new(method: reqwest::Method, url: &str) -> Result<Self> {
+        Ok(Self::with_url(method, normalize_url(url)?))
+    }
+
+    /// Creates a request for `url` with its path kept as given, e.g. with a
+    /// trailing slash. The URL is still validated.
+    pub fn new_exact(method: reqwest::Method, url: &str) -> Result<Self> {
+        parse_url(url)?;
+        Ok(Self::with_url(method, url.trim().to_string()))
+    }
+
+    fn with_url(method: reqwest::Method, url: String) -> Self {
+        Self {
+            method,
+            url,
+            headers: HashMap::new(),
+            body: None,
+            stream: None,
+            request_id: new_request_id(),
+            batch_id: None,
+            created_at: Instant::now(),
+            completion: Arc::default(),
+        }
+    }
+
+    /// Sets a header.
+    pub fn header<K: Into<String>, V: Into<String>>(mut self, name: K, value: V) -> Self {
+        self.headers.insert(name.into(), value.into());
+        self
+    }
+
+    /// Sets the request body.
+    pub fn body(mut self, body: Vec<u8>) -> Self {
+        self.body = Some(body);
+        self
+    }
+
+    /// Whether the body is streamed from a file or reader, and so not
+    /// available to middleware in [`body`](Self::body).
+    pub fn is_streamed(&self) -> bool {
+        self.stream.is_some()
+    }
+}
+
+impl fmt::Debug for Request {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let headers: HashMap<&str, &str> = self
+            .headers
+            .iter()
+            .map(|(name, value)| {
+                let redacted = REDACTED_HEADERS.iter().any(|h| name.eq_ignore_ascii_case(h));
+                (name.as_str(), if redacted { "[REDACTED]" } else { value.as_str() })
+            })
+            .collect();
+
+        f.debug_struct("Request")
+            .field("method", &self.method)
+            .field("url", &self.url)
+            .field("headers", &headers)
+            .field("body_len", &self.body.as_ref().map(Vec::len))
+            .field("stream", &self.stream)
+            .field("request_id", &self.request_id)
+            .field("batch_id", &self.batch_id)
+            .finish()
+    }
+}
+
+/// Parses an absolute `http` or `https` URL with a host.
+///
+/// Parsing lowercases the host, drops the scheme's default port and resolves
+/// `.` and `..` path segments.
+pub(crate) fn parse_url(input: &str) -> Result<Url> {
+    let invalid = |reason: String| Error::InvalidUrl {
+        input: input.to_string(),
+        reason,
+        context: None,
+    };
+
+    let url = Url::parse(input.trim()).map_err(|e| invalid(e.to_string()))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(invalid(format!("unsupported scheme {:?}, expected http or https", url.scheme())));
+    }
+    if url.host_str().map_or(true, str::is_empty) {
+        return Err(invalid("missing host".to_string()));
+    }
+    Ok(url)
+}
+
+/// Returns the normalized form of a request URL.
+///
+/// On top of the normalization done by parsing (lowercase host, no default
+/// port, resolved dot segments), the fragment and any trailing slash after
+/// a non-root path are dropped. Path case is never changed. Requests are
+/// sent to, cached under and recorded in payment history with this form.
+///
+/// ```rust
+/// assert_eq!(
+///     v402_client::normalize_url("HTTPS://Example.COM:443/a/./b/?page=2#top")?,
+///     "https://example.com/a/b?page=2"
+/// );
+/// # Ok::<(), v402_client::Error>(())
+/// ```
+pub fn normalize_url(input: &str) -> Result<String> {
+    cache_key(input, false)
+}
+
+/// Returns the cache key of a request URL: its normalized form, or with
+/// `exact_path` only the host and port normalized.
+pub(crate) fn cache_key(input: &str, exact_path: bool) -> Result<String> {
+    let mut url = parse_url(input)?;
+    url.set_fragment(None);
+    if !exact_path && url.path().len() > 1 && url.path().ends_with('/') {
+        let path = url.path().trim_end_matches('/').to_string();
+        url.set_path(&path);
+    }
+    Ok(url.to_string())
+}
+
+/// Generates a new time-ordered request ID.
+pub(crate) fn new_request_id() -> String {
+    Uuid::now_v7().to_string()
+}
+
+/// Custom DNS resolver shared by the client's HTTP connections.
+///
+/// Wraps any [`reqwest::dns::Resolve`] implementation so it can be stored in
+/// a [`Config`]; set it with [`ConfigBuilder::dns_resolver`](crate::ConfigBuilder::dns_resolver).
+#[derive(Clone)]
+pub struct DnsResolver(Arc<dyn Resolve>);
+
+impl DnsResolver {
+    /// Wraps a resolver.
+    pub fn new(resolver: Arc<dyn Resolve>) -> Self {
+        Self(resolver)
+    }
+}
+
+impl Resolve for DnsResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        self.0.resolve(name)
+    }
+}
+
+impl fmt::Debug for DnsResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DnsResolver(..)")
+    }
+}
+
+/// Applies the configured DNS resolver and static overrides to a client builder.
+pub(crate) fn with_dns(mut builder: reqwest::ClientBuilder, config: &Config) -> reqwest::ClientBuilder {
+    if let Some(resolver) = &config.dns_resolver {
+        builder = builder.dns_resolver(Arc::new(resolver.clone()));
+    }
+    for (host, addr) in &config.dns_overrides {
+        builder = builder.resolve(host, *addr);
+    }
+    builder
+}
+
+/// Maximum number of redirects followed per request, as by `reqwest` by default.
+const MAX_REDIRECTS: usize = 10;
+
+/// Follows redirects only to hosts `hosts` allows.
+pub(crate) fn redirect_policy(hosts: Arc<HostPolicy>) -> reqwest::redirect::Policy {
+    reqwest::redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() > MAX_REDIRECTS {
+            return attempt.error("too many redirects");
+        }
+        match hosts.refusal(attempt.url()) {
+            Some(reason) => {
+                let host = attempt.url().host_str().unwrap_or_default().to_string();
+                attempt.error(RedirectRefused { host, reason })
+            }
+            None => attempt.follow(),
+        }
+    })
+}
+
+/// Connection usage of the HTTP client.
+///
+/// `reqwest` does not expose its pool, so usage is counted around each
+/// request and the idle count is an estimate.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConnectionPoolStats {
+    /// Connections currently carrying a request, from sending it until its body has been read
+    pub active: u32,
+
+    /// Connections likely kept alive in the pool, estimated from peak usage
+    pub idle: u32,
+
+    /// Maximum number of idle connections kept per host
+    pub max: u32,
+
+    /// Active requests still waiting for response headers, including connection setup
+    pub pending: u32,
+}
+
+/// Counts connections in use by the HTTP client.
+#[derive(Debug)]
+pub(crate) struct ConnectionPool {
+    active: AtomicU32,
+    pending: AtomicU32,
+    /// Most connections in use at once, which the pool keeps around once released
+    peak: AtomicU32,
+    max: u32,
+}
+
+impl ConnectionPool {
+    fn new(max: usize) -> Self {
+        Self {
+            active: AtomicU32::new(0),
+            pending: AtomicU32::new(0),
+            peak: AtomicU32::new(0),
+            max: u32::try_from(max).unwrap_or(u32::MAX),
+        }
+    }
+
+    /// Marks a connection as in use until the returned guard is dropped.
+    fn acquire(self: &Arc<Self>) -> ConnectionGuard {
+        let active = self.active.fetch_add(1, Ordering::Relaxed) + 1;
+        self.pending.fetch_add(1, Ordering::Relaxed);
+        self.peak.fetch_max(active, Ordering::Relaxed);
+        ConnectionGuard {
+            pool: Arc::clone(self),
+            pending: true,
+        }
+    }
+
+    /// Returns the current connection usage.
+    pub(crate) fn stats(&self) -> ConnectionPoolStats {
+        let active = self.active.load(Ordering::Relaxed);
+        ConnectionPoolStats {
+            active,
+            idle: self.peak.load(Ordering::Relaxed).min(self.max).saturating_sub(active),
+            max: self.max,
+            pending: self.pending.load(Ordering::Relaxed).min(active),
+        }
+    }
+}
+
+/// Releases a connection counted by [`ConnectionPool::acquire`], also when
+/// the request is cancelled.
+#[derive(Debug)]
+struct ConnectionGuard {
+    pool: Arc<ConnectionPool>,
+    pending: bool,
+}
+
+impl ConnectionGuard {
+    fn headers_received(&mut self) {
+        if std::mem::take(&mut self.pending) {
+            self.pool.pending.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.headers_received();
+        self.pool.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Pooled HTTP client used for all outgoing requests.
+#[derive(Debug)]
+pub(crate) struct HttpClient {
+    inner: reqwest::Client,
+    pool: Arc<ConnectionPool>,
+    timeout: Duration,
+    hosts: Arc<HostPolicy>,
+}
+
+impl HttpClient {
+    /// Creates the HTTP client from the client configuration.
+    pub(crate) async fn new(config: &Arc<Config>) -> Result<Self> {
+        let hosts = Arc::new(HostPolicy::new(config)?);
+        let inner = with_dns(reqwest::Client::builder(), config)
+            .redirect(redirect_policy(Arc::clone(&hosts)))
+            .timeout(config.timeout)
+            .pool_max_idle_per_host(config.max_connections)
+            .user_agent(crate::USER_AGENT)
+            .build()
+            .map_err(|e| Error::Network(format!("Failed to build HTTP client: {}", e).into()))?;
+
+        Ok(Self {
+            inner,
+            pool: Arc::new(ConnectionPool::new(config.max_connections)),
+            timeout: config.timeout,
+            hosts,
+        })
+    }
+
+    /// Fails with [`Error::HostNotAllowed`] if the host policy refuses `url`.
+    pub(crate) fn check_host(&self, url: &str) -> Result<()> {
+        self.hosts.check(url)
+    }
+
+    /// Returns the connection usage counters, shared with the metrics collector.
+    pub(crate) fn connection_pool(&self) -> Arc<ConnectionPool> {
+        Arc::clone(&self.pool)
+    }
+
+    /// Returns the current connection usage.
+    pub(crate) fn connection_pool_stats(&self) -> ConnectionPoolStats {
+        self.pool.stats()
+    }
+
+    /// Sends a request and buffers the response.
+    pub(crate) async fn send(&self, mut request: Request) -> Result<PaymentResponse> {
+        let (response, connection) = self.start(&mut request, None).await?;
+        let mut head = response_head(request, &response);
+        head.body = response
+            .bytes()
+            .await
+            .map_err(|e| Error::Network(format!("Failed to read response body from {}: {}", head.url, e).into()))?;
+        drop(connection);
+
+        debug!(url = %head.url, status = head.status, bytes = head.body.len(), "Received response");
+
+        Ok(head)
+    }
+
+    /// Sends a request without reading the response body, which is left in
+    /// `slot`; the returned response has an empty body.
+    pub(crate) async fn send_streaming(&self, mut request: Request, slot: &StreamSlot) -> Result<PaymentResponse> {
+        let (response, connection) = self.start(&mut request, Some(slot.timeout)).await?;
+        let head = response_head(request, &response);
+        debug!(url = %head.url, status = head.status, "Received response headers");
+
+        *slot.body.lock() = Some(BodyStream {
+            url: head.url.clone(),
+            timeout: slot.timeout,
+            response,
+            _connection: connection,
+        });
+        Ok(head)
+    }
+
+    /// Sends a request, taking its body, and returns the response once its headers arrive.
+    ///
+    /// `timeout` replaces the configured timeout for this request.
+    ///
+    /// Every request goes through here, so this is where the host policy is
+    /// enforced, whichever path built the request.
+    async fn start(
+        &self,
+        request: &mut Request,
+        timeout: Option<Duration>,
+    ) -> Result<(reqwest::Response, ConnectionGuard)> {
+        self.check_host(&request.url)?;
+        let mut builder = self.inner.request(request.method.clone(), &request.url);
+        for (name, value) in &request.headers {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+        if let Some(body) = request.body.take() {
+            builder = builder.body(body);
+        }
+        if let Some(stream) = &request.stream {
+            let (body, length) = stream.open().await?;
+            let has_length = request.headers.keys().any(|name| name.eq_ignore_ascii_case("content-length"));
+            if let Some(length) = length.filter(|_| !has_length) {
+                builder = builder.header(reqwest::header::CONTENT_LENGTH, length);
+            }
+            builder = builder.body(body);
+        }
+        if let Some(timeout) = timeout {
+            builder = builder.timeout(timeout);
+        }
+
+        let mut connection = self.pool.acquire();
+        let response = builder.send().await.map_err(|e| {
+            if e.is_timeout() {
+                Error::Timeout(request.url.clone().into(), timeout.unwrap_or(self.timeout))
+            } else if let Some(dns) = dns_error(&e) {
+                dns
+            } else if let Some(refused) = redirect_refused(&e) {
+                refused
+            } else {
+                Error::Network(format!("Request to {} failed: {}", request.url, e).into())
+            }
+        })?;
+        connection.headers_received();
+
+        Ok((response, connection))
+    }
+
+    /// Resolves the host of `url` and opens a pooled connection to it with
+    /// a `HEAD` request, negotiating HTTP/2 where the server supports it.
+    ///
+    /// Any response counts as success, since only the connection is kept.
+    pub(crate) async fn preconnect(&self, url: &str) -> Result<()> {
+        self.check_host(url)?;
+        self.inner.head(url).send().await.map_err(|e| {
+            if e.is_timeout() {
+                Error::Timeout(url.to_string().into(), self.timeout)
+            } else if let Some(dns) = dns_error(&e) {
+                dns
+            } else if let Some(refused) = redirect_refused(&e) {
+                refused
+            } else {
+                Error::Network(format!("Connection to {} failed: {}", url, e).into())
+            }
+        })?;
+        Ok(())
+    }
+
+    /// Checks that the HTTP client is usable.
+    pub(crate) async fn health_check(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Builds the client response for `request` from the status and headers of
+/// `response`, with an empty body.
+fn response_head(request: Request, response: &reqwest::Response) -> PaymentResponse {
+    let status = response.status().as_u16();
+    PaymentResponse {
+        url: response.url().to_string(),
+        status,
+        headers: response
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect(),
+        body: Bytes::new(),
+        payment_made: false,
+        payment_amount: None,
+        payment_asset: None,
+        network: None,
+        transaction_hash: None,
+        payer: None,
+        request_id: request.request_id,
+        batch_id: request.batch_id,
+        timestamp: Utc::now(),
+        payment_timing: None,
+        not_modified: status == 304,
+        requirements: None,
+        dry_run: false,
+        content_hash: None,
+        payment_hints: None,
+    }
+}
+
+/// Receives the unread body of a response sent with [`HttpClient::send_streaming`].
+#[derive(Debug)]
+pub(crate) struct StreamSlot {
+    /// Time allowed for the request, including reading its body
+    timeout: Duration,
+    body: parking_lot::Mutex<Option<BodyStream>>,
+}
+
+impl StreamSlot {
+    /// Creates an empty slot for a request allowed `timeout`.
+    pub(crate) fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            body: parking_lot::Mutex::new(None),
+        }
+    }
+
+    /// Takes the body of the last response sent into the slot.
+    pub(crate) fn take(&self) -> Option<BodyStream> {
+        self.body.lock().take()
+    }
+}
+
+/// Body of a response read as it arrives; the connection counts as active until it is dropped.
+#[derive(Debug)]
+pub(crate) struct BodyStream {
+    url: String,
+    timeout: Duration,
+    response: reqwest::Response,
+    _connection: ConnectionGuard,
+}
+
+impl BodyStream {
+    /// Returns the next chunk of the body, or `None` once it is complete.
+    pub(crate) async fn chunk(&mut self) -> Result<Option<Bytes>> {
+        self.response.chunk().await.map_err(|e| {
+            if e.is_timeout() {
+                Error::Timeout(self.url.clone().into(), self.timeout)
+            } else {
+                Error::Network(format!("Failed to read response body from {}: {}", self.url, e).into())
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::{matchers::any, Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn send_refuses_denied_hosts() {
+        let server = MockServer::start().await;
+        Mock::given(any()).respond_with(ResponseTemplate::new(200)).expect(0).mount(&server).await;
+
+        let config = Arc::new(Config {
+            denied_hosts: vec!["127.0.0.1".to_string()],
+            ..Config::default()
+        });
+        let client = HttpClient::new(&config).await.unwrap();
+        let request = Request::new(reqwest::Method::HEAD, &server.uri()).unwrap();
+
+        let err = client.send(request).await.unwrap_err();
+        assert!(matches!(err, Error::HostNotAllowed { ref host, .. } if host == "127.0.0.1"), "{err}");
+    }
+
+    #[test]
+    fn debug_output_redacts_credentials() {
+        let mut request = Request::new(reqwest::Method::GET, "https://api.example.com/data").unwrap();
+        request.headers.insert("Authorization".to_string(), "Bearer tok_secret".to_string());
+        request.headers.insert("x-api-key".to_string(), "key_secret".to_string());
+        request.headers.insert("Accept".to_string(), "application/json".to_string());
+
+        let debug = format!("{:?}", request);
+        assert!(!debug.contains("secret"), "{debug}");
+        assert!(debug.contains("application/json"), "{debug}");
+    }
+
+    /// Resolves every host to `addr`, or fails if there is none.
+    struct FixedResolver(Option<std::net::SocketAddr>);
+
+    impl Resolve for FixedResolver {
+        fn resolve(&self, name: Name) -> Resolving {
+            let addr = self.0;
+            Box::pin(async move {
+                match addr {
+                    Some(addr) => Ok(Box::new(std::iter::once(addr)) as reqwest::dns::Addrs),
+                    None => Err(format!("no record for {}", name.as_str()).into()),
+                }
+            })
+        }
+    }
+
+    async fn get(config: Config, url: &str) -> Result<PaymentResponse> {
+        let client = HttpClient::new(&Arc::new(config)).await.unwrap();
+        client.send(Request::new(reqwest::Method::GET, url).unwrap()).await
+    }
+
+    #[tokio::test]
+    async fn static_overrides_and_custom_resolvers_reach_fake_hosts() {
+        let server = MockServer::start().await;
+        Mock::given(any()).respond_with(ResponseTemplate::new(200)).expect(2).mount(&server).await;
+        let port = server.address().port();
+
+        let config = Config::builder()
+            .resolve("canary.staging.invalid", *server.address())
+            .build()
+            .unwrap();
+        let response = get(config, &format!("http://canary.staging.invalid:{}/data", port)).await.unwrap();
+        assert_eq!(response.status, 200);
+
+        let config = Config::builder()
+            .dns_resolver(Arc::new(FixedResolver(Some(*server.address()))))
+            .build()
+            .unwrap();
+        let response = get(config, &format!("http://facilitator.staging.invalid:{}/data", port)).await.unwrap();
+        assert_eq!(response.status, 200);
+    }
+
+    #[tokio::test]
+    async fn resolution_failures_are_told_apart_from_connection_failures() {
+        let config = Config::builder().dns_resolver(Arc::new(FixedResolver(None))).build().unwrap();
+        let err = get(config, "http://facilitator.staging.invalid/data").await.unwrap_err();
+        assert!(matches!(err, Error::Dns { ref host, .. } if host == "facilitator.staging.invalid"), "{err}");
+
+        // Nothing listens on the port of a closed listener
+        let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let config = Config::builder().resolve("facilitator.staging.invalid", addr).build().unwrap();
+        let err = get(config, &format!("http://facilitator.staging.invalid:{}/data", addr.port()))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Network(_)), "{err}");
+    }
+
+    #[tokio::test]
+    async fn facilitator_checks_report_resolution_failures() {
+        let config = Config::builder()
+            .dns_resolver(Arc::new(FixedResolver(None)))
+            .facilitator_url("https://facilitator.staging.invalid")
+            .build()
+            .unwrap();
+        let Err(Error::InvalidConfig(issues)) = config.validate_deep().await else {
+            panic!("unresolvable facilitator passed the check");
+        };
+        assert_eq!(issues[0].field, "facilitator_url");
+        assert!(issues[0].message.contains("DNS resolution failed"), "{}", issues[0].message);
+    }
+}
@@ -17,7 +17,7 @@
 //! 
 //! ## Quick Start
 //! 
-//! ```rust
+//! ```rust,no_run
 //! use v402_client::{Client, Config, ChainConfig, ChainType};
 //! 
 //! #[tokio::main]
@@ -39,7 +39,7 @@
 //!         .await?;
 //! 
 //!     if response.payment_made {
-//!         println!("Paid {} for content", response.payment_amount.unwrap());
+//!         println!("Paid {} for content", response.payment_amount.as_deref().unwrap_or_default());
 //!     }
 //! 
 //!     println!("Content: {}", response.text().await?);
@@ -64,11 +64,7 @@
 //!     "https://example.com/article3",
 //! ];
 //! 
-//! let responses = client
-//!     .batch_get(&urls)
-//!     .max_concurrent(10)
-//!     .execute()
-//!     .await?;
+//! let responses = client.batch_get(&urls, 10).await?;
 //! 
 //! for (i, response) in responses.iter().enumerate() {
 //!     match response {
@@ -83,30 +79,26 @@
 //! ### Custom Middleware
 //! 
 //! ```rust
-//! use v402_client::{Client, middleware::Middleware, Request, Response};
+//! use v402_client::{Client, middleware::{Middleware, Next}, PaymentResponse, Request};
 //! use async_trait::async_trait;
 //! 
+//! #[derive(Debug)]
 //! struct AuthMiddleware {
 //!     token: String,
 //! }
 //! 
 //! #[async_trait]
 //! impl Middleware for AuthMiddleware {
-//!     async fn call(
-//!         &self,
-//!         req: Request,
-//!         next: Box<dyn Fn(Request) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Response, v402_client::Error>> + Send>> + Send>,
-//!     ) -> Result<Response, v402_client::Error> {
-//!         let mut req = req;
-//!         req.headers.insert("Authorization", format!("Bearer {}", self.token));
-//!         next(req).await
+//!     async fn handle(&self, mut req: Request, next: Next<'_>) -> v402_client::Result<PaymentResponse> {
+//!         req.headers.insert("Authorization".to_string(), format!("Bearer {}", self.token));
+//!         next.run(req).await
 //!     }
 //! }
 //! 
 //! # #[tokio::main]
 //! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
 //! let client = Client::builder()
-//!     .middleware(AuthMiddleware { token: "abc123".to_string() })
+//!     .middleware(Box::new(AuthMiddleware { token: "abc123".to_string() }))
 //!     .build()
 //!     .await?;
 //! # Ok(())
@@ -123,10 +115,18 @@
 #![forbid(unsafe_code)]
 
 // Re-export main types
-pub use client::{Client, ClientBuilder};
-pub use config::{Config, ConfigBuilder, ChainConfig, ChainType};
-pub use error::{Error, Result};
-pub use types::{PaymentResponse, PaymentHistory, PaymentStatistics, HealthStatus};
+pub use body::Body;
+pub use client::{AuditScope, BatchBuilder, BatchStream, Client, ClientBuilder, ClientRequestBuilder, WeakClient};
+pub use config::{
+    Config, ConfigBuilder, ConfigIssue, ChainConfig, ChainHealthConfig, ChainType, ClockConfig, DegradationPolicy,
+    HostSpendThreshold, PaymentRequiredBehavior, PreapproveConfig, PriceJumpAction, PriceJumpConfig, SpendAlertConfig,
+    WalletConfig, WalletRoute,
+};
+pub use chains::{NetworkId, TokenInfo, TokenRegistry};
+pub use error::{Error, ErrorContext, ErrorMessage, Result};
+pub use events::{ClientEvent, PaymentEvent, PriceJump, SpendAlert};
+pub use http::{normalize_url, ConnectionPoolStats, DnsResolver, Request};
+pub use types::{Priority, PaymentResponse, PaymentCheckResult, PaymentTiming, PaymentHistory, PaymentStatistics, PeriodStats, PeriodType, HealthStatus, HostStatistics, PrefetchReport, RequestOptions, ServiceState, TaskInfo, Validator, WalletStatistics, WarmUpReport, WarmUpResult};
 
 // Modules
 pub mod client;
@@ -138,18 +138,32 @@ pub mod payment;
 pub mod middleware;
 pub mod metrics;
 pub mod cache;
+pub mod events;
+pub mod verify;
+pub mod secrets;
+pub mod report;
+pub mod challenge;
+pub mod crypto;
+pub mod download;
+pub mod audit;
+pub mod body;
+pub mod degradation;
 
 // Internal modules
+mod hosts;
 mod http;
-mod crypto;
-mod utils;
+mod limiter;
+mod tasks;
 
 // Feature-gated modules
 #[cfg(feature = "ethereum")]
 pub mod ethereum;
 
-#[cfg(feature = "solana")]
-pub mod solana;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+
+#[cfg(feature = "testing")]
+pub mod testing;
 
 /// Version information
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
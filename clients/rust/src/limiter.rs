@@ -0,0 +1,150 @@
+//! Priority-aware limit on concurrent requests.
+//!
+//! Requests wait in one FIFO queue per [`Priority`] and freed slots go to the
+//! highest waiting priority. With aging enabled, a waiter queued longer than
+//! the aging interval competes as if it were high priority, so sustained
+//! high-priority traffic cannot starve low-priority work indefinitely.
+
+use crate::types::Priority;
+use parking_lot::Mutex;
+use std::{
+    collections::VecDeque,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::oneshot;
+
+/// A request waiting for a slot.
+#[derive(Debug)]
+struct Waiter {
+    enqueued_at: Instant,
+    wake: oneshot::Sender<()>,
+}
+
+#[derive(Debug)]
+struct LimiterState {
+    available: usize,
+    /// Waiters per priority, highest first
+    queues: [VecDeque<Waiter>; 3],
+}
+
+/// Concurrency limiter granting slots by priority.
+#[derive(Debug)]
+pub(crate) struct PriorityLimiter {
+    state: Mutex<LimiterState>,
+    aging: Option<Duration>,
+}
+
+/// A held slot, released on drop.
+#[derive(Debug)]
+pub(crate) struct Permit {
+    limiter: Arc<PriorityLimiter>,
+}
+
+impl PriorityLimiter {
+    /// Creates a limiter allowing `max_concurrent` requests at once.
+    pub(crate) fn new(max_concurrent: usize, aging: Option<Duration>) -> Self {
+        Self {
+            state: Mutex::new(LimiterState {
+                available: max_concurrent.max(1),
+                queues: Default::default(),
+            }),
+            aging,
+        }
+    }
+
+    /// Waits for a slot at the given priority.
+    pub(crate) async fn acquire(self: &Arc<Self>, priority: Priority) -> Permit {
+        let wait = {
+            let mut state = self.state.lock();
+            if state.available > 0 && state.queues.iter().all(VecDeque::is_empty) {
+                state.available -= 1;
+                None
+            } else {
+                let (wake, wait) = oneshot::channel();
+                state.queues[priority.index()].push_back(Waiter {
+                    enqueued_at: Instant::now(),
+                    wake,
+                });
+                Some(wait)
+            }
+        };
+
+        if let Some(wait) = wait {
+            let mut pending = PendingWait {
+                limiter: self,
+                wait: Some(wait),
+            };
+            if let Some(wait) = pending.wait.as_mut() {
+                let _ = wait.await;
+            }
+            pending.wait = None;
+        }
+
+        Permit { limiter: self.clone() }
+    }
+
+    /// Number of live waiters per priority: high, normal, low.
+    pub(crate) fn queue_depths(&self) -> [usize; 3] {
+        let state = self.state.lock();
+        std::array::from_fn(|priority| {
+            state.queues[priority]
+                .iter()
+                .filter(|waiter| !waiter.wake.is_closed())
+                .count()
+        })
+    }
+
+    /// Hands a freed slot to the next waiter, or returns it to the pool.
+    fn release(&self) {
+        let mut state = self.state.lock();
+        while let Some(waiter) = self.next_waiter(&mut state) {
+            // Waiters whose request was cancelled have dropped their receiver
+            if waiter.wake.send(()).is_ok() {
+                return;
+            }
+        }
+        state.available += 1;
+    }
+
+    /// Removes the waiter that should run next.
+    fn next_waiter(&self, state: &mut LimiterState) -> Option<Waiter> {
+        let now = Instant::now();
+        let level = state
+            .queues
+            .iter()
+            .enumerate()
+            .filter_map(|(level, queue)| {
+                let head = queue.front()?;
+                let aged = self.aging.is_some_and(|aging| now.duration_since(head.enqueued_at) >= aging);
+                Some(((if aged { 0 } else { level }), head.enqueued_at, level))
+            })
+            .min()
+            .map(|(_, _, level)| level)?;
+
+        state.queues[level].pop_front()
+    }
+}
+
+/// Passes on a slot granted to a waiter that was cancelled before it could take it.
+struct PendingWait<'a> {
+    limiter: &'a PriorityLimiter,
+    wait: Option<oneshot::Receiver<()>>,
+}
+
+impl Drop for PendingWait<'_> {
+    fn drop(&mut self) {
+        if let Some(mut wait) = self.wait.take() {
+            wait.close();
+            if wait.try_recv().is_ok() {
+                self.limiter.release();
+            }
+        }
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.limiter.release();
+    }
+}
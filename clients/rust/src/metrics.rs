@@ -0,0 +1,953 @@
+//! Client metrics collection.
+//!
+//! Counters and histograms are kept in atomics so recording is lock-free on
+//! the request path. Durations are tracked in log-linear histograms rather
+//! than averages so tail latency stays visible; poll them with
+//! [`MetricsCollector::snapshot`].
+//!
+//! Applications can register their own counters and gauges with
+//! [`MetricsCollector::counter`] and [`MetricsCollector::gauge`]; they are
+//! exported alongside the client's metrics under `<prefix>_user_`.
+//!
+//! Without the `metrics` feature the collector keeps its API but records
+//! nothing, as if [`MetricsConfig::enabled`](crate::config::MetricsConfig::enabled)
+//! were off.
+
+use crate::{
+    config::MetricsConfig,
+    degradation::{self, Component, Degradation},
+    error::Result,
+    http::{ConnectionPool, ConnectionPoolStats},
+    types::{PaymentResponse, PaymentTiming},
+};
+use serde::{Deserialize, Serialize};
+use parking_lot::{Mutex, RwLock};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::debug;
+use url::Url;
+
+/// Upper bounds in milliseconds of the cumulative buckets reported for export.
+const EXPORT_BUCKETS_MS: [u64; 12] = [1, 5, 10, 25, 50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000];
+
+/// Sub-buckets per power of two, as a power of two; quantiles are accurate to about 3%.
+const SUB_BUCKET_BITS: u32 = 5;
+
+/// Number of linear sub-buckets each power of two is split into.
+const SUB_BUCKETS: u64 = 1 << SUB_BUCKET_BITS;
+
+/// Longest recordable duration in microseconds (about 19 hours); longer ones are clamped.
+const MAX_MICROS: u64 = (1 << 36) - 1;
+
+/// Number of buckets covering `0..=MAX_MICROS`.
+const BUCKET_COUNT: usize = bucket_index(MAX_MICROS) + 1;
+
+/// Most distinct labels (hosts, methods) tracked per histogram family;
+/// further labels are recorded under [`OVERFLOW_LABEL`].
+const MAX_LABELS: usize = 256;
+
+/// Label recording values of labels past [`MAX_LABELS`].
+const OVERFLOW_LABEL: &str = "other";
+
+/// Collects request, cache and payment metrics for a client.
+#[derive(Debug)]
+pub struct MetricsCollector {
+    /// Whether values are recorded, shared with the custom metrics
+    enabled: Arc<AtomicBool>,
+    /// Counts the values skipped while the collector is degraded
+    degradation: Option<Arc<Degradation>>,
+    prefix: String,
+    requests_total: AtomicU64,
+    requests_failed: AtomicU64,
+    request_duration_micros: AtomicU64,
+    cache_hits: AtomicU64,
+    payments_reused: AtomicU64,
+    payments_resigned: AtomicU64,
+    preemptive_payments: AtomicU64,
+    preemptive_payment_fallbacks: AtomicU64,
+    integrity_failures: AtomicU64,
+    payment_required_encountered: AtomicU64,
+    clock_skew_retries: AtomicU64,
+    price_jumps: AtomicU64,
+    payments_live: AtomicU64,
+    payments_dry_run: AtomicU64,
+    /// Durations of all requests
+    requests: Histogram,
+    /// Request durations per HTTP method
+    requests_by_method: LabeledHistograms,
+    /// Request durations per target host
+    requests_by_host: LabeledHistograms,
+    /// Time from receiving a 402 to receiving the paid response
+    payment_latency: Histogram,
+    /// Round trip of the paid request, during which the server settles the payment
+    settlement_latency: Histogram,
+    /// Duration histograms per payment phase, in [`PaymentTiming::PHASES`] order
+    payment_phases: [Histogram; 6],
+    /// Connection usage of the client's HTTP transport
+    connection_pool: Option<Arc<ConnectionPool>>,
+    /// Counters and gauges registered by the application, by name
+    custom: RwLock<BTreeMap<String, Arc<CustomMetric>>>,
+    /// Last gas price seen per chain by gas monitors, in gwei
+    gas_prices: RwLock<BTreeMap<String, f64>>,
+}
+
+/// Kind of a user-defined metric.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CustomKind {
+    Counter,
+    Gauge,
+}
+
+impl CustomKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            CustomKind::Counter => "counter",
+            CustomKind::Gauge => "gauge",
+        }
+    }
+}
+
+/// A user-defined metric with one value per label set.
+#[derive(Debug)]
+struct CustomMetric {
+    kind: CustomKind,
+    help: String,
+    label_names: Vec<String>,
+    enabled: Arc<AtomicBool>,
+    values: Mutex<BTreeMap<Vec<String>, f64>>,
+}
+
+impl CustomMetric {
+    /// Applies `update` to the value of `labels`, ignoring label sets of the wrong length.
+    ///
+    /// Label sets past [`MAX_LABELS`] are recorded under [`OVERFLOW_LABEL`].
+    fn update(&self, labels: &[&str], update: impl FnOnce(&mut f64)) {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return;
+        }
+        if labels.len() != self.label_names.len() {
+            debug!(
+                expected = self.label_names.len(),
+                got = labels.len(),
+                "Ignoring custom metric update with wrong number of labels"
+            );
+            return;
+        }
+
+        let mut values = self.values.lock();
+        let mut key: Vec<String> = labels.iter().map(|label| label.to_string()).collect();
+        if values.len() >= MAX_LABELS && !values.contains_key(&key) {
+            key = vec![OVERFLOW_LABEL.to_string(); key.len()];
+        }
+        update(values.entry(key).or_insert(0.0));
+    }
+}
+
+/// Handle to a user-defined counter, see [`MetricsCollector::counter`].
+#[derive(Debug, Clone)]
+pub struct CounterHandle {
+    metric: Arc<CustomMetric>,
+}
+
+impl CounterHandle {
+    /// Adds `by` to the counter for `labels`, given in the order the label
+    /// names were registered. Negative amounts are ignored.
+    pub fn increment(&self, labels: &[&str], by: f64) {
+        if by >= 0.0 {
+            self.metric.update(labels, |value| *value += by);
+        }
+    }
+}
+
+/// Handle to a user-defined gauge, see [`MetricsCollector::gauge`].
+#[derive(Debug, Clone)]
+pub struct GaugeHandle {
+    metric: Arc<CustomMetric>,
+}
+
+impl GaugeHandle {
+    /// Sets the gauge for `labels`, given in the order the label names were registered.
+    pub fn set(&self, labels: &[&str], value: f64) {
+        self.metric.update(labels, |current| *current = value);
+    }
+}
+
+/// Log-linear duration histogram with microsecond resolution.
+///
+/// Values below `2 * SUB_BUCKETS` microseconds get a bucket each; above that,
+/// every power of two is split into `SUB_BUCKETS` equal buckets, in the
+/// manner of HDR histograms. All state is atomic, so recording never waits
+/// on a snapshot being taken.
+#[derive(Debug)]
+struct Histogram {
+    buckets: Box<[AtomicU64]>,
+    sum_micros: AtomicU64,
+    min_micros: AtomicU64,
+    max_micros: AtomicU64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            buckets: (0..BUCKET_COUNT).map(|_| AtomicU64::new(0)).collect(),
+            sum_micros: AtomicU64::new(0),
+            min_micros: AtomicU64::new(u64::MAX),
+            max_micros: AtomicU64::new(0),
+        }
+    }
+}
+
+/// Returns the bucket holding a duration of `micros`.
+const fn bucket_index(micros: u64) -> usize {
+    if micros < 2 * SUB_BUCKETS {
+        return micros as usize;
+    }
+    let shift = 63 - micros.leading_zeros() - SUB_BUCKET_BITS;
+    ((shift as u64 + 1) * SUB_BUCKETS + (micros >> shift) - SUB_BUCKETS) as usize
+}
+
+/// Returns the largest duration in microseconds that falls into bucket `index`.
+fn bucket_upper_bound(index: usize) -> u64 {
+    let index = index as u64;
+    if index < 2 * SUB_BUCKETS {
+        return index;
+    }
+    let shift = index / SUB_BUCKETS - 1;
+    ((SUB_BUCKETS + index % SUB_BUCKETS + 1) << shift) - 1
+}
+
+impl Histogram {
+    fn record(&self, duration: Duration) {
+        let micros = u64::try_from(duration.as_micros()).unwrap_or(u64::MAX);
+        self.buckets[bucket_index(micros.min(MAX_MICROS))].fetch_add(1, Ordering::Relaxed);
+        self.sum_micros.fetch_add(micros, Ordering::Relaxed);
+        self.min_micros.fetch_min(micros, Ordering::Relaxed);
+        self.max_micros.fetch_max(micros, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> HistogramSnapshot {
+        let counts: Vec<u64> = self.buckets.iter().map(|count| count.load(Ordering::Relaxed)).collect();
+        let count: u64 = counts.iter().sum();
+        if count == 0 {
+            return HistogramSnapshot {
+                buckets: EXPORT_BUCKETS_MS.iter().map(|bound| (*bound, 0)).collect(),
+                ..Default::default()
+            };
+        }
+
+        // Counts are read bucket by bucket while recording continues, so the
+        // extremes may be slightly ahead of the buckets; clamp to them anyway
+        let min_micros = self.min_micros.load(Ordering::Relaxed);
+        let max_micros = self.max_micros.load(Ordering::Relaxed);
+        let quantile = |q: f64| {
+            let rank = ((q * count as f64).ceil() as u64).max(1);
+            let mut cumulative = 0;
+            let index = counts
+                .iter()
+                .position(|bucket| {
+                    cumulative += bucket;
+                    cumulative >= rank
+                })
+                .unwrap_or(BUCKET_COUNT - 1);
+            bucket_upper_bound(index).clamp(min_micros.min(max_micros), max_micros)
+        };
+
+        let buckets = EXPORT_BUCKETS_MS
+            .iter()
+            .map(|bound| {
+                let last = bucket_index((bound * 1_000).min(MAX_MICROS));
+                (*bound, counts[..=last].iter().sum())
+            })
+            .collect();
+
+        HistogramSnapshot {
+            count,
+            sum_micros: self.sum_micros.load(Ordering::Relaxed),
+            min_micros,
+            max_micros,
+            p50_micros: quantile(0.5),
+            p90_micros: quantile(0.9),
+            p99_micros: quantile(0.99),
+            p999_micros: quantile(0.999),
+            buckets,
+        }
+    }
+}
+
+/// Histograms keyed by a label such as the HTTP method or host.
+///
+/// The map is only write-locked the first time a label is seen; recording
+/// and snapshotting share the read lock.
+#[derive(Debug, Default)]
+struct LabeledHistograms {
+    histograms: RwLock<HashMap<String, Arc<Histogram>>>,
+}
+
+impl LabeledHistograms {
+    fn record(&self, label: &str, duration: Duration) {
+        if let Some(histogram) = self.histograms.read().get(label) {
+            histogram.record(duration);
+            return;
+        }
+
+        let mut histograms = self.histograms.write();
+        let label = if histograms.len() < MAX_LABELS || histograms.contains_key(label) {
+            label
+        } else {
+            OVERFLOW_LABEL
+        };
+        histograms.entry(label.to_string()).or_default().record(duration);
+    }
+
+    fn snapshot(&self) -> BTreeMap<String, HistogramSnapshot> {
+        let histograms: Vec<_> = self
+            .histograms
+            .read()
+            .iter()
+            .map(|(label, histogram)| (label.clone(), Arc::clone(histogram)))
+            .collect();
+        histograms
+            .into_iter()
+            .map(|(label, histogram)| (label, histogram.snapshot()))
+            .collect()
+    }
+}
+
+/// Point-in-time copy of a duration histogram.
+///
+/// Quantiles are the upper bound of the bucket they fall into, so they
+/// overstate the true value by at most about 3%.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HistogramSnapshot {
+    /// Number of recorded durations
+    pub count: u64,
+
+    /// Sum of recorded durations in microseconds
+    pub sum_micros: u64,
+
+    /// Shortest recorded duration in microseconds
+    pub min_micros: u64,
+
+    /// Longest recorded duration in microseconds
+    pub max_micros: u64,
+
+    /// Median duration in microseconds
+    pub p50_micros: u64,
+
+    /// 90th percentile duration in microseconds
+    pub p90_micros: u64,
+
+    /// 99th percentile duration in microseconds
+    pub p99_micros: u64,
+
+    /// 99.9th percentile duration in microseconds
+    pub p999_micros: u64,
+
+    /// Cumulative counts as `(upper bound in milliseconds, count)` pairs;
+    /// durations above the last bound are only included in `count`
+    pub buckets: Vec<(u64, u64)>,
+}
+
+impl HistogramSnapshot {
+    /// Mean of the recorded durations, if any were recorded.
+    pub fn mean(&self) -> Option<Duration> {
+        (self.count > 0).then(|| Duration::from_micros(self.sum_micros / self.count))
+    }
+}
+
+/// Point-in-time copy of the collected counters.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MetricsCounters {
+    /// Total requests recorded
+    pub requests_total: u64,
+
+    /// Requests that returned an error
+    pub requests_failed: u64,
+
+    /// Cumulative request duration in microseconds
+    pub request_duration_micros: u64,
+
+    /// Responses served from the cache
+    pub cache_hits: u64,
+
+    /// Retries that reused an existing payment header
+    pub payments_reused: u64,
+
+    /// Retries that had to sign a fresh payment because the previous one would expire
+    pub payments_resigned: u64,
+
+    /// Requests paid on the first attempt using cached requirements
+    pub preemptive_payments: u64,
+
+    /// Preemptive payments rejected with a 402, falling back to the two-step flow
+    pub preemptive_payment_fallbacks: u64,
+
+    /// Responses rejected by a response verifier
+    pub integrity_failures: u64,
+
+    /// 402 responses received, whether or not they were paid
+    pub payment_required_encountered: u64,
+
+    /// Paid requests re-signed with a recalibrated clock after a validity window rejection
+    pub clock_skew_retries: u64,
+
+    /// Payments asking for far more than their resource usually costs
+    pub price_jumps: u64,
+
+    /// Payments settled on chain
+    pub payments_live: u64,
+
+    /// Payments only simulated on dry-run chains
+    pub payments_dry_run: u64,
+
+    /// High-priority requests waiting for a concurrency slot
+    pub queued_high: u64,
+
+    /// Normal-priority requests waiting for a concurrency slot
+    pub queued_normal: u64,
+
+    /// Low-priority requests waiting for a concurrency slot
+    pub queued_low: u64,
+
+    /// Duration histograms per payment phase, keyed by phase name
+    pub payment_phases: BTreeMap<String, HistogramSnapshot>,
+}
+
+/// Point-in-time copy of all collected metrics, including latency histograms.
+///
+/// Taking a snapshot does not block requests from being recorded, so it can
+/// be polled as often as needed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MetricsSnapshot {
+    /// Counter values and payment phase histograms
+    pub counters: MetricsCounters,
+
+    /// Durations of all requests
+    pub requests: HistogramSnapshot,
+
+    /// Request durations keyed by HTTP method
+    pub requests_by_method: BTreeMap<String, HistogramSnapshot>,
+
+    /// Request durations keyed by target host; hosts past the first 256 are
+    /// grouped under `other`
+    pub requests_by_host: BTreeMap<String, HistogramSnapshot>,
+
+    /// Time from receiving a 402 to receiving the paid response
+    pub payment_latency: HistogramSnapshot,
+
+    /// Round trip of the paid request, during which the server verifies and
+    /// settles the payment with its facilitator
+    pub settlement_latency: HistogramSnapshot,
+
+    /// Connection usage of the HTTP transport, if attached to a client
+    pub connection_pool: Option<ConnectionPoolStats>,
+}
+
+/// A collector that records nothing is the no-op version.
+impl Component for MetricsCollector {
+    const NAME: &'static str = degradation::METRICS;
+
+    type Config = MetricsConfig;
+
+    fn create(config: &MetricsConfig) -> Result<Self> {
+        Self::new(config)
+    }
+
+    fn noop(config: &MetricsConfig) -> Self {
+        Self::build(config, false)
+    }
+}
+
+impl MetricsCollector {
+    /// Creates a collector from the metrics configuration.
+    pub fn new(config: &MetricsConfig) -> Result<Self> {
+        Ok(Self::build(config, config.enabled && cfg!(feature = "metrics")))
+    }
+
+    /// Creates a collector from the metrics configuration, recording only if `enabled`.
+    fn build(config: &MetricsConfig, enabled: bool) -> Self {
+        Self {
+            enabled: Arc::new(AtomicBool::new(enabled)),
+            degradation: None,
+            prefix: config.prefix.clone(),
+            requests_total: AtomicU64::new(0),
+            requests_failed: AtomicU64::new(0),
+            request_duration_micros: AtomicU64::new(0),
+            cache_hits: AtomicU64::new(0),
+            payments_reused: AtomicU64::new(0),
+            payments_resigned: AtomicU64::new(0),
+            preemptive_payments: AtomicU64::new(0),
+            preemptive_payment_fallbacks: AtomicU64::new(0),
+            integrity_failures: AtomicU64::new(0),
+            payment_required_encountered: AtomicU64::new(0),
+            clock_skew_retries: AtomicU64::new(0),
+            price_jumps: AtomicU64::new(0),
+            payments_live: AtomicU64::new(0),
+            payments_dry_run: AtomicU64::new(0),
+            requests: Histogram::default(),
+            requests_by_method: LabeledHistograms::default(),
+            requests_by_host: LabeledHistograms::default(),
+            payment_latency: Histogram::default(),
+            settlement_latency: Histogram::default(),
+            payment_phases: Default::default(),
+            connection_pool: None,
+            custom: RwLock::new(BTreeMap::new()),
+            gas_prices: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    /// Registers a counter exported as `<prefix>_user_<name>`, with one
+    /// series per combination of `labels`.
+    ///
+    /// Registering an existing name returns a handle to the existing
+    /// metric, keeping its help text and labels. Characters Prometheus does
+    /// not allow in names are replaced with `_`.
+    ///
+    /// ```rust
+    /// use v402_client::{config::MetricsConfig, metrics::MetricsCollector};
+    ///
+    /// let metrics = MetricsCollector::new(&MetricsConfig::default())?;
+    /// let articles = metrics.counter("articles_read", "Articles read by section", &["section"]);
+    /// articles.increment(&["science"], 1.0);
+    ///
+    /// assert!(metrics
+    ///     .export_prometheus()
+    ///     .contains("v402_user_articles_read{section=\"science\"} 1"));
+    /// # Ok::<(), v402_client::Error>(())
+    /// ```
+    pub fn counter(&self, name: &str, help: &str, labels: &[&str]) -> CounterHandle {
+        CounterHandle {
+            metric: self.register(CustomKind::Counter, name, help, labels),
+        }
+    }
+
+    /// Registers a gauge exported as `<prefix>_user_<name>`, with one series
+    /// per combination of `labels`.
+    ///
+    /// Names are handled as for [`counter`](Self::counter).
+    pub fn gauge(&self, name: &str, help: &str, labels: &[&str]) -> GaugeHandle {
+        GaugeHandle {
+            metric: self.register(CustomKind::Gauge, name, help, labels),
+        }
+    }
+
+    /// Returns the user-defined metric named `name`, registering it if needed.
+    fn register(&self, kind: CustomKind, name: &str, help: &str, labels: &[&str]) -> Arc<CustomMetric> {
+        let name: String = name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == ':' { c } else { '_' })
+            .collect();
+        let metric = self.custom.write().entry(name.clone()).or_insert_with(|| {
+            Arc::new(CustomMetric {
+                kind,
+                help: help.to_string(),
+                label_names: labels.iter().map(|label| label.to_string()).collect(),
+                enabled: self.enabled.clone(),
+                values: Mutex::new(BTreeMap::new()),
+            })
+        })
+        .clone();
+
+        if metric.kind != kind {
+            debug!(name = %name, registered = metric.kind.as_str(), "Custom metric already registered as another kind");
+        }
+        metric
+    }
+
+    /// Reports connection usage of the HTTP transport alongside the other metrics.
+    pub(crate) fn with_connection_pool(mut self, pool: Arc<ConnectionPool>) -> Self {
+        self.connection_pool = Some(pool);
+        self
+    }
+
+    /// Counts the values skipped while the collector is degraded in `degradation`.
+    pub(crate) fn with_degradation(mut self, degradation: Arc<Degradation>) -> Self {
+        self.degradation = Some(degradation);
+        self
+    }
+
+    /// Returns whether values are recorded.
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Starts or stops recording, including the custom metrics.
+    pub(crate) fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Returns whether values are recorded, counting the value as skipped if
+    /// the collector is degraded.
+    fn recording(&self) -> bool {
+        let enabled = self.enabled.load(Ordering::Relaxed);
+        if !enabled {
+            if let Some(degradation) = &self.degradation {
+                degradation.record_operation(degradation::METRICS);
+            }
+        }
+        enabled
+    }
+
+    /// Records the gas price of `chain` seen by a gas monitor.
+    pub(crate) fn set_gas_price(&self, chain: &str, gwei: f64) {
+        if self.recording() {
+            self.gas_prices.write().insert(chain.to_string(), gwei);
+        }
+    }
+
+    /// Records a completed request to `url`.
+    pub fn record_request(&self, method: &str, url: &str, result: &Result<PaymentResponse>, duration: Duration) {
+        if !self.recording() {
+            return;
+        }
+
+        self.requests_total.fetch_add(1, Ordering::Relaxed);
+        if result.is_err() {
+            self.requests_failed.fetch_add(1, Ordering::Relaxed);
+        }
+        self.request_duration_micros
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+
+        self.requests.record(duration);
+        self.requests_by_method.record(method, duration);
+        let host = Url::parse(url).ok().and_then(|url| url.host_str().map(str::to_string));
+        self.requests_by_host.record(host.as_deref().unwrap_or("unknown"), duration);
+
+        debug!(method, duration_ms = duration.as_millis() as u64, "Recorded request metrics");
+    }
+
+    /// Records a response served from the cache.
+    pub fn increment_cache_hits(&self) {
+        if self.recording() {
+            self.cache_hits.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Records a rate-limit retry that reused the original payment header.
+    pub fn increment_payments_reused(&self) {
+        if self.recording() {
+            self.payments_reused.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Records a rate-limit retry that required signing a new payment.
+    pub fn increment_payments_resigned(&self) {
+        if self.recording() {
+            self.payments_resigned.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Records a request paid up front with cached requirements.
+    pub fn increment_preemptive_payments(&self) {
+        if self.recording() {
+            self.preemptive_payments.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Records a preemptive payment that fell back to the two-step flow.
+    pub fn increment_preemptive_payment_fallbacks(&self) {
+        if self.recording() {
+            self.preemptive_payment_fallbacks.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Records a response rejected by a response verifier.
+    pub fn increment_integrity_failures(&self) {
+        if self.recording() {
+            self.integrity_failures.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Records a 402 response, whether or not it is paid.
+    pub fn increment_payment_required(&self) {
+        if self.recording() {
+            self.payment_required_encountered.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Records a paid request retried after the clock was recalibrated.
+    pub fn increment_clock_skew_retries(&self) {
+        if self.recording() {
+            self.clock_skew_retries.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Records a payment far above the usual price of its resource.
+    pub fn increment_price_jumps(&self) {
+        if self.recording() {
+            self.price_jumps.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Records a completed payment, live or simulated on a dry-run chain.
+    pub fn increment_payments(&self, dry_run: bool) {
+        if self.recording() {
+            let counter = if dry_run { &self.payments_dry_run } else { &self.payments_live };
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Records the duration of each phase of a payment, along with its
+    /// end-to-end and settlement latency.
+    pub fn record_payment_timing(&self, timing: &PaymentTiming) {
+        if !self.recording() {
+            return;
+        }
+
+        // Phases that did not run, e.g. settlement without a settlement header, are skipped
+        for (histogram, (_, duration)) in self.payment_phases.iter().zip(timing.phases()) {
+            if !duration.is_zero() {
+                histogram.record(duration);
+            }
+        }
+        self.payment_latency.record(timing.total() - timing.process_settlement);
+        self.settlement_latency.record(timing.paid_request);
+
+        debug!(total_ms = timing.total().as_millis() as u64, "Recorded payment timing");
+    }
+
+    /// Returns the current counter values.
+    pub fn counters(&self) -> MetricsCounters {
+        MetricsCounters {
+            requests_total: self.requests_total.load(Ordering::Relaxed),
+            requests_failed: self.requests_failed.load(Ordering::Relaxed),
+            request_duration_micros: self.request_duration_micros.load(Ordering::Relaxed),
+            cache_hits: self.cache_hits.load(Ordering::Relaxed),
+            payments_reused: self.payments_reused.load(Ordering::Relaxed),
+            payments_resigned: self.payments_resigned.load(Ordering::Relaxed),
+            preemptive_payments: self.preemptive_payments.load(Ordering::Relaxed),
+            preemptive_payment_fallbacks: self.preemptive_payment_fallbacks.load(Ordering::Relaxed),
+            integrity_failures: self.integrity_failures.load(Ordering::Relaxed),
+            payment_required_encountered: self.payment_required_encountered.load(Ordering::Relaxed),
+            clock_skew_retries: self.clock_skew_retries.load(Ordering::Relaxed),
+            price_jumps: self.price_jumps.load(Ordering::Relaxed),
+            payments_live: self.payments_live.load(Ordering::Relaxed),
+            payments_dry_run: self.payments_dry_run.load(Ordering::Relaxed),
+            payment_phases: PaymentTiming::PHASES
+                .iter()
+                .zip(&self.payment_phases)
+                .map(|(phase, histogram)| (phase.to_string(), histogram.snapshot()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the counters together with the request and payment latency histograms.
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            counters: self.counters(),
+            requests: self.requests.snapshot(),
+            requests_by_method: self.requests_by_method.snapshot(),
+            requests_by_host: self.requests_by_host.snapshot(),
+            payment_latency: self.payment_latency.snapshot(),
+            settlement_latency: self.settlement_latency.snapshot(),
+            connection_pool: self.connection_pool.as_ref().map(|pool| pool.stats()),
+        }
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format.
+    ///
+    /// Metric names start with the configured prefix and durations are
+    /// reported in seconds.
+    pub fn export_prometheus(&self) -> String {
+        let snapshot = self.snapshot();
+        let counters = &snapshot.counters;
+        let prefix = &self.prefix;
+        let mut out = String::new();
+
+        let counter_values = [
+            ("requests_total", "Requests recorded", counters.requests_total),
+            ("requests_failed_total", "Requests that returned an error", counters.requests_failed),
+            ("cache_hits_total", "Responses served from the cache", counters.cache_hits),
+            ("payments_reused_total", "Retries that reused a payment header", counters.payments_reused),
+            ("payments_resigned_total", "Retries that signed a fresh payment", counters.payments_resigned),
+            ("preemptive_payments_total", "Requests paid up front", counters.preemptive_payments),
+            (
+                "preemptive_payment_fallbacks_total",
+                "Preemptive payments that fell back to the two-step flow",
+                counters.preemptive_payment_fallbacks,
+            ),
+            (
+                "integrity_failures_total",
+                "Responses rejected by a response verifier",
+                counters.integrity_failures,
+            ),
+            (
+                "payment_required_encountered_total",
+                "402 responses received, paid or not",
+                counters.payment_required_encountered,
+            ),
+            (
+                "clock_skew_retries_total",
+                "Paid requests retried after recalibrating the clock",
+                counters.clock_skew_retries,
+            ),
+            (
+                "price_jumps_total",
+                "Payments asking for far more than their resource usually costs",
+                counters.price_jumps,
+            ),
+        ];
+        for (name, help, value) in counter_values {
+            write_metric(&mut out, prefix, name, "counter", help, &[(String::new(), value)]);
+        }
+        write_metric(
+            &mut out,
+            prefix,
+            "payments_total",
+            "counter",
+            "Payments made, by live or dry-run mode",
+            &[
+                (labels(&[("mode", "live")]), counters.payments_live),
+                (labels(&[("mode", "dry_run")]), counters.payments_dry_run),
+            ],
+        );
+
+        if let Some(pool) = snapshot.connection_pool {
+            let gauges = [
+                ("http_pool_active_connections", "Connections carrying a request", pool.active),
+                ("http_pool_idle_connections", "Estimated idle pooled connections", pool.idle),
+                ("http_pool_max_idle_connections", "Idle connections kept per host", pool.max),
+                ("http_pool_pending_requests", "Requests waiting for response headers", pool.pending),
+            ];
+            for (name, help, value) in gauges {
+                write_metric(&mut out, prefix, name, "gauge", help, &[(String::new(), value)]);
+            }
+        }
+
+        let gas_prices: Vec<_> = self
+            .gas_prices
+            .read()
+            .iter()
+            .map(|(chain, gwei)| (labels(&[("chain", chain)]), *gwei))
+            .collect();
+        if !gas_prices.is_empty() {
+            write_metric(&mut out, prefix, "chain_gas_price_gwei", "gauge", "Gas price per chain in gwei", &gas_prices);
+        }
+
+        let requests: Vec<_> = snapshot
+            .requests_by_method
+            .iter()
+            .map(|(method, histogram)| (labels(&[("method", method)]), histogram))
+            .collect();
+        write_histogram(&mut out, prefix, "request_duration_seconds", "Request duration by method", &requests);
+
+        let hosts: Vec<_> = snapshot
+            .requests_by_host
+            .iter()
+            .map(|(host, histogram)| (labels(&[("host", host)]), histogram))
+            .collect();
+        write_histogram(&mut out, prefix, "host_request_duration_seconds", "Request duration by host", &hosts);
+
+        write_histogram(
+            &mut out,
+            prefix,
+            "payment_latency_seconds",
+            "Time from a 402 response to the paid response",
+            &[(String::new(), &snapshot.payment_latency)],
+        );
+        write_histogram(
+            &mut out,
+            prefix,
+            "settlement_latency_seconds",
+            "Round trip of paid requests, including settlement",
+            &[(String::new(), &snapshot.settlement_latency)],
+        );
+
+        let phases: Vec<_> = counters
+            .payment_phases
+            .iter()
+            .map(|(phase, histogram)| (labels(&[("phase", phase)]), histogram))
+            .collect();
+        write_histogram(&mut out, prefix, "payment_phase_duration_seconds", "Duration of payment phases", &phases);
+
+        let user_prefix = format!("{}_user", prefix);
+        let custom: Vec<_> = self.custom.read().iter().map(|(name, metric)| (name.clone(), metric.clone())).collect();
+        for (name, metric) in custom {
+            let samples: Vec<_> = metric
+                .values
+                .lock()
+                .iter()
+                .map(|(values, value)| {
+                    let pairs: Vec<_> = metric
+                        .label_names
+                        .iter()
+                        .map(String::as_str)
+                        .zip(values.iter().map(String::as_str))
+                        .collect();
+                    (labels(&pairs), *value)
+                })
+                .collect();
+            write_metric(&mut out, &user_prefix, &name, metric.kind.as_str(), &metric.help, &samples);
+        }
+
+        out
+    }
+
+    /// Prefix applied to exported metric names.
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// Flushes and shuts down the collector.
+    pub async fn close(&self) -> Result<()> {
+        debug!(counters = ?self.counters(), "Closing metrics collector");
+        Ok(())
+    }
+}
+
+/// Formats Prometheus labels, escaping their values.
+fn labels(pairs: &[(&str, &str)]) -> String {
+    pairs
+        .iter()
+        .map(|(name, value)| {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+            format!("{}=\"{}\"", name, value)
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Writes a counter or gauge with one sample per label set.
+fn write_metric<V: std::fmt::Display>(
+    out: &mut String,
+    prefix: &str,
+    name: &str,
+    kind: &str,
+    help: &str,
+    samples: &[(String, V)],
+) {
+    let _ = writeln!(out, "# HELP {}_{} {}", prefix, name, help);
+    let _ = writeln!(out, "# TYPE {}_{} {}", prefix, name, kind);
+    for (labels, value) in samples {
+        let _ = writeln!(out, "{}_{}{} {}", prefix, name, braced(labels), value);
+    }
+}
+
+/// Writes a histogram with one series per label set, converting to seconds.
+fn write_histogram(out: &mut String, prefix: &str, name: &str, help: &str, series: &[(String, &HistogramSnapshot)]) {
+    let _ = writeln!(out, "# HELP {}_{} {}", prefix, name, help);
+    let _ = writeln!(out, "# TYPE {}_{} histogram", prefix, name);
+    for (labels, histogram) in series {
+        let separator = if labels.is_empty() { "" } else { "," };
+        for (bound_ms, count) in &histogram.buckets {
+            let le = *bound_ms as f64 / 1_000.0;
+            let _ = writeln!(out, "{}_{}_bucket{{{}{}le=\"{}\"}} {}", prefix, name, labels, separator, le, count);
+        }
+        let _ = writeln!(out, "{}_{}_bucket{{{}{}le=\"+Inf\"}} {}", prefix, name, labels, separator, histogram.count);
+        let _ = writeln!(out, "{}_{}_sum{} {}", prefix, name, braced(labels), histogram.sum_micros as f64 / 1_000_000.0);
+        let _ = writeln!(out, "{}_{}_count{} {}", prefix, name, braced(labels), histogram.count);
+    }
+}
+
+/// Wraps non-empty labels in braces.
+fn braced(labels: &str) -> String {
+    if labels.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", labels)
+    }
+}
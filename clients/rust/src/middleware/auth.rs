@@ -0,0 +1,324 @@
+//! Bearer token authentication.
+//!
+//! [`BearerAuthMiddleware`] attaches an `Authorization: Bearer` header to
+//! every request, caching the token from a [`CredentialProvider`] until it
+//! nears expiry. Tokens inside the refresh margin are still used while a
+//! fresh one is fetched in the background, and a `401` forces a refresh and
+//! a single retry. Tokens are held as [`SecretString`] and never logged.
+
+use super::{Middleware, Next};
+use crate::{
+    error::{Error, Result},
+    http::Request,
+    types::PaymentResponse,
+};
+use async_trait::async_trait;
+use secrecy::{ExposeSecret, SecretString};
+use serde::Deserialize;
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+use tracing::{debug, warn};
+
+/// Time before expiry at which a cached token is refreshed in the background.
+const DEFAULT_REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+/// Lifetime assumed for tokens whose provider reports none.
+const DEFAULT_TOKEN_LIFETIME: Duration = Duration::from_secs(300);
+
+/// A token together with how long it stays valid.
+pub struct TokenGrant {
+    /// Bearer token
+    pub token: SecretString,
+
+    /// Time until the token expires, if known
+    pub expires_in: Option<Duration>,
+}
+
+impl fmt::Debug for TokenGrant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TokenGrant")
+            .field("token", &"[REDACTED]")
+            .field("expires_in", &self.expires_in)
+            .finish()
+    }
+}
+
+/// Source of bearer tokens.
+#[async_trait]
+pub trait CredentialProvider: Send + Sync + fmt::Debug {
+    /// Fetches a new token.
+    async fn token(&self) -> Result<SecretString>;
+
+    /// Fetches a new token with its lifetime.
+    ///
+    /// Providers that know when their tokens expire should override this;
+    /// the default reports no lifetime.
+    async fn token_grant(&self) -> Result<TokenGrant> {
+        Ok(TokenGrant {
+            token: self.token().await?,
+            expires_in: None,
+        })
+    }
+}
+
+/// A cached token and when it expires.
+struct CachedToken {
+    token: SecretString,
+    expires_at: Instant,
+}
+
+/// Shared token state, also used by background refreshes.
+struct TokenCache {
+    provider: Arc<dyn CredentialProvider>,
+    current: Mutex<Option<CachedToken>>,
+    refreshing: AtomicBool,
+}
+
+impl TokenCache {
+    /// Fetches a token from the provider.
+    async fn fetch(&self, default_lifetime: Duration) -> Result<CachedToken> {
+        let grant = self.provider.token_grant().await?;
+        let lifetime = grant.expires_in.unwrap_or(default_lifetime);
+        debug!(lifetime_secs = lifetime.as_secs(), "Fetched bearer token");
+
+        Ok(CachedToken {
+            token: grant.token,
+            expires_at: Instant::now() + lifetime,
+        })
+    }
+}
+
+/// Middleware adding an `Authorization: Bearer` header from a [`CredentialProvider`].
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use v402_client::{middleware::{BearerAuthMiddleware, ClientCredentialsProvider}, Client};
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let provider = ClientCredentialsProvider::new(
+///     "https://auth.example.com/oauth/token",
+///     "my-client-id",
+///     std::env::var("CLIENT_SECRET")?,
+/// )?
+/// .scope("data:read");
+///
+/// let client = Client::builder()
+///     .middleware(Box::new(BearerAuthMiddleware::new(provider)))
+///     .build()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct BearerAuthMiddleware {
+    cache: Arc<TokenCache>,
+    refresh_margin: Duration,
+    default_lifetime: Duration,
+}
+
+impl BearerAuthMiddleware {
+    /// Creates the middleware for a credential provider.
+    pub fn new<P: CredentialProvider + 'static>(provider: P) -> Self {
+        Self {
+            cache: Arc::new(TokenCache {
+                provider: Arc::new(provider),
+                current: Mutex::new(None),
+                refreshing: AtomicBool::new(false),
+            }),
+            refresh_margin: DEFAULT_REFRESH_MARGIN,
+            default_lifetime: DEFAULT_TOKEN_LIFETIME,
+        }
+    }
+
+    /// Sets how long before expiry a token is refreshed in the background.
+    pub fn refresh_margin(mut self, margin: Duration) -> Self {
+        self.refresh_margin = margin;
+        self
+    }
+
+    /// Sets the lifetime assumed for tokens whose provider reports none.
+    pub fn default_lifetime(mut self, lifetime: Duration) -> Self {
+        self.default_lifetime = lifetime;
+        self
+    }
+
+    /// Returns a valid token, fetching or refreshing it as needed.
+    async fn current_token(&self) -> Result<SecretString> {
+        let mut current = self.cache.current.lock().await;
+        let now = Instant::now();
+
+        match current.as_ref() {
+            Some(cached) if cached.expires_at > now => {
+                if cached.expires_at <= now + self.refresh_margin {
+                    self.spawn_refresh();
+                }
+                Ok(cached.token.clone())
+            }
+            _ => {
+                let fetched = self.cache.fetch(self.default_lifetime).await?;
+                Ok(current.insert(fetched).token.clone())
+            }
+        }
+    }
+
+    /// Discards the cached token and fetches a new one.
+    async fn force_refresh(&self) -> Result<SecretString> {
+        let mut current = self.cache.current.lock().await;
+        *current = None;
+        let fetched = self.cache.fetch(self.default_lifetime).await?;
+        Ok(current.insert(fetched).token.clone())
+    }
+
+    /// Refreshes the token in the background unless a refresh is already running.
+    fn spawn_refresh(&self) {
+        if self.cache.refreshing.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        let cache = self.cache.clone();
+        let default_lifetime = self.default_lifetime;
+        tokio::spawn(async move {
+            // Requests keep using the current token while the new one is fetched
+            match cache.fetch(default_lifetime).await {
+                Ok(fetched) => *cache.current.lock().await = Some(fetched),
+                Err(e) => warn!(error = %e, "Background bearer token refresh failed"),
+            }
+            cache.refreshing.store(false, Ordering::Release);
+        });
+    }
+}
+
+impl fmt::Debug for BearerAuthMiddleware {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BearerAuthMiddleware")
+            .field("provider", &self.cache.provider)
+            .field("refresh_margin", &self.refresh_margin)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl Middleware for BearerAuthMiddleware {
+    async fn handle(&self, request: Request, next: Next<'_>) -> Result<PaymentResponse> {
+        let token = self.current_token().await?;
+        let response = next.run(with_bearer(request.clone(), &token)).await?;
+
+        if response.status != 401 {
+            return Ok(response);
+        }
+
+        debug!(url = %request.url, "Bearer token rejected, refreshing and retrying once");
+        let token = self.force_refresh().await?;
+        next.run(with_bearer(request, &token)).await
+    }
+}
+
+/// Sets the `Authorization` header of a request.
+fn with_bearer(mut request: Request, token: &SecretString) -> Request {
+    request
+        .headers
+        .insert("Authorization".to_string(), format!("Bearer {}", token.expose_secret()));
+    request
+}
+
+/// OAuth2 client-credentials grant (RFC 6749 section 4.4).
+///
+/// Client credentials are sent with HTTP Basic authentication.
+#[derive(Debug)]
+pub struct ClientCredentialsProvider {
+    token_url: url::Url,
+    client_id: String,
+    client_secret: SecretString,
+    scopes: Vec<String>,
+    http: reqwest::Client,
+}
+
+/// Token endpoint response.
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: SecretString,
+    #[serde(default)]
+    expires_in: Option<u64>,
+}
+
+impl ClientCredentialsProvider {
+    /// Creates a provider for the given token endpoint and client credentials.
+    pub fn new<U, I, S>(token_url: U, client_id: I, client_secret: S) -> Result<Self>
+    where
+        U: AsRef<str>,
+        I: Into<String>,
+        S: Into<String>,
+    {
+        let token_url = url::Url::parse(token_url.as_ref())
+            .map_err(|e| Error::Config(format!("Invalid token URL {}: {}", token_url.as_ref(), e).into()))?;
+
+        let http = reqwest::Client::builder()
+            .user_agent(crate::USER_AGENT)
+            .build()
+            .map_err(|e| Error::Network(format!("Failed to build HTTP client: {}", e).into()))?;
+
+        Ok(Self {
+            token_url,
+            client_id: client_id.into(),
+            client_secret: SecretString::new(client_secret.into()),
+            scopes: Vec::new(),
+            http,
+        })
+    }
+
+    /// Requests an additional scope.
+    pub fn scope<S: Into<String>>(mut self, scope: S) -> Self {
+        self.scopes.push(scope.into());
+        self
+    }
+}
+
+#[async_trait]
+impl CredentialProvider for ClientCredentialsProvider {
+    async fn token(&self) -> Result<SecretString> {
+        Ok(self.token_grant().await?.token)
+    }
+
+    async fn token_grant(&self) -> Result<TokenGrant> {
+        let scope = self.scopes.join(" ");
+        let mut form = vec![("grant_type", "client_credentials")];
+        if !scope.is_empty() {
+            form.push(("scope", &scope));
+        }
+
+        let response = self
+            .http
+            .post(self.token_url.clone())
+            .basic_auth(&self.client_id, Some(self.client_secret.expose_secret()))
+            .form(&form)
+            .send()
+            .await
+            .map_err(|e| Error::Auth(format!("Token request to {} failed: {}", self.token_url, e).into()))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(Error::Auth(format!(
+                "Token endpoint {} returned {}",
+                self.token_url, status
+            ).into()));
+        }
+
+        let body: TokenResponse = response
+            .json()
+            .await
+            .map_err(|e| Error::Auth(format!("Invalid token response from {}: {}", self.token_url, e).into()))?;
+
+        Ok(TokenGrant {
+            token: body.access_token,
+            expires_in: body.expires_in.map(Duration::from_secs),
+        })
+    }
+}
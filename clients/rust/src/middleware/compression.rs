@@ -0,0 +1,256 @@
+//! Compression of request bodies.
+
+use super::WrapMiddleware;
+use crate::{error::Result, http::Request};
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+use tracing::{debug, warn};
+
+/// Encodings the HTTP client decodes in responses.
+const ACCEPT_ENCODING: &str = "gzip, deflate, br";
+
+/// Bodies smaller than this many bytes are sent as is unless set with
+/// [`CompressionMiddleware::min_size_bytes`].
+pub const DEFAULT_MIN_COMPRESSION_SIZE: usize = 1024;
+
+/// Algorithm compressing request bodies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    /// gzip, understood by virtually every server
+    Gzip,
+    /// Brotli, levels 0 to 11
+    Brotli,
+    /// Zstandard, levels 1 to 22
+    Zstd,
+}
+
+impl Compression {
+    /// Value of the `Content-Encoding` header for this algorithm.
+    pub fn content_encoding(&self) -> &'static str {
+        match self {
+            Compression::Gzip => "gzip",
+            Compression::Brotli => "br",
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    /// Level balancing speed and ratio: 6 for gzip, 5 for Brotli and 3 for Zstandard.
+    pub fn default_level(&self) -> u32 {
+        match self {
+            Compression::Gzip => 6,
+            Compression::Brotli => 5,
+            Compression::Zstd => 3,
+        }
+    }
+
+    /// Highest level the algorithm supports.
+    fn max_level(&self) -> u32 {
+        match self {
+            Compression::Gzip => 9,
+            Compression::Brotli => 11,
+            Compression::Zstd => 22,
+        }
+    }
+
+    /// Compresses `data` at `level`.
+    fn compress(&self, data: &[u8], level: u32) -> io::Result<Vec<u8>> {
+        match self {
+            Compression::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::new(level));
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            Compression::Brotli => {
+                // 22 is the largest window Brotli decoders are required to support
+                let mut encoder = brotli::CompressorWriter::new(Vec::new(), 4096, level, 22);
+                encoder.write_all(data)?;
+                Ok(encoder.into_inner())
+            }
+            Compression::Zstd => zstd::bulk::compress(data, level as i32),
+        }
+    }
+}
+
+/// Compresses request bodies and asks servers for compressed responses.
+///
+/// Bodies of at least [`min_size_bytes`](Self::min_size_bytes) are
+/// compressed and sent with a matching `Content-Encoding` header. Requests
+/// that already carry a `Content-Encoding` are left as they are, as are
+/// bodies that do not shrink. Every request without an `Accept-Encoding`
+/// header gets one listing the encodings the client decodes, except ranged
+/// requests, whose byte offsets refer to the unencoded content.
+///
+/// Only use it with servers known to accept compressed bodies; most reject
+/// them with `415 Unsupported Media Type`.
+///
+/// ```rust
+/// use v402_client::middleware::{Compression, CompressionMiddleware};
+/// # async fn example(client: v402_client::Client) {
+/// client.add_middleware(Box::new(
+///     CompressionMiddleware::new(Compression::Zstd).min_size_bytes(4096).level(9),
+/// ));
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct CompressionMiddleware {
+    algorithm: Compression,
+    level: u32,
+    min_size_bytes: usize,
+}
+
+impl CompressionMiddleware {
+    /// Creates a middleware compressing bodies of at least
+    /// [`DEFAULT_MIN_COMPRESSION_SIZE`] bytes with `algorithm` at its default level.
+    pub fn new(algorithm: Compression) -> Self {
+        Self {
+            algorithm,
+            level: algorithm.default_level(),
+            min_size_bytes: DEFAULT_MIN_COMPRESSION_SIZE,
+        }
+    }
+
+    /// Sets the compression level, capped at the algorithm's maximum.
+    pub fn level(mut self, level: u32) -> Self {
+        self.level = level.min(self.algorithm.max_level());
+        self
+    }
+
+    /// Sets the size below which bodies are sent uncompressed.
+    pub fn min_size_bytes(mut self, min_size_bytes: usize) -> Self {
+        self.min_size_bytes = min_size_bytes;
+        self
+    }
+}
+
+impl WrapMiddleware for CompressionMiddleware {
+    fn before(&self, request: &mut Request) -> Result<()> {
+        if !has_header(request, "accept-encoding") && !has_header(request, "range") {
+            request.headers.insert("Accept-Encoding".to_string(), ACCEPT_ENCODING.to_string());
+        }
+
+        if has_header(request, "content-encoding") {
+            return Ok(());
+        }
+        let Some(body) = request.body.as_ref().filter(|body| body.len() >= self.min_size_bytes) else {
+            return Ok(());
+        };
+
+        match self.algorithm.compress(body, self.level) {
+            Ok(compressed) if compressed.len() < body.len() => {
+                debug!(
+                    encoding = self.algorithm.content_encoding(),
+                    original = body.len(),
+                    compressed = compressed.len(),
+                    "Compressed request body"
+                );
+                request.body = Some(compressed);
+                // A length set by the caller describes the uncompressed body
+                request.headers.retain(|name, _| !name.eq_ignore_ascii_case("content-length"));
+                request.headers.insert(
+                    "Content-Encoding".to_string(),
+                    self.algorithm.content_encoding().to_string(),
+                );
+            }
+            Ok(_) => debug!(size = body.len(), "Request body does not compress, sending it as is"),
+            Err(e) => warn!(error = %e, "Failed to compress request body, sending it as is"),
+        }
+        Ok(())
+    }
+}
+
+/// Returns `true` if the request has a header named `name`, in any case.
+fn has_header(request: &Request, name: &str) -> bool {
+    request.headers.keys().any(|key| key.eq_ignore_ascii_case(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    /// Returns a compressible JSON body of about 8 KiB.
+    fn body() -> Vec<u8> {
+        let items: Vec<_> = (0..200).map(|i| format!(r#"{{"id":{i},"name":"item"}}"#)).collect();
+        format!("[{}]", items.join(",")).into_bytes()
+    }
+
+    fn request(body: Vec<u8>) -> Request {
+        Request::new(reqwest::Method::POST, "https://api.example.com/upload").unwrap().body(body)
+    }
+
+    fn decompress(algorithm: Compression, data: &[u8]) -> Vec<u8> {
+        let mut decoded = Vec::new();
+        match algorithm {
+            Compression::Gzip => {
+                flate2::read::GzDecoder::new(data).read_to_end(&mut decoded).unwrap();
+            }
+            Compression::Brotli => {
+                brotli::Decompressor::new(data, 4096).read_to_end(&mut decoded).unwrap();
+            }
+            Compression::Zstd => decoded = zstd::stream::decode_all(data).unwrap(),
+        }
+        decoded
+    }
+
+    #[test]
+    fn compressed_bodies_decode_to_the_original() {
+        for algorithm in [Compression::Gzip, Compression::Brotli, Compression::Zstd] {
+            for level in [1, algorithm.default_level(), 99] {
+                let mut request = request(body()).header("Content-Length", body().len().to_string());
+                CompressionMiddleware::new(algorithm).level(level).before(&mut request).unwrap();
+
+                let compressed = request.body.as_ref().unwrap();
+                assert!(compressed.len() < body().len(), "{algorithm:?} at {level}");
+                assert_eq!(decompress(algorithm, compressed), body(), "{algorithm:?} at {level}");
+                assert_eq!(request.headers["Content-Encoding"], algorithm.content_encoding());
+                assert_eq!(request.headers["Accept-Encoding"], ACCEPT_ENCODING);
+                assert!(!has_header(&request, "content-length"));
+            }
+        }
+    }
+
+    #[test]
+    fn small_encoded_and_incompressible_bodies_are_sent_as_is() {
+        let middleware = CompressionMiddleware::new(Compression::Gzip);
+
+        let mut small = request(b"{}".to_vec());
+        middleware.before(&mut small).unwrap();
+        assert_eq!(small.body.as_deref(), Some(&b"{}"[..]));
+        assert!(!has_header(&small, "content-encoding"));
+
+        let mut encoded = request(body()).header("content-encoding", "identity");
+        middleware.before(&mut encoded).unwrap();
+        assert_eq!(encoded.body, Some(body()));
+        assert_eq!(encoded.headers["content-encoding"], "identity");
+
+        // Already compressed data grows when compressed again
+        let random: Vec<u8> = (0..64).flat_map(|_| *uuid::Uuid::new_v4().as_bytes()).collect();
+        let mut incompressible = request(random.clone());
+        middleware.min_size_bytes(16).before(&mut incompressible).unwrap();
+        assert_eq!(incompressible.body, Some(random));
+        assert!(!has_header(&incompressible, "content-encoding"));
+    }
+
+    #[test]
+    fn accept_encoding_is_added_unless_set_or_ranged() {
+        let middleware = CompressionMiddleware::new(Compression::Zstd);
+
+        let mut plain = Request::new(reqwest::Method::GET, "https://api.example.com/data").unwrap();
+        middleware.before(&mut plain).unwrap();
+        assert_eq!(plain.headers["Accept-Encoding"], ACCEPT_ENCODING);
+
+        let mut custom = Request::new(reqwest::Method::GET, "https://api.example.com/data")
+            .unwrap()
+            .header("accept-encoding", "identity");
+        middleware.before(&mut custom).unwrap();
+        assert_eq!(custom.headers.len(), 1);
+        assert_eq!(custom.headers["accept-encoding"], "identity");
+
+        let mut ranged = Request::new(reqwest::Method::GET, "https://api.example.com/data")
+            .unwrap()
+            .header("Range", "bytes=0-99");
+        middleware.before(&mut ranged).unwrap();
+        assert!(!has_header(&ranged, "accept-encoding"));
+    }
+}
@@ -0,0 +1,373 @@
+//! Coalescing of concurrent identical requests.
+
+use super::{Middleware, Next};
+use crate::{error::Result, http::Request, types::PaymentResponse};
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::broadcast;
+use tracing::debug;
+
+/// Default time followers wait for the request they joined, including its payment.
+const DEFAULT_MAX_WAIT: Duration = Duration::from_secs(60);
+
+/// Headers that differ between otherwise identical requests and are left out of the key.
+const VOLATILE_HEADERS: [&str; 6] = ["x-payment", "traceparent", "tracestate", "b3", "x-b3-traceid", "x-b3-spanid"];
+
+/// Outcome shared with followers; `None` tells them to send the request themselves.
+type Outcome = Option<PaymentResponse>;
+
+/// Requests in flight by key.
+type InFlightMap = Mutex<HashMap<String, InFlight>>;
+
+/// Coalesces concurrent identical requests into a single upstream request.
+///
+/// The first of several concurrent `GET` or `HEAD` requests with the same
+/// URL and headers is sent; the others wait for its response and receive a
+/// copy of it ("single flight"). When that response is a `402 Payment
+/// Required`, only the first caller receives it and pays, and the others
+/// wait for the paid retry instead, so a cold cache costs one payment rather
+/// than one per caller. Followers' responses are not marked as paid.
+///
+/// Other methods may have side effects and are never coalesced, nor are
+/// requests carrying a payment held back. If the first request fails, is
+/// cancelled, or its caller is done with it without a paid response, for
+/// example because it returned the 402 or the payment was refused, followers
+/// send their own requests, as they do after the maximum wait.
+///
+/// Add it before middleware that make each request unique, such as
+/// credentials per user; trace headers, the payment header and headers
+/// carrying the request or batch ID are ignored when comparing requests.
+///
+/// ```rust
+/// use v402_client::middleware::DeduplicationMiddleware;
+/// # async fn example() -> v402_client::Result<()> {
+/// let client = v402_client::Client::builder()
+///     .middleware(Box::new(DeduplicationMiddleware::new()))
+///     .build()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct DeduplicationMiddleware {
+    in_flight: Arc<InFlightMap>,
+    next_id: AtomicU64,
+    coalesced: AtomicU64,
+    max_wait: Duration,
+}
+
+/// A request being sent on behalf of its followers.
+#[derive(Debug)]
+struct InFlight {
+    /// Distinguishes the entry from a later one for the same key
+    id: u64,
+    sender: broadcast::Sender<Outcome>,
+    started_at: Instant,
+    /// The first response was a 402 and followers wait for the paid retry
+    awaiting_payment: bool,
+    /// A paid retry is in flight
+    paying: bool,
+}
+
+impl DeduplicationMiddleware {
+    /// Creates a middleware whose followers wait up to a minute.
+    pub fn new() -> Self {
+        Self {
+            in_flight: Arc::default(),
+            next_id: AtomicU64::new(0),
+            coalesced: AtomicU64::new(0),
+            max_wait: DEFAULT_MAX_WAIT,
+        }
+    }
+
+    /// Sets how long after the first request started followers keep waiting
+    /// for it before sending their own.
+    pub fn max_wait(mut self, max_wait: Duration) -> Self {
+        self.max_wait = max_wait;
+        self
+    }
+
+    /// Number of requests answered with the response of another request.
+    pub fn coalesced_count(&self) -> u64 {
+        self.coalesced.load(Ordering::Relaxed)
+    }
+
+    /// Sends a paid request, resolving followers waiting for it.
+    async fn handle_paid(&self, key: String, request: Request, next: Next<'_>) -> Result<PaymentResponse> {
+        let waiting = match self.in_flight.lock().get_mut(&key) {
+            Some(entry) if entry.awaiting_payment && !entry.paying => {
+                entry.paying = true;
+                Some(entry.id)
+            }
+            _ => None,
+        };
+        let Some(id) = waiting else {
+            return next.run(request).await;
+        };
+
+        let mut leader = Leader { middleware: self, key, id, done: false };
+        let result = next.run(request).await;
+        match &result {
+            // Rate-limited payments are retried by the client, which resolves the followers then
+            Ok(response) if matches!(response.status, 402 | 429 | 503) => {
+                leader.update(|entry| entry.paying = false);
+            }
+            Ok(response) => leader.resolve(Some(response.clone())),
+            Err(_) => leader.resolve(None),
+        }
+        result
+    }
+}
+
+impl Default for DeduplicationMiddleware {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Middleware for DeduplicationMiddleware {
+    async fn handle(&self, request: Request, next: Next<'_>) -> Result<PaymentResponse> {
+        // Streamed bodies cannot be compared, nor shared with followers
+        if !matches!(request.method, reqwest::Method::GET | reqwest::Method::HEAD) || request.is_streamed() {
+            return next.run(request).await;
+        }
+        let key = request_key(&request);
+        if request.headers.keys().any(|name| name.eq_ignore_ascii_case("x-payment")) {
+            return self.handle_paid(key, request, next).await;
+        }
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let joined = {
+            let mut in_flight = self.in_flight.lock();
+            match in_flight.get(&key) {
+                Some(entry) if entry.started_at.elapsed() < self.max_wait => {
+                    Some((entry.sender.subscribe(), self.max_wait - entry.started_at.elapsed()))
+                }
+                _ => {
+                    let (sender, _) = broadcast::channel(1);
+                    in_flight.insert(
+                        key.clone(),
+                        InFlight {
+                            id,
+                            sender,
+                            started_at: Instant::now(),
+                            awaiting_payment: false,
+                            paying: false,
+                        },
+                    );
+                    None
+                }
+            }
+        };
+
+        if let Some((mut receiver, remaining)) = joined {
+            debug!(url = %request.url, "Waiting for identical request in flight");
+            if let Ok(Ok(Some(mut response))) = tokio::time::timeout(remaining, receiver.recv()).await {
+                self.coalesced.fetch_add(1, Ordering::Relaxed);
+                response.request_id = request.request_id;
+                response.batch_id = request.batch_id;
+                return Ok(response);
+            }
+            return next.run(request).await;
+        }
+
+        let completion = request.completion.clone();
+        let mut leader = Leader { middleware: self, key, id, done: false };
+        let result = next.run(request).await;
+        match &result {
+            Ok(response) if response.status == 402 => {
+                leader.update(|entry| entry.awaiting_payment = true);
+                // Release followers still waiting once the caller is done, paid or not
+                let (in_flight, key) = (self.in_flight.clone(), leader.key.clone());
+                completion.on_complete(move || resolve(&in_flight, &key, id, None));
+            }
+            Ok(response) => leader.resolve(Some(response.clone())),
+            Err(_) => leader.resolve(None),
+        }
+        result
+    }
+}
+
+/// Resolves the followers of a request, telling them to go ahead on their
+/// own if it is dropped unresolved.
+struct Leader<'a> {
+    middleware: &'a DeduplicationMiddleware,
+    key: String,
+    id: u64,
+    done: bool,
+}
+
+impl Leader<'_> {
+    /// Keeps followers waiting, updating the state of the request.
+    fn update(&mut self, f: impl FnOnce(&mut InFlight)) {
+        self.done = true;
+        if let Some(entry) = self.middleware.in_flight.lock().get_mut(&self.key) {
+            if entry.id == self.id {
+                f(entry);
+            }
+        }
+    }
+
+    /// Sends the outcome to the followers and forgets the request.
+    fn resolve(&mut self, outcome: Outcome) {
+        self.done = true;
+        resolve(&self.middleware.in_flight, &self.key, self.id, outcome);
+    }
+}
+
+/// Sends `outcome` to the followers of request `id` under `key` and forgets
+/// the request, unless it was already resolved.
+fn resolve(in_flight: &InFlightMap, key: &str, id: u64, outcome: Outcome) {
+    let mut in_flight = in_flight.lock();
+    if in_flight.get(key).is_some_and(|entry| entry.id == id) {
+        if let Some(entry) = in_flight.remove(key) {
+            // No receivers just means no request joined
+            let _ = entry.sender.send(outcome);
+        }
+    }
+}
+
+impl Drop for Leader<'_> {
+    fn drop(&mut self) {
+        if !self.done {
+            self.resolve(None);
+        }
+    }
+}
+
+/// Identifies requests that can share a response.
+fn request_key(request: &Request) -> String {
+    let mut headers: Vec<_> = request
+        .headers
+        .iter()
+        .filter(|(name, value)| {
+            !VOLATILE_HEADERS.iter().any(|h| name.eq_ignore_ascii_case(h))
+                && **value != request.request_id
+                && Some(*value) != request.batch_id.as_ref()
+        })
+        .map(|(name, value)| format!("{}:{}", name.to_ascii_lowercase(), value))
+        .collect();
+    headers.sort();
+
+    let body = request.body.as_deref().map(crate::crypto::keccak256).map(hex::encode);
+    format!(
+        "{} {}\n{}\n{}",
+        request.method,
+        request.url,
+        headers.join("\n"),
+        body.unwrap_or_default()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{ChainConfig, Config},
+        Client,
+    };
+    use serde_json::json;
+    use wiremock::{
+        matchers::{body_partial_json, header_exists, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    const KEY: &str = "0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+
+    /// Shares a middleware with the test after handing it to the client.
+    #[derive(Debug)]
+    struct Shared(Arc<DeduplicationMiddleware>);
+
+    #[async_trait]
+    impl Middleware for Shared {
+        async fn handle(&self, request: Request, next: Next<'_>) -> Result<PaymentResponse> {
+            self.0.handle(request, next).await
+        }
+    }
+
+    /// Mounts a chain RPC answering every call, so payments can be signed.
+    async fn mount_rpc(server: &MockServer) {
+        for (rpc_method, result) in [
+            ("eth_chainId", json!("0x2105")),
+            ("eth_call", json!(format!("0x{}{}", "0".repeat(32), "f".repeat(32)))),
+        ] {
+            Mock::given(method("POST"))
+                .and(path("/rpc"))
+                .and(body_partial_json(json!({ "method": rpc_method })))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "jsonrpc": "2.0", "id": 1, "result": result })))
+                .mount(server)
+                .await;
+        }
+        Mock::given(method("POST"))
+            .and(path("/rpc"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "jsonrpc": "2.0", "id": 1, "result": "0x10" })))
+            .with_priority(10)
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn concurrent_identical_requests_pay_once() {
+        let server = MockServer::start().await;
+        mount_rpc(&server).await;
+        Mock::given(method("GET"))
+            .and(path("/data"))
+            .and(header_exists("x-payment"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("paid content"))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/data"))
+            .respond_with(
+                ResponseTemplate::new(402)
+                    .set_body_json(json!({
+                        "x402Version": 1,
+                        "accepts": [{
+                            "scheme": "exact",
+                            "network": "base",
+                            "maxAmountRequired": "1000",
+                            "resource": format!("{}/data", server.uri()),
+                            "payTo": "0x1111111111111111111111111111111111111111",
+                            "asset": "0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913",
+                            "maxTimeoutSeconds": 60,
+                        }],
+                    }))
+                    .set_delay(Duration::from_millis(100)),
+            )
+            .with_priority(10)
+            .mount(&server)
+            .await;
+
+        let dedup = Arc::new(DeduplicationMiddleware::new());
+        let client = Client::new(Config {
+            chains: vec![ChainConfig::base_mainnet().with_rpc_url(format!("{}/rpc", server.uri()))],
+            private_key: Some(KEY.to_string().into()),
+            facilitator_url: Some(server.uri()),
+            allow_insecure_facilitator: true,
+            ..Config::default()
+        })
+        .await
+        .unwrap();
+        client.add_middleware(Box::new(Shared(dedup.clone())));
+
+        let url = format!("{}/data", server.uri());
+        let responses = futures::future::join_all((0..8).map(|_| client.get(&url))).await;
+        for response in responses {
+            assert_eq!(response.unwrap().body, "paid content");
+        }
+
+        let requests = server.received_requests().await.unwrap();
+        let payments = requests.iter().filter(|request| request.headers.contains_key("x-payment")).count();
+        assert_eq!(payments, 1);
+        assert_eq!(dedup.coalesced_count(), 7);
+    }
+}
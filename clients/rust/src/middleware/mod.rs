@@ -0,0 +1,318 @@
+//! Composable request/response middleware.
+//!
+//! Middleware wrap every HTTP exchange made by the client, including the
+//! paid retry after a `402 Payment Required`. Each middleware receives the
+//! request and a [`Next`] handle that runs the rest of the chain.
+//!
+//! ```rust
+//! use v402_client::{middleware::{Middleware, Next}, PaymentResponse, Request, Result};
+//! use async_trait::async_trait;
+//!
+//! #[derive(Debug)]
+//! struct AuthMiddleware {
+//!     token: String,
+//! }
+//!
+//! #[async_trait]
+//! impl Middleware for AuthMiddleware {
+//!     async fn handle(&self, mut request: Request, next: Next<'_>) -> Result<PaymentResponse> {
+//!         request.headers.insert("Authorization".to_string(), format!("Bearer {}", self.token));
+//!         next.run(request).await
+//!     }
+//! }
+//! ```
+
+mod auth;
+#[cfg(feature = "compression")]
+mod compression;
+mod dedup;
+mod propagation;
+mod request_id;
+
+pub use auth::{BearerAuthMiddleware, ClientCredentialsProvider, CredentialProvider, TokenGrant};
+#[cfg(feature = "compression")]
+pub use compression::{Compression, CompressionMiddleware, DEFAULT_MIN_COMPRESSION_SIZE};
+pub use dedup::DeduplicationMiddleware;
+pub use propagation::{PropagationFormat, TracingPropagationMiddleware};
+pub use request_id::RequestIdMiddleware;
+
+use crate::{
+    error::Result,
+    http::{HttpClient, Request, StreamSlot},
+    types::PaymentResponse,
+};
+use arc_swap::ArcSwap;
+use async_trait::async_trait;
+use std::{fmt, sync::Arc};
+
+/// A request/response interceptor.
+#[async_trait]
+pub trait Middleware: Send + Sync + fmt::Debug {
+    /// Handles a request, calling `next.run` to continue the chain.
+    async fn handle(&self, request: Request, next: Next<'_>) -> Result<PaymentResponse>;
+
+    /// Name of the middleware in the audit log, its type name by default.
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+}
+
+/// Middleware that only inspects or modifies the request before it is sent
+/// and the response after it is received.
+///
+/// Every `WrapMiddleware` is a [`Middleware`] that runs [`before`](Self::before),
+/// the rest of the chain, then [`after`](Self::after). An error from either
+/// hook aborts the request.
+///
+/// ```rust
+/// use v402_client::{middleware::WrapMiddleware, PaymentResponse, Request, Result};
+///
+/// /// Adds the total response time to every response.
+/// #[derive(Debug)]
+/// struct ResponseTiming;
+///
+/// impl WrapMiddleware for ResponseTiming {
+///     fn after(&self, request: &Request, response: &mut PaymentResponse) -> Result<()> {
+///         let elapsed = request.created_at.elapsed().as_millis().to_string();
+///         response.headers.insert("x-response-time-ms".to_string(), elapsed);
+///         Ok(())
+///     }
+/// }
+/// ```
+pub trait WrapMiddleware: Send + Sync + fmt::Debug {
+    /// Called before the request is passed down the chain.
+    fn before(&self, request: &mut Request) -> Result<()> {
+        let _ = request;
+        Ok(())
+    }
+
+    /// Called with the response returned by the rest of the chain.
+    fn after(&self, request: &Request, response: &mut PaymentResponse) -> Result<()> {
+        let _ = (request, response);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<T: WrapMiddleware> Middleware for T {
+    async fn handle(&self, mut request: Request, next: Next<'_>) -> Result<PaymentResponse> {
+        self.before(&mut request)?;
+        let mut response = next.run(request.clone()).await?;
+        self.after(&request, &mut response)?;
+        Ok(response)
+    }
+}
+
+/// The remainder of a middleware chain.
+///
+/// `Next` is `Copy`, so a middleware may run the rest of the chain more than
+/// once, e.g. to retry a request.
+#[derive(Clone, Copy)]
+pub struct Next<'a> {
+    http_client: &'a HttpClient,
+    middlewares: &'a [Arc<dyn Middleware>],
+    /// Where the response body is left unread, for streamed requests
+    stream: Option<&'a StreamSlot>,
+}
+
+impl Next<'_> {
+    /// Runs the remaining middleware and sends the request.
+    pub async fn run(self, request: Request) -> Result<PaymentResponse> {
+        match self.middlewares.split_first() {
+            Some((middleware, rest)) => {
+                let next = Next {
+                    middlewares: rest,
+                    ..self
+                };
+                middleware.handle(request, next).await
+            }
+            None => match self.stream {
+                Some(slot) => self.http_client.send_streaming(request, slot).await,
+                None => self.http_client.send(request).await,
+            },
+        }
+    }
+}
+
+impl fmt::Debug for Next<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Next")
+            .field("remaining", &self.middlewares.len())
+            .finish()
+    }
+}
+
+/// Ordered, copy-on-write collection of middleware.
+///
+/// Each execution works on a snapshot of the stack taken when it starts, so
+/// middleware added concurrently never affect in-flight requests and become
+/// visible to every request started afterwards.
+#[derive(Debug, Default)]
+pub struct MiddlewareStack {
+    middlewares: ArcSwap<Vec<Arc<dyn Middleware>>>,
+}
+
+impl MiddlewareStack {
+    /// Creates an empty stack.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a middleware to the end of the stack.
+    pub fn add(&self, middleware: Box<dyn Middleware>) {
+        let middleware: Arc<dyn Middleware> = Arc::from(middleware);
+        self.middlewares.rcu(|current| {
+            let mut updated = Vec::with_capacity(current.len() + 1);
+            updated.extend(current.iter().cloned());
+            updated.push(middleware.clone());
+            updated
+        });
+    }
+
+    /// Appends a [`WrapMiddleware`] to the end of the stack.
+    pub fn wrap_around<W: WrapMiddleware + 'static>(&self, middleware: W) {
+        self.add(Box::new(middleware));
+    }
+
+    /// Number of middleware in the stack.
+    pub fn len(&self) -> usize {
+        self.middlewares.load().len()
+    }
+
+    /// Returns `true` if no middleware have been added.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Runs a request through a snapshot of the stack.
+    pub(crate) async fn execute(&self, request: Request, http_client: &HttpClient) -> Result<PaymentResponse> {
+        let snapshot = self.middlewares.load_full();
+        Next {
+            http_client,
+            middlewares: &snapshot,
+            stream: None,
+        }
+        .run(request)
+        .await
+    }
+
+    /// Runs a request through a snapshot of the stack, leaving the response
+    /// body unread in `slot`.
+    ///
+    /// Middleware see the response with an empty body.
+    pub(crate) async fn execute_streaming(
+        &self,
+        request: Request,
+        http_client: &HttpClient,
+        slot: &StreamSlot,
+    ) -> Result<PaymentResponse> {
+        let snapshot = self.middlewares.load_full();
+        Next {
+            http_client,
+            middlewares: &snapshot,
+            stream: Some(slot),
+        }
+        .run(request)
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use wiremock::{matchers::any, Mock, MockServer, ResponseTemplate};
+
+    /// Counts the requests it sees.
+    #[derive(Debug)]
+    struct Counter(Arc<AtomicUsize>);
+
+    #[async_trait]
+    impl Middleware for Counter {
+        async fn handle(&self, request: Request, next: Next<'_>) -> Result<PaymentResponse> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            next.run(request).await
+        }
+    }
+
+    async fn http_client(server: &MockServer) -> Arc<HttpClient> {
+        Mock::given(any()).respond_with(ResponseTemplate::new(200)).mount(server).await;
+        Arc::new(HttpClient::new(&Arc::new(Config::default())).await.unwrap())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn middleware_added_during_requests_apply_to_later_requests() {
+        let server = MockServer::start().await;
+        let http_client = http_client(&server).await;
+        let stack = Arc::new(MiddlewareStack::new());
+        let seen = Arc::new(AtomicUsize::new(0));
+
+        let requests = (0..4)
+            .map(|_| {
+                let (stack, http_client, url) = (stack.clone(), http_client.clone(), server.uri());
+                tokio::spawn(async move {
+                    for _ in 0..25 {
+                        let request = Request::new(reqwest::Method::GET, &url).unwrap();
+                        stack.execute(request, &http_client).await.unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        let additions = {
+            let (stack, seen) = (stack.clone(), seen.clone());
+            tokio::spawn(async move {
+                for _ in 0..10 {
+                    stack.add(Box::new(Counter(seen.clone())));
+                    tokio::task::yield_now().await;
+                }
+            })
+        };
+        additions.await.unwrap();
+        for requests in requests {
+            requests.await.unwrap();
+        }
+        assert_eq!(stack.len(), 10);
+
+        // Every middleware added is seen by requests started afterwards
+        let before = seen.load(Ordering::SeqCst);
+        let request = Request::new(reqwest::Method::GET, &server.uri()).unwrap();
+        stack.execute(request, &http_client).await.unwrap();
+        assert_eq!(seen.load(Ordering::SeqCst) - before, 10);
+    }
+
+    /// Adds the time the rest of the chain took to every response.
+    #[derive(Debug)]
+    struct ResponseTiming;
+
+    impl WrapMiddleware for ResponseTiming {
+        fn before(&self, request: &mut Request) -> Result<()> {
+            request.headers.insert("x-timed".to_string(), "1".to_string());
+            Ok(())
+        }
+
+        fn after(&self, request: &Request, response: &mut PaymentResponse) -> Result<()> {
+            let elapsed = request.created_at.elapsed().as_micros().to_string();
+            response.headers.insert("x-response-time-us".to_string(), elapsed);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn wrap_middleware_sees_the_request_and_the_response() {
+        let server = MockServer::start().await;
+        Mock::given(wiremock::matchers::header("x-timed", "1"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let http_client = Arc::new(HttpClient::new(&Arc::new(Config::default())).await.unwrap());
+        let stack = MiddlewareStack::new();
+        stack.wrap_around(ResponseTiming);
+
+        let request = Request::new(reqwest::Method::GET, &server.uri()).unwrap();
+        let response = stack.execute(request, &http_client).await.unwrap();
+        assert_eq!(response.status, 200);
+        assert!(response.header("x-response-time-us").unwrap().parse::<u64>().is_ok());
+    }
+}
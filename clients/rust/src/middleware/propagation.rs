@@ -0,0 +1,217 @@
+//! Trace context propagation for outgoing requests.
+
+use super::{Middleware, Next};
+use crate::{error::Result, http::Request, types::PaymentResponse};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+use uuid::Uuid;
+
+/// Header name of the `Server-Timing` response header.
+const SERVER_TIMING_HEADER: &str = "server-timing";
+
+/// Wire format used to propagate the trace context.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PropagationFormat {
+    /// W3C Trace Context: `traceparent` and `tracestate`
+    W3c,
+    /// Zipkin B3 multi-header: `X-B3-TraceId`, `X-B3-SpanId` and `X-B3-Sampled`
+    B3,
+    /// Zipkin B3 single header: `b3`
+    B3Single,
+}
+
+impl PropagationFormat {
+    /// Headers written by this format.
+    fn headers(&self) -> &'static [&'static str] {
+        match self {
+            PropagationFormat::W3c => &["traceparent"],
+            PropagationFormat::B3 => &["x-b3-traceid", "x-b3-spanid", "x-b3-sampled"],
+            PropagationFormat::B3Single => &["b3"],
+        }
+    }
+}
+
+/// Injects trace context headers into every request and records the
+/// server's `Server-Timing` metrics as events on the current span.
+///
+/// With the `opentelemetry` feature, the trace and span IDs come from the
+/// OpenTelemetry context of the current `tracing` span. Otherwise, or when
+/// that span is not being exported, the request's correlation ID is used as
+/// the trace ID so that logs and upstream traces can be joined on it, and
+/// every request gets a fresh span ID.
+///
+/// Requests that already carry headers of the chosen format are left unchanged.
+///
+/// ```rust
+/// use v402_client::middleware::{PropagationFormat, TracingPropagationMiddleware};
+/// # async fn example(client: v402_client::Client) {
+/// client.add_middleware(Box::new(TracingPropagationMiddleware::new(PropagationFormat::W3c)));
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct TracingPropagationMiddleware {
+    format: PropagationFormat,
+}
+
+impl TracingPropagationMiddleware {
+    /// Creates a middleware propagating the trace context in `format`.
+    pub fn new(format: PropagationFormat) -> Self {
+        Self { format }
+    }
+
+    /// Writes the context to the request in the configured format.
+    fn inject(&self, context: &TraceContext, request: &mut Request) {
+        let trace_id = hex::encode(context.trace_id);
+        let span_id = hex::encode(context.span_id);
+        let sampled = if context.sampled { "1" } else { "0" };
+
+        match self.format {
+            PropagationFormat::W3c => {
+                request.headers.insert(
+                    "traceparent".to_string(),
+                    format!("00-{}-{}-0{}", trace_id, span_id, sampled),
+                );
+                if let Some(state) = &context.trace_state {
+                    request.headers.insert("tracestate".to_string(), state.clone());
+                }
+            }
+            PropagationFormat::B3 => {
+                request.headers.insert("X-B3-TraceId".to_string(), trace_id);
+                request.headers.insert("X-B3-SpanId".to_string(), span_id);
+                request.headers.insert("X-B3-Sampled".to_string(), sampled.to_string());
+            }
+            PropagationFormat::B3Single => {
+                request
+                    .headers
+                    .insert("b3".to_string(), format!("{}-{}-{}", trace_id, span_id, sampled));
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Middleware for TracingPropagationMiddleware {
+    async fn handle(&self, mut request: Request, next: Next<'_>) -> Result<PaymentResponse> {
+        let present = self
+            .format
+            .headers()
+            .iter()
+            .any(|name| request.headers.keys().any(|key| key.eq_ignore_ascii_case(name)));
+        if !present {
+            let context = TraceContext::current(&request);
+            self.inject(&context, &mut request);
+        }
+
+        let response = next.run(request).await?;
+
+        if let Some(header) = response.header(SERVER_TIMING_HEADER) {
+            for metric in parse_server_timing(header) {
+                debug!(
+                    server_timing = %metric.name,
+                    duration_ms = metric.duration_ms,
+                    description = metric.description.as_deref(),
+                    "Server-Timing"
+                );
+            }
+        }
+
+        Ok(response)
+    }
+}
+
+/// Identifiers propagated to the upstream service.
+#[derive(Debug, Clone)]
+struct TraceContext {
+    trace_id: [u8; 16],
+    span_id: [u8; 8],
+    sampled: bool,
+    trace_state: Option<String>,
+}
+
+impl TraceContext {
+    /// Context of the current span, falling back to one derived from the request.
+    fn current(request: &Request) -> Self {
+        #[cfg(feature = "opentelemetry")]
+        if let Some(context) = Self::from_opentelemetry() {
+            return context;
+        }
+
+        let trace_id = Uuid::parse_str(&request.request_id)
+            .ok()
+            .filter(|id| !id.is_nil())
+            .unwrap_or_else(Uuid::new_v4);
+        let mut span_id = [0u8; 8];
+        span_id.copy_from_slice(&Uuid::new_v4().as_bytes()[..8]);
+
+        Self {
+            trace_id: *trace_id.as_bytes(),
+            span_id,
+            sampled: true,
+            trace_state: None,
+        }
+    }
+
+    /// Context of the current span as seen by `tracing-opentelemetry`.
+    #[cfg(feature = "opentelemetry")]
+    fn from_opentelemetry() -> Option<Self> {
+        use opentelemetry::trace::TraceContextExt;
+        use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+        let context = tracing::Span::current().context();
+        let span = context.span();
+        let span_context = span.span_context();
+        if !span_context.is_valid() {
+            return None;
+        }
+
+        let trace_state = span_context.trace_state().header();
+        Some(Self {
+            trace_id: span_context.trace_id().to_bytes(),
+            span_id: span_context.span_id().to_bytes(),
+            sampled: span_context.is_sampled(),
+            trace_state: Some(trace_state).filter(|state| !state.is_empty()),
+        })
+    }
+}
+
+/// One metric of a `Server-Timing` header.
+#[derive(Debug, Clone, PartialEq)]
+struct ServerTimingMetric {
+    name: String,
+    duration_ms: Option<f64>,
+    description: Option<String>,
+}
+
+/// Parses a `Server-Timing` header, e.g. `db;dur=53.2, cache;desc="Cache Read";dur=2`.
+///
+/// Malformed parameters are ignored rather than rejecting the whole header.
+fn parse_server_timing(header: &str) -> Vec<ServerTimingMetric> {
+    header
+        .split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.split(';');
+            let name = parts.next()?.trim();
+            if name.is_empty() {
+                return None;
+            }
+
+            let mut metric = ServerTimingMetric {
+                name: name.to_string(),
+                duration_ms: None,
+                description: None,
+            };
+            for param in parts {
+                let Some((key, value)) = param.split_once('=') else { continue };
+                let value = value.trim().trim_matches('"');
+                match key.trim().to_ascii_lowercase().as_str() {
+                    "dur" => metric.duration_ms = value.parse().ok(),
+                    "desc" => metric.description = Some(value.to_string()),
+                    _ => {}
+                }
+            }
+            Some(metric)
+        })
+        .collect()
+}
@@ -0,0 +1,79 @@
+//! Request ID propagation for correlating requests across services.
+
+use super::{Middleware, Next};
+use crate::{config::DEFAULT_REQUEST_ID_HEADER, error::Result, http::Request, types::PaymentResponse};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use tracing::{info_span, Instrument};
+use uuid::Uuid;
+
+/// Sends a request ID header with every request and adopts the ID the
+/// server echoes back.
+///
+/// Requests that already carry the header, such as one propagated from an
+/// upstream caller, keep it unchanged; others get a random UUID v4. The ID
+/// becomes the request's [`request_id`](Request::request_id) and is recorded
+/// as the `request_id` field of a span around the rest of the chain. If the
+/// response carries the header, its value replaces
+/// [`PaymentResponse::request_id`].
+///
+/// The client already sends its correlation ID in
+/// [`Config::request_id_header`](crate::Config::request_id_header); use this
+/// middleware to send it under another name or to pick up the server's ID.
+///
+/// ```rust
+/// use v402_client::middleware::RequestIdMiddleware;
+/// # async fn example(client: v402_client::Client) {
+/// client.add_middleware(Box::new(RequestIdMiddleware::new("X-Correlation-ID")));
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct RequestIdMiddleware {
+    header_name: String,
+}
+
+impl Default for RequestIdMiddleware {
+    fn default() -> Self {
+        Self::new(DEFAULT_REQUEST_ID_HEADER)
+    }
+}
+
+impl RequestIdMiddleware {
+    /// Creates a middleware sending the request ID in `header_name`.
+    pub fn new(header_name: &str) -> Self {
+        Self {
+            header_name: header_name.to_string(),
+        }
+    }
+
+    /// Returns the value of the request ID header in `headers`, matching its name case-insensitively.
+    fn find<'a>(&self, headers: &'a HashMap<String, String>) -> Option<&'a String> {
+        headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(&self.header_name))
+            .map(|(_, value)| value)
+    }
+}
+
+#[async_trait]
+impl Middleware for RequestIdMiddleware {
+    async fn handle(&self, mut request: Request, next: Next<'_>) -> Result<PaymentResponse> {
+        let request_id = match self.find(&request.headers) {
+            Some(request_id) => request_id.clone(),
+            None => {
+                let request_id = Uuid::new_v4().to_string();
+                request.headers.insert(self.header_name.clone(), request_id.clone());
+                request_id
+            }
+        };
+        request.request_id = request_id.clone();
+
+        let span = info_span!("request_id", request_id = %request_id);
+        let mut response = next.run(request).instrument(span).await?;
+
+        if let Some(echoed) = self.find(&response.headers).filter(|echoed| !echoed.is_empty()) {
+            response.request_id = echoed.clone();
+        }
+        Ok(response)
+    }
+}
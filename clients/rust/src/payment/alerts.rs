@@ -0,0 +1,98 @@
+//! Per-host spend alerts.
+
+use crate::{config::SpendAlertConfig, events::SpendAlert};
+use parking_lot::{Mutex, RwLock};
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    time::Instant,
+};
+
+/// Callback invoked when spend to a host crosses its alert threshold.
+///
+/// Handlers run synchronously on the task that recorded the payment, so
+/// they should return quickly; hand longer work off to a channel or task.
+pub trait SpendAlertHandler: Send + Sync + fmt::Debug {
+    /// Called once each time a host's spend reaches its threshold.
+    fn on_spend_alert(&self, alert: &SpendAlert);
+}
+
+/// Sums spend per host over a sliding window and raises alerts.
+#[derive(Debug)]
+pub(crate) struct SpendTracker {
+    config: SpendAlertConfig,
+    hosts: Mutex<HashMap<String, HostSpend>>,
+    handlers: RwLock<Vec<Box<dyn SpendAlertHandler>>>,
+}
+
+/// Payments to one host within the window.
+#[derive(Debug, Default)]
+struct HostSpend {
+    payments: VecDeque<(Instant, u128)>,
+    /// Whether the host is above its threshold and has been alerted
+    alerted: bool,
+}
+
+impl SpendTracker {
+    /// Creates a tracker for `config`.
+    pub(crate) fn new(config: &SpendAlertConfig) -> Self {
+        Self {
+            config: config.clone(),
+            hosts: Mutex::new(HashMap::new()),
+            handlers: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Adds a handler called on every alert.
+    pub(crate) fn add_handler(&self, handler: Box<dyn SpendAlertHandler>) {
+        self.handlers.write().push(handler);
+    }
+
+    /// Records a payment of `amount` to `url`, returning the alert it raised, if any.
+    ///
+    /// Handlers are called before returning; publishing the alert is left
+    /// to the caller.
+    pub(crate) fn record(&self, url: &str, amount: u128) -> Option<SpendAlert> {
+        let parsed = url::Url::parse(url).ok()?;
+        let threshold = self.config.threshold_for(&parsed)?;
+        let limit = threshold.parse::<u128>().ok()?;
+        let host = parsed.host_str()?.to_ascii_lowercase();
+
+        let now = Instant::now();
+        let alert = {
+            let mut hosts = self.hosts.lock();
+            let spend = hosts.entry(host.clone()).or_default();
+            spend.payments.push_back((now, amount));
+            while let Some((at, _)) = spend.payments.front() {
+                if now.duration_since(*at) < self.config.window {
+                    break;
+                }
+                spend.payments.pop_front();
+            }
+
+            let spent = spend.payments.iter().fold(0u128, |sum, (_, amount)| sum.saturating_add(*amount));
+            if spent < limit {
+                spend.alerted = false;
+                return None;
+            }
+            if spend.alerted {
+                return None;
+            }
+            spend.alerted = true;
+
+            SpendAlert {
+                host,
+                url: url.to_string(),
+                spent: spent.to_string(),
+                threshold: threshold.to_string(),
+                payments: spend.payments.len() as u64,
+                window: self.config.window,
+            }
+        };
+
+        for handler in self.handlers.read().iter() {
+            handler.on_spend_alert(&alert);
+        }
+        Some(alert)
+    }
+}
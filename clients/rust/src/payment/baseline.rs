@@ -0,0 +1,170 @@
+//! Detection of sudden price increases.
+
+use crate::{
+    config::{PriceJumpAction, PriceJumpConfig},
+    events::PriceJump,
+};
+use async_trait::async_trait;
+use parking_lot::{Mutex, RwLock};
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    sync::Arc,
+};
+
+/// Decides whether a payment well above a resource's usual price goes ahead.
+///
+/// Consulted when [`PriceJumpConfig::action`] is [`PriceJumpAction::Approve`].
+#[async_trait]
+pub trait PaymentApprover: Send + Sync + fmt::Debug {
+    /// Returns `true` to pay despite the jump.
+    async fn approve(&self, jump: &PriceJump) -> bool;
+}
+
+/// Recent prices quoted per host and path prefix.
+#[derive(Debug)]
+pub(crate) struct PriceBaselines {
+    config: PriceJumpConfig,
+    /// Prices quoted per `(host, path prefix)`, oldest first
+    prices: Mutex<HashMap<(String, String), VecDeque<u128>>>,
+    approver: RwLock<Option<Arc<dyn PaymentApprover>>>,
+}
+
+impl PriceBaselines {
+    /// Creates baselines for `config`.
+    pub(crate) fn new(config: &PriceJumpConfig) -> Self {
+        Self {
+            config: config.clone(),
+            prices: Mutex::new(HashMap::new()),
+            approver: RwLock::new(None),
+        }
+    }
+
+    /// Sets the approver consulted for jumps, replacing any previous one.
+    pub(crate) fn set_approver(&self, approver: Box<dyn PaymentApprover>) {
+        *self.approver.write() = Some(Arc::from(approver));
+    }
+
+    /// Returns the jump `amount` makes for `url`, if it exceeds the baseline
+    /// by more than the configured multiplier.
+    ///
+    /// The baseline is the median of the recent prices, so a single spike
+    /// does not raise it; a new price becomes the baseline once it makes up
+    /// more than half the history.
+    pub(crate) fn detect(&self, url: &str, amount: u128) -> Option<PriceJump> {
+        let multiplier = self.config.multiplier?;
+        let (host, path_prefix) = self.key(url)?;
+
+        let baseline = {
+            let prices = self.prices.lock();
+            let mut history: Vec<u128> = prices.get(&(host.clone(), path_prefix.clone()))?.iter().copied().collect();
+            history.sort_unstable();
+            history[(history.len() - 1) / 2]
+        };
+
+        let ratio = amount as f64 / baseline.max(1) as f64;
+        (ratio > multiplier).then(|| PriceJump {
+            host,
+            path_prefix,
+            url: url.to_string(),
+            amount: amount.to_string(),
+            baseline: baseline.to_string(),
+            ratio,
+            action: self.config.action,
+        })
+    }
+
+    /// Decides whether to pay despite `jump`, consulting the approver if the
+    /// action requires approval. Payments needing approval are refused when
+    /// no approver is set.
+    pub(crate) async fn allows(&self, jump: &PriceJump) -> bool {
+        match jump.action {
+            PriceJumpAction::Warn => true,
+            PriceJumpAction::Reject => false,
+            PriceJumpAction::Approve => {
+                let approver = self.approver.read().clone();
+                match approver {
+                    Some(approver) => approver.approve(jump).await,
+                    None => false,
+                }
+            }
+        }
+    }
+
+    /// Records a price quoted for `url`, dropping the oldest once the history is full.
+    pub(crate) fn record(&self, url: &str, amount: u128) {
+        if self.config.multiplier.is_none() {
+            return;
+        }
+        let Some(key) = self.key(url) else { return };
+
+        let mut prices = self.prices.lock();
+        let history = prices.entry(key).or_default();
+        if history.len() >= self.config.history_size.max(1) {
+            history.pop_front();
+        }
+        history.push_back(amount);
+    }
+
+    /// Returns the host and the first [`path_depth`](PriceJumpConfig::path_depth) path segments of `url`.
+    fn key(&self, url: &str) -> Option<(String, String)> {
+        let url = url::Url::parse(url).ok()?;
+        let host = url.host_str()?.to_ascii_lowercase();
+        let segments: Vec<_> = url
+            .path_segments()
+            .into_iter()
+            .flatten()
+            .filter(|segment| !segment.is_empty())
+            .take(self.config.path_depth)
+            .collect();
+        Some((host, format!("/{}", segments.join("/"))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn baselines() -> PriceBaselines {
+        PriceBaselines::new(&PriceJumpConfig {
+            multiplier: Some(2.0),
+            action: PriceJumpAction::Reject,
+            history_size: 4,
+            path_depth: 1,
+        })
+    }
+
+    /// Quotes recorded as `detect_price_jump` does, before the payment decision.
+    fn quote(prices: &PriceBaselines, amount: u128) -> Option<PriceJump> {
+        let jump = prices.detect("https://api.example.com/data/item", amount);
+        prices.record("https://api.example.com/data/other", amount);
+        jump
+    }
+
+    #[test]
+    fn single_spike_does_not_raise_baseline() {
+        let prices = baselines();
+        for _ in 0..3 {
+            assert!(quote(&prices, 100).is_none());
+        }
+
+        let jump = quote(&prices, 1_000).expect("spike is a jump");
+        assert_eq!(jump.baseline, "100");
+        assert_eq!(jump.path_prefix, "/data");
+        assert!(quote(&prices, 100).is_none());
+    }
+
+    #[test]
+    fn refused_price_becomes_baseline() {
+        let prices = baselines();
+        for _ in 0..4 {
+            quote(&prices, 100);
+        }
+
+        for _ in 0..3 {
+            assert!(quote(&prices, 1_000).is_some());
+        }
+        // Most of the history is now at the new price, which no longer counts as a jump
+        assert!(quote(&prices, 1_000).is_none());
+    }
+}
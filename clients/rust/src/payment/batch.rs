@@ -0,0 +1,406 @@
+//! Paying several providers in one transaction.
+//!
+//! [`PaymentManager::batch_pay`] sends a single transaction calling
+//! `batchTransfer(address[],uint256[])` on the batch contract configured for
+//! the token with [`ChainConfig::with_batch_contract`], so either every payee
+//! is paid or none is. The contract's ABI is bundled as [`BATCH_TRANSFER_ABI`].
+//!
+//! The payments are recorded as pending until the transaction is included,
+//! then as confirmed, or failed if it reverted. On dry-run chains the
+//! transaction is only simulated and the payments are recorded as confirmed
+//! dry runs.
+//!
+//! [`ChainConfig::with_batch_contract`]: crate::ChainConfig::with_batch_contract
+
+use super::{schema, PaymentManager};
+use crate::{
+    config::DEFAULT_WALLET,
+    crypto,
+    error::{Error, Result},
+    payment::AssetInfo,
+    types::{PaymentHistory, PaymentStatus},
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::info;
+use uuid::Uuid;
+
+/// JSON ABI of the `batchTransfer` function called by [`PaymentManager::batch_pay`].
+pub const BATCH_TRANSFER_ABI: &str = include_str!("abi/batch_transfer.json");
+
+/// Signature of the batch transfer function.
+const BATCH_TRANSFER_SIGNATURE: &str = "batchTransfer(address[],uint256[])";
+
+/// One transfer of a batch payment.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SinglePayment {
+    /// Address receiving the payment
+    pub payee: String,
+
+    /// Amount in the token's smallest unit
+    pub amount: String,
+
+    /// Token contract address
+    pub token: String,
+
+    /// Network the payment is made on
+    pub chain: String,
+}
+
+/// Outcome of a batch payment.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchPaymentReceipt {
+    /// Hash of the transaction paying every payee, `None` on dry-run chains
+    pub tx_hash: Option<String>,
+
+    /// Payment records, in the order the payments were given
+    pub payments: Vec<PaymentHistory>,
+}
+
+impl PaymentManager {
+    /// Pays every payment of `payments` in one transaction sent from the
+    /// default wallet.
+    ///
+    /// All payments must be made in the same token on the same EVM chain,
+    /// which needs a [`chain_id`](crate::ChainConfig::chain_id) and a batch
+    /// contract for the token. Each payment must be within the per-request
+    /// maximum and the default wallet's limit, and is recorded in the
+    /// history under a shared batch ID, as pending until the transaction is
+    /// included.
+    pub async fn batch_pay(&self, payments: Vec<SinglePayment>) -> Result<BatchPaymentReceipt> {
+        let first = payments
+            .first()
+            .ok_or_else(|| Error::Payment("A batch payment needs at least one payment".to_string().into()))?;
+        if let Some(other) = payments
+            .iter()
+            .find(|payment| payment.chain != first.chain || !payment.token.eq_ignore_ascii_case(&first.token))
+        {
+            return Err(Error::Payment(format!(
+                "Batch payments must share one token and chain, got {} on {} and {} on {}",
+                first.token, first.chain, other.token, other.chain
+            ).into()));
+        }
+        let (chain, token) = (first.chain.clone(), first.token.clone());
+        let chain_config = self.chain_manager.chain(&chain)?;
+        let dry_run = chain_config.dry_run;
+        let contract = chain_config
+            .batch_contract(&token)
+            .ok_or_else(|| Error::Config(format!("No batch contract configured for {} on {}", token, chain).into()))?
+            .to_string();
+
+        let wallet = self.config.default_wallet.clone().unwrap_or_else(|| DEFAULT_WALLET.to_string());
+        let token_info = self.chain_manager.tokens().get(&chain, &token);
+        let asset = AssetInfo {
+            address: token.clone(),
+            symbol: token_info.as_ref().map(|t| t.symbol.clone()),
+            decimals: token_info.as_ref().map(|t| t.decimals),
+            name: None,
+            version: None,
+        };
+        let mut total: u128 = 0;
+        for payment in &payments {
+            self.check_asset_amount(&chain, Some(&asset), &payment.amount, &wallet).await?;
+            let amount: u128 = payment.amount.parse().unwrap_or(0);
+            total = total
+                .checked_add(amount)
+                .ok_or_else(|| Error::Payment("Batch payment total overflows".to_string().into()))?;
+        }
+
+        let calldata = encode_batch_transfer(&payments)?;
+        let payer = self.chain_manager.address(&chain)?;
+        let tx_hash = self.chain_manager.send_transaction(&chain, &payer, &contract, &calldata).await?;
+
+        let batch_id = format!("batch_{}", Uuid::new_v4().simple());
+        let records: Vec<_> = payments
+            .into_iter()
+            .map(|payment| PaymentHistory {
+                schema_version: schema::CURRENT_SCHEMA_VERSION,
+                payment_id: format!("pay_{}", Uuid::new_v4().simple()),
+                url: String::new(),
+                amount: payment.amount,
+                asset: payment.token,
+                asset_decimals: token_info.as_ref().map(|t| t.decimals),
+                asset_symbol: token_info.as_ref().map(|t| t.symbol.clone()),
+                usd_value: None,
+                transaction_hash: tx_hash.clone(),
+                network: payment.chain.into(),
+                payer: Some(payer.clone()),
+                description: format!("Batch transfer to {}", payment.payee),
+                payee: payment.payee,
+                timestamp: Utc::now(),
+                status: if dry_run { PaymentStatus::Confirmed } else { PaymentStatus::Pending },
+                metadata: HashMap::new(),
+                request_id: None,
+                batch_id: Some(batch_id.clone()),
+                facilitator: None,
+                timing: None,
+                receipt: None,
+                wallet: Some(wallet.clone()),
+                dry_run,
+                authorization_nonce: None,
+            })
+            .collect();
+
+        if let Some(tx_hash) = &tx_hash {
+            self.track_pending(tx_hash, &chain, &total.to_string());
+        }
+        for record in &records {
+            self.push_history(record.clone());
+        }
+        info!(tx_hash = ?tx_hash, %batch_id, payments = records.len(), dry_run, "Sent batch payment");
+
+        Ok(BatchPaymentReceipt {
+            tx_hash,
+            payments: records,
+        })
+    }
+}
+
+/// ABI-encodes the `batchTransfer` call paying `payments`.
+fn encode_batch_transfer(payments: &[SinglePayment]) -> Result<Vec<u8>> {
+    let count = payments.len() as u128;
+    let mut calldata = crypto::function_selector(BATCH_TRANSFER_SIGNATURE).to_vec();
+
+    // Heads of the two dynamic arrays: offsets from the start of the arguments
+    calldata.extend(crypto::uint_word(0x40));
+    calldata.extend(crypto::uint_word(0x40 + 0x20 * (count + 1)));
+
+    calldata.extend(crypto::uint_word(count));
+    for payment in payments {
+        calldata.extend(crypto::address_word(&crypto::parse_address(&payment.payee)?));
+    }
+    calldata.extend(crypto::uint_word(count));
+    for payment in payments {
+        calldata.extend(
+            crypto::decimal_word(&payment.amount)
+                .map_err(|_| Error::Payment(format!("Invalid amount {} for {}", payment.amount, payment.payee).into()))?,
+        );
+    }
+    Ok(calldata)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        chains::ChainManager,
+        config::{ChainConfig, Config, WalletConfig},
+    };
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+    use wiremock::{matchers::body_partial_json, Mock, MockServer, ResponseTemplate};
+
+    const TOKEN: &str = "0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913";
+    const CONTRACT: &str = "0x2222222222222222222222222222222222222222";
+    const PAYEES: [&str; 3] = [
+        "0x1111111111111111111111111111111111111111",
+        "0x3333333333333333333333333333333333333333",
+        "0x4444444444444444444444444444444444444444",
+    ];
+
+    fn payments() -> Vec<SinglePayment> {
+        PAYEES
+            .iter()
+            .zip(["1000", "2500", "70000"])
+            .map(|(payee, amount)| SinglePayment {
+                payee: payee.to_string(),
+                amount: amount.to_string(),
+                token: TOKEN.to_string(),
+                chain: "base".to_string(),
+            })
+            .collect()
+    }
+
+    /// Answers the JSON-RPC `method` with `result`.
+    async fn rpc(server: &MockServer, method: &str, result: Value) {
+        Mock::given(body_partial_json(json!({ "method": method })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "jsonrpc": "2.0", "id": 1, "result": result })))
+            .mount(server)
+            .await;
+    }
+
+    /// Returns the params of the first `method` call received by `server`.
+    async fn params(server: &MockServer, method: &str) -> Value {
+        server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .filter_map(|request| serde_json::from_slice::<Value>(&request.body).ok())
+            .find(|body| body["method"] == method)
+            .map(|body| body["params"].clone())
+            .unwrap_or_else(|| panic!("no {method} call"))
+    }
+
+    #[test]
+    fn calldata_encodes_every_recipient() {
+        let calldata = encode_batch_transfer(&payments()).unwrap();
+
+        assert_eq!(calldata[..4], crypto::function_selector(BATCH_TRANSFER_SIGNATURE));
+        let words: Vec<&[u8]> = calldata[4..].chunks(32).collect();
+        assert_eq!(words.len(), 2 + 2 * (1 + PAYEES.len()));
+        assert_eq!(words[0], crypto::uint_word(0x40));
+        assert_eq!(words[1], crypto::uint_word(0x40 + 0x20 * 4));
+        assert_eq!(words[2], crypto::uint_word(3));
+        for (index, payee) in PAYEES.iter().enumerate() {
+            assert_eq!(words[3 + index], crypto::address_word(&crypto::parse_address(payee).unwrap()));
+        }
+        assert_eq!(words[6], crypto::uint_word(3));
+        for (index, amount) in [1000, 2500, 70000].into_iter().enumerate() {
+            assert_eq!(words[7 + index], crypto::uint_word(amount));
+        }
+    }
+
+    #[test]
+    fn calldata_rejects_invalid_amounts() {
+        let mut payments = payments();
+        payments[1].amount = "1.5".to_string();
+        assert!(matches!(encode_batch_transfer(&payments), Err(Error::Payment(_))));
+    }
+
+    #[tokio::test]
+    async fn batch_pay_calls_batch_contract_and_records_pending() {
+        let server = MockServer::start().await;
+        let tx_hash = format!("0x{}", "ab".repeat(32));
+        rpc(&server, "eth_getTransactionCount", json!("0x7")).await;
+        rpc(&server, "eth_estimateGas", json!("0x15f90")).await;
+        rpc(&server, "eth_maxPriorityFeePerGas", json!("0x3b9aca00")).await;
+        rpc(&server, "eth_getBlockByNumber", json!({ "baseFeePerGas": "0x3b9aca00" })).await;
+        rpc(&server, "eth_sendRawTransaction", json!(tx_hash)).await;
+
+        let config = Arc::new(Config {
+            chains: vec![ChainConfig::base_mainnet()
+                .with_rpc_url(server.uri())
+                .with_batch_contract(TOKEN.to_ascii_lowercase(), CONTRACT)],
+            private_key: Some("0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318".to_string().into()),
+            ..Config::default()
+        });
+        let chain_manager = Arc::new(ChainManager::new(&config).await.unwrap());
+        let manager = PaymentManager::new(&config, &chain_manager).await.unwrap();
+
+        let receipt = manager.batch_pay(payments()).await.unwrap();
+        assert_eq!(receipt.tx_hash.as_deref(), Some(tx_hash.as_str()));
+        assert_eq!(receipt.payments.len(), 3);
+        assert!(receipt
+            .payments
+            .iter()
+            .all(|record| record.status == PaymentStatus::Pending && record.transaction_hash.as_deref() == Some(tx_hash.as_str())));
+
+        // Sent to the batch contract, not the token, carrying every recipient
+        assert_eq!(params(&server, "eth_estimateGas").await[0]["to"], CONTRACT);
+        let raw = params(&server, "eth_sendRawTransaction").await[0].as_str().unwrap().to_string();
+        assert!(raw.contains(&hex::encode(encode_batch_transfer(&payments()).unwrap())));
+        assert!(raw.contains(&CONTRACT[2..]));
+
+        rpc(&server, "eth_getTransactionReceipt", json!({ "status": "0x1" })).await;
+        manager.poll_pending_payments().await;
+        assert_eq!(manager.pending_count(), 0);
+        let history = manager.get_history(10).await.unwrap();
+        assert_eq!(history.len(), 3);
+        assert!(history.iter().all(|record| record.status == PaymentStatus::Confirmed));
+    }
+
+    #[tokio::test]
+    async fn batch_pay_on_dry_run_chains_only_simulates() {
+        let server = MockServer::start().await;
+        rpc(&server, "eth_call", json!("0x")).await;
+
+        let config = Arc::new(Config {
+            chains: vec![ChainConfig::base_mainnet()
+                .with_rpc_url(server.uri())
+                .with_batch_contract(TOKEN, CONTRACT)
+                .with_dry_run(true)],
+            private_key: Some("0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318".to_string().into()),
+            ..Config::default()
+        });
+        let chain_manager = Arc::new(ChainManager::new(&config).await.unwrap());
+        let manager = PaymentManager::new(&config, &chain_manager).await.unwrap();
+
+        let receipt = manager.batch_pay(payments()).await.unwrap();
+        assert_eq!(receipt.tx_hash, None);
+        assert!(receipt
+            .payments
+            .iter()
+            .all(|record| record.dry_run && record.status == PaymentStatus::Confirmed && record.transaction_hash.is_none()));
+        assert_eq!(manager.pending_count(), 0);
+
+        let call = &params(&server, "eth_call").await[0];
+        assert_eq!(call["to"], CONTRACT);
+        assert_eq!(call["data"], format!("0x{}", hex::encode(encode_batch_transfer(&payments()).unwrap())));
+        let methods: Vec<Value> = server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .filter_map(|request| serde_json::from_slice::<Value>(&request.body).ok())
+            .map(|body| body["method"].clone())
+            .collect();
+        assert_eq!(methods, [json!("eth_call")]);
+    }
+
+    #[tokio::test]
+    async fn batch_pay_applies_the_amount_limits_to_each_payment() {
+        let server = MockServer::start().await;
+        let key = "0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+        let chains = vec![ChainConfig::base_mainnet()
+            .with_rpc_url(server.uri())
+            .with_batch_contract(TOKEN, CONTRACT)];
+
+        let config = Arc::new(Config {
+            chains: chains.clone(),
+            private_key: Some(key.to_string().into()),
+            max_amount_per_request: Some("50000".to_string()),
+            ..Config::default()
+        });
+        let chain_manager = Arc::new(ChainManager::new(&config).await.unwrap());
+        let manager = PaymentManager::new(&config, &chain_manager).await.unwrap();
+        let err = manager.batch_pay(payments()).await.unwrap_err();
+        assert!(matches!(err, Error::Payment(ref message) if message.contains("exceeds maximum")), "{err}");
+
+        let mut wallet = WalletConfig::new("ops", key);
+        wallet.max_amount_per_request = Some("2000".to_string());
+        let config = Arc::new(Config {
+            chains,
+            wallets: vec![wallet],
+            default_wallet: Some("ops".to_string()),
+            ..Config::default()
+        });
+        let chain_manager = Arc::new(ChainManager::new(&config).await.unwrap());
+        let manager = PaymentManager::new(&config, &chain_manager).await.unwrap();
+        let err = manager.batch_pay(payments()).await.unwrap_err();
+        assert!(matches!(err, Error::Payment(ref message) if message.contains("wallet ops")), "{err}");
+
+        // Nothing reaches the chain once a payment is over a limit
+        assert!(server.received_requests().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn batch_pay_rejects_totals_overflowing_u128() {
+        let config = Arc::new(Config {
+            chains: vec![ChainConfig::base_mainnet().with_batch_contract(TOKEN, CONTRACT)],
+            max_amount_per_request: Some(u128::MAX.to_string()),
+            ..Config::default()
+        });
+        let chain_manager = Arc::new(ChainManager::new(&config).await.unwrap());
+        let manager = PaymentManager::new(&config, &chain_manager).await.unwrap();
+
+        let mut payments = payments();
+        payments[0].amount = u128::MAX.to_string();
+        let err = manager.batch_pay(payments).await.unwrap_err();
+        assert!(matches!(err, Error::Payment(ref message) if message.contains("overflows")), "{err}");
+    }
+
+    #[tokio::test]
+    async fn batch_pay_needs_batch_contract() {
+        let config = Arc::new(Config {
+            chains: vec![ChainConfig::base_mainnet()],
+            ..Config::default()
+        });
+        let chain_manager = Arc::new(ChainManager::new(&config).await.unwrap());
+        let manager = PaymentManager::new(&config, &chain_manager).await.unwrap();
+
+        let err = manager.batch_pay(payments()).await.unwrap_err();
+        assert!(matches!(err, Error::Config(ref message) if message.contains("No batch contract")), "{err}");
+    }
+}
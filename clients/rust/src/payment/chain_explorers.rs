@@ -0,0 +1,154 @@
+//! Block explorer links for settlement transactions.
+//!
+//! Transactions on the networks in [`KNOWN_EXPLORERS`] link to their
+//! public explorer. Explorers of other networks, or replacements for the
+//! built-in ones, are registered with
+//! [`PaymentManager::register_explorer`](super::PaymentManager::register_explorer), and the
+//! [`explorer_url`](crate::ChainConfig::explorer_url) of every configured
+//! chain is registered when the manager is created. Registrations belong to
+//! the manager and are resolved with
+//! [`PaymentManager::explorer_url`](super::PaymentManager::explorer_url).
+
+use crate::{chains::NetworkId, types::PaymentHistory};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use std::collections::HashMap;
+use url::Url;
+
+/// Transaction URL prefix of the built-in explorers, by canonical network name.
+///
+/// ```rust
+/// use v402_client::payment::chain_explorers::KNOWN_EXPLORERS;
+///
+/// assert_eq!(KNOWN_EXPLORERS["base"], "https://basescan.org/tx/");
+/// ```
+pub static KNOWN_EXPLORERS: Lazy<HashMap<&'static str, &'static str>> = Lazy::new(|| {
+    HashMap::from([
+        ("ethereum", "https://etherscan.io/tx/"),
+        ("ethereum-sepolia", "https://sepolia.etherscan.io/tx/"),
+        ("base", "https://basescan.org/tx/"),
+        ("base-sepolia", "https://sepolia.basescan.org/tx/"),
+        ("polygon", "https://polygonscan.com/tx/"),
+        ("arbitrum", "https://arbiscan.io/tx/"),
+        ("optimism", "https://optimistic.etherscan.io/tx/"),
+        ("avalanche", "https://snowtrace.io/tx/"),
+        ("avalanche-fuji", "https://testnet.snowtrace.io/tx/"),
+        ("bsc", "https://bscscan.com/tx/"),
+        ("bsc-testnet", "https://testnet.bscscan.com/tx/"),
+        ("solana", "https://explorer.solana.com/tx/"),
+    ])
+});
+
+/// Returns the transaction URL prefix of `network` among the
+/// [`KNOWN_EXPLORERS`], if it has one.
+pub fn explorer_for(network: &NetworkId) -> Option<String> {
+    KNOWN_EXPLORERS.get(network.as_str()).map(|prefix| prefix.to_string())
+}
+
+/// Transaction URL prefixes registered with one payment manager, by
+/// canonical network name, on top of the [`KNOWN_EXPLORERS`].
+#[derive(Debug, Default)]
+pub(crate) struct ExplorerRegistry {
+    registered: RwLock<HashMap<String, String>>,
+}
+
+impl ExplorerRegistry {
+    /// Registers `prefix` as the transaction URL prefix of `network`,
+    /// replacing any earlier or built-in one.
+    pub(crate) fn register(&self, network: &NetworkId, prefix: &str) {
+        self.registered.write().insert(network.as_str().to_string(), prefix.to_string());
+    }
+
+    /// Returns the transaction URL prefix of `network`, if it has an explorer.
+    pub(crate) fn explorer_for(&self, network: &NetworkId) -> Option<String> {
+        if let Some(prefix) = self.registered.read().get(network.as_str()) {
+            return Some(prefix.clone());
+        }
+        explorer_for(network)
+    }
+
+    /// Returns the explorer page of the settlement transaction of `payment`.
+    pub(crate) fn transaction_url(&self, payment: &PaymentHistory) -> Option<Url> {
+        let hash = payment.transaction_hash.as_deref()?;
+        let prefix = self.explorer_for(&payment.network)?;
+        Url::parse(&format!("{}{}", prefix, hash)).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::payment::{tests::payment_manager, PaymentManager};
+    use crate::{
+        config::{ChainConfig, Config},
+        types::PaymentStatus,
+    };
+    use chrono::Utc;
+
+    fn payment(network: &str) -> PaymentHistory {
+        PaymentHistory {
+            schema_version: crate::payment::schema::CURRENT_SCHEMA_VERSION,
+            payment_id: "pay_1".to_string(),
+            url: "https://api.example.com/report".to_string(),
+            amount: "1000".to_string(),
+            asset: "0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913".to_string(),
+            asset_decimals: None,
+            asset_symbol: None,
+            usd_value: None,
+            transaction_hash: Some("0xabc123".to_string()),
+            network: network.into(),
+            payer: None,
+            payee: "0x1111111111111111111111111111111111111111".to_string(),
+            timestamp: Utc::now(),
+            status: PaymentStatus::Confirmed,
+            description: "Report".to_string(),
+            metadata: HashMap::new(),
+            request_id: None,
+            batch_id: None,
+            facilitator: None,
+            timing: None,
+            receipt: None,
+            wallet: None,
+            dry_run: false,
+            authorization_nonce: None,
+        }
+    }
+
+    #[test]
+    fn known_chains_link_to_their_explorer() {
+        let registry = ExplorerRegistry::default();
+        let link = |network: &str| registry.transaction_url(&payment(network)).map(String::from);
+
+        assert_eq!(link("ethereum").as_deref(), Some("https://etherscan.io/tx/0xabc123"));
+        assert_eq!(link("polygon").as_deref(), Some("https://polygonscan.com/tx/0xabc123"));
+        assert_eq!(link("base").as_deref(), Some("https://basescan.org/tx/0xabc123"));
+        assert_eq!(link("bsc").as_deref(), Some("https://bscscan.com/tx/0xabc123"));
+        assert_eq!(link("arbitrum").as_deref(), Some("https://arbiscan.io/tx/0xabc123"));
+        assert_eq!(link("sei"), None);
+
+        let mut unsettled = payment("base");
+        unsettled.transaction_hash = None;
+        assert_eq!(registry.transaction_url(&unsettled), None);
+    }
+
+    #[tokio::test]
+    async fn managers_keep_their_own_registrations() {
+        let first = payment_manager(Config {
+            chains: vec![ChainConfig::base_mainnet().with_explorer_url("https://base.blockscout.com/")],
+            ..Config::default()
+        })
+        .await;
+        let second = payment_manager(Config {
+            chains: Vec::new(),
+            ..Config::default()
+        })
+        .await;
+        first.register_explorer("sei", "https://seitrace.com/tx/");
+
+        let link = |manager: &PaymentManager, network: &str| manager.explorer_url(&payment(network)).map(String::from);
+        assert_eq!(link(&first, "base").as_deref(), Some("https://base.blockscout.com/tx/0xabc123"));
+        assert_eq!(link(&first, "sei").as_deref(), Some("https://seitrace.com/tx/0xabc123"));
+        assert_eq!(link(&second, "base").as_deref(), Some("https://basescan.org/tx/0xabc123"));
+        assert_eq!(link(&second, "sei"), None);
+    }
+}
@@ -0,0 +1,135 @@
+//! Time source for payment validity windows.
+
+use crate::{
+    config::ClockConfig,
+    error::{Error, Result},
+    types::PaymentResponse,
+};
+use chrono::{DateTime, Utc};
+use std::sync::atomic::{AtomicI64, Ordering};
+
+/// x402 error reasons reporting an authorization outside its validity window.
+const TIME_VALIDITY_ERRORS: [&str; 2] = [
+    "invalid_exact_evm_payload_authorization_valid_after",
+    "invalid_exact_evm_payload_authorization_valid_before",
+];
+
+/// Clock used to timestamp payment authorizations.
+///
+/// Reads the local clock shifted by an offset: initially the configured
+/// [`ClockConfig::offset_secs`], replaced by the difference to the server's
+/// clock whenever the clock is calibrated against a `Date` header.
+#[derive(Debug)]
+pub struct PaymentClock {
+    offset_secs: AtomicI64,
+    tolerance_secs: i64,
+    calibrate_from_server: bool,
+}
+
+impl PaymentClock {
+    /// Creates a clock from its configuration.
+    pub fn new(config: &ClockConfig) -> Self {
+        Self {
+            offset_secs: AtomicI64::new(config.offset_secs),
+            tolerance_secs: config.tolerance.as_secs() as i64,
+            calibrate_from_server: config.calibrate_from_server,
+        }
+    }
+
+    /// Current Unix timestamp, corrected by the offset.
+    pub fn now(&self) -> i64 {
+        Utc::now().timestamp() + self.offset_secs()
+    }
+
+    /// Seconds added to the local clock.
+    pub fn offset_secs(&self) -> i64 {
+        self.offset_secs.load(Ordering::Relaxed)
+    }
+
+    /// Returns the `(validAfter, validBefore)` window of an authorization
+    /// valid for `max_timeout_secs`, padded by the tolerance on both sides.
+    pub fn validity_window(&self, max_timeout_secs: u64) -> (i64, i64) {
+        let now = self.now();
+        (
+            now - self.tolerance_secs,
+            now + max_timeout_secs as i64 + self.tolerance_secs,
+        )
+    }
+
+    /// Sets the offset to the difference between an HTTP `Date` header and
+    /// the local clock, returning the new offset.
+    ///
+    /// The header has a resolution of one second, so the offset is accurate
+    /// to about a second plus the time the response took to arrive.
+    pub fn calibrate(&self, date: &str) -> Result<i64> {
+        let server_time = DateTime::parse_from_rfc2822(date.trim())
+            .map_err(|e| Error::Serialization(format!("Invalid Date header {:?}: {}", date, e).into()))?;
+        let offset = server_time.timestamp() - Utc::now().timestamp();
+        self.offset_secs.store(offset, Ordering::Relaxed);
+        Ok(offset)
+    }
+
+    /// Calibrates against the `Date` header of a response, if calibration
+    /// from server responses is enabled and the header is valid.
+    pub(crate) fn observe(&self, response: &PaymentResponse) {
+        if !self.calibrate_from_server {
+            return;
+        }
+        if let Some(date) = response.header("Date") {
+            let _ = self.calibrate(date);
+        }
+    }
+}
+
+/// Whether a settlement error reason is one of the x402 codes reporting an
+/// authorization outside its validity window.
+pub(crate) fn is_time_validity_error(reason: &str) -> bool {
+    let reason = reason.trim();
+    TIME_VALIDITY_ERRORS.iter().any(|code| reason.eq_ignore_ascii_case(code))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Seconds the authorization in the tests stays valid.
+    const TIMEOUT_SECS: u64 = 60;
+
+    fn valid_at(window: (i64, i64), timestamp: i64) -> bool {
+        window.0 <= timestamp && timestamp < window.1
+    }
+
+    #[test]
+    fn calibration_corrects_five_minutes_of_drift() {
+        for drift in [-300, 300] {
+            let clock = PaymentClock::new(&ClockConfig::default());
+            let server_now = Utc::now().timestamp() + drift;
+            assert!(!valid_at(clock.validity_window(TIMEOUT_SECS), server_now), "drift {drift}");
+
+            let date = DateTime::from_timestamp(server_now, 0).unwrap().to_rfc2822();
+            let offset = clock.calibrate(&date).unwrap();
+            assert!((offset - drift).abs() <= 1, "drift {drift}, offset {offset}");
+            let window = clock.validity_window(TIMEOUT_SECS);
+            assert!(valid_at(window, server_now), "drift {drift}");
+            assert!(valid_at(window, server_now + TIMEOUT_SECS as i64), "drift {drift}");
+        }
+    }
+
+    #[test]
+    fn configured_offset_corrects_drift() {
+        for drift in [-300, 300] {
+            let config = ClockConfig { offset_secs: drift, ..ClockConfig::default() };
+            let clock = PaymentClock::new(&config);
+            assert!(valid_at(clock.validity_window(TIMEOUT_SECS), Utc::now().timestamp() + drift));
+        }
+    }
+
+    #[test]
+    fn only_validity_codes_are_time_errors() {
+        assert!(is_time_validity_error("invalid_exact_evm_payload_authorization_valid_after"));
+        assert!(is_time_validity_error(" INVALID_EXACT_EVM_PAYLOAD_AUTHORIZATION_VALID_BEFORE "));
+        assert!(!is_time_validity_error("API key expired"));
+        assert!(!is_time_validity_error("clock_mismatch_on_rpc_node"));
+        assert!(!is_time_validity_error("insufficient_funds"));
+    }
+}
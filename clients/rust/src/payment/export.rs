@@ -0,0 +1,467 @@
+//! Export of payment history for accounting.
+//!
+//! Records are streamed page by page from the [`PaymentManager`] to any
+//! [`AsyncWrite`], so exporting a large history never holds more than one
+//! page of records in memory. Records already in hand can also be written as
+//! a QuickBooks IIF import file with [`export_to_quickbooks_iif`].
+
+use super::PaymentManager;
+use crate::{
+    error::Result,
+    types::{PaymentHistory, PaymentStatus},
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+use std::io::Write;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+/// Number of records fetched from the history per page.
+const EXPORT_PAGE_SIZE: usize = 256;
+
+/// Column order used by every export format.
+pub const EXPORT_COLUMNS: [&str; 11] = [
+    "timestamp",
+    "domain",
+    "url",
+    "network",
+    "asset",
+    "amount_raw",
+    "amount_decimal",
+    "usd_value",
+    "transaction_hash",
+    "status",
+    "payment_id",
+];
+
+/// Output format of a payment history export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// RFC 4180 CSV with a header row
+    Csv,
+    /// One JSON object per line
+    JsonLines,
+}
+
+/// Inclusive time range used to filter exported records.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DateRange {
+    /// Earliest timestamp to include
+    pub from: Option<DateTime<Utc>>,
+
+    /// Latest timestamp to include
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl DateRange {
+    /// Range covering the whole history.
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    /// Range between two timestamps, both inclusive.
+    pub fn between(from: DateTime<Utc>, to: DateTime<Utc>) -> Self {
+        Self {
+            from: Some(from),
+            to: Some(to),
+        }
+    }
+
+    /// Returns `true` if `timestamp` falls inside the range.
+    pub fn contains(&self, timestamp: &DateTime<Utc>) -> bool {
+        self.from.map_or(true, |from| *timestamp >= from) && self.to.map_or(true, |to| *timestamp <= to)
+    }
+}
+
+/// A single exported row, serialized with stable field order.
+#[derive(Debug, Serialize)]
+struct ExportRecord<'a> {
+    timestamp: String,
+    domain: String,
+    url: &'a str,
+    network: &'a str,
+    asset: &'a str,
+    amount_raw: &'a str,
+    amount_decimal: Option<String>,
+    usd_value: Option<f64>,
+    transaction_hash: Option<&'a str>,
+    status: &'a str,
+    payment_id: &'a str,
+}
+
+impl<'a> From<&'a PaymentHistory> for ExportRecord<'a> {
+    fn from(record: &'a PaymentHistory) -> Self {
+        Self {
+            timestamp: record.timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+            domain: record.domain(),
+            url: &record.url,
+            network: record.network.as_str(),
+            asset: &record.asset,
+            amount_raw: &record.amount,
+            amount_decimal: record.amount_decimal(),
+            usd_value: record.usd_value,
+            transaction_hash: record.transaction_hash.as_deref(),
+            status: record.status.as_str(),
+            payment_id: &record.payment_id,
+        }
+    }
+}
+
+impl ExportRecord<'_> {
+    fn to_csv_row(&self) -> String {
+        let fields = [
+            self.timestamp.clone(),
+            self.domain.clone(),
+            self.url.to_string(),
+            self.network.to_string(),
+            self.asset.to_string(),
+            self.amount_raw.to_string(),
+            self.amount_decimal.clone().unwrap_or_default(),
+            self.usd_value.map(|v| v.to_string()).unwrap_or_default(),
+            self.transaction_hash.unwrap_or_default().to_string(),
+            self.status.to_string(),
+            self.payment_id.to_string(),
+        ];
+
+        let mut row = fields
+            .iter()
+            .map(|field| escape_csv(field))
+            .collect::<Vec<_>>()
+            .join(",");
+        row.push_str("\r\n");
+        row
+    }
+}
+
+/// Quotes a CSV field if it contains a delimiter, quote or line break.
+pub fn escape_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Writes a single record in the given format.
+pub async fn write_record<W>(format: ExportFormat, record: &PaymentHistory, writer: &mut W) -> Result<()>
+where
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    let record = ExportRecord::from(record);
+
+    match format {
+        ExportFormat::Csv => {
+            writer.write_all(record.to_csv_row().as_bytes()).await?;
+        }
+        ExportFormat::JsonLines => {
+            let mut line = serde_json::to_vec(&record)?;
+            line.push(b'\n');
+            writer.write_all(&line).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Streams the payment history held by `manager` to `writer`.
+///
+/// Returns the number of records written.
+pub async fn export_history<W>(
+    manager: &PaymentManager,
+    format: ExportFormat,
+    range: DateRange,
+    writer: &mut W,
+) -> Result<usize>
+where
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    if format == ExportFormat::Csv {
+        let header = format!("{}\r\n", EXPORT_COLUMNS.join(","));
+        writer.write_all(header.as_bytes()).await?;
+    }
+
+    let mut last_payment: Option<String> = None;
+    let mut exported = 0;
+
+    loop {
+        let page = manager.history_page(last_payment.as_deref(), EXPORT_PAGE_SIZE);
+        let Some(last) = page.last() else {
+            break;
+        };
+        last_payment = Some(last.payment_id.clone());
+
+        for record in page.iter().filter(|r| range.contains(&r.timestamp)) {
+            write_record(format, record, writer).await?;
+            exported += 1;
+        }
+    }
+
+    writer.flush().await?;
+
+    Ok(exported)
+}
+
+/// Header rows declaring the IIF transaction, split and end columns.
+const IIF_HEADER: &str = "!TRNS\tTRNSTYPE\tDATE\tACCNT\tAMOUNT\tMEMO\r\n\
+                          !SPL\tTRNSTYPE\tDATE\tACCNT\tAMOUNT\tMEMO\r\n\
+                          !ENDTRNS\r\n";
+
+/// QuickBooks accounts that IIF transactions are booked against.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IifAccounts {
+    /// Account the payments are made from, credited by each transaction
+    pub payment_account: String,
+
+    /// Expense account debited by each transaction
+    pub expense_account: String,
+}
+
+impl Default for IifAccounts {
+    fn default() -> Self {
+        Self {
+            payment_account: "Crypto Wallet".to_string(),
+            expense_account: "API Services".to_string(),
+        }
+    }
+}
+
+/// Writes payments as a QuickBooks IIF import file.
+///
+/// Each payment becomes a `CHECK` transaction crediting
+/// [`payment_account`](IifAccounts::payment_account) and a balancing split
+/// debiting [`expense_account`](IifAccounts::expense_account), dated in
+/// `MM/DD/YYYY` form with the USD value as amount and the transaction hash
+/// (or payment ID) as memo. Failed payments and payments without a
+/// [`usd_value`](PaymentHistory::usd_value) are skipped, as IIF amounts
+/// must be in the company currency.
+///
+/// Returns the number of transactions written.
+pub fn export_to_quickbooks_iif<W: Write + ?Sized>(
+    history: &[PaymentHistory],
+    accounts: &IifAccounts,
+    output: &mut W,
+) -> Result<usize> {
+    output.write_all(IIF_HEADER.as_bytes())?;
+
+    let payment_account = iif_field(&accounts.payment_account);
+    let expense_account = iif_field(&accounts.expense_account);
+    let mut exported = 0;
+
+    for record in history.iter().filter(|record| record.status != PaymentStatus::Failed) {
+        let Some(usd_value) = record.usd_value else {
+            continue;
+        };
+
+        let date = record.timestamp.format("%m/%d/%Y");
+        let memo = iif_field(record.transaction_hash.as_deref().unwrap_or(&record.payment_id));
+        write!(
+            output,
+            "TRNS\tCHECK\t{date}\t{payment_account}\t{:.2}\t{memo}\r\n\
+             SPL\tCHECK\t{date}\t{expense_account}\t{:.2}\t{memo}\r\n\
+             ENDTRNS\r\n",
+            -usd_value,
+            usd_value,
+        )?;
+        exported += 1;
+    }
+
+    output.flush()?;
+    Ok(exported)
+}
+
+/// Replaces the tabs and line breaks that would break an IIF row.
+fn iif_field(field: &str) -> String {
+    field.replace(['\t', '\r', '\n'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        chains::format_units,
+        config::Config,
+        payment::tests::{payment_manager, record},
+    };
+    use serde_json::Value;
+    use std::collections::HashMap;
+
+    const URL: &str = "https://api.example.com/search?q=a,b&name=\"x\"";
+
+    async fn manager_with_history() -> PaymentManager {
+        let manager = payment_manager(Config { chains: Vec::new(), ..Config::default() }).await;
+        for index in 0..EXPORT_PAGE_SIZE + 2 {
+            let record = PaymentHistory {
+                url: URL.to_string(),
+                amount: (1_000_000 + index).to_string(),
+                transaction_hash: Some(format!("0x{index:064x}")),
+                usd_value: Some(1.5),
+                ..record(&format!("pay_{index}"))
+            };
+            manager.push_history(record);
+        }
+        manager
+    }
+
+    /// Splits RFC 4180 CSV into rows of unquoted fields.
+    fn parse_csv(csv: &str) -> Vec<Vec<String>> {
+        let mut rows = Vec::new();
+        let (mut row, mut field, mut quoted) = (Vec::new(), String::new(), false);
+        let mut chars = csv.chars().peekable();
+        while let Some(c) = chars.next() {
+            match (c, quoted) {
+                ('"', true) if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                ('"', _) => quoted = !quoted,
+                (',', false) => row.push(std::mem::take(&mut field)),
+                ('\r', false) => {}
+                ('\n', false) => {
+                    row.push(std::mem::take(&mut field));
+                    rows.push(std::mem::take(&mut row));
+                }
+                _ => field.push(c),
+            }
+        }
+        rows
+    }
+
+    #[tokio::test]
+    async fn csv_export_round_trips() {
+        let manager = manager_with_history().await;
+        let mut output = Vec::new();
+        let exported = export_history(&manager, ExportFormat::Csv, DateRange::all(), &mut output).await.unwrap();
+        assert_eq!(exported, EXPORT_PAGE_SIZE + 2);
+
+        let rows = parse_csv(std::str::from_utf8(&output).unwrap());
+        assert_eq!(rows[0], EXPORT_COLUMNS);
+        assert_eq!(rows.len(), exported + 1);
+        for (index, row) in rows[1..].iter().enumerate() {
+            let field = |name| &row[EXPORT_COLUMNS.iter().position(|column| *column == name).unwrap()];
+            assert_eq!(field("url"), URL);
+            assert_eq!(field("domain"), "api.example.com");
+            assert_eq!(field("amount_raw"), &(1_000_000 + index).to_string());
+            assert_eq!(field("amount_decimal"), &format_units(&(1_000_000 + index).to_string(), 6));
+            assert_eq!(field("usd_value"), "1.5");
+            assert_eq!(field("transaction_hash"), &format!("0x{index:064x}"));
+            assert_eq!(field("payment_id"), &format!("pay_{index}"));
+        }
+    }
+
+    #[tokio::test]
+    async fn json_lines_export_round_trips() {
+        let manager = manager_with_history().await;
+        let mut output = Vec::new();
+        export_history(&manager, ExportFormat::JsonLines, DateRange::all(), &mut output).await.unwrap();
+
+        let lines: Vec<Value> = output
+            .split(|byte| *byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), EXPORT_PAGE_SIZE + 2);
+        for (index, line) in lines.iter().enumerate() {
+            let columns: Vec<&str> = line.as_object().unwrap().keys().map(String::as_str).collect();
+            assert_eq!(columns.len(), EXPORT_COLUMNS.len());
+            assert_eq!(line["url"], URL);
+            assert_eq!(line["amount_raw"], (1_000_000 + index).to_string());
+            assert_eq!(line["payment_id"], format!("pay_{index}"));
+        }
+    }
+
+    /// IIF transaction: its `TRNS` row and `SPL` rows, as fields by column name.
+    struct IifTransaction {
+        trns: HashMap<String, String>,
+        splits: Vec<HashMap<String, String>>,
+    }
+
+    /// Parses an IIF file the way QuickBooks imports it.
+    ///
+    /// `!` rows declare the columns of a record type; `TRNS` opens a
+    /// transaction, `SPL` adds a split to it and `ENDTRNS` closes it. Panics
+    /// on rows QuickBooks would reject, including unbalanced transactions.
+    fn parse_iif(iif: &str) -> Vec<IifTransaction> {
+        let mut columns: HashMap<String, Vec<String>> = HashMap::new();
+        let mut transactions = Vec::new();
+        let mut open: Option<IifTransaction> = None;
+
+        for line in iif.split("\r\n").filter(|line| !line.is_empty()) {
+            let fields: Vec<&str> = line.split('\t').collect();
+            if let Some(kind) = fields[0].strip_prefix('!') {
+                columns.insert(kind.to_string(), fields[1..].iter().map(|f| f.to_string()).collect());
+                continue;
+            }
+            let names = columns.get(fields[0]).unwrap_or_else(|| panic!("undeclared record {}", fields[0]));
+            assert_eq!(fields.len() - 1, names.len(), "column count of {line:?}");
+            let row: HashMap<String, String> =
+                names.iter().cloned().zip(fields[1..].iter().map(|f| f.to_string())).collect();
+
+            match fields[0] {
+                "TRNS" => {
+                    assert!(open.is_none(), "TRNS inside a transaction");
+                    open = Some(IifTransaction { trns: row, splits: Vec::new() });
+                }
+                "SPL" => open.as_mut().expect("SPL outside a transaction").splits.push(row),
+                "ENDTRNS" => {
+                    let transaction = open.take().expect("ENDTRNS without TRNS");
+                    let total: f64 = std::iter::once(&transaction.trns)
+                        .chain(&transaction.splits)
+                        .map(|row| row["AMOUNT"].parse::<f64>().unwrap())
+                        .sum();
+                    assert!(total.abs() < 0.005, "unbalanced transaction");
+                    transactions.push(transaction);
+                }
+                other => panic!("unexpected record {other}"),
+            }
+        }
+        assert!(open.is_none(), "unterminated transaction");
+        transactions
+    }
+
+    #[test]
+    fn iif_export_parses_as_balanced_transactions() {
+        let records = [
+            PaymentHistory {
+                usd_value: Some(1.5),
+                transaction_hash: Some("0xabc".to_string()),
+                timestamp: "2024-03-05T10:00:00Z".parse().unwrap(),
+                ..record("pay_1")
+            },
+            // Without a USD value or settled payment, nothing can be booked
+            PaymentHistory { usd_value: None, ..record("pay_2") },
+            PaymentHistory {
+                usd_value: Some(2.0),
+                status: PaymentStatus::Failed,
+                ..record("pay_3")
+            },
+            PaymentHistory {
+                usd_value: Some(0.25),
+                timestamp: "2024-12-31T23:59:59Z".parse().unwrap(),
+                ..record("pay\t4")
+            },
+        ];
+        let accounts = IifAccounts {
+            payment_account: "Base\tWallet".to_string(),
+            ..IifAccounts::default()
+        };
+
+        let mut output = Vec::new();
+        assert_eq!(export_to_quickbooks_iif(&records, &accounts, &mut output).unwrap(), 2);
+
+        let transactions = parse_iif(std::str::from_utf8(&output).unwrap());
+        assert_eq!(transactions.len(), 2);
+        let first = &transactions[0];
+        assert_eq!(first.trns["TRNSTYPE"], "CHECK");
+        assert_eq!(first.trns["DATE"], "03/05/2024");
+        assert_eq!(first.trns["ACCNT"], "Base Wallet");
+        assert_eq!(first.trns["AMOUNT"], "-1.50");
+        assert_eq!(first.trns["MEMO"], "0xabc");
+        assert_eq!(first.splits.len(), 1);
+        assert_eq!(first.splits[0]["ACCNT"], "API Services");
+        assert_eq!(first.splits[0]["AMOUNT"], "1.50");
+
+        let second = &transactions[1];
+        assert_eq!(second.trns["DATE"], "12/31/2024");
+        assert_eq!(second.trns["AMOUNT"], "-0.25");
+        assert_eq!(second.trns["MEMO"], "pay 4");
+    }
+}
@@ -0,0 +1,269 @@
+//! Settlement status and payment verification queries to facilitators.
+//!
+//! Facilitators that settle asynchronously answer the paid request before
+//! the transfer is submitted, so the response carries no
+//! `X-PAYMENT-RESPONSE` header. Such payments are recorded as pending
+//! without a transaction hash, and their settlement is looked up by the
+//! nonce of their authorization at
+//! `GET {facilitator}/settlement/{nonce}?network={network}`.
+//!
+//! The facilitator answers with the settlement JSON of the header and a
+//! `status` of `pending`, `settled` or `failed`; a `404` means it has not
+//! seen the payment yet and counts as pending.
+//!
+//! With [`Config::verify_payments`] set, each signed payment is first sent to
+//! `POST {facilitator}/verify` with the requirements it pays, and only sent
+//! to the server if the facilitator reports it valid.
+
+use super::{PaymentRequirements, Settlement, X402_VERSION};
+use crate::{
+    config::Config,
+    error::{Error, Result},
+    hosts::{redirect_refused, HostPolicy},
+};
+use serde::Deserialize;
+use std::{sync::Arc, time::Duration};
+
+/// Time [`Client::poll_settlement`](crate::Client::poll_settlement) waits
+/// for a settlement unless set with [`PollOptions::timeout`].
+pub const DEFAULT_POLL_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Shortest delay between settlement queries; shorter intervals, including
+/// zero, are raised to it.
+pub const MIN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Options of [`Client::poll_settlement`](crate::Client::poll_settlement).
+#[derive(Debug, Clone)]
+pub struct PollOptions {
+    /// Time to wait for the payment to settle or fail
+    pub timeout: Duration,
+
+    /// Delay before the second query, doubled after each query, at least
+    /// [`MIN_POLL_INTERVAL`]
+    pub initial_interval: Duration,
+
+    /// Longest delay between queries, at least [`MIN_POLL_INTERVAL`]
+    pub max_interval: Duration,
+}
+
+impl Default for PollOptions {
+    fn default() -> Self {
+        Self {
+            timeout: DEFAULT_POLL_TIMEOUT,
+            initial_interval: Duration::from_secs(1),
+            max_interval: Duration::from_secs(30),
+        }
+    }
+}
+
+impl PollOptions {
+    /// Creates options waiting five minutes, querying after 1s, 2s, 4s… up to every 30s.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the time to wait for the payment to settle or fail.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the delay before the second query, raised to [`MIN_POLL_INTERVAL`].
+    pub fn initial_interval(mut self, interval: Duration) -> Self {
+        self.initial_interval = interval.max(MIN_POLL_INTERVAL);
+        self
+    }
+
+    /// Sets the longest delay between queries, raised to [`MIN_POLL_INTERVAL`].
+    pub fn max_interval(mut self, interval: Duration) -> Self {
+        self.max_interval = interval.max(MIN_POLL_INTERVAL);
+        self
+    }
+
+    /// Returns the initial and longest delays between queries, raised to
+    /// [`MIN_POLL_INTERVAL`] in case the fields were set directly.
+    pub(crate) fn intervals(&self) -> (Duration, Duration) {
+        let max_interval = self.max_interval.max(MIN_POLL_INTERVAL);
+        (self.initial_interval.clamp(MIN_POLL_INTERVAL, max_interval), max_interval)
+    }
+}
+
+/// Settlement state of a payment as reported by its facilitator.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SettlementStatus {
+    /// Not settled yet
+    Pending,
+    /// Settled, with the settlement transaction
+    Settled(Settlement),
+    /// Settlement failed, with the reason in [`Settlement::error_reason`]
+    Failed(Settlement),
+}
+
+/// Body of a settlement status response.
+#[derive(Debug, Deserialize)]
+struct StatusBody {
+    status: String,
+    #[serde(flatten)]
+    settlement: Settlement,
+}
+
+/// Body of a payment verification response.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VerifyBody {
+    is_valid: bool,
+    #[serde(default)]
+    invalid_reason: Option<String>,
+}
+
+/// Client for the settlement status and verification endpoints of facilitators.
+///
+/// Facilitators are subject to the same allowed and denied hosts as requests.
+#[derive(Debug)]
+pub(crate) struct FacilitatorClient {
+    http: reqwest::Client,
+    hosts: Arc<HostPolicy>,
+}
+
+impl FacilitatorClient {
+    /// Creates a facilitator client from the client configuration.
+    pub(crate) fn new(config: &Config) -> Result<Self> {
+        let hosts = Arc::new(HostPolicy::new(config)?);
+        let http = crate::http::with_dns(reqwest::Client::builder(), config)
+            .redirect(crate::http::redirect_policy(Arc::clone(&hosts)))
+            .timeout(config.timeout)
+            .user_agent(crate::USER_AGENT)
+            .build()
+            .map_err(|e| Error::Network(format!("Failed to build HTTP client: {}", e).into()))?;
+        Ok(Self { http, hosts })
+    }
+
+    /// Queries `facilitator` for the settlement of the authorization with `nonce` on `network`.
+    pub(crate) async fn settlement_status(&self, facilitator: &str, network: &str, nonce: &str) -> Result<SettlementStatus> {
+        let url = format!("{}/settlement/{}", facilitator.trim_end_matches('/'), nonce);
+        self.hosts.check(&url)?;
+        let response = self
+            .http
+            .get(&url)
+            .query(&[("network", network)])
+            .send()
+            .await
+            .map_err(|e| {
+                redirect_refused(&e)
+                    .unwrap_or_else(|| Error::Network(format!("Request to {} failed: {}", url, e).into()))
+            })?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(SettlementStatus::Pending);
+        }
+        if !response.status().is_success() {
+            return Err(Error::Network(format!("{} returned {}", url, response.status()).into()));
+        }
+
+        let body: StatusBody = response
+            .json()
+            .await
+            .map_err(|e| Error::Payment(format!("Invalid settlement status from {}: {}", url, e).into()))?;
+        match body.status.to_ascii_lowercase().as_str() {
+            "pending" => Ok(SettlementStatus::Pending),
+            "settled" => Ok(SettlementStatus::Settled(Settlement { success: true, ..body.settlement })),
+            "failed" => Ok(SettlementStatus::Failed(Settlement { success: false, ..body.settlement })),
+            other => Err(Error::Payment(format!("Unknown settlement status {:?} from {}", other, url).into())),
+        }
+    }
+
+    /// Asks `facilitator` whether `payment_header` is a valid payment of `requirements`.
+    ///
+    /// Fails with [`Error::Payment`] if the facilitator reports it invalid.
+    pub(crate) async fn verify(&self, facilitator: &str, payment_header: &str, requirements: &PaymentRequirements) -> Result<()> {
+        let url = format!("{}/verify", facilitator.trim_end_matches('/'));
+        self.hosts.check(&url)?;
+        let response = self
+            .http
+            .post(&url)
+            .json(&serde_json::json!({
+                "x402Version": X402_VERSION,
+                "paymentHeader": payment_header,
+                "paymentRequirements": requirements,
+            }))
+            .send()
+            .await
+            .map_err(|e| {
+                redirect_refused(&e)
+                    .unwrap_or_else(|| Error::Network(format!("Request to {} failed: {}", url, e).into()))
+            })?;
+        if !response.status().is_success() {
+            return Err(Error::Network(format!("{} returned {}", url, response.status()).into()));
+        }
+
+        let body: VerifyBody = response
+            .json()
+            .await
+            .map_err(|e| Error::Payment(format!("Invalid verification response from {}: {}", url, e).into()))?;
+        if body.is_valid {
+            Ok(())
+        } else {
+            Err(Error::Payment(format!(
+                "Facilitator {} rejected the payment: {}",
+                facilitator,
+                body.invalid_reason.as_deref().unwrap_or("no reason given")
+            ).into()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use wiremock::{
+        matchers::{body_partial_json, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    fn requirements() -> PaymentRequirements {
+        serde_json::from_value(json!({
+            "scheme": "exact",
+            "network": "base",
+            "maxAmountRequired": "1000",
+            "resource": "https://api.example.com/data",
+            "payTo": "0x1111111111111111111111111111111111111111",
+            "asset": "0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913",
+            "maxTimeoutSeconds": 60
+        }))
+        .unwrap()
+    }
+
+    async fn facilitator(response: serde_json::Value) -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/verify"))
+            .and(body_partial_json(json!({
+                "x402Version": 1,
+                "paymentHeader": "header",
+                "paymentRequirements": { "network": "base", "maxAmountRequired": "1000" }
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(response))
+            .expect(1)
+            .mount(&server)
+            .await;
+        server
+    }
+
+    #[tokio::test]
+    async fn verify_accepts_valid_payments() {
+        let server = facilitator(json!({ "isValid": true })).await;
+        let client = FacilitatorClient::new(&Config::default()).unwrap();
+
+        client.verify(&server.uri(), "header", &requirements()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn verify_rejects_invalid_payments() {
+        let server = facilitator(json!({ "isValid": false, "invalidReason": "insufficient_funds" })).await;
+        let client = FacilitatorClient::new(&Config::default()).unwrap();
+
+        let error = client.verify(&server.uri(), "header", &requirements()).await.unwrap_err();
+        assert!(matches!(&error, Error::Payment(message) if message.contains("insufficient_funds")), "{error}");
+    }
+}
@@ -0,0 +1,190 @@
+//! Payment hints advertised on unpaid responses.
+//!
+//! Servers can announce what a resource costs before it is requested, so
+//! users can be warned before following a link to paid content. Hints are
+//! read from the headers listed in
+//! [`Config::payment_hint_headers`](crate::Config::payment_hint_headers), in
+//! any of these forms:
+//!
+//! - `X-PAYMENT-REQUIRED: <requirements>` with the JSON or base64-encoded
+//!   JSON requirements a 402 would carry, for the resource they name
+//! - `X-Payment-Price: <amount>; network=<network>; asset=<address>; url=<url>`
+//!   with the amount in the asset's smallest unit, for `url` or else the
+//!   response's own URL; the parameters are optional
+//! - `Link: <url>; rel="payment"; price="<amount>"; network="<network>"; asset="<address>"`,
+//!   one hint per link with `rel="payment"`
+//!
+//! Hints carrying full requirements for a resource on the same origin as the
+//! response are cached, so a later request for it is paid up front without a
+//! 402 round-trip when [`preemptive_payment`](crate::Config::preemptive_payment)
+//! is on. Malformed hints are skipped.
+
+use super::PaymentRequirements;
+use crate::chains::NetworkId;
+use reqwest::header::HeaderValue;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::debug;
+use url::Url;
+
+/// Headers read for payment hints unless configured otherwise.
+pub const DEFAULT_PAYMENT_HINT_HEADERS: &[&str] = &["X-PAYMENT-REQUIRED", "X-Payment-Price", "Link"];
+
+/// Price advertised for one resource.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaymentHint {
+    /// Absolute URL of the priced resource
+    pub url: String,
+
+    /// Price in the asset's smallest unit
+    pub amount: String,
+
+    /// Network the price applies to, if given
+    pub network: Option<NetworkId>,
+
+    /// Address of the asset the price is in, if given
+    pub asset: Option<String>,
+
+    /// Full requirements, for hints that carry them
+    pub requirements: Option<PaymentRequirements>,
+
+    /// Header the hint was read from
+    pub header: String,
+}
+
+/// Payment hints found on a response.
+///
+/// ```rust
+/// # async fn example(client: v402_client::Client) -> v402_client::Result<()> {
+/// let page = client.get("https://example.com/articles").await?;
+/// let hint = page
+///     .payment_hints
+///     .as_ref()
+///     .and_then(|hints| hints.for_url("https://example.com/articles/42"));
+/// if let Some(hint) = hint {
+///     println!("Reading the article costs {}", hint.amount);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PaymentHints {
+    /// Hints in the order their headers were read
+    pub hints: Vec<PaymentHint>,
+}
+
+impl PaymentHints {
+    /// Parses the hints in `headers` of a response for `url`, reading the
+    /// headers named in `names`. Returns `None` without any valid hint.
+    pub(crate) fn parse(url: &str, headers: &HashMap<String, String>, names: &[String]) -> Option<Self> {
+        let base = Url::parse(url).ok()?;
+        let mut hints = Vec::new();
+        for name in names {
+            let Some((header, value)) = headers.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)) else {
+                continue;
+            };
+            let parsed = if header.eq_ignore_ascii_case("link") {
+                Ok(parse_links(&base, header, value))
+            } else {
+                parse_hint(&base, header, value).map(|hint| vec![hint])
+            };
+            match parsed {
+                Ok(parsed) => hints.extend(parsed),
+                Err(reason) => debug!(url, header = %header, value = %value, reason, "Ignoring malformed payment hint"),
+            }
+        }
+        (!hints.is_empty()).then_some(Self { hints })
+    }
+
+    /// Returns the hint for `url`, if any.
+    pub fn for_url(&self, url: &str) -> Option<&PaymentHint> {
+        let url = Url::parse(url).ok()?;
+        self.hints
+            .iter()
+            .find(|hint| Url::parse(&hint.url).is_ok_and(|hinted| hinted == url))
+    }
+
+    /// Returns `true` without hints.
+    pub fn is_empty(&self) -> bool {
+        self.hints.is_empty()
+    }
+}
+
+/// Parses a requirements or price hint from a header other than `Link`.
+fn parse_hint(base: &Url, header: &str, value: &str) -> std::result::Result<PaymentHint, String> {
+    let value = value.trim();
+    let first = value.split(';').next().unwrap_or_default().trim();
+
+    // A bare amount is a price, anything else must be requirements
+    if !first.is_empty() && first.bytes().all(|b| b.is_ascii_digit()) {
+        let params = params(value);
+        let url = match params.get("url") {
+            Some(url) => base.join(url).map_err(|e| format!("invalid url {url:?}: {e}"))?,
+            None => base.clone(),
+        };
+        return Ok(PaymentHint {
+            url: url.to_string(),
+            amount: first.to_string(),
+            network: params.get("network").map(|network| NetworkId::from(network.as_str())),
+            asset: params.get("asset").cloned(),
+            requirements: None,
+            header: header.to_string(),
+        });
+    }
+
+    let header_value = HeaderValue::from_str(value).map_err(|e| e.to_string())?;
+    let requirements = PaymentRequirements::try_from(&header_value).map_err(|e| e.to_string())?;
+    Ok(PaymentHint {
+        url: requirements.resource.to_string(),
+        amount: requirements.max_amount_required.clone(),
+        network: Some(requirements.network.clone()),
+        asset: requirements.primary_asset().map(|asset| asset.address.clone()),
+        requirements: Some(requirements),
+        header: header.to_string(),
+    })
+}
+
+/// Parses the `rel="payment"` links of a `Link` header, skipping malformed ones.
+fn parse_links(base: &Url, header: &str, value: &str) -> Vec<PaymentHint> {
+    value
+        .split(',')
+        .filter_map(|link| {
+            let (target, rest) = link.trim().strip_prefix('<')?.split_once('>')?;
+            let params = params(rest);
+            let is_payment = params
+                .get("rel")
+                .is_some_and(|rel| rel.split_whitespace().any(|rel| rel.eq_ignore_ascii_case("payment")));
+            if !is_payment {
+                return None;
+            }
+            let amount = params
+                .get("price")
+                .filter(|price| !price.is_empty() && price.bytes().all(|b| b.is_ascii_digit()));
+            let (Some(amount), Ok(url)) = (amount, base.join(target)) else {
+                debug!(link, "Ignoring malformed payment link");
+                return None;
+            };
+            Some(PaymentHint {
+                url: url.to_string(),
+                amount: amount.clone(),
+                network: params.get("network").map(|network| NetworkId::from(network.as_str())),
+                asset: params.get("asset").cloned(),
+                requirements: None,
+                header: header.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Parses `; name=value` parameters, unquoting values and lowercasing names.
+fn params(value: &str) -> HashMap<String, String> {
+    value
+        .split(';')
+        .filter_map(|param| {
+            let (name, value) = param.split_once('=')?;
+            let value = value.trim();
+            let value = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')).unwrap_or(value);
+            Some((name.trim().to_ascii_lowercase(), value.to_string()))
+        })
+        .collect()
+}
@@ -0,0 +1,235 @@
+//! Signed off-chain invoices.
+//!
+//! A payee creates an [`Invoice`] with [`PaymentManager::create_invoice`]
+//! stating what a resource costs before anyone visits it. The invoice is
+//! signed by the payee's wallet with EIP-191 `personal_sign`, so a consumer
+//! holding it can check with [`PaymentManager::verify_invoice`] that the
+//! terms come from the address being paid. Its QR code encodes an
+//! [EIP-681](https://eips.ethereum.org/EIPS/eip-681) `ethereum:` URI
+//! requesting the token transfer, readable by mobile wallets.
+
+use super::PaymentManager;
+use crate::{
+    config::DEFAULT_WALLET,
+    crypto,
+    error::{Error, Result},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use url::Url;
+use uuid::Uuid;
+
+/// Validity of an invoice unless set with [`InvoiceRequest::valid_for`].
+pub const DEFAULT_INVOICE_VALIDITY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Terms of an invoice to create.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvoiceRequest {
+    /// Amount in the token's smallest unit
+    pub amount: String,
+
+    /// Token contract address
+    pub token: String,
+
+    /// Network the invoice is paid on
+    pub chain: String,
+
+    /// URL of the resource the invoice pays for
+    pub payment_url: Url,
+
+    /// Label of the wallet issuing the invoice and receiving the payment
+    pub wallet: String,
+
+    /// Time from creation until the invoice expires
+    pub valid_for: Duration,
+
+    /// Render a QR code of the invoice's `ethereum:` URI
+    pub qr_code: bool,
+}
+
+impl InvoiceRequest {
+    /// Creates a request for `amount` of `token` on `chain`, payable to the
+    /// default wallet for `payment_url` and valid for [`DEFAULT_INVOICE_VALIDITY`].
+    pub fn new<A, T, C>(amount: A, token: T, chain: C, payment_url: Url) -> Self
+    where
+        A: Into<String>,
+        T: Into<String>,
+        C: Into<String>,
+    {
+        Self {
+            amount: amount.into(),
+            token: token.into(),
+            chain: chain.into(),
+            payment_url,
+            wallet: DEFAULT_WALLET.to_string(),
+            valid_for: DEFAULT_INVOICE_VALIDITY,
+            qr_code: true,
+        }
+    }
+
+    /// Issues the invoice from the wallet labelled `wallet`.
+    pub fn wallet<S: Into<String>>(mut self, wallet: S) -> Self {
+        self.wallet = wallet.into();
+        self
+    }
+
+    /// Sets the time from creation until the invoice expires.
+    pub fn valid_for(mut self, valid_for: Duration) -> Self {
+        self.valid_for = valid_for;
+        self
+    }
+
+    /// Sets whether the invoice carries a QR code.
+    pub fn qr_code(mut self, qr_code: bool) -> Self {
+        self.qr_code = qr_code;
+        self
+    }
+}
+
+/// Payee-signed statement of what a resource costs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Invoice {
+    /// Invoice ID
+    pub id: Uuid,
+
+    /// Address receiving the payment, which signed the invoice
+    pub payee: String,
+
+    /// Amount in the token's smallest unit
+    pub amount: String,
+
+    /// Ticker symbol of the token, or its address if unknown
+    pub currency: String,
+
+    /// Token contract address
+    pub token: String,
+
+    /// Network the invoice is paid on
+    pub chain: String,
+
+    /// Time after which the invoice is no longer honoured
+    pub valid_until: DateTime<Utc>,
+
+    /// URL of the resource the invoice pays for
+    pub payment_url: Url,
+
+    /// SVG QR code of [`ethereum_uri`](Self::ethereum_uri); not signed
+    pub qr_code_svg: Option<String>,
+
+    /// EIP-191 signature by `payee` over [`digest`](Self::digest)
+    pub signature: String,
+}
+
+/// Signed fields of an invoice, in signing order.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct InvoiceBody<'a> {
+    id: &'a Uuid,
+    payee: &'a str,
+    amount: &'a str,
+    currency: &'a str,
+    token: &'a str,
+    chain: &'a str,
+    valid_until: &'a DateTime<Utc>,
+    payment_url: &'a Url,
+}
+
+impl Invoice {
+    /// Hash of the signed fields; the signature covers its `personal_sign` form.
+    pub fn digest(&self) -> Result<[u8; 32]> {
+        let body = InvoiceBody {
+            id: &self.id,
+            payee: &self.payee,
+            amount: &self.amount,
+            currency: &self.currency,
+            token: &self.token,
+            chain: &self.chain,
+            valid_until: &self.valid_until,
+            payment_url: &self.payment_url,
+        };
+        Ok(crypto::keccak256(&serde_json::to_vec(&body)?))
+    }
+
+    /// Returns `true` once the invoice's validity has passed.
+    pub fn is_expired(&self) -> bool {
+        Utc::now() >= self.valid_until
+    }
+
+    /// Returns the EIP-681 URI requesting the transfer of the invoiced
+    /// amount, e.g. `ethereum:0xToken@8453/transfer?address=0xPayee&uint256=1000`.
+    ///
+    /// `chain_id` is omitted from the URI if unknown, which wallets read as
+    /// the chain they are connected to.
+    pub fn ethereum_uri(&self, chain_id: Option<u64>) -> String {
+        let chain = chain_id.map(|id| format!("@{}", id)).unwrap_or_default();
+        format!(
+            "ethereum:{}{}/transfer?address={}&uint256={}",
+            self.token, chain, self.payee, self.amount
+        )
+    }
+}
+
+impl PaymentManager {
+    /// Creates an invoice for `request`, signed by the wallet receiving the payment.
+    ///
+    /// The chain must be configured and EVM-compatible, and the amount a
+    /// whole number of the token's smallest unit.
+    pub fn create_invoice(&self, request: InvoiceRequest) -> Result<Invoice> {
+        if !crypto::is_uint256(&request.amount) {
+            return Err(Error::Payment(format!(
+                "Invoice amount must be a whole number of the token's smallest unit, got {:?}",
+                request.amount
+            ).into()));
+        }
+        crypto::parse_address(&request.token)?;
+        let chain_id = self.chain_manager.chain(&request.chain)?.chain_id;
+        let payee = self.chain_manager.wallet_address(&request.wallet, &request.chain)?;
+        let valid_for = chrono::Duration::from_std(request.valid_for)
+            .map_err(|_| Error::Payment(format!("Invoice validity {:?} is too long", request.valid_for).into()))?;
+
+        let currency = self
+            .chain_manager
+            .tokens()
+            .get(&request.chain, &request.token)
+            .map(|token| token.symbol)
+            .unwrap_or_else(|| request.token.clone());
+        let mut invoice = Invoice {
+            id: Uuid::new_v4(),
+            payee,
+            amount: request.amount,
+            currency,
+            token: request.token,
+            chain: request.chain,
+            valid_until: Utc::now() + valid_for,
+            payment_url: request.payment_url,
+            qr_code_svg: None,
+            signature: String::new(),
+        };
+        invoice.signature = self.chain_manager.sign_message(&invoice.payee, &invoice.digest()?)?;
+
+        if request.qr_code {
+            let code = qrcode::QrCode::new(invoice.ethereum_uri(chain_id))
+                .map_err(|e| Error::Payment(format!("Failed to encode invoice QR code: {}", e).into()))?;
+            invoice.qr_code_svg = Some(
+                code.render::<qrcode::render::svg::Color<'_>>()
+                    .min_dimensions(200, 200)
+                    .build(),
+            );
+        }
+        Ok(invoice)
+    }
+
+    /// Checks that `invoice` was signed by its payee.
+    ///
+    /// Returns `false` if the signature recovers to another address, which
+    /// means the terms were altered or not issued by the payee. Fails if the
+    /// signature is malformed. Expiry is not checked; see
+    /// [`Invoice::is_expired`].
+    pub fn verify_invoice(&self, invoice: &Invoice) -> Result<bool> {
+        let digest = crypto::personal_message_hash(&invoice.digest()?);
+        let signer = crypto::recover_address(&digest, &invoice.signature)?;
+        Ok(signer.eq_ignore_ascii_case(&invoice.payee))
+    }
+}
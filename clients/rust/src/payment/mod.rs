@@ -0,0 +1,2250 @@
+//! Payment processing for the v402 protocol.
+//!
+//! The [`PaymentManager`] turns `402 Payment Required` responses into signed
+//! `X-PAYMENT` headers, processes settlement responses and keeps an in-memory
+//! record of every payment made by the client.
+
+mod alerts;
+mod baseline;
+#[cfg(feature = "batch-payment")]
+mod batch;
+pub mod chain_explorers;
+mod clock;
+pub mod export;
+pub mod facilitator;
+pub mod hints;
+#[cfg(feature = "invoicing")]
+mod invoice;
+pub mod nonce;
+mod price;
+#[cfg(feature = "proof")]
+mod proof;
+mod receipt;
+mod requirements;
+pub mod schema;
+
+pub use alerts::SpendAlertHandler;
+pub use baseline::PaymentApprover;
+#[cfg(feature = "batch-payment")]
+pub use batch::{BatchPaymentReceipt, SinglePayment, BATCH_TRANSFER_ABI};
+pub use clock::PaymentClock;
+pub use facilitator::{PollOptions, SettlementStatus};
+pub use hints::{PaymentHint, PaymentHints};
+pub(crate) use clock::is_time_validity_error;
+#[cfg(feature = "invoicing")]
+pub use invoice::{Invoice, InvoiceRequest, DEFAULT_INVOICE_VALIDITY};
+pub use price::{PriceOracle, TokenPrice};
+#[cfg(feature = "proof")]
+pub use proof::PaymentProof;
+pub use receipt::{Receipt, PAYMENT_RECEIPT_HEADER};
+pub use requirements::{AssetInfo, PaymentDestination, PaymentRequirements, PAYMENT_REQUIRED_HEADER};
+
+use crate::{
+    chains::{ChainManager, NetworkId, TokenInfo},
+    config::{ChainConfig, Config, PreapproveConfig, DEFAULT_WALLET},
+    crypto,
+    error::{Error, Result},
+    events::{ClientEvent, EventBus, PaymentEvent, PriceJump},
+    tasks::{Restart, TaskManager},
+    types::{
+        ConfirmedPayment, HostStatistics, PaymentHistory, PaymentResponse, PaymentStatistics, PaymentStatus, PaymentTiming,
+        WalletStatistics,
+    },
+};
+use alerts::SpendTracker;
+use baseline::PriceBaselines;
+use chain_explorers::ExplorerRegistry;
+use facilitator::FacilitatorClient;
+use nonce::NonceCounters;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use chrono::{DateTime, Utc};
+use parking_lot::RwLock;
+use reqwest::header::HeaderValue;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{hash_map::Entry, BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{sync::Mutex, time::MissedTickBehavior};
+use tracing::{debug, info, info_span, warn, Instrument, Span};
+use uuid::Uuid;
+
+/// Maximum number of payment records kept in memory.
+const MAX_HISTORY_ENTRIES: usize = 10_000;
+
+/// Maximum number of pending payments watched for a receipt.
+const MAX_PENDING_PAYMENTS: usize = 1_000;
+
+/// Version of the x402 payment payload produced by the client.
+const X402_VERSION: u32 = 1;
+
+/// Signature of the payment contract's nonce view function.
+const GET_NONCE_SIGNATURE: &str = "getNonce(address)";
+
+/// Signature of the EIP-3009 view reporting whether a nonce was used.
+const AUTHORIZATION_STATE_SIGNATURE: &str = "authorizationState(address,bytes32)";
+
+/// Signature of the ERC-20 function granting an allowance.
+const APPROVE_SIGNATURE: &str = "approve(address,uint256)";
+
+/// Signature of the ERC-20 view returning an allowance.
+const ALLOWANCE_SIGNATURE: &str = "allowance(address,address)";
+
+/// Header carrying payment metadata as base64-encoded JSON.
+pub const PAYMENT_METADATA_HEADER: &str = "X-PAYMENT-METADATA";
+
+/// Maximum size in bytes of the JSON-encoded payment metadata.
+pub const MAX_METADATA_BYTES: usize = 4096;
+
+/// Number of body bytes included in [`Error::InvalidPaymentRequirements`].
+const BODY_PREVIEW_BYTES: usize = 512;
+
+/// Body of a 402 response.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PaymentRequiredBody {
+    #[serde(default)]
+    accepts: Vec<PaymentRequirements>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// EIP-3009 `transferWithAuthorization` parameters signed by the payer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Authorization {
+    /// Payer address
+    pub from: String,
+    /// Payee address
+    pub to: String,
+    /// Amount in the asset's smallest unit
+    pub value: String,
+    /// Unix timestamp after which the authorization is valid
+    pub valid_after: String,
+    /// Unix timestamp before which the authorization is valid
+    pub valid_before: String,
+    /// Unique 32-byte hex nonce
+    pub nonce: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PaymentPayload<'a> {
+    x402_version: u32,
+    scheme: &'a str,
+    network: &'a str,
+    payload: ExactPayload<'a>,
+}
+
+#[derive(Debug, Serialize)]
+struct ExactPayload<'a> {
+    signature: String,
+    authorization: &'a Authorization,
+}
+
+/// Settlement result decoded from the `X-PAYMENT-RESPONSE` header.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Settlement {
+    /// Whether settlement succeeded
+    #[serde(default)]
+    pub success: bool,
+
+    /// Settlement transaction hash
+    #[serde(rename = "transaction", default)]
+    pub transaction_hash: Option<String>,
+
+    /// Network the payment settled on
+    #[serde(default)]
+    pub network: Option<String>,
+
+    /// Address that paid
+    #[serde(default)]
+    pub payer: Option<String>,
+
+    /// Reason for a failed settlement
+    #[serde(default)]
+    pub error_reason: Option<String>,
+}
+
+/// Manages payment creation, settlement and history.
+#[derive(Debug)]
+pub struct PaymentManager {
+    config: Arc<Config>,
+    chain_manager: Arc<ChainManager>,
+    history: RwLock<VecDeque<PaymentHistory>>,
+    /// Next on-chain nonce per `(network, payer)`, seeded from the payment contract
+    nonces: Mutex<HashMap<(NetworkId, String), NonceState>>,
+    /// Requirements from earlier 402 responses per URL, with their expiry
+    requirements_cache: RwLock<HashMap<String, (PaymentRequirements, Instant)>>,
+    price_oracle: PriceOracle,
+    facilitator: FacilitatorClient,
+    /// Settled payments awaiting a transaction receipt, by transaction hash
+    pending: RwLock<HashMap<String, PendingEntry>>,
+    events: EventBus,
+    clock: PaymentClock,
+    spend: SpendTracker,
+    prices: PriceBaselines,
+    /// Counters of deterministic nonces, when enabled
+    nonce_counters: Option<NonceCounters>,
+    /// Hash of the last approval transaction per `(network, token, spender)`
+    approvals: RwLock<HashMap<(NetworkId, String, String), String>>,
+    /// Explorers registered on top of the built-in ones
+    explorers: ExplorerRegistry,
+}
+
+/// Sequential on-chain nonces of a payer.
+#[derive(Debug)]
+struct NonceState {
+    /// Lowest nonce not used by a sent payment
+    next: u128,
+    /// Nonces signed into payments not sent yet
+    reserved: BTreeSet<u128>,
+}
+
+impl NonceState {
+    /// Hands out the lowest nonce neither used nor reserved.
+    fn reserve(&mut self) -> u128 {
+        let mut nonce = self.next;
+        while self.reserved.contains(&nonce) {
+            nonce += 1;
+        }
+        self.reserved.insert(nonce);
+        nonce
+    }
+}
+
+/// A settled payment whose transaction has no receipt yet.
+#[derive(Debug, Clone)]
+pub struct PendingPayment {
+    /// Settlement transaction hash
+    pub tx_hash: String,
+
+    /// Network the transaction was submitted on
+    pub chain: String,
+
+    /// Time the settlement was received
+    pub submitted_at: Instant,
+
+    /// Amount paid in the asset's smallest unit
+    pub amount: String,
+}
+
+#[derive(Debug)]
+struct PendingEntry {
+    payment: PendingPayment,
+    /// Whether a stuck event has been published for this payment
+    reported_stuck: bool,
+}
+
+impl PaymentManager {
+    /// Creates a new payment manager.
+    pub async fn new(config: &Arc<Config>, chain_manager: &Arc<ChainManager>) -> Result<Self> {
+        // Link transactions on configured chains to their explorer
+        let explorers = ExplorerRegistry::default();
+        for chain in &config.chains {
+            if let Some(explorer) = &chain.explorer_url {
+                let prefix = format!("{}/tx/", explorer.trim_end_matches('/'));
+                explorers.register(&NetworkId::from(chain.name.as_str()), &prefix);
+            }
+        }
+
+        Ok(Self {
+            config: config.clone(),
+            chain_manager: chain_manager.clone(),
+            history: RwLock::new(VecDeque::new()),
+            nonces: Mutex::new(HashMap::new()),
+            requirements_cache: RwLock::new(HashMap::new()),
+            price_oracle: PriceOracle::new(config)?,
+            facilitator: FacilitatorClient::new(config)?,
+            pending: RwLock::new(HashMap::new()),
+            events: EventBus::new(),
+            clock: PaymentClock::new(&config.clock),
+            spend: SpendTracker::new(&config.spend_alerts),
+            prices: PriceBaselines::new(&config.price_jumps),
+            nonce_counters: config.nonce_counter_path.clone().map(NonceCounters::new),
+            approvals: RwLock::new(HashMap::new()),
+            explorers,
+        })
+    }
+
+    /// Clock timestamping payment authorizations.
+    pub fn clock(&self) -> &PaymentClock {
+        &self.clock
+    }
+
+    /// Adds a handler called when spend to a host crosses its alert threshold.
+    pub fn add_spend_alert_handler(&self, handler: Box<dyn SpendAlertHandler>) {
+        self.spend.add_handler(handler);
+    }
+
+    /// Sets the approver consulted for payments far above their usual price,
+    /// see [`PriceJumpAction::Approve`](crate::config::PriceJumpAction::Approve).
+    pub fn set_payment_approver(&self, approver: Box<dyn PaymentApprover>) {
+        self.prices.set_approver(approver);
+    }
+
+    /// Links settlement transactions on `chain` to `url` followed by the
+    /// transaction hash, e.g. `https://explorer.example.org/tx/`.
+    ///
+    /// Replaces any built-in or earlier explorer of the network, for the
+    /// payments of this manager only. See [`chain_explorers`].
+    pub fn register_explorer(&self, chain: &str, url: &str) {
+        self.explorers.register(&NetworkId::from(chain), url);
+    }
+
+    /// Returns the block explorer page of the settlement transaction of
+    /// `payment`, using the explorers registered with this manager before
+    /// the built-in ones.
+    pub fn explorer_url(&self, payment: &PaymentHistory) -> Option<url::Url> {
+        self.explorers.transaction_url(payment)
+    }
+
+    /// Returns the jump a payment of `requirements` for `url` makes over
+    /// the usual price of its host and path prefix, if any.
+    ///
+    /// The price is then recorded whether or not it gets paid, so a lasting
+    /// price change becomes the baseline even while its payments are refused.
+    pub fn detect_price_jump(&self, url: &str, requirements: &PaymentRequirements) -> Option<PriceJump> {
+        let amount = requirements.max_amount_required.parse().ok()?;
+        let jump = self.prices.detect(url, amount);
+        self.prices.record(url, amount);
+        jump
+    }
+
+    /// Publishes `jump` and fails unless the configured action lets the payment go ahead.
+    pub async fn resolve_price_jump(&self, jump: PriceJump) -> Result<()> {
+        warn!(
+            url = %jump.url,
+            amount = %jump.amount,
+            baseline = %jump.baseline,
+            ratio = jump.ratio,
+            action = ?jump.action,
+            "Price jump detected"
+        );
+        let allowed = self.prices.allows(&jump).await;
+        let message = format!(
+            "Price of {} for {} is {:.1}x its usual {}",
+            jump.amount, jump.url, jump.ratio, jump.baseline
+        );
+        self.events.publish(ClientEvent::Payment(PaymentEvent::PriceJump(jump)));
+
+        if allowed {
+            Ok(())
+        } else {
+            Err(Error::Payment(message.into()))
+        }
+    }
+
+    /// Publishes payment events on `events`.
+    pub(crate) fn with_events(mut self, events: EventBus) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Starts polling pending payments every [`Config::pending_poll_interval`].
+    ///
+    /// The monitor runs as a task of `tasks`, restarted if it panics, and
+    /// stops when `tasks` shuts down or the manager is dropped.
+    pub(crate) fn start_pending_monitor(self: &Arc<Self>, tasks: &TaskManager) {
+        let manager = Arc::downgrade(self);
+        let interval = self.config.pending_poll_interval;
+        tasks.spawn("pending_payment_monitor", Restart::WithBackoff, move |shutdown| {
+            let manager = manager.clone();
+            async move {
+                let mut ticker = tokio::time::interval(interval);
+                ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                loop {
+                    tokio::select! {
+                        _ = shutdown.cancelled() => break,
+                        _ = ticker.tick() => {}
+                    }
+                    let Some(manager) = manager.upgrade() else { break };
+                    manager.poll_pending_payments().await;
+                }
+            }
+        });
+    }
+
+    /// Returns settled payments whose transaction has no receipt yet, oldest first.
+    pub fn get_pending_payments(&self) -> Vec<PendingPayment> {
+        let mut pending: Vec<_> = self
+            .pending
+            .read()
+            .values()
+            .map(|entry| entry.payment.clone())
+            .collect();
+        pending.sort_by_key(|payment| payment.submitted_at);
+        pending
+    }
+
+    /// Number of settled payments whose transaction has no receipt yet.
+    pub fn pending_count(&self) -> usize {
+        self.pending.read().len()
+    }
+
+    /// Drops pending payments that now have a receipt, settling the
+    /// records still pending on them, and reports those pending for longer
+    /// than [`Config::stuck_payment_threshold`].
+    async fn poll_pending_payments(&self) {
+        for payment in self.get_pending_payments() {
+            match self
+                .chain_manager
+                .transaction_status(&payment.chain, &payment.tx_hash)
+                .await
+            {
+                Ok(Some(success)) => {
+                    debug!(tx_hash = %payment.tx_hash, success, "Pending payment confirmed");
+                    self.pending.write().remove(&payment.tx_hash);
+                    self.settle_transaction(&payment.tx_hash, success);
+                    continue;
+                }
+                Ok(None) => {}
+                Err(e) => debug!(tx_hash = %payment.tx_hash, error = %e, "Failed to check pending payment"),
+            }
+
+            let age = payment.submitted_at.elapsed();
+            if age < self.config.stuck_payment_threshold {
+                continue;
+            }
+
+            let newly_stuck = self
+                .pending
+                .write()
+                .get_mut(&payment.tx_hash)
+                .is_some_and(|entry| !std::mem::replace(&mut entry.reported_stuck, true));
+            if newly_stuck {
+                warn!(
+                    tx_hash = %payment.tx_hash,
+                    chain = %payment.chain,
+                    age_secs = age.as_secs(),
+                    "Payment stuck pending"
+                );
+                self.events.publish(ClientEvent::Payment(PaymentEvent::PendingPaymentStuck {
+                    tx_hash: payment.tx_hash,
+                    age,
+                }));
+            }
+        }
+    }
+
+    /// Extracts payment requirements from a 402 response.
+    ///
+    /// The `X-PAYMENT-REQUIRED` header takes precedence over the body.
+    /// Balances are checked for the wallet paying for the response's URL.
+    pub async fn requirements_from_response(&self, response: &PaymentResponse) -> Result<PaymentRequirements> {
+        let header = response
+            .headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(PAYMENT_REQUIRED_HEADER));
+
+        match header {
+            Some((_, value)) => {
+                let value = HeaderValue::from_str(value)
+                    .map_err(|e| Error::Payment(format!("Invalid {} header: {}", PAYMENT_REQUIRED_HEADER, e).into()))?;
+                PaymentRequirements::try_from(&value)
+            }
+            None => {
+                self.parse_requirements_body(&response.body, response.header("content-type"), Some(&response.url))
+                    .await
+            }
+        }
+    }
+
+    /// Parses payment requirements from the body of a 402 response.
+    ///
+    /// When the server accepts several payment options, they are ranked with
+    /// [`Config::chain_rank`] and the first one accepted by
+    /// [`select_chain`](Self::select_chain) is used.
+    ///
+    /// A body that is not JSON, such as an HTML error page, fails with
+    /// [`Error::InvalidPaymentRequirements`] showing the start of the body.
+    pub async fn parse_payment_requirements(&self, body: &[u8]) -> Result<PaymentRequirements> {
+        self.parse_requirements_body(body, None, None).await
+    }
+
+    /// Parses the body of a 402 response for `url` served with `content_type`.
+    async fn parse_requirements_body(
+        &self,
+        body: &[u8],
+        content_type: Option<&str>,
+        url: Option<&str>,
+    ) -> Result<PaymentRequirements> {
+        debug!(content_type, body = %String::from_utf8_lossy(body), "Parsing payment requirements");
+
+        let invalid = |parse_error: String| Error::InvalidPaymentRequirements {
+            content_type: content_type.map(str::to_string),
+            body_preview: body_preview(body),
+            parse_error,
+            context: None,
+        };
+
+        // Only trust a non-JSON content type if the body does not look like JSON either
+        let looks_like_json = body.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'{');
+        if let Some(content_type) = content_type.filter(|ct| !ct.to_ascii_lowercase().contains("json")) {
+            if !looks_like_json {
+                return Err(invalid(format!("expected JSON, got {}", content_type)));
+            }
+        }
+
+        let parsed: PaymentRequiredBody = serde_json::from_slice(body).map_err(|e| invalid(e.to_string()))?;
+
+        if let Some(error) = parsed.error.as_deref().filter(|e| !e.is_empty()) {
+            debug!(error = %error, "Server reported payment error");
+        }
+
+        let configured: HashSet<NetworkId> = self.config.chains.iter().map(|c| NetworkId::from(c.name.as_str())).collect();
+
+        let mut accepts = Vec::with_capacity(parsed.accepts.len());
+        let mut last_error = None;
+        for requirements in parsed.accepts {
+            match requirements.validate() {
+                Ok(()) => accepts.push(requirements),
+                Err(e) => {
+                    debug!(network = %requirements.network, error = %e, "Skipping invalid payment requirements");
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        accepts.sort_by_key(|req| self.config.chain_rank(req.network.as_str()));
+
+        let mut quarantined = Vec::new();
+        let mut rejection = None;
+        for (index, requirements) in accepts.iter().enumerate() {
+            if !configured.contains(&requirements.network) {
+                continue;
+            }
+            if self.chain_manager.is_quarantined(requirements.network.as_str()) {
+                quarantined.push(requirements.network.to_string());
+                continue;
+            }
+            match self.select_chain_for(requirements, url.unwrap_or(requirements.resource.as_str())).await {
+                Ok(_) => return Ok(accepts.swap_remove(index)),
+                Err(e) => {
+                    debug!(network = %requirements.network, error = %e, "Skipping payment option");
+                    rejection.get_or_insert(e);
+                }
+            }
+        }
+
+        match rejection {
+            Some(e) => Err(e),
+            None if !quarantined.is_empty() => Err(Error::Chain(format!(
+                "All offered networks are quarantined: {}",
+                quarantined.join(", ")
+            ).into())),
+            None if !accepts.is_empty() => Ok(accepts.swap_remove(0)),
+            None => Err(last_error.unwrap_or_else(|| Error::Payment("No acceptable payment requirements".to_string().into()))),
+        }
+    }
+
+    /// Returns the chain to pay `requirements` on, if it can be used.
+    ///
+    /// The chain must be configured and not quarantined, the requirements
+    /// must list an asset, and the payer's balance of that asset must cover
+    /// the amount. The balance check doubles as an RPC health check and is
+    /// skipped when no wallet is routed to the requirements' resource.
+    pub async fn select_chain(&self, requirements: &PaymentRequirements) -> Result<&ChainConfig> {
+        self.select_chain_for(requirements, requirements.resource.as_str()).await
+    }
+
+    /// Like [`select_chain`](Self::select_chain), checking the balance of the wallet paying for `url`.
+    async fn select_chain_for(&self, requirements: &PaymentRequirements, url: &str) -> Result<&ChainConfig> {
+        let network = requirements.network.as_str();
+        let chain = self.chain_manager.chain(network)?;
+        if self.chain_manager.is_quarantined(network) {
+            return Err(Error::Chain(format!("Network {} is quarantined", network).into()));
+        }
+
+        let asset = requirements
+            .primary_asset()
+            .ok_or_else(|| Error::Payment("Payment requirements list no asset".to_string().into()))?;
+
+        let owner = self
+            .chain_manager
+            .route_wallet(url)
+            .and_then(|wallet| self.chain_manager.wallet_address(&wallet, network));
+        let Ok(owner) = owner else {
+            return Ok(chain);
+        };
+        let amount: u128 = requirements.max_amount_required.parse().map_err(|_| {
+            Error::Payment(format!("Invalid payment amount: {}", requirements.max_amount_required).into())
+        })?;
+        let balance = self.chain_manager.token_balance(network, &asset.address, &owner).await?;
+        if balance < amount {
+            return Err(Error::Payment(format!(
+                "Insufficient balance on {}: {} available, {} required",
+                network,
+                self.format_amount(requirements, &balance.to_string()),
+                self.format_amount(requirements, &requirements.max_amount_required)
+            ).into()));
+        }
+
+        Ok(chain)
+    }
+
+    /// Returns the facilitator for a payment on `network`, honouring a per-request override.
+    pub fn facilitator_for<'a>(&'a self, network: &str, override_url: Option<&'a str>) -> Result<&'a str> {
+        match override_url {
+            Some(url) => {
+                self.config.validate_facilitator_url(url)?;
+                Ok(url)
+            }
+            None => Ok(self.config.facilitator_for(network)),
+        }
+    }
+
+    /// Has `facilitator` verify that `payment_header` pays `requirements`,
+    /// see [`Config::verify_payments`].
+    pub async fn verify_payment(&self, facilitator: &str, payment_header: &str, requirements: &PaymentRequirements) -> Result<()> {
+        self.facilitator.verify(facilitator, payment_header, requirements).await
+    }
+
+    /// Caches the requirements advertised for `url`.
+    ///
+    /// Entries expire after the requirements' `maxTimeoutSeconds`.
+    pub fn cache_requirements(&self, url: &str, requirements: &PaymentRequirements) {
+        let expires_at = Instant::now() + Duration::from_secs(requirements.max_timeout_seconds);
+        self.requirements_cache
+            .write()
+            .insert(url.to_string(), (requirements.clone(), expires_at));
+    }
+
+    /// Returns unexpired cached requirements for `url`.
+    pub fn cached_requirements(&self, url: &str) -> Option<PaymentRequirements> {
+        {
+            let cache = self.requirements_cache.read();
+            match cache.get(url) {
+                Some((requirements, expires_at)) if *expires_at > Instant::now() => {
+                    return Some(requirements.clone());
+                }
+                Some(_) => {}
+                None => return None,
+            }
+        }
+        self.invalidate_requirements(url);
+        None
+    }
+
+    /// Drops cached requirements for `url`.
+    pub fn invalidate_requirements(&self, url: &str) {
+        self.requirements_cache.write().remove(url);
+    }
+
+    /// Creates a signed, base64-encoded `X-PAYMENT` header for the given requirements.
+    ///
+    /// The payment is made from the wallet routed to the requirements' resource.
+    pub async fn create_payment_header(&self, requirements: &PaymentRequirements) -> Result<String> {
+        let wallet = self.chain_manager.route_wallet(requirements.resource.as_str())?;
+        self.create_payment_header_timed(requirements, &wallet, &mut PaymentTiming::default())
+            .await
+    }
+
+    /// Creates a payment header paid from `wallet`, adding the time spent in each phase to `timing`.
+    pub(crate) async fn create_payment_header_timed(
+        &self,
+        requirements: &PaymentRequirements,
+        wallet: &str,
+        timing: &mut PaymentTiming,
+    ) -> Result<String> {
+        requirements.validate()?;
+        time_phase(
+            info_span!("check_balance"),
+            &mut timing.check_balance,
+            self.check_amount(requirements, wallet),
+        )
+        .await?;
+
+        let to = requirements
+            .primary_pay_to()
+            .map(|destination| destination.address.clone())
+            .unwrap_or_default();
+        let (from, nonce) = time_phase(
+            info_span!("select_chain", network = %requirements.network),
+            &mut timing.select_chain,
+            async {
+                if self.chain_manager.is_quarantined(requirements.network.as_str()) {
+                    return Err(Error::Chain(format!("Network {} is quarantined", requirements.network).into()));
+                }
+                let from = self.chain_manager.wallet_address(wallet, requirements.network.as_str())?;
+                let nonce = self.replay_protection(requirements, &from).await?;
+                Ok::<_, Error>((from, nonce))
+            },
+        )
+        .await?;
+        let (valid_after, valid_before) = self.clock.validity_window(requirements.max_timeout_seconds);
+
+        let authorization = Authorization {
+            from,
+            to,
+            value: requirements.max_amount_required.clone(),
+            valid_after: valid_after.to_string(),
+            valid_before: valid_before.to_string(),
+            nonce,
+        };
+
+        let signature = time_phase(
+            info_span!("sign_payment"),
+            &mut timing.sign_payment,
+            self.chain_manager.sign_authorization(requirements, &authorization),
+        )
+        .await?;
+
+        let payload = PaymentPayload {
+            x402_version: X402_VERSION,
+            scheme: &requirements.scheme,
+            network: requirements.network.as_str(),
+            payload: ExactPayload {
+                signature,
+                authorization: &authorization,
+            },
+        };
+
+        Ok(BASE64.encode(serde_json::to_vec(&payload)?))
+    }
+
+    /// Returns the nonce to use for the next payment from `payer`.
+    ///
+    /// By default a random 32-byte nonce is generated. With
+    /// [`Config::nonce_counter_path`] set, the nonce is derived from the
+    /// payer's key, the requirements and the payer's next counter, see
+    /// [`nonce`]. With [`Config::on_chain_nonce`] enabled, nonces are
+    /// sequential and seeded from the payment contract's `getNonce(address)`
+    /// on first use, so a restarted client continues from the on-chain value
+    /// instead of zero. Such a nonce is only reserved: it is used up by
+    /// [`commit_nonce`](Self::commit_nonce) once the payment is sent, and
+    /// handed out again after [`release_nonce`](Self::release_nonce).
+    pub async fn replay_protection(&self, requirements: &PaymentRequirements, payer: &str) -> Result<String> {
+        if !self.config.on_chain_nonce {
+            if let Some(counters) = &self.nonce_counters {
+                let counter = counters.reserve(payer).await?;
+                debug!(payer, counter, "Deriving payment nonce");
+                return self.reconstruct_nonce(requirements, payer, counter);
+            }
+
+            let mut nonce = [0u8; 32];
+            nonce[..16].copy_from_slice(Uuid::new_v4().as_bytes());
+            nonce[16..].copy_from_slice(Uuid::new_v4().as_bytes());
+            return Ok(format!("0x{}", hex::encode(nonce)));
+        }
+
+        let key = (requirements.network.clone(), payer.to_ascii_lowercase());
+        let mut nonces = self.nonces.lock().await;
+
+        let state = match nonces.entry(key) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let nonce = self.read_on_chain_nonce(requirements.network.as_str(), payer).await?;
+                debug!(network = %requirements.network, payer, nonce, "Recovered on-chain nonce");
+                entry.insert(NonceState { next: nonce, reserved: BTreeSet::new() })
+            }
+        };
+        let nonce = state.reserve();
+
+        Ok(format!("0x{}", hex::encode(crypto::uint_word(nonce))))
+    }
+
+    /// Marks the on-chain nonce of `payment_header`, a payment on `network`
+    /// that was sent, as used, see [`replay_protection`](Self::replay_protection).
+    ///
+    /// Does nothing unless [`Config::on_chain_nonce`] is enabled.
+    pub async fn commit_nonce(&self, network: &str, payment_header: &str) {
+        self.finish_nonce(network, payment_header, true).await;
+    }
+
+    /// Hands the on-chain nonce of `payment_header`, a payment on `network`
+    /// that was never sent, out again to the next payment.
+    ///
+    /// Does nothing unless [`Config::on_chain_nonce`] is enabled.
+    pub async fn release_nonce(&self, network: &str, payment_header: &str) {
+        self.finish_nonce(network, payment_header, false).await;
+    }
+
+    /// Ends the reservation of the nonce of `payment_header`, using it up if `sent`.
+    async fn finish_nonce(&self, network: &str, payment_header: &str, sent: bool) {
+        if !self.config.on_chain_nonce {
+            return;
+        }
+        let Some(authorization) = decode_authorization(payment_header) else {
+            return;
+        };
+        let Ok(nonce) = crypto::parse_bytes32(&authorization.nonce).and_then(|word| crypto::word_to_uint(&word)) else {
+            return;
+        };
+
+        let key = (NetworkId::from(network), authorization.from.to_ascii_lowercase());
+        if let Some(state) = self.nonces.lock().await.get_mut(&key) {
+            state.reserved.remove(&nonce);
+            if sent {
+                state.next = state.next.max(nonce + 1);
+            }
+        }
+    }
+
+    /// Recomputes the deterministic nonce `payer` used, or will use, for
+    /// `requirements` at `counter`.
+    ///
+    /// `payer` must be one of the client's wallets.
+    pub fn reconstruct_nonce(&self, requirements: &PaymentRequirements, payer: &str, counter: u64) -> Result<String> {
+        let (resource_hash, requirements_hash) = nonce::nonce_inputs(requirements)?;
+        let nonce = self.chain_manager.derive_nonce(payer, &resource_hash, &requirements_hash, counter)?;
+        Ok(format!("0x{}", hex::encode(nonce)))
+    }
+
+    /// Returns the counter of the next deterministic nonce of `payer`, or
+    /// `None` if deterministic nonces are disabled.
+    pub async fn nonce_counter(&self, payer: &str) -> Result<Option<u64>> {
+        match &self.nonce_counters {
+            Some(counters) => Ok(Some(counters.next(payer).await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns whether the token of `requirements` has marked `nonce` of
+    /// `payer` as used, by calling its EIP-3009 `authorizationState`.
+    pub async fn is_nonce_used(&self, requirements: &PaymentRequirements, payer: &str, nonce: &str) -> Result<bool> {
+        let token = requirements
+            .primary_asset()
+            .ok_or_else(|| Error::Payment("Payment requirements name no asset".to_string().into()))?;
+
+        let mut calldata = crypto::function_selector(AUTHORIZATION_STATE_SIGNATURE).to_vec();
+        calldata.extend_from_slice(&crypto::address_word(&crypto::parse_address(payer)?));
+        calldata.extend_from_slice(&crypto::parse_bytes32(nonce)?);
+
+        let result = self.chain_manager.call_view(requirements.network.as_str(), &token.address, &calldata).await?;
+        Ok(crypto::word_to_uint(&result)? != 0)
+    }
+
+    /// Submits an ERC-20 `approve(spender, amount)` of `token` on `chain`
+    /// from the default wallet, returning the transaction hash.
+    ///
+    /// The transaction is sent right away, whatever the current allowance,
+    /// and its hash is kept for [`approval`](Self::approval). It is not
+    /// waited for. On dry-run chains the approval is only simulated and
+    /// `None` is returned.
+    pub async fn preapprove(&self, chain: &str, token: &str, spender: &str, amount: u128) -> Result<Option<String>> {
+        let mut calldata = crypto::function_selector(APPROVE_SIGNATURE).to_vec();
+        calldata.extend_from_slice(&crypto::address_word(&crypto::parse_address(spender)?));
+        calldata.extend_from_slice(&crypto::uint_word(amount));
+
+        let owner = self.chain_manager.address(chain)?;
+        let Some(tx_hash) = self.chain_manager.send_transaction(chain, &owner, token, &calldata).await? else {
+            return Ok(None);
+        };
+        info!(chain, token, spender, amount = %amount, tx_hash = %tx_hash, "Submitted token approval");
+
+        self.approvals.write().insert(approval_key(chain, token, spender), tx_hash.clone());
+        Ok(Some(tx_hash))
+    }
+
+    /// Returns the hash of the last approval submitted for `spender` to spend `token` on `chain`.
+    pub fn approval(&self, chain: &str, token: &str, spender: &str) -> Option<String> {
+        self.approvals.read().get(&approval_key(chain, token, spender)).cloned()
+    }
+
+    /// Reads the ERC-20 allowance `owner` gave `spender` on `token`.
+    ///
+    /// Allowances above `u128::MAX`, such as unlimited approvals, are
+    /// returned as `u128::MAX`.
+    pub async fn allowance(&self, chain: &str, token: &str, owner: &str, spender: &str) -> Result<u128> {
+        let mut calldata = crypto::function_selector(ALLOWANCE_SIGNATURE).to_vec();
+        calldata.extend_from_slice(&crypto::address_word(&crypto::parse_address(owner)?));
+        calldata.extend_from_slice(&crypto::address_word(&crypto::parse_address(spender)?));
+
+        let result = self.chain_manager.call_view(chain, token, &calldata).await?;
+        match result.get(..32) {
+            Some(word) if word[..16].iter().any(|b| *b != 0) => Ok(u128::MAX),
+            Some(word) => crypto::word_to_uint(word),
+            None => Err(Error::Chain(format!("Invalid allowance returned by {}", token).into())),
+        }
+    }
+
+    /// Approves the allowances of [`Config::preapprove_tokens`] not yet
+    /// covered on chain, one after the other, returning the transaction hashes.
+    ///
+    /// Approvals on dry-run chains are only simulated, see
+    /// [`preapprove`](Self::preapprove). An allowance that cannot be read or
+    /// approved is logged and left for the next one; payments needing it
+    /// fail later.
+    pub(crate) async fn preapprove_configured(&self) -> Vec<String> {
+        let mut tx_hashes = Vec::new();
+        for preapprove in &self.config.preapprove_tokens {
+            let PreapproveConfig { chain, token, spender, amount } = preapprove;
+            match self.preapprove_if_needed(preapprove).await {
+                Ok(Some(tx_hash)) => tx_hashes.push(tx_hash),
+                Ok(None) => {}
+                Err(e) => warn!(
+                    chain = %chain,
+                    token = %token,
+                    spender = %spender,
+                    amount = %amount,
+                    error = %e,
+                    "Failed to pre-approve token allowance"
+                ),
+            }
+        }
+        tx_hashes
+    }
+
+    /// Approves `preapprove` unless the current allowance covers it, returning
+    /// the hash of the transaction if one was sent.
+    async fn preapprove_if_needed(&self, preapprove: &PreapproveConfig) -> Result<Option<String>> {
+        let PreapproveConfig { chain, token, spender, amount } = preapprove;
+        let owner = self.chain_manager.address(chain)?;
+        let allowance = self.allowance(chain, token, &owner, spender).await?;
+        if allowance >= *amount {
+            debug!(chain = %chain, token = %token, spender = %spender, allowance, "Allowance already approved");
+            return Ok(None);
+        }
+        self.preapprove(chain, token, spender, *amount).await
+    }
+
+    /// Reads `getNonce(payer)` from the network's payment contract.
+    async fn read_on_chain_nonce(&self, network: &str, payer: &str) -> Result<u128> {
+        let contract = self
+            .chain_manager
+            .chain(network)?
+            .payment_contract
+            .clone()
+            .ok_or_else(|| Error::Config(format!("No payment contract configured for {}", network).into()))?;
+
+        let mut calldata = crypto::function_selector(GET_NONCE_SIGNATURE).to_vec();
+        calldata.extend_from_slice(&crypto::address_word(&crypto::parse_address(payer)?));
+
+        let result = self.chain_manager.call_view(network, &contract, &calldata).await?;
+        crypto::word_to_uint(&result)
+    }
+
+    /// Decodes the `X-PAYMENT-RESPONSE` settlement header.
+    pub async fn process_settlement(&self, header: &str) -> Result<Settlement> {
+        let decoded = BASE64
+            .decode(header.trim())
+            .map_err(|e| Error::Payment(format!("Invalid settlement header: {}", e).into()))?;
+
+        Ok(serde_json::from_slice(&decoded)?)
+    }
+
+    /// Returns the reason a paid request was rejected if it reports an
+    /// authorization outside its validity window, such as one signed by a
+    /// machine whose clock is off.
+    ///
+    /// The reason is read from the `error` field of a 402 body or from a
+    /// failed settlement in the `X-PAYMENT-RESPONSE` header.
+    pub(crate) async fn time_validity_error(&self, response: &PaymentResponse) -> Option<String> {
+        let settlement_error = match response.header("X-PAYMENT-RESPONSE") {
+            Some(header) => match self.process_settlement(header).await {
+                Ok(settlement) if !settlement.success => settlement.error_reason,
+                _ => None,
+            },
+            None => None,
+        };
+        let body_error = (response.status == 402)
+            .then(|| serde_json::from_slice::<PaymentRequiredBody>(&response.body).ok())
+            .flatten()
+            .and_then(|body| body.error);
+
+        settlement_error.into_iter().chain(body_error).find(|reason| is_time_validity_error(reason))
+    }
+
+    /// Encodes payment metadata as the value of the `X-PAYMENT-METADATA` header.
+    ///
+    /// Keys are serialized in sorted order so identical metadata always
+    /// produces the same header. Fails if the JSON encoding exceeds
+    /// [`MAX_METADATA_BYTES`].
+    pub fn encode_metadata(&self, metadata: &HashMap<String, String>) -> Result<String> {
+        let sorted: BTreeMap<&String, &String> = metadata.iter().collect();
+        let json = serde_json::to_vec(&sorted)?;
+
+        if json.len() > MAX_METADATA_BYTES {
+            return Err(Error::Payment(format!(
+                "Payment metadata is {} bytes, maximum is {}",
+                json.len(),
+                MAX_METADATA_BYTES
+            ).into()));
+        }
+
+        Ok(BASE64.encode(json))
+    }
+
+    /// Decodes an `X-PAYMENT-METADATA` header value.
+    pub fn decode_metadata(header: &str) -> Result<HashMap<String, String>> {
+        let decoded = BASE64
+            .decode(header.trim())
+            .map_err(|e| Error::Payment(format!("Invalid payment metadata header: {}", e).into()))?;
+
+        Ok(serde_json::from_slice(&decoded)?)
+    }
+
+    /// Returns the `validBefore` Unix timestamp of a signed `X-PAYMENT` header.
+    ///
+    /// Returns `None` if the header cannot be decoded or carries no authorization.
+    pub fn payment_valid_before(header: &str) -> Option<i64> {
+        decode_authorization(header)?.valid_before.parse().ok()
+    }
+
+    /// Simulates the settlement of a signed `X-PAYMENT` header without
+    /// broadcasting it.
+    ///
+    /// The facilitator's `transferWithAuthorization` call is replayed with
+    /// `eth_call`; the returned settlement succeeds if the call would, carries
+    /// the revert reason otherwise, and never has a transaction hash.
+    pub async fn simulate_payment(&self, requirements: &PaymentRequirements, payment_header: &str) -> Result<Settlement> {
+        let asset = requirements
+            .primary_asset()
+            .ok_or_else(|| Error::Payment("Payment requirements list no asset".to_string().into()))?;
+        let (authorization, signature) = decode_signed_authorization(payment_header)
+            .ok_or_else(|| Error::Payment("Payment header carries no authorization".to_string().into()))?;
+
+        let revert_reason = self
+            .chain_manager
+            .simulate_transfer(requirements.network.as_str(), &asset.address, &authorization, &signature)
+            .await?;
+
+        Ok(Settlement {
+            success: revert_reason.is_none(),
+            transaction_hash: None,
+            network: Some(requirements.network.to_string()),
+            payer: Some(authorization.from),
+            error_reason: revert_reason,
+        })
+    }
+
+    /// Issues a receipt for a settled payment, signed by the wallet that authorized it.
+    pub async fn issue_receipt(
+        &self,
+        url: &str,
+        requirements: &PaymentRequirements,
+        payment_header: &str,
+        settlement: &Settlement,
+        request_id: &str,
+        paid_at: DateTime<Utc>,
+    ) -> Result<Receipt> {
+        let payer = decode_authorization(payment_header)
+            .map(|authorization| authorization.from)
+            .ok_or_else(|| Error::Payment("Payment header carries no authorization".to_string().into()))?;
+        let mut receipt = Receipt {
+            url: url.to_string(),
+            requirements_hash: receipt::requirements_hash(requirements)?,
+            payment_header: payment_header.to_string(),
+            settlement: settlement.clone(),
+            transaction_hash: settlement.transaction_hash.clone(),
+            network: requirements.network.to_string(),
+            payer,
+            request_id: request_id.to_string(),
+            paid_at,
+            issued_at: Utc::now(),
+            signature: String::new(),
+        };
+        receipt.signature = self.chain_manager.sign_message(&receipt.payer, &receipt.digest()?)?;
+        Ok(receipt)
+    }
+
+    /// Returns the receipt of the payment made by the request with `request_id`.
+    pub fn get_receipt(&self, request_id: &str) -> Option<Receipt> {
+        self.history
+            .read()
+            .iter()
+            .rev()
+            .find(|record| record.request_id.as_deref() == Some(request_id))
+            .and_then(|record| record.receipt.clone())
+    }
+
+    /// Records a payment made for `url` from `wallet` with `payment_header`
+    /// in the payment history.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_payment(
+        &self,
+        url: &str,
+        requirements: &PaymentRequirements,
+        payment_header: &str,
+        response: &PaymentResponse,
+        metadata: HashMap<String, String>,
+        facilitator: &str,
+        receipt: Option<Receipt>,
+        wallet: &str,
+    ) -> PaymentHistory {
+        let status = match (&response.transaction_hash, response.is_success()) {
+            _ if response.dry_run && response.is_success() => PaymentStatus::Confirmed,
+            _ if response.dry_run => PaymentStatus::Failed,
+            (Some(_), _) => PaymentStatus::Confirmed,
+            (None, true) => PaymentStatus::Pending,
+            (None, false) => PaymentStatus::Failed,
+        };
+
+        let asset = requirements.primary_asset();
+        let token = asset.and_then(|a| self.chain_manager.tokens().get(requirements.network.as_str(), &a.address));
+        let usd_value = asset.and_then(|a| self.usd_value(requirements.network.as_str(), a, &requirements.max_amount_required));
+        let record = PaymentHistory {
+            schema_version: schema::CURRENT_SCHEMA_VERSION,
+            payment_id: format!("pay_{}", Uuid::new_v4().simple()),
+            url: url.to_string(),
+            amount: requirements.max_amount_required.clone(),
+            asset: asset.map(|a| a.address.clone()).unwrap_or_default(),
+            asset_decimals: asset.and_then(|a| a.decimals).or(token.as_ref().map(|t| t.decimals)),
+            asset_symbol: token.map(|t| t.symbol).or_else(|| asset.and_then(|a| a.symbol.clone())),
+            usd_value,
+            transaction_hash: response.transaction_hash.clone(),
+            network: requirements.network.clone(),
+            payer: response.payer.clone(),
+            payee: requirements
+                .primary_pay_to()
+                .map(|destination| destination.address.clone())
+                .unwrap_or_default(),
+            timestamp: Utc::now(),
+            status,
+            description: requirements.description.clone(),
+            metadata,
+            request_id: Some(response.request_id.clone()).filter(|id| !id.is_empty()),
+            batch_id: response.batch_id.clone(),
+            facilitator: Some(facilitator.to_string()),
+            timing: response.payment_timing,
+            receipt,
+            wallet: Some(wallet.to_string()),
+            dry_run: response.dry_run,
+            authorization_nonce: decode_authorization(payment_header).map(|authorization| authorization.nonce),
+        };
+
+        if let Some(tx_hash) = &record.transaction_hash {
+            self.track_pending(tx_hash, record.network.as_str(), &record.amount);
+        }
+
+        let spent = matches!(record.status, PaymentStatus::Confirmed | PaymentStatus::Pending) && !record.dry_run;
+        if spent {
+            if let Some(alert) = self.spend.record(url, record.amount.parse().unwrap_or(0)) {
+                warn!(
+                    host = %alert.host,
+                    spent = %alert.spent,
+                    threshold = %alert.threshold,
+                    payments = alert.payments,
+                    "Spend alert threshold crossed"
+                );
+                self.events.publish(ClientEvent::Payment(PaymentEvent::SpendAlert(alert)));
+            }
+        }
+
+        self.push_history(record.clone());
+        info!(
+            payment_id = %record.payment_id,
+            request_id = record.request_id.as_deref().unwrap_or_default(),
+            status = %record.status,
+            "Recorded payment"
+        );
+
+        record
+    }
+
+    /// Watches the transaction `tx_hash` until it has a receipt.
+    ///
+    /// At most [`MAX_PENDING_PAYMENTS`] are watched; past that the oldest
+    /// one is dropped, leaving its record pending.
+    fn track_pending(&self, tx_hash: &str, chain: &str, amount: &str) {
+        let mut pending = self.pending.write();
+        if pending.len() >= MAX_PENDING_PAYMENTS && !pending.contains_key(tx_hash) {
+            let oldest = pending
+                .values()
+                .min_by_key(|entry| entry.payment.submitted_at)
+                .map(|entry| entry.payment.tx_hash.clone());
+            if let Some(oldest) = oldest {
+                warn!(tx_hash = %oldest, "Too many pending payments, no longer watching the oldest");
+                pending.remove(&oldest);
+            }
+        }
+        pending.insert(
+            tx_hash.to_string(),
+            PendingEntry {
+                payment: PendingPayment {
+                    tx_hash: tx_hash.to_string(),
+                    chain: chain.to_string(),
+                    submitted_at: Instant::now(),
+                    amount: amount.to_string(),
+                },
+                reported_stuck: false,
+            },
+        );
+    }
+
+    /// Waits for the facilitator to settle the payment `payment_id`, made
+    /// without a settlement in its response, and updates its record.
+    ///
+    /// The facilitator is queried with exponential backoff until the payment
+    /// settles or fails, and the updated record is returned. Records with a
+    /// transaction hash or a final status are returned as they are. Fails
+    /// with [`Error::Timeout`] if the payment is still pending after
+    /// [`PollOptions::timeout`], or with the last error if the facilitator
+    /// could not be queried.
+    pub async fn poll_settlement(&self, payment_id: &str, options: &PollOptions) -> Result<PaymentHistory> {
+        let record = self
+            .history
+            .read()
+            .iter()
+            .rev()
+            .find(|record| record.payment_id == payment_id)
+            .cloned()
+            .ok_or_else(|| Error::Payment(format!("No payment with ID {}", payment_id).into()))?;
+        if record.transaction_hash.is_some() || record.status != PaymentStatus::Pending {
+            return Ok(record);
+        }
+        let Some(nonce) = &record.authorization_nonce else {
+            return Err(Error::Payment(format!(
+                "Payment {} has no authorization nonce to look up its settlement",
+                payment_id
+            ).into()));
+        };
+        let facilitator = self.record_facilitator(&record);
+
+        let deadline = Instant::now() + options.timeout;
+        let (mut interval, max_interval) = options.intervals();
+        loop {
+            let last_error = match self.facilitator.settlement_status(facilitator, record.network.as_str(), nonce).await {
+                Ok(SettlementStatus::Pending) => None,
+                Ok(status) => {
+                    return self
+                        .apply_settlement(payment_id, status)
+                        .ok_or_else(|| Error::Payment(format!("Payment {} left the history while polling", payment_id).into()));
+                }
+                Err(e) => {
+                    debug!(payment_id, error = %e, "Settlement status query failed");
+                    Some(e)
+                }
+            };
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(last_error.unwrap_or_else(|| Error::Timeout(facilitator.into(), options.timeout)));
+            }
+            tokio::time::sleep(interval.min(remaining)).await;
+            interval = (interval * 2).min(max_interval);
+        }
+    }
+
+    /// Starts asking facilitators every [`Config::pending_poll_interval`] for
+    /// the settlement of payments made without one, if
+    /// [`Config::poll_settlement`] is set.
+    ///
+    /// Runs like the [pending monitor](Self::start_pending_monitor).
+    pub(crate) fn start_settlement_monitor(self: &Arc<Self>, tasks: &TaskManager) {
+        if !self.config.poll_settlement {
+            return;
+        }
+        let manager = Arc::downgrade(self);
+        let interval = self.config.pending_poll_interval;
+        tasks.spawn("settlement_monitor", Restart::WithBackoff, move |shutdown| {
+            let manager = manager.clone();
+            async move {
+                let mut ticker = tokio::time::interval(interval);
+                ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                loop {
+                    tokio::select! {
+                        _ = shutdown.cancelled() => break,
+                        _ = ticker.tick() => {}
+                    }
+                    let Some(manager) = manager.upgrade() else { break };
+                    manager.poll_unsettled_payments().await;
+                }
+            }
+        });
+    }
+
+    /// Asks facilitators once for the settlement of each payment made without
+    /// one, younger than [`Config::settlement_poll_timeout`].
+    async fn poll_unsettled_payments(&self) {
+        let now = Utc::now();
+        let unsettled: Vec<(String, NetworkId, String, String)> = self
+            .history
+            .read()
+            .iter()
+            .filter(|record| record.status == PaymentStatus::Pending && record.transaction_hash.is_none())
+            .filter(|record| (now - record.timestamp).to_std().unwrap_or_default() < self.config.settlement_poll_timeout)
+            .filter_map(|record| {
+                let nonce = record.authorization_nonce.clone()?;
+                let facilitator = self.record_facilitator(record).to_string();
+                Some((record.payment_id.clone(), record.network.clone(), facilitator, nonce))
+            })
+            .collect();
+
+        for (payment_id, network, facilitator, nonce) in unsettled {
+            match self.facilitator.settlement_status(&facilitator, network.as_str(), &nonce).await {
+                Ok(SettlementStatus::Pending) => {}
+                Ok(status) => {
+                    self.apply_settlement(&payment_id, status);
+                }
+                Err(e) => debug!(payment_id, error = %e, "Settlement status query failed"),
+            }
+        }
+    }
+
+    /// Returns the facilitator that handled `record`, or the one configured
+    /// for its network for records that do not name one, such as imported history.
+    fn record_facilitator<'a>(&'a self, record: &'a PaymentHistory) -> &'a str {
+        record
+            .facilitator
+            .as_deref()
+            .unwrap_or_else(|| self.config.facilitator_for(record.network.as_str()))
+    }
+
+    /// Updates the record of `payment_id` with a final settlement status and
+    /// publishes [`PaymentEvent::PaymentSettled`], returning the record.
+    fn apply_settlement(&self, payment_id: &str, status: SettlementStatus) -> Option<PaymentHistory> {
+        let record = {
+            let mut history = self.history.write();
+            let record = history.iter_mut().rev().find(|record| record.payment_id == payment_id)?;
+            match status {
+                SettlementStatus::Pending => return Some(record.clone()),
+                SettlementStatus::Settled(settlement) => {
+                    record.status = PaymentStatus::Confirmed;
+                    record.transaction_hash = settlement.transaction_hash;
+                    record.payer = settlement.payer.or(record.payer.take());
+                }
+                SettlementStatus::Failed(settlement) => {
+                    record.status = PaymentStatus::Failed;
+                    warn!(
+                        payment_id,
+                        reason = settlement.error_reason.as_deref().unwrap_or_default(),
+                        "Facilitator failed to settle payment"
+                    );
+                }
+            }
+            record.clone()
+        };
+
+        if let Some(tx_hash) = &record.transaction_hash {
+            self.track_pending(tx_hash, record.network.as_str(), &record.amount);
+        }
+        info!(
+            payment_id,
+            status = %record.status,
+            transaction_hash = record.transaction_hash.as_deref().unwrap_or_default(),
+            "Payment settlement received from facilitator"
+        );
+        self.events.publish(ClientEvent::Payment(PaymentEvent::PaymentSettled {
+            payment_id: payment_id.to_string(),
+            transaction_hash: record.transaction_hash.clone(),
+            success: record.status == PaymentStatus::Confirmed,
+        }));
+        Some(record)
+    }
+
+    /// Marks the pending records paid by the transaction `tx_hash` as
+    /// confirmed, or failed if the transaction did not succeed.
+    fn settle_transaction(&self, tx_hash: &str, success: bool) {
+        let status = if success { PaymentStatus::Confirmed } else { PaymentStatus::Failed };
+        let settled: Vec<String> = self
+            .history
+            .write()
+            .iter_mut()
+            .filter(|record| record.status == PaymentStatus::Pending && record.transaction_hash.as_deref() == Some(tx_hash))
+            .map(|record| {
+                record.status = status;
+                record.payment_id.clone()
+            })
+            .collect();
+
+        for payment_id in settled {
+            info!(%payment_id, %tx_hash, %status, "Payment transaction included");
+            self.events.publish(ClientEvent::Payment(PaymentEvent::PaymentSettled {
+                payment_id,
+                transaction_hash: Some(tx_hash.to_string()),
+                success,
+            }));
+        }
+    }
+
+    /// Appends `record` to the history, dropping the oldest record once it is full.
+    fn push_history(&self, record: PaymentHistory) {
+        let mut history = self.history.write();
+        if history.len() >= MAX_HISTORY_ENTRIES {
+            history.pop_front();
+        }
+        history.push_back(record);
+    }
+
+    /// Returns up to `limit` of the most recent payments, newest first.
+    pub async fn get_history(&self, limit: usize) -> Result<Vec<PaymentHistory>> {
+        Ok(self.history.read().iter().rev().take(limit).cloned().collect())
+    }
+
+    /// Returns up to `count` payments recorded after the payment with ID
+    /// `after`, or from the oldest one if `after` is `None`, oldest first.
+    ///
+    /// Used to walk the history in bounded pages without cloning all of it,
+    /// passing the ID of the last payment of each page to get the next. The
+    /// walk neither skips nor repeats payments as new ones are recorded and
+    /// old ones dropped: if `after` has been dropped, every payment still
+    /// held is newer and the page starts from the oldest.
+    pub fn history_page(&self, after: Option<&str>, count: usize) -> Vec<PaymentHistory> {
+        let history = self.history.read();
+        let start = after
+            .and_then(|after| history.iter().rposition(|record| record.payment_id == after))
+            .map_or(0, |position| position + 1);
+        history.iter().skip(start).take(count).cloned().collect()
+    }
+
+    /// Computes aggregate statistics over the recorded payments.
+    pub async fn get_statistics(&self) -> Result<PaymentStatistics> {
+        let history = self.history.read();
+
+        if history.is_empty() {
+            return Ok(PaymentStatistics {
+                total_amount: "0".to_string(),
+                average_amount: "0".to_string(),
+                min_amount: "0".to_string(),
+                max_amount: "0".to_string(),
+                ..Default::default()
+            });
+        }
+
+        let amounts: Vec<u128> = history
+            .iter()
+            .filter(|p| p.status == PaymentStatus::Confirmed)
+            .filter_map(|p| p.amount.parse::<u128>().ok())
+            .collect();
+
+        let total = amounts.iter().fold(0u128, |total, amount| total.saturating_add(*amount));
+        let successful = amounts.len() as u64;
+        let failed = history
+            .iter()
+            .filter(|p| matches!(p.status, PaymentStatus::Failed | PaymentStatus::Expired))
+            .count() as u64;
+
+        // Payments recorded before multi-wallet support were made by the private key's wallet
+        let mut by_wallet: BTreeMap<String, (WalletStatistics, u128)> = BTreeMap::new();
+        for payment in history.iter() {
+            let (stats, total) = by_wallet
+                .entry(payment.wallet.clone().unwrap_or_else(|| DEFAULT_WALLET.to_string()))
+                .or_default();
+            stats.total_payments += 1;
+            if payment.status == PaymentStatus::Confirmed {
+                stats.successful_payments += 1;
+                *total = total.saturating_add(payment.amount.parse::<u128>().unwrap_or(0));
+            }
+        }
+
+        let networks: HashSet<&str> = history.iter().map(|p| p.network.as_str()).collect();
+        let resources: HashSet<&str> = history.iter().map(|p| p.url.as_str()).collect();
+
+        Ok(PaymentStatistics {
+            total_payments: history.len() as u64,
+            successful_payments: successful,
+            failed_payments: failed,
+            total_amount: total.to_string(),
+            average_amount: if successful > 0 { (total / successful as u128).to_string() } else { "0".to_string() },
+            min_amount: amounts.iter().min().copied().unwrap_or(0).to_string(),
+            max_amount: amounts.iter().max().copied().unwrap_or(0).to_string(),
+            unique_resources: resources.len(),
+            unique_networks: networks.len(),
+            time_period_start: history.front().map(|p| p.timestamp),
+            time_period_end: history.back().map(|p| p.timestamp),
+            by_wallet: by_wallet
+                .into_iter()
+                .map(|(wallet, (stats, total))| {
+                    let total_amount = total.to_string();
+                    (wallet, WalletStatistics { total_amount, ..stats })
+                })
+                .collect(),
+            confirmed: history
+                .iter()
+                .filter(|p| p.status == PaymentStatus::Confirmed)
+                .filter_map(|p| match p.amount.parse() {
+                    Ok(amount) => Some(ConfirmedPayment {
+                        timestamp: p.timestamp,
+                        amount,
+                        payee: p.payee.clone(),
+                    }),
+                    Err(_) => {
+                        warn!(payment_id = %p.payment_id, amount = %p.amount, "Leaving payment with an invalid amount out of the time series");
+                        None
+                    }
+                })
+                .collect(),
+        })
+    }
+
+    /// Computes statistics over the recorded payments per paid host.
+    ///
+    /// Payments to URLs without a host are grouped under the full URL.
+    pub fn get_statistics_by_host(&self) -> BTreeMap<String, HostStatistics> {
+        let mut by_host: BTreeMap<String, (HostStatistics, u128)> = BTreeMap::new();
+        for payment in self.history.read().iter() {
+            let host = url::Url::parse(&payment.url)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_ascii_lowercase))
+                .unwrap_or_else(|| payment.url.clone());
+            let (stats, total) = by_host.entry(host).or_default();
+            stats.total_payments += 1;
+            if payment.status == PaymentStatus::Confirmed {
+                stats.successful_payments += 1;
+                *total = total.saturating_add(payment.amount.parse::<u128>().unwrap_or(0));
+            }
+            stats.last_payment = stats.last_payment.max(Some(payment.timestamp));
+        }
+
+        by_host
+            .into_iter()
+            .map(|(host, (stats, total))| {
+                let average = total.checked_div(stats.successful_payments.into()).unwrap_or(0);
+                let stats = HostStatistics {
+                    total_amount: total.to_string(),
+                    average_amount: average.to_string(),
+                    ..stats
+                };
+                (host, stats)
+            })
+            .collect()
+    }
+
+    /// Returns the per-request maximum in units of `asset` on `network`.
+    ///
+    /// A USD cap is converted with the price oracle. If no price is available
+    /// the unit cap is used when configured, otherwise the payment is refused.
+    async fn max_asset_amount(&self, network: &str, asset: Option<&AssetInfo>) -> Result<u128> {
+        if let Some(usd) = self.config.max_amount_usd {
+            match self.usd_to_units(usd, network, asset).await {
+                Ok(max) => return Ok(max),
+                Err(e) if self.config.max_amount_per_request.is_some() => {
+                    warn!(error = %e, "Falling back to max_amount_per_request");
+                }
+                Err(e) => {
+                    return Err(match e {
+                        Error::PriceOracleUnavailable(_) => e,
+                        other => Error::PriceOracleUnavailable(other.to_string().into()),
+                    });
+                }
+            }
+        }
+
+        self.config
+            .max_amount_per_request
+            .as_deref()
+            .unwrap_or(crate::MAX_PAYMENT_AMOUNT)
+            .parse()
+            .map_err(|_| Error::Config("Invalid max_amount_per_request".to_string().into()))
+    }
+
+    /// Converts a USD amount into units of `asset` on `network`.
+    async fn usd_to_units(&self, usd: f64, network: &str, asset: Option<&AssetInfo>) -> Result<u128> {
+        let asset = asset.ok_or_else(|| Error::Payment("Payment requirements list no asset".to_string().into()))?;
+
+        let price = self.price_oracle.price(network, &asset.address).await?;
+        let decimals = self.asset_decimals(network, asset).await?;
+
+        Ok((usd / price.usd * 10f64.powi(decimals as i32)).floor() as u128)
+    }
+
+    /// Returns the decimals of an asset, looking them up in the token registry if not advertised.
+    async fn asset_decimals(&self, network: &str, asset: &AssetInfo) -> Result<u8> {
+        if let Some(decimals) = asset.decimals {
+            return Ok(decimals);
+        }
+
+        Ok(self.chain_manager.resolve_token(network, &asset.address).await?.decimals)
+    }
+
+    /// Values an amount in USD using only cached prices and known decimals.
+    fn usd_value(&self, network: &str, asset: &AssetInfo, amount: &str) -> Option<f64> {
+        let price = self.price_oracle.cached_price(network, &asset.address)?;
+        let decimals = asset
+            .decimals
+            .or_else(|| self.chain_manager.tokens().get(network, &asset.address).map(|t| t.decimals))?;
+
+        let amount: f64 = amount.parse().ok()?;
+        Some(amount / 10f64.powi(decimals as i32) * price.usd)
+    }
+
+    /// Releases resources held by the payment manager.
+    ///
+    /// The pending payment monitor is stopped by the client's task manager.
+    pub async fn close(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Formats an amount of the requirements' asset for display, e.g. `1.5 USDC`.
+    ///
+    /// The token registry takes precedence over the symbol and decimals
+    /// advertised by the server.
+    pub fn format_amount(&self, requirements: &PaymentRequirements, amount: &str) -> String {
+        self.format_asset_amount(requirements.network.as_str(), requirements.primary_asset(), amount)
+    }
+
+    /// Formats an amount of `asset` on `network` for display, see [`format_amount`](Self::format_amount).
+    fn format_asset_amount(&self, network: &str, asset: Option<&AssetInfo>, amount: &str) -> String {
+        let Some(asset) = asset else {
+            return format!("{} units", amount);
+        };
+
+        let tokens = self.chain_manager.tokens();
+        match (&asset.symbol, asset.decimals) {
+            (Some(symbol), Some(decimals)) if tokens.get(network, &asset.address).is_none() => {
+                TokenInfo::new(&asset.address, symbol, decimals).format_amount(amount)
+            }
+            _ => tokens.format_amount(network, &asset.address, amount),
+        }
+    }
+
+    /// Rejects requirements exceeding the configured per-request maximum or
+    /// the limit of the paying wallet.
+    async fn check_amount(&self, requirements: &PaymentRequirements, wallet: &str) -> Result<()> {
+        self.check_asset_amount(
+            requirements.network.as_str(),
+            requirements.primary_asset(),
+            &requirements.max_amount_required,
+            wallet,
+        )
+        .await
+    }
+
+    /// Rejects a payment of `amount` of `asset` on `network` from `wallet`
+    /// exceeding the configured per-request maximum or the wallet's limit.
+    pub(crate) async fn check_asset_amount(
+        &self,
+        network: &str,
+        asset: Option<&AssetInfo>,
+        amount: &str,
+        wallet: &str,
+    ) -> Result<()> {
+        let amount: u128 = amount
+            .parse()
+            .map_err(|_| Error::Payment(format!("Invalid payment amount: {}", amount).into()))?;
+        let format = |amount: u128| self.format_asset_amount(network, asset, &amount.to_string());
+
+        let max = self.max_asset_amount(network, asset).await?;
+
+        if amount > max {
+            return Err(Error::Payment(format!(
+                "Payment of {} exceeds maximum of {} per request",
+                format(amount),
+                format(max)
+            ).into()));
+        }
+
+        let wallet_max = self
+            .config
+            .wallet(wallet)
+            .and_then(|config| config.max_amount_per_request.as_deref());
+        if let Some(wallet_max) = wallet_max {
+            let wallet_max: u128 = wallet_max
+                .parse()
+                .map_err(|_| Error::Config(format!("Invalid max_amount_per_request of wallet {}", wallet).into()))?;
+            if amount > wallet_max {
+                return Err(Error::Payment(format!(
+                    "Payment of {} exceeds wallet {}'s maximum of {} per request",
+                    format(amount),
+                    wallet,
+                    format(wallet_max)
+                ).into()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Key of an approval in [`PaymentManager::approvals`], with addresses lowercased.
+fn approval_key(chain: &str, token: &str, spender: &str) -> (NetworkId, String, String) {
+    (NetworkId::from(chain), token.to_ascii_lowercase(), spender.to_ascii_lowercase())
+}
+
+/// Decodes the authorization from a signed `X-PAYMENT` header.
+pub(crate) fn decode_authorization(header: &str) -> Option<Authorization> {
+    decode_signed_authorization(header).map(|(authorization, _)| authorization)
+}
+
+/// Decodes the authorization and its signature from a signed `X-PAYMENT` header.
+fn decode_signed_authorization(header: &str) -> Option<(Authorization, String)> {
+    #[derive(Deserialize)]
+    struct SignedPayload {
+        payload: SignedAuthorization,
+    }
+
+    #[derive(Deserialize)]
+    struct SignedAuthorization {
+        signature: String,
+        authorization: Authorization,
+    }
+
+    let decoded = BASE64.decode(header.trim()).ok()?;
+    let signed: SignedPayload = serde_json::from_slice(&decoded).ok()?;
+    Some((signed.payload.authorization, signed.payload.signature))
+}
+
+/// Runs one phase of a payment inside `span`, adding its duration to `elapsed`.
+///
+/// Emits a `debug` event with the phase duration, so the breakdown is
+/// available from plain `tracing` subscribers without an OpenTelemetry exporter.
+pub(crate) async fn time_phase<F: Future>(span: Span, elapsed: &mut Duration, phase: F) -> F::Output {
+    let started = Instant::now();
+    let output = phase.instrument(span.clone()).await;
+    let duration = started.elapsed();
+    *elapsed += duration;
+    span.in_scope(|| debug!(duration_ms = duration.as_secs_f64() * 1000.0, "Payment phase completed"));
+    output
+}
+
+/// Returns up to the first [`BODY_PREVIEW_BYTES`] of `body`, lossily decoded
+/// as UTF-8 without splitting a character at the cut.
+fn body_preview(body: &[u8]) -> String {
+    let mut end = body.len().min(BODY_PREVIEW_BYTES);
+    // Back off continuation bytes so the last character is not cut in half
+    while end < body.len() && end > 0 && body[end] & 0xC0 == 0x80 {
+        end -= 1;
+    }
+    String::from_utf8_lossy(&body[..end]).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::PeriodType;
+    use serde_json::{json, Value};
+    use wiremock::{
+        matchers::{body_partial_json, method, path, query_param},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    const KEY: &str = "0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+
+    /// Returns a pending payment record with ID `payment_id`.
+    pub(crate) fn record(payment_id: &str) -> PaymentHistory {
+        serde_json::from_value(json!({
+            "payment_id": payment_id,
+            "url": "https://api.example.com/data",
+            "amount": "1000",
+            "asset": "0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913",
+            "asset_decimals": 6,
+            "usd_value": null,
+            "transaction_hash": null,
+            "network": "base",
+            "payer": null,
+            "payee": "0x1111111111111111111111111111111111111111",
+            "timestamp": Utc::now(),
+            "status": "pending",
+            "description": "",
+        }))
+        .unwrap()
+    }
+
+    pub(crate) async fn payment_manager(config: Config) -> PaymentManager {
+        let config = Arc::new(config);
+        let chain_manager = Arc::new(ChainManager::new(&config).await.unwrap());
+        PaymentManager::new(&config, &chain_manager).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn approvals_on_dry_run_chains_are_never_broadcast() {
+        const TOKEN: &str = "0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913";
+        const SPENDER: &str = "0x2222222222222222222222222222222222222222";
+
+        // Every eth_call succeeds: no allowance yet, and the approval simulates fine
+        let rpc = MockServer::start().await;
+        Mock::given(body_partial_json(json!({ "method": "eth_call" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "result": format!("0x{}", "00".repeat(32)),
+            })))
+            .mount(&rpc)
+            .await;
+
+        let manager = payment_manager(Config {
+            chains: vec![ChainConfig::base_mainnet().with_rpc_url(rpc.uri()).with_dry_run(true)],
+            private_key: Some("0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318".to_string().into()),
+            preapprove_tokens: vec![PreapproveConfig {
+                chain: "base".to_string(),
+                token: TOKEN.to_string(),
+                spender: SPENDER.to_string(),
+                amount: 1_000_000,
+            }],
+            ..Config::default()
+        })
+        .await;
+
+        assert_eq!(manager.preapprove("base", TOKEN, SPENDER, 1_000_000).await.unwrap(), None);
+        assert!(manager.preapprove_configured().await.is_empty());
+        assert_eq!(manager.approval("base", TOKEN, SPENDER), None);
+
+        let calls: Vec<Value> = rpc
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .map(|request| serde_json::from_slice(&request.body).unwrap())
+            .collect();
+        let approve = format!("0x{}", hex::encode(crypto::function_selector(APPROVE_SIGNATURE)));
+        let simulated = calls
+            .iter()
+            .filter(|call| call["params"][0]["data"].as_str().is_some_and(|data| data.starts_with(&approve)))
+            .count();
+        // Once by preapprove, once more by preapprove_configured after reading the allowance
+        assert_eq!(simulated, 2);
+        assert!(calls.iter().all(|call| call["method"] == "eth_call"), "{calls:?}");
+    }
+
+    fn requirements() -> PaymentRequirements {
+        serde_json::from_value(json!({
+            "scheme": "exact",
+            "network": "base",
+            "maxAmountRequired": "1000",
+            "resource": "https://api.example.com/data",
+            "payTo": "0x1111111111111111111111111111111111111111",
+            "asset": "0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913",
+            "maxTimeoutSeconds": 60
+        }))
+        .unwrap()
+    }
+
+    /// Starts an RPC endpoint whose payment contract reports `nonce` for every payer.
+    async fn nonce_rpc(nonce: u128) -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(body_partial_json(json!({ "method": "eth_call" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "result": format!("0x{}", hex::encode(crypto::uint_word(nonce))),
+            })))
+            .mount(&server)
+            .await;
+        server
+    }
+
+    fn on_chain_nonce_config(rpc: &MockServer) -> Config {
+        Config {
+            chains: vec![ChainConfig::base_mainnet()
+                .with_rpc_url(rpc.uri())
+                .with_payment_contract("0x2222222222222222222222222222222222222222")],
+            private_key: Some(KEY.to_string().into()),
+            on_chain_nonce: true,
+            ..Config::default()
+        }
+    }
+
+    /// Reserves the next nonce of the default wallet, returning it and a header carrying it.
+    async fn reserve(manager: &PaymentManager) -> (u128, String) {
+        let payer = manager.chain_manager.wallet_address(DEFAULT_WALLET, "base").unwrap();
+        let nonce = manager.replay_protection(&requirements(), &payer).await.unwrap();
+        let authorization = Authorization {
+            from: payer,
+            to: "0x1111111111111111111111111111111111111111".to_string(),
+            value: "1000".to_string(),
+            valid_after: "0".to_string(),
+            valid_before: "0".to_string(),
+            nonce: nonce.clone(),
+        };
+        let header = BASE64.encode(
+            serde_json::to_vec(&json!({ "payload": { "signature": "0x", "authorization": authorization } })).unwrap(),
+        );
+        (crypto::word_to_uint(&crypto::parse_bytes32(&nonce).unwrap()).unwrap(), header)
+    }
+
+    #[tokio::test]
+    async fn on_chain_nonce_advances_only_for_sent_payments() {
+        let rpc = nonce_rpc(5).await;
+        let manager = payment_manager(on_chain_nonce_config(&rpc)).await;
+
+        // A payment that was never sent gives its nonce back
+        let (nonce, header) = reserve(&manager).await;
+        assert_eq!(nonce, 5);
+        manager.release_nonce("base", &header).await;
+
+        // Concurrent payments get distinct nonces
+        let (first, first_header) = reserve(&manager).await;
+        let (second, second_header) = reserve(&manager).await;
+        assert_eq!((first, second), (5, 6));
+        manager.commit_nonce("base", &first_header).await;
+        manager.release_nonce("base", &second_header).await;
+        assert_eq!(reserve(&manager).await.0, 6);
+    }
+
+    #[tokio::test]
+    async fn on_chain_nonce_survives_restarts() {
+        let rpc = nonce_rpc(5).await;
+        let manager = payment_manager(on_chain_nonce_config(&rpc)).await;
+        let (nonce, header) = reserve(&manager).await;
+        manager.commit_nonce("base", &header).await;
+        assert_eq!(nonce, 5);
+        drop(manager);
+
+        // The restarted client recovers the nonce the contract has moved on to
+        let rpc = nonce_rpc(6).await;
+        let manager = payment_manager(on_chain_nonce_config(&rpc)).await;
+        assert_eq!(reserve(&manager).await.0, 6);
+    }
+
+    #[tokio::test]
+    async fn history_pages_neither_skip_nor_repeat_payments() {
+        let manager = payment_manager(Config { chains: Vec::new(), ..Config::default() }).await;
+        for index in 0..MAX_HISTORY_ENTRIES {
+            manager.push_history(record(&index.to_string()));
+        }
+        let ids = |page: Vec<PaymentHistory>| page.into_iter().map(|record| record.payment_id).collect::<Vec<_>>();
+
+        assert_eq!(ids(manager.history_page(None, 2)), ["0", "1"]);
+
+        // Dropping the oldest payment does not shift the next page
+        manager.push_history(record("new"));
+        assert_eq!(ids(manager.history_page(Some("1"), 2)), ["2", "3"]);
+
+        // Once the cursor itself is dropped, the page starts at the oldest payment held
+        manager.push_history(record("newer"));
+        manager.push_history(record("newest"));
+        assert_eq!(ids(manager.history_page(Some("1"), 2)), ["3", "4"]);
+        assert_eq!(ids(manager.history_page(Some("newer"), 2)), ["newest"]);
+    }
+
+    #[tokio::test]
+    async fn pending_payments_are_capped() {
+        let manager = payment_manager(Config { chains: Vec::new(), ..Config::default() }).await;
+
+        manager.track_pending("0xoldest", "base", "1000");
+        std::thread::sleep(Duration::from_millis(2));
+        for index in 0..MAX_PENDING_PAYMENTS {
+            manager.track_pending(&format!("0x{index:x}"), "base", "1000");
+        }
+
+        assert_eq!(manager.pending_count(), MAX_PENDING_PAYMENTS);
+        assert!(manager.get_pending_payments().iter().all(|payment| payment.tx_hash != "0xoldest"));
+    }
+
+    /// Returns a confirmed payment of `amount` to `payee` made at `timestamp`.
+    fn confirmed(payment_id: &str, timestamp: &str, amount: &str, payee: &str) -> PaymentHistory {
+        PaymentHistory {
+            timestamp: timestamp.parse().unwrap(),
+            amount: amount.to_string(),
+            payee: payee.to_string(),
+            status: PaymentStatus::Confirmed,
+            ..record(payment_id)
+        }
+    }
+
+    #[tokio::test]
+    async fn time_series_buckets_payments_by_day() {
+        let manager = payment_manager(Config { chains: Vec::new(), ..Config::default() }).await;
+        manager.history.write().extend([
+            confirmed("a", "2024-03-14T10:00:00Z", "1000", "0xaaa"),
+            confirmed("b", "2024-03-14T23:59:59Z", "3000", "0xbbb"),
+            record("pending"),
+            confirmed("c", "2024-03-16T00:00:00Z", "500", "0xaaa"),
+            confirmed("invalid", "2024-03-16T12:00:00Z", "lots", "0xccc"),
+        ]);
+
+        let series = manager.get_statistics().await.unwrap().time_series(PeriodType::Day);
+        let days: Vec<_> = series
+            .iter()
+            .map(|day| (day.period_start.to_rfc3339(), day.payment_count, day.total_amount_wei, day.unique_payees, day.average_amount_wei))
+            .collect();
+        assert_eq!(
+            days,
+            [
+                ("2024-03-14T00:00:00+00:00".to_string(), 2, 4000, 2, 2000),
+                ("2024-03-15T00:00:00+00:00".to_string(), 0, 0, 0, 0),
+                ("2024-03-16T00:00:00+00:00".to_string(), 1, 500, 1, 500),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn time_series_totals_saturate() {
+        let manager = payment_manager(Config { chains: Vec::new(), ..Config::default() }).await;
+        let max = u128::MAX.to_string();
+        manager.history.write().extend([
+            confirmed("a", "2024-03-14T10:00:00Z", &max, "0xaaa"),
+            confirmed("b", "2024-03-14T11:00:00Z", &max, "0xaaa"),
+        ]);
+
+        let series = manager.get_statistics().await.unwrap().time_series(PeriodType::Day);
+        assert_eq!(series[0].total_amount_wei, u128::MAX);
+    }
+
+    #[tokio::test]
+    async fn poll_settlement_raises_zero_intervals() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/settlement/0x01"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "status": "pending" })))
+            .mount(&server)
+            .await;
+
+        let manager = payment_manager(Config {
+            chains: Vec::new(),
+            facilitator_url: Some(server.uri()),
+            allow_insecure_facilitator: true,
+            ..Config::default()
+        })
+        .await;
+        let record = PaymentHistory {
+            authorization_nonce: Some("0x01".to_string()),
+            ..record("pending")
+        };
+        manager.history.write().push_back(record);
+
+        let options = PollOptions {
+            timeout: Duration::from_millis(350),
+            initial_interval: Duration::ZERO,
+            max_interval: Duration::ZERO,
+        };
+        let error = manager.poll_settlement("pending", &options).await.unwrap_err();
+        assert!(matches!(error, Error::Timeout(..)), "{error}");
+        // Queries at 0, 100, 200 and 300 ms, then one when the time is up
+        let queries = server.received_requests().await.unwrap().len();
+        assert!(queries <= 5, "{queries} queries");
+    }
+
+    #[tokio::test]
+    async fn poll_settlement_falls_back_to_configured_facilitator() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/settlement/0x01"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "status": "settled",
+                "transaction": "0xabc",
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let manager = payment_manager(Config {
+            chains: Vec::new(),
+            facilitator_url: Some(server.uri()),
+            allow_insecure_facilitator: true,
+            ..Config::default()
+        })
+        .await;
+
+        // Imported records may not name the facilitator that handled them
+        let record = PaymentHistory {
+            authorization_nonce: Some("0x01".to_string()),
+            ..record("imported")
+        };
+        manager.history.write().push_back(record);
+
+        let settled = manager.poll_settlement("imported", &PollOptions::default()).await.unwrap();
+        assert_eq!(settled.status, PaymentStatus::Confirmed);
+        assert_eq!(settled.transaction_hash.as_deref(), Some("0xabc"));
+    }
+
+    #[tokio::test]
+    async fn payment_metadata_round_trips() {
+        let manager = payment_manager(Config { chains: Vec::new(), ..Config::default() }).await;
+        let metadata = HashMap::from([
+            ("order_id".to_string(), "ord_1234".to_string()),
+            ("coupon".to_string(), "SPRING=10%".to_string()),
+        ]);
+
+        let header = manager.encode_metadata(&metadata).unwrap();
+        assert_eq!(PaymentManager::decode_metadata(&header).unwrap(), metadata);
+        // Keys are sorted, so the same metadata always gives the same header
+        assert_eq!(manager.encode_metadata(&metadata).unwrap(), header);
+
+        let history = PaymentHistory { metadata: metadata.clone(), ..record("with-metadata") };
+        let restored: PaymentHistory = serde_json::from_str(&serde_json::to_string(&history).unwrap()).unwrap();
+        assert_eq!(restored.metadata, metadata);
+    }
+
+    #[tokio::test]
+    async fn payment_metadata_over_the_limit_is_rejected() {
+        let manager = payment_manager(Config { chains: Vec::new(), ..Config::default() }).await;
+        let metadata = HashMap::from([("note".to_string(), "x".repeat(MAX_METADATA_BYTES))]);
+        assert!(matches!(manager.encode_metadata(&metadata), Err(Error::Payment(_))));
+        assert!(PaymentManager::decode_metadata("not base64!").is_err());
+    }
+
+    /// Returns requirements for `base` USDC with its decimals advertised.
+    fn usdc_requirements() -> PaymentRequirements {
+        let mut requirements = requirements();
+        requirements.supported_assets[0].decimals = Some(6);
+        requirements
+    }
+
+    /// Starts a price API quoting base USDC at `usd`, expecting `calls` requests.
+    async fn price_oracle(usd: f64, calls: u64) -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/base"))
+            .and(query_param("contract_addresses", "0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913"))
+            .and(query_param("vs_currencies", "usd"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "0x833589fcd6edb6e08f4c7c32d4f71b54bda02913": { "usd": usd },
+            })))
+            .expect(calls)
+            .mount(&server)
+            .await;
+        server
+    }
+
+    #[tokio::test]
+    async fn usd_cap_is_converted_with_the_cached_price() {
+        let oracle = price_oracle(0.5, 1).await;
+        let manager = payment_manager(Config {
+            chains: Vec::new(),
+            max_amount_usd: Some(2.5),
+            max_amount_per_request: Some("1".to_string()),
+            price_oracle_url: Some(oracle.uri()),
+            ..Config::default()
+        })
+        .await;
+
+        // 2.5 USD at 0.5 USD per token is 5 tokens of 6 decimals
+        let requirements = usdc_requirements();
+        let max = || manager.max_asset_amount(requirements.network.as_str(), requirements.primary_asset());
+        assert_eq!(max().await.unwrap(), 5_000_000);
+        assert_eq!(max().await.unwrap(), 5_000_000);
+    }
+
+    #[tokio::test]
+    async fn usd_cap_falls_back_to_the_unit_cap_without_a_price() {
+        let oracle = MockServer::start().await;
+        Mock::given(method("GET")).respond_with(ResponseTemplate::new(503)).mount(&oracle).await;
+        let config = Config {
+            chains: Vec::new(),
+            max_amount_usd: Some(2.5),
+            price_oracle_url: Some(oracle.uri()),
+            ..Config::default()
+        };
+        let requirements = usdc_requirements();
+        let (network, asset) = (requirements.network.as_str(), requirements.primary_asset());
+
+        let manager = payment_manager(Config {
+            max_amount_per_request: Some("1000".to_string()),
+            ..config.clone()
+        })
+        .await;
+        assert_eq!(manager.max_asset_amount(network, asset).await.unwrap(), 1000);
+
+        let manager = payment_manager(config).await;
+        let error = manager.max_asset_amount(network, asset).await.unwrap_err();
+        assert!(matches!(error, Error::PriceOracleUnavailable(_)), "{error}");
+    }
+
+    #[tokio::test]
+    async fn derived_nonces_can_be_reconstructed_after_a_restart() {
+        let path = std::env::temp_dir().join(format!("v402-nonce-counters-{}.json", Uuid::new_v4()));
+        let config = || Config {
+            private_key: Some(KEY.to_string().into()),
+            nonce_counter_path: Some(path.clone()),
+            ..Config::default()
+        };
+
+        let manager = payment_manager(config()).await;
+        let payer = manager.chain_manager.wallet_address(DEFAULT_WALLET, "base").unwrap();
+        let first = manager.replay_protection(&requirements(), &payer).await.unwrap();
+        let second = manager.replay_protection(&requirements(), &payer).await.unwrap();
+        assert_ne!(first, second);
+        assert_eq!(first, nonce::derive_nonce(KEY, &requirements(), 0).unwrap());
+
+        // After a crash the counter file tells a recovery tool which nonces were signed
+        let manager = payment_manager(config()).await;
+        assert_eq!(manager.nonce_counter(&payer).await.unwrap(), Some(2));
+        assert_eq!(manager.reconstruct_nonce(&requirements(), &payer, 0).unwrap(), first);
+        assert_eq!(manager.reconstruct_nonce(&requirements(), &payer, 1).unwrap(), second);
+        assert!(manager
+            .reconstruct_nonce(&requirements(), "0x1111111111111111111111111111111111111111", 0)
+            .is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn nonces_are_random_without_a_counter_file() {
+        let manager = payment_manager(Config {
+            private_key: Some(KEY.to_string().into()),
+            ..Config::default()
+        })
+        .await;
+        let payer = manager.chain_manager.wallet_address(DEFAULT_WALLET, "base").unwrap();
+
+        assert_eq!(manager.nonce_counter(&payer).await.unwrap(), None);
+        let nonce = manager.replay_protection(&requirements(), &payer).await.unwrap();
+        assert_ne!(nonce, manager.replay_protection(&requirements(), &payer).await.unwrap());
+        assert_ne!(nonce, nonce::derive_nonce(KEY, &requirements(), 0).unwrap());
+    }
+
+    /// Outcome expected from parsing a 402 fixture.
+    enum Parsed {
+        /// Requirements for this amount and recipient
+        Requirements { amount: &'static str, pay_to: &'static str },
+        /// An [`Error::InvalidPaymentRequirements`] with a body preview of this many bytes
+        Invalid { preview_bytes: usize },
+    }
+
+    #[tokio::test]
+    async fn malformed_402_bodies_are_reported_with_a_preview() {
+        const PAY_TO: &str = "0x1111111111111111111111111111111111111111";
+        let cases: [(&str, &[u8], Option<&str>, Parsed); 6] = [
+            (
+                "html_error_page",
+                include_bytes!("../../tests/fixtures/402/html_error_page.html"),
+                Some("text/html; charset=utf-8"),
+                Parsed::Invalid { preview_bytes: 169 },
+            ),
+            (
+                "truncated",
+                include_bytes!("../../tests/fixtures/402/truncated.json"),
+                Some("application/json"),
+                Parsed::Invalid { preview_bytes: 128 },
+            ),
+            (
+                "empty",
+                include_bytes!("../../tests/fixtures/402/empty.txt"),
+                None,
+                Parsed::Invalid { preview_bytes: 0 },
+            ),
+            // The two-byte character straddling the limit is left out whole
+            (
+                "oversized",
+                include_bytes!("../../tests/fixtures/402/oversized.html"),
+                Some("text/html"),
+                Parsed::Invalid { preview_bytes: BODY_PREVIEW_BYTES - 1 },
+            ),
+            (
+                "snake_case",
+                include_bytes!("../../tests/fixtures/402/snake_case.json"),
+                Some("application/json"),
+                Parsed::Requirements { amount: "2500", pay_to: PAY_TO },
+            ),
+            // A JSON content type is not required when the body is JSON
+            (
+                "numeric_amount",
+                include_bytes!("../../tests/fixtures/402/numeric_amount.json"),
+                Some("text/plain"),
+                Parsed::Requirements { amount: "1000000", pay_to: PAY_TO },
+            ),
+        ];
+        let manager = payment_manager(Config { chains: Vec::new(), ..Config::default() }).await;
+
+        for (fixture, body, content_type, expected) in cases {
+            let result = manager.parse_requirements_body(body, content_type, None).await;
+            match expected {
+                Parsed::Requirements { amount, pay_to } => {
+                    let requirements = result.unwrap_or_else(|e| panic!("{fixture}: {e}"));
+                    assert_eq!(requirements.max_amount_required, amount, "{fixture}");
+                    assert_eq!(requirements.primary_pay_to().unwrap().address, pay_to, "{fixture}");
+                }
+                Parsed::Invalid { preview_bytes } => match result {
+                    Err(Error::InvalidPaymentRequirements {
+                        content_type: reported,
+                        body_preview,
+                        parse_error,
+                        ..
+                    }) => {
+                        assert_eq!(reported.as_deref(), content_type, "{fixture}");
+                        assert_eq!(body_preview.len(), preview_bytes, "{fixture}");
+                        assert!(body.starts_with(body_preview.as_bytes()), "{fixture}");
+                        if content_type.is_some_and(|ct| ct.starts_with("text/html")) {
+                            assert!(parse_error.starts_with("expected JSON"), "{fixture}: {parse_error}");
+                        }
+                    }
+                    other => panic!("{fixture}: expected invalid requirements, got {other:?}"),
+                },
+            }
+        }
+    }
+
+    #[test]
+    fn snake_case_requirements_keep_every_field() {
+        let body: PaymentRequiredBody =
+            serde_json::from_slice(include_bytes!("../../tests/fixtures/402/snake_case.json")).unwrap();
+        let requirements = &body.accepts[0];
+        assert_eq!(requirements.mime_type.as_deref(), Some("application/json"));
+        assert_eq!(requirements.max_timeout_seconds, 120);
+    }
+}
@@ -0,0 +1,208 @@
+//! Deterministic payment nonces.
+//!
+//! Every EIP-3009 authorization carries a 32-byte nonce that the token
+//! contract marks as used on settlement. Random nonces leave a client that
+//! crashed mid-payment unable to tell which authorizations it signed. With
+//! [`Config::nonce_counter_path`](crate::Config::nonce_counter_path) set,
+//! nonces are derived instead, so a recovery tool can recompute them with
+//! [`PaymentManager::reconstruct_nonce`](super::PaymentManager::reconstruct_nonce)
+//! and check [`is_nonce_used`](super::PaymentManager::is_nonce_used) before
+//! paying again.
+//!
+//! # Derivation
+//!
+//! ```text
+//! resource_hash     = keccak256(requirements.resource as UTF-8)
+//! requirements_hash = keccak256(canonical JSON of the requirements)
+//! nonce             = HMAC-SHA256(
+//!                         key: wallet private key (32 bytes),
+//!                         msg: "v402-payment-nonce-v1" || resource_hash || requirements_hash || counter,
+//!                     )
+//! ```
+//!
+//! The canonical JSON is the requirements as serialized by this crate with
+//! object keys sorted and no whitespace. The counter is kept per paying
+//! address as 8 big-endian bytes; it starts at 0 and its increment is
+//! written to the counter file before the authorization is signed, so no
+//! counter value is ever used twice.
+//!
+//! # Test vectors
+//!
+//! With the key `0x01` repeated 32 times:
+//!
+//! | `resource_hash` | `requirements_hash` | counter | nonce |
+//! |-----------------|---------------------|---------|-------|
+//! | `0x00` × 32 | `0x00` × 32 | 0 | `0x9abcad59ea1faedf0ad453f462422c19b91405044ccd0a85f00b4eeee4ff9437` |
+//! | `0xaa` × 32 | `0xbb` × 32 | 7 | `0xe5bb1b9e9553b60613351337bc6b494358387ee3d41fa9752dd17dac3688a9ab` |
+//!
+//! Full derivation from requirements:
+//!
+//! ```rust
+//! use v402_client::payment::{nonce::derive_nonce, PaymentRequirements};
+//!
+//! let requirements: PaymentRequirements = serde_json::from_value(serde_json::json!({
+//!     "scheme": "exact",
+//!     "network": "base-sepolia",
+//!     "maxAmountRequired": "1000",
+//!     "resource": "https://example.com/article",
+//!     "description": "Article",
+//!     "payTo": "0x2222222222222222222222222222222222222222",
+//!     "maxTimeoutSeconds": 60,
+//!     "asset": "0x3333333333333333333333333333333333333333"
+//! }))?;
+//! let key = format!("0x{}", "01".repeat(32));
+//!
+//! assert_eq!(
+//!     derive_nonce(&key, &requirements, 0)?,
+//!     "0x7d0b47a519b72ebee8385a8ea5d1a19c0ae0d5a22838234d39d917508d76ac9e",
+//! );
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use super::PaymentRequirements;
+use crate::{crypto, error::Result};
+use std::{collections::BTreeMap, io::ErrorKind, path::PathBuf};
+use tokio::{io::AsyncWriteExt, sync::Mutex};
+
+/// Derives the nonce of the payment with `counter` signed by `private_key`
+/// for `requirements`.
+pub fn derive_nonce(private_key: &str, requirements: &PaymentRequirements, counter: u64) -> Result<String> {
+    let key = secrecy::zeroize::Zeroizing::new(crypto::parse_bytes32(private_key)?);
+    let (resource_hash, requirements_hash) = nonce_inputs(requirements)?;
+    let nonce = crypto::derive_payment_nonce(&*key, &resource_hash, &requirements_hash, counter);
+    Ok(format!("0x{}", hex::encode(nonce)))
+}
+
+/// Returns the resource and requirements hashes a nonce is derived from.
+pub(crate) fn nonce_inputs(requirements: &PaymentRequirements) -> Result<([u8; 32], [u8; 32])> {
+    // Round-tripping through a `Value` sorts the keys of `extra`
+    let canonical = serde_json::to_vec(&serde_json::to_value(requirements)?)?;
+    Ok((
+        crypto::keccak256(requirements.resource.as_str().as_bytes()),
+        crypto::keccak256(&canonical),
+    ))
+}
+
+/// Per-payer nonce counters persisted to a JSON file.
+#[derive(Debug)]
+pub(crate) struct NonceCounters {
+    path: PathBuf,
+    /// Next counter by lowercase payer address, loaded on first use
+    counters: Mutex<Option<BTreeMap<String, u64>>>,
+}
+
+impl NonceCounters {
+    /// Creates counters persisted to `path`, which need not exist yet.
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            counters: Mutex::new(None),
+        }
+    }
+
+    /// Reserves the next counter of `payer`, persisting the increment before
+    /// returning it.
+    pub(crate) async fn reserve(&self, payer: &str) -> Result<u64> {
+        let mut guard = self.counters.lock().await;
+        let counters = match guard.as_mut() {
+            Some(counters) => counters,
+            None => guard.insert(self.load().await?),
+        };
+
+        let payer = payer.to_ascii_lowercase();
+        let counter = counters.get(&payer).copied().unwrap_or(0);
+        let mut updated = counters.clone();
+        updated.insert(payer, counter + 1);
+        self.save(&updated).await?;
+        *counters = updated;
+        Ok(counter)
+    }
+
+    /// Returns the counter the next payment from `payer` will use.
+    pub(crate) async fn next(&self, payer: &str) -> Result<u64> {
+        let mut guard = self.counters.lock().await;
+        let counters = match guard.as_mut() {
+            Some(counters) => counters,
+            None => guard.insert(self.load().await?),
+        };
+        Ok(counters.get(&payer.to_ascii_lowercase()).copied().unwrap_or(0))
+    }
+
+    /// Reads the counter file, treating a missing file as no counters.
+    async fn load(&self) -> Result<BTreeMap<String, u64>> {
+        match tokio::fs::read(&self.path).await {
+            Ok(contents) => Ok(serde_json::from_slice(&contents)?),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Replaces the counter file, writing a temporary file first so a crash
+    /// never leaves it truncated.
+    async fn save(&self, counters: &BTreeMap<String, u64>) -> Result<()> {
+        let temporary = self.path.with_extension("tmp");
+        let mut file = tokio::fs::File::create(&temporary).await?;
+        file.write_all(&serde_json::to_vec_pretty(counters)?).await?;
+        file.sync_all().await?;
+        tokio::fs::rename(&temporary, &self.path).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Returns a path in the temporary directory no other test uses.
+    fn counter_path() -> PathBuf {
+        std::env::temp_dir().join(format!("v402-nonce-counters-{}.json", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn nonces_match_the_documented_vectors() {
+        let key = [0x01; 32];
+        assert_eq!(
+            hex::encode(crypto::derive_payment_nonce(&key, &[0x00; 32], &[0x00; 32], 0)),
+            "9abcad59ea1faedf0ad453f462422c19b91405044ccd0a85f00b4eeee4ff9437"
+        );
+        assert_eq!(
+            hex::encode(crypto::derive_payment_nonce(&key, &[0xaa; 32], &[0xbb; 32], 7)),
+            "e5bb1b9e9553b60613351337bc6b494358387ee3d41fa9752dd17dac3688a9ab"
+        );
+
+        let requirements: PaymentRequirements = serde_json::from_value(json!({
+            "scheme": "exact",
+            "network": "base-sepolia",
+            "maxAmountRequired": "1000",
+            "resource": "https://example.com/article",
+            "description": "Article",
+            "payTo": "0x2222222222222222222222222222222222222222",
+            "maxTimeoutSeconds": 60,
+            "asset": "0x3333333333333333333333333333333333333333"
+        }))
+        .unwrap();
+        let key = format!("0x{}", "01".repeat(32));
+        assert_eq!(
+            derive_nonce(&key, &requirements, 0).unwrap(),
+            "0x7d0b47a519b72ebee8385a8ea5d1a19c0ae0d5a22838234d39d917508d76ac9e"
+        );
+        assert_ne!(derive_nonce(&key, &requirements, 1).unwrap(), derive_nonce(&key, &requirements, 0).unwrap());
+    }
+
+    #[tokio::test]
+    async fn counters_are_persisted_per_payer() {
+        let path = counter_path();
+        let counters = NonceCounters::new(path.clone());
+        assert_eq!(counters.reserve("0xAAAA").await.unwrap(), 0);
+        assert_eq!(counters.reserve("0xaaaa").await.unwrap(), 1);
+        assert_eq!(counters.reserve("0xbbbb").await.unwrap(), 0);
+
+        // A new process continues where the file left off
+        let counters = NonceCounters::new(path.clone());
+        assert_eq!(counters.next("0xAaAa").await.unwrap(), 2);
+        assert_eq!(counters.reserve("0xbbbb").await.unwrap(), 1);
+        assert!(!path.with_extension("tmp").exists());
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,154 @@
+//! Token prices in US dollars.
+//!
+//! Prices come from a CoinGecko-compatible `simple/token_price` API and are
+//! cached per token for [`Config::price_ttl_secs`]. They are used to convert
+//! [`Config::max_amount_usd`] into token units and to value recorded payments.
+
+use crate::{
+    config::{Config, DEFAULT_PRICE_ORACLE_URL},
+    error::{Error, Result},
+};
+use chrono::{DateTime, Utc};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use tracing::debug;
+
+/// USD price of a token.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenPrice {
+    /// Network the token lives on
+    pub network: String,
+
+    /// Token contract address
+    pub asset: String,
+
+    /// Price of one whole token in US dollars
+    pub usd: f64,
+
+    /// Time the price was fetched
+    pub fetched_at: DateTime<Utc>,
+}
+
+/// Cached client for the token price API.
+#[derive(Debug)]
+pub struct PriceOracle {
+    base_url: String,
+    ttl: Duration,
+    http: reqwest::Client,
+    /// Prices per `(network, asset)` with the time they were fetched
+    prices: RwLock<HashMap<(String, String), (TokenPrice, Instant)>>,
+}
+
+impl PriceOracle {
+    /// Creates an oracle from the client configuration.
+    pub fn new(config: &Config) -> Result<Self> {
+        let http = crate::http::with_dns(reqwest::Client::builder(), config)
+            .timeout(config.timeout)
+            .user_agent(crate::USER_AGENT)
+            .build()
+            .map_err(|e| Error::Network(format!("Failed to build HTTP client: {}", e).into()))?;
+
+        Ok(Self {
+            base_url: config
+                .price_oracle_url
+                .as_deref()
+                .unwrap_or(DEFAULT_PRICE_ORACLE_URL)
+                .trim_end_matches('/')
+                .to_string(),
+            ttl: Duration::from_secs(config.price_ttl_secs),
+            http,
+            prices: RwLock::new(HashMap::new()),
+        })
+    }
+
+    /// Returns the USD price of `asset` on `network`, fetching it if the cached price expired.
+    pub async fn price(&self, network: &str, asset: &str) -> Result<TokenPrice> {
+        if let Some(price) = self.cached_price(network, asset) {
+            return Ok(price);
+        }
+
+        let price = self.fetch(network, asset).await?;
+        debug!(network, asset, usd = price.usd, "Fetched token price");
+        self.prices.write().insert(
+            (network.to_string(), asset.to_ascii_lowercase()),
+            (price.clone(), Instant::now()),
+        );
+        Ok(price)
+    }
+
+    /// Returns the cached price of `asset` on `network` if it has not expired.
+    pub fn cached_price(&self, network: &str, asset: &str) -> Option<TokenPrice> {
+        let key = (network.to_string(), asset.to_ascii_lowercase());
+        self.prices
+            .read()
+            .get(&key)
+            .filter(|(_, fetched)| fetched.elapsed() < self.ttl)
+            .map(|(price, _)| price.clone())
+    }
+
+    /// Queries the price API.
+    async fn fetch(&self, network: &str, asset: &str) -> Result<TokenPrice> {
+        let platform = platform_id(network)
+            .ok_or_else(|| Error::PriceOracleUnavailable(format!("No price platform for network {}", network).into()))?;
+        let url = format!("{}/{}", self.base_url, platform);
+
+        let response = self
+            .http
+            .get(&url)
+            .query(&[("contract_addresses", asset), ("vs_currencies", "usd")])
+            .send()
+            .await
+            .map_err(|e| Error::PriceOracleUnavailable(format!("Request to {} failed: {}", url, e).into()))?;
+
+        if !response.status().is_success() {
+            return Err(Error::PriceOracleUnavailable(format!(
+                "{} returned {}",
+                url,
+                response.status()
+            ).into()));
+        }
+
+        let body: HashMap<String, Value> = response
+            .json()
+            .await
+            .map_err(|e| Error::PriceOracleUnavailable(format!("Invalid response from {}: {}", url, e).into()))?;
+
+        // Contract addresses are returned lowercased
+        let usd = body
+            .iter()
+            .find(|(address, _)| address.eq_ignore_ascii_case(asset))
+            .and_then(|(_, prices)| prices.get("usd"))
+            .and_then(Value::as_f64)
+            .filter(|usd| usd.is_finite() && *usd > 0.0)
+            .ok_or_else(|| Error::PriceOracleUnavailable(format!("No USD price for {} on {}", asset, network).into()))?;
+
+        Ok(TokenPrice {
+            network: network.to_string(),
+            asset: asset.to_string(),
+            usd,
+            fetched_at: Utc::now(),
+        })
+    }
+}
+
+/// Maps a network name to the price API's platform identifier.
+///
+/// Testnets have no market price and map to `None`.
+fn platform_id(network: &str) -> Option<&'static str> {
+    match network {
+        "ethereum" => Some("ethereum"),
+        "base" => Some("base"),
+        "polygon" => Some("polygon-pos"),
+        "arbitrum" => Some("arbitrum-one"),
+        "optimism" => Some("optimistic-ethereum"),
+        "avalanche" => Some("avalanche"),
+        "bsc" => Some("binance-smart-chain"),
+        "solana" => Some("solana"),
+        _ => None,
+    }
+}
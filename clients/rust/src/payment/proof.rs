@@ -0,0 +1,723 @@
+//! Merkle inclusion proofs of settled payments.
+//!
+//! A [`PaymentProof`] shows that a block includes the token transfer of a
+//! payment: it carries the nodes of the block's receipts trie on the path to
+//! the settlement transaction's receipt, along with the transfer log. Anyone
+//! holding the block hash can check it without access to the payer's keys.
+
+use super::PaymentManager;
+use crate::{
+    crypto::{self, rlp_bytes, rlp_list, rlp_uint},
+    error::{Error, Result},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Signature of the ERC-20 transfer event emitted when a payment settles.
+const TRANSFER_EVENT: &str = "Transfer(address,address,uint256)";
+
+/// Proof that a block includes the transfer made by a payment.
+///
+/// `merkle_proof` holds the RLP-encoded nodes of the block's receipts trie on
+/// the path to the settlement transaction's receipt, root first. A verifier
+/// checks that `receipts_root` is the one in the header of `block_hash`, as
+/// reported by a node they trust, then calls
+/// [`verify_inclusion`](Self::verify_inclusion) and
+/// [`verify_signature`](Self::verify_signature).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaymentProof {
+    /// Settlement transaction hash
+    pub tx_hash: String,
+
+    /// Hash of the block including the transaction
+    pub block_hash: String,
+
+    /// Number of the block including the transaction
+    pub block_number: u64,
+
+    /// Root of the block's receipts trie
+    pub receipts_root: String,
+
+    /// Position of the transaction in the block, the key of its receipt in the trie
+    pub transaction_index: u64,
+
+    /// Hex-encoded trie nodes from the root to the receipt
+    pub merkle_proof: Vec<String>,
+
+    /// Transfer log, ABI-encoded as `(address emitter, bytes32[] topics, bytes data)`
+    pub abi_encoded_log: String,
+
+    /// Address of the wallet that paid
+    pub payer: String,
+}
+
+impl PaymentProof {
+    /// Re-derives the sender of the transfer from the log and checks it is
+    /// the payer.
+    ///
+    /// Payments are transfers authorized by the payer's EIP-3009 signature,
+    /// so a transfer from the payer shows the payer signed it.
+    pub fn verify_signature(&self) -> bool {
+        let Some(log) = self.log() else { return false };
+        let transfer = crypto::keccak256(TRANSFER_EVENT.as_bytes());
+        match log.topics.as_slice() {
+            [event, from, ..] if *event == transfer => {
+                format!("0x{}", hex::encode(&from[12..])).eq_ignore_ascii_case(&self.payer)
+            }
+            _ => false,
+        }
+    }
+
+    /// Checks that the proof leads from `receipts_root` to a receipt
+    /// containing the transfer log.
+    pub fn verify_inclusion(&self) -> bool {
+        match (self.log(), self.proven_receipt()) {
+            (Some(log), Some(receipt)) => receipt_logs(&receipt).is_some_and(|logs| logs.contains(&log)),
+            _ => false,
+        }
+    }
+
+    /// Decodes the transfer log.
+    fn log(&self) -> Option<Log> {
+        Log::abi_decode(&crypto::decode_hex(&self.abi_encoded_log).ok()?)
+    }
+
+    /// Walks the proof from the root to the receipt of the transaction.
+    fn proven_receipt(&self) -> Option<Vec<u8>> {
+        let nodes = self
+            .merkle_proof
+            .iter()
+            .map(|node| crypto::decode_hex(node).ok())
+            .collect::<Option<Vec<_>>>()?;
+        let mut nodes = nodes.iter();
+        let key = nibbles(&rlp_uint(self.transaction_index));
+
+        let root = crypto::parse_bytes32(&self.receipts_root).ok()?;
+        let mut node = hashed_node(nodes.next()?, &root)?;
+        let mut depth = 0;
+        loop {
+            let Rlp::List(items) = node else { return None };
+            let child = match items.as_slice() {
+                [children @ .., value] if children.len() == 16 => {
+                    if depth == key.len() {
+                        return value.bytes().map(<[u8]>::to_vec);
+                    }
+                    depth += 1;
+                    &children[key[depth - 1] as usize]
+                }
+                [path, value] => {
+                    let (path, leaf) = decode_hex_prefix(path.bytes()?)?;
+                    if !key[depth..].starts_with(&path) {
+                        return None;
+                    }
+                    depth += path.len();
+                    if leaf {
+                        return (depth == key.len()).then(|| value.bytes().map(<[u8]>::to_vec)).flatten();
+                    }
+                    value
+                }
+                _ => return None,
+            };
+            node = match child {
+                Rlp::Bytes(hash) if hash.len() == 32 => hashed_node(nodes.next()?, hash)?,
+                Rlp::List(_) => child.clone(),
+                Rlp::Bytes(_) => return None,
+            };
+        }
+    }
+}
+
+impl PaymentManager {
+    /// Builds a [`PaymentProof`] for the settlement transaction `tx_hash` on
+    /// the EVM network `chain`.
+    ///
+    /// Fetches the transaction's receipt and the receipts of its block,
+    /// rebuilds the block's receipts trie and checks it against the block
+    /// header before extracting the proof. The proven log is the transfer
+    /// sent by the payer recorded for the transaction, or by one of the
+    /// client's wallets if the payment is not in the history.
+    pub async fn get_receipt_proof(&self, tx_hash: &str, chain: &str) -> Result<PaymentProof> {
+        let rpc = move |method: &'static str, params: Value| self.chain_manager.evm_rpc(chain, method, params);
+
+        let receipt = rpc("eth_getTransactionReceipt", json!([tx_hash])).await?;
+        if receipt.is_null() {
+            return Err(Error::Chain(format!("Transaction {} has no receipt on {}", tx_hash, chain).into()));
+        }
+        let block_hash = string_field(&receipt, "blockHash")?.to_string();
+        let block_number = quantity_field(&receipt, "blockNumber")?;
+        let transaction_index = quantity_field(&receipt, "transactionIndex")?;
+
+        let block = rpc("eth_getBlockByHash", json!([block_hash, false])).await?;
+        if block.is_null() {
+            return Err(Error::Chain(format!("Block {} not found on {}", block_hash, chain).into()));
+        }
+        let receipts_root = string_field(&block, "receiptsRoot")?.to_string();
+
+        // Not every node serves eth_getBlockReceipts; fall back to one request per transaction
+        let receipts = match rpc("eth_getBlockReceipts", json!([block_hash])).await {
+            Ok(Value::Array(receipts)) => receipts,
+            _ => {
+                let hashes = block
+                    .get("transactions")
+                    .and_then(Value::as_array)
+                    .ok_or_else(|| Error::Chain(format!("Block {} has no transaction list", block_hash).into()))?;
+                futures::future::try_join_all(
+                    hashes.iter().map(|hash| rpc("eth_getTransactionReceipt", json!([hash]))),
+                )
+                .await?
+            }
+        };
+
+        let entries = receipts
+            .iter()
+            .map(|receipt| {
+                let index = quantity_field(receipt, "transactionIndex")?;
+                Ok((nibbles(&rlp_uint(index)), encode_receipt(receipt)?))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let target = nibbles(&rlp_uint(transaction_index));
+        let (root, merkle_proof) = trie_proof(entries, &target);
+        if !crypto::decode_hex(&receipts_root)?.eq(&root) {
+            return Err(Error::Chain(format!(
+                "Receipts of block {} on {} do not match its receipts root",
+                block_hash, chain
+            ).into()));
+        }
+
+        let payers = self.payer_candidates(tx_hash, chain);
+        let transfer = crypto::keccak256(TRANSFER_EVENT.as_bytes());
+        let (log, payer) = receipt_logs_json(&receipt)?
+            .into_iter()
+            .find_map(|log| {
+                let [event, from, ..] = log.topics.as_slice() else { return None };
+                let from = format!("0x{}", hex::encode(&from[12..]));
+                let payer = payers.iter().find(|payer| payer.eq_ignore_ascii_case(&from))?.clone();
+                (*event == transfer).then_some((log, payer))
+            })
+            .ok_or_else(|| Error::Payment(format!("Transaction {} has no transfer from a client wallet", tx_hash).into()))?;
+
+        Ok(PaymentProof {
+            tx_hash: tx_hash.to_string(),
+            block_hash,
+            block_number,
+            receipts_root,
+            transaction_index,
+            merkle_proof: merkle_proof.iter().map(|node| format!("0x{}", hex::encode(node))).collect(),
+            abi_encoded_log: format!("0x{}", hex::encode(log.abi_encode())),
+            payer,
+        })
+    }
+
+    /// Addresses that may have paid in a transaction: the recorded payer, or
+    /// every wallet of the client.
+    fn payer_candidates(&self, tx_hash: &str, chain: &str) -> Vec<String> {
+        let recorded = self.history.read().iter().find_map(|record| {
+            record
+                .transaction_hash
+                .as_deref()
+                .filter(|hash| hash.eq_ignore_ascii_case(tx_hash))
+                .and(record.payer.clone())
+        });
+        match recorded {
+            Some(payer) => vec![payer],
+            None => self
+                .chain_manager
+                .wallets()
+                .iter()
+                .filter_map(|wallet| self.chain_manager.wallet_address(wallet, chain).ok())
+                .collect(),
+        }
+    }
+}
+
+/// An event log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Log {
+    address: [u8; 20],
+    topics: Vec<[u8; 32]>,
+    data: Vec<u8>,
+}
+
+impl Log {
+    /// ABI-encodes the log as `(address, bytes32[], bytes)`.
+    fn abi_encode(&self) -> Vec<u8> {
+        let topics_offset = 3 * 32;
+        let data_offset = topics_offset + 32 * (self.topics.len() + 1);
+
+        let mut out = Vec::with_capacity(data_offset + 32 + self.data.len() + 31);
+        out.extend(crypto::address_word(&self.address));
+        out.extend(crypto::uint_word(topics_offset as u128));
+        out.extend(crypto::uint_word(data_offset as u128));
+        out.extend(crypto::uint_word(self.topics.len() as u128));
+        self.topics.iter().for_each(|topic| out.extend(topic));
+        out.extend(crypto::uint_word(self.data.len() as u128));
+        out.extend(&self.data);
+        out.resize(out.len() + (32 - self.data.len() % 32) % 32, 0);
+        out
+    }
+
+    /// Decodes a log encoded by [`abi_encode`](Self::abi_encode).
+    fn abi_decode(encoded: &[u8]) -> Option<Self> {
+        let word = |offset: usize| encoded.get(offset..offset.checked_add(32)?);
+        let uint = |offset: usize| usize::try_from(crypto::word_to_uint(word(offset)?).ok()?).ok();
+
+        let address: [u8; 20] = word(0)?[12..].try_into().ok()?;
+        let topics_offset = uint(32)?;
+        let topics: Vec<[u8; 32]> = (0..uint(topics_offset)?)
+            .map(|i| word(topics_offset + 32 * (i + 1))?.try_into().ok())
+            .collect::<Option<Vec<_>>>()?;
+        let data_offset = uint(64)?;
+        let data_start = data_offset.checked_add(32)?;
+        let data = encoded.get(data_start..data_start.checked_add(uint(data_offset)?)?)?.to_vec();
+        Some(Self { address, topics, data })
+    }
+}
+
+/// Reads a string field of an RPC result.
+fn string_field<'a>(value: &'a Value, field: &str) -> Result<&'a str> {
+    value
+        .get(field)
+        .and_then(Value::as_str)
+        .ok_or_else(|| Error::Chain(format!("RPC result has no {} field: {}", field, value).into()))
+}
+
+/// Reads a hex quantity field of an RPC result.
+fn quantity_field(value: &Value, field: &str) -> Result<u64> {
+    let hex = string_field(value, field)?;
+    u64::from_str_radix(hex.trim_start_matches("0x"), 16)
+        .map_err(|e| Error::Chain(format!("Invalid {} {:?}: {}", field, hex, e).into()))
+}
+
+/// Reads the logs of a JSON-RPC receipt.
+fn receipt_logs_json(receipt: &Value) -> Result<Vec<Log>> {
+    let logs = receipt
+        .get("logs")
+        .and_then(Value::as_array)
+        .ok_or_else(|| Error::Chain(format!("Receipt has no logs: {}", receipt).into()))?;
+    logs.iter()
+        .map(|log| {
+            let topics = log
+                .get("topics")
+                .and_then(Value::as_array)
+                .ok_or_else(|| Error::Chain(format!("Log has no topics: {}", log).into()))?
+                .iter()
+                .map(|topic| crypto::parse_bytes32(topic.as_str().unwrap_or_default()))
+                .collect::<Result<_>>()?;
+            Ok(Log {
+                address: crypto::parse_address(string_field(log, "address")?)?,
+                topics,
+                data: crypto::decode_hex(string_field(log, "data")?)?,
+            })
+        })
+        .collect()
+}
+
+/// Encodes a JSON-RPC receipt the way it is stored in the receipts trie.
+///
+/// Typed receipts are prefixed with their type. OP Stack deposit receipts
+/// (type `0x7e`) carry the deposit nonce and receipt version when present.
+fn encode_receipt(receipt: &Value) -> Result<Vec<u8>> {
+    let tx_type = match receipt.get("type") {
+        Some(_) => quantity_field(receipt, "type")?,
+        None => 0,
+    };
+    // Receipts from before Byzantium carry a state root instead of a status
+    let outcome = match receipt.get("status") {
+        Some(_) => rlp_uint(quantity_field(receipt, "status")?),
+        None => rlp_bytes(&crypto::decode_hex(string_field(receipt, "root")?)?),
+    };
+    let logs: Vec<_> = receipt_logs_json(receipt)?
+        .iter()
+        .map(|log| {
+            let topics: Vec<_> = log.topics.iter().map(|topic| rlp_bytes(topic)).collect();
+            rlp_list(&[rlp_bytes(&log.address), rlp_list(&topics), rlp_bytes(&log.data)])
+        })
+        .collect();
+
+    let mut fields = vec![
+        outcome,
+        rlp_uint(quantity_field(receipt, "cumulativeGasUsed")?),
+        rlp_bytes(&crypto::decode_hex(string_field(receipt, "logsBloom")?)?),
+        rlp_list(&logs),
+    ];
+    if tx_type == 0x7e {
+        for field in ["depositNonce", "depositReceiptVersion"] {
+            if receipt.get(field).is_some_and(|value| !value.is_null()) {
+                fields.push(rlp_uint(quantity_field(receipt, field)?));
+            }
+        }
+    }
+
+    let encoded = rlp_list(&fields);
+    Ok(match tx_type {
+        0 => encoded,
+        tx_type => [&[tx_type as u8][..], &encoded].concat(),
+    })
+}
+
+/// Decodes the logs of a receipt as stored in the receipts trie.
+fn receipt_logs(receipt: &[u8]) -> Option<Vec<Log>> {
+    let body = match receipt.first()? {
+        0xc0..=0xff => receipt,
+        _ => &receipt[1..],
+    };
+    let Rlp::List(fields) = rlp_decode(body)? else { return None };
+    let Rlp::List(logs) = fields.get(3)? else { return None };
+    logs.iter()
+        .map(|log| match log {
+            Rlp::List(parts) => match parts.as_slice() {
+                [Rlp::Bytes(address), Rlp::List(topics), Rlp::Bytes(data)] => Some(Log {
+                    address: (*address).try_into().ok()?,
+                    topics: topics
+                        .iter()
+                        .map(|topic| topic.bytes()?.try_into().ok())
+                        .collect::<Option<_>>()?,
+                    data: data.to_vec(),
+                }),
+                _ => None,
+            },
+            Rlp::Bytes(_) => None,
+        })
+        .collect()
+}
+
+/// Builds a Merkle Patricia trie over `entries` and returns its root hash
+/// along with the hashed nodes on the path to `target`, root first.
+fn trie_proof(mut entries: Vec<(Vec<u8>, Vec<u8>)>, target: &[u8]) -> ([u8; 32], Vec<Vec<u8>>) {
+    entries.sort();
+    let mut proof = Vec::new();
+    let root = trie_node(&entries, 0, Some(target), &mut proof);
+    proof.push(root.clone());
+    proof.reverse();
+    (crypto::keccak256(&root), proof)
+}
+
+/// Encodes the node holding `entries`, whose keys share their first `depth`
+/// nibbles, collecting the nodes below it on the path to `target` in `proof`.
+fn trie_node(
+    entries: &[(Vec<u8>, Vec<u8>)],
+    depth: usize,
+    target: Option<&[u8]>,
+    proof: &mut Vec<Vec<u8>>,
+) -> Vec<u8> {
+    let (first, last) = match entries {
+        [] => return rlp_bytes(&[]),
+        [(key, value)] => return rlp_list(&[rlp_bytes(&hex_prefix(&key[depth..], true)), rlp_bytes(value)]),
+        [(first, _), .., (last, _)] => (first, last),
+    };
+
+    let shared = first[depth..].iter().zip(&last[depth..]).take_while(|(a, b)| a == b).count();
+    if shared > 0 {
+        let path = &first[depth..depth + shared];
+        let target = target.filter(|target| target.get(depth..).is_some_and(|rest| rest.starts_with(path)));
+        let child = trie_node(entries, depth + shared, target, proof);
+        return rlp_list(&[rlp_bytes(&hex_prefix(path, false)), child_reference(child, target.is_some(), proof)]);
+    }
+
+    let mut items = Vec::with_capacity(17);
+    let mut rest = entries;
+    let value = match rest.first() {
+        Some((key, value)) if key.len() == depth => {
+            rest = &rest[1..];
+            rlp_bytes(value)
+        }
+        _ => rlp_bytes(&[]),
+    };
+    for nibble in 0..16u8 {
+        let split = rest.iter().take_while(|(key, _)| key[depth] == nibble).count();
+        let (branch, remaining) = rest.split_at(split);
+        rest = remaining;
+        if branch.is_empty() {
+            items.push(rlp_bytes(&[]));
+            continue;
+        }
+        let target = target.filter(|target| target.get(depth) == Some(&nibble));
+        let child = trie_node(branch, depth + 1, target, proof);
+        items.push(child_reference(child, target.is_some(), proof));
+    }
+    items.push(value);
+    rlp_list(&items)
+}
+
+/// References a child node from its parent: embedded if shorter than a
+/// hash, by hash otherwise, in which case it is part of the proof when on
+/// the path.
+fn child_reference(child: Vec<u8>, on_path: bool, proof: &mut Vec<Vec<u8>>) -> Vec<u8> {
+    if child.len() < 32 {
+        return child;
+    }
+    let hash = rlp_bytes(&crypto::keccak256(&child));
+    if on_path {
+        proof.push(child);
+    }
+    hash
+}
+
+/// Decodes a proof node, checking it has the expected hash.
+fn hashed_node<'a>(node: &'a [u8], hash: &[u8]) -> Option<Rlp<'a>> {
+    (crypto::keccak256(node) == hash).then(|| rlp_decode(node)).flatten()
+}
+
+/// Splits bytes into nibbles, high nibble first.
+fn nibbles(bytes: &[u8]) -> Vec<u8> {
+    bytes.iter().flat_map(|byte| [byte >> 4, byte & 0x0f]).collect()
+}
+
+/// Hex-prefix encodes a path of nibbles for a leaf or extension node.
+fn hex_prefix(path: &[u8], leaf: bool) -> Vec<u8> {
+    let flag = if leaf { 2 } else { 0 };
+    let (mut out, rest) = match path.len() % 2 {
+        1 => (vec![(flag + 1) << 4 | path[0]], &path[1..]),
+        _ => (vec![flag << 4], path),
+    };
+    out.extend(rest.chunks(2).map(|pair| pair[0] << 4 | pair[1]));
+    out
+}
+
+/// Decodes a hex-prefix encoded path into its nibbles and whether it ends in a leaf.
+fn decode_hex_prefix(encoded: &[u8]) -> Option<(Vec<u8>, bool)> {
+    let flag = encoded.first()? >> 4;
+    let mut path = nibbles(encoded);
+    path.drain(..if flag & 1 == 1 { 1 } else { 2 });
+    (flag < 4).then_some((path, flag & 2 == 2))
+}
+
+/// A decoded RLP item.
+#[derive(Debug, Clone)]
+enum Rlp<'a> {
+    Bytes(&'a [u8]),
+    List(Vec<Rlp<'a>>),
+}
+
+impl<'a> Rlp<'a> {
+    /// The item's bytes, if it is not a list.
+    fn bytes(&self) -> Option<&'a [u8]> {
+        match self {
+            Rlp::Bytes(bytes) => Some(bytes),
+            Rlp::List(_) => None,
+        }
+    }
+}
+
+/// Decodes a single RLP item spanning all of `data`.
+fn rlp_decode(data: &[u8]) -> Option<Rlp<'_>> {
+    match rlp_item(data)? {
+        (item, []) => Some(item),
+        _ => None,
+    }
+}
+
+/// Decodes the RLP item at the start of `data`, returning it and the rest.
+fn rlp_item(data: &[u8]) -> Option<(Rlp<'_>, &[u8])> {
+    let (&prefix, rest) = data.split_first()?;
+    let (len, rest) = match prefix {
+        0x00..=0x7f => return Some((Rlp::Bytes(&data[..1]), rest)),
+        0x80..=0xb7 => ((prefix - 0x80) as usize, rest),
+        0xb8..=0xbf => long_length(rest, (prefix - 0xb7) as usize)?,
+        0xc0..=0xf7 => ((prefix - 0xc0) as usize, rest),
+        _ => long_length(rest, (prefix - 0xf7) as usize)?,
+    };
+    if rest.len() < len {
+        return None;
+    }
+    let (payload, rest) = rest.split_at(len);
+    if prefix < 0xc0 {
+        return Some((Rlp::Bytes(payload), rest));
+    }
+
+    let mut items = Vec::new();
+    let mut remaining = payload;
+    while !remaining.is_empty() {
+        let (item, next) = rlp_item(remaining)?;
+        items.push(item);
+        remaining = next;
+    }
+    Some((Rlp::List(items), rest))
+}
+
+/// Reads a big-endian length of `size` bytes.
+fn long_length(data: &[u8], size: usize) -> Option<(usize, &[u8])> {
+    if data.len() < size || size > std::mem::size_of::<usize>() {
+        return None;
+    }
+    let (bytes, rest) = data.split_at(size);
+    let len = bytes.iter().fold(0usize, |len, byte| len << 8 | *byte as usize);
+    Some((len, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{ChainConfig, Config},
+        payment::tests::{payment_manager, record},
+        types::PaymentHistory,
+    };
+    use wiremock::{
+        matchers::{body_partial_json, method},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    const PAYER: &str = "0x2c7536e3605d9c16a7a3d7b1898e529396a65c23";
+    const BLOCK_HASH: &str = "0x0101010101010101010101010101010101010101010101010101010101010101";
+
+    /// Returns the root of a trie holding `entries`, keyed by their raw bytes.
+    fn root(entries: &[(&str, &str)]) -> String {
+        let entries = entries
+            .iter()
+            .map(|(key, value)| (nibbles(key.as_bytes()), value.as_bytes().to_vec()))
+            .collect();
+        format!("0x{}", hex::encode(trie_proof(entries, &[]).0))
+    }
+
+    #[test]
+    fn trie_roots_match_reference_vectors() {
+        // From the Ethereum trie tests (trieanyorder.json)
+        assert_eq!(root(&[]), "0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421");
+        assert_eq!(
+            root(&[("do", "verb"), ("horse", "stallion"), ("doge", "coin"), ("dog", "puppy")]),
+            "0x5991bb8c6514148a29db676a14ac506cd2cd5775ace63c30a4fe457715e9ac84"
+        );
+        assert_eq!(
+            root(&[("doe", "reindeer"), ("dog", "puppy"), ("dogglesworth", "cat")]),
+            "0x8aad789dff2f538bca5d8ea56e8abe10f4c7ba3a5dea95fea4cd6e7c3a1168d3"
+        );
+    }
+
+    /// Returns an RPC receipt of transaction `index` with a transfer from `from`.
+    fn receipt(index: u64, from: &str) -> Value {
+        let from = format!("0x{}{}", "0".repeat(24), from.trim_start_matches("0x"));
+        let to = format!("0x{}{}", "0".repeat(24), "11".repeat(20));
+        json!({
+            "transactionHash": format!("0x{:064x}", index + 1),
+            "transactionIndex": format!("0x{:x}", index),
+            "blockHash": BLOCK_HASH,
+            "blockNumber": "0x10",
+            "type": "0x2",
+            "status": "0x1",
+            "cumulativeGasUsed": format!("0x{:x}", 21_000 * (index + 1)),
+            "logsBloom": format!("0x{}", "0".repeat(512)),
+            "logs": [{
+                "address": "0x833589fcd6edb6e08f4c7c32d4f71b54bda02913",
+                "topics": [format!("0x{}", hex::encode(crypto::keccak256(TRANSFER_EVENT.as_bytes()))), from, to],
+                "data": format!("0x{}", hex::encode(crypto::uint_word(1000 + index as u128))),
+            }],
+        })
+    }
+
+    /// Returns the receipts of a block whose transactions are all paid by `PAYER`.
+    fn block_receipts(count: u64) -> Vec<Value> {
+        (0..count).map(|index| receipt(index, PAYER)).collect()
+    }
+
+    /// Builds the proof of transaction `index` among `receipts`.
+    fn proof(receipts: &[Value], index: u64) -> PaymentProof {
+        let entries = receipts
+            .iter()
+            .enumerate()
+            .map(|(i, receipt)| (nibbles(&rlp_uint(i as u64)), encode_receipt(receipt).unwrap()))
+            .collect();
+        let (root, nodes) = trie_proof(entries, &nibbles(&rlp_uint(index)));
+        let log = receipt_logs_json(&receipts[index as usize]).unwrap().remove(0);
+        PaymentProof {
+            tx_hash: format!("0x{:064x}", index + 1),
+            block_hash: BLOCK_HASH.to_string(),
+            block_number: 16,
+            receipts_root: format!("0x{}", hex::encode(root)),
+            transaction_index: index,
+            merkle_proof: nodes.iter().map(|node| format!("0x{}", hex::encode(node))).collect(),
+            abi_encoded_log: format!("0x{}", hex::encode(log.abi_encode())),
+            payer: PAYER.to_string(),
+        }
+    }
+
+    #[test]
+    fn proofs_verify_for_every_receipt_of_a_block() {
+        let receipts = block_receipts(40);
+        for index in 0..receipts.len() as u64 {
+            let proof = proof(&receipts, index);
+            assert!(proof.verify_inclusion(), "receipt {index}");
+            assert!(proof.verify_signature(), "receipt {index}");
+        }
+    }
+
+    #[test]
+    fn tampered_proofs_fail() {
+        let receipts = block_receipts(40);
+        let valid = proof(&receipts, 7);
+
+        let other_receipt = PaymentProof { transaction_index: 8, ..valid.clone() };
+        assert!(!other_receipt.verify_inclusion());
+
+        let mut altered_node = valid.clone();
+        let node = altered_node.merkle_proof.last_mut().unwrap();
+        node.replace_range(node.len() - 2.., "ff");
+        assert!(!altered_node.verify_inclusion());
+
+        let other_log = PaymentProof { abi_encoded_log: proof(&receipts, 8).abi_encoded_log, ..valid.clone() };
+        assert!(!other_log.verify_inclusion());
+
+        let other_payer = PaymentProof { payer: format!("0x{}", "22".repeat(20)), ..valid };
+        assert!(!other_payer.verify_signature());
+    }
+
+    /// Mounts an RPC result for `rpc_method` called with `params`.
+    async fn mount_rpc(server: &MockServer, rpc_method: &str, params: Value, result: Value) {
+        Mock::given(method("POST"))
+            .and(body_partial_json(json!({ "method": rpc_method, "params": params })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "jsonrpc": "2.0", "id": 1, "result": result })))
+            .mount(server)
+            .await;
+    }
+
+    /// Starts an RPC serving a block of `receipts` whose header claims `receipts_root`.
+    async fn block_rpc(receipts: &[Value], receipts_root: &str) -> MockServer {
+        let server = MockServer::start().await;
+        for receipt in receipts {
+            mount_rpc(&server, "eth_getTransactionReceipt", json!([receipt["transactionHash"]]), receipt.clone()).await;
+        }
+        let block = json!({ "hash": BLOCK_HASH, "receiptsRoot": receipts_root });
+        mount_rpc(&server, "eth_getBlockByHash", json!([BLOCK_HASH, false]), block).await;
+        mount_rpc(&server, "eth_getBlockReceipts", json!([BLOCK_HASH]), json!(receipts)).await;
+        server
+    }
+
+    async fn proof_manager(rpc: &MockServer, tx_hash: &str) -> PaymentManager {
+        let manager = payment_manager(Config {
+            chains: vec![ChainConfig::base_mainnet().with_rpc_url(rpc.uri())],
+            ..Config::default()
+        })
+        .await;
+        manager.history.write().push_back(PaymentHistory {
+            transaction_hash: Some(tx_hash.to_string()),
+            payer: Some(PAYER.to_string()),
+            ..record("paid")
+        });
+        manager
+    }
+
+    #[tokio::test]
+    async fn receipt_proofs_are_built_from_the_chain() {
+        let mut receipts = block_receipts(5);
+        receipts.push(receipt(5, &format!("0x{}", "33".repeat(20))));
+        let expected = proof(&receipts, 3);
+        let rpc = block_rpc(&receipts, &expected.receipts_root).await;
+        let manager = proof_manager(&rpc, &expected.tx_hash).await;
+
+        let proof = manager.get_receipt_proof(&expected.tx_hash, "base").await.unwrap();
+        assert_eq!(proof, expected);
+        assert!(proof.verify_inclusion());
+        assert!(proof.verify_signature());
+    }
+
+    #[tokio::test]
+    async fn receipts_not_matching_the_block_are_rejected() {
+        let receipts = block_receipts(5);
+        let tx_hash = format!("0x{:064x}", 3);
+        let rpc = block_rpc(&receipts, BLOCK_HASH).await;
+        let manager = proof_manager(&rpc, &tx_hash).await;
+
+        let error = manager.get_receipt_proof(&tx_hash, "base").await.unwrap_err();
+        assert!(matches!(error, Error::Chain(_)), "{error}");
+    }
+}
@@ -0,0 +1,147 @@
+//! Signed proof of purchase.
+//!
+//! A [`Receipt`] is issued for every settled payment and stored with its
+//! [`PaymentHistory`](crate::PaymentHistory) record. It bundles the paid URL,
+//! a hash of the requirements, the signed `X-PAYMENT` header and the
+//! facilitator's settlement, and is signed by the paying wallet with EIP-191
+//! `personal_sign` so it can be verified offline. Servers that accept proof
+//! of an earlier payment read it from the [`PAYMENT_RECEIPT_HEADER`].
+
+use super::{decode_authorization, PaymentRequirements, Settlement};
+use crate::{
+    crypto,
+    error::{Error, Result},
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Header carrying a base64-encoded JSON [`Receipt`].
+pub const PAYMENT_RECEIPT_HEADER: &str = "X-PAYMENT-RECEIPT";
+
+/// Wallet-signed proof that a resource was paid for.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Receipt {
+    /// URL of the paid resource
+    pub url: String,
+
+    /// Keccak-256 hash of the JSON-encoded payment requirements
+    pub requirements_hash: String,
+
+    /// Signed `X-PAYMENT` header sent with the request
+    pub payment_header: String,
+
+    /// Settlement decoded from the `X-PAYMENT-RESPONSE` header
+    pub settlement: Settlement,
+
+    /// Settlement transaction hash
+    pub transaction_hash: Option<String>,
+
+    /// Network the payment was made on
+    pub network: String,
+
+    /// Address of the wallet that paid and signed the receipt
+    pub payer: String,
+
+    /// Correlation ID of the paying request
+    pub request_id: String,
+
+    /// Time the paid request was sent
+    pub paid_at: DateTime<Utc>,
+
+    /// Time the receipt was signed
+    pub issued_at: DateTime<Utc>,
+
+    /// EIP-191 signature by `payer` over [`digest`](Self::digest)
+    pub signature: String,
+}
+
+/// Signed fields of a receipt, in signing order.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ReceiptBody<'a> {
+    url: &'a str,
+    requirements_hash: &'a str,
+    payment_header: &'a str,
+    settlement: &'a Settlement,
+    transaction_hash: Option<&'a str>,
+    network: &'a str,
+    payer: &'a str,
+    request_id: &'a str,
+    paid_at: &'a DateTime<Utc>,
+    issued_at: &'a DateTime<Utc>,
+}
+
+impl Receipt {
+    /// Hash of the signed fields; the signature covers its `personal_sign` form.
+    pub fn digest(&self) -> Result<[u8; 32]> {
+        let body = ReceiptBody {
+            url: &self.url,
+            requirements_hash: &self.requirements_hash,
+            payment_header: &self.payment_header,
+            settlement: &self.settlement,
+            transaction_hash: self.transaction_hash.as_deref(),
+            network: &self.network,
+            payer: &self.payer,
+            request_id: &self.request_id,
+            paid_at: &self.paid_at,
+            issued_at: &self.issued_at,
+        };
+        Ok(crypto::keccak256(&serde_json::to_vec(&body)?))
+    }
+
+    /// Verifies the receipt offline.
+    ///
+    /// `public_key` is the hex-encoded SEC1 public key or the address of the
+    /// wallet expected to have signed the receipt. The signature must
+    /// recover to that wallet, which must also be the payer named in the
+    /// receipt and in its signed payment authorization, and the settlement
+    /// must have succeeded with the receipt's transaction hash.
+    pub fn verify(&self, public_key: &str) -> Result<()> {
+        let invalid = |reason: String| Error::Payment(format!("Invalid receipt: {}", reason).into());
+
+        let expected = crypto::public_key_address(public_key)?;
+        let digest = crypto::personal_message_hash(&self.digest()?);
+        let signer = crypto::recover_address(&digest, &self.signature)?;
+        if !signer.eq_ignore_ascii_case(&expected) {
+            return Err(invalid(format!("signed by {}, expected {}", signer, expected)));
+        }
+        if !signer.eq_ignore_ascii_case(&self.payer) {
+            return Err(invalid(format!("signed by {}, but paid by {}", signer, self.payer)));
+        }
+
+        let authorization = decode_authorization(&self.payment_header)
+            .ok_or_else(|| invalid("payment header carries no authorization".to_string()))?;
+        if !authorization.from.eq_ignore_ascii_case(&self.payer) {
+            return Err(invalid(format!("payment authorized by {}", authorization.from)));
+        }
+
+        if !self.settlement.success {
+            return Err(invalid("settlement did not succeed".to_string()));
+        }
+        if self.settlement.transaction_hash != self.transaction_hash {
+            return Err(invalid("transaction hash does not match the settlement".to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Encodes the receipt as the value of the [`PAYMENT_RECEIPT_HEADER`].
+    pub fn to_header(&self) -> Result<String> {
+        Ok(BASE64.encode(serde_json::to_vec(self)?))
+    }
+
+    /// Decodes a [`PAYMENT_RECEIPT_HEADER`] value.
+    pub fn from_header(header: &str) -> Result<Self> {
+        let decoded = BASE64
+            .decode(header.trim())
+            .map_err(|e| Error::Payment(format!("Invalid receipt header: {}", e).into()))?;
+        Ok(serde_json::from_slice(&decoded)?)
+    }
+}
+
+/// Returns the `0x`-prefixed Keccak-256 hash of the JSON-encoded requirements.
+pub(crate) fn requirements_hash(requirements: &PaymentRequirements) -> Result<String> {
+    Ok(format!("0x{}", hex::encode(crypto::keccak256(&serde_json::to_vec(requirements)?))))
+}
@@ -0,0 +1,346 @@
+//! Typed payment requirements.
+//!
+//! Requirements arrive either in the body of a 402 response or in the
+//! `X-PAYMENT-REQUIRED` header. Both use the x402 wire format with a single
+//! `payTo` address and `asset`; the typed form also accepts lists of
+//! destinations and assets so servers can offer several ways to pay.
+
+use crate::{
+    chains::NetworkId,
+    crypto,
+    error::{Error, Result},
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use reqwest::header::HeaderValue;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use url::Url;
+
+/// Header carrying payment requirements as JSON or base64-encoded JSON.
+pub const PAYMENT_REQUIRED_HEADER: &str = "X-PAYMENT-REQUIRED";
+
+/// Address that receives a payment.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentDestination {
+    /// Recipient address
+    pub address: String,
+}
+
+/// Token accepted as payment.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AssetInfo {
+    /// Token contract address
+    pub address: String,
+
+    /// Token symbol (e.g. `USDC`)
+    #[serde(default)]
+    pub symbol: Option<String>,
+
+    /// Number of decimals of the token
+    #[serde(default)]
+    pub decimals: Option<u8>,
+
+    /// EIP-712 domain name of the token
+    #[serde(default)]
+    pub name: Option<String>,
+
+    /// EIP-712 domain version of the token
+    #[serde(default)]
+    pub version: Option<String>,
+}
+
+/// Payment requirements advertised by a server in a 402 response.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", try_from = "RawPaymentRequirements")]
+pub struct PaymentRequirements {
+    /// Payment scheme (e.g. `exact`)
+    pub scheme: String,
+
+    /// Network the payment must be made on
+    pub network: NetworkId,
+
+    /// Maximum amount required, in the asset's smallest unit
+    pub max_amount_required: String,
+
+    /// Resource being paid for
+    pub resource: Url,
+
+    /// Description of the resource
+    pub description: String,
+
+    /// MIME type of the resource
+    pub mime_type: Option<String>,
+
+    /// Addresses receiving the payment, preferred first
+    pub pay_to: Vec<PaymentDestination>,
+
+    /// Maximum time in seconds the payment authorization stays valid
+    pub max_timeout_seconds: u64,
+
+    /// Assets accepted as payment, preferred first
+    pub supported_assets: Vec<AssetInfo>,
+
+    /// Scheme-specific extra data
+    pub extra: HashMap<String, Value>,
+}
+
+impl PaymentRequirements {
+    /// Checks that the requirements can be paid.
+    ///
+    /// The amount must be a decimal `uint256`, the resource an HTTP(S) URL,
+    /// and at least one destination and one asset must be listed.
+    pub fn validate(&self) -> Result<()> {
+        if self.scheme.is_empty() {
+            return Err(Error::Payment("Payment requirements have no scheme".to_string().into()));
+        }
+        if self.network.as_str().is_empty() {
+            return Err(Error::Payment("Payment requirements have no network".to_string().into()));
+        }
+        if !crypto::is_uint256(&self.max_amount_required) {
+            return Err(Error::Payment(format!(
+                "Invalid payment amount: {}",
+                self.max_amount_required
+            ).into()));
+        }
+        if !matches!(self.resource.scheme(), "http" | "https") {
+            return Err(Error::Payment(format!("Invalid resource URL: {}", self.resource).into()));
+        }
+        if self.primary_pay_to().is_none() {
+            return Err(Error::Payment("Payment requirements list no recipient".to_string().into()));
+        }
+        if self.primary_asset().is_none() {
+            return Err(Error::Payment("Payment requirements list no asset".to_string().into()));
+        }
+        Ok(())
+    }
+
+    /// Returns the preferred recipient.
+    pub fn primary_pay_to(&self) -> Option<&PaymentDestination> {
+        self.pay_to.iter().find(|destination| !destination.address.is_empty())
+    }
+
+    /// Returns the preferred asset.
+    pub fn primary_asset(&self) -> Option<&AssetInfo> {
+        self.supported_assets.iter().find(|asset| !asset.address.is_empty())
+    }
+}
+
+impl TryFrom<&HeaderValue> for PaymentRequirements {
+    type Error = Error;
+
+    /// Parses and validates an `X-PAYMENT-REQUIRED` header value.
+    fn try_from(value: &HeaderValue) -> Result<Self> {
+        let value = value
+            .to_str()
+            .map_err(|_| Error::Payment(format!("{} header is not valid ASCII", PAYMENT_REQUIRED_HEADER).into()))?
+            .trim();
+
+        let json = if value.starts_with('{') {
+            value.as_bytes().to_vec()
+        } else {
+            BASE64
+                .decode(value)
+                .map_err(|e| Error::Payment(format!("Invalid {} header: {}", PAYMENT_REQUIRED_HEADER, e).into()))?
+        };
+
+        let requirements: Self = serde_json::from_slice(&json)
+            .map_err(|e| Error::Payment(format!("Invalid payment requirements: {}", e).into()))?;
+        requirements.validate()?;
+        Ok(requirements)
+    }
+}
+
+/// Payment requirements as they appear on the wire.
+///
+/// Snake-case field names and integer amounts are accepted as well, since
+/// several servers deviate from the spec in these ways.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawPaymentRequirements {
+    scheme: String,
+    network: String,
+    #[serde(alias = "max_amount_required", deserialize_with = "amount_string")]
+    max_amount_required: String,
+    resource: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default, alias = "mime_type")]
+    mime_type: Option<String>,
+    #[serde(default, alias = "pay_to")]
+    pay_to: OneOrMany<RawDestination>,
+    #[serde(default = "default_max_timeout_seconds", alias = "max_timeout_seconds")]
+    max_timeout_seconds: u64,
+    #[serde(default)]
+    asset: Option<String>,
+    #[serde(default, alias = "supported_assets")]
+    supported_assets: Vec<AssetInfo>,
+    #[serde(default)]
+    extra: Option<HashMap<String, Value>>,
+}
+
+fn default_max_timeout_seconds() -> u64 {
+    60
+}
+
+/// Reads an amount given either as a decimal string or as a JSON integer.
+fn amount_string<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<String, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Amount {
+        String(String),
+        Integer(u64),
+    }
+
+    Ok(match Amount::deserialize(deserializer)? {
+        Amount::String(amount) => amount,
+        Amount::Integer(amount) => amount.to_string(),
+    })
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+impl<T> Default for OneOrMany<T> {
+    fn default() -> Self {
+        Self::Many(Vec::new())
+    }
+}
+
+impl<T> OneOrMany<T> {
+    fn into_vec(self) -> Vec<T> {
+        match self {
+            Self::One(item) => vec![item],
+            Self::Many(items) => items,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawDestination {
+    Address(String),
+    Destination(PaymentDestination),
+}
+
+impl TryFrom<RawPaymentRequirements> for PaymentRequirements {
+    type Error = String;
+
+    fn try_from(raw: RawPaymentRequirements) -> std::result::Result<Self, String> {
+        let resource = Url::parse(&raw.resource).map_err(|e| format!("invalid resource URL {}: {}", raw.resource, e))?;
+        let extra = raw.extra.unwrap_or_default();
+
+        let pay_to = raw
+            .pay_to
+            .into_vec()
+            .into_iter()
+            .map(|destination| match destination {
+                RawDestination::Address(address) => PaymentDestination { address },
+                RawDestination::Destination(destination) => destination,
+            })
+            .collect();
+
+        // The single x402 `asset` comes first, with its EIP-712 domain from `extra`
+        let mut supported_assets = Vec::with_capacity(raw.supported_assets.len() + 1);
+        if let Some(address) = raw.asset {
+            let domain_field = |key: &str| extra.get(key).and_then(Value::as_str).map(str::to_string);
+            supported_assets.push(AssetInfo {
+                address,
+                symbol: None,
+                decimals: None,
+                name: domain_field("name"),
+                version: domain_field("version"),
+            });
+        }
+        supported_assets.extend(raw.supported_assets);
+
+        Ok(Self {
+            scheme: raw.scheme,
+            network: raw.network.into(),
+            max_amount_required: raw.max_amount_required,
+            resource,
+            description: raw.description,
+            mime_type: raw.mime_type.filter(|mime| !mime.is_empty()),
+            pay_to,
+            max_timeout_seconds: raw.max_timeout_seconds,
+            supported_assets,
+            extra,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn wire() -> Value {
+        json!({
+            "scheme": "exact",
+            "network": "base",
+            "maxAmountRequired": "1000",
+            "resource": "https://api.example.com/data",
+            "payTo": "0x1111111111111111111111111111111111111111",
+            "asset": "0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913",
+            "extra": { "name": "USD Coin", "version": "2" },
+        })
+    }
+
+    /// Parses `requirements` from a header, base64-encoded like servers send it.
+    fn parse(requirements: &Value) -> Result<PaymentRequirements> {
+        let header = HeaderValue::from_str(&BASE64.encode(requirements.to_string())).unwrap();
+        PaymentRequirements::try_from(&header)
+    }
+
+    /// Returns the wire requirements with `field` set to `value`.
+    fn with(field: &str, value: Value) -> Value {
+        let mut requirements = wire();
+        requirements[field] = value;
+        requirements
+    }
+
+    #[test]
+    fn header_requirements_are_parsed() {
+        let requirements = parse(&wire()).unwrap();
+        assert_eq!(requirements.primary_pay_to().unwrap().address, "0x1111111111111111111111111111111111111111");
+        let asset = requirements.primary_asset().unwrap();
+        assert_eq!(asset.name.as_deref(), Some("USD Coin"));
+        assert_eq!(asset.version.as_deref(), Some("2"));
+
+        // Plain JSON headers are accepted too
+        let header = HeaderValue::from_str(&wire().to_string()).unwrap();
+        assert_eq!(PaymentRequirements::try_from(&header).unwrap().max_amount_required, "1000");
+    }
+
+    #[test]
+    fn malformed_headers_are_rejected() {
+        assert!(PaymentRequirements::try_from(&HeaderValue::from_static("not base64!")).is_err());
+        assert!(PaymentRequirements::try_from(&HeaderValue::from_static("{\"scheme\":")).is_err());
+        assert!(PaymentRequirements::try_from(&HeaderValue::from_bytes(b"\xff").unwrap()).is_err());
+    }
+
+    #[test]
+    fn invalid_requirements_are_rejected() {
+        let mut without_asset = wire();
+        without_asset.as_object_mut().unwrap().remove("asset");
+        let cases = [
+            with("maxAmountRequired", json!("-1")),
+            with("maxAmountRequired", json!("1.5")),
+            with("maxAmountRequired", json!("1".repeat(80))),
+            with("resource", json!("not a url")),
+            with("resource", json!("ftp://api.example.com/data")),
+            with("payTo", json!([])),
+            with("scheme", json!("")),
+            without_asset,
+        ];
+        for requirements in cases {
+            assert!(parse(&requirements).is_err(), "{requirements}");
+        }
+    }
+}
@@ -0,0 +1,296 @@
+//! Versioned storage format of payment history records.
+//!
+//! Every persisted [`PaymentHistory`] carries a `schema_version`. Records
+//! written by older releases are upgraded step by step through a
+//! [`MigrationRegistry`] when loaded, so the in-memory type can evolve
+//! without breaking existing stores. Records from a newer release than this
+//! one are rejected with [`Error::UnsupportedSchemaVersion`].
+//!
+//! | Version | Changes |
+//! |---------|---------|
+//! | 1 | Initial format, no `schema_version` field |
+//! | 2 | Adds `metadata`, `request_id`, `batch_id` and `facilitator` |
+//! | 3 | Adds `timing` |
+//! | 4 | Adds `asset_symbol` |
+//! | 5 | Adds `receipt` |
+//! | 6 | Adds `wallet` |
+//! | 7 | Adds `dry_run` |
+//! | 8 | Adds `authorization_nonce` |
+
+use crate::{
+    error::{Error, Result},
+    types::PaymentHistory,
+};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+/// Schema version written by this release.
+pub const CURRENT_SCHEMA_VERSION: u32 = 8;
+
+/// Name of the version field in serialized records.
+const VERSION_FIELD: &str = "schema_version";
+
+/// Upgrades a record by exactly one schema version.
+pub type Migration = fn(Map<String, Value>) -> Result<Map<String, Value>>;
+
+/// Migrations used to upgrade stored records to the current schema.
+#[derive(Debug, Clone)]
+pub struct MigrationRegistry {
+    /// Migrations keyed by the version they upgrade from
+    migrations: BTreeMap<u32, Migration>,
+    target_version: u32,
+}
+
+impl Default for MigrationRegistry {
+    fn default() -> Self {
+        Self::empty(CURRENT_SCHEMA_VERSION)
+            .register(1, migrate_v1_to_v2)
+            .register(2, migrate_v2_to_v3)
+            .register(3, migrate_v3_to_v4)
+            .register(4, migrate_v4_to_v5)
+            .register(5, migrate_v5_to_v6)
+            .register(6, migrate_v6_to_v7)
+            .register(7, migrate_v7_to_v8)
+    }
+}
+
+impl MigrationRegistry {
+    /// Creates a registry with the built-in migrations.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a registry without migrations that upgrades to `target_version`.
+    pub fn empty(target_version: u32) -> Self {
+        Self {
+            migrations: BTreeMap::new(),
+            target_version,
+        }
+    }
+
+    /// Registers the migration from version `from` to `from + 1`, replacing any existing one.
+    pub fn register(mut self, from: u32, migration: Migration) -> Self {
+        self.migrations.insert(from, migration);
+        self
+    }
+
+    /// Schema version records are upgraded to.
+    pub fn target_version(&self) -> u32 {
+        self.target_version
+    }
+
+    /// Upgrades a serialized record to the target version.
+    ///
+    /// Records without a version field are treated as version 1.
+    pub fn upgrade(&self, record: Value) -> Result<Value> {
+        let Value::Object(mut record) = record else {
+            return Err(Error::Serialization("Payment record is not a JSON object".to_string().into()));
+        };
+
+        let mut version = match record.get(VERSION_FIELD) {
+            None => 1,
+            Some(value) => value
+                .as_u64()
+                .and_then(|v| u32::try_from(v).ok())
+                .ok_or_else(|| Error::Serialization(format!("Invalid {}: {}", VERSION_FIELD, value).into()))?,
+        };
+
+        if version > self.target_version {
+            return Err(Error::UnsupportedSchemaVersion {
+                found: version,
+                supported: self.target_version,
+                context: None,
+            });
+        }
+
+        while version < self.target_version {
+            let migration = self.migrations.get(&version).ok_or_else(|| {
+                Error::Serialization(format!("No migration from schema version {}", version).into())
+            })?;
+            record = migration(record)?;
+            version += 1;
+            record.insert(VERSION_FIELD.to_string(), Value::from(version));
+        }
+
+        Ok(Value::Object(record))
+    }
+
+    /// Upgrades and deserializes a stored record.
+    pub fn load(&self, record: Value) -> Result<PaymentHistory> {
+        Ok(serde_json::from_value(self.upgrade(record)?)?)
+    }
+
+    /// Parses, upgrades and deserializes a stored JSON record.
+    pub fn load_slice(&self, bytes: &[u8]) -> Result<PaymentHistory> {
+        self.load(serde_json::from_slice(bytes)?)
+    }
+}
+
+/// Serializes a record in the current schema.
+pub fn to_value(record: &PaymentHistory) -> Result<Value> {
+    let mut value = serde_json::to_value(record)?;
+    if let Value::Object(map) = &mut value {
+        map.insert(VERSION_FIELD.to_string(), Value::from(CURRENT_SCHEMA_VERSION));
+    }
+    Ok(value)
+}
+
+/// Version 2 added optional fields, which default to empty.
+fn migrate_v1_to_v2(mut record: Map<String, Value>) -> Result<Map<String, Value>> {
+    record.entry("metadata").or_insert_with(|| Value::Object(Map::new()));
+    for field in ["request_id", "batch_id", "facilitator"] {
+        record.entry(field).or_insert(Value::Null);
+    }
+    Ok(record)
+}
+
+/// Version 3 added phase timings, which are unknown for older payments.
+fn migrate_v2_to_v3(mut record: Map<String, Value>) -> Result<Map<String, Value>> {
+    record.entry("timing").or_insert(Value::Null);
+    Ok(record)
+}
+
+/// Version 4 added the asset symbol, which is unknown for older payments.
+fn migrate_v3_to_v4(mut record: Map<String, Value>) -> Result<Map<String, Value>> {
+    record.entry("asset_symbol").or_insert(Value::Null);
+    Ok(record)
+}
+
+/// Version 5 added receipts, which older payments do not have.
+fn migrate_v4_to_v5(mut record: Map<String, Value>) -> Result<Map<String, Value>> {
+    record.entry("receipt").or_insert(Value::Null);
+    Ok(record)
+}
+
+/// Version 6 added the paying wallet's label, unknown for older payments.
+fn migrate_v5_to_v6(mut record: Map<String, Value>) -> Result<Map<String, Value>> {
+    record.entry("wallet").or_insert(Value::Null);
+    Ok(record)
+}
+
+/// Version 7 added dry-run payments; every older payment was settled.
+fn migrate_v6_to_v7(mut record: Map<String, Value>) -> Result<Map<String, Value>> {
+    record.entry("dry_run").or_insert(Value::Bool(false));
+    Ok(record)
+}
+
+/// Version 8 added the authorization nonce, which was not kept for older payments.
+fn migrate_v7_to_v8(mut record: Map<String, Value>) -> Result<Map<String, Value>> {
+    record.entry("authorization_nonce").or_insert(Value::Null);
+    Ok(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use serde_json::json;
+
+    /// Fields added by each schema version, with the value older records load with.
+    fn added_fields(version: u32) -> Vec<(&'static str, Value)> {
+        match version {
+            2 => vec![
+                ("metadata", json!({})),
+                ("request_id", Value::Null),
+                ("batch_id", Value::Null),
+                ("facilitator", Value::Null),
+            ],
+            3 => vec![("timing", Value::Null)],
+            4 => vec![("asset_symbol", Value::Null)],
+            5 => vec![("receipt", Value::Null)],
+            6 => vec![("wallet", Value::Null)],
+            7 => vec![("dry_run", Value::Bool(false))],
+            8 => vec![("authorization_nonce", Value::Null)],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns `record` as written at schema `version`, with the fields added
+    /// later removed, and the record it loads as.
+    fn at_version(record: &PaymentHistory, version: u32) -> (Value, Value) {
+        let mut expected = to_value(record).unwrap();
+        let mut stored = expected.clone();
+        for later in version + 1..=CURRENT_SCHEMA_VERSION {
+            for (field, default) in added_fields(later) {
+                expected[field] = default;
+                stored.as_object_mut().unwrap().remove(field);
+            }
+        }
+        if version == 1 {
+            stored.as_object_mut().unwrap().remove(VERSION_FIELD);
+        } else {
+            stored[VERSION_FIELD] = Value::from(version);
+        }
+        (stored, expected)
+    }
+
+    fn payment_history() -> impl Strategy<Value = PaymentHistory> {
+        (
+            any::<u128>(),
+            prop::sample::select(vec!["pending", "confirmed", "failed", "expired"]),
+            "[a-z0-9=&]{0,24}",
+            prop::option::of("0x[0-9a-f]{40}"),
+            prop::collection::hash_map("[a-z_]{1,8}", ".{0,16}", 0..4),
+            any::<bool>(),
+        )
+            .prop_map(|(amount, status, query, payer, metadata, dry_run)| {
+                serde_json::from_value(json!({
+                    "schema_version": CURRENT_SCHEMA_VERSION,
+                    "payment_id": "pay_1",
+                    "url": format!("https://api.example.com/data?{}", query),
+                    "amount": amount.to_string(),
+                    "asset": "0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913",
+                    "asset_decimals": 6,
+                    "asset_symbol": "USDC",
+                    "usd_value": null,
+                    "transaction_hash": null,
+                    "network": "base",
+                    "payer": payer,
+                    "payee": "0x1111111111111111111111111111111111111111",
+                    "timestamp": "2024-05-01T12:00:00Z",
+                    "status": status,
+                    "description": "",
+                    "metadata": metadata,
+                    "request_id": "req_1",
+                    "wallet": "primary",
+                    "dry_run": dry_run,
+                    "authorization_nonce": "0x01",
+                }))
+                .unwrap()
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn records_of_every_version_round_trip(record in payment_history()) {
+            let registry = MigrationRegistry::new();
+            for version in 1..=CURRENT_SCHEMA_VERSION {
+                let (stored, expected) = at_version(&record, version);
+                let loaded = registry.load_slice(&serde_json::to_vec(&stored).unwrap()).unwrap();
+                prop_assert_eq!(to_value(&loaded).unwrap(), expected, "version {}", version);
+            }
+        }
+
+        #[test]
+        fn redacted_records_keep_no_query_or_payer_data(record in payment_history()) {
+            let redacted = MigrationRegistry::new().load(to_value(&record.redacted()).unwrap()).unwrap();
+            prop_assert_eq!(redacted.url, "https://api.example.com/data");
+            prop_assert!(redacted.payer.is_none());
+            prop_assert!(redacted.metadata.is_empty());
+            prop_assert_eq!(redacted.amount, record.amount);
+        }
+    }
+
+    #[test]
+    fn records_from_a_newer_release_are_rejected() {
+        let record = json!({ "schema_version": CURRENT_SCHEMA_VERSION + 1, "payment_id": "pay_1" });
+        let error = MigrationRegistry::new().load(record).unwrap_err();
+        assert!(
+            matches!(error, Error::UnsupportedSchemaVersion { found, supported, .. }
+                if found == CURRENT_SCHEMA_VERSION + 1 && supported == CURRENT_SCHEMA_VERSION),
+            "{error}"
+        );
+        assert!(MigrationRegistry::new().load(json!({ "schema_version": "2" })).is_err());
+        assert!(MigrationRegistry::new().load(json!([])).is_err());
+    }
+}
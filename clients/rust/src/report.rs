@@ -0,0 +1,225 @@
+//! Periodic spend reports.
+//!
+//! A reporter started with
+//! [`Client::start_spend_reporter`](crate::Client::start_spend_reporter)
+//! hands a [`SpendSnapshot`] of the activity since the previous one to a
+//! [`SpendSink`] at a fixed interval, and a last one when the client is
+//! closed. Sinks for log lines ([`TracingSink`]), JSON Lines files
+//! ([`JsonFileSink`]) and HTTP collectors ([`HttpSink`]) are included.
+//!
+//! ```rust,no_run
+//! use std::time::Duration;
+//! use v402_client::{report::JsonFileSink, Client};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let client = Client::builder()
+//!     .spend_reporter(Duration::from_secs(60), Box::new(JsonFileSink::new("/var/log/v402/spend.jsonl")))
+//!     .build()
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt, path::PathBuf, time::Duration};
+use tokio::io::AsyncWriteExt;
+use tracing::{info, warn};
+
+/// Time an [`HttpSink`] waits for the collector to answer.
+const HTTP_SINK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Client activity during one reporting period.
+///
+/// Every count covers only the period, not the client's lifetime.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SpendSnapshot {
+    /// Start of the period
+    pub period_start: DateTime<Utc>,
+
+    /// End of the period
+    pub period_end: DateTime<Utc>,
+
+    /// Requests completed, not counting those served from the cache
+    pub requests: u64,
+
+    /// Requests that failed
+    pub failed_requests: u64,
+
+    /// Payments made
+    pub payments: u64,
+
+    /// Amount paid per network, in the asset's smallest unit
+    pub paid_by_network: BTreeMap<String, String>,
+
+    /// Lookups served from the cache
+    pub cache_hits: u64,
+
+    /// Lookups that missed the cache
+    pub cache_misses: u64,
+
+    /// Share of lookups served from the cache, 0.0 without lookups
+    pub cache_hit_ratio: f64,
+}
+
+/// Destination of spend reports.
+#[async_trait]
+pub trait SpendSink: Send + Sync + fmt::Debug {
+    /// Delivers one snapshot.
+    ///
+    /// Sinks handle their own failures, logging them rather than retrying
+    /// until the next report is due.
+    async fn report(&self, snapshot: SpendSnapshot);
+}
+
+/// Logs each snapshot as a structured `info` event with the `v402::spend` target.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TracingSink;
+
+#[async_trait]
+impl SpendSink for TracingSink {
+    async fn report(&self, snapshot: SpendSnapshot) {
+        info!(
+            target: "v402::spend",
+            period_start = %snapshot.period_start,
+            period_end = %snapshot.period_end,
+            requests = snapshot.requests,
+            failed_requests = snapshot.failed_requests,
+            payments = snapshot.payments,
+            paid_by_network = ?snapshot.paid_by_network,
+            cache_hit_ratio = snapshot.cache_hit_ratio,
+            "Spend report"
+        );
+    }
+}
+
+/// Appends each snapshot to a file as one line of JSON.
+#[derive(Debug, Clone)]
+pub struct JsonFileSink {
+    path: PathBuf,
+}
+
+impl JsonFileSink {
+    /// Creates a sink appending to `path`, created on the first report if missing.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+#[async_trait]
+impl SpendSink for JsonFileSink {
+    async fn report(&self, snapshot: SpendSnapshot) {
+        let result = async {
+            let mut line = serde_json::to_vec(&snapshot)?;
+            line.push(b'\n');
+            let mut file = tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .await?;
+            file.write_all(&line).await?;
+            file.flush().await?;
+            Ok::<_, crate::Error>(())
+        };
+        if let Err(e) = result.await {
+            warn!(path = %self.path.display(), error = %e, "Failed to write spend report");
+        }
+    }
+}
+
+/// Posts each snapshot as JSON to a collector URL.
+#[derive(Debug, Clone)]
+pub struct HttpSink {
+    url: String,
+    client: reqwest::Client,
+}
+
+impl HttpSink {
+    /// Creates a sink posting to `url`.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl SpendSink for HttpSink {
+    async fn report(&self, snapshot: SpendSnapshot) {
+        let result = self
+            .client
+            .post(&self.url)
+            .timeout(HTTP_SINK_TIMEOUT)
+            .json(&snapshot)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status);
+        if let Err(e) = result {
+            warn!(url = %self.url, error = %e, "Failed to send spend report");
+        }
+    }
+}
+
+/// Cumulative counters a reporter takes the difference of.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SpendTotals {
+    pub(crate) requests: u64,
+    pub(crate) failed_requests: u64,
+    pub(crate) payments: u64,
+    pub(crate) paid_by_network: BTreeMap<String, u128>,
+    pub(crate) cache_hits: u64,
+    pub(crate) cache_misses: u64,
+}
+
+/// Turns cumulative totals into snapshots of the activity since the previous one.
+#[derive(Debug)]
+pub(crate) struct SpendReporter {
+    /// Totals at the end of the previous period, and when it ended
+    previous: Mutex<(SpendTotals, DateTime<Utc>)>,
+}
+
+impl SpendReporter {
+    /// Creates a reporter whose first period starts now from `totals`.
+    pub(crate) fn new(totals: SpendTotals) -> Self {
+        Self {
+            previous: Mutex::new((totals, Utc::now())),
+        }
+    }
+
+    /// Returns the activity between the previous call and `totals`, starting a new period.
+    pub(crate) fn snapshot(&self, totals: SpendTotals) -> SpendSnapshot {
+        let now = Utc::now();
+        let mut previous = self.previous.lock();
+        let (before, period_start) = &*previous;
+
+        let paid_by_network = totals
+            .paid_by_network
+            .iter()
+            .filter_map(|(network, total)| {
+                let paid = total.saturating_sub(before.paid_by_network.get(network).copied().unwrap_or(0));
+                (paid > 0).then(|| (network.clone(), paid.to_string()))
+            })
+            .collect();
+        let cache_hits = totals.cache_hits.saturating_sub(before.cache_hits);
+        let cache_misses = totals.cache_misses.saturating_sub(before.cache_misses);
+        let lookups = cache_hits + cache_misses;
+
+        let snapshot = SpendSnapshot {
+            period_start: *period_start,
+            period_end: now,
+            requests: totals.requests.saturating_sub(before.requests),
+            failed_requests: totals.failed_requests.saturating_sub(before.failed_requests),
+            payments: totals.payments.saturating_sub(before.payments),
+            paid_by_network,
+            cache_hits,
+            cache_misses,
+            cache_hit_ratio: if lookups == 0 { 0.0 } else { cache_hits as f64 / lookups as f64 },
+        };
+        *previous = (totals, now);
+        snapshot
+    }
+}
@@ -0,0 +1,352 @@
+//! Loading private keys from a secret store.
+//!
+//! Instead of putting the private key in the environment or a file, set a
+//! [`SecretProvider`] with [`ConfigBuilder::secret_store`] and the key's
+//! path with [`ConfigBuilder::private_key_secret_path`]; [`Client::new`]
+//! fetches the key before anything signs with it. Secrets are returned in
+//! [`Zeroizing`] buffers, cleared when dropped.
+//!
+//! Paths of the bundled providers may end with `#field` to select a field
+//! of a secret holding several key-value pairs; without it the secret must
+//! hold a single value.
+//!
+//! ```rust,no_run
+//! # #[cfg(feature = "vault")]
+//! # async fn example() -> v402_client::Result<()> {
+//! use v402_client::{secrets::HashicorpVault, Client, Config};
+//!
+//! let config = Config::builder()
+//!     .secret_store(HashicorpVault::new("https://vault.internal:8200", std::env::var("VAULT_TOKEN").unwrap()))
+//!     .private_key_secret_path("secret/data/payments#private_key")
+//!     .build()?;
+//! let client = Client::new(config).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`ConfigBuilder::secret_store`]: crate::ConfigBuilder::secret_store
+//! [`ConfigBuilder::private_key_secret_path`]: crate::ConfigBuilder::private_key_secret_path
+//! [`Client::new`]: crate::Client::new
+
+use crate::error::Result;
+use async_trait::async_trait;
+use std::{fmt, sync::Arc};
+
+#[cfg(any(feature = "aws", feature = "vault"))]
+use crate::error::Error;
+
+pub use secrecy::zeroize::Zeroizing;
+
+#[cfg(feature = "aws")]
+pub use aws::AwsSecretsManager;
+#[cfg(feature = "vault")]
+pub use vault::HashicorpVault;
+
+/// Source of secrets such as private keys.
+#[async_trait]
+pub trait SecretProvider: Send + Sync + fmt::Debug {
+    /// Returns the secret stored at `path`.
+    ///
+    /// Failures to reach the store or read the secret should be reported
+    /// as [`Error::Auth`](crate::Error::Auth).
+    async fn get_secret(&self, path: &str) -> Result<Zeroizing<String>>;
+}
+
+/// Secret provider stored in a [`Config`](crate::Config), set with
+/// [`ConfigBuilder::secret_store`](crate::ConfigBuilder::secret_store).
+#[derive(Clone)]
+pub struct SecretStore(Arc<dyn SecretProvider>);
+
+impl SecretStore {
+    /// Wraps a provider.
+    pub fn new(provider: Arc<dyn SecretProvider>) -> Self {
+        Self(provider)
+    }
+
+    /// Returns the secret stored at `path`.
+    pub async fn get_secret(&self, path: &str) -> Result<Zeroizing<String>> {
+        self.0.get_secret(path).await
+    }
+}
+
+impl fmt::Debug for SecretStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("SecretStore").field(&self.0).finish()
+    }
+}
+
+/// Splits a `path#field` secret path.
+#[cfg(any(feature = "aws", feature = "vault"))]
+fn split_field(path: &str) -> (&str, Option<&str>) {
+    match path.rsplit_once('#') {
+        Some((path, field)) => (path, Some(field)),
+        None => (path, None),
+    }
+}
+
+/// Selects `field` of a secret holding key-value pairs, or its only value.
+#[cfg(any(feature = "aws", feature = "vault"))]
+fn select_field(
+    values: &serde_json::Map<String, serde_json::Value>,
+    field: Option<&str>,
+    path: &str,
+) -> Result<Zeroizing<String>> {
+    let value = match field {
+        Some(field) => values
+            .get(field)
+            .ok_or_else(|| Error::Auth(format!("Secret {} has no field {}", path, field).into()))?,
+        None if values.len() == 1 => values.values().next().expect("one value"),
+        None => {
+            return Err(Error::Auth(format!(
+                "Secret {} holds {} values; select one with {}#<field>",
+                path,
+                values.len(),
+                path
+            ).into()))
+        }
+    };
+    value
+        .as_str()
+        .map(|value| Zeroizing::new(value.to_string()))
+        .ok_or_else(|| Error::Auth(format!("Secret {} is not a string", path).into()))
+}
+
+#[cfg(feature = "aws")]
+mod aws {
+    use super::{select_field, split_field, SecretProvider, Zeroizing};
+    use crate::error::{Error, Result};
+    use async_trait::async_trait;
+    use chrono::Utc;
+    use hmac::{Hmac, Mac};
+    use serde_json::{json, Value};
+    use sha2::{Digest, Sha256};
+
+    /// Service name of Secrets Manager in request signatures.
+    const SERVICE: &str = "secretsmanager";
+
+    /// AWS Secrets Manager, read with the `GetSecretValue` API.
+    ///
+    /// Credentials are read from the `AWS_ACCESS_KEY_ID`,
+    /// `AWS_SECRET_ACCESS_KEY` and optional `AWS_SESSION_TOKEN` environment
+    /// variables on every request. The path is the secret's name or ARN; a
+    /// secret stored as JSON key-value pairs needs a `#field`.
+    #[derive(Debug, Clone)]
+    pub struct AwsSecretsManager {
+        /// Region of the secrets, e.g. `us-east-1`
+        pub region: String,
+
+        /// Endpoint used instead of `https://secretsmanager.{region}.amazonaws.com`,
+        /// e.g. a VPC endpoint
+        pub endpoint: Option<String>,
+    }
+
+    impl AwsSecretsManager {
+        /// Reads secrets from `region`.
+        pub fn new<R: Into<String>>(region: R) -> Self {
+            Self {
+                region: region.into(),
+                endpoint: None,
+            }
+        }
+
+        /// Sends requests to `endpoint` instead of the regional endpoint.
+        pub fn endpoint<E: Into<String>>(mut self, endpoint: E) -> Self {
+            self.endpoint = Some(endpoint.into());
+            self
+        }
+    }
+
+    #[async_trait]
+    impl SecretProvider for AwsSecretsManager {
+        async fn get_secret(&self, path: &str) -> Result<Zeroizing<String>> {
+            let (secret_id, field) = split_field(path);
+            let env = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+            let (access_key, secret_key) = match (env("AWS_ACCESS_KEY_ID"), env("AWS_SECRET_ACCESS_KEY")) {
+                (Some(access_key), Some(secret_key)) => (access_key, Zeroizing::new(secret_key)),
+                _ => {
+                    return Err(Error::Auth(
+                        "AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY must be set to read AWS secrets".to_string().into(),
+                    ))
+                }
+            };
+            let session_token = env("AWS_SESSION_TOKEN");
+
+            let endpoint = self
+                .endpoint
+                .clone()
+                .unwrap_or_else(|| format!("https://{}.{}.amazonaws.com", SERVICE, self.region));
+            let host = url::Url::parse(&endpoint)
+                .ok()
+                .filter(|url| url.has_host())
+                .map(|url| url[url::Position::BeforeHost..url::Position::AfterPort].to_string())
+                .ok_or_else(|| Error::Config(format!("Invalid Secrets Manager endpoint {}", endpoint).into()))?;
+            let body = json!({ "SecretId": secret_id }).to_string();
+
+            let now = Utc::now();
+            let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+            let mut headers = vec![
+                ("content-type", "application/x-amz-json-1.1".to_string()),
+                ("host", host),
+                ("x-amz-date", amz_date.clone()),
+                ("x-amz-target", "secretsmanager.GetSecretValue".to_string()),
+            ];
+            if let Some(token) = session_token {
+                headers.push(("x-amz-security-token", token));
+            }
+            headers.sort();
+
+            let canonical_headers: String = headers.iter().map(|(name, value)| format!("{}:{}\n", name, value)).collect();
+            let signed_headers = headers.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(";");
+            let canonical_request = format!(
+                "POST\n/\n\n{}\n{}\n{}",
+                canonical_headers,
+                signed_headers,
+                hex::encode(Sha256::digest(body.as_bytes()))
+            );
+            let scope = format!("{}/{}/{}/aws4_request", &amz_date[..8], self.region, SERVICE);
+            let signature = sigv4_signature(&secret_key, &amz_date, &self.region, SERVICE, &canonical_request);
+
+            let mut request = reqwest::Client::new().post(&endpoint).body(body).header(
+                "authorization",
+                format!(
+                    "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                    access_key, scope, signed_headers, signature
+                ),
+            );
+            for (name, value) in &headers {
+                if *name != "host" {
+                    request = request.header(*name, value);
+                }
+            }
+
+            let response = request
+                .send()
+                .await
+                .map_err(|e| Error::Auth(format!("Failed to reach AWS Secrets Manager: {}", e).into()))?;
+            let status = response.status();
+            let response: Value = response
+                .json()
+                .await
+                .map_err(|e| Error::Auth(format!("Invalid response from AWS Secrets Manager: {}", e).into()))?;
+            if !status.is_success() {
+                let kind = response.get("__type").and_then(Value::as_str).unwrap_or("error");
+                let message = response
+                    .get("message")
+                    .or_else(|| response.get("Message"))
+                    .and_then(Value::as_str)
+                    .unwrap_or_default();
+                return Err(Error::Auth(format!("Failed to read AWS secret {}: {} {}", secret_id, kind, message).into()));
+            }
+
+            let secret = response
+                .get("SecretString")
+                .and_then(Value::as_str)
+                .ok_or_else(|| Error::Auth(format!("AWS secret {} has no string value", secret_id).into()))?;
+            match field {
+                None => Ok(Zeroizing::new(secret.to_string())),
+                Some(_) => {
+                    let values: serde_json::Map<String, Value> = serde_json::from_str(secret)
+                        .map_err(|_| Error::Auth(format!("AWS secret {} is not a JSON object", secret_id).into()))?;
+                    select_field(&values, field, secret_id)
+                }
+            }
+        }
+    }
+
+    /// Computes an AWS Signature Version 4 over `canonical_request`.
+    pub(super) fn sigv4_signature(
+        secret_key: &str,
+        amz_date: &str,
+        region: &str,
+        service: &str,
+        canonical_request: &str,
+    ) -> String {
+        let hmac = |key: &[u8], data: &str| {
+            let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+            mac.update(data.as_bytes());
+            mac.finalize().into_bytes().to_vec()
+        };
+
+        let date = &amz_date[..8];
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}/{}/{}/aws4_request\n{}",
+            amz_date,
+            date,
+            region,
+            service,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+        let key = Zeroizing::new(hmac(format!("AWS4{}", secret_key).as_bytes(), date));
+        let key = Zeroizing::new(hmac(&key, region));
+        let key = Zeroizing::new(hmac(&key, service));
+        let key = Zeroizing::new(hmac(&key, "aws4_request"));
+        hex::encode(hmac(&key, &string_to_sign))
+    }
+}
+
+#[cfg(feature = "vault")]
+mod vault {
+    use super::{select_field, split_field, SecretProvider, Zeroizing};
+    use crate::error::{Error, Result};
+    use async_trait::async_trait;
+    use secrecy::{ExposeSecret, SecretString};
+    use serde_json::Value;
+
+    /// HashiCorp Vault, read from a KV secrets engine.
+    ///
+    /// The path is the API path below `/v1/`, e.g. `secret/data/payments`
+    /// for the KV version 2 engine mounted at `secret`.
+    #[derive(Debug, Clone)]
+    pub struct HashicorpVault {
+        /// Vault address, e.g. `https://vault.internal:8200`
+        pub addr: String,
+
+        /// Token sent as `X-Vault-Token`
+        pub token: SecretString,
+    }
+
+    impl HashicorpVault {
+        /// Reads secrets from the Vault at `addr` with `token`.
+        pub fn new<A: Into<String>, T: Into<String>>(addr: A, token: T) -> Self {
+            Self {
+                addr: addr.into(),
+                token: SecretString::new(token.into()),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl SecretProvider for HashicorpVault {
+        async fn get_secret(&self, path: &str) -> Result<Zeroizing<String>> {
+            let (path, field) = split_field(path);
+            let url = format!("{}/v1/{}", self.addr.trim_end_matches('/'), path.trim_start_matches('/'));
+
+            let response = reqwest::Client::new()
+                .get(&url)
+                .header("X-Vault-Token", self.token.expose_secret())
+                .send()
+                .await
+                .map_err(|e| Error::Auth(format!("Failed to reach Vault at {}: {}", self.addr, e).into()))?;
+            let status = response.status();
+            let response: Value = response
+                .json()
+                .await
+                .map_err(|e| Error::Auth(format!("Invalid response from Vault: {}", e).into()))?;
+            if !status.is_success() {
+                let errors = response.get("errors").map(Value::to_string).unwrap_or_default();
+                return Err(Error::Auth(format!("Failed to read Vault secret {}: {} {}", path, status, errors).into()));
+            }
+
+            // KV version 2 nests the values under `data.data`, version 1 under `data`
+            let data = response.get("data");
+            let values = match data.and_then(|data| data.get("data")) {
+                Some(values) if data.and_then(|data| data.get("metadata")).is_some() => values,
+                _ => data.unwrap_or(&Value::Null),
+            };
+            let values = values
+                .as_object()
+                .ok_or_else(|| Error::Auth(format!("Vault secret {} holds no values", path).into()))?;
+            select_field(values, field, path)
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! Supervision of the client's background tasks.
+
+use crate::types::TaskInfo;
+use futures::FutureExt;
+use parking_lot::Mutex;
+use std::{any::Any, collections::BTreeMap, future::Future, panic::AssertUnwindSafe, sync::Arc, time::Duration};
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, warn};
+
+/// Delay before the first restart of a panicked task, doubled for each further restart.
+const INITIAL_RESTART_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Upper bound on the delay between restarts.
+const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(60);
+
+/// What happens when a background task panics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Restart {
+    /// The task stays stopped
+    Never,
+    /// The task is started again after an exponential backoff
+    WithBackoff,
+}
+
+/// Owns the background tasks of a client.
+///
+/// Every task is spawned into one [`JoinSet`] under a name and receives a
+/// token that is cancelled on [`shutdown`](Self::shutdown), which then waits
+/// for the tasks to finish. Panics are caught and logged with the task name.
+#[derive(Debug, Default)]
+pub(crate) struct TaskManager {
+    tasks: Mutex<JoinSet<()>>,
+    shutdown: CancellationToken,
+    registry: Arc<Mutex<BTreeMap<String, TaskInfo>>>,
+}
+
+impl TaskManager {
+    /// Creates a manager without tasks.
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Spawns the task built by `task`, building it again after a panic if
+    /// `restart` allows.
+    ///
+    /// Long-running tasks should return once the token they are given is
+    /// cancelled. Does nothing after shutdown.
+    pub(crate) fn spawn<F, Fut>(&self, name: &str, restart: Restart, mut task: F)
+    where
+        F: FnMut(CancellationToken) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        if self.shutdown.is_cancelled() {
+            return;
+        }
+
+        let running = Running::start(&self.registry, name);
+        let shutdown = self.shutdown.clone();
+        let mut tasks = self.tasks.lock();
+        // Collect finished tasks so the set only holds running ones
+        while let Some(Some(_)) = tasks.join_next().now_or_never() {}
+        tasks.spawn(async move {
+            let mut backoff = INITIAL_RESTART_BACKOFF;
+            loop {
+                let Err(panic) = AssertUnwindSafe(task(shutdown.clone())).catch_unwind().await else {
+                    break;
+                };
+                error!(task = %running.name, panic = %panic_message(&*panic), "Background task panicked");
+                running.update(|info| info.panics += 1);
+                if restart == Restart::Never {
+                    break;
+                }
+
+                warn!(task = %running.name, delay_ms = backoff.as_millis() as u64, "Restarting background task");
+                tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    _ = tokio::time::sleep(backoff) => {}
+                }
+                backoff = (backoff * 2).min(MAX_RESTART_BACKOFF);
+                running.update(|info| info.restarts += 1);
+            }
+        });
+    }
+
+    /// Spawns a task that runs once and is not restarted.
+    pub(crate) fn spawn_once<Fut>(&self, name: &str, task: Fut)
+    where
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let mut task = Some(task);
+        self.spawn(name, Restart::Never, move |_| {
+            let task = task.take();
+            async move {
+                if let Some(task) = task {
+                    task.await;
+                }
+            }
+        });
+    }
+
+    /// Returns every task spawned so far, by name.
+    pub(crate) fn tasks(&self) -> Vec<TaskInfo> {
+        self.registry.lock().values().cloned().collect()
+    }
+
+    /// Cancels the token given to the tasks and waits up to `timeout` for
+    /// them to finish, aborting those still running.
+    pub(crate) async fn shutdown(&self, timeout: Duration) {
+        self.shutdown.cancel();
+        let mut tasks = std::mem::take(&mut *self.tasks.lock());
+        let finished = tokio::time::timeout(timeout, async {
+            while tasks.join_next().await.is_some() {}
+        })
+        .await;
+
+        if finished.is_err() {
+            warn!(remaining = tasks.len(), "Background tasks did not stop in time, aborting");
+            tasks.shutdown().await;
+        }
+    }
+}
+
+/// Counts a task as running for as long as it is alive, aborted or not.
+struct Running {
+    registry: Arc<Mutex<BTreeMap<String, TaskInfo>>>,
+    name: String,
+}
+
+impl Running {
+    fn start(registry: &Arc<Mutex<BTreeMap<String, TaskInfo>>>, name: &str) -> Self {
+        let running = Self {
+            registry: registry.clone(),
+            name: name.to_string(),
+        };
+        running.update(|info| info.running += 1);
+        running
+    }
+
+    /// Updates the task's entry in the registry.
+    fn update(&self, f: impl FnOnce(&mut TaskInfo)) {
+        let mut registry = self.registry.lock();
+        let info = registry.entry(self.name.clone()).or_insert_with(|| TaskInfo {
+            name: self.name.clone(),
+            ..TaskInfo::default()
+        });
+        f(info);
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.update(|info| info.running = info.running.saturating_sub(1));
+    }
+}
+
+/// Extracts the message of a panic payload.
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
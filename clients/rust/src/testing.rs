@@ -0,0 +1,50 @@
+//! Helpers for testing code that uses the client against a mock server.
+//!
+//! A mock v402 server answers unpaid requests with a 402 body listing its
+//! requirements, reads the authorization from the `X-PAYMENT` header of the
+//! paid retry, and reports settlement in `X-PAYMENT-RESPONSE`. These helpers
+//! produce and read those values in the format the client uses.
+//!
+//! ```rust
+//! use v402_client::{payment::{PaymentRequirements, Settlement}, testing};
+//!
+//! let requirements: PaymentRequirements = serde_json::from_str(r#"{
+//!     "scheme": "exact",
+//!     "network": "base",
+//!     "maxAmountRequired": "1000",
+//!     "resource": "https://api.example.com/report",
+//!     "payTo": "0x1111111111111111111111111111111111111111",
+//!     "asset": "0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913"
+//! }"#)?;
+//! let body = testing::payment_required_body(&[requirements]);
+//! assert!(body.contains("\"x402Version\":1"));
+//!
+//! let settlement = Settlement {
+//!     success: true,
+//!     transaction_hash: Some("0xabc".to_string()),
+//!     ..Settlement::default()
+//! };
+//! let header = testing::settlement_header(&settlement);
+//! assert!(testing::payment_authorization("not a payment").is_none());
+//! # Ok::<(), serde_json::Error>(())
+//! ```
+
+use crate::payment::{Authorization, PaymentRequirements, Settlement};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use serde_json::json;
+
+/// Returns the body of a 402 response offering `accepts`.
+pub fn payment_required_body(accepts: &[PaymentRequirements]) -> String {
+    json!({ "x402Version": 1, "accepts": accepts }).to_string()
+}
+
+/// Returns the value of the `X-PAYMENT-RESPONSE` header reporting `settlement`.
+pub fn settlement_header(settlement: &Settlement) -> String {
+    BASE64.encode(serde_json::to_vec(settlement).unwrap_or_default())
+}
+
+/// Returns the authorization signed in an `X-PAYMENT` header, or `None` if
+/// the header is not a payment made by the client.
+pub fn payment_authorization(payment_header: &str) -> Option<Authorization> {
+    crate::payment::decode_authorization(payment_header)
+}
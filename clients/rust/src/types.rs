@@ -0,0 +1,1092 @@
+//! Core data types returned by the v402 client.
+
+use crate::{
+    chains::{format_units, truncate_address, NetworkId},
+    error::Result,
+    payment::{PaymentHints, PaymentRequirements, Receipt},
+};
+use bytes::Bytes;
+use chrono::{DateTime, Datelike, Duration as ChronoDuration, NaiveDate, NaiveTime, TimeZone, Timelike, Utc};
+use http::{header::HeaderName, HeaderMap, HeaderValue, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    time::Duration,
+};
+
+/// Response returned for every request made through the client.
+///
+/// Besides the usual HTTP status, headers and body, it records whether a
+/// payment was made to obtain the content and the settlement details.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaymentResponse {
+    /// Final URL of the response
+    pub url: String,
+
+    /// HTTP status code
+    pub status: u16,
+
+    /// Response headers
+    pub headers: HashMap<String, String>,
+
+    /// Response body
+    pub body: Bytes,
+
+    /// Whether a payment was made for this response
+    pub payment_made: bool,
+
+    /// Amount paid in the asset's smallest unit
+    pub payment_amount: Option<String>,
+
+    /// Address of the asset paid with
+    #[serde(default)]
+    pub payment_asset: Option<String>,
+
+    /// Network the payment was made on
+    pub network: Option<NetworkId>,
+
+    /// Settlement transaction hash
+    pub transaction_hash: Option<String>,
+
+    /// Address that paid
+    pub payer: Option<String>,
+
+    /// Correlation ID sent with the request
+    #[serde(default)]
+    pub request_id: String,
+
+    /// ID of the batch the request belonged to
+    #[serde(default)]
+    pub batch_id: Option<String>,
+
+    /// Time the response was received
+    pub timestamp: DateTime<Utc>,
+
+    /// Time spent in each phase of the payment, if one was made
+    #[serde(default)]
+    pub payment_timing: Option<PaymentTiming>,
+
+    /// Whether the server answered a conditional request with 304 Not Modified
+    #[serde(default)]
+    pub not_modified: bool,
+
+    /// Requirements parsed from an unpaid 402 response, with
+    /// [`PaymentRequiredBehavior::ReturnParsed`](crate::PaymentRequiredBehavior::ReturnParsed)
+    #[serde(default)]
+    pub requirements: Option<PaymentRequirements>,
+
+    /// Whether the payment was only simulated on a
+    /// [dry-run](crate::ChainConfig::dry_run) chain; the request itself was not sent
+    #[serde(default)]
+    pub dry_run: bool,
+
+    /// Hex-encoded SHA-256 hash of the body, set on successful responses
+    /// with [`CacheConfig::content_dedup`](crate::config::CacheConfig::content_dedup)
+    #[serde(default)]
+    pub content_hash: Option<String>,
+
+    /// Prices the server advertised for resources, read from the headers in
+    /// [`Config::payment_hint_headers`](crate::Config::payment_hint_headers)
+    #[serde(default)]
+    pub payment_hints: Option<PaymentHints>,
+}
+
+impl PaymentResponse {
+    /// Returns a header value, matching the name case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns `true` if the status code is in the 2xx range.
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// Returns the body decoded as UTF-8 text.
+    pub async fn text(&self) -> Result<String> {
+        Ok(String::from_utf8_lossy(&self.body).into_owned())
+    }
+
+    /// Deserializes the body as JSON.
+    pub async fn json<T: serde::de::DeserializeOwned>(&self) -> Result<T> {
+        Ok(serde_json::from_slice(&self.body)?)
+    }
+
+    /// Returns the response's cache validators, strongest first, to persist
+    /// for the next [`get_if_modified`](crate::Client::get_if_modified).
+    pub fn validators(&self) -> Vec<Validator> {
+        let etag = self.header("etag").map(|value| Validator::ETag(value.to_string()));
+        let last_modified = self
+            .header("last-modified")
+            .map(|value| Validator::LastModified(value.to_string()));
+        etag.into_iter().chain(last_modified).collect()
+    }
+
+    /// Splits the response into its status, headers and body, as taken by
+    /// `Response::from_parts` of HTTP frameworks built on the `http` crate.
+    ///
+    /// Hop-by-hop headers such as `Connection` and `Transfer-Encoding`
+    /// describe the connection to the server, not the content, and are left
+    /// out, as are headers that are not valid HTTP.
+    ///
+    /// ```rust
+    /// # fn example(response: v402_client::PaymentResponse) {
+    /// let (status, headers, body) = response.as_parts();
+    /// println!("{status}: {} headers, {} bytes", headers.len(), body.len());
+    /// # }
+    /// ```
+    pub fn as_parts(&self) -> (StatusCode, HeaderMap, Bytes) {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::BAD_GATEWAY);
+        let headers = self
+            .headers
+            .iter()
+            .filter(|(name, _)| !HOP_BY_HOP_HEADERS.iter().any(|hop| name.eq_ignore_ascii_case(hop)))
+            .filter_map(|(name, value)| {
+                Some((HeaderName::from_bytes(name.as_bytes()).ok()?, HeaderValue::from_str(value).ok()?))
+            })
+            .collect();
+        (status, headers, self.body.clone())
+    }
+
+    /// Converts the response to an [`http::Response`], with the headers of
+    /// [`as_parts`](Self::as_parts).
+    pub fn to_http_response(&self) -> http::Response<Bytes> {
+        let (status, headers, body) = self.as_parts();
+        let mut response = http::Response::new(body);
+        *response.status_mut() = status;
+        *response.headers_mut() = headers;
+        response
+    }
+}
+
+/// Headers describing a single connection, not passed on by [`PaymentResponse::as_parts`].
+const HOP_BY_HOP_HEADERS: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Responds with the status, headers and body of
+/// [`as_parts`](PaymentResponse::as_parts), to return a paid response from
+/// an axum handler as it is.
+///
+/// axum 0.7 is built on version 1 of the `http` crate while the rest of the
+/// client uses version 0.2, so the parts are converted one by one.
+#[cfg(feature = "axum")]
+impl axum::response::IntoResponse for PaymentResponse {
+    fn into_response(self) -> axum::response::Response {
+        let (status, headers, body) = self.as_parts();
+        let mut response = axum::response::Response::new(axum::body::Body::from(body));
+        *response.status_mut() =
+            http1::StatusCode::from_u16(status.as_u16()).unwrap_or(http1::StatusCode::BAD_GATEWAY);
+        let response_headers = response.headers_mut();
+        for (name, value) in &headers {
+            if let (Ok(name), Ok(value)) = (
+                http1::HeaderName::from_bytes(name.as_str().as_bytes()),
+                http1::HeaderValue::from_bytes(value.as_bytes()),
+            ) {
+                response_headers.append(name, value);
+            }
+        }
+        response
+    }
+}
+
+/// Cache validator of a previously fetched response, for conditional requests.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Validator {
+    /// `ETag` value, sent as `If-None-Match`
+    ETag(String),
+
+    /// `Last-Modified` value, sent as `If-Modified-Since`
+    LastModified(String),
+}
+
+impl Validator {
+    /// Returns the conditional request header name and value carrying this validator.
+    pub fn header(&self) -> (&'static str, &str) {
+        match self {
+            Self::ETag(etag) => ("If-None-Match", etag),
+            Self::LastModified(date) => ("If-Modified-Since", date),
+        }
+    }
+}
+
+/// Time spent in each phase of a payment.
+///
+/// Phases that ran more than once, e.g. signing again after a rate-limited
+/// retry, hold their cumulative duration. Phases that did not run are zero.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct PaymentTiming {
+    /// Parsing the payment requirements from the 402 response
+    pub parse_requirements: Duration,
+
+    /// Resolving the paying account and nonce on the requirement's network
+    pub select_chain: Duration,
+
+    /// Checking the amount against the configured spending limits
+    pub check_balance: Duration,
+
+    /// Signing the payment authorization
+    pub sign_payment: Duration,
+
+    /// Sending the request with the payment attached, including retries
+    pub paid_request: Duration,
+
+    /// Decoding the settlement response
+    pub process_settlement: Duration,
+}
+
+impl PaymentTiming {
+    /// Names of the phases, in the order they run.
+    pub const PHASES: [&'static str; 6] = [
+        "parse_requirements",
+        "select_chain",
+        "check_balance",
+        "sign_payment",
+        "paid_request",
+        "process_settlement",
+    ];
+
+    /// Returns each phase name with its duration, in the order they run.
+    pub fn phases(&self) -> [(&'static str, Duration); 6] {
+        let [parse, select, check, sign, request, settle] = Self::PHASES;
+        [
+            (parse, self.parse_requirements),
+            (select, self.select_chain),
+            (check, self.check_balance),
+            (sign, self.sign_payment),
+            (request, self.paid_request),
+            (settle, self.process_settlement),
+        ]
+    }
+
+    /// Total time spent across all phases.
+    pub fn total(&self) -> Duration {
+        self.phases().iter().map(|(_, duration)| *duration).sum()
+    }
+}
+
+/// Result of probing a URL with [`Client::head_check`](crate::Client::head_check).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaymentCheckResult {
+    /// Whether the server answered `402 Payment Required`
+    pub requires_payment: bool,
+
+    /// Advertised payment requirements, if payment is required and the
+    /// response advertised them
+    pub requirements: Option<PaymentRequirements>,
+
+    /// Content type of the resource
+    pub content_type: Option<String>,
+
+    /// Size of the resource in bytes
+    pub content_length: Option<u64>,
+}
+
+/// Results of a batch created with [`Client::batch`](crate::Client::batch).
+#[derive(Debug)]
+pub struct BatchResponse {
+    /// Result of each request, in the order of the input URLs
+    pub results: Vec<Result<PaymentResponse>>,
+
+    /// Totals over the whole batch
+    pub summary: BatchSummary,
+}
+
+/// Totals of a batch run.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BatchSummary {
+    /// Requests that were sent
+    pub attempted: u64,
+
+    /// Requests that completed with a response
+    pub succeeded: u64,
+
+    /// Requests that were sent but failed
+    pub failed: u64,
+
+    /// Requests never sent because the deadline passed first
+    pub skipped: u64,
+
+    /// Total amount paid, in the assets' smallest units
+    pub total_paid: String,
+
+    /// Wall-clock time taken by the batch
+    pub elapsed: Duration,
+}
+
+/// Outcome of [`Client::prefetch`](crate::Client::prefetch).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrefetchReport {
+    /// URLs fetched and cached
+    pub fetched: usize,
+
+    /// URLs already cached, not fetched again
+    pub from_cache: usize,
+
+    /// URLs that could not be fetched
+    pub failed: usize,
+
+    /// Amount paid per asset in its smallest unit, keyed by
+    /// `"{network}:{asset}"`, as amounts in different assets cannot be added
+    pub total_paid: BTreeMap<String, String>,
+
+    /// Wall-clock time taken by the prefetch
+    pub duration: Duration,
+}
+
+/// Scheduling priority of a request waiting for a concurrency slot.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    /// Interactive requests, served first
+    High,
+    /// Default priority
+    #[default]
+    Normal,
+    /// Background work such as batches
+    Low,
+}
+
+impl Priority {
+    /// Position of the priority's queue, highest first.
+    pub(crate) fn index(self) -> usize {
+        match self {
+            Priority::High => 0,
+            Priority::Normal => 1,
+            Priority::Low => 2,
+        }
+    }
+}
+
+/// Per-request options that extend the client's defaults.
+///
+/// # Example
+///
+/// ```rust
+/// use v402_client::types::RequestOptions;
+///
+/// let options = RequestOptions::new()
+///     .payment_metadata("order_id", "ord_1234")
+///     .payment_metadata("customer_id", "cus_42");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct RequestOptions {
+    /// Key-value metadata sent with the payment in the `X-PAYMENT-METADATA` header
+    pub payment_metadata: HashMap<String, String>,
+
+    /// Additional headers sent with the request
+    pub headers: HashMap<String, String>,
+
+    /// Skip the response cache for this request
+    pub bypass_cache: bool,
+
+    /// Caller-supplied correlation ID; a UUID v7 is generated when unset
+    pub request_id: Option<String>,
+
+    /// ID of the batch the request belongs to
+    pub batch_id: Option<String>,
+
+    /// Facilitator overriding the configured one for this request
+    pub facilitator_url: Option<String>,
+
+    /// Priority when waiting for a concurrency slot
+    pub priority: Priority,
+
+    /// Send the URL's path exactly as given instead of normalizing it
+    pub preserve_url: bool,
+
+    /// Make the request conditional on the content having changed since this validator
+    pub if_modified: Option<Validator>,
+}
+
+impl RequestOptions {
+    /// Creates empty request options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attaches a metadata entry to any payment made for this request.
+    pub fn payment_metadata(mut self, key: &str, value: &str) -> Self {
+        self.payment_metadata.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// Adds a header to the request.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Skips the response cache for this request.
+    pub fn bypass_cache(mut self) -> Self {
+        self.bypass_cache = true;
+        self
+    }
+
+    /// Uses the given correlation ID instead of generating one.
+    pub fn request_id(mut self, id: &str) -> Self {
+        self.request_id = Some(id.to_string());
+        self
+    }
+
+    /// Overrides the facilitator for any payment made for this request.
+    pub fn facilitator_url(mut self, url: &str) -> Self {
+        self.facilitator_url = Some(url.to_string());
+        self
+    }
+
+    /// Sets the priority used when waiting for a concurrency slot.
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Sends the URL's path exactly as given, e.g. keeping a trailing slash.
+    ///
+    /// Only the host and port are normalized for the cache key, see
+    /// [`normalize_url`](crate::normalize_url).
+    pub fn preserve_url(mut self) -> Self {
+        self.preserve_url = true;
+        self
+    }
+
+    /// Makes the request conditional, see [`Client::get_if_modified`](crate::Client::get_if_modified).
+    pub fn if_modified(mut self, validator: Validator) -> Self {
+        self.if_modified = Some(validator);
+        self
+    }
+
+    /// Marks the request as part of a batch.
+    pub fn batch_id(mut self, id: &str) -> Self {
+        self.batch_id = Some(id.to_string());
+        self
+    }
+}
+
+/// Status of a recorded payment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PaymentStatus {
+    /// Submitted but not yet settled
+    Pending,
+    /// Settled on-chain
+    Confirmed,
+    /// Settlement failed
+    Failed,
+    /// Authorization expired before settlement
+    Expired,
+}
+
+impl PaymentStatus {
+    /// Returns the lowercase string form used in exports and logs.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PaymentStatus::Pending => "pending",
+            PaymentStatus::Confirmed => "confirmed",
+            PaymentStatus::Failed => "failed",
+            PaymentStatus::Expired => "expired",
+        }
+    }
+}
+
+impl std::fmt::Display for PaymentStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Record of a payment made by the client.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaymentHistory {
+    /// Version of the record's storage schema, see [`payment::schema`](crate::payment::schema)
+    #[serde(default = "default_schema_version")]
+    pub schema_version: u32,
+
+    /// Unique payment identifier
+    pub payment_id: String,
+
+    /// URL of the paid resource
+    pub url: String,
+
+    /// Amount paid in the asset's smallest unit
+    pub amount: String,
+
+    /// Asset (token contract address) used for the payment
+    pub asset: String,
+
+    /// Decimals of the asset, if known
+    pub asset_decimals: Option<u8>,
+
+    /// Symbol of the asset, if known
+    #[serde(default)]
+    pub asset_symbol: Option<String>,
+
+    /// USD value of the payment at the time it was made, if a price oracle is configured
+    pub usd_value: Option<f64>,
+
+    /// Settlement transaction hash
+    pub transaction_hash: Option<String>,
+
+    /// Network the payment was made on
+    pub network: NetworkId,
+
+    /// Address that paid
+    pub payer: Option<String>,
+
+    /// Address that received the payment
+    pub payee: String,
+
+    /// Time the payment was made
+    pub timestamp: DateTime<Utc>,
+
+    /// Payment status
+    pub status: PaymentStatus,
+
+    /// Description of the paid resource
+    pub description: String,
+
+    /// Metadata attached to the payment via `X-PAYMENT-METADATA`
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+
+    /// Correlation ID of the request that made the payment
+    #[serde(default)]
+    pub request_id: Option<String>,
+
+    /// ID of the batch the paying request belonged to
+    #[serde(default)]
+    pub batch_id: Option<String>,
+
+    /// Facilitator that handled settlement
+    #[serde(default)]
+    pub facilitator: Option<String>,
+
+    /// Time spent in each phase of the payment
+    #[serde(default)]
+    pub timing: Option<PaymentTiming>,
+
+    /// Signed proof of purchase, if the payment was settled
+    #[serde(default)]
+    pub receipt: Option<Receipt>,
+
+    /// Label of the wallet that paid
+    #[serde(default)]
+    pub wallet: Option<String>,
+
+    /// Whether the payment was simulated on a dry-run chain instead of settled
+    #[serde(default)]
+    pub dry_run: bool,
+
+    /// Nonce of the payment's authorization, used to look up its settlement
+    #[serde(default)]
+    pub authorization_nonce: Option<String>,
+}
+
+fn default_schema_version() -> u32 {
+    1
+}
+
+impl PaymentHistory {
+    /// Returns the block explorer page of the settlement transaction, if the
+    /// payment has one and its network has a built-in explorer, see
+    /// [`payment::chain_explorers`](crate::payment::chain_explorers).
+    ///
+    /// Explorers registered with a client are taken into account by
+    /// [`Client::explorer_url`](crate::Client::explorer_url).
+    ///
+    /// ```rust
+    /// use v402_client::PaymentHistory;
+    ///
+    /// let link = |network: &str| {
+    ///     let payment: PaymentHistory = serde_json::from_value(serde_json::json!({
+    ///         "payment_id": "pay_1",
+    ///         "url": "https://api.example.com/report",
+    ///         "amount": "1000",
+    ///         "asset": "0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913",
+    ///         "transaction_hash": "0xabc123",
+    ///         "network": network,
+    ///         "payee": "0x1111111111111111111111111111111111111111",
+    ///         "timestamp": "2024-05-01T12:00:00Z",
+    ///         "status": "confirmed",
+    ///         "description": "Report"
+    ///     })).unwrap();
+    ///     payment.chain_explorer_url().map(String::from)
+    /// };
+    ///
+    /// assert_eq!(link("ethereum").as_deref(), Some("https://etherscan.io/tx/0xabc123"));
+    /// assert_eq!(link("polygon").as_deref(), Some("https://polygonscan.com/tx/0xabc123"));
+    /// assert_eq!(link("base").as_deref(), Some("https://basescan.org/tx/0xabc123"));
+    /// assert_eq!(link("bsc").as_deref(), Some("https://bscscan.com/tx/0xabc123"));
+    /// assert_eq!(link("arbitrum").as_deref(), Some("https://arbiscan.io/tx/0xabc123"));
+    /// assert_eq!(link("sei"), None);
+    /// ```
+    pub fn chain_explorer_url(&self) -> Option<url::Url> {
+        let hash = self.transaction_hash.as_deref()?;
+        let prefix = crate::payment::chain_explorers::explorer_for(&self.network)?;
+        url::Url::parse(&format!("{}{}", prefix, hash)).ok()
+    }
+
+    /// Returns a copy safe to share outside the organisation.
+    ///
+    /// Strips the query string and fragment from the URL and drops the payer
+    /// address and any attached metadata.
+    pub fn redacted(&self) -> Self {
+        let url = match url::Url::parse(&self.url) {
+            Ok(mut url) => {
+                url.set_query(None);
+                url.set_fragment(None);
+                url.to_string()
+            }
+            Err(_) => self.url.split(['?', '#']).next().unwrap_or_default().to_string(),
+        };
+
+        Self {
+            url,
+            payer: None,
+            metadata: HashMap::new(),
+            receipt: None,
+            ..self.clone()
+        }
+    }
+
+    /// Returns the host of the paid URL, or the raw URL if it cannot be parsed.
+    pub fn domain(&self) -> String {
+        url::Url::parse(&self.url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_else(|| self.url.clone())
+    }
+
+    /// Returns the amount formatted with the asset's decimals, if known.
+    pub fn amount_decimal(&self) -> Option<String> {
+        Some(format_units(&self.amount, self.asset_decimals?))
+    }
+
+    /// Returns the amount for display, e.g. `1.5 USDC`.
+    ///
+    /// Payments in a token of unknown decimals are shown in raw units with a
+    /// shortened asset address, e.g. `1500000 units of 0x1234…cdef`.
+    pub fn display_amount(&self) -> String {
+        match (self.amount_decimal(), &self.asset_symbol) {
+            (Some(amount), Some(symbol)) => format!("{} {}", amount, symbol),
+            (Some(amount), None) => format!("{} of {}", amount, truncate_address(&self.asset)),
+            (None, _) => format!("{} units of {}", self.amount, truncate_address(&self.asset)),
+        }
+    }
+}
+
+/// Aggregated payment statistics.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PaymentStatistics {
+    /// Total number of payments
+    pub total_payments: u64,
+
+    /// Number of confirmed payments
+    pub successful_payments: u64,
+
+    /// Number of failed or expired payments
+    pub failed_payments: u64,
+
+    /// Sum of confirmed payment amounts
+    pub total_amount: String,
+
+    /// Average confirmed payment amount
+    pub average_amount: String,
+
+    /// Smallest confirmed payment amount
+    pub min_amount: String,
+
+    /// Largest confirmed payment amount
+    pub max_amount: String,
+
+    /// Number of distinct paid URLs
+    pub unique_resources: usize,
+
+    /// Number of distinct networks used
+    pub unique_networks: usize,
+
+    /// Timestamp of the earliest payment
+    pub time_period_start: Option<DateTime<Utc>>,
+
+    /// Timestamp of the latest payment
+    pub time_period_end: Option<DateTime<Utc>>,
+
+    /// Spend per wallet label
+    #[serde(default)]
+    pub by_wallet: BTreeMap<String, WalletStatistics>,
+
+    /// Confirmed payments behind [`time_series`](Self::time_series); not serialized
+    #[serde(skip)]
+    pub(crate) confirmed: Vec<ConfirmedPayment>,
+}
+
+impl PaymentStatistics {
+    /// Returns payment volume per `period`, oldest first, for charts.
+    ///
+    /// Only confirmed payments are counted, as in the totals; payments whose
+    /// amount is not a number are logged and left out, and totals saturate
+    /// at `u128::MAX`. Periods without payments between the first and the
+    /// last one are included with zero counts so the series has no gaps.
+    /// Computed from the history the statistics were taken from; empty for
+    /// deserialized statistics.
+    pub fn time_series(&self, period: PeriodType) -> Vec<PeriodStats> {
+        let mut buckets: BTreeMap<DateTime<Utc>, Vec<&ConfirmedPayment>> = BTreeMap::new();
+        for payment in &self.confirmed {
+            buckets.entry(period.start_of(payment.timestamp)).or_default().push(payment);
+        }
+        let (Some(&first), Some(&last)) = (buckets.keys().next(), buckets.keys().next_back()) else {
+            return Vec::new();
+        };
+
+        let mut series = Vec::new();
+        let mut period_start = first;
+        while period_start <= last {
+            let payments = buckets.get(&period_start).map(Vec::as_slice).unwrap_or_default();
+            let total = payments.iter().fold(0u128, |total, payment| total.saturating_add(payment.amount));
+            let payees: HashSet<&str> = payments.iter().map(|payment| payment.payee.as_str()).collect();
+            series.push(PeriodStats {
+                period_start,
+                payment_count: payments.len() as u64,
+                total_amount_wei: total,
+                unique_payees: payees.len(),
+                average_amount_wei: total.checked_div(payments.len() as u128).unwrap_or(0),
+            });
+            period_start = period.next(period_start);
+        }
+        series
+    }
+}
+
+/// Confirmed payment kept in [`PaymentStatistics`] for its time series.
+#[derive(Debug, Clone)]
+pub(crate) struct ConfirmedPayment {
+    pub(crate) timestamp: DateTime<Utc>,
+    pub(crate) amount: u128,
+    pub(crate) payee: String,
+}
+
+/// Length of the periods of [`PaymentStatistics::time_series`], in UTC.
+///
+/// ```rust
+/// use chrono::{TimeZone, Utc};
+/// use v402_client::PeriodType;
+///
+/// let paid = Utc.with_ymd_and_hms(2024, 3, 14, 15, 9, 26).unwrap();
+/// assert_eq!(PeriodType::Hour.start_of(paid), Utc.with_ymd_and_hms(2024, 3, 14, 15, 0, 0).unwrap());
+/// assert_eq!(PeriodType::Day.start_of(paid), Utc.with_ymd_and_hms(2024, 3, 14, 0, 0, 0).unwrap());
+/// assert_eq!(PeriodType::Week.start_of(paid), Utc.with_ymd_and_hms(2024, 3, 11, 0, 0, 0).unwrap());
+/// assert_eq!(PeriodType::Month.start_of(paid), Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap());
+///
+/// let december = Utc.with_ymd_and_hms(2023, 12, 1, 0, 0, 0).unwrap();
+/// assert_eq!(PeriodType::Month.next(december), Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PeriodType {
+    /// Hours, starting on the hour
+    Hour,
+    /// Days, starting at midnight
+    Day,
+    /// ISO weeks, starting on Monday at midnight
+    Week,
+    /// Calendar months, starting on the first at midnight
+    Month,
+}
+
+impl PeriodType {
+    /// Returns the start of the period containing `time`.
+    pub fn start_of(&self, time: DateTime<Utc>) -> DateTime<Utc> {
+        let date = time.date_naive();
+        let (date, hour) = match self {
+            PeriodType::Hour => (date, time.hour()),
+            PeriodType::Day => (date, 0),
+            PeriodType::Week => (date - ChronoDuration::days(date.weekday().num_days_from_monday().into()), 0),
+            PeriodType::Month => (date.with_day(1).unwrap_or(date), 0),
+        };
+        Utc.from_utc_datetime(&date.and_time(NaiveTime::MIN)) + ChronoDuration::hours(hour.into())
+    }
+
+    /// Returns the start of the period following the one starting at `start`.
+    pub fn next(&self, start: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            PeriodType::Hour => start + ChronoDuration::hours(1),
+            PeriodType::Day => start + ChronoDuration::days(1),
+            PeriodType::Week => start + ChronoDuration::weeks(1),
+            PeriodType::Month => {
+                let (year, month) = match start.month() {
+                    12 => (start.year() + 1, 1),
+                    month => (start.year(), month + 1),
+                };
+                let first = NaiveDate::from_ymd_opt(year, month, 1).unwrap_or(start.date_naive());
+                Utc.from_utc_datetime(&first.and_time(NaiveTime::MIN))
+            }
+        }
+    }
+}
+
+/// Payment volume of one period of [`PaymentStatistics::time_series`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeriodStats {
+    /// Start of the period
+    pub period_start: DateTime<Utc>,
+
+    /// Number of confirmed payments
+    pub payment_count: u64,
+
+    /// Sum of confirmed payment amounts in the asset's smallest unit
+    pub total_amount_wei: u128,
+
+    /// Number of distinct addresses paid
+    pub unique_payees: usize,
+
+    /// Average confirmed payment amount in the asset's smallest unit
+    pub average_amount_wei: u128,
+}
+
+/// Payment statistics of a single paid host.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostStatistics {
+    /// Total number of payments
+    pub total_payments: u64,
+
+    /// Number of confirmed payments
+    pub successful_payments: u64,
+
+    /// Sum of confirmed payment amounts
+    pub total_amount: String,
+
+    /// Average confirmed payment amount
+    pub average_amount: String,
+
+    /// Timestamp of the latest payment
+    pub last_payment: Option<DateTime<Utc>>,
+}
+
+/// Payment statistics of a single wallet.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WalletStatistics {
+    /// Total number of payments
+    pub total_payments: u64,
+
+    /// Number of confirmed payments
+    pub successful_payments: u64,
+
+    /// Sum of confirmed payment amounts
+    pub total_amount: String,
+}
+
+/// Default fraction of healthy components below which the client is unhealthy.
+pub const DEFAULT_DEGRADED_THRESHOLD: f64 = 0.5;
+
+/// Coarse service state derived from component health.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ServiceState {
+    /// All components are healthy
+    Healthy,
+    /// Some components are down but the client can still operate
+    Degraded,
+    /// Too many components are down for the client to operate
+    Unhealthy,
+}
+
+impl ServiceState {
+    /// Returns the HTTP status code conventionally used to report this state.
+    pub fn http_status(&self) -> u16 {
+        match self {
+            ServiceState::Healthy => 200,
+            ServiceState::Degraded => 207,
+            ServiceState::Unhealthy => 503,
+        }
+    }
+}
+
+/// Health status of the client and its components.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthStatus {
+    /// Overall health (`true` only when [`ServiceState::Healthy`])
+    pub healthy: bool,
+
+    /// Service state derived from component health
+    pub state: ServiceState,
+
+    /// Fraction of healthy components below which the state is `Unhealthy`
+    pub degraded_threshold: f64,
+
+    /// Time of the check
+    pub timestamp: DateTime<Utc>,
+
+    /// Health of individual components
+    pub components: HashMap<String, bool>,
+
+    /// Human-readable descriptions of detected issues
+    pub issues: Vec<String>,
+
+    /// Runtime metrics captured during the check
+    pub metrics: HashMap<String, serde_json::Value>,
+
+    /// Background tasks of the client, by name
+    #[serde(default)]
+    pub background_tasks: Vec<TaskInfo>,
+}
+
+/// State of a named background task of the client.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskInfo {
+    /// Name the task was spawned under
+    pub name: String,
+
+    /// Instances currently running
+    pub running: u64,
+
+    /// Times the task was restarted after a panic
+    pub restarts: u64,
+
+    /// Times the task panicked
+    pub panics: u64,
+}
+
+impl HealthStatus {
+    /// Creates an empty, healthy status using the default degraded threshold.
+    pub fn new() -> Self {
+        Self {
+            healthy: true,
+            state: ServiceState::Healthy,
+            degraded_threshold: DEFAULT_DEGRADED_THRESHOLD,
+            timestamp: Utc::now(),
+            components: HashMap::new(),
+            issues: Vec::new(),
+            metrics: HashMap::new(),
+            background_tasks: Vec::new(),
+        }
+    }
+
+    /// Number of components reporting healthy.
+    pub fn healthy_component_count(&self) -> usize {
+        self.components.values().filter(|healthy| **healthy).count()
+    }
+
+    /// Total number of components checked.
+    pub fn total_component_count(&self) -> usize {
+        self.components.len()
+    }
+
+    /// Recomputes [`state`](Self::state) and [`healthy`](Self::healthy) from the components.
+    ///
+    /// All components up is `Healthy`. Otherwise the state is `Unhealthy` if the
+    /// healthy fraction falls below `degraded_threshold`, and `Degraded` if not.
+    pub fn update_state(&mut self) {
+        let total = self.total_component_count();
+        let healthy = self.healthy_component_count();
+
+        self.state = if healthy == total {
+            ServiceState::Healthy
+        } else if (healthy as f64 / total as f64) < self.degraded_threshold {
+            ServiceState::Unhealthy
+        } else {
+            ServiceState::Degraded
+        };
+        self.healthy = self.state == ServiceState::Healthy;
+    }
+}
+
+impl Default for HealthStatus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Outcome of [`Client::warm_up`](crate::Client::warm_up).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WarmUpReport {
+    /// Connections to the requested hosts
+    pub hosts: Vec<WarmUpResult>,
+
+    /// RPC connections and account state of the configured chains
+    pub chains: Vec<WarmUpResult>,
+
+    /// Connections to the facilitators of the configured chains
+    pub facilitators: Vec<WarmUpResult>,
+
+    /// Wall-clock time taken by the warm-up
+    pub elapsed: Duration,
+}
+
+impl WarmUpReport {
+    /// Returns `true` if every target was warmed up.
+    pub fn is_complete(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    /// Targets that failed or timed out.
+    pub fn failures(&self) -> impl Iterator<Item = &WarmUpResult> {
+        self.hosts
+            .iter()
+            .chain(&self.chains)
+            .chain(&self.facilitators)
+            .filter(|result| result.error.is_some())
+    }
+}
+
+/// Warm-up of a single host, chain or facilitator.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WarmUpResult {
+    /// Host, chain name or facilitator URL
+    pub target: String,
+
+    /// Time taken, up to the warm-up timeout
+    pub elapsed: Duration,
+
+    /// Why the warm-up failed, if it did
+    pub error: Option<String>,
+}
+
+#[cfg(all(test, feature = "axum"))]
+mod tests {
+    use super::*;
+    use axum::response::IntoResponse;
+
+    #[test]
+    fn axum_response_keeps_status_and_headers() {
+        let response = PaymentResponse {
+            url: "https://api.example.com/data".to_string(),
+            status: 200,
+            headers: HashMap::from([
+                ("Content-Type".to_string(), "application/json".to_string()),
+                ("Connection".to_string(), "keep-alive".to_string()),
+            ]),
+            body: Bytes::from_static(b"{}"),
+            payment_made: true,
+            payment_amount: Some("1000".to_string()),
+            payment_asset: None,
+            network: None,
+            transaction_hash: None,
+            payer: None,
+            request_id: String::new(),
+            batch_id: None,
+            timestamp: Utc::now(),
+            payment_timing: None,
+            not_modified: false,
+            requirements: None,
+            dry_run: false,
+            content_hash: None,
+            payment_hints: None,
+        };
+
+        let response = response.into_response();
+        assert_eq!(response.status(), http1::StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "application/json");
+        assert!(!response.headers().contains_key("connection"));
+    }
+}
@@ -0,0 +1,225 @@
+//! Content integrity checks on responses.
+//!
+//! A [`ResponseVerifier`] inspects every successful response after its body
+//! has been read and before it is cached. A verifier that returns an error
+//! fails the request with [`Error::IntegrityCheckFailed`] and the response is
+//! never cached.
+//!
+//! ```rust
+//! use v402_client::{verify::{SignatureVerifier, Sha256Verifier}, Client};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let client = Client::builder()
+//!     .verifier(Box::new(Sha256Verifier::new().required()))
+//!     .verifier(Box::new(SignatureVerifier::new("0x742d35cc6634c0532925a3b844bc454e4438f44e")?))
+//!     .build()
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    crypto,
+    error::{Error, Result},
+    types::PaymentResponse,
+};
+use arc_swap::ArcSwap;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use sha2::{Digest, Sha256};
+use std::{fmt, sync::Arc};
+
+/// Header carrying the hex-encoded SHA-256 digest of the body.
+pub const CONTENT_SHA256_HEADER: &str = "X-Content-SHA256";
+
+/// Header carrying an EIP-191 signature over the SHA-256 digest of the body.
+pub const CONTENT_SIGNATURE_HEADER: &str = "X-Content-Signature";
+
+/// Check run on each successful response before it is cached.
+pub trait ResponseVerifier: Send + Sync + fmt::Debug {
+    /// Returns an error if the response fails the check.
+    ///
+    /// Errors other than [`Error::IntegrityCheckFailed`] are reported to the
+    /// caller as one, with the error's message as the reason.
+    fn verify(&self, response: &PaymentResponse) -> Result<()>;
+
+    /// Name of the verifier in the audit log, its type name by default.
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+}
+
+/// SHA-256 digest of a body, computed as it arrives.
+///
+/// Lets a streamed body be checked against the headers of its response
+/// without buffering it.
+#[derive(Debug, Clone, Default)]
+pub struct ContentHasher {
+    hasher: Sha256,
+}
+
+impl ContentHasher {
+    /// Starts a new digest.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the next chunk of the body.
+    pub fn update(&mut self, chunk: &[u8]) {
+        self.hasher.update(chunk);
+    }
+
+    /// Returns the digest of all chunks added so far.
+    pub fn finish(self) -> [u8; 32] {
+        self.hasher.finalize().into()
+    }
+}
+
+/// Checks the body against a SHA-256 digest sent by the server.
+///
+/// The digest is read from [`CONTENT_SHA256_HEADER`] as hex, or from a
+/// `Content-Digest` (RFC 9530) or `Digest` (RFC 3230) header's `sha-256`
+/// entry as base64. Responses without a digest pass unless the verifier is
+/// [`required`](Self::required).
+#[derive(Debug, Clone, Default)]
+pub struct Sha256Verifier {
+    required: bool,
+}
+
+impl Sha256Verifier {
+    /// Creates a verifier that skips responses without a digest.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rejects responses that do not carry a digest.
+    pub fn required(mut self) -> Self {
+        self.required = true;
+        self
+    }
+
+    /// Checks a digest computed over the body, e.g. with a [`ContentHasher`],
+    /// against the response headers.
+    pub fn verify_digest(&self, response: &PaymentResponse, digest: &[u8; 32]) -> Result<()> {
+        let expected = match expected_sha256(response).map_err(|reason| failed(response, reason))? {
+            Some(expected) => expected,
+            None if self.required => return Err(failed(response, "response carries no SHA-256 digest".to_string())),
+            None => return Ok(()),
+        };
+        if expected != *digest {
+            return Err(failed(
+                response,
+                format!("SHA-256 digest is {}, expected {}", hex::encode(digest), hex::encode(expected)),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl ResponseVerifier for Sha256Verifier {
+    fn verify(&self, response: &PaymentResponse) -> Result<()> {
+        self.verify_digest(response, &Sha256::digest(&response.body).into())
+    }
+}
+
+/// Checks a detached signature over the body against a known public key.
+///
+/// The server signs the SHA-256 digest of the body with EIP-191
+/// `personal_sign` and sends the 65-byte signature as hex in
+/// [`CONTENT_SIGNATURE_HEADER`]. Unsigned responses are rejected.
+#[derive(Debug, Clone)]
+pub struct SignatureVerifier {
+    signer: String,
+}
+
+impl SignatureVerifier {
+    /// Creates a verifier for content signed by `public_key`, a hex-encoded
+    /// SEC1 public key or the address of the signing wallet.
+    pub fn new(public_key: &str) -> Result<Self> {
+        Ok(Self {
+            signer: crypto::public_key_address(public_key)?,
+        })
+    }
+
+    /// Checks a digest computed over the body, e.g. with a [`ContentHasher`],
+    /// against the response's signature.
+    pub fn verify_digest(&self, response: &PaymentResponse, digest: &[u8; 32]) -> Result<()> {
+        let signature = response
+            .header(CONTENT_SIGNATURE_HEADER)
+            .ok_or_else(|| failed(response, format!("response has no {} header", CONTENT_SIGNATURE_HEADER)))?;
+        let signer = crypto::recover_address(&crypto::personal_message_hash(digest), signature.trim())
+            .map_err(|e| failed(response, e.to_string()))?;
+        if !signer.eq_ignore_ascii_case(&self.signer) {
+            return Err(failed(response, format!("content signed by {}, expected {}", signer, self.signer)));
+        }
+        Ok(())
+    }
+}
+
+impl ResponseVerifier for SignatureVerifier {
+    fn verify(&self, response: &PaymentResponse) -> Result<()> {
+        self.verify_digest(response, &Sha256::digest(&response.body).into())
+    }
+}
+
+/// Builds the error reported for a response that failed a check.
+fn failed(response: &PaymentResponse, reason: String) -> Error {
+    Error::IntegrityCheckFailed {
+        url: response.url.clone(),
+        reason,
+        context: None,
+    }
+}
+
+/// Reads the SHA-256 digest announced in the response headers, if any.
+pub(crate) fn expected_sha256(response: &PaymentResponse) -> std::result::Result<Option<[u8; 32]>, String> {
+    let invalid = |header: &str| format!("invalid SHA-256 digest in {} header", header);
+
+    if let Some(value) = response.header(CONTENT_SHA256_HEADER) {
+        let bytes = hex::decode(value.trim().trim_start_matches("0x")).map_err(|_| invalid(CONTENT_SHA256_HEADER))?;
+        return bytes.try_into().map(Some).map_err(|_| invalid(CONTENT_SHA256_HEADER));
+    }
+
+    for header in ["Content-Digest", "Digest"] {
+        let Some(value) = response.header(header) else { continue };
+        let entry = value.split(',').find_map(|entry| {
+            let (algorithm, digest) = entry.trim().split_once('=')?;
+            algorithm.eq_ignore_ascii_case("sha-256").then(|| digest.trim().trim_matches(':'))
+        });
+        if let Some(digest) = entry {
+            let bytes = BASE64.decode(digest).map_err(|_| invalid(header))?;
+            return bytes.try_into().map(Some).map_err(|_| invalid(header));
+        }
+    }
+    Ok(None)
+}
+
+/// Copy-on-write list of the verifiers registered on a client.
+#[derive(Debug, Default)]
+pub(crate) struct VerifierSet {
+    verifiers: ArcSwap<Vec<Arc<dyn ResponseVerifier>>>,
+}
+
+impl VerifierSet {
+    /// Appends a verifier, run after those already registered.
+    pub(crate) fn add(&self, verifier: Box<dyn ResponseVerifier>) {
+        let verifier: Arc<dyn ResponseVerifier> = Arc::from(verifier);
+        self.verifiers.rcu(|current| {
+            let mut updated = Vec::with_capacity(current.len() + 1);
+            updated.extend(current.iter().cloned());
+            updated.push(verifier.clone());
+            updated
+        });
+    }
+
+    /// Runs every verifier, stopping at the first failure.
+    pub(crate) fn verify(&self, response: &PaymentResponse) -> Result<()> {
+        for verifier in self.verifiers.load().iter() {
+            verifier.verify(response).map_err(|e| match e {
+                Error::IntegrityCheckFailed { .. } => e,
+                other => failed(response, other.to_string()),
+            })?;
+        }
+        Ok(())
+    }
+}
@@ -1,34 +1,196 @@
 use anyhow::Result;
-use reqwest::Client;
-use serde_json::json;
+use reqwest::header::{self, HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Client, Method, RequestBuilder};
+use serde::Serialize;
+use serde_json::{json, Value};
 use std::time::Duration;
-use tracing::{info, error};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use tracing::{info, error, warn};
+use uuid::Uuid;
 
 use crate::models::*;
 use crate::config::Config;
 
+/// keccak256("Transfer(address,address,uint256)")
+const TRANSFER_TOPIC: &str = "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+
+/// WebSocket connection to `/ws/products`.
+pub type ProductSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// How failed requests are retried, set with [`V402Client::with_retry`].
+#[derive(Debug, Clone)]
+pub struct RetryConfig {
+    /// Attempts per request, including the first
+    pub max_attempts: u32,
+    /// Response statuses that are retried
+    pub retry_on_status: Vec<u16>,
+    /// Whether requests that fail without a response, e.g. on a refused connection or timeout, are retried
+    pub retry_on_error: bool,
+    /// Delay before each retry
+    pub backoff: BackoffStrategy,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            retry_on_status: vec![429, 500, 502, 503, 504],
+            retry_on_error: true,
+            backoff: BackoffStrategy::Exponential {
+                base: Duration::from_millis(200),
+                max: Duration::from_secs(5),
+            },
+        }
+    }
+}
+
+/// Delay before retrying a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackoffStrategy {
+    /// The same delay before every retry
+    Fixed(Duration),
+    /// `base` before the first retry, doubling before each next one up to `max`
+    Exponential { base: Duration, max: Duration },
+    /// The delay times the number of the retry
+    Linear(Duration),
+}
+
+impl BackoffStrategy {
+    /// Delay before retry number `retry`, counting from 1.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let retry = retry.max(1);
+        match *self {
+            BackoffStrategy::Fixed(delay) => delay,
+            BackoffStrategy::Exponential { base, max } => {
+                let factor = 1u32.checked_shl(retry - 1).unwrap_or(u32::MAX);
+                base.checked_mul(factor).map_or(max, |delay| delay.min(max))
+            }
+            BackoffStrategy::Linear(step) => step.saturating_mul(retry),
+        }
+    }
+}
+
+/// Request that can be sent more than once.
+///
+/// The body is kept as bytes, so every retry sends it in full rather than a
+/// body already consumed by the previous attempt.
+#[derive(Debug, Clone)]
+pub struct RetryableRequest {
+    method: Method,
+    url: String,
+    headers: HeaderMap,
+    body: Option<Vec<u8>>,
+}
+
+impl RetryableRequest {
+    /// Creates a request without headers or body.
+    pub fn new(method: Method, url: &str) -> Self {
+        Self {
+            method,
+            url: url.to_string(),
+            headers: HeaderMap::new(),
+            body: None,
+        }
+    }
+
+    /// Sets a header sent with every attempt.
+    pub fn header(mut self, name: HeaderName, value: &'static str) -> Self {
+        self.headers.insert(name, HeaderValue::from_static(value));
+        self
+    }
+
+    /// Sets the body to `body` serialized as JSON.
+    pub fn json<T: Serialize + ?Sized>(mut self, body: &T) -> Result<Self> {
+        self.body = Some(serde_json::to_vec(body)?);
+        self.headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        Ok(self)
+    }
+
+    /// Builds one attempt of the request on `client`.
+    fn build(&self, client: &Client) -> RequestBuilder {
+        let request = client.request(self.method.clone(), &self.url).headers(self.headers.clone());
+        match &self.body {
+            Some(body) => request.body(body.clone()),
+            None => request,
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct V402Client {
     client: Client,
+    /// Client without a total timeout, for long-lived event streams
+    stream_client: Client,
     config: Config,
+    /// Retries of failed requests, none unless set with [`with_retry`](Self::with_retry)
+    retry: Option<RetryConfig>,
 }
 
 impl V402Client {
     pub fn new(config: Config) -> Result<Self> {
-        let client = Client::builder()
+        let client = pooled_client(&config)
             .timeout(Duration::from_secs(config.timeout))
             .build()?;
+        let stream_client = pooled_client(&config)
+            .connect_timeout(Duration::from_secs(config.timeout))
+            .build()?;
+
+        Ok(Self { client, stream_client, config, retry: None })
+    }
 
-        Ok(Self { client, config })
+    /// Retries failed requests of every method as `retry` says.
+    ///
+    /// Requests are retried whatever their HTTP method, so only retry on
+    /// statuses the API returns before acting on a request, or make sure
+    /// repeated payments are rejected by the server.
+    pub fn with_retry(mut self, retry: RetryConfig) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Sends `request` with the default client, retrying as configured.
+    async fn send(&self, request: RetryableRequest) -> Result<reqwest::Response> {
+        self.send_with(&self.client, &request).await
+    }
+
+    /// Sends `request` with `client`, retrying as configured by [`with_retry`](Self::with_retry).
+    ///
+    /// Once attempts run out the last response is returned, whatever its
+    /// status, so callers still see the server's error.
+    async fn send_with(&self, client: &Client, request: &RetryableRequest) -> Result<reqwest::Response> {
+        let Some(retry) = &self.retry else {
+            return Ok(request.build(client).send().await?);
+        };
+
+        let mut attempt = 1;
+        loop {
+            let can_retry = attempt < retry.max_attempts;
+            match request.build(client).send().await {
+                Ok(response) if can_retry && retry.retry_on_status.contains(&response.status().as_u16()) => {
+                    warn!(
+                        "{} {} returned {}, retrying (attempt {}/{})",
+                        request.method, request.url, response.status(), attempt, retry.max_attempts
+                    );
+                }
+                Err(e) if can_retry && retry.retry_on_error => {
+                    warn!(
+                        "{} {} failed, retrying (attempt {}/{}): {}",
+                        request.method, request.url, attempt, retry.max_attempts, e
+                    );
+                }
+                result => return Ok(result?),
+            }
+
+            tokio::time::sleep(retry.backoff.delay(attempt)).await;
+            attempt += 1;
+        }
     }
 
     pub async fn create_product(&self, product: &ProductCreate) -> Result<Product> {
         let url = format!("{}/api/v1/products", self.config.base_url);
         
-        let response = self.client
-            .post(&url)
-            .json(product)
-            .send()
-            .await?;
+        let response = self.send(RetryableRequest::new(Method::POST, &url).json(product)?).await?;
 
         if !response.status().is_success() {
             let error_text = response.text().await?;
@@ -43,10 +205,7 @@ impl V402Client {
     pub async fn get_product(&self, product_id: &str) -> Result<Product> {
         let url = format!("{}/api/v1/products/{}", self.config.base_url, product_id);
         
-        let response = self.client
-            .get(&url)
-            .send()
-            .await?;
+        let response = self.send(RetryableRequest::new(Method::GET, &url)).await?;
 
         if !response.status().is_success() {
             let error_text = response.text().await?;
@@ -68,10 +227,7 @@ impl V402Client {
             url.push_str(&format!("{}limit={}", separator, limit));
         }
 
-        let response = self.client
-            .get(&url)
-            .send()
-            .await?;
+        let response = self.send(RetryableRequest::new(Method::GET, &url)).await?;
 
         if !response.status().is_success() {
             let error_text = response.text().await?;
@@ -85,11 +241,7 @@ impl V402Client {
     pub async fn update_product(&self, product_id: &str, product: &ProductUpdate) -> Result<Product> {
         let url = format!("{}/api/v1/products/{}", self.config.base_url, product_id);
         
-        let response = self.client
-            .put(&url)
-            .json(product)
-            .send()
-            .await?;
+        let response = self.send(RetryableRequest::new(Method::PATCH, &url).json(product)?).await?;
 
         if !response.status().is_success() {
             let error_text = response.text().await?;
@@ -104,10 +256,7 @@ impl V402Client {
     pub async fn delete_product(&self, product_id: &str) -> Result<()> {
         let url = format!("{}/api/v1/products/{}", self.config.base_url, product_id);
         
-        let response = self.client
-            .delete(&url)
-            .send()
-            .await?;
+        let response = self.send(RetryableRequest::new(Method::DELETE, &url)).await?;
 
         if !response.status().is_success() {
             let error_text = response.text().await?;
@@ -118,14 +267,48 @@ impl V402Client {
         Ok(())
     }
 
+    /// Opens the server-sent event stream of product changes, optionally limited to `product_ids`.
+    pub async fn product_events(&self, product_ids: &[Uuid]) -> Result<reqwest::Response> {
+        let mut url = format!("{}/api/v1/products/events", self.config.base_url);
+        if !product_ids.is_empty() {
+            let ids: Vec<String> = product_ids.iter().map(Uuid::to_string).collect();
+            url.push_str(&format!("?product_ids={}", ids.join(",")));
+        }
+
+        let request = RetryableRequest::new(Method::GET, &url).header(header::ACCEPT, "text/event-stream");
+        let response = self.send_with(&self.stream_client, &request).await?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await?;
+            return Err(anyhow::anyhow!("Failed to subscribe to product events: {}", error_text));
+        }
+
+        Ok(response)
+    }
+
+    /// Opens a WebSocket to `/ws/products`, on the base URL with a `ws` or `wss` scheme.
+    pub async fn connect_product_watch(&self) -> Result<ProductSocket> {
+        let base_url = &self.config.base_url;
+        let url = match base_url.split_once("://") {
+            Some(("https", rest)) => format!("wss://{}/ws/products", rest),
+            Some(("http", rest)) => format!("ws://{}/ws/products", rest),
+            _ => return Err(anyhow::anyhow!("Unsupported base URL for WebSocket: {}", base_url)),
+        };
+
+        let (socket, _) = tokio::time::timeout(
+            Duration::from_secs(self.config.timeout),
+            tokio_tungstenite::connect_async(url.as_str()),
+        )
+        .await
+        .map_err(|_| anyhow::anyhow!("Timed out connecting to {}", url))??;
+
+        Ok(socket)
+    }
+
     pub async fn process_payment(&self, payment: &PaymentRequest) -> Result<PaymentResponse> {
         let url = format!("{}/api/v1/payments", self.config.base_url);
         
-        let response = self.client
-            .post(&url)
-            .json(payment)
-            .send()
-            .await?;
+        let response = self.send(RetryableRequest::new(Method::POST, &url).json(payment)?).await?;
 
         if !response.status().is_success() {
             let error_text = response.text().await?;
@@ -140,10 +323,7 @@ impl V402Client {
     pub async fn get_payment(&self, transaction_hash: &str) -> Result<PaymentResponse> {
         let url = format!("{}/api/v1/payments/{}", self.config.base_url, transaction_hash);
         
-        let response = self.client
-            .get(&url)
-            .send()
-            .await?;
+        let response = self.send(RetryableRequest::new(Method::GET, &url)).await?;
 
         if !response.status().is_success() {
             let error_text = response.text().await?;
@@ -157,11 +337,7 @@ impl V402Client {
     pub async fn check_access(&self, access_request: &AccessRequest) -> Result<AccessResponse> {
         let url = format!("{}/api/v1/access/check", self.config.base_url);
         
-        let response = self.client
-            .post(&url)
-            .json(access_request)
-            .send()
-            .await?;
+        let response = self.send(RetryableRequest::new(Method::POST, &url).json(access_request)?).await?;
 
         if !response.status().is_success() {
             let error_text = response.text().await?;
@@ -172,14 +348,40 @@ impl V402Client {
         Ok(access_response)
     }
 
+    /// Extends an existing access grant by the product's subscription period.
+    ///
+    /// Fails with [`Error::AccessNotFound`] if the user has no access record to extend.
+    pub async fn refresh_access(&self, product_id: Uuid, user_address: &str) -> Result<AccessResponse> {
+        let url = format!("{}/api/v1/access/refresh", self.config.base_url);
+        let refresh_request = AccessRefreshRequest {
+            product_id,
+            user_address: user_address.to_string(),
+        };
+
+        let response = self.send(RetryableRequest::new(Method::POST, &url).json(&refresh_request)?).await?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(Error::AccessNotFound {
+                product_id,
+                user_address: user_address.to_string(),
+            }
+            .into());
+        }
+
+        if !response.status().is_success() {
+            let error_text = response.text().await?;
+            return Err(anyhow::anyhow!("Failed to refresh access: {}", error_text));
+        }
+
+        let access_response: AccessResponse = response.json().await?;
+        info!("Refreshed access for product: {}, expires at: {:?}", product_id, access_response.expires_at);
+        Ok(access_response)
+    }
+
     pub async fn get_analytics(&self, analytics_request: &AnalyticsRequest) -> Result<AnalyticsResponse> {
         let url = format!("{}/api/v1/analytics", self.config.base_url);
         
-        let response = self.client
-            .post(&url)
-            .json(analytics_request)
-            .send()
-            .await?;
+        let response = self.send(RetryableRequest::new(Method::POST, &url).json(analytics_request)?).await?;
 
         if !response.status().is_success() {
             let error_text = response.text().await?;
@@ -190,13 +392,83 @@ impl V402Client {
         Ok(analytics)
     }
 
+    /// Sends a `HEAD` request to `url`, which need not be on the API.
+    pub async fn head(&self, url: &str) -> Result<reqwest::Response> {
+        self.send(RetryableRequest::new(Method::HEAD, url)).await
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Fetches ERC-20 `Transfer` events sent by `from` in the given block range.
+    pub async fn get_transfer_logs(&self, from: &str, from_block: u64, to_block: u64) -> Result<Vec<ChainPayment>> {
+        let from_topic = format!("0x{:0>64}", from.trim_start_matches("0x").to_lowercase());
+
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "eth_getLogs",
+            "params": [{
+                "address": self.config.token_address,
+                "fromBlock": format!("0x{:x}", from_block),
+                "toBlock": format!("0x{:x}", to_block),
+                "topics": [TRANSFER_TOPIC, from_topic],
+            }],
+        });
+
+        let response: Value = self
+            .send(RetryableRequest::new(Method::POST, &self.config.rpc_url).json(&request)?)
+            .await?
+            .json()
+            .await?;
+
+        if let Some(error) = response.get("error") {
+            return Err(anyhow::anyhow!("eth_getLogs failed: {}", error));
+        }
+
+        let logs = response["result"]
+            .as_array()
+            .ok_or_else(|| anyhow::anyhow!("eth_getLogs returned no result"))?;
+
+        let payments = logs
+            .iter()
+            .map(|log| -> Result<ChainPayment> {
+                let topic_address = |index: usize| -> Result<String> {
+                    let topic = log["topics"][index]
+                        .as_str()
+                        .ok_or_else(|| anyhow::anyhow!("Transfer log is missing topic {}", index))?;
+                    let address = topic
+                        .get(topic.len().saturating_sub(40)..)
+                        .filter(|address| address.len() == 40)
+                        .ok_or_else(|| anyhow::anyhow!("Invalid address topic {}", topic))?;
+                    Ok(format!("0x{}", address))
+                };
+                let hex_field = |field: &str| log[field].as_str().unwrap_or("0x0").trim_start_matches("0x").to_string();
+
+                let data = hex_field("data");
+                let amount_hex = data.trim_start_matches('0');
+                let amount_wei = if amount_hex.is_empty() { 0 } else { u128::from_str_radix(amount_hex, 16)? };
+
+                Ok(ChainPayment {
+                    chain: self.config.chain.clone(),
+                    transaction_hash: log["transactionHash"].as_str().unwrap_or_default().to_string(),
+                    block_number: u64::from_str_radix(&hex_field("blockNumber"), 16)?,
+                    from: topic_address(1)?,
+                    to: topic_address(2)?,
+                    amount_wei,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        info!("Fetched {} transfer logs in blocks {}-{}", payments.len(), from_block, to_block);
+        Ok(payments)
+    }
+
     pub async fn health_check(&self) -> Result<HealthCheck> {
         let url = format!("{}/health", self.config.base_url);
         
-        let response = self.client
-            .get(&url)
-            .send()
-            .await?;
+        let response = self.send(RetryableRequest::new(Method::GET, &url)).await?;
 
         if !response.status().is_success() {
             let error_text = response.text().await?;
@@ -207,3 +479,165 @@ impl V402Client {
         Ok(health)
     }
 }
+
+/// Starts a client builder with the connection pool settings of `config`.
+fn pooled_client(config: &Config) -> reqwest::ClientBuilder {
+    Client::builder()
+        .tcp_keepalive(config.tcp_keepalive_duration())
+        .pool_idle_timeout(config.idle_connection_timeout_duration())
+        .pool_max_idle_per_host(config.pool_max_idle_per_host)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Starts an HTTP/1.1 server keeping connections open, returning its URL
+    /// and the number of connections it accepted.
+    async fn keep_alive_server() -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let connections = Arc::new(AtomicUsize::new(0));
+        let accepted = connections.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                accepted.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(async move {
+                    let mut buffer = [0; 1024];
+                    // Requests have no body and fit in one read
+                    while matches!(stream.read(&mut buffer).await, Ok(n) if n > 0) {
+                        let response = "HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok";
+                        if stream.write_all(response.as_bytes()).await.is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+        (url, connections)
+    }
+
+    /// Sends two requests `pause` apart, returning the connections the server accepted.
+    async fn connections_for(config: &Config, pause: Duration) -> usize {
+        let (url, connections) = keep_alive_server().await;
+        let client = pooled_client(config).build().unwrap();
+
+        client.get(&url).send().await.unwrap().text().await.unwrap();
+        tokio::time::sleep(pause).await;
+        client.get(&url).send().await.unwrap().text().await.unwrap();
+        connections.load(Ordering::SeqCst)
+    }
+
+    #[tokio::test]
+    async fn idle_connections_are_reused_within_the_timeout() {
+        let config = Config { idle_connection_timeout: 1, ..Config::default() };
+        assert_eq!(connections_for(&config, Duration::from_millis(100)).await, 1);
+    }
+
+    #[tokio::test]
+    async fn idle_connections_are_closed_after_the_timeout() {
+        let config = Config { idle_connection_timeout: 1, ..Config::default() };
+        assert_eq!(connections_for(&config, Duration::from_secs(2)).await, 2);
+    }
+
+    /// Starts an HTTP/1.1 server answering with `statuses` in turn, then
+    /// 200, returning its URL and the bodies of the requests it received.
+    async fn scripted_server(statuses: Vec<u16>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let bodies = Arc::new(Mutex::new(Vec::new()));
+        let received = bodies.clone();
+        tokio::spawn(async move {
+            let mut statuses = statuses.into_iter();
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+                // Read the headers, then as much body as they announce
+                let body_start = loop {
+                    let n = stream.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..n]);
+                    if let Some(end) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+                        break end + 4;
+                    }
+                };
+                let head = String::from_utf8_lossy(&request[..body_start]).to_lowercase();
+                let length = head
+                    .lines()
+                    .find_map(|line| line.strip_prefix("content-length:"))
+                    .map_or(0, |length| length.trim().parse::<usize>().unwrap());
+                while request.len() < body_start + length {
+                    let n = stream.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..n]);
+                }
+                received.lock().unwrap().push(String::from_utf8_lossy(&request[body_start..]).into_owned());
+
+                let status = statuses.next().unwrap_or(200);
+                let response = format!("HTTP/1.1 {} Status\r\nconnection: close\r\ncontent-length: 2\r\n\r\nok", status);
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (url, bodies)
+    }
+
+    /// Returns a client retrying up to 3 attempts on 500 and 503 without waiting long.
+    fn retrying_client() -> V402Client {
+        V402Client::new(Config::default()).unwrap().with_retry(RetryConfig {
+            max_attempts: 3,
+            retry_on_status: vec![500, 503],
+            retry_on_error: false,
+            backoff: BackoffStrategy::Fixed(Duration::from_millis(10)),
+        })
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_retried_with_the_full_body() {
+        let (url, bodies) = scripted_server(vec![500, 500]).await;
+        let request = RetryableRequest::new(Method::POST, &url).json(&json!({ "title": "Article" })).unwrap();
+
+        let response = retrying_client().send(request).await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(*bodies.lock().unwrap(), vec![r#"{"title":"Article"}"#; 3]);
+    }
+
+    #[tokio::test]
+    async fn the_last_response_is_returned_once_attempts_run_out() {
+        let (url, bodies) = scripted_server(vec![503, 503, 503]).await;
+        let response = retrying_client().send(RetryableRequest::new(Method::GET, &url)).await.unwrap();
+        assert_eq!(response.status(), 503);
+        assert_eq!(bodies.lock().unwrap().len(), 3);
+
+        // Statuses that are not listed are returned at once
+        let (url, bodies) = scripted_server(vec![404]).await;
+        let response = retrying_client().send(RetryableRequest::new(Method::GET, &url)).await.unwrap();
+        assert_eq!(response.status(), 404);
+        assert_eq!(bodies.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn requests_are_sent_once_without_retry() {
+        let (url, bodies) = scripted_server(vec![500]).await;
+        let client = V402Client::new(Config::default()).unwrap();
+        let response = client.send(RetryableRequest::new(Method::GET, &url)).await.unwrap();
+        assert_eq!(response.status(), 500);
+        assert_eq!(bodies.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn backoff_delays_grow_as_configured() {
+        let delays = |backoff: BackoffStrategy| (1..=5).map(|retry| backoff.delay(retry).as_millis()).collect::<Vec<_>>();
+        assert_eq!(delays(BackoffStrategy::Fixed(Duration::from_millis(100))), [100, 100, 100, 100, 100]);
+        assert_eq!(delays(BackoffStrategy::Linear(Duration::from_millis(100))), [100, 200, 300, 400, 500]);
+        let exponential = BackoffStrategy::Exponential {
+            base: Duration::from_millis(100),
+            max: Duration::from_millis(1000),
+        };
+        assert_eq!(delays(exponential), [100, 200, 400, 800, 1000]);
+        assert_eq!(exponential.delay(64), Duration::from_millis(1000));
+    }
+}
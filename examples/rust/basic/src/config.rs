@@ -9,8 +9,11 @@ pub struct Config {
     pub public_key: String,
     pub private_key: String,
     pub chain_id: u64,
+    pub chain: String,
     pub rpc_url: String,
     pub contract_address: String,
+    pub token_address: String,
+    pub token_decimals: u8,
     pub default_currency: String,
     pub gas_limit: u64,
     pub gas_price: String,
@@ -18,6 +21,26 @@ pub struct Config {
     pub enable_metrics: bool,
     pub metrics_port: u16,
     pub health_check: bool,
+    /// Check that a product's `content_url` is reachable before creating it
+    #[serde(default)]
+    pub validate_content_urls: bool,
+    /// Seconds between TCP keep-alive probes on open connections, none if unset
+    #[serde(default)]
+    pub tcp_keepalive_interval: Option<u64>,
+    /// Seconds an unused connection is kept open for the next request
+    #[serde(default = "default_idle_connection_timeout")]
+    pub idle_connection_timeout: u64,
+    /// Unused connections kept open per host
+    #[serde(default = "default_pool_max_idle_per_host")]
+    pub pool_max_idle_per_host: usize,
+}
+
+fn default_idle_connection_timeout() -> u64 {
+    90
+}
+
+fn default_pool_max_idle_per_host() -> usize {
+    10
 }
 
 impl Default for Config {
@@ -29,8 +52,11 @@ impl Default for Config {
             public_key: "0x1234567890abcdef1234567890abcdef12345678".to_string(),
             private_key: "0xabcdef1234567890abcdef1234567890abcdef1234567890abcdef1234567890".to_string(),
             chain_id: 1, // Ethereum mainnet
+            chain: "ethereum".to_string(),
             rpc_url: "https://mainnet.infura.io/v3/your-project-id".to_string(),
             contract_address: "0x1234567890abcdef1234567890abcdef12345678".to_string(),
+            token_address: "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48".to_string(), // USDC
+            token_decimals: 6,
             default_currency: "USDC".to_string(),
             gas_limit: 100000,
             gas_price: "20000000000".to_string(), // 20 gwei
@@ -38,6 +64,10 @@ impl Default for Config {
             enable_metrics: true,
             metrics_port: 9090,
             health_check: true,
+            validate_content_urls: false,
+            tcp_keepalive_interval: None,
+            idle_connection_timeout: default_idle_connection_timeout(),
+            pool_max_idle_per_host: default_pool_max_idle_per_host(),
         }
     }
 }
@@ -78,4 +108,12 @@ impl Config {
     pub fn timeout_duration(&self) -> Duration {
         Duration::from_secs(self.timeout)
     }
+
+    pub fn tcp_keepalive_duration(&self) -> Option<Duration> {
+        self.tcp_keepalive_interval.map(Duration::from_secs)
+    }
+
+    pub fn idle_connection_timeout_duration(&self) -> Duration {
+        Duration::from_secs(self.idle_connection_timeout)
+    }
 }
@@ -1,11 +1,12 @@
 use anyhow::Result;
+use futures::StreamExt;
 use tracing::{info, error};
 use uuid::Uuid;
 use chrono::Utc;
 
 use crate::models::*;
 use crate::config::Config;
-use crate::client::V402Client;
+use crate::client::{RetryConfig, V402Client};
 use crate::services::*;
 
 #[tokio::main]
@@ -26,8 +27,8 @@ async fn main() -> Result<()> {
 
     info!("Configuration loaded successfully");
 
-    // Create v402 client
-    let client = V402Client::new(config)?;
+    // Create v402 client, retrying rate-limited and failed requests
+    let client = V402Client::new(config)?.with_retry(RetryConfig::default());
     
     // Create services
     let mut product_service = ProductService::new(client.clone());
@@ -49,6 +50,48 @@ async fn main() -> Result<()> {
         }
     }
 
+    let graph = health_service.dependency_graph().await;
+    if graph.is_healthy() {
+        info!("All dependencies healthy");
+    } else {
+        error!("Failing dependencies: {}", graph.critical_path().join(" -> "));
+    }
+
+    // Watch product changes in the background
+    info!("=== Subscribing to Product Updates ===");
+    match product_service.subscribe_to_updates(&[]).await {
+        Ok(updates) => {
+            tokio::spawn(async move {
+                futures::pin_mut!(updates);
+                while let Some(update) = updates.next().await {
+                    info!("Product {} update: {:?}", update.product_id, update.event_type);
+                }
+            });
+        }
+        Err(e) => {
+            error!("Failed to subscribe to product updates: {}", e);
+        }
+    }
+
+    // Watch prices over a WebSocket
+    info!("=== Watching Product Prices ===");
+    let watched_product = Uuid::new_v4(); // In real usage, this would be the actual product ID
+    match product_service.watch(&[watched_product]).await {
+        Ok(mut watcher) => {
+            tokio::spawn(async move {
+                while let Some(update) = watcher.next().await {
+                    info!("Product {} price: {} -> {}", update.product_id, update.old_price, update.new_price);
+                    if let Err(e) = watcher.unsubscribe(update.product_id).await {
+                        error!("Failed to unsubscribe from {}: {}", update.product_id, e);
+                    }
+                }
+            });
+        }
+        Err(e) => {
+            error!("Failed to watch product prices: {}", e);
+        }
+    }
+
     // Example 2: Create a product
     info!("=== Creating Product ===");
     let product_data = ProductCreate {
@@ -118,6 +161,8 @@ async fn main() -> Result<()> {
         timestamp: Utc::now().timestamp(),
         signature: "signature-123".to_string(),
     };
+    let product_id = access_request.product_id;
+    let user_address = access_request.user_address.clone();
 
     match access_service.check_access(access_request).await {
         Ok(access_response) => {
@@ -135,6 +180,27 @@ async fn main() -> Result<()> {
         }
     }
 
+    match access_service.get_access_history(product_id, &user_address, 10) {
+        Ok(history) => {
+            for entry in history {
+                info!("Checked at {}: has access: {}", entry.checked_at, entry.had_access);
+            }
+        }
+        Err(e) => error!("Failed to read access history: {}", e),
+    }
+
+    // Extend the subscription without a new payment
+    match access_service.refresh_access(product_id, &user_address).await {
+        Ok(access_response) => {
+            info!("Access refreshed, expires at: {:?}", access_response.expires_at);
+        }
+        Err(e) => match e.downcast_ref::<Error>() {
+            Some(Error::AccessNotFound { .. }) => info!("No existing access to refresh"),
+            Some(other) => error!("Failed to refresh access: {}", other),
+            None => error!("Failed to refresh access: {}", e),
+        },
+    }
+
     // Example 6: Get analytics
     info!("=== Getting Analytics ===");
     let analytics_request = AnalyticsRequest {
@@ -159,18 +225,48 @@ async fn main() -> Result<()> {
         }
     }
 
-    // Example 7: Service statistics
+    // Follow live analytics, as a status page would
+    match analytics_service.realtime_dashboard(None, std::time::Duration::from_secs(5)).await {
+        Ok(mut dashboard) => {
+            info!("Dashboard views: {}", dashboard.current().views);
+            if dashboard.receiver.changed().await.is_ok() {
+                info!("Dashboard updated, views: {}", dashboard.current().views);
+            }
+            dashboard.stop_and_wait().await;
+        }
+        Err(e) => {
+            error!("Failed to start realtime dashboard: {}", e);
+        }
+    }
+
+    // Example 7: Reconcile payments against the chain
+    info!("=== Reconciling Payments ===");
+    match payment_service.reconcile("ethereum", 19_000_000, 19_010_000).await {
+        Ok(report) => {
+            info!("Matched: {}", report.matched);
+            info!("Recorded but not on-chain: {}", report.local_only.len());
+            info!("On-chain but not recorded: {}", report.chain_only.len());
+            info!("Total discrepancy: {} wei", report.total_discrepancy_wei);
+        }
+        Err(e) => {
+            error!("Failed to reconcile payments: {}", e);
+        }
+    }
+
+    // Example 8: Service statistics
     info!("=== Service Statistics ===");
     info!("Cached products: {}", product_service.cache.len());
     info!("Payment history entries: {}", payment_service.payment_history.len());
     info!("Cached access checks: {}", access_service.access_cache.len());
+    info!("Access history entries: {}", access_service.total_history_entries());
     info!("Cached analytics: {}", analytics_service.analytics_cache.len());
 
-    // Example 8: Clear caches
+    // Example 9: Clear caches
     info!("=== Clearing Caches ===");
     product_service.clear_cache();
     payment_service.clear_history();
     access_service.clear_cache();
+    access_service.clear_history(product_id, &user_address);
     analytics_service.clear_cache();
     info!("All caches cleared");
 
@@ -28,13 +28,51 @@ pub struct Product {
     pub updated_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ProductStatus {
     Active,
     Inactive,
     Draft,
 }
 
+/// Kind of change announced on the product event stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UpdateEventType {
+    PriceChanged,
+    StatusChanged,
+    Deleted,
+}
+
+/// Change to a product, sent on `/api/v1/products/events`.
+///
+/// `product` is the product after the change, or its last state for `Deleted`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProductUpdateEvent {
+    pub product_id: Uuid,
+    pub event_type: UpdateEventType,
+    pub product: Product,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Price change of a watched product, sent on `/ws/products`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PriceUpdateEvent {
+    pub product_id: Uuid,
+    pub old_price: String,
+    pub new_price: String,
+    pub changed_at: DateTime<Utc>,
+}
+
+/// Message sent by clients of `/ws/products` to change which products they watch.
+///
+/// Serialized as `{"subscribe": [ids]}` or `{"unsubscribe": [ids]}`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchCommand {
+    Subscribe(Vec<Uuid>),
+    Unsubscribe(Vec<Uuid>),
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct ProductCreate {
     #[validate(length(min = 1, max = 200))]
@@ -101,6 +139,64 @@ pub struct PaymentResponse {
     pub error: Option<String>,
 }
 
+/// A token transfer from the payer found on-chain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainPayment {
+    pub chain: String,
+    pub transaction_hash: String,
+    pub block_number: u64,
+    pub from: String,
+    pub to: String,
+    pub amount_wei: u128,
+}
+
+/// Changes between two catalog snapshots, as computed by `ProductService::diff_products`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CatalogDiff<'a> {
+    /// Products only in the new snapshot
+    pub added: Vec<&'a Product>,
+    /// IDs of products only in the old snapshot
+    pub removed: Vec<Uuid>,
+    pub price_changed: Vec<PriceChange>,
+    pub status_changed: Vec<StatusChange>,
+}
+
+impl CatalogDiff<'_> {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.price_changed.is_empty() && self.status_changed.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PriceChange {
+    pub product_id: Uuid,
+    pub old_price: String,
+    pub new_price: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatusChange {
+    pub product_id: Uuid,
+    pub old_status: ProductStatus,
+    pub new_status: ProductStatus,
+}
+
+/// Result of comparing local payment history with on-chain transfers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReconciliationReport {
+    pub chain: String,
+    pub from_block: u64,
+    pub to_block: u64,
+    /// Payments found both locally and on-chain
+    pub matched: usize,
+    /// Payments recorded locally but never seen on-chain
+    pub local_only: Vec<PaymentResponse>,
+    /// Transfers on-chain with no local record
+    pub chain_only: Vec<ChainPayment>,
+    /// Sum of unmatched amounts plus amount differences of matched payments
+    pub total_discrepancy_wei: u128,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum PaymentStatus {
     Pending,
@@ -126,6 +222,48 @@ pub struct AccessResponse {
     pub expires_at: Option<i64>,
 }
 
+/// Result of probing a product's content URL, from `ProductService::validate_content_url`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContentUrlCheck {
+    /// The URL answered with a 2xx status, or 402 or 405, which show the content is there
+    pub accessible: bool,
+    /// Status of the response, `None` if none was received
+    pub status_code: Option<u16>,
+    pub content_type: Option<String>,
+    /// Size announced in `Content-Length`
+    pub size_bytes: Option<u64>,
+    pub latency_ms: u64,
+}
+
+/// One access check recorded by `AccessService`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccessHistoryEntry {
+    pub checked_at: DateTime<Utc>,
+    pub had_access: bool,
+    /// Reason given by the API, or the error if the check failed
+    pub reason: Option<String>,
+    pub expires_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccessRefreshRequest {
+    pub product_id: Uuid,
+    pub user_address: String,
+}
+
+/// Errors callers may want to handle, returned inside `anyhow::Error`.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("No access record for product {product_id} and user {user_address}")]
+    AccessNotFound { product_id: Uuid, user_address: String },
+
+    #[error("Access request timestamp {request_timestamp} is too far from server time {server_timestamp}")]
+    StaleAccessRequest { request_timestamp: i64, server_timestamp: i64 },
+
+    #[error("Content URL {url} is unreachable: {reason}")]
+    ContentUrlUnreachable { url: String, reason: String },
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct AnalyticsRequest {
     pub product_id: Option<Uuid>,
@@ -182,6 +320,8 @@ pub struct User {
 
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct AccessLog {
+    // Assigned by the server when the entry is recorded
+    #[serde(default)]
     pub id: Uuid,
     pub product_id: Uuid,
     pub user_address: String,
@@ -190,10 +330,11 @@ pub struct AccessLog {
     pub user_agent: Option<String>,
     pub referrer: Option<String>,
     pub country: Option<String>,
+    #[serde(default = "Utc::now")]
     pub created_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum AccessType {
     View,
     Purchase,
@@ -216,8 +357,140 @@ pub struct HealthCheck {
     pub database_status: Option<String>,
 }
 
+/// Components checked by `HealthService::dependency_graph` and the services they depend on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DependencyGraph {
+    pub timestamp: DateTime<Utc>,
+    /// Top-level components
+    pub roots: Vec<DependencyNode>,
+}
+
+/// A component or external service in a [`DependencyGraph`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DependencyNode {
+    pub name: String,
+    /// Whether the node and all its dependencies are healthy
+    pub healthy: bool,
+    /// Slowest check of the node or its dependencies
+    pub latency_ms: u64,
+    /// Dependencies of the node, empty for external services
+    pub children: Vec<DependencyNode>,
+}
+
+impl DependencyNode {
+    /// Creates a node without dependencies.
+    pub fn new(name: impl Into<String>, healthy: bool, latency_ms: u64) -> Self {
+        Self { name: name.into(), healthy, latency_ms, children: Vec::new() }
+    }
+
+    /// Adds `children` as dependencies, which the node's health and latency then include.
+    pub fn with_children(mut self, children: Vec<DependencyNode>) -> Self {
+        self.healthy &= children.iter().all(|child| child.healthy);
+        self.latency_ms = children.iter().map(|child| child.latency_ms).fold(self.latency_ms, u64::max);
+        self.children = children;
+        self
+    }
+
+    /// Returns the names along the longest unhealthy path starting at this node.
+    fn unhealthy_path(&self) -> Vec<String> {
+        if self.healthy {
+            return Vec::new();
+        }
+        let mut path = vec![self.name.clone()];
+        path.extend(longest(self.children.iter().map(Self::unhealthy_path)));
+        path
+    }
+
+    /// Writes the node and its edges as DOT statements, numbering nodes from `next_id`.
+    fn write_dot(&self, out: &mut String, next_id: &mut usize) -> usize {
+        let id = *next_id;
+        *next_id += 1;
+        let color = if self.healthy { "green" } else { "red" };
+        let label = self.name.replace('\\', "\\\\").replace('"', "\\\"");
+        out.push_str(&format!("    n{} [label=\"{}\\n{} ms\", color={}];\n", id, label, self.latency_ms, color));
+        for child in &self.children {
+            let child_id = child.write_dot(out, next_id);
+            out.push_str(&format!("    n{} -> n{};\n", id, child_id));
+        }
+        id
+    }
+}
+
+impl DependencyGraph {
+    pub fn is_healthy(&self) -> bool {
+        self.roots.iter().all(|root| root.healthy)
+    }
+
+    /// Returns the names along the deepest chain of unhealthy dependencies, from a
+    /// top-level component down to the failing service, the first one on ties.
+    pub fn critical_path(&self) -> Vec<String> {
+        longest(self.roots.iter().map(DependencyNode::unhealthy_path))
+    }
+
+    /// Renders the graph in Graphviz DOT format, healthy nodes in green and unhealthy ones in red.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph dependencies {\n    node [shape=box];\n");
+        let mut next_id = 0;
+        for root in &self.roots {
+            root.write_dot(&mut out, &mut next_id);
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+/// Returns the longest of `paths`, the first one on ties.
+fn longest(paths: impl Iterator<Item = Vec<String>>) -> Vec<String> {
+    paths.fold(Vec::new(), |longest, path| if path.len() > longest.len() { path } else { longest })
+}
+
 // Validation regex constants
 lazy_static::lazy_static! {
     static ref PRICE_REGEX: regex::Regex = regex::Regex::new(r"^\d+\.\d{2}$").unwrap();
     static ref ETH_ADDRESS_REGEX: regex::Regex = regex::Regex::new(r"^0x[a-fA-F0-9]{40}$").unwrap();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph() -> DependencyGraph {
+        let rpc = DependencyNode::new("https://rpc.example.com", false, 40);
+        let chain = DependencyNode::new("chain_base", true, 0).with_children(vec![rpc]);
+        let facilitator = DependencyNode::new("https://facilitator.example.com", true, 12);
+        let payments = DependencyNode::new("payment_manager", true, 0).with_children(vec![facilitator, chain]);
+        let database = DependencyNode::new("database", false, 5);
+        let api = DependencyNode::new("api", true, 5).with_children(vec![database]);
+        DependencyGraph { timestamp: Utc::now(), roots: vec![api, payments, DependencyNode::new("cache", true, 1)] }
+    }
+
+    #[test]
+    fn critical_path_is_the_deepest_unhealthy_chain() {
+        let graph = graph();
+        assert!(!graph.is_healthy());
+        assert_eq!(graph.critical_path(), ["payment_manager", "chain_base", "https://rpc.example.com"]);
+
+        let healthy = DependencyGraph { timestamp: Utc::now(), roots: vec![DependencyNode::new("api", true, 3)] };
+        assert!(healthy.is_healthy());
+        assert!(healthy.critical_path().is_empty());
+    }
+
+    #[test]
+    fn to_dot_renders_every_node_and_edge() {
+        let mut graph = graph();
+        graph.roots.truncate(1);
+        graph.roots[0].name = "api \"v1\"".to_string();
+
+        assert_eq!(
+            graph.to_dot(),
+            concat!(
+                "digraph dependencies {\n",
+                "    node [shape=box];\n",
+                "    n0 [label=\"api \\\"v1\\\"\\n5 ms\", color=red];\n",
+                "    n1 [label=\"database\\n5 ms\", color=red];\n",
+                "    n0 -> n1;\n",
+                "}\n",
+            )
+        );
+    }
+}
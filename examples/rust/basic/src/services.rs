@@ -1,11 +1,40 @@
 use anyhow::Result;
+use futures::stream::{SplitSink, SplitStream};
+use futures::{SinkExt, Stream, StreamExt};
 use tracing::{info, error, warn};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+use tokio_tungstenite::tungstenite::Message;
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 
 use crate::models::*;
-use crate::client::V402Client;
+use crate::client::{ProductSocket, V402Client};
+
+/// First delay before reconnecting to the product event stream.
+const EVENTS_INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Longest delay between reconnection attempts.
+const EVENTS_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Shortest interval between dashboard polls; shorter intervals, including zero, are raised to it.
+const MIN_DASHBOARD_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Largest difference between an access request's timestamp and the local clock accepted by
+/// [`AccessService::new`].
+const DEFAULT_MAX_CLOCK_SKEW: Duration = Duration::from_secs(300);
+
+/// Access checks kept per product and user; older ones are dropped.
+const MAX_ACCESS_HISTORY: usize = 1_000;
+
+/// Access checks per `(product_id, user_address)`, oldest first.
+type AccessHistory = HashMap<(Uuid, String), VecDeque<AccessHistoryEntry>>;
 
 pub struct ProductService {
     client: V402Client,
@@ -20,7 +49,86 @@ impl ProductService {
         }
     }
 
+    /// Creates a product, checking its content URL first if
+    /// [`Config::validate_content_urls`](crate::config::Config::validate_content_urls) is set.
     pub async fn create_product(&mut self, product_data: ProductCreate) -> Result<Product> {
+        if self.client.config().validate_content_urls {
+            return self.create_product_validated(product_data).await;
+        }
+        self.insert_product(product_data).await
+    }
+
+    /// Creates a product after checking that its content URL is accessible.
+    ///
+    /// Fails with [`Error::ContentUrlUnreachable`] without creating the
+    /// product if it is not, so buyers never pay for content they cannot fetch.
+    pub async fn create_product_validated(&mut self, product_data: ProductCreate) -> Result<Product> {
+        let (check, failure) = self.probe_content_url(&product_data.content_url).await;
+        if !check.accessible {
+            let reason = match (failure, check.status_code) {
+                (Some(failure), _) => failure,
+                (None, Some(status)) => format!("HEAD returned {}", status),
+                (None, None) => "no response".to_string(),
+            };
+            return Err(Error::ContentUrlUnreachable {
+                url: product_data.content_url,
+                reason,
+            }
+            .into());
+        }
+        self.insert_product(product_data).await
+    }
+
+    /// Checks with a `HEAD` request whether `url` serves content.
+    ///
+    /// URLs that cannot be reached are reported as not accessible rather
+    /// than as errors. A 2xx response counts as accessible, and so do 402,
+    /// since paid content answers it until paid for, and 405 from servers
+    /// that do not answer `HEAD`.
+    pub async fn validate_content_url(&self, url: &str) -> Result<ContentUrlCheck> {
+        Ok(self.probe_content_url(url).await.0)
+    }
+
+    /// Probes `url`, returning the check and the request error if no response was received.
+    async fn probe_content_url(&self, url: &str) -> (ContentUrlCheck, Option<String>) {
+        let started = Instant::now();
+        let response = self.client.head(url).await;
+        let latency_ms = started.elapsed().as_millis() as u64;
+
+        match response {
+            Ok(response) => {
+                let header = |name: reqwest::header::HeaderName| {
+                    response.headers().get(name).and_then(|value| value.to_str().ok())
+                };
+                let status = response.status();
+                let check = ContentUrlCheck {
+                    accessible: status.is_success()
+                        || status == reqwest::StatusCode::PAYMENT_REQUIRED
+                        || status == reqwest::StatusCode::METHOD_NOT_ALLOWED,
+                    status_code: Some(response.status().as_u16()),
+                    content_type: header(reqwest::header::CONTENT_TYPE).map(str::to_string),
+                    // Read the header: the body of a HEAD response is always empty
+                    size_bytes: header(reqwest::header::CONTENT_LENGTH).and_then(|length| length.parse().ok()),
+                    latency_ms,
+                };
+                info!("Content URL {} returned {} in {}ms", url, response.status(), latency_ms);
+                (check, None)
+            }
+            Err(e) => {
+                warn!("Content URL {} is unreachable: {}", url, e);
+                let check = ContentUrlCheck {
+                    accessible: false,
+                    status_code: None,
+                    content_type: None,
+                    size_bytes: None,
+                    latency_ms,
+                };
+                (check, Some(e.to_string()))
+            }
+        }
+    }
+
+    async fn insert_product(&mut self, product_data: ProductCreate) -> Result<Product> {
         info!("Creating product: {}", product_data.title);
         
         let product = self.client.create_product(&product_data).await?;
@@ -81,6 +189,142 @@ impl ProductService {
         Ok(())
     }
 
+    /// Streams price, status and deletion events for `product_ids`, or all products if empty.
+    ///
+    /// The first connection is made before returning so that errors surface
+    /// immediately. After that the stream reconnects on its own with
+    /// exponential backoff, and ends when it is dropped.
+    pub async fn subscribe_to_updates(&self, product_ids: &[Uuid]) -> Result<impl Stream<Item = ProductUpdateEvent>> {
+        let response = self.client.product_events(product_ids).await?;
+        info!("Subscribed to product updates for {} products", product_ids.len());
+
+        let client = self.client.clone();
+        let product_ids = product_ids.to_vec();
+        let (sender, receiver) = mpsc::channel(64);
+
+        tokio::spawn(async move {
+            let mut response = Some(response);
+            let mut backoff = EVENTS_INITIAL_BACKOFF;
+
+            loop {
+                let connection = match response.take() {
+                    Some(response) => Ok(response),
+                    None => client.product_events(&product_ids).await,
+                };
+
+                match connection {
+                    Ok(mut response) => {
+                        backoff = EVENTS_INITIAL_BACKOFF;
+                        let mut decoder = SseDecoder::default();
+
+                        loop {
+                            let chunk = match response.chunk().await {
+                                Ok(Some(chunk)) => chunk,
+                                Ok(None) => break,
+                                Err(e) => {
+                                    warn!("Product event stream failed: {}", e);
+                                    break;
+                                }
+                            };
+
+                            for data in decoder.feed(&chunk) {
+                                let event: ProductUpdateEvent = match serde_json::from_str(&data) {
+                                    Ok(event) => event,
+                                    Err(e) => {
+                                        warn!("Ignoring malformed product event: {}", e);
+                                        continue;
+                                    }
+                                };
+                                if !product_ids.is_empty() && !product_ids.contains(&event.product_id) {
+                                    continue;
+                                }
+                                if sender.send(event).await.is_err() {
+                                    return;
+                                }
+                            }
+                        }
+
+                        warn!("Product event stream disconnected, reconnecting in {:?}", backoff);
+                    }
+                    Err(e) => {
+                        error!("Failed to reconnect to product events, retrying in {:?}: {}", backoff, e);
+                    }
+                }
+
+                if sender.is_closed() {
+                    return;
+                }
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(EVENTS_MAX_BACKOFF);
+            }
+        });
+
+        Ok(futures::stream::unfold(receiver, |mut receiver| async move {
+            receiver.recv().await.map(|event| (event, receiver))
+        }))
+    }
+
+    /// Watches the prices of `product_ids` over a WebSocket to `/ws/products`.
+    ///
+    /// Unlike [`subscribe_to_updates`](Self::subscribe_to_updates), the
+    /// watcher does not reconnect: its stream ends when the socket closes.
+    pub async fn watch(&self, product_ids: &[Uuid]) -> Result<ProductWatcher> {
+        let socket = self.client.connect_product_watch().await?;
+        let (sink, stream) = socket.split();
+
+        let watcher = ProductWatcher {
+            sink: tokio::sync::Mutex::new(sink),
+            events: Box::pin(price_updates(stream)),
+        };
+        watcher.send(&WatchCommand::Subscribe(product_ids.to_vec())).await?;
+
+        info!("Watching prices of {} products", product_ids.len());
+        Ok(watcher)
+    }
+
+    /// Compares two catalog snapshots without calling the API.
+    ///
+    /// Added products and price or status changes are listed in `new_snapshot`
+    /// order, removed products in `old_snapshot` order. A product whose price
+    /// and status both changed appears in both lists.
+    pub fn diff_products<'a>(old_snapshot: &[Product], new_snapshot: &'a [Product]) -> CatalogDiff<'a> {
+        let old_by_id: HashMap<Uuid, &Product> = old_snapshot.iter().map(|product| (product.id, product)).collect();
+        let new_ids: HashSet<Uuid> = new_snapshot.iter().map(|product| product.id).collect();
+
+        let mut diff = CatalogDiff {
+            removed: old_snapshot
+                .iter()
+                .map(|product| product.id)
+                .filter(|id| !new_ids.contains(id))
+                .collect(),
+            ..Default::default()
+        };
+
+        for product in new_snapshot {
+            let Some(old) = old_by_id.get(&product.id) else {
+                diff.added.push(product);
+                continue;
+            };
+
+            if old.price != product.price {
+                diff.price_changed.push(PriceChange {
+                    product_id: product.id,
+                    old_price: old.price.clone(),
+                    new_price: product.price.clone(),
+                });
+            }
+            if old.status != product.status {
+                diff.status_changed.push(StatusChange {
+                    product_id: product.id,
+                    old_status: old.status.clone(),
+                    new_status: product.status.clone(),
+                });
+            }
+        }
+
+        diff
+    }
+
     pub fn get_cached_product(&self, product_id: Uuid) -> Option<&Product> {
         self.cache.get(&product_id)
     }
@@ -91,6 +335,92 @@ impl ProductService {
     }
 }
 
+/// Live price updates of watched products, created by [`ProductService::watch`].
+///
+/// The socket is closed when the watcher is dropped.
+pub struct ProductWatcher {
+    sink: tokio::sync::Mutex<SplitSink<ProductSocket, Message>>,
+    events: Pin<Box<dyn Stream<Item = PriceUpdateEvent> + Send>>,
+}
+
+impl ProductWatcher {
+    /// Starts watching more products on the same socket.
+    pub async fn subscribe(&self, product_ids: &[Uuid]) -> Result<()> {
+        self.send(&WatchCommand::Subscribe(product_ids.to_vec())).await
+    }
+
+    /// Stops watching `product_id` without closing the socket.
+    pub async fn unsubscribe(&self, product_id: Uuid) -> Result<()> {
+        self.send(&WatchCommand::Unsubscribe(vec![product_id])).await
+    }
+
+    async fn send(&self, command: &WatchCommand) -> Result<()> {
+        let message = Message::Text(serde_json::to_string(command)?);
+        self.sink.lock().await.send(message).await?;
+        Ok(())
+    }
+}
+
+impl Stream for ProductWatcher {
+    type Item = PriceUpdateEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.events.as_mut().poll_next(cx)
+    }
+}
+
+/// Decodes the price updates received on a product watch socket, ending when it closes.
+fn price_updates(stream: SplitStream<ProductSocket>) -> impl Stream<Item = PriceUpdateEvent> + Send {
+    futures::stream::unfold(stream, |mut stream| async move {
+        loop {
+            match stream.next().await? {
+                Ok(Message::Text(text)) => match serde_json::from_str(&text) {
+                    Ok(event) => return Some((event, stream)),
+                    Err(e) => warn!("Ignoring malformed price update: {}", e),
+                },
+                Ok(Message::Close(_)) => return None,
+                Ok(_) => {}
+                Err(e) => {
+                    warn!("Product watch socket failed: {}", e);
+                    return None;
+                }
+            }
+        }
+    })
+}
+
+/// Incremental decoder for `text/event-stream` bodies, yielding the data of each event.
+///
+/// Chunks are buffered as bytes and only complete lines are decoded, so
+/// characters split across chunks arrive intact.
+#[derive(Default)]
+struct SseDecoder {
+    buffer: Vec<u8>,
+    data: Vec<String>,
+}
+
+impl SseDecoder {
+    fn feed(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut events = Vec::new();
+        while let Some(end) = self.buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line[..end]);
+            let line = line.strip_suffix('\r').unwrap_or(&line);
+            if line.is_empty() {
+                if !self.data.is_empty() {
+                    events.push(self.data.join("\n"));
+                    self.data.clear();
+                }
+            } else if let Some(data) = line.strip_prefix("data:") {
+                self.data.push(data.strip_prefix(' ').unwrap_or(data).to_string());
+            }
+        }
+        events
+    }
+}
+
 pub struct PaymentService {
     client: V402Client,
     payment_history: HashMap<String, PaymentResponse>,
@@ -140,35 +470,151 @@ impl PaymentService {
         self.payment_history.clear();
         info!("Payment history cleared");
     }
+
+    /// Compares local payment history with the payer's on-chain token transfers.
+    ///
+    /// Local records without a block number are treated as never broadcast
+    /// and reported as local-only; records mined outside the range are ignored.
+    pub async fn reconcile(&self, chain: &str, from_block: u64, to_block: u64) -> Result<ReconciliationReport> {
+        let config = self.client.config();
+        if chain != config.chain {
+            return Err(anyhow::anyhow!("Chain {} is not configured (configured: {})", chain, config.chain));
+        }
+        if from_block > to_block {
+            return Err(anyhow::anyhow!("Invalid block range {}-{}", from_block, to_block));
+        }
+
+        info!("Reconciling payments on {} in blocks {}-{}", chain, from_block, to_block);
+
+        let mut chain_payments: HashMap<String, ChainPayment> = self.client
+            .get_transfer_logs(&config.public_key, from_block, to_block)
+            .await?
+            .into_iter()
+            .map(|payment| (payment.transaction_hash.to_lowercase(), payment))
+            .collect();
+
+        let mut matched = 0;
+        let mut local_only = Vec::new();
+        let mut total_discrepancy_wei: u128 = 0;
+
+        for payment in self.payment_history.values() {
+            let in_range = payment.block_number.map_or(true, |block| (from_block..=to_block).contains(&block));
+            if !in_range {
+                continue;
+            }
+
+            let local_wei = match parse_units(&payment.amount, config.token_decimals) {
+                Ok(wei) => wei,
+                Err(e) => {
+                    warn!("Skipping payment {} with invalid amount: {}", payment.transaction_hash, e);
+                    continue;
+                }
+            };
+
+            match chain_payments.remove(&payment.transaction_hash.to_lowercase()) {
+                Some(chain_payment) => {
+                    matched += 1;
+                    total_discrepancy_wei += chain_payment.amount_wei.abs_diff(local_wei);
+                }
+                None => {
+                    total_discrepancy_wei += local_wei;
+                    local_only.push(payment.clone());
+                }
+            }
+        }
+
+        let chain_only: Vec<ChainPayment> = chain_payments.into_values().collect();
+        total_discrepancy_wei += chain_only.iter().map(|payment| payment.amount_wei).sum::<u128>();
+
+        if local_only.is_empty() && chain_only.is_empty() {
+            info!("Reconciliation complete: {} payments matched", matched);
+        } else {
+            error!(
+                "Reconciliation found discrepancies: {} local-only, {} chain-only, {} wei",
+                local_only.len(), chain_only.len(), total_discrepancy_wei
+            );
+        }
+
+        Ok(ReconciliationReport {
+            chain: chain.to_string(),
+            from_block,
+            to_block,
+            matched,
+            local_only,
+            chain_only,
+            total_discrepancy_wei,
+        })
+    }
+}
+
+/// Converts a decimal amount such as `15.00` into the token's smallest unit.
+fn parse_units(amount: &str, decimals: u8) -> Result<u128> {
+    let (whole, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+    if fraction.len() > decimals as usize {
+        return Err(anyhow::anyhow!("{} has more than {} decimals", amount, decimals));
+    }
+    let digits = format!("{}{:0<width$}", whole, fraction, width = decimals as usize);
+    Ok(digits.parse()?)
 }
 
 pub struct AccessService {
     client: V402Client,
     access_cache: HashMap<(Uuid, String), AccessResponse>,
+    access_history: Arc<RwLock<AccessHistory>>,
+    /// Largest accepted difference between a request's timestamp and the local clock
+    max_skew: Duration,
 }
 
 impl AccessService {
+    /// Creates a service accepting access requests signed up to five minutes from now.
     pub fn new(client: V402Client) -> Self {
+        Self::new_with_clock_skew(client, DEFAULT_MAX_CLOCK_SKEW)
+    }
+
+    /// Creates a service rejecting access requests whose timestamp is more than `max_skew` from now.
+    pub fn new_with_clock_skew(client: V402Client, max_skew: Duration) -> Self {
         Self {
             client,
             access_cache: HashMap::new(),
+            access_history: Arc::new(RwLock::new(HashMap::new())),
+            max_skew,
         }
     }
 
+    /// Checks whether the user has access, recording the outcome in the access history.
+    ///
+    /// Fails with [`Error::StaleAccessRequest`] if the request's timestamp is too far from now,
+    /// so old signed requests cannot be replayed.
     pub async fn check_access(&mut self, access_request: AccessRequest) -> Result<AccessResponse> {
         let cache_key = (access_request.product_id, access_request.user_address.clone());
         
+        // Reject stale requests before the cache can answer them
+        let server_timestamp = Utc::now().timestamp();
+        if server_timestamp.abs_diff(access_request.timestamp) > self.max_skew.as_secs() {
+            let error = anyhow::Error::from(Error::StaleAccessRequest {
+                request_timestamp: access_request.timestamp,
+                server_timestamp,
+            });
+            warn!("Rejected stale access request for product: {}, user: {}: {}",
+                  access_request.product_id, access_request.user_address, error);
+            self.record_access(cache_key, Err(&error));
+            return Err(error);
+        }
+        
         // Check cache first
-        if let Some(access_response) = self.access_cache.get(&cache_key) {
+        if let Some(access_response) = self.access_cache.get(&cache_key).cloned() {
             info!("Access check found in cache for product: {}, user: {}", 
                   access_request.product_id, access_request.user_address);
-            return Ok(access_response.clone());
+            self.record_access(cache_key, Ok(&access_response));
+            return Ok(access_response);
         }
 
         info!("Checking access for product: {}, user: {}", 
               access_request.product_id, access_request.user_address);
         
-        let access_response = self.client.check_access(&access_request).await?;
+        let result = self.client.check_access(&access_request).await;
+        self.record_access(cache_key.clone(), result.as_ref());
+        let access_response = result?;
         
         // Cache the response
         self.access_cache.insert(cache_key, access_response.clone());
@@ -176,6 +622,82 @@ impl AccessService {
         Ok(access_response)
     }
 
+    /// Returns up to `limit` access checks of the user for a product, newest first.
+    pub fn get_access_history(
+        &self,
+        product_id: Uuid,
+        user_address: &str,
+        limit: u32,
+    ) -> Result<Vec<AccessHistoryEntry>> {
+        let history = self
+            .access_history
+            .read()
+            .map_err(|_| anyhow::anyhow!("Access history lock poisoned"))?;
+
+        Ok(history
+            .get(&(product_id, user_address.to_string()))
+            .map(|entries| entries.iter().rev().take(limit as usize).cloned().collect())
+            .unwrap_or_default())
+    }
+
+    /// Forgets the access checks of the user for a product.
+    pub fn clear_history(&self, product_id: Uuid, user_address: &str) {
+        if let Ok(mut history) = self.access_history.write() {
+            history.remove(&(product_id, user_address.to_string()));
+        }
+    }
+
+    /// Number of access checks recorded across all products and users.
+    pub fn total_history_entries(&self) -> usize {
+        self.access_history
+            .read()
+            .map(|history| history.values().map(VecDeque::len).sum())
+            .unwrap_or(0)
+    }
+
+    /// Appends the outcome of an access check to the history.
+    fn record_access(&self, key: (Uuid, String), outcome: std::result::Result<&AccessResponse, &anyhow::Error>) {
+        let entry = match outcome {
+            Ok(response) => AccessHistoryEntry {
+                checked_at: Utc::now(),
+                had_access: response.has_access,
+                reason: response.reason.clone(),
+                expires_at: response.expires_at,
+            },
+            Err(e) => AccessHistoryEntry {
+                checked_at: Utc::now(),
+                had_access: false,
+                reason: Some(e.to_string()),
+                expires_at: None,
+            },
+        };
+
+        let Ok(mut history) = self.access_history.write() else {
+            warn!("Access history lock poisoned, dropping entry");
+            return;
+        };
+        let entries = history.entry(key).or_default();
+        if entries.len() >= MAX_ACCESS_HISTORY {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+
+    /// Extends the user's access and replaces the cached grant with the extended one.
+    pub async fn refresh_access(&mut self, product_id: Uuid, user_address: &str) -> Result<AccessResponse> {
+        let cache_key = (product_id, user_address.to_string());
+
+        // Drop the old grant first so a failed refresh never serves a stale expiry
+        self.access_cache.remove(&cache_key);
+
+        info!("Refreshing access for product: {}, user: {}", product_id, user_address);
+
+        let access_response = self.client.refresh_access(product_id, user_address).await?;
+        self.access_cache.insert(cache_key, access_response.clone());
+
+        Ok(access_response)
+    }
+
     pub fn clear_cache(&mut self) {
         self.access_cache.clear();
         info!("Access cache cleared");
@@ -217,6 +739,80 @@ impl AnalyticsService {
         self.analytics_cache.clear();
         info!("Analytics cache cleared");
     }
+
+    /// Polls daily analytics for `product_id`, or all products if `None`,
+    /// every `poll_interval` and publishes each result to the returned handle.
+    ///
+    /// The first fetch is made before returning so that the receiver always
+    /// holds a value and errors surface immediately. After that failed polls
+    /// are logged and skipped, keeping the last value. Polls bypass the
+    /// analytics cache. The task stops when the handle is stopped or every
+    /// receiver is dropped. A zero `poll_interval` is raised to one millisecond.
+    pub async fn realtime_dashboard(&self, product_id: Option<Uuid>, poll_interval: Duration) -> Result<DashboardHandle> {
+        let poll_interval = poll_interval.max(MIN_DASHBOARD_INTERVAL);
+        let request = AnalyticsRequest {
+            product_id,
+            start_date: None,
+            end_date: None,
+            period: PeriodType::Daily,
+        };
+        let analytics = self.client.get_analytics(&request).await?;
+        let (sender, receiver) = watch::channel(analytics);
+
+        let client = self.client.clone();
+        let stop = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(poll_interval);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            // The first tick completes immediately and the first fetch is already published
+            interval.tick().await;
+
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    _ = sender.closed() => return,
+                }
+
+                match client.get_analytics(&request).await {
+                    Ok(analytics) => {
+                        if sender.send(analytics).is_err() {
+                            return;
+                        }
+                    }
+                    Err(e) => warn!("Skipping dashboard update, failed to fetch analytics: {}", e),
+                }
+            }
+        });
+
+        info!("Started realtime dashboard, polling every {:?}", poll_interval);
+        Ok(DashboardHandle { receiver, stop })
+    }
+}
+
+/// Live analytics published by [`AnalyticsService::realtime_dashboard`].
+pub struct DashboardHandle {
+    /// Latest analytics; use `changed()` to wait for the next update
+    pub receiver: watch::Receiver<AnalyticsResponse>,
+    /// Polling task
+    pub stop: JoinHandle<()>,
+}
+
+impl DashboardHandle {
+    /// Returns the latest analytics.
+    pub fn current(&self) -> AnalyticsResponse {
+        self.receiver.borrow().clone()
+    }
+
+    /// Cancels the polling task and waits for it to finish.
+    pub async fn stop_and_wait(self) {
+        self.stop.abort();
+        // A cancelled task resolves with a cancellation error, which is expected here
+        if let Err(e) = self.stop.await {
+            if !e.is_cancelled() {
+                error!("Dashboard task failed: {}", e);
+            }
+        }
+        info!("Realtime dashboard stopped");
+    }
 }
 
 pub struct HealthService {
@@ -234,6 +830,35 @@ impl HealthService {
         }
     }
 
+    /// Checks the API server, with the database it reports as a dependency.
+    ///
+    /// An API server that cannot be reached is an unhealthy node without
+    /// dependencies.
+    pub async fn dependency_graph(&self) -> DependencyGraph {
+        DependencyGraph { timestamp: Utc::now(), roots: vec![self.api_node().await] }
+    }
+
+    /// Checks the API server, with its database as a dependency.
+    async fn api_node(&self) -> DependencyNode {
+        let started = Instant::now();
+        let health = self.client.health_check().await;
+        let latency_ms = started.elapsed().as_millis().try_into().unwrap_or(u64::MAX);
+
+        let health = match health {
+            Ok(health) => health,
+            Err(e) => {
+                warn!("API health check failed: {}", e);
+                return DependencyNode::new("api", false, latency_ms);
+            }
+        };
+        let database = health
+            .database_status
+            .iter()
+            .map(|status| DependencyNode::new("database", status == "healthy", latency_ms))
+            .collect();
+        DependencyNode::new("api", health.status == "healthy", latency_ms).with_children(database)
+    }
+
     pub async fn check_health(&mut self) -> Result<HealthCheck> {
         info!("Performing health check");
         
@@ -254,3 +879,221 @@ impl HealthService {
         self.last_check
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sse_decoder_keeps_characters_split_across_chunks() {
+        let body = "data: {\"name\":\"Café\"}\r\n\r\n".as_bytes();
+        let split = body.iter().position(|&byte| byte == 0xc3).unwrap() + 1;
+
+        let mut decoder = SseDecoder::default();
+        assert!(decoder.feed(&body[..split]).is_empty());
+        assert_eq!(decoder.feed(&body[split..]), vec!["{\"name\":\"Café\"}".to_string()]);
+    }
+
+    #[test]
+    fn sse_decoder_joins_multiline_data() {
+        let mut decoder = SseDecoder::default();
+        assert_eq!(decoder.feed(b"event: update\ndata: a\ndata: b\n\n: ping\n\n"), vec!["a\nb".to_string()]);
+    }
+
+    fn product(price: &str, status: ProductStatus) -> Product {
+        Product {
+            id: Uuid::new_v4(),
+            title: "Market data".to_string(),
+            description: "Daily market data".to_string(),
+            price: price.to_string(),
+            currency: "USDC".to_string(),
+            content_url: "https://example.com/content".to_string(),
+            category: None,
+            tags: Vec::new(),
+            author: None,
+            status,
+            view_count: 0,
+            purchase_count: 0,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn diff_products_reports_every_change_type() {
+        let unchanged = product("1.00", ProductStatus::Active);
+        let removed = product("2.00", ProductStatus::Active);
+        let repriced = product("3.00", ProductStatus::Active);
+        let disabled = product("4.00", ProductStatus::Active);
+        let both = product("5.00", ProductStatus::Draft);
+        let old_snapshot = vec![unchanged.clone(), removed.clone(), repriced.clone(), disabled.clone(), both.clone()];
+
+        let added = product("6.00", ProductStatus::Active);
+        let new_snapshot = vec![
+            unchanged,
+            Product { price: "3.50".to_string(), ..repriced.clone() },
+            Product { status: ProductStatus::Inactive, ..disabled.clone() },
+            Product { price: "4.50".to_string(), status: ProductStatus::Active, ..both.clone() },
+            added.clone(),
+        ];
+
+        let diff = ProductService::diff_products(&old_snapshot, &new_snapshot);
+        assert_eq!(diff.added.iter().map(|product| product.id).collect::<Vec<_>>(), vec![added.id]);
+        assert_eq!(diff.removed, vec![removed.id]);
+        assert_eq!(
+            diff.price_changed,
+            vec![
+                PriceChange { product_id: repriced.id, old_price: "3.00".to_string(), new_price: "3.50".to_string() },
+                PriceChange { product_id: both.id, old_price: "5.00".to_string(), new_price: "4.50".to_string() },
+            ]
+        );
+        assert_eq!(
+            diff.status_changed,
+            vec![
+                StatusChange {
+                    product_id: disabled.id,
+                    old_status: ProductStatus::Active,
+                    new_status: ProductStatus::Inactive,
+                },
+                StatusChange { product_id: both.id, old_status: ProductStatus::Draft, new_status: ProductStatus::Active },
+            ]
+        );
+
+        assert!(ProductService::diff_products(&new_snapshot, &new_snapshot).is_empty());
+    }
+
+    /// Returns an access request for one product and user, signed `age_secs` ago.
+    fn access_request(age_secs: i64) -> AccessRequest {
+        AccessRequest {
+            product_id: Uuid::nil(),
+            user_address: "0x1111111111111111111111111111111111111111".to_string(),
+            timestamp: Utc::now().timestamp() - age_secs,
+            signature: "0xsigned".to_string(),
+        }
+    }
+
+    /// Returns a client for an API that is not listening.
+    fn offline_client() -> V402Client {
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        V402Client::new(crate::config::Config {
+            base_url: format!("http://127.0.0.1:{}", port),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    /// Returns the timestamps of the stale request error of `result`, if it is one.
+    fn stale_timestamps(result: Result<AccessResponse>) -> Option<(i64, i64)> {
+        match result.unwrap_err().downcast::<Error>() {
+            Ok(Error::StaleAccessRequest { request_timestamp, server_timestamp }) => {
+                Some((request_timestamp, server_timestamp))
+            }
+            _ => None,
+        }
+    }
+
+    #[tokio::test]
+    async fn access_requests_older_than_five_minutes_are_rejected() {
+        let mut service = AccessService::new(offline_client());
+        let cached = AccessResponse { has_access: true, reason: None, expires_at: None };
+        let request = access_request(301);
+        service.access_cache.insert((request.product_id, request.user_address.clone()), cached);
+
+        // A cached grant must not answer a replayed request
+        let (request_timestamp, server_timestamp) = stale_timestamps(service.check_access(request.clone()).await).unwrap();
+        assert_eq!(request_timestamp, request.timestamp);
+        assert!(server_timestamp - request_timestamp >= 301);
+        assert!(stale_timestamps(service.check_access(access_request(-301)).await).is_some());
+
+        // Fresh requests reach the cache
+        assert!(service.check_access(access_request(299)).await.unwrap().has_access);
+        assert!(service.check_access(access_request(0)).await.unwrap().has_access);
+
+        let history = service.get_access_history(request.product_id, &request.user_address, 10).unwrap();
+        assert_eq!(history.len(), 4);
+        assert!(history[2..].iter().all(|entry| !entry.had_access && entry.reason.as_ref().unwrap().contains("too far from server time")));
+    }
+
+    /// Serves analytics whose `views` is the number of the request, starting
+    /// at zero, and fails request number `failing`. Returns the base URL.
+    async fn analytics_server(failing: u64) -> String {
+        use axum::{http::StatusCode, routing::post, Json, Router};
+        use std::sync::atomic::{AtomicU64, Ordering};
+
+        let requests = Arc::new(AtomicU64::new(0));
+        let app = Router::new().route(
+            "/api/v1/analytics",
+            post(move || {
+                let requests = requests.clone();
+                async move {
+                    let views = requests.fetch_add(1, Ordering::SeqCst);
+                    if views == failing {
+                        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+                    }
+                    Ok(Json(serde_json::json!({
+                        "product_id": null,
+                        "views": views,
+                        "purchases": 0,
+                        "revenue": "0",
+                        "currency": "USDC",
+                        "period": "Daily",
+                        "generated_at": Utc::now(),
+                        "conversion_rate": 0.0,
+                        "top_countries": [],
+                        "top_referrers": [],
+                    })))
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        base_url
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn realtime_dashboard_publishes_each_successful_poll() {
+        // A paused clock jumps straight to the next timer while a request waits
+        // on the socket, which would be the request timeout; tick it forward
+        // in small steps instead
+        tokio::spawn(async {
+            let mut heartbeat = tokio::time::interval(Duration::from_millis(1));
+            loop {
+                heartbeat.tick().await;
+            }
+        });
+
+        let poll_interval = Duration::from_millis(500);
+        let client = V402Client::new(crate::config::Config {
+            base_url: analytics_server(2).await,
+            ..Default::default()
+        })
+        .unwrap();
+        let service = AnalyticsService::new(client);
+
+        let started = tokio::time::Instant::now();
+        let handle = service.realtime_dashboard(None, poll_interval).await.unwrap();
+        assert_eq!(handle.current().views, 0);
+
+        // The third request fails and is skipped, keeping the last value
+        let mut receiver = handle.receiver.clone();
+        for views in [1, 3, 4] {
+            receiver.changed().await.unwrap();
+            assert_eq!(receiver.borrow_and_update().views, views);
+        }
+        assert!(started.elapsed() >= poll_interval * 4);
+        assert_eq!(handle.current().views, 4);
+
+        handle.stop_and_wait().await;
+        assert!(receiver.changed().await.is_err(), "the polling task still holds the sender");
+    }
+
+    #[tokio::test]
+    async fn clock_skew_is_configurable() {
+        let mut service = AccessService::new_with_clock_skew(offline_client(), Duration::from_secs(60));
+        assert!(stale_timestamps(service.check_access(access_request(120)).await).is_some());
+
+        // A fresh request gets past the check and fails on the unreachable API instead
+        assert_eq!(stale_timestamps(service.check_access(access_request(30)).await), None);
+    }
+}
@@ -1,17 +1,26 @@
 use axum::{
-    extract::{Path, Query, State},
-    http::StatusCode,
-    response::Json,
-    routing::{get, post, put, delete},
+    body::Body,
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path, Query, Request, State,
+    },
+    http::{HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Json, Response,
+    },
+    routing::{get, post, patch, delete},
     Router,
 };
+use futures::Stream;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, Mutex, RwLock};
 use tower::ServiceBuilder;
 use tower_http::{cors::CorsLayer, trace::TraceLayer};
-use tracing::{info, error};
+use tracing::{info, error, warn};
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 
@@ -27,6 +36,42 @@ pub struct AppState {
     pub access_service: Arc<RwLock<AccessService>>,
     pub analytics_service: Arc<RwLock<AnalyticsService>>,
     pub health_service: Arc<RwLock<HealthService>>,
+    pub product_events: broadcast::Sender<ProductUpdateEvent>,
+    pub price_updates: broadcast::Sender<PriceUpdateEvent>,
+    pub access_logs: Arc<Mutex<Vec<AccessLog>>>,
+}
+
+impl AppState {
+    // Announce a product change to every connected event stream
+    fn publish_product_event(&self, event_type: UpdateEventType, product: &Product) {
+        let event = ProductUpdateEvent {
+            product_id: product.id,
+            event_type,
+            product: product.clone(),
+            timestamp: Utc::now(),
+        };
+        // Sending only fails when nobody is subscribed
+        let _ = self.product_events.send(event);
+    }
+
+    // Announce a price change to every product watch socket
+    fn publish_price_update(&self, product: &Product, old_price: String) {
+        let update = PriceUpdateEvent {
+            product_id: product.id,
+            old_price,
+            new_price: product.price.clone(),
+            changed_at: Utc::now(),
+        };
+        // Sending only fails when nobody is watching
+        let _ = self.price_updates.send(update);
+    }
+
+    // Append an entry to the access log under a new server-side ID
+    async fn record_access(&self, mut log: AccessLog) -> AccessLog {
+        log.id = Uuid::new_v4();
+        self.access_logs.lock().await.push(log.clone());
+        log
+    }
 }
 
 // Query parameters for pagination
@@ -46,6 +91,36 @@ pub struct ProductFilterQuery {
     pub search: Option<String>,
 }
 
+// Query parameters for the product event stream
+#[derive(Debug, Deserialize)]
+pub struct ProductEventsQuery {
+    // Comma-separated product IDs; all products when absent
+    pub product_ids: Option<String>,
+}
+
+// Query parameters for access log retrieval
+#[derive(Debug, Deserialize)]
+pub struct AccessLogQuery {
+    pub user_address: Option<String>,
+    pub product_id: Option<Uuid>,
+    pub access_type: Option<AccessType>,
+    pub start_date: Option<DateTime<Utc>>,
+    pub end_date: Option<DateTime<Utc>>,
+}
+
+impl AccessLogQuery {
+    fn matches(&self, log: &AccessLog) -> bool {
+        self.user_address.as_ref().map_or(true, |address| address.eq_ignore_ascii_case(&log.user_address))
+            && self.product_id.map_or(true, |id| id == log.product_id)
+            && self.access_type.map_or(true, |access_type| access_type == log.access_type)
+            && self.start_date.map_or(true, |start| log.created_at >= start)
+            && self.end_date.map_or(true, |end| log.created_at <= end)
+    }
+}
+
+// Largest access check body buffered by the access logging middleware
+const MAX_ACCESS_CHECK_BODY: usize = 64 * 1024;
+
 // Product handlers
 pub async fn create_product(
     State(state): State<AppState>,
@@ -57,6 +132,7 @@ pub async fn create_product(
     match product_service.create_product(payload).await {
         Ok(product) => {
             info!("Product created successfully: {}", product.id);
+            state.publish_product_event(UpdateEventType::StatusChanged, &product);
             Ok(Json(product))
         }
         Err(e) => {
@@ -111,10 +187,28 @@ pub async fn update_product(
 ) -> Result<Json<Product>, StatusCode> {
     info!("Updating product: {}", product_id);
     
+    let price_changed = payload.price.is_some();
+    let status_changed = payload.status.is_some();
+    
     let mut product_service = state.product_service.write().await;
+    // Keep the previous price for product watchers
+    let old_price = if price_changed {
+        product_service.get_product(product_id).await.ok().map(|product| product.price)
+    } else {
+        None
+    };
     match product_service.update_product(product_id, payload).await {
         Ok(product) => {
             info!("Product updated successfully: {}", product_id);
+            if price_changed {
+                state.publish_product_event(UpdateEventType::PriceChanged, &product);
+            }
+            if let Some(old_price) = old_price.filter(|old_price| *old_price != product.price) {
+                state.publish_price_update(&product, old_price);
+            }
+            if status_changed {
+                state.publish_product_event(UpdateEventType::StatusChanged, &product);
+            }
             Ok(Json(product))
         }
         Err(e) => {
@@ -131,9 +225,14 @@ pub async fn delete_product(
     info!("Deleting product: {}", product_id);
     
     let mut product_service = state.product_service.write().await;
+    // Keep the last state of the product for the deletion event
+    let product = product_service.get_product(product_id).await.ok();
     match product_service.delete_product(product_id).await {
         Ok(_) => {
             info!("Product deleted successfully: {}", product_id);
+            if let Some(product) = product {
+                state.publish_product_event(UpdateEventType::Deleted, &product);
+            }
             Ok(StatusCode::NO_CONTENT)
         }
         Err(e) => {
@@ -143,6 +242,94 @@ pub async fn delete_product(
     }
 }
 
+// Server-sent event stream of product changes
+pub async fn product_events(
+    State(state): State<AppState>,
+    Query(params): Query<ProductEventsQuery>,
+) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+    let filter: Option<HashSet<Uuid>> = params
+        .product_ids
+        .map(|ids| ids.split(',').filter_map(|id| id.trim().parse().ok()).collect());
+    info!("Product event subscriber connected, filter: {:?}", filter);
+    
+    let receiver = state.product_events.subscribe();
+    let stream = futures::stream::unfold((receiver, filter), |(mut receiver, filter)| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => {
+                    if filter.as_ref().map_or(true, |ids| ids.contains(&event.product_id)) {
+                        let event = Event::default().event("product_update").json_data(&event);
+                        return Some((event, (receiver, filter)));
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("Product event subscriber lagged, skipped {} events", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+    
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+// Upgrade to a WebSocket sending price changes of the products the client subscribes to
+pub async fn watch_products(
+    ws: WebSocketUpgrade,
+    State(state): State<AppState>,
+) -> Response {
+    ws.on_upgrade(move |socket| watch_products_socket(socket, state))
+}
+
+async fn watch_products_socket(mut socket: WebSocket, state: AppState) {
+    info!("Product watcher connected");
+    
+    let mut updates = state.price_updates.subscribe();
+    let mut watched: HashSet<Uuid> = HashSet::new();
+    loop {
+        tokio::select! {
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str::<WatchCommand>(&text) {
+                    Ok(WatchCommand::Subscribe(product_ids)) => watched.extend(product_ids),
+                    Ok(WatchCommand::Unsubscribe(product_ids)) => {
+                        for product_id in &product_ids {
+                            watched.remove(product_id);
+                        }
+                    }
+                    Err(e) => warn!("Ignoring malformed watch command: {}", e),
+                },
+                Some(Ok(Message::Close(_))) | None => break,
+                Some(Ok(_)) => {}
+                Some(Err(e)) => {
+                    warn!("Product watch socket failed: {}", e);
+                    break;
+                }
+            },
+            update = updates.recv() => match update {
+                Ok(update) if watched.contains(&update.product_id) => {
+                    let text = match serde_json::to_string(&update) {
+                        Ok(text) => text,
+                        Err(e) => {
+                            error!("Failed to encode price update: {}", e);
+                            continue;
+                        }
+                    };
+                    if socket.send(Message::Text(text)).await.is_err() {
+                        break;
+                    }
+                }
+                Ok(_) => {}
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("Product watcher lagged, skipped {} price updates", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+        }
+    }
+    
+    info!("Product watcher disconnected");
+}
+
 // Payment handlers
 pub async fn process_payment(
     State(state): State<AppState>,
@@ -202,6 +389,73 @@ pub async fn check_access(
     }
 }
 
+pub async fn create_access_log(
+    State(state): State<AppState>,
+    Json(payload): Json<AccessLog>,
+) -> (StatusCode, Json<AccessLog>) {
+    info!("Recording access to product: {}, user: {}", payload.product_id, payload.user_address);
+    
+    let log = state.record_access(payload).await;
+    (StatusCode::CREATED, Json(log))
+}
+
+pub async fn list_access_logs(
+    State(state): State<AppState>,
+    Query(params): Query<AccessLogQuery>,
+) -> Json<Vec<AccessLog>> {
+    info!("Listing access logs: {:?}", params);
+    
+    let access_logs = state.access_logs.lock().await;
+    let logs: Vec<AccessLog> = access_logs.iter().filter(|log| params.matches(log)).cloned().collect();
+    info!("Retrieved {} access log entries", logs.len());
+    Json(logs)
+}
+
+// Record every successful access check in the access log
+pub async fn log_access(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let (parts, body) = request.into_parts();
+    let bytes = match axum::body::to_bytes(body, MAX_ACCESS_CHECK_BODY).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            warn!("Failed to read access check body: {}", e);
+            return StatusCode::PAYLOAD_TOO_LARGE.into_response();
+        }
+    };
+    // Malformed bodies are left for the handler to reject
+    let access_request = serde_json::from_slice::<AccessRequest>(&bytes).ok();
+    let headers = parts.headers.clone();
+    
+    let response = next.run(Request::from_parts(parts, Body::from(bytes))).await;
+    
+    if let (true, Some(access_request)) = (response.status().is_success(), access_request) {
+        let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok()).map(str::to_string);
+        let log = AccessLog {
+            id: Uuid::nil(),
+            product_id: access_request.product_id,
+            user_address: access_request.user_address,
+            access_type: AccessType::Access,
+            ip_address: client_ip(&headers),
+            user_agent: header("user-agent"),
+            referrer: header("referer"),
+            country: header("cf-ipcountry"),
+            created_at: Utc::now(),
+        };
+        state.record_access(log).await;
+    }
+    
+    response
+}
+
+// Client address as reported by the first proxy in front of the server
+fn client_ip(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get("x-forwarded-for")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(',').next())
+        .map(|ip| ip.trim().to_string())
+        .or_else(|| headers.get("x-real-ip").and_then(|value| value.to_str().ok()).map(str::to_string))
+}
+
 // Analytics handlers
 pub async fn get_analytics(
     State(state): State<AppState>,
@@ -222,17 +476,27 @@ pub async fn get_analytics(
     }
 }
 
+// Map a reported health status to an HTTP status code:
+// 200 for healthy, 207 for degraded, 503 for anything else
+fn health_status_code(status: &str) -> StatusCode {
+    match status.to_ascii_lowercase().as_str() {
+        "healthy" | "ok" => StatusCode::OK,
+        "degraded" => StatusCode::MULTI_STATUS,
+        _ => StatusCode::SERVICE_UNAVAILABLE,
+    }
+}
+
 // Health check handler
 pub async fn health_check(
     State(state): State<AppState>,
-) -> Result<Json<HealthCheck>, StatusCode> {
+) -> Result<(StatusCode, Json<HealthCheck>), StatusCode> {
     info!("Performing health check");
-    
+
     let mut health_service = state.health_service.write().await;
     match health_service.check_health().await {
         Ok(health) => {
             info!("Health check successful: {}", health.status);
-            Ok(Json(health))
+            Ok((health_status_code(&health.status), Json(health)))
         }
         Err(e) => {
             error!("Health check failed: {}", e);
@@ -269,16 +533,23 @@ pub fn create_app(state: AppState) -> Router {
         // Product routes
         .route("/api/v1/products", post(create_product))
         .route("/api/v1/products", get(list_products))
+        .route("/api/v1/products/events", get(product_events))
         .route("/api/v1/products/:id", get(get_product))
-        .route("/api/v1/products/:id", put(update_product))
+        .route("/api/v1/products/:id", patch(update_product))
         .route("/api/v1/products/:id", delete(delete_product))
+        .route("/ws/products", get(watch_products))
         
         // Payment routes
         .route("/api/v1/payments", post(process_payment))
         .route("/api/v1/payments/:transaction_hash", get(get_payment))
         
         // Access routes
-        .route("/api/v1/access/check", post(check_access))
+        .route(
+            "/api/v1/access/check",
+            post(check_access).route_layer(middleware::from_fn_with_state(state.clone(), log_access)),
+        )
+        .route("/api/v1/access/logs", post(create_access_log))
+        .route("/api/v1/access/logs", get(list_access_logs))
         
         // Analytics routes
         .route("/api/v1/analytics", post(get_analytics))
@@ -2,6 +2,7 @@ use anyhow::Result;
 use axum::Router;
 use std::net::SocketAddr;
 use tokio::net::TcpListener;
+use tokio::sync::{broadcast, Mutex};
 use tracing::{info, error};
 
 use crate::config::Config;
@@ -9,6 +10,9 @@ use crate::client::V402Client;
 use crate::services::*;
 use crate::handlers::{create_app, AppState};
 
+// Product events buffered per subscriber before it starts lagging
+const PRODUCT_EVENTS_CAPACITY: usize = 256;
+
 pub struct Server {
     config: Config,
     state: AppState,
@@ -25,6 +29,8 @@ impl Server {
         let access_service = Arc::new(RwLock::new(AccessService::new(client.clone())));
         let analytics_service = Arc::new(RwLock::new(AnalyticsService::new(client.clone())));
         let health_service = Arc::new(RwLock::new(HealthService::new(client)));
+        let (product_events, _) = broadcast::channel(PRODUCT_EVENTS_CAPACITY);
+        let (price_updates, _) = broadcast::channel(PRODUCT_EVENTS_CAPACITY);
 
         let state = AppState {
             product_service,
@@ -32,6 +38,9 @@ impl Server {
             access_service,
             analytics_service,
             health_service,
+            product_events,
+            price_updates,
+            access_logs: Arc::new(Mutex::new(Vec::new())),
         };
 
         Ok(Self { config, state })